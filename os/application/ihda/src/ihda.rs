@@ -0,0 +1,359 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[allow(unused_imports)]
+use runtime::*;
+use io::{print, println};
+use audio::{active_streams, capabilities, dump, play_test_tone, poll_event, set_sidetone_level, set_verb_trace, speaker_test_report, subscribe_events, verb_trace, AudioEvent, Dump, stream_position, Endpoint, SpeakerTestSignal, StreamClock, StreamOwner, StreamState, TestToneError, RouteCapabilities, WALL_CLOCK_FREQUENCY_HZ};
+use concurrent::{process, thread};
+
+const DEFAULT_FREQUENCY: u32 = 440;
+const TEST_TONE_DURATION_MS: usize = 2000;
+const EVENT_POLL_INTERVAL_MS: usize = 100;
+const DEFAULT_TRACE_DUMP_ENTRIES: usize = 50;
+
+fn print_usage() {
+    println!("Usage: ihda play [<codec address>:<node id>] [<frequency in Hz>]");
+    println!("       Without an endpoint, the default line out endpoint is used.");
+    println!("       ihda test-speakers [<codec address>:<node id>]");
+    println!("       Plays a sine sweep and beeps on each channel (once on channel 0, twice on channel 1, ...) to check the speaker wiring.");
+    println!("       ihda sidetone <level in percent>");
+    println!("       Mixes the recorded input into the default output while recording, 0 turns it off.");
+    println!("       ihda events <seconds>");
+    println!("       Prints the audio events (jacks, volume changes, underruns, device errors) reported within the given time.");
+    println!("       ihda streams");
+    println!("       Lists all streams using the sound card and their owners.");
+    println!("       ihda caps");
+    println!("       Shows the formats and latencies of the default output and input routes.");
+    println!("       ihda clock <stream descriptor>");
+    println!("       Shows the wall clock of the sound card and the position of a stream.");
+    println!("       ihda regs");
+    println!("       Dumps the controller and stream descriptor registers.");
+    println!("       ihda codecs");
+    println!("       Prints the widget graph of all codecs with the pin configuration defaults.");
+    println!("       ihda path");
+    println!("       Shows the widget paths of the running output streams.");
+    println!("       ihda validate");
+    println!("       Reads back the widgets on the paths of the running output streams and reports settings the codec ignored.");
+    println!("       ihda graph [serial]");
+    println!("       Exports the codec graph with the current widget state as JSON, optionally also to the serial port.");
+    println!("       ihda errors");
+    println!("       Prints the FIFO, descriptor and command ring errors of the controller and how often they got recovered from.");
+    println!("       ihda settings [serial]");
+    println!("       Prints the audio settings, which get restored at boot if QEMU is started with them (see run.sh --audio-settings).");
+    println!("       ihda trace on|off");
+    println!("       Records every verb sent to the codecs and every response, turning it on drops the previous trace.");
+    println!("       ihda trace dump [<entries>]");
+    println!("       Prints the last entries of the verb trace (default {}) with their wall clock ticks.", DEFAULT_TRACE_DUMP_ENTRIES);
+}
+
+// accepts decimal numbers and hexadecimal numbers with prefix 0x, as node ids are usually printed in hex
+fn parse_number(string: &str) -> Option<u32> {
+    match string.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => string.parse().ok()
+    }
+}
+
+fn parse_endpoint(string: &str) -> Option<Endpoint> {
+    let (codec_address, node_id) = string.split_once(':')?;
+    Some(Endpoint::new(parse_number(codec_address)? as u8, parse_number(node_id)? as u8))
+}
+
+fn play(arguments: &[String]) {
+    let mut endpoint = None;
+    let mut frequency = DEFAULT_FREQUENCY;
+
+    for argument in arguments {
+        if argument.contains(':') {
+            endpoint = match parse_endpoint(argument) {
+                Some(endpoint) => Some(endpoint),
+                None => {
+                    println!("Invalid endpoint [{}]!", argument);
+                    return;
+                }
+            };
+        } else {
+            frequency = match parse_number(argument) {
+                Some(frequency) if frequency > 0 => frequency,
+                _ => {
+                    println!("Invalid frequency [{}]!", argument);
+                    return;
+                }
+            };
+        }
+    }
+
+    match play_test_tone(endpoint, frequency, TEST_TONE_DURATION_MS) {
+        Ok(_) => println!("Played {} Hz test tone", frequency),
+        Err(error) => print_test_tone_error(error)
+    }
+}
+
+fn print_test_tone_error(error: TestToneError) {
+    match error {
+        TestToneError::NoDefaultEndpoint => println!("No default line out endpoint found!"),
+        TestToneError::UnknownEndpoint => println!("Endpoint not found!"),
+        TestToneError::NotAnOutputEndpoint => println!("Endpoint is not an output!"),
+        TestToneError::NoConverterOnPath => println!("No audio output converter connected to endpoint!"),
+        TestToneError::UnsupportedFormat => println!("Endpoint does not support 48 kHz/16 bit PCM!"),
+        TestToneError::NotAnInputEndpoint => println!("Endpoint is not an input!"),
+        TestToneError::DeviceError => println!("Sound card failed to set up the stream!"),
+        TestToneError::NoAudioDevice => println!("No sound card available!"),
+        TestToneError::InvalidChannel => println!("Endpoint does not play this channel!"),
+        TestToneError::NoSidetonePath => println!("Codec can not mix the input into the default output!"),
+        TestToneError::StreamStopped => println!("Sound card stopped the stream while playing!"),
+        TestToneError::Unknown(code) => println!("Playing test tone failed (Error: {})!", code)
+    }
+}
+
+// Plays a sine sweep on all channels and then beeps on one channel after another, once on the first channel, twice on the
+// second and so on, so that the channel mapping can be checked by ear.
+fn test_speakers(arguments: &[String]) {
+    let endpoint = match arguments.first() {
+        Some(argument) => match parse_endpoint(argument) {
+            Some(endpoint) => Some(endpoint),
+            None => {
+                println!("Invalid endpoint [{}]!", argument);
+                return;
+            }
+        },
+        None => None
+    };
+
+    if let Some(report) = speaker_test_report(endpoint) {
+        print!("{}", report);
+    }
+
+    println!("Playing sine sweep from 100 Hz to 10 kHz on all channels");
+    if let Err(error) = audio::test_speakers(endpoint, SpeakerTestSignal::Sweep) {
+        print_test_tone_error(error);
+        return;
+    }
+
+    for channel in 0..=u8::MAX {
+        println!("Beeping {} times on channel {}", channel as usize + 1, channel);
+        match audio::test_speakers(endpoint, SpeakerTestSignal::Channel(channel)) {
+            Ok(_) => {}
+            // all channels have been played
+            Err(TestToneError::InvalidChannel) => return,
+            Err(error) => {
+                print_test_tone_error(error);
+                return;
+            }
+        }
+    }
+}
+
+fn sidetone(arguments: &[String]) {
+    let level_percent = match arguments.first().and_then(|argument| parse_number(argument)) {
+        Some(level_percent) if level_percent <= 100 => level_percent as u8,
+        _ => {
+            print_usage();
+            return;
+        }
+    };
+
+    match set_sidetone_level(level_percent) {
+        Ok(_) if level_percent == 0 => println!("Sidetone turned off"),
+        Ok(_) => println!("Sidetone set to {} %", level_percent),
+        Err(error) => print_test_tone_error(error)
+    }
+}
+
+fn events(arguments: &[String]) {
+    let seconds = match arguments.first().and_then(|argument| parse_number(argument)) {
+        Some(seconds) if seconds > 0 => seconds as usize,
+        _ => {
+            print_usage();
+            return;
+        }
+    };
+
+    subscribe_events(true);
+    println!("Waiting {} s for audio events", seconds);
+    for _ in 0..seconds * 1000 / EVENT_POLL_INTERVAL_MS {
+        while let Some(event) = poll_event() {
+            print_event(event);
+        }
+        thread::sleep(EVENT_POLL_INTERVAL_MS);
+    }
+    subscribe_events(false);
+}
+
+fn print_event(event: AudioEvent) {
+    match event {
+        AudioEvent::OutputChanged(Some(endpoint)) => println!("Output moved to endpoint {}:{}", endpoint.codec_address(), endpoint.node_id()),
+        AudioEvent::OutputChanged(None) => println!("No output endpoint left"),
+        AudioEvent::JackInserted(endpoint) => println!("Jack inserted into endpoint {}:{}", endpoint.codec_address(), endpoint.node_id()),
+        AudioEvent::JackRemoved(endpoint) => println!("Jack removed from endpoint {}:{}", endpoint.codec_address(), endpoint.node_id()),
+        AudioEvent::Underrun(stream_descriptor) => println!("Underrun on stream descriptor {}", stream_descriptor),
+        AudioEvent::VolumeChanged { endpoint, volume_percent, muted } => println!("Volume of endpoint {}:{} set to {} %{}",
+            endpoint.codec_address(), endpoint.node_id(), volume_percent, if muted { " (muted)" } else { "" }),
+        AudioEvent::DeviceError(error) => println!("Device error: {:?}", error),
+        AudioEvent::Unknown(code) => println!("Unknown audio event {}", code),
+    }
+}
+
+fn streams() {
+    let streams = active_streams();
+    if streams.is_empty() {
+        println!("No active streams");
+        return;
+    }
+
+    println!("SD  ID  Owner                 Endpoint  Format            State     Fill                FIFO");
+    for stream in streams {
+        let owner = match stream.owner {
+            StreamOwner::Kernel(subsystem) => format!("kernel ({})", subsystem),
+            StreamOwner::Process(id) => format!("process {}", id)
+        };
+        let endpoint = match stream.endpoint {
+            Some(endpoint) => format!("{}:{:#x}", endpoint.codec_address(), endpoint.node_id()),
+            None => String::from("-")
+        };
+        let format = format!("{} Hz/{} bit/{} ch", stream.sample_rate, stream.bits_per_sample, stream.channels);
+        let state = match stream.state {
+            StreamState::Reset => "reset",
+            StreamState::Configured => "configured",
+            StreamState::Prepared => "prepared",
+            StreamState::Running => "running",
+            StreamState::Paused => "paused",
+            StreamState::Draining => "draining",
+            StreamState::Error => "error"
+        };
+        let fill = format!("{}/{} bytes", stream.fill_level, stream.buffer_length);
+        let fifo = match stream.fifo_watermark {
+            Some(watermark) => format!("{} bytes, watermark {} bytes, min buffer {} bytes", stream.fifo_size, watermark, stream.min_buffer_length),
+            None => format!("{} bytes, min buffer {} bytes", stream.fifo_size, stream.min_buffer_length),
+        };
+        println!("{:<3} {:<3} {:<21} {:<9} {:<17} {:<9} {:<19} {}", stream.stream_descriptor, stream.stream_id, owner, endpoint, format, state, fill, fifo);
+    }
+}
+
+fn clock(arguments: &[String]) {
+    let stream_descriptor = match arguments.first().and_then(|argument| parse_number(argument)) {
+        Some(stream_descriptor) => stream_descriptor,
+        None => {
+            print_usage();
+            return;
+        }
+    };
+
+    let clock = match StreamClock::map(stream_descriptor) {
+        Some(clock) => clock,
+        None => {
+            println!("No stream on stream descriptor [{}]!", stream_descriptor);
+            return;
+        }
+    };
+
+    let wall_clock = clock.wall_clock();
+    println!("Wall clock: {} ticks ({} ms)", wall_clock, wall_clock as u64 * 1000 / WALL_CLOCK_FREQUENCY_HZ as u64);
+    println!("Position:   {} bytes", clock.position_in_bytes());
+    if let Some(position) = stream_position(stream_descriptor) {
+        println!("            {} frames", position);
+    }
+}
+
+fn caps() {
+    let capabilities = match capabilities() {
+        Some(capabilities) => capabilities,
+        None => {
+            println!("No sound card available!");
+            return;
+        }
+    };
+    print_route_capabilities("Output", capabilities.output.as_ref());
+    print_route_capabilities("Input", capabilities.input.as_ref());
+}
+
+fn print_route_capabilities(name: &str, route: Option<&RouteCapabilities>) {
+    let route = match route {
+        Some(route) => route,
+        None => {
+            println!("{}: no default endpoint", name);
+            return;
+        }
+    };
+    let sample_rates: Vec<String> = route.sample_rates.iter().map(|sample_rate| format!("{}", sample_rate)).collect();
+    let bits_per_sample: Vec<String> = route.bits_per_sample.iter().map(|bits_per_sample| format!("{}", bits_per_sample)).collect();
+    println!("{}: endpoint {}:{:#x}", name, route.endpoint.codec_address(), route.endpoint.node_id());
+    println!("  Sample rates: {} Hz", sample_rates.join(", "));
+    println!("  Bit depths:   {} bit", bits_per_sample.join(", "));
+    println!("  Channels:     1 to {}", route.max_channels);
+    println!("  Latency:      {} to {} ms", route.min_latency_ms, route.max_latency_ms);
+}
+
+fn trace(arguments: &[String]) {
+    let arguments: Vec<&str> = arguments.iter().map(|argument| argument.as_str()).collect();
+    let available = match arguments.as_slice() {
+        ["on"] => set_verb_trace(true),
+        ["off"] => set_verb_trace(false),
+        ["dump"] => print_verb_trace(DEFAULT_TRACE_DUMP_ENTRIES),
+        ["dump", entries] => match parse_number(entries) {
+            Some(entries) => print_verb_trace(entries as usize),
+            None => {
+                println!("Invalid amount of entries [{}]!", entries);
+                return;
+            }
+        },
+        _ => {
+            print_usage();
+            return;
+        }
+    };
+    if !available {
+        println!("No sound card available!");
+    }
+}
+
+// returns false if there is no sound card
+fn print_verb_trace(entries: usize) -> bool {
+    let text = verb_trace(entries);
+    print!("{}", text);
+    !text.is_empty()
+}
+
+fn print_dump(kind: Dump) {
+    let text = dump(kind);
+    if text.is_empty() {
+        println!("No sound card available!");
+        return;
+    }
+    print!("{}", text);
+}
+
+#[no_mangle]
+pub fn main() {
+    let arguments = process::arguments();
+
+    match arguments.first().map(|argument| argument.as_str()) {
+        Some("play") => play(&arguments[1..]),
+        Some("test-speakers") => test_speakers(&arguments[1..]),
+        Some("sidetone") => sidetone(&arguments[1..]),
+        Some("events") => events(&arguments[1..]),
+        Some("streams") => streams(),
+        Some("caps") => caps(),
+        Some("clock") => clock(&arguments[1..]),
+        Some("regs") => print_dump(Dump::Registers),
+        Some("codecs") => print_dump(Dump::Codecs),
+        Some("path") => print_dump(Dump::PlaybackPaths),
+        Some("validate") => print_dump(Dump::PathValidation),
+        Some("graph") => match arguments.get(1).map(|argument| argument.as_str()) {
+            Some("serial") => print_dump(Dump::CodecGraphToSerialPort),
+            _ => print_dump(Dump::CodecGraph),
+        },
+        Some("errors") => print_dump(Dump::Errors),
+        Some("settings") => match arguments.get(1).map(|argument| argument.as_str()) {
+            Some("serial") => print_dump(Dump::SettingsToSerialPort),
+            _ => print_dump(Dump::Settings),
+        },
+        Some("trace") => trace(&arguments[1..]),
+        _ => print_usage()
+    }
+}
@@ -0,0 +1,88 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+#[allow(unused_imports)]
+use runtime::*;
+use io::{print, println};
+use audio::{dump, route_endpoint, set_widget_gain, set_widget_mute, Dump, Endpoint, MixerError};
+use concurrent::process;
+
+fn print_usage() {
+    println!("Usage: mixer");
+    println!("       Lists the playback and capture paths of all endpoints with the gain, mute state and selected connection of each widget.");
+    println!("       mixer set <codec address>:<node id> gain <percent>");
+    println!("       Sets the gain of a widget. Input endpoints use the amp on their pin widget, all other widgets their output amp.");
+    println!("       mixer mute <codec address>:<node id>");
+    println!("       mixer unmute <codec address>:<node id>");
+    println!("       mixer route <codec address>:<pin node id> <converter node id>");
+    println!("       Selects the connections from an endpoint to a converter, until a stream gets played or recorded on the endpoint.");
+}
+
+fn print_error(error: MixerError) {
+    match error {
+        MixerError::NoAudioDevice => println!("No sound card available!"),
+        MixerError::UnknownWidget => println!("Unknown widget!"),
+        MixerError::NoAmplifier => println!("Widget has no amplifier!"),
+        MixerError::NotMuteCapable => println!("Amplifier of the widget can't be muted!"),
+        MixerError::NotAnEndpoint => println!("Widget is no endpoint (see mixer without arguments)!"),
+        MixerError::NotAConverter => println!("Widget is no converter of the direction of the endpoint!"),
+        MixerError::NoRoute => println!("Endpoint can't be connected to this converter!"),
+        MixerError::Command => println!("Codec didn't respond properly!"),
+        error => println!("Mixer control failed ({:?})!", error)
+    }
+}
+
+// accepts decimal numbers and hexadecimal numbers with prefix 0x, as node ids are printed in hex
+fn parse_number(string: &str) -> Option<u32> {
+    match string.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => string.parse().ok()
+    }
+}
+
+fn parse_widget(string: &str) -> Option<Endpoint> {
+    let (codec_address, node_id) = string.split_once(':')?;
+    Some(Endpoint::new(parse_number(codec_address)? as u8, parse_number(node_id)? as u8))
+}
+
+fn list() {
+    let mixer = dump(Dump::Mixer);
+    if mixer.is_empty() {
+        println!("No sound card available!");
+    } else {
+        print!("{}", mixer);
+    }
+}
+
+fn control(arguments: &[String]) -> Option<Result<(), MixerError>> {
+    match arguments {
+        [command, widget, control, percent] if command == "set" && control == "gain" => {
+            let percent = parse_number(percent).filter(|percent| *percent <= 100)?;
+            Some(set_widget_gain(parse_widget(widget)?, percent as u8))
+        }
+        [command, widget] if command == "mute" => Some(set_widget_mute(parse_widget(widget)?, true)),
+        [command, widget] if command == "unmute" => Some(set_widget_mute(parse_widget(widget)?, false)),
+        [command, endpoint, converter] if command == "route" => {
+            let converter = parse_number(converter).filter(|node_id| *node_id <= u8::MAX as u32)?;
+            Some(route_endpoint(parse_widget(endpoint)?, converter as u8))
+        }
+        _ => None
+    }
+}
+
+#[no_mangle]
+pub fn main() {
+    let arguments = process::arguments();
+    if arguments.is_empty() {
+        list();
+        return;
+    }
+
+    match control(&arguments) {
+        Some(Ok(_)) => {}
+        Some(Err(error)) => print_error(error),
+        None => print_usage()
+    }
+}
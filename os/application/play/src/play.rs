@@ -0,0 +1,103 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+#[allow(unused_imports)]
+use runtime::*;
+use io::{print, println};
+use audio::{pause_playback, play_file, playback_progress, resume_playback, stop_playback, PlaybackError, PlaybackProgress};
+use concurrent::process;
+
+const PROGRESS_BAR_LENGTH: usize = 30;
+
+fn print_usage() {
+    println!("Usage: play <file>");
+    println!("       Streams a WAVE file from the initial ramdisk or a recording (see rec) in the background.");
+    println!("       play status");
+    println!("       Shows the elapsed and the total time of the current playback.");
+    println!("       play pause");
+    println!("       play resume");
+    println!("       play stop");
+}
+
+fn print_error(error: PlaybackError) {
+    match error {
+        PlaybackError::NoAudioDevice => println!("No sound card available!"),
+        PlaybackError::UnsupportedFormat => println!("Audio service only supports 16 bit samples!"),
+        PlaybackError::NoSamples => println!("File does not contain any samples!"),
+        PlaybackError::UnsupportedSampleRate => println!("Sample rate of the sound card is not supported!"),
+        PlaybackError::NotPlaying => println!("Nothing is playing!"),
+        PlaybackError::DeviceError => println!("Sound card failed to set up the stream!"),
+        PlaybackError::FileNotFound => println!("File not found!"),
+        PlaybackError::InvalidWaveFile => println!("Not a WAVE file!"),
+        PlaybackError::UnsupportedEncoding => println!("Only WAVE files with 8, 16, 24 or 32 bit PCM or IMA ADPCM samples can be played!"),
+        PlaybackError::FileExists => println!("File already exists!"),
+        PlaybackError::RecordingTooLong => println!("Not enough space left for the recording!"),
+        PlaybackError::OutputBusy => println!("Another application uses the sound card exclusively!"),
+        PlaybackError::InvalidFileName => println!("Invalid file name!"),
+        PlaybackError::Unknown(code) => println!("Playback failed (Error: {})!", code)
+    }
+}
+
+fn format_time(time_ms: usize) -> String {
+    let seconds = time_ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn print_progress(progress: &PlaybackProgress) {
+    let filled = match progress.total_frames {
+        0 => PROGRESS_BAR_LENGTH,
+        total_frames => progress.played_frames.min(total_frames) * PROGRESS_BAR_LENGTH / total_frames
+    };
+    let state = if progress.is_finished() {
+        " (finished)"
+    } else if progress.paused {
+        " (paused)"
+    } else {
+        ""
+    };
+
+    println!("[{}{}] {} / {}{}", "#".repeat(filled), "-".repeat(PROGRESS_BAR_LENGTH - filled),
+        format_time(progress.elapsed_ms()), format_time(progress.total_ms()), state);
+}
+
+fn play(name: &str) {
+    if let Err(error) = play_file(name) {
+        print_error(error);
+        return;
+    }
+
+    println!("Playing [{}]", name);
+    if let Some(progress) = playback_progress() {
+        print_progress(&progress);
+    }
+}
+
+fn status() {
+    match playback_progress() {
+        Some(progress) => print_progress(&progress),
+        None => println!("Nothing is playing")
+    }
+}
+
+#[no_mangle]
+pub fn main() {
+    let arguments = process::arguments();
+
+    match arguments.first().map(|argument| argument.as_str()) {
+        Some("status") => status(),
+        Some("pause") => match pause_playback() {
+            Ok(_) => status(),
+            Err(error) => print_error(error)
+        },
+        Some("resume") => match resume_playback() {
+            Ok(_) => status(),
+            Err(error) => print_error(error)
+        },
+        Some("stop") => stop_playback(),
+        Some(name) => play(name),
+        None => print_usage()
+    }
+}
@@ -0,0 +1,49 @@
+#![no_std]
+
+extern crate alloc;
+
+#[allow(unused_imports)]
+use runtime::*;
+use io::{print, println};
+use audio::{record_file, PlaybackError};
+use concurrent::process;
+
+fn print_usage() {
+    println!("Usage: rec <seconds> <file>");
+    println!("       Records from the default input (e.g. a microphone) into a WAVE file, which can be played with play.");
+    println!("       Recordings are kept in memory until shutdown, as there is no writable file system.");
+}
+
+fn print_error(error: PlaybackError) {
+    match error {
+        PlaybackError::NoAudioDevice => println!("No sound card available!"),
+        PlaybackError::DeviceError => println!("No input connected or the sound card failed to record!"),
+        PlaybackError::FileExists => println!("File already exists!"),
+        PlaybackError::RecordingTooLong => println!("Not enough space left for a recording of this length!"),
+        error => println!("Recording failed ({:?})!", error)
+    }
+}
+
+#[no_mangle]
+pub fn main() {
+    let arguments = process::arguments();
+    let (seconds, name) = match arguments.as_slice() {
+        [seconds, name] => match seconds.parse::<usize>() {
+            Ok(seconds) if seconds > 0 => (seconds, name),
+            _ => {
+                print_usage();
+                return;
+            }
+        },
+        _ => {
+            print_usage();
+            return;
+        }
+    };
+
+    println!("Recording {} seconds into [{}]", seconds, name);
+    match record_file(name, seconds * 1000) {
+        Ok(_) => println!("Recorded [{}]", name),
+        Err(error) => print_error(error)
+    }
+}
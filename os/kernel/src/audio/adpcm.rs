@@ -0,0 +1,148 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::audio::decoder::{AudioDecoder, DecoderError, FrameBuffer};
+use crate::audio::wav::WavFormat;
+
+// Decoder for IMA ADPCM (also known as DVI ADPCM) in WAVE files, which stores 4 bit differences to the previous sample.
+// The data consists of blocks of block_align bytes, which can be decoded independently of each other. Every block starts
+// with a header of 4 bytes per channel with the first sample (i16) and the index into the step table (u8, followed by a
+// reserved byte). The header sample is the first frame of the block. The differences follow in groups of 4 bytes per channel,
+// each holding 8 samples of one channel, low nibble first.
+
+const BITS_PER_SAMPLE: u16 = 4;
+const BLOCK_HEADER_LENGTH_PER_CHANNEL: usize = 4;
+const GROUP_LENGTH_PER_CHANNEL: usize = 4;
+const SAMPLES_PER_GROUP: usize = 8;
+
+// step sizes of the quantizer, which get selected by the step index (see IMA Digital Audio Focus and Technical Working Groups,
+// "Recommended Practices for Enhancing Digital Audio Compatibility in Multimedia Systems", 1992)
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107, 118, 130, 143,
+    157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411,
+    1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442,
+    11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+// change of the step index after each difference, the sign bit of the difference doesn't matter
+const INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+pub struct ImaAdpcmDecoder {
+    sample_rate: u32,
+    number_of_channels: u8,
+    block_align: usize,
+}
+
+impl ImaAdpcmDecoder {
+    pub fn create(format: &WavFormat) -> Result<Box<dyn AudioDecoder>, DecoderError> {
+        if *format.bits_per_sample() != BITS_PER_SAMPLE {
+            return Err(DecoderError::UnsupportedBitsPerSample(*format.bits_per_sample()));
+        }
+        let number_of_channels = *format.number_of_channels();
+        let header_length = BLOCK_HEADER_LENGTH_PER_CHANNEL * number_of_channels as usize;
+        let group_length = GROUP_LENGTH_PER_CHANNEL * number_of_channels as usize;
+        let block_align = *format.block_align() as usize;
+        if block_align < header_length || (block_align - header_length) % group_length != 0 {
+            return Err(DecoderError::InvalidBlockAlign(*format.block_align()));
+        }
+        Ok(Box::new(Self { sample_rate: *format.sample_rate(), number_of_channels, block_align }))
+    }
+
+    // frames of a block with the given length, which might be the shorter last block of the file
+    fn frames_in_block(&self, block_length: usize) -> usize {
+        let header_length = BLOCK_HEADER_LENGTH_PER_CHANNEL * self.number_of_channels as usize;
+        if block_length < header_length {
+            return 0;
+        }
+        1 + (block_length - header_length) / (GROUP_LENGTH_PER_CHANNEL * self.number_of_channels as usize) * SAMPLES_PER_GROUP
+    }
+
+    fn decode_block(&self, block: &[u8], output: &mut FrameBuffer) -> Result<(), DecoderError> {
+        let number_of_channels = self.number_of_channels as usize;
+        let length_in_frames = self.frames_in_block(block.len());
+        let mut channels: Vec<Vec<i16>> = vec![Vec::with_capacity(length_in_frames); number_of_channels];
+
+        for (channel, samples) in channels.iter_mut().enumerate() {
+            let header = &block[channel * BLOCK_HEADER_LENGTH_PER_CHANNEL..(channel + 1) * BLOCK_HEADER_LENGTH_PER_CHANNEL];
+            let mut predictor = i16::from_le_bytes([header[0], header[1]]) as i32;
+            let mut step_index = header[2] as i32;
+            if step_index >= STEP_TABLE.len() as i32 {
+                return Err(DecoderError::CorruptData);
+            }
+            samples.push(predictor as i16);
+
+            let groups = block[BLOCK_HEADER_LENGTH_PER_CHANNEL * number_of_channels..].chunks_exact(GROUP_LENGTH_PER_CHANNEL * number_of_channels);
+            for group in groups {
+                for byte in &group[channel * GROUP_LENGTH_PER_CHANNEL..(channel + 1) * GROUP_LENGTH_PER_CHANNEL] {
+                    for nibble in [byte & 0x0F, byte >> 4] {
+                        (predictor, step_index) = decode_nibble(nibble, predictor, step_index);
+                        samples.push(predictor as i16);
+                    }
+                }
+            }
+        }
+
+        let mut frame = Vec::with_capacity(number_of_channels);
+        for frame_index in 0..length_in_frames {
+            frame.clear();
+            frame.extend(channels.iter().map(|samples| samples[frame_index]));
+            output.push_frame(&frame);
+        }
+        Ok(())
+    }
+}
+
+impl AudioDecoder for ImaAdpcmDecoder {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn number_of_channels(&self) -> u8 {
+        self.number_of_channels
+    }
+
+    fn block_length_in_bytes(&self) -> usize {
+        self.block_align
+    }
+
+    fn length_in_frames(&self, input_length_in_bytes: usize) -> usize {
+        input_length_in_bytes / self.block_align * self.frames_in_block(self.block_align) + self.frames_in_block(input_length_in_bytes % self.block_align)
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut FrameBuffer) -> Result<usize, DecoderError> {
+        if input.len() < self.block_align {
+            if self.frames_in_block(input.len()) == 0 {
+                return Ok(0);
+            }
+            self.decode_block(input, output)?;
+            return Ok(input.len());
+        }
+
+        let blocks = input.chunks_exact(self.block_align);
+        let consumed = blocks.len() * self.block_align;
+        for block in blocks {
+            self.decode_block(block, output)?;
+        }
+        Ok(consumed)
+    }
+}
+
+// returns the next sample and step index
+fn decode_nibble(nibble: u8, predictor: i32, step_index: i32) -> (i32, i32) {
+    let step = STEP_TABLE[step_index as usize];
+    let mut difference = step >> 3;
+    if nibble & 0b0001 != 0 {
+        difference += step >> 2;
+    }
+    if nibble & 0b0010 != 0 {
+        difference += step >> 1;
+    }
+    if nibble & 0b0100 != 0 {
+        difference += step;
+    }
+    let predictor = match nibble & 0b1000 != 0 {
+        true => predictor - difference,
+        false => predictor + difference,
+    };
+    let step_index = step_index + INDEX_TABLE[nibble as usize];
+    (predictor.clamp(i16::MIN as i32, i16::MAX as i32), step_index.clamp(0, STEP_TABLE.len() as i32 - 1))
+}
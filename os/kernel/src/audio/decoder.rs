@@ -0,0 +1,165 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::audio::adpcm::ImaAdpcmDecoder;
+use crate::audio::wav::{WavFormat, WAVE_FORMAT_IMA_ADPCM, WAVE_FORMAT_PCM};
+
+// Decoders turn the data of a file into interleaved 16 bit frames, as the audio service only plays 16 bit samples. The file
+// gets passed to a decoder in chunks of whole blocks (see fn AudioDecoder::block_length_in_bytes), so that decoders don't have
+// to parse containers and the IHDA code never sees a file format. Decoders get created from the "fmt " chunk of a WAVE file
+// by the constructor registered for its format tag (see DecoderRegistry). PCM and IMA ADPCM are built in.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecoderError {
+    // no decoder has been registered for the format tag
+    UnsupportedEncoding(u16),
+    UnsupportedBitsPerSample(u16),
+    // the block length doesn't fit to the amount of channels (e.g. an ADPCM block without room for the block headers)
+    InvalidBlockAlign(u16),
+    // the file contains data which can't have been produced by the encoder
+    CorruptData,
+}
+
+// interleaved 16 bit samples, which always consist of complete frames
+pub struct FrameBuffer {
+    number_of_channels: u8,
+    samples: Vec<i16>,
+}
+
+impl FrameBuffer {
+    pub fn new(number_of_channels: u8) -> Self {
+        if number_of_channels == 0 {
+            panic!("Frame buffer needs at least one channel");
+        }
+        Self { number_of_channels, samples: Vec::new() }
+    }
+
+    pub fn number_of_channels(&self) -> u8 {
+        self.number_of_channels
+    }
+
+    pub fn length_in_frames(&self) -> usize {
+        self.samples.len() / self.number_of_channels as usize
+    }
+
+    pub fn push_frame(&mut self, frame: &[i16]) {
+        if frame.len() != self.number_of_channels as usize {
+            panic!("Frame buffer: frame with {} samples pushed into a buffer with {} channels", frame.len(), self.number_of_channels);
+        }
+        self.samples.extend_from_slice(frame);
+    }
+
+    // samples of a range of frames, cut off at the last frame in the buffer
+    pub fn frames(&self, frames: Range<usize>) -> &[i16] {
+        let channels = self.number_of_channels as usize;
+        let end = (frames.end * channels).min(self.samples.len());
+        &self.samples[(frames.start * channels).min(end)..end]
+    }
+
+    // removes the first frames, e.g. once they have been played
+    pub fn discard_frames(&mut self, amount: usize) {
+        let amount = amount.min(self.length_in_frames());
+        self.samples.drain(..amount * self.number_of_channels as usize);
+    }
+}
+
+pub trait AudioDecoder: Send {
+    fn sample_rate(&self) -> u32;
+
+    fn number_of_channels(&self) -> u8;
+
+    // Input gets passed in multiples of this length, only the last chunk of a file may be shorter.
+    fn block_length_in_bytes(&self) -> usize;
+
+    // amount of frames the given amount of data decodes to
+    fn length_in_frames(&self, input_length_in_bytes: usize) -> usize;
+
+    // Decodes the blocks of the input and appends their frames to the output, which has the channels of the decoder.
+    // A chunk shorter than a block is the end of the file and gets decoded as far as possible. Returns the amount of bytes
+    // consumed, which is 0 if the input doesn't contain anything that can be decoded.
+    fn decode_chunk(&mut self, input: &[u8], output: &mut FrameBuffer) -> Result<usize, DecoderError>;
+}
+
+pub type DecoderConstructor = fn(&WavFormat) -> Result<Box<dyn AudioDecoder>, DecoderError>;
+
+const BUILTIN_DECODERS: [(u16, DecoderConstructor); 2] = [
+    (WAVE_FORMAT_PCM, PcmDecoder::create),
+    (WAVE_FORMAT_IMA_ADPCM, ImaAdpcmDecoder::create),
+];
+
+// format tags with the constructors of their decoders, registered decoders take precedence over the built-in ones
+pub struct DecoderRegistry {
+    decoders: Vec<(u16, DecoderConstructor)>,
+}
+
+impl DecoderRegistry {
+    pub const fn new() -> Self {
+        Self { decoders: Vec::new() }
+    }
+
+    // replaces the decoder registered for the format tag before
+    pub fn register(&mut self, encoding: u16, constructor: DecoderConstructor) {
+        self.decoders.retain(|(registered_encoding, _)| *registered_encoding != encoding);
+        self.decoders.push((encoding, constructor));
+    }
+
+    pub fn create(&self, format: &WavFormat) -> Result<Box<dyn AudioDecoder>, DecoderError> {
+        let constructor = self.decoders.iter().chain(BUILTIN_DECODERS.iter())
+            .find(|(encoding, _)| encoding == format.encoding())
+            .map(|(_, constructor)| constructor)
+            .ok_or(DecoderError::UnsupportedEncoding(*format.encoding()))?;
+        constructor(format)
+    }
+}
+
+// Uncompressed PCM with 8, 16, 24 or 32 bit samples. 8 bit samples are unsigned and get shifted to the signed range, wider
+// samples are little endian and get cut down to their 16 most significant bits.
+pub struct PcmDecoder {
+    sample_rate: u32,
+    number_of_channels: u8,
+    bytes_per_sample: usize,
+}
+
+impl PcmDecoder {
+    pub fn create(format: &WavFormat) -> Result<Box<dyn AudioDecoder>, DecoderError> {
+        let bits_per_sample = *format.bits_per_sample();
+        if !matches!(bits_per_sample, 8 | 16 | 24 | 32) {
+            return Err(DecoderError::UnsupportedBitsPerSample(bits_per_sample));
+        }
+        Ok(Box::new(Self { sample_rate: *format.sample_rate(), number_of_channels: *format.number_of_channels(), bytes_per_sample: bits_per_sample as usize / 8 }))
+    }
+}
+
+impl AudioDecoder for PcmDecoder {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn number_of_channels(&self) -> u8 {
+        self.number_of_channels
+    }
+
+    fn block_length_in_bytes(&self) -> usize {
+        self.bytes_per_sample * self.number_of_channels as usize
+    }
+
+    fn length_in_frames(&self, input_length_in_bytes: usize) -> usize {
+        input_length_in_bytes / self.block_length_in_bytes()
+    }
+
+    fn decode_chunk(&mut self, input: &[u8], output: &mut FrameBuffer) -> Result<usize, DecoderError> {
+        let bytes_per_sample = self.bytes_per_sample;
+        let mut frame = Vec::with_capacity(self.number_of_channels as usize);
+        let frames = input.chunks_exact(self.block_length_in_bytes());
+        let consumed = frames.len() * self.block_length_in_bytes();
+        for input_frame in frames {
+            frame.clear();
+            frame.extend(input_frame.chunks_exact(bytes_per_sample).map(|sample| match bytes_per_sample {
+                1 => ((sample[0] as i16) - 128) << 8,
+                _ => i16::from_le_bytes([sample[bytes_per_sample - 2], sample[bytes_per_sample - 1]]),
+            }));
+            output.push_frame(&frame);
+        }
+        Ok(consumed)
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use x86_64::PhysAddr;
+use x86_64::structures::paging::frame::PhysFrameRange;
+use crate::audio::format::AudioFormat;
+use crate::audio::refill::BufferCompletions;
+use crate::audio::streams::StreamOwner;
+use crate::device::ihda_api::PlaybackError;
+
+// Sound hardware the audio system can play interleaved 16 bit PCM samples on. Each driver manages the streams it opened itself
+// (e.g. which DMA engine plays them), so the audio system only refers to them by their handle. New sound hardware only has
+// to implement AudioOutputDevice and register itself in the output device registry to be usable by all playback sessions.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputStreamHandle(usize);
+
+impl OutputStreamHandle {
+    pub fn new(value: usize) -> Self {
+        Self(value)
+    }
+
+    pub fn value(&self) -> usize {
+        self.0
+    }
+}
+
+// Layout of the ring of a stream, whose page frames get mapped into a process that writes its samples directly (see
+// AudioOutputDevice::share_ring). The ring consists of buffer_amount buffers, which start buffer_stride_in_bytes apart in the
+// frames, so the byte at position p of the ring lies at (p / buffer_length_in_bytes) * buffer_stride_in_bytes + p % buffer_length_in_bytes.
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct SharedRing {
+    frames: PhysFrameRange,
+    buffer_amount: usize,
+    buffer_length_in_bytes: u32,
+    buffer_stride_in_bytes: u32,
+    // wall clock and position of the stream in bytes, both on pages which can be mapped read-only
+    wall_clock_address: PhysAddr,
+    position_address: PhysAddr,
+}
+
+impl SharedRing {
+    pub fn new(frames: PhysFrameRange, buffer_amount: usize, buffer_length_in_bytes: u32, buffer_stride_in_bytes: u32, wall_clock_address: PhysAddr, position_address: PhysAddr) -> Self {
+        Self {
+            frames,
+            buffer_amount,
+            buffer_length_in_bytes,
+            buffer_stride_in_bytes,
+            wall_clock_address,
+            position_address,
+        }
+    }
+
+    pub fn length_in_bytes(&self) -> u32 {
+        self.buffer_amount as u32 * self.buffer_length_in_bytes
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioDeviceError {
+    UnsupportedFormat,
+    NoFreeStream,
+    UnknownStream(OutputStreamHandle),
+    // the amount of samples written at once must be a multiple of the amount of channels
+    IncompleteFrame,
+    Playback(PlaybackError),
+    // the device can't hand out the ring of a stream (see fn AudioOutputDevice::share_ring)
+    NotShareable,
+    // a write position in frames, which lies outside of the shared ring
+    InvalidWritePosition(u32),
+}
+
+pub trait AudioOutputDevice: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // formats which can be played without resampling
+    fn supported_formats(&self) -> Vec<AudioFormat>;
+
+    // The stream starts playing as soon as its buffer is full for the first time or it gets drained.
+    // Streams may borrow resources of the device, so only registered devices, which live forever, can open them.
+    fn open_stream(&'static self, owner: StreamOwner, format: AudioFormat) -> Result<OutputStreamHandle, AudioDeviceError>;
+
+    // Copies as many whole frames as fit into the buffer of the stream right now and returns the amount of samples copied.
+    fn write(&self, handle: OutputStreamHandle, samples: &[i16]) -> Result<usize, AudioDeviceError>;
+
+    // only affects samples written afterwards
+    fn set_volume(&self, handle: OutputStreamHandle, volume_percent: u8) -> Result<(), AudioDeviceError>;
+
+    // position of the hardware in the buffer of the stream in frames
+    fn position(&self, handle: OutputStreamHandle) -> Result<u32, AudioDeviceError>;
+
+    // Starts the stream if it isn't running yet and returns the amount of frames, which have been written but not played yet.
+    fn drain(&self, handle: OutputStreamHandle) -> Result<u32, AudioDeviceError>;
+
+    // stops the stream immediately
+    fn close_stream(&self, handle: OutputStreamHandle) -> Result<(), AudioDeviceError>;
+
+    // Hands the ring of the stream out to be mapped into a process, which then writes its samples directly instead of through
+    // fn write. The device keeps raising buffer completions for the stream, but leaves the content of the ring alone from now on.
+    fn share_ring(&self, _handle: OutputStreamHandle) -> Result<SharedRing, AudioDeviceError> {
+        Err(AudioDeviceError::NotShareable)
+    }
+
+    // buffer completions of the stream among the completions handled by the refill thread, 0 if the stream raises none
+    fn buffer_completions(&self, _handle: OutputStreamHandle, _completions: &BufferCompletions) -> usize {
+        0
+    }
+
+    // for shared rings: the position in frames behind the last frame the process wrote, which decides how much fn drain waits for
+    fn set_write_position(&self, _handle: OutputStreamHandle, _position: u32) -> Result<(), AudioDeviceError> {
+        Err(AudioDeviceError::NotShareable)
+    }
+}
+
+pub struct OutputDeviceRegistry {
+    devices: Vec<&'static dyn AudioOutputDevice>,
+}
+
+impl OutputDeviceRegistry {
+    pub const fn new() -> Self {
+        Self { devices: Vec::new() }
+    }
+
+    pub fn register(&mut self, device: &'static dyn AudioOutputDevice) {
+        self.devices.push(device);
+    }
+
+    pub fn devices(&self) -> &[&'static dyn AudioOutputDevice] {
+        &self.devices
+    }
+
+    // the first registered device which can play the format, so the device registered first is preferred
+    pub fn select(&self, format: &AudioFormat) -> Option<&'static dyn AudioOutputDevice> {
+        self.devices.iter().find(|device| device.supported_formats().contains(format)).copied()
+    }
+}
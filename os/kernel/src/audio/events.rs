@@ -0,0 +1,144 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::audio::settings::EndpointId;
+
+// Events of the audio stack, which get published at the places where the driver and the audio service detect them and are
+// delivered to kernel listeners (see fn AudioService::subscribe_events) and to subscribed user processes, which poll them
+// with a system call (see fn AudioService::poll_event).
+// Events get published while locks of the driver or the audio service might be held (e.g. underruns during a refill of the
+// mixer), so listeners don't get called right away, but by fn AudioService::dispatch_events, which runs periodically.
+
+// a process which doesn't poll its events in time loses the oldest ones
+const MAX_QUEUED_EVENTS_PER_PROCESS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioEvent {
+    // output gets played on another endpoint now, e.g. because running streams got moved to headphones or the default
+    // codec changed; None if there is no output endpoint left
+    OutputChanged(Option<EndpointId>),
+    JackInserted(EndpointId),
+    JackRemoved(EndpointId),
+    // the DMA engine of the stream descriptor overtook the refills of its owner (see fn Stream::detect_underrun)
+    Underrun(u32),
+    VolumeChanged { endpoint: EndpointId, volume_percent: u8, muted: bool },
+    DeviceError(DeviceError),
+}
+
+impl AudioEvent {
+    // event codes returned to user space, which must match the audio library
+    pub fn code(&self) -> usize {
+        match self {
+            AudioEvent::OutputChanged(_) => 1,
+            AudioEvent::JackInserted(_) => 2,
+            AudioEvent::JackRemoved(_) => 3,
+            AudioEvent::Underrun(_) => 4,
+            AudioEvent::VolumeChanged { .. } => 5,
+            AudioEvent::DeviceError(_) => 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceError {
+    FifoError(u32),
+    // the stream descriptor got reset after the error, which only fails if the DMA engine doesn't stop
+    DescriptorError { stream_descriptor_number: u32, recovered: bool },
+    // the stream watchdog gave up on a DMA engine which stopped advancing (see fn IntelHDAudioDevice::check_stalled_streams)
+    StreamStalled(u32),
+    CodecRemoved(u8),
+    // the CORB DMA engine stopped after a memory error, so both command rings got set up again
+    CommandRingError { recovered: bool },
+}
+
+impl DeviceError {
+    // error codes returned to user space, which must match the audio library
+    pub fn code(&self) -> usize {
+        match self {
+            DeviceError::FifoError(_) => 1,
+            DeviceError::DescriptorError { .. } => 2,
+            DeviceError::StreamStalled(_) => 3,
+            DeviceError::CodecRemoved(_) => 4,
+            DeviceError::CommandRingError { .. } => 5,
+        }
+    }
+}
+
+pub type AudioEventListener = Arc<dyn Fn(&AudioEvent) + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioEventSubscription(usize);
+
+impl AudioEventSubscription {
+    pub fn value(&self) -> usize {
+        self.0
+    }
+}
+
+struct ProcessQueue {
+    process_id: usize,
+    events: VecDeque<AudioEvent>,
+}
+
+pub struct AudioEvents {
+    // published, but not yet passed to the kernel listeners
+    pending: Vec<AudioEvent>,
+    listeners: Vec<(AudioEventSubscription, AudioEventListener)>,
+    next_subscription: usize,
+    process_queues: Vec<ProcessQueue>,
+}
+
+impl AudioEvents {
+    pub const fn new() -> Self {
+        Self { pending: Vec::new(), listeners: Vec::new(), next_subscription: 0, process_queues: Vec::new() }
+    }
+
+    pub fn publish(&mut self, event: AudioEvent) {
+        if !self.listeners.is_empty() {
+            self.pending.push(event);
+        }
+        for queue in self.process_queues.iter_mut() {
+            if queue.events.len() == MAX_QUEUED_EVENTS_PER_PROCESS {
+                queue.events.pop_front();
+            }
+            queue.events.push_back(event);
+        }
+    }
+
+    pub fn subscribe(&mut self, listener: AudioEventListener) -> AudioEventSubscription {
+        let subscription = AudioEventSubscription(self.next_subscription);
+        self.next_subscription += 1;
+        self.listeners.push((subscription, listener));
+        subscription
+    }
+
+    // returns false if the subscription doesn't exist (anymore)
+    pub fn unsubscribe(&mut self, subscription: AudioEventSubscription) -> bool {
+        let listener_amount = self.listeners.len();
+        self.listeners.retain(|(handle, _)| *handle != subscription);
+        self.listeners.len() != listener_amount
+    }
+
+    // A process only receives the events published after it subscribed. Subscribing again keeps the events already queued.
+    pub fn set_process_subscription(&mut self, process_id: usize, enable: bool) {
+        let subscribed = self.process_queues.iter().any(|queue| queue.process_id == process_id);
+        if enable && !subscribed {
+            self.process_queues.push(ProcessQueue { process_id, events: VecDeque::new() });
+        } else if !enable {
+            self.process_queues.retain(|queue| queue.process_id != process_id);
+        }
+    }
+
+    // None if the process has no queued events or isn't subscribed
+    pub fn poll(&mut self, process_id: usize) -> Option<AudioEvent> {
+        self.process_queues.iter_mut().find(|queue| queue.process_id == process_id)?.events.pop_front()
+    }
+
+    // Takes the pending events together with the listeners they have to be passed to, so that the listeners can be called
+    // without holding the lock. Processes don't unsubscribe when they exit, so their queues get dropped here.
+    pub fn take_pending(&mut self, active_process_ids: &[usize]) -> (Vec<AudioEvent>, Vec<AudioEventListener>) {
+        self.process_queues.retain(|queue| active_process_ids.contains(&queue.process_id));
+        let listeners = self.listeners.iter().map(|(_, listener)| listener.clone()).collect();
+        (core::mem::take(&mut self.pending), listeners)
+    }
+}
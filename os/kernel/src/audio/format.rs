@@ -0,0 +1,173 @@
+use core::ops::BitAnd;
+use derive_getters::Getters;
+use crate::device::ihda_api::{BitsPerSample, SampleContainer, SetStreamFormatPayload, StreamFormatResponse, StreamType};
+
+// The one format description shared by the whole audio stack: sessions and output devices negotiate it, streams get
+// created with it and the converters of the codec get programmed with it.
+// The sample rate is stored as base rate, multiple and divisor, as the stream descriptors and converters of Intel HD Audio
+// encode it this way (see table 53 in section 3.7.1 of the specification). SDFMT and the payload of the stream format verb
+// share the same 16 bit layout, so all encoding and decoding happens in fn to_sdfmt_bits and fn from_sdfmt_bits.
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct AudioFormat {
+    number_of_channels: u8,
+    bits_per_sample: BitsPerSample,
+    sample_base_rate_divisor: u8,
+    sample_base_rate_multiple: u8,
+    sample_base_rate: u16,
+    stream_type: StreamType,
+}
+
+impl AudioFormat {
+    fn new(
+        number_of_channels: u8,
+        bits_per_sample: BitsPerSample,
+        sample_base_rate_divisor: u8,
+        sample_base_rate_multiple: u8,
+        sample_base_rate: u16,
+        stream_type: StreamType,
+    ) -> Self {
+        Self {
+            number_of_channels,
+            bits_per_sample,
+            sample_base_rate_divisor,
+            sample_base_rate_multiple,
+            sample_base_rate,
+            stream_type,
+        }
+    }
+
+    pub fn from_sdfmt_bits(raw_value: u16) -> Self {
+        let sample_base_rate_multiple = (raw_value >> 11).bitand(0b111) as u8 + 1;
+        if sample_base_rate_multiple > 4 {
+            panic!("Unsupported sample rate base multiple, see table 53 in section 3.7.1: Stream Format Structure of the specification");
+        }
+        let number_of_channels = (raw_value.bitand(0xF) as u8) + 1;
+        let bits_per_sample = match (raw_value >> 4).bitand(0b111) {
+            0b000 => BitsPerSample::Eight,
+            0b001 => BitsPerSample::Sixteen,
+            0b010 => BitsPerSample::Twenty,
+            0b011 => BitsPerSample::Twentyfour,
+            0b100 => BitsPerSample::Thirtytwo,
+            // 0b101 to 0b111 reserved
+            _ => panic!("Unsupported bit depth, see table 53 in section 3.7.1: Stream Format Structure of the specification")
+        };
+        let sample_base_rate_divisor = (raw_value >> 8).bitand(0b111) as u8 + 1;
+        let sample_base_rate = if ((raw_value >> 14) & 1) != 0 { 44100 } else { 48000 };
+        let stream_type = if ((raw_value >> 15) & 1) != 0 { StreamType::NonPCM } else { StreamType::PCM };
+
+        Self::new(number_of_channels, bits_per_sample, sample_base_rate_divisor, sample_base_rate_multiple, sample_base_rate, stream_type)
+    }
+
+    pub fn to_sdfmt_bits(&self) -> u16 {
+        let number_of_channels = self.number_of_channels - 1;
+        let bits_per_sample = match self.bits_per_sample {
+            BitsPerSample::Eight => 0b000,
+            BitsPerSample::Sixteen => 0b001,
+            BitsPerSample::Twenty => 0b010,
+            BitsPerSample::Twentyfour => 0b011,
+            BitsPerSample::Thirtytwo => 0b100,
+        };
+        let sample_base_rate_divisor = self.sample_base_rate_divisor - 1;
+        let sample_base_rate_multiple = self.sample_base_rate_multiple - 1;
+        let sample_base_rate = if self.sample_base_rate == 44100 { 1 } else { 0 };
+        let stream_type = match self.stream_type {
+            StreamType::PCM => 0,
+            StreamType::NonPCM => 1,
+        };
+        (stream_type as u16) << 15
+            | (sample_base_rate as u16) << 14
+            | (sample_base_rate_multiple as u16) << 11
+            | (sample_base_rate_divisor as u16) << 8
+            | (bits_per_sample as u16) << 4
+            | number_of_channels as u16
+    }
+
+    // payload of the set stream format verb of a converter widget (see section 7.3.3.8 of the specification)
+    pub fn to_converter_payload(&self) -> SetStreamFormatPayload {
+        SetStreamFormatPayload::new(self.to_sdfmt_bits())
+    }
+
+    // format a converter widget reported through the get stream format verb
+    pub fn from_response(response: &StreamFormatResponse) -> Self {
+        Self::from_sdfmt_bits(*response.raw_value())
+    }
+
+    // Finds base rate, multiple and divisor for a PCM sample rate (see table 53 in section 3.7.1 of the specification).
+    // Returns None for rates which can't be derived from 48 kHz or 44.1 kHz.
+    pub fn pcm(number_of_channels: u8, bits_per_sample: BitsPerSample, sample_rate: u32) -> Option<Self> {
+        for sample_base_rate in [48000u16, 44100] {
+            for sample_base_rate_multiple in 1..=4u8 {
+                for sample_base_rate_divisor in 1..=8u8 {
+                    if sample_base_rate as u32 * sample_base_rate_multiple as u32 == sample_rate * sample_base_rate_divisor as u32 {
+                        return Some(Self::new(number_of_channels, bits_per_sample, sample_base_rate_divisor, sample_base_rate_multiple, sample_base_rate, StreamType::PCM));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // IEC 61937 streams (e.g. AC3 passthrough) get transported like 16 bit stereo PCM, but with the non-PCM bit set,
+    // so the sample rate is the one of the compressed stream
+    pub fn non_pcm(sample_rate: u32) -> Option<Self> {
+        Self::pcm(2, BitsPerSample::Sixteen, sample_rate).map(|format| Self {
+            stream_type: StreamType::NonPCM,
+            ..format
+        })
+    }
+
+    pub fn is_pcm(&self) -> bool {
+        matches!(self.stream_type, StreamType::PCM)
+    }
+
+    pub fn mono_48khz_16bit() -> Self {
+        Self::new(1, BitsPerSample::Sixteen, 1, 1, 48000, StreamType::PCM)
+    }
+
+    pub fn stereo_48khz_16bit() -> Self {
+        Self::new(2, BitsPerSample::Sixteen, 1, 1, 48000, StreamType::PCM)
+    }
+
+    pub fn mono_44khz_16bit() -> Self {
+        Self::new(1, BitsPerSample::Sixteen, 1, 1, 44100, StreamType::PCM)
+    }
+
+    pub fn stereo_44khz_16bit() -> Self {
+        Self::new(2, BitsPerSample::Sixteen, 1, 1, 44100, StreamType::PCM)
+    }
+
+    // 96 kHz is the 48 kHz base rate with a multiple of 2 (see table 53 in section 3.7.1 of the specification)
+    pub fn stereo_96khz_16bit() -> Self {
+        Self::new(2, BitsPerSample::Sixteen, 1, 2, 48000, StreamType::PCM)
+    }
+
+    // 24 bit samples are stored in 32 bit containers, so a frame takes 4 bytes per channel
+    pub fn mono_48khz_24bit() -> Self {
+        Self::new(1, BitsPerSample::Twentyfour, 1, 1, 48000, StreamType::PCM)
+    }
+
+    pub fn stereo_48khz_24bit() -> Self {
+        Self::new(2, BitsPerSample::Twentyfour, 1, 1, 48000, StreamType::PCM)
+    }
+
+    pub fn mono_96khz_24bit() -> Self {
+        Self::new(1, BitsPerSample::Twentyfour, 1, 2, 48000, StreamType::PCM)
+    }
+
+    pub fn stereo_96khz_24bit() -> Self {
+        Self::new(2, BitsPerSample::Twentyfour, 1, 2, 48000, StreamType::PCM)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_base_rate as u32 * self.sample_base_rate_multiple as u32 / self.sample_base_rate_divisor as u32
+    }
+
+    // a frame holds one sample container per channel (see specification, section 4.5.1)
+    pub fn frame_size_in_bytes(&self) -> u32 {
+        SampleContainer::size_in_bytes(self.bits_per_sample) * self.number_of_channels as u32
+    }
+
+    pub fn bytes_per_second(&self) -> u32 {
+        self.sample_rate() * self.frame_size_in_bytes()
+    }
+}
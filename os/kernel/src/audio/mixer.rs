@@ -0,0 +1,391 @@
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use crate::audio::mixer;
+use crate::audio::refill::BufferCompletions;
+use crate::audio::resampler::LinearResampler;
+use crate::audio::streams::StreamOwner;
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_api::{PlaybackError, Stream};
+use crate::INTEL_HD_AUDIO;
+
+// Software mixer, which sums up any number of PCM sources into the cyclic buffer of a single output stream.
+// All sources get converted to the format of the mixer when they are added, so mixing is only a saturating addition.
+// The mixer stream raises an interrupt each time the DMA engine finished a buffer, which then gets refilled with the next mixed frames.
+// If the refills fall behind and the DMA engine overtakes them, the buffers it would replay get silenced (see fn Stream::detect_underrun).
+// Notification sources (e.g. system beeps) have a higher priority: while one of them is playing, all other sources get attenuated
+// by the ducking amount. Their own volumes are left untouched, so they are restored as soon as the last notification finished.
+// Looping sources (e.g. alarms) keep repeating a region of their clip without being fed again, until their loop gets released.
+
+pub const MIXER_SAMPLE_RATE: u32 = 48000;
+pub const MIXER_NUMBER_OF_CHANNELS: u8 = 2;
+const MIXER_OUTPUT_STREAM_DESCRIPTOR: usize = 2;
+// four buffers with 1024 frames each, so one buffer lasts about 21 ms
+const MIXER_BUFFER_AMOUNT: u32 = 4;
+const MIXER_PAGES_PER_BUFFER: u32 = 8;
+pub const MAX_SOURCE_VOLUME_PERCENT: u8 = 100;
+pub const DEFAULT_DUCKING_PERCENT: u8 = 70;
+// notifications are meant to be short, so that other sources don't stay attenuated for long
+pub const MAX_NOTIFICATION_LENGTH_IN_MS: usize = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceHandle(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MixerError {
+    NoAudioDevice,
+    // only 16 bit samples can be mixed at the moment
+    UnsupportedBitsPerSample,
+    UnknownSource(SourceHandle),
+    // the notification would be longer than MAX_NOTIFICATION_LENGTH_IN_MS
+    NotificationTooLong,
+    // the loop region doesn't lie within the clip, or its crossfade is longer than the loop or the frames before it
+    InvalidLoopRegion(LoopRegion),
+    Playback(PlaybackError),
+}
+
+// Frames of a clip, which a looping source repeats after it played up to the end of the region. The last crossfade frames
+// of the region get blended with the frames right before its start, so that the jump back to the start doesn't click.
+// All offsets are given in frames at the rate of the clip.
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct LoopRegion {
+    start_in_frames: usize,
+    end_in_frames: usize,
+    crossfade_in_frames: usize,
+}
+
+impl LoopRegion {
+    pub fn new(start_in_frames: usize, end_in_frames: usize, crossfade_in_frames: usize) -> Self {
+        Self { start_in_frames, end_in_frames, crossfade_in_frames }
+    }
+
+    // loops the whole clip without crossfade, as there are no frames before its start
+    pub fn whole_clip(length_in_frames: usize) -> Self {
+        Self::new(0, length_in_frames, 0)
+    }
+
+    fn is_valid(&self, length_in_frames: usize) -> bool {
+        self.start_in_frames < self.end_in_frames && self.end_in_frames <= length_in_frames
+            && self.crossfade_in_frames <= self.start_in_frames && self.crossfade_in_frames <= self.end_in_frames - self.start_in_frames
+    }
+
+    fn resampled(&self, source_rate: u32, target_rate: u32) -> Self {
+        let convert = |frames: usize| (frames as u64 * target_rate as u64 / source_rate as u64) as usize;
+        Self::new(convert(self.start_in_frames), convert(self.end_in_frames), convert(self.crossfade_in_frames))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SourcePriority {
+    Normal,
+    // ducks all normal sources while it is playing
+    Notification,
+}
+
+struct MixerSource {
+    handle: SourceHandle,
+    owner: StreamOwner,
+    // interleaved samples in the format of the mixer
+    samples: Vec<i16>,
+    position_in_frames: usize,
+    volume_percent: u8,
+    paused: bool,
+    priority: SourcePriority,
+    // in frames at the rate of the mixer, None once the loop got released
+    loop_region: Option<LoopRegion>,
+}
+
+impl MixerSource {
+    fn length_in_frames(&self) -> usize {
+        self.samples.len() / MIXER_NUMBER_OF_CHANNELS as usize
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position_in_frames >= self.length_in_frames()
+    }
+
+    fn is_audible_notification(&self) -> bool {
+        self.priority == SourcePriority::Notification && !self.paused && !self.is_finished()
+    }
+
+    // adds the next frames of the source to the accumulator and advances its position
+    fn mix_into(&mut self, accumulator: &mut [i32], volume_percent: i32) {
+        let number_of_channels = MIXER_NUMBER_OF_CHANNELS as usize;
+        let region = match self.loop_region {
+            Some(region) => region,
+            None => {
+                let start = self.position_in_frames * number_of_channels;
+                let end = (start + accumulator.len()).min(self.samples.len());
+                for (index, sample) in self.samples[start..end].iter().enumerate() {
+                    accumulator[index] += *sample as i32 * volume_percent / MAX_SOURCE_VOLUME_PERCENT as i32;
+                }
+                self.position_in_frames += (end - start) / number_of_channels;
+                return;
+            }
+        };
+
+        for frame in accumulator.chunks_exact_mut(number_of_channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample += self.looped_sample(&region, channel) * volume_percent / MAX_SOURCE_VOLUME_PERCENT as i32;
+            }
+            self.position_in_frames += 1;
+            if self.position_in_frames == region.end_in_frames {
+                self.position_in_frames = region.start_in_frames;
+            }
+        }
+    }
+
+    // Within the crossfade, the frames of the region fade out, while the frames leading up to its start fade in. The last
+    // frame of the crossfade is therefore almost the frame before the start, which the jump back to the start continues.
+    fn looped_sample(&self, region: &LoopRegion, channel: usize) -> i32 {
+        let number_of_channels = MIXER_NUMBER_OF_CHANNELS as usize;
+        let sample = self.samples[self.position_in_frames * number_of_channels + channel] as i32;
+        let crossfade_start = region.end_in_frames - region.crossfade_in_frames;
+        if self.position_in_frames < crossfade_start {
+            return sample;
+        }
+
+        let offset = self.position_in_frames - crossfade_start;
+        let lead_in_sample = self.samples[(region.start_in_frames - region.crossfade_in_frames + offset) * number_of_channels + channel] as i32;
+        let crossfade_in_frames = region.crossfade_in_frames as i32;
+        (sample * (crossfade_in_frames - offset as i32) + lead_in_sample * offset as i32) / crossfade_in_frames
+    }
+}
+
+pub struct Mixer {
+    sources: Vec<MixerSource>,
+    next_handle: usize,
+    stream: Option<Stream<'static>>,
+    // index of the next buffer to refill; every buffer between it and the buffer the DMA engine is playing has been played already
+    write_cursor: usize,
+    // attenuation of normal sources in percent of their volume, while a notification is playing
+    ducking_percent: u8,
+}
+
+// the stream only gets accessed while holding the lock of the mixer
+unsafe impl Send for Mixer {}
+
+impl Mixer {
+    pub const fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            next_handle: 0,
+            stream: None,
+            write_cursor: 0,
+            ducking_percent: DEFAULT_DUCKING_PERCENT,
+        }
+    }
+
+    // The source plays once and gets removed afterwards. Mono sources get played on both channels and channels beyond the
+    // second one get dropped.
+    pub fn add_source(&mut self, owner: StreamOwner, samples: &[i16], format: AudioFormat) -> Result<SourceHandle, MixerError> {
+        self.add_source_with_priority(owner, samples, format, SourcePriority::Normal, None)
+    }
+
+    // Same as fn add_source, but the source repeats the loop region after it played up to its end, until fn release_loop
+    // gets called. The region gets converted to the rate of the mixer together with the samples.
+    pub fn add_looping_source(&mut self, owner: StreamOwner, samples: &[i16], format: AudioFormat, region: LoopRegion) -> Result<SourceHandle, MixerError> {
+        let length_in_frames = samples.len() / (*format.number_of_channels()).max(1) as usize;
+        if !region.is_valid(length_in_frames) {
+            return Err(MixerError::InvalidLoopRegion(region));
+        }
+        self.add_source_with_priority(owner, samples, format, SourcePriority::Normal, Some(region))
+    }
+
+    // Same as fn add_source, but all other sources get ducked while the notification is playing.
+    pub fn add_notification(&mut self, owner: StreamOwner, samples: &[i16], format: AudioFormat) -> Result<SourceHandle, MixerError> {
+        let length_in_frames = samples.len() / (*format.number_of_channels()).max(1) as usize;
+        if length_in_frames > MAX_NOTIFICATION_LENGTH_IN_MS * format.sample_rate() as usize / 1000 {
+            return Err(MixerError::NotificationTooLong);
+        }
+        self.add_source_with_priority(owner, samples, format, SourcePriority::Notification, None)
+    }
+
+    fn add_source_with_priority(&mut self, owner: StreamOwner, samples: &[i16], format: AudioFormat, priority: SourcePriority, loop_region: Option<LoopRegion>) -> Result<SourceHandle, MixerError> {
+        if format.bits_per_sample().bit_depth() != 16 {
+            return Err(MixerError::UnsupportedBitsPerSample);
+        }
+        self.ensure_running()?;
+
+        let number_of_channels = *format.number_of_channels();
+        let resampled = LinearResampler::new(format.sample_rate(), MIXER_SAMPLE_RATE, number_of_channels).resample(samples);
+        let mut converted = Vec::with_capacity(resampled.len() / number_of_channels as usize * MIXER_NUMBER_OF_CHANNELS as usize);
+        for frame in resampled.chunks_exact(number_of_channels as usize) {
+            for channel in 0..MIXER_NUMBER_OF_CHANNELS as usize {
+                converted.push(frame[channel.min(frame.len() - 1)]);
+            }
+        }
+
+        // the resampler rounds the length of the clip, so the converted region gets checked against the converted samples again
+        let length_in_frames = converted.len() / MIXER_NUMBER_OF_CHANNELS as usize;
+        let loop_region = loop_region.map(|region| region.resampled(format.sample_rate(), MIXER_SAMPLE_RATE));
+        if let Some(region) = loop_region.filter(|region| !region.is_valid(length_in_frames)) {
+            return Err(MixerError::InvalidLoopRegion(region));
+        }
+
+        let handle = SourceHandle(self.next_handle);
+        self.next_handle += 1;
+        self.sources.push(MixerSource {
+            handle,
+            owner,
+            samples: converted,
+            position_in_frames: 0,
+            volume_percent: MAX_SOURCE_VOLUME_PERCENT,
+            paused: false,
+            priority,
+            loop_region,
+        });
+
+        Ok(handle)
+    }
+
+    pub fn remove_source(&mut self, handle: SourceHandle) -> Result<(), MixerError> {
+        let index = self.sources.iter().position(|source| source.handle == handle).ok_or(MixerError::UnknownSource(handle))?;
+        self.sources.remove(index);
+        Ok(())
+    }
+
+    // removes all sources of a process, e.g. when it exits
+    pub fn remove_sources_of(&mut self, owner: StreamOwner) {
+        self.sources.retain(|source| source.owner != owner);
+    }
+
+    // removes the sources of all processes which aren't active anymore
+    pub fn remove_sources_of_exited_processes(&mut self, active_process_ids: &[usize]) {
+        self.sources.retain(|source| match source.owner {
+            StreamOwner::Process(id) => active_process_ids.contains(&id),
+            StreamOwner::Kernel(_) => true,
+        });
+    }
+
+    // the source finishes the current pass through its loop region and plays the rest of its clip afterwards
+    pub fn release_loop(&mut self, handle: SourceHandle) -> Result<(), MixerError> {
+        self.source_mut(handle)?.loop_region = None;
+        Ok(())
+    }
+
+    pub fn set_volume(&mut self, handle: SourceHandle, volume_percent: u8) -> Result<(), MixerError> {
+        self.source_mut(handle)?.volume_percent = volume_percent.min(MAX_SOURCE_VOLUME_PERCENT);
+        Ok(())
+    }
+
+    // 0 leaves other sources unchanged during notifications, 100 mutes them
+    pub fn set_ducking_percent(&mut self, ducking_percent: u8) {
+        self.ducking_percent = ducking_percent.min(MAX_SOURCE_VOLUME_PERCENT);
+    }
+
+    pub fn ducking_percent(&self) -> u8 {
+        self.ducking_percent
+    }
+
+    // times the DMA engine overtook the refills, e.g. because the refill thread didn't get to run in time
+    pub fn underrun_count(&self) -> usize {
+        self.stream.as_ref().map_or(0, |stream| stream.underrun_count())
+    }
+
+    pub fn is_ducking(&self) -> bool {
+        self.sources.iter().any(|source| source.is_audible_notification())
+    }
+
+    pub fn pause(&mut self, handle: SourceHandle) -> Result<(), MixerError> {
+        self.source_mut(handle)?.paused = true;
+        Ok(())
+    }
+
+    pub fn resume(&mut self, handle: SourceHandle) -> Result<(), MixerError> {
+        self.source_mut(handle)?.paused = false;
+        Ok(())
+    }
+
+    pub fn is_playing(&self, handle: SourceHandle) -> bool {
+        self.sources.iter().any(|source| source.handle == handle && !source.paused)
+    }
+
+    fn source_mut(&mut self, handle: SourceHandle) -> Result<&mut MixerSource, MixerError> {
+        self.sources.iter_mut().find(|source| source.handle == handle).ok_or(MixerError::UnknownSource(handle))
+    }
+
+    // the mixer stream gets opened with the first source and keeps running afterwards, playing silence while there are no sources
+    fn ensure_running(&mut self) -> Result<(), MixerError> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+
+        let device = INTEL_HD_AUDIO.get().ok_or(MixerError::NoAudioDevice)?;
+        let stream_format = AudioFormat::stereo_48khz_16bit();
+        let stream = device.open_output_stream(
+            StreamOwner::Kernel("mixer"),
+            None,
+            stream_format,
+            MIXER_OUTPUT_STREAM_DESCRIPTOR,
+            MIXER_BUFFER_AMOUNT,
+            MIXER_PAGES_PER_BUFFER,
+        ).map_err(MixerError::Playback)?;
+
+        stream.clear_buffers();
+        device.enable_buffer_completion_interrupt(&stream);
+        device.enable_low_latency(MIXER_OUTPUT_STREAM_DESCRIPTOR);
+        device.start_stream(MIXER_OUTPUT_STREAM_DESCRIPTOR, &stream);
+        self.stream = Some(stream);
+        self.write_cursor = 0;
+
+        Ok(())
+    }
+
+    // Fills the buffers the DMA engine finished since the last refill, so that they contain the next frames once the engine wraps
+    // around to them. Buffers the DMA engine replayed, because it overtook the refills, get silenced.
+    fn refill(&mut self, completions: &BufferCompletions) {
+        let stream = match self.stream.as_ref() {
+            Some(stream) => stream,
+            None => return,
+        };
+        let device = INTEL_HD_AUDIO.get().unwrap();
+        let completed_buffers = completions.of(device.output_stream_descriptor_number(MIXER_OUTPUT_STREAM_DESCRIPTOR));
+        if completed_buffers == 0 {
+            return;
+        }
+
+        let current_buffer_index = stream.current_buffer_index();
+        if stream.detect_underrun(self.write_cursor, current_buffer_index, completed_buffers) {
+            let mut buffer_index = current_buffer_index;
+            loop {
+                stream.clear_buffer(buffer_index);
+                buffer_index = (buffer_index + 1) % MIXER_BUFFER_AMOUNT as usize;
+                if buffer_index == self.write_cursor {
+                    break;
+                }
+            }
+        }
+
+        while self.write_cursor != current_buffer_index {
+            // the whole buffer gets ducked, even if the notification ends within it, so the volume doesn't jump back in the middle of it
+            let ducking_percent = if self.is_ducking() { self.ducking_percent } else { 0 };
+            let mixed = Self::mix(&mut self.sources, stream.buffer_length_in_frames(), ducking_percent);
+            stream.write_data_to_buffer(self.write_cursor, &mixed);
+            self.write_cursor = (self.write_cursor + 1) % MIXER_BUFFER_AMOUNT as usize;
+        }
+
+        self.sources.retain(|source| !source.is_finished());
+    }
+
+    // sums up the next frames of all sources, saturating at the limits of a 16 bit sample instead of wrapping around
+    fn mix(sources: &mut [MixerSource], length_in_frames: usize, ducking_percent: u8) -> Vec<i16> {
+        let number_of_channels = MIXER_NUMBER_OF_CHANNELS as usize;
+        let mut accumulator: Vec<i32> = Vec::new();
+        accumulator.resize(length_in_frames * number_of_channels, 0);
+
+        for source in sources.iter_mut().filter(|source| !source.paused) {
+            let volume_percent = match source.priority {
+                SourcePriority::Normal => source.volume_percent as i32 * (MAX_SOURCE_VOLUME_PERCENT - ducking_percent) as i32 / MAX_SOURCE_VOLUME_PERCENT as i32,
+                SourcePriority::Notification => source.volume_percent as i32,
+            };
+            source.mix_into(&mut accumulator, volume_percent);
+        }
+
+        accumulator.iter().map(|sample| (*sample).clamp(i16::MIN as i32, i16::MAX as i32) as i16).collect()
+    }
+}
+
+// Called by the refill thread (see audio::refill) with the buffer completion interrupts of every stream descriptor.
+pub fn handle_buffer_completion(completions: &BufferCompletions) {
+    mixer().lock().refill(completions);
+}
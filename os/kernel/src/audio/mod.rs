@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::audio::device::OutputDeviceRegistry;
+use crate::audio::mixer::Mixer;
+use crate::audio::recordings::RecordingStore;
+use crate::audio::session::SessionTable;
+use crate::audio::streams::{StreamInfo, StreamRegistry};
+use crate::INTEL_HD_AUDIO;
+
+pub mod adpcm;
+pub mod decoder;
+pub mod device;
+pub mod events;
+pub mod format;
+pub mod mixer;
+pub mod persistence;
+pub mod playback;
+pub mod recordings;
+pub mod refill;
+pub mod resampler;
+pub mod service;
+pub mod session;
+pub mod settings;
+pub mod streams;
+pub mod synth;
+pub mod wav;
+
+static STREAM_REGISTRY: Mutex<StreamRegistry> = Mutex::new(StreamRegistry::new());
+static MIXER: Mutex<Mixer> = Mutex::new(Mixer::new());
+static SESSIONS: Mutex<SessionTable> = Mutex::new(SessionTable::new());
+static OUTPUT_DEVICES: Mutex<OutputDeviceRegistry> = Mutex::new(OutputDeviceRegistry::new());
+static RECORDINGS: Mutex<RecordingStore> = Mutex::new(RecordingStore::new());
+
+pub fn stream_registry() -> &'static Mutex<StreamRegistry> {
+    &STREAM_REGISTRY
+}
+
+pub fn mixer() -> &'static Mutex<Mixer> {
+    &MIXER
+}
+
+pub fn sessions() -> &'static Mutex<SessionTable> {
+    &SESSIONS
+}
+
+pub fn output_devices() -> &'static Mutex<OutputDeviceRegistry> {
+    &OUTPUT_DEVICES
+}
+
+pub fn recordings() -> &'static Mutex<RecordingStore> {
+    &RECORDINGS
+}
+
+// snapshot of all streams with up-to-date fill levels and FIFO tuning
+pub fn active_streams() -> Vec<StreamInfo> {
+    let mut streams = STREAM_REGISTRY.lock().streams().clone();
+    if let Some(device) = INTEL_HD_AUDIO.get() {
+        for stream in streams.iter_mut() {
+            stream.update_fifo_tuning(device.fifo_tuning(*stream.stream_descriptor_number()));
+            if stream.state().is_active() {
+                stream.update_fill_level(device.stream_position(*stream.stream_descriptor_number()));
+            }
+        }
+    }
+    streams
+}
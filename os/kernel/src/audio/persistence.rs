@@ -0,0 +1,175 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use derive_getters::Getters;
+use crate::audio::settings::{EndpointId, EndpointKind};
+use crate::device::ihda_api::routing::OutputRouting;
+
+// The part of the audio state that a user chooses and expects to survive a restart: the default codec, the output routing,
+// the roles of retasked pins and the volume and mute of every endpoint.
+// D3OS has no writable storage, so the settings get saved by the host: They can be dumped (also to the serial port) and
+// are read back from the fw_cfg file AUDIO_SETTINGS_FILE when the sound card gets initialized.
+// Any incompatible change to the serialized format requires incrementing PERSISTED_SETTINGS_VERSION.
+pub const PERSISTED_SETTINGS_VERSION: u16 = 1;
+// QEMU gets started with "-fw_cfg name=opt/d3os/audio_settings,file=<file>" to restore settings saved in <file>
+pub const AUDIO_SETTINGS_FILE: &str = "opt/d3os/audio_settings";
+
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct PersistedEndpoint {
+    id: EndpointId,
+    volume_percent: u8,
+    muted: bool,
+}
+
+impl PersistedEndpoint {
+    pub fn new(id: EndpointId, volume_percent: u8, muted: bool) -> Self {
+        Self {
+            id,
+            volume_percent,
+            muted,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Getters)]
+pub struct PersistedSettings {
+    // None if no codec has been selected, so that the first available codec gets used
+    default_codec_address: Option<u8>,
+    output_routing: OutputRouting,
+    pin_roles: Vec<(EndpointId, EndpointKind)>,
+    endpoints: Vec<PersistedEndpoint>,
+}
+
+impl PersistedSettings {
+    pub fn new(default_codec_address: Option<u8>, output_routing: OutputRouting, pin_roles: Vec<(EndpointId, EndpointKind)>, endpoints: Vec<PersistedEndpoint>) -> Self {
+        Self {
+            default_codec_address,
+            output_routing,
+            pin_roles,
+            endpoints,
+        }
+    }
+
+    // serializes the settings into the line based "key=value" format of SoundSettings::serialize:
+    // version=1
+    // default_codec=<codec>|none
+    // routing=<routing>
+    // pin=<codec>:<node> role=<kind>
+    // endpoint=<codec>:<node> volume=<percent> muted=<bool>
+    pub fn serialize(&self) -> String {
+        let mut serialized = String::new();
+        writeln!(serialized, "version={}", PERSISTED_SETTINGS_VERSION).unwrap();
+        match self.default_codec_address {
+            Some(codec_address) => writeln!(serialized, "default_codec={}", codec_address).unwrap(),
+            None => writeln!(serialized, "default_codec=none").unwrap(),
+        }
+        writeln!(serialized, "routing={:?}", self.output_routing).unwrap();
+
+        for (endpoint, role) in self.pin_roles.iter() {
+            writeln!(serialized, "pin={}:{} role={:?}", endpoint.codec_address(), endpoint.node_id(), role).unwrap();
+        }
+        for endpoint in self.endpoints.iter() {
+            writeln!(serialized, "endpoint={}:{} volume={} muted={}",
+                     endpoint.id().codec_address(),
+                     endpoint.id().node_id(),
+                     endpoint.volume_percent(),
+                     endpoint.muted()).unwrap();
+        }
+
+        serialized
+    }
+
+    // Parses settings written by fn serialize. Empty lines are skipped, anything else that can't be parsed rejects the whole
+    // file, as applying half of a damaged file would leave the user with settings they never chose.
+    pub fn parse(serialized: &str) -> Result<Self, PersistedSettingsError> {
+        let mut version = None;
+        let mut settings = Self::new(None, OutputRouting::SinglePin, Vec::new(), Vec::new());
+
+        for (index, line) in serialized.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let line_number = index + 1;
+            let fields = parse_fields(line).ok_or(PersistedSettingsError::InvalidLine(line_number))?;
+            let (key, value) = fields[0];
+
+            // the version has to come first, as it decides how all other lines are read
+            if version.is_none() && key != "version" {
+                return Err(PersistedSettingsError::MissingVersion);
+            }
+
+            match (key, fields.len()) {
+                ("version", 1) => {
+                    let file_version = value.parse().map_err(|_| PersistedSettingsError::InvalidLine(line_number))?;
+                    if file_version != PERSISTED_SETTINGS_VERSION {
+                        return Err(PersistedSettingsError::UnsupportedVersion(file_version));
+                    }
+                    version = Some(file_version);
+                }
+                ("default_codec", 1) => {
+                    settings.default_codec_address = match value {
+                        "none" => None,
+                        codec_address => Some(codec_address.parse().map_err(|_| PersistedSettingsError::InvalidLine(line_number))?),
+                    };
+                }
+                ("routing", 1) => {
+                    settings.output_routing = match value {
+                        "SinglePin" => OutputRouting::SinglePin,
+                        "Mirrored" => OutputRouting::Mirrored,
+                        _ => return Err(PersistedSettingsError::InvalidLine(line_number)),
+                    };
+                }
+                ("pin", 2) if fields[1].0 == "role" => {
+                    let endpoint = parse_endpoint_id(value).ok_or(PersistedSettingsError::InvalidLine(line_number))?;
+                    let role = parse_endpoint_kind(fields[1].1).ok_or(PersistedSettingsError::InvalidLine(line_number))?;
+                    settings.pin_roles.push((endpoint, role));
+                }
+                ("endpoint", 3) if fields[1].0 == "volume" && fields[2].0 == "muted" => {
+                    let id = parse_endpoint_id(value).ok_or(PersistedSettingsError::InvalidLine(line_number))?;
+                    let volume_percent = fields[1].1.parse().map_err(|_| PersistedSettingsError::InvalidLine(line_number))?;
+                    let muted = fields[2].1.parse().map_err(|_| PersistedSettingsError::InvalidLine(line_number))?;
+                    settings.endpoints.push(PersistedEndpoint::new(id, volume_percent, muted));
+                }
+                _ => return Err(PersistedSettingsError::InvalidLine(line_number)),
+            }
+        }
+
+        match version {
+            Some(_) => Ok(settings),
+            None => Err(PersistedSettingsError::MissingVersion),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PersistedSettingsError {
+    MissingVersion,
+    UnsupportedVersion(u16),
+    // line number, starting at 1
+    InvalidLine(usize),
+}
+
+// splits a line into its "key=value" pairs, None if a field has no '='
+fn parse_fields(line: &str) -> Option<Vec<(&str, &str)>> {
+    line.split_whitespace()
+        .map(|field| field.split_once('='))
+        .collect()
+}
+
+// "<codec>:<node>", as written by fn serialize
+fn parse_endpoint_id(string: &str) -> Option<EndpointId> {
+    let (codec_address, node_id) = string.split_once(':')?;
+    Some(EndpointId::new(codec_address.parse().ok()?, node_id.parse().ok()?))
+}
+
+// the Debug names of EndpointKind, as written by fn serialize
+fn parse_endpoint_kind(string: &str) -> Option<EndpointKind> {
+    match string {
+        "LineOut" => Some(EndpointKind::LineOut),
+        "Speaker" => Some(EndpointKind::Speaker),
+        "Headphone" => Some(EndpointKind::Headphone),
+        "DigitalOut" => Some(EndpointKind::DigitalOut),
+        "LineIn" => Some(EndpointKind::LineIn),
+        "Microphone" => Some(EndpointKind::Microphone),
+        "DigitalIn" => Some(EndpointKind::DigitalIn),
+        "Other" => Some(EndpointKind::Other),
+        _ => None,
+    }
+}
@@ -0,0 +1,224 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::audio::streams::StreamState;
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_api::{IntelHDAudioDevice, PlaybackError, Stream};
+
+// Gapless playback of arbitrarily long audio through a single output stream. Instead of looping prefilled buffers, the
+// scheduler keeps a queue of pending PCM chunks and copies them into the buffers the DMA engine has already played.
+// Only every n-th buffer raises an interrupt on completion, so each interrupt refills a whole group of buffers, while the
+// remaining buffers keep the DMA engine busy. If the queue runs dry, the buffers get filled with silence and the underrun
+// gets counted, so that the stream keeps running and continues seamlessly once new chunks arrive. If the refills themselves
+// are too late, the DMA engine overtakes the write cursor and the buffers it would replay get silenced instead.
+
+pub struct PlaybackScheduler {
+    stream: Stream<'static>,
+    output_stream_descriptor_index: usize,
+    // interleaved 16 bit chunks in the format of the stream, which haven't been copied into a buffer completely
+    pending_chunks: VecDeque<Vec<i16>>,
+    // samples of the first pending chunk, which have already been copied into a buffer
+    offset_in_first_chunk: usize,
+    // index of the next buffer to refill; every buffer between it and the buffer the DMA engine is playing has been played already
+    write_cursor: usize,
+    underrun_count: usize,
+    // frames queued since the scheduler got created or reconfigured
+    queued_frames: usize,
+    // frames of queued chunks the DMA engine has completely played, which doesn't include the silence of underruns
+    played_frames: usize,
+    // frames of queued chunks in each buffer, the rest of the buffer is silence
+    frames_in_buffer: Vec<usize>,
+}
+
+// the stream only gets accessed while holding the lock of its owner
+unsafe impl Send for PlaybackScheduler {}
+
+impl PlaybackScheduler {
+    // The interval has to divide the amount of buffers and must be smaller than it, so that some buffers are left to play
+    // while the completed ones get refilled.
+    pub fn new(stream: Stream<'static>, output_stream_descriptor_index: usize, interrupt_interval: usize) -> Self {
+        if interrupt_interval >= stream.buffer_amount() {
+            panic!("Stream {}: an interrupt on completion interval of {} leaves no buffer to play during a refill", stream.id(), interrupt_interval);
+        }
+        if stream.stream_format().bits_per_sample().bit_depth() != 16 {
+            panic!("Stream {}: the playback scheduler only supports 16 bit samples", stream.id());
+        }
+        stream.set_interrupt_on_completion_interval(interrupt_interval);
+
+        let buffer_amount = stream.buffer_amount();
+        Self {
+            stream,
+            output_stream_descriptor_index,
+            pending_chunks: VecDeque::new(),
+            offset_in_first_chunk: 0,
+            write_cursor: 0,
+            underrun_count: 0,
+            queued_frames: 0,
+            played_frames: 0,
+            frames_in_buffer: vec![0; buffer_amount],
+        }
+    }
+
+    pub fn stream(&self) -> &Stream<'static> {
+        &self.stream
+    }
+
+    pub fn output_stream_descriptor_index(&self) -> usize {
+        self.output_stream_descriptor_index
+    }
+
+    // buffers that ran out of queued samples and got padded with silence, plus the times the DMA engine overtook the write cursor
+    pub fn underrun_count(&self) -> usize {
+        self.underrun_count + self.stream.underrun_count()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stream.state().is_active()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.stream.state() == StreamState::Paused
+    }
+
+    // samples which have been queued, but not copied into a buffer yet
+    pub fn pending_samples(&self) -> usize {
+        self.pending_chunks.iter().map(|chunk| chunk.len()).sum::<usize>() - self.offset_in_first_chunk
+    }
+
+    pub fn queued_frames(&self) -> usize {
+        self.queued_frames
+    }
+
+    // Frames of queued chunks that have been played, which are the ones of all completed buffers and the ones of the
+    // current buffer in front of the position of the DMA engine. Doesn't pass fn queued_frames, even if the stream keeps playing silence.
+    pub fn played_frames(&self) -> usize {
+        let buffer_amount = self.stream.buffer_amount();
+        let frame_size_in_bytes = self.stream.stream_format().frame_size_in_bytes();
+        let buffer_length_in_bytes = self.stream.buffer_length_in_bytes() / buffer_amount as u32;
+        let position_in_bytes = self.stream.hardware_position_in_bytes();
+        let current_buffer_index = (position_in_bytes / buffer_length_in_bytes) as usize % buffer_amount;
+        let played_frames_in_current_buffer = ((position_in_bytes % buffer_length_in_bytes) / frame_size_in_bytes) as usize;
+
+        // buffers between the write cursor and the current buffer have been completed, but not refilled yet
+        let mut played_frames = self.played_frames + played_frames_in_current_buffer.min(self.frames_in_buffer[current_buffer_index]);
+        let mut buffer_index = self.write_cursor;
+        while buffer_index != current_buffer_index {
+            played_frames += self.frames_in_buffer[buffer_index];
+            buffer_index = (buffer_index + 1) % buffer_amount;
+        }
+        played_frames.min(self.queued_frames)
+    }
+
+    pub fn queue(&mut self, samples: Vec<i16>) {
+        let number_of_channels = *self.stream.stream_format().number_of_channels() as usize;
+        if samples.len() % number_of_channels != 0 {
+            panic!("Stream {}: {} samples don't make up whole frames of {} channels", self.stream.id(), samples.len(), number_of_channels);
+        }
+        if !samples.is_empty() {
+            self.queued_frames += samples.len() / number_of_channels;
+            self.pending_chunks.push_back(samples);
+        }
+    }
+
+    // fills all buffers from the queue and starts the DMA engine at the first one
+    pub fn start(&mut self, device: &IntelHDAudioDevice) {
+        for buffer_index in 0..self.stream.buffer_amount() {
+            self.fill_buffer(buffer_index);
+        }
+        self.write_cursor = 0;
+        self.played_frames = 0;
+
+        device.enable_buffer_completion_interrupt(&self.stream);
+        device.enable_low_latency(self.output_stream_descriptor_index);
+        device.start_stream(self.output_stream_descriptor_index, &self.stream);
+    }
+
+    // The DMA engine keeps its position and the buffers keep their samples, so fn resume continues without refilling them.
+    pub fn pause(&self, device: &IntelHDAudioDevice) -> Result<(), PlaybackError> {
+        device.pause_stream(self.output_stream_descriptor_index, &self.stream)
+    }
+
+    pub fn resume(&self, device: &IntelHDAudioDevice) {
+        device.resume_stream(self.output_stream_descriptor_index, &self.stream);
+    }
+
+    // Switches the stream to another format, e.g. for the next song. Queued chunks are still in the old format, so they get dropped.
+    pub fn reconfigure(&mut self, device: &IntelHDAudioDevice, stream_format: AudioFormat) -> Result<(), PlaybackError> {
+        if stream_format.bits_per_sample().bit_depth() != 16 {
+            panic!("Stream {}: the playback scheduler only supports 16 bit samples", self.stream.id());
+        }
+        self.pending_chunks.clear();
+        self.offset_in_first_chunk = 0;
+        // the buffers still hold samples in the old format, which don't count as played anymore
+        self.queued_frames = 0;
+        self.played_frames = 0;
+        self.frames_in_buffer.fill(0);
+        device.reconfigure_stream(self.output_stream_descriptor_index, &mut self.stream, stream_format)
+    }
+
+    // Called by the refill thread after the DMA engine completed a buffer, with the amount of buffer completion interrupts since
+    // the last call. Refills every buffer the DMA engine completed since the last call, which are all buffers from the write cursor
+    // up to the one that is currently playing. If the DMA engine wrapped around in the meantime, the buffers from the current one up
+    // to the write cursor have been played again with their old content, so they get silenced rather than being played a third time.
+    pub fn handle_buffer_completion(&mut self, completed_buffers: usize) {
+        let buffer_amount = self.stream.buffer_amount();
+        let current_buffer_index = self.stream.current_buffer_index();
+        if self.stream.detect_underrun(self.write_cursor, current_buffer_index, completed_buffers) {
+            let mut buffer_index = current_buffer_index;
+            loop {
+                self.played_frames += self.frames_in_buffer[buffer_index];
+                self.frames_in_buffer[buffer_index] = 0;
+                self.stream.clear_buffer(buffer_index);
+                buffer_index = (buffer_index + 1) % buffer_amount;
+                if buffer_index == self.write_cursor {
+                    break;
+                }
+            }
+        }
+        while self.write_cursor != current_buffer_index {
+            self.played_frames += self.frames_in_buffer[self.write_cursor];
+            self.fill_buffer(self.write_cursor);
+            self.write_cursor = (self.write_cursor + 1) % buffer_amount;
+        }
+    }
+
+    pub fn close(self, device: &IntelHDAudioDevice) {
+        device.close_stream(self.output_stream_descriptor_index, &self.stream);
+    }
+
+    // The pending chunks get written straight into the buffer (see fn Stream::write_iov), the part of the buffer the queue
+    // can't fill gets silent.
+    fn fill_buffer(&mut self, buffer_index: usize) {
+        let length_in_samples = self.stream.buffer_length_in_frames() * *self.stream.stream_format().number_of_channels() as usize;
+        let mut slices: Vec<&[i16]> = Vec::new();
+        let mut gathered = 0;
+        let mut offset = self.offset_in_first_chunk;
+        for chunk in self.pending_chunks.iter() {
+            if gathered >= length_in_samples {
+                break;
+            }
+            slices.push(&chunk[offset..]);
+            gathered += chunk.len() - offset;
+            offset = 0;
+        }
+        let written = self.stream.write_iov(buffer_index, &slices);
+
+        // drops the chunks which have been copied completely
+        let mut remaining = written;
+        while remaining > 0 {
+            let left_in_first_chunk = self.pending_chunks.front().unwrap().len() - self.offset_in_first_chunk;
+            if remaining < left_in_first_chunk {
+                self.offset_in_first_chunk += remaining;
+                break;
+            }
+            remaining -= left_in_first_chunk;
+            self.pending_chunks.pop_front();
+            self.offset_in_first_chunk = 0;
+        }
+
+        self.frames_in_buffer[buffer_index] = written / *self.stream.stream_format().number_of_channels() as usize;
+        if written < length_in_samples {
+            self.underrun_count += 1;
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// WAVE files recorded by the audio service (see AudioService::record_file). D3OS has no writable file system yet and the
+// initial ramdisk is read only, so recordings are kept in the kernel heap and get looked up by name next to the files of the ramdisk.
+// A recording may still be playing while another one gets made, so recordings are never freed or replaced. The store takes at
+// most MAX_RECORDINGS_SIZE_IN_BYTES of the heap, which is about five seconds of stereo samples at 48 kHz.
+const MAX_RECORDINGS_SIZE_IN_BYTES: usize = 0x100000;
+
+pub struct RecordingStore {
+    recordings: Vec<(String, &'static [u8])>,
+    size_in_bytes: usize,
+}
+
+impl RecordingStore {
+    pub const fn new() -> Self {
+        Self { recordings: Vec::new(), size_in_bytes: 0 }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&'static [u8]> {
+        self.recordings.iter()
+            .find(|(recording_name, _)| recording_name.as_str() == name)
+            .map(|(_, file)| *file)
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        MAX_RECORDINGS_SIZE_IN_BYTES - self.size_in_bytes
+    }
+
+    // None if the file doesn't fit into the store anymore
+    pub fn add(&mut self, name: &str, file: Vec<u8>) -> Option<&'static [u8]> {
+        if self.find(name).is_some() {
+            panic!("Recordings: there already is a recording with the name [{}]", name);
+        }
+        if file.len() > self.free_bytes() {
+            return None;
+        }
+
+        let file: &'static [u8] = file.leak();
+        self.size_in_bytes += file.len();
+        self.recordings.push((name.to_string(), file));
+        Some(file)
+    }
+}
@@ -0,0 +1,81 @@
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::audio;
+use crate::process::thread::Thread;
+use crate::{scheduler, INTEL_HD_AUDIO};
+
+// Buffer refills run in a dedicated kernel thread (bottom half) instead of the interrupt handler of the sound card. The interrupt
+// handler only notes which stream descriptors completed a buffer and wakes the thread up, which is prioritized, so that it runs
+// ahead of all other ready threads (see fn Scheduler::prioritize). So mixing and copying samples doesn't happen with interrupts
+// disabled, but it also doesn't have to wait until busy threads used up their time slices.
+
+// the thread also wakes up on its own, in case a wake up got lost, because the scheduler was locked or the thread was still busy
+const REFILL_FALLBACK_INTERVAL_IN_MS: usize = 5;
+
+// INTSTS has one status bit for each of up to 30 stream descriptors (see specification, section 3.3.15)
+const MAX_STREAM_DESCRIPTORS: usize = 30;
+
+// entry n counts the buffer completion interrupts of stream descriptor n, which haven't been handled by a refill yet
+static PENDING_BUFFER_COMPLETIONS: [AtomicUsize; MAX_STREAM_DESCRIPTORS] = [const { AtomicUsize::new(0) }; MAX_STREAM_DESCRIPTORS];
+// 0 until the refill thread has been started
+static REFILL_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Buffer completion interrupts per stream descriptor since the last refill. A late refill thread sees more than one interrupt
+// of a stream descriptor, which lets the owner of a stream notice that the DMA engine overtook its refills (see fn Stream::detect_underrun).
+pub struct BufferCompletions([usize; MAX_STREAM_DESCRIPTORS]);
+
+impl BufferCompletions {
+    pub fn of(&self, stream_descriptor_number: u32) -> usize {
+        self.0.get(stream_descriptor_number as usize).copied().unwrap_or(0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|completions| *completions == 0)
+    }
+}
+
+pub fn start_refill_thread() {
+    let thread = Thread::new_kernel_thread(Box::new(|| {
+        loop {
+            refill_completed_buffers();
+            scheduler().sleep(REFILL_FALLBACK_INTERVAL_IN_MS);
+        }
+    }));
+    REFILL_THREAD_ID.store(thread.id(), Ordering::Relaxed);
+    scheduler().prioritize(thread.id());
+    scheduler().ready(thread);
+}
+
+// Called by the interrupt handler of the sound card with bit n set for every stream descriptor n that completed a buffer.
+pub fn notify_buffer_completions(completed_stream_descriptors: u32) {
+    if completed_stream_descriptors == 0 {
+        return;
+    }
+
+    for (stream_descriptor_number, completions) in PENDING_BUFFER_COMPLETIONS.iter().enumerate() {
+        if completed_stream_descriptors & (1 << stream_descriptor_number) != 0 {
+            completions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    let thread_id = REFILL_THREAD_ID.load(Ordering::Relaxed);
+    if thread_id != 0 {
+        scheduler().wake_up(thread_id);
+    }
+}
+
+// the software mixer and the streaming playback of the audio service refill their buffers, mapped playback sessions get
+// the position of the DMA engine published, and streams with an interrupt handler of their own get it called
+// (see fn IntelHDAudioDevice::set_stream_interrupt_handler)
+fn refill_completed_buffers() {
+    let completions = BufferCompletions(core::array::from_fn(|stream_descriptor_number| PENDING_BUFFER_COMPLETIONS[stream_descriptor_number].swap(0, Ordering::Relaxed)));
+    if completions.is_empty() {
+        return;
+    }
+
+    audio::mixer::handle_buffer_completion(&completions);
+    audio::service::handle_buffer_completion(&completions);
+    audio::session::handle_buffer_completion(&completions);
+    if let Some(device) = INTEL_HD_AUDIO.get() {
+        device.dispatch_buffer_completions(&completions);
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+// Linear interpolation between the two source frames surrounding the position of each output frame.
+// All positions are computed from the output frame index, so buffers can be filled in any order without keeping state.
+// Good enough for speech and system sounds, but it doesn't filter frequencies above the new Nyquist frequency when downsampling.
+
+#[derive(Clone, Copy, Debug)]
+pub struct LinearResampler {
+    source_rate: u32,
+    target_rate: u32,
+    number_of_channels: u8,
+}
+
+impl LinearResampler {
+    pub fn new(source_rate: u32, target_rate: u32, number_of_channels: u8) -> Self {
+        if source_rate == 0 || target_rate == 0 || number_of_channels == 0 {
+            panic!("Resampler needs sample rates and a channel count greater than 0");
+        }
+
+        Self {
+            source_rate,
+            target_rate,
+            number_of_channels,
+        }
+    }
+
+    pub fn source_rate(&self) -> u32 {
+        self.source_rate
+    }
+
+    pub fn target_rate(&self) -> u32 {
+        self.target_rate
+    }
+
+    pub fn output_length_in_frames(&self, input_length_in_frames: usize) -> usize {
+        (input_length_in_frames as u64 * self.target_rate as u64).div_ceil(self.source_rate as u64) as usize
+    }
+
+    // input contains interleaved samples, returns 0 for frames after the end of the input
+    pub fn sample_at(&self, input: &[i16], output_frame_index: usize, channel: u8) -> i16 {
+        self.sample_in_window_at(input, 0, output_frame_index, channel)
+    }
+
+    // Source frames needed to compute a range of output frames with fn resample_window, limited to the length of the source.
+    pub fn input_window(&self, output_frames: Range<usize>, input_length_in_frames: usize) -> Range<usize> {
+        if output_frames.is_empty() {
+            return 0..0;
+        }
+        let first = (output_frames.start as u64 * self.source_rate as u64 / self.target_rate as u64) as usize;
+        // the last output frame gets interpolated towards the source frame behind it
+        let last = ((output_frames.end - 1) as u64 * self.source_rate as u64 / self.target_rate as u64) as usize + 1;
+        first.min(input_length_in_frames)..(last + 1).min(input_length_in_frames)
+    }
+
+    // Same as fn resample, but only computes a range of output frames from a window of the source starting at
+    // first_input_frame (see fn input_window), so that long sources can be resampled in chunks.
+    pub fn resample_window(&self, window: &[i16], first_input_frame: usize, output_frames: Range<usize>) -> Vec<i16> {
+        let mut output = Vec::with_capacity(output_frames.len() * self.number_of_channels as usize);
+        for frame_index in output_frames {
+            for channel in 0..self.number_of_channels {
+                output.push(self.sample_in_window_at(window, first_input_frame, frame_index, channel));
+            }
+        }
+        output
+    }
+
+    // window contains interleaved samples of the source beginning at first_input_frame
+    fn sample_in_window_at(&self, window: &[i16], first_input_frame: usize, output_frame_index: usize, channel: u8) -> i16 {
+        let channels = self.number_of_channels as usize;
+        let window_length_in_frames = window.len() / channels;
+        let position = output_frame_index as u64 * self.source_rate as u64;
+        let frame_index = (position / self.target_rate as u64) as usize - first_input_frame;
+        let fraction = (position % self.target_rate as u64) as i64;
+
+        if frame_index >= window_length_in_frames {
+            return 0;
+        }
+
+        let current = window[frame_index * channels + channel as usize] as i64;
+        let next = if frame_index + 1 < window_length_in_frames {
+            window[(frame_index + 1) * channels + channel as usize] as i64
+        } else {
+            current
+        };
+
+        (current + (next - current) * fraction / self.target_rate as i64) as i16
+    }
+
+    pub fn resample(&self, input: &[i16]) -> Vec<i16> {
+        let output_length_in_frames = self.output_length_in_frames(input.len() / self.number_of_channels as usize);
+        let mut output = Vec::with_capacity(output_length_in_frames * self.number_of_channels as usize);
+        for frame_index in 0..output_length_in_frames {
+            for channel in 0..self.number_of_channels {
+                output.push(self.sample_at(input, frame_index, channel));
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,637 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use derive_getters::Getters;
+use log::{debug, warn};
+use spin::Mutex;
+use crate::audio::decoder::{AudioDecoder, DecoderConstructor, DecoderError, DecoderRegistry, FrameBuffer};
+use crate::audio::events::{AudioEvent, AudioEventListener, AudioEventSubscription, AudioEvents};
+use crate::audio::mixer;
+use crate::audio::mixer::{LoopRegion, MixerError, SourceHandle};
+use crate::audio::playback::PlaybackScheduler;
+use crate::audio::refill::BufferCompletions;
+use crate::audio::resampler::LinearResampler;
+use crate::audio::{recordings, sessions, stream_registry};
+use crate::audio::session::PreemptionPolicy;
+use crate::audio::streams::{StreamOwner, StreamState};
+use crate::audio::wav::{WavError, WavFile, WavWriter};
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_api::{BitsPerSample, CodecChange, DeviceCapabilities, IntelHDAudioDevice, PlaybackError, Stream};
+use crate::{audio_service, initrd, process_manager, INTEL_HD_AUDIO};
+
+// Kernel-level playback API, so that kernel modules and user processes can produce sound without touching any IHDA registers.
+// The service owns one output stream descriptor, which is separate from the one used for test tones and demos.
+// Every playback has to claim the output from the playback sessions first (see fn SessionTable::claim_output), so that an
+// exclusive session of a process either gets preempted or the playback fails with AudioServiceError::OutputBusy.
+
+const AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR: usize = 1;
+// the minimum amount of entries of a buffer descriptor list (see specification, section 3.6.2)
+const AUDIO_SERVICE_BUFFER_AMOUNT: u32 = 2;
+// gets eight buffers of 4 KiB for a stereo stream at 48 kHz, which raises an interrupt every 43 ms and has 128 ms left to play meanwhile
+// (see BufferTopology::for_latency, which always uses an even amount of buffers)
+const STREAMING_TARGET_LATENCY_IN_MS: u32 = 170;
+const STREAMING_INTERRUPT_INTERVAL: usize = 2;
+// a buffer completion refills STREAMING_INTERRUPT_INTERVAL buffers, so one cycle of decoded samples in reserve is plenty
+const FILE_CYCLES_QUEUED_AHEAD: usize = 2;
+// data of a file passed to its decoder at once, rounded down to whole blocks of the decoder
+const FILE_DECODE_CHUNK_LENGTH_IN_BYTES: usize = 4096;
+
+// set by the exit hook of the process manager, as it can't release anything itself
+static PROCESS_EXITED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioServiceError {
+    NoAudioDevice,
+    // only 16 bit samples can be written into the audio buffers at the moment
+    UnsupportedBitsPerSample,
+    NoSamples,
+    // the rate the codec runs at can't be expressed as a stream format
+    UnsupportedSampleRate(u32),
+    // fn queue got called without opening a stream with fn open_stream first
+    NotStreaming,
+    // fn pause or fn resume got called without a playback
+    NotPlaying,
+    Playback(PlaybackError),
+    Mixer(MixerError),
+    // there is no file with the given name in the initial ramdisk or among the recordings
+    FileNotFound,
+    InvalidWaveFile(WavError),
+    // a recording can't replace a file of the initial ramdisk or an earlier recording
+    FileExists,
+    // the recording doesn't fit into the space left for recordings (see audio::recordings)
+    RecordingTooLong,
+    // no decoder can decode the file (see audio::decoder)
+    Decoder(DecoderError),
+    // another process holds an exclusive session, which the preemption policy doesn't let the caller interrupt
+    OutputBusy,
+    // the file name passed by a system call isn't valid UTF-8
+    InvalidFileName,
+}
+
+impl AudioServiceError {
+    // error codes returned to user space, which must match the audio library
+    pub fn code(&self) -> usize {
+        match self {
+            AudioServiceError::NoAudioDevice => 1,
+            AudioServiceError::UnsupportedBitsPerSample => 2,
+            AudioServiceError::NoSamples => 3,
+            AudioServiceError::UnsupportedSampleRate(_) => 4,
+            AudioServiceError::NotStreaming => 5,
+            AudioServiceError::NotPlaying => 6,
+            AudioServiceError::Playback(_) => 7,
+            AudioServiceError::Mixer(_) => 8,
+            AudioServiceError::FileNotFound => 9,
+            AudioServiceError::InvalidWaveFile(_) => 10,
+            AudioServiceError::FileExists => 11,
+            AudioServiceError::RecordingTooLong => 12,
+            AudioServiceError::Decoder(_) => 13,
+            AudioServiceError::OutputBusy => 14,
+            AudioServiceError::InvalidFileName => 15,
+        }
+    }
+}
+
+// how far a streaming playback got, in frames at the rate of the stream
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct PlaybackProgress {
+    played_frames: usize,
+    total_frames: usize,
+    sample_rate: u32,
+    paused: bool,
+}
+
+// A file, which gets decoded and converted into chunks of one cycle through the buffers of the stream while it plays,
+// as the kernel heap can't hold a whole song. Resampling works on windows of the decoded frames (see
+// LinearResampler::resample_window), so chunks join without gaps. Decoded frames are kept until no window needs them anymore.
+struct FileSource {
+    decoder: Box<dyn AudioDecoder>,
+    data: &'static [u8],
+    // bytes of the data passed to the decoder so far
+    decoded_bytes: usize,
+    // frames decoded from the data, beginning with frame first_decoded_frame of the file
+    decoded: FrameBuffer,
+    first_decoded_frame: usize,
+    // in frames at the rate of the file
+    input_length_in_frames: usize,
+    resampler: LinearResampler,
+    // in frames at the rate of the stream
+    length_in_frames: usize,
+    next_frame: usize,
+}
+
+impl FileSource {
+    fn new(decoder: Box<dyn AudioDecoder>, data: &'static [u8], target_rate: u32) -> Self {
+        let resampler = LinearResampler::new(decoder.sample_rate(), target_rate, decoder.number_of_channels());
+        let input_length_in_frames = decoder.length_in_frames(data.len());
+        let length_in_frames = resampler.output_length_in_frames(input_length_in_frames);
+        let decoded = FrameBuffer::new(decoder.number_of_channels());
+        Self { decoder, data, decoded_bytes: 0, decoded, first_decoded_frame: 0, input_length_in_frames, resampler, length_in_frames, next_frame: 0 }
+    }
+
+    // Queues chunks until the scheduler holds FILE_CYCLES_QUEUED_AHEAD cycles through its buffers or the file is exhausted.
+    // Data the decoder can't decode ends the file early.
+    fn queue_ahead(&mut self, scheduler: &mut PlaybackScheduler) {
+        let stream = scheduler.stream();
+        let chunk_length_in_frames = stream.buffer_length_in_frames() * stream.buffer_amount();
+        let number_of_channels = *stream.stream_format().number_of_channels() as usize;
+        while self.next_frame < self.length_in_frames && scheduler.pending_samples() < FILE_CYCLES_QUEUED_AHEAD * chunk_length_in_frames * number_of_channels {
+            let frames = self.next_frame..(self.next_frame + chunk_length_in_frames).min(self.length_in_frames);
+            let window = self.resampler.input_window(frames.clone(), self.input_length_in_frames);
+            if let Err(error) = self.decode_until(window.end) {
+                warn!("Stopping file playback at frame {}, as the file could not be decoded: {:?}", self.next_frame, error);
+                self.length_in_frames = self.next_frame;
+                return;
+            }
+
+            let decoded_window = self.decoded.frames(window.start - self.first_decoded_frame..window.end - self.first_decoded_frame);
+            let chunk = self.resampler.resample_window(decoded_window, window.start, frames.clone());
+            self.next_frame = frames.end;
+            scheduler.queue(chunk);
+
+            let next_window_start = self.resampler.input_window(frames.end..frames.end + 1, self.input_length_in_frames).start;
+            self.decoded.discard_frames(next_window_start - self.first_decoded_frame);
+            self.first_decoded_frame = next_window_start;
+        }
+    }
+
+    // decodes chunks of the data until the frame before end_frame has been decoded or the data is exhausted
+    fn decode_until(&mut self, end_frame: usize) -> Result<(), DecoderError> {
+        let block_length = self.decoder.block_length_in_bytes();
+        let chunk_length = (FILE_DECODE_CHUNK_LENGTH_IN_BYTES / block_length).max(1) * block_length;
+        while self.first_decoded_frame + self.decoded.length_in_frames() < end_frame && self.decoded_bytes < self.data.len() {
+            let input = &self.data[self.decoded_bytes..(self.decoded_bytes + chunk_length).min(self.data.len())];
+            match self.decoder.decode_chunk(input, &mut self.decoded)? {
+                0 => break,
+                consumed => self.decoded_bytes += consumed,
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Playback {
+    // all buffers hold the samples, which the DMA engine keeps cycling through
+    Looping(Stream<'static>),
+    // the buffers get refilled with queued chunks as the DMA engine completes them
+    Streaming(PlaybackScheduler),
+}
+
+// the stream only gets accessed while holding the lock of the audio service
+unsafe impl Send for Playback {}
+
+impl Playback {
+    fn close(self, device: &IntelHDAudioDevice) {
+        match self {
+            Playback::Looping(stream) => device.close_stream(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR, &stream),
+            Playback::Streaming(scheduler) => scheduler.close(device),
+        }
+    }
+}
+
+pub struct AudioService {
+    playback: Mutex<Option<Playback>>,
+    // file fed into a streaming playback (see fn play_file), must only be locked while holding the lock of the playback
+    file: Mutex<Option<FileSource>>,
+    events: Mutex<AudioEvents>,
+    decoders: Mutex<DecoderRegistry>,
+}
+
+impl AudioService {
+    pub const fn new() -> Self {
+        Self { playback: Mutex::new(None), file: Mutex::new(None), events: Mutex::new(AudioEvents::new()), decoders: Mutex::new(DecoderRegistry::new()) }
+    }
+
+    // Plays interleaved 16 bit samples on the default output endpoint. The samples get copied into the audio buffers of the stream,
+    // which the DMA engine keeps cycling through, so the samples are repeated until fn stop gets called.
+    // Samples with a rate the codec doesn't support get resampled while the buffers are filled.
+    // A playback that is already running gets replaced.
+    pub fn play(&self, samples: &[i16], format: AudioFormat) -> Result<(), AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        if format.bits_per_sample().bit_depth() != 16 {
+            return Err(AudioServiceError::UnsupportedBitsPerSample);
+        }
+        if samples.is_empty() {
+            return Err(AudioServiceError::NoSamples);
+        }
+        claim_output()?;
+
+        let mut playback = self.playback.lock();
+        *self.file.lock() = None;
+        if let Some(previous) = playback.take() {
+            previous.close(device);
+        }
+
+        let number_of_channels = *format.number_of_channels();
+        let target_rate = device.negotiate_output_sample_rate(None, format.sample_rate()).map_err(AudioServiceError::Playback)?;
+        let stream_format = AudioFormat::pcm(number_of_channels, *format.bits_per_sample(), target_rate)
+            .ok_or(AudioServiceError::UnsupportedSampleRate(target_rate))?;
+        let resampler = LinearResampler::new(format.sample_rate(), target_rate, number_of_channels);
+
+        let length_in_frames = resampler.output_length_in_frames(samples.len() / number_of_channels as usize);
+        let length_in_bytes = length_in_frames as u32 * stream_format.frame_size_in_bytes();
+        let pages_per_buffer = device.pages_per_buffer_for(length_in_bytes, AUDIO_SERVICE_BUFFER_AMOUNT);
+        let stream = device.open_output_stream(
+            current_owner(),
+            None,
+            stream_format,
+            AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR,
+            AUDIO_SERVICE_BUFFER_AMOUNT,
+            pages_per_buffer,
+        ).map_err(AudioServiceError::Playback)?;
+
+        // the last buffer gets padded with silence
+        for buffer_index in 0..AUDIO_SERVICE_BUFFER_AMOUNT as usize {
+            stream.write_resampled_data_to_buffer(buffer_index, samples, buffer_index * stream.buffer_length_in_frames(), &resampler);
+        }
+
+        device.start_stream(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR, &stream);
+        *playback = Some(Playback::Looping(stream));
+
+        Ok(())
+    }
+
+    // Opens a stream on the default output endpoint, which plays the chunks passed to fn queue one after another without gaps.
+    // Chunks are not resampled, as a resampler would have to keep its state across chunk boundaries, so the rate has to be
+    // supported by the codec. A looping playback gets replaced, while a stream opened before gets switched to the new format
+    // without reallocating its buffers, which drops the chunks that haven't been played yet.
+    pub fn open_stream(&self, format: AudioFormat) -> Result<(), AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        if format.bits_per_sample().bit_depth() != 16 {
+            return Err(AudioServiceError::UnsupportedBitsPerSample);
+        }
+
+        let target_rate = device.negotiate_output_sample_rate(None, format.sample_rate()).map_err(AudioServiceError::Playback)?;
+        if target_rate != format.sample_rate() {
+            return Err(AudioServiceError::UnsupportedSampleRate(format.sample_rate()));
+        }
+        claim_output()?;
+
+        let mut playback = self.playback.lock();
+        *self.file.lock() = None;
+        if let Some(Playback::Streaming(scheduler)) = playback.as_mut() {
+            return scheduler.reconfigure(device, format).map_err(AudioServiceError::Playback);
+        }
+        if let Some(previous) = playback.take() {
+            previous.close(device);
+        }
+
+        let (stream, topology) = device.open_output_stream_for_latency(
+            current_owner(),
+            None,
+            format,
+            AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR,
+            STREAMING_TARGET_LATENCY_IN_MS,
+        ).map_err(AudioServiceError::Playback)?;
+        debug!("Audio service streams through {} buffers with {} pages each, so the latency is {} ms", topology.buffer_amount(), topology.pages_per_buffer(), topology.latency_in_ms());
+
+        *playback = Some(Playback::Streaming(PlaybackScheduler::new(stream, AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR, STREAMING_INTERRUPT_INTERVAL)));
+        Ok(())
+    }
+
+    // Appends interleaved samples in the format of the stream to the queue. The stream starts with the first chunk and plays
+    // silence whenever the queue runs dry (see fn underrun_count).
+    pub fn queue(&self, samples: Vec<i16>) -> Result<(), AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        let mut playback = self.playback.lock();
+        let scheduler = match playback.as_mut() {
+            Some(Playback::Streaming(scheduler)) => scheduler,
+            _ => return Err(AudioServiceError::NotStreaming),
+        };
+
+        scheduler.queue(samples);
+        // a paused stream only continues with fn resume
+        if !scheduler.is_running() && !scheduler.is_paused() {
+            scheduler.start(device);
+        }
+        Ok(())
+    }
+
+    // Lets WAVE files with the format tag get played with decoders created by the constructor (see audio::decoder),
+    // replacing the decoder registered or built in for the format tag before.
+    pub fn register_decoder(&self, encoding: u16, constructor: DecoderConstructor) {
+        self.decoders.lock().register(encoding, constructor);
+    }
+
+    // Streams a WAVE file from the initial ramdisk or a recording (see audio::wav and fn record_file), replacing any playback
+    // that is already running. The file gets decoded and converted into chunks while it plays (see FileSource), which also
+    // resamples it to a rate the codec supports. Its progress can be followed with fn progress.
+    pub fn play_file(&self, name: &str) -> Result<(), AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        let file = find_file(name).ok_or(AudioServiceError::FileNotFound)?;
+        let wav = WavFile::parse(file).map_err(AudioServiceError::InvalidWaveFile)?;
+        let decoder = self.decoders.lock().create(wav.format()).map_err(AudioServiceError::Decoder)?;
+        if decoder.length_in_frames(wav.data().len()) == 0 {
+            return Err(AudioServiceError::NoSamples);
+        }
+
+        let target_rate = device.negotiate_output_sample_rate(None, decoder.sample_rate()).map_err(AudioServiceError::Playback)?;
+        let format = AudioFormat::pcm(decoder.number_of_channels(), BitsPerSample::Sixteen, target_rate)
+            .ok_or(AudioServiceError::UnsupportedSampleRate(target_rate))?;
+
+        // a stream opened before would keep playing the rest of its buffers in the old format
+        self.stop();
+        self.open_stream(format)?;
+
+        let mut playback = self.playback.lock();
+        let scheduler = match playback.as_mut() {
+            Some(Playback::Streaming(scheduler)) => scheduler,
+            _ => return Err(AudioServiceError::NotStreaming),
+        };
+        let mut source = FileSource::new(decoder, wav.data(), target_rate);
+        source.queue_ahead(scheduler);
+        scheduler.start(device);
+        *self.file.lock() = Some(source);
+
+        Ok(())
+    }
+
+    // Records from the default input endpoint into a 16 bit WAVE file with the channels and the rate the codec captures with,
+    // which can be played with fn play_file afterwards. The recording stops on a buffer boundary (see
+    // IntelHDAudioDevice::record_continuously), so it may be a little longer than requested. Returns its length in frames.
+    pub fn record_file(&self, name: &str, duration_in_ms: usize) -> Result<usize, AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        if find_file(name).is_some() {
+            return Err(AudioServiceError::FileExists);
+        }
+
+        let format = device.capture_format(None).map_err(AudioServiceError::Playback)?;
+        let number_of_channels = *format.number_of_channels();
+        let length_in_frames = IntelHDAudioDevice::continuous_capture_length_in_frames(&format, duration_in_ms);
+        if WavWriter::length_in_bytes(number_of_channels, length_in_frames) > recordings().lock().free_bytes() {
+            return Err(AudioServiceError::RecordingTooLong);
+        }
+
+        let mut writer = WavWriter::new(format.sample_rate(), number_of_channels, length_in_frames);
+        device.record_continuously(current_owner(), None, duration_in_ms, &mut |samples| writer.write(samples))
+            .map_err(AudioServiceError::Playback)?;
+        let length_in_frames = writer.length_in_frames();
+        debug!("Recorded {} frames into [{}]", length_in_frames, name);
+
+        // another recording may have taken the space meanwhile
+        recordings().lock().add(name, writer.finish()).ok_or(AudioServiceError::RecordingTooLong)?;
+        Ok(length_in_frames)
+    }
+
+    // None if the playback doesn't stream chunks (see fn open_stream). The total length is the one of the file being played
+    // (see fn play_file) or the amount of frames queued so far.
+    pub fn progress(&self) -> Option<PlaybackProgress> {
+        let playback = self.playback.lock();
+        let scheduler = match playback.as_ref() {
+            Some(Playback::Streaming(scheduler)) => scheduler,
+            _ => return None,
+        };
+        let total_frames = match self.file.lock().as_ref() {
+            Some(source) => source.length_in_frames,
+            None => scheduler.queued_frames(),
+        };
+
+        Some(PlaybackProgress {
+            played_frames: scheduler.played_frames(),
+            total_frames,
+            sample_rate: scheduler.stream().stream_format().sample_rate(),
+            paused: scheduler.is_paused(),
+        })
+    }
+
+    // formats applications can submit on the default output and input routes, so that they can choose one before opening a stream
+    pub fn capabilities(&self) -> Result<DeviceCapabilities, AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        Ok(device.capabilities())
+    }
+
+    // amount of buffers that had to be padded with silence, because the queue of the stream ran dry
+    pub fn underrun_count(&self) -> usize {
+        match self.playback.lock().as_ref() {
+            Some(Playback::Streaming(scheduler)) => scheduler.underrun_count(),
+            _ => 0,
+        }
+    }
+
+    // Plays a short clip (e.g. a system beep) once through the notification channel of the mixer. All other mixer sources
+    // get attenuated by the ducking amount while it is playing and return to their volume afterwards.
+    pub fn play_notification(&self, samples: &[i16], format: AudioFormat) -> Result<SourceHandle, AudioServiceError> {
+        if samples.is_empty() {
+            return Err(AudioServiceError::NoSamples);
+        }
+        claim_output()?;
+        mixer().lock().add_notification(current_owner(), samples, format).map_err(AudioServiceError::Mixer)
+    }
+
+    // Plays a short clip (e.g. an alarm) through the mixer, repeating its loop region until fn release_loop gets called,
+    // so that the caller doesn't have to feed it again.
+    pub fn play_loop(&self, samples: &[i16], format: AudioFormat, region: LoopRegion) -> Result<SourceHandle, AudioServiceError> {
+        if samples.is_empty() {
+            return Err(AudioServiceError::NoSamples);
+        }
+        claim_output()?;
+        mixer().lock().add_looping_source(current_owner(), samples, format, region).map_err(AudioServiceError::Mixer)
+    }
+
+    // lets a clip started with fn play_loop play to its end and stop
+    pub fn release_loop(&self, handle: SourceHandle) -> Result<(), AudioServiceError> {
+        mixer().lock().release_loop(handle).map_err(AudioServiceError::Mixer)
+    }
+
+    // how much other sources get attenuated during a notification, in percent of their volume
+    pub fn set_notification_ducking_percent(&self, ducking_percent: u8) {
+        mixer().lock().set_ducking_percent(ducking_percent);
+    }
+
+    // Stops the DMA engine of the playback, which keeps its position and the samples in the buffers, so that fn resume
+    // continues exactly where it stopped. Chunks can still be queued while the playback is paused.
+    pub fn pause(&self) -> Result<(), AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        match self.playback.lock().as_ref() {
+            Some(Playback::Looping(stream)) => device.pause_stream(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR, stream),
+            Some(Playback::Streaming(scheduler)) => scheduler.pause(device),
+            None => return Err(AudioServiceError::NotPlaying),
+        }.map_err(AudioServiceError::Playback)
+    }
+
+    pub fn resume(&self) -> Result<(), AudioServiceError> {
+        let device = INTEL_HD_AUDIO.get().ok_or(AudioServiceError::NoAudioDevice)?;
+        match self.playback.lock().as_ref() {
+            Some(Playback::Looping(stream)) => device.resume_stream(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR, stream),
+            Some(Playback::Streaming(scheduler)) => scheduler.resume(device),
+            None => return Err(AudioServiceError::NotPlaying),
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        match self.playback.lock().as_ref() {
+            Some(Playback::Looping(stream)) => stream.state() == StreamState::Paused,
+            Some(Playback::Streaming(scheduler)) => scheduler.is_paused(),
+            None => false,
+        }
+    }
+
+    pub fn stop(&self) {
+        let mut playback = self.playback.lock();
+        *self.file.lock() = None;
+        if let Some(playback) = playback.take() {
+            if let Some(device) = INTEL_HD_AUDIO.get() {
+                playback.close(device);
+            }
+        }
+    }
+
+    // The device restores the routing of all registered streams after a codec changed, so only a playback on a codec that
+    // disappeared has to be stopped, as it would otherwise keep the stream descriptor busy without being audible.
+    pub fn handle_codec_change(&self, change: CodecChange) {
+        let (device, codec_address) = match (INTEL_HD_AUDIO.get(), change) {
+            (Some(device), CodecChange::Removed(codec_address)) => (device, codec_address),
+            _ => return,
+        };
+
+        let stream_descriptor_number = device.output_stream_descriptor_number(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR);
+        let on_removed_codec = stream_registry().lock().streams().iter()
+            .find(|stream| *stream.stream_descriptor_number() == stream_descriptor_number)
+            .and_then(|stream| *stream.endpoint())
+            .is_some_and(|endpoint| *endpoint.codec_address() == codec_address);
+        if on_removed_codec {
+            warn!("Stopping audio service playback, as codec {} has been removed", codec_address);
+            self.stop();
+        }
+    }
+
+    // The stream watchdog of the device gave up on a stream whose DMA engine stopped advancing (see
+    // fn IntelHDAudioDevice::check_stalled_streams). A playback on it would never finish, so it gets stopped.
+    pub fn handle_stalled_stream(&self, stream_descriptor_number: u32) {
+        let device = match INTEL_HD_AUDIO.get() {
+            Some(device) => device,
+            None => return,
+        };
+
+        if stream_descriptor_number == device.output_stream_descriptor_number(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR) && self.is_playing() {
+            warn!("Stopping audio service playback, as its DMA engine stalled");
+            self.stop();
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self.playback.lock().as_ref() {
+            Some(Playback::Looping(stream)) => stream.state().is_active(),
+            Some(Playback::Streaming(scheduler)) => scheduler.is_running(),
+            None => false,
+        }
+    }
+
+    // ########## ownership ##########
+
+    // decides whether the kernel can interrupt an exclusive session of a process (see audio::session)
+    pub fn set_preemption_policy(&self, policy: PreemptionPolicy) {
+        sessions().lock().set_preemption_policy(policy);
+    }
+
+    // Releases the sessions, mixer sources and the playback processes left behind when they exited, so that a crashed process
+    // can't keep the output. Gets called periodically and only does something after a process exited (see fn handle_process_exit).
+    pub fn reclaim_exited_processes(&self) {
+        if !PROCESS_EXITED.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        let active_process_ids = process_manager().read().active_process_ids();
+        sessions().lock().close_sessions_of_exited_processes();
+        mixer().lock().remove_sources_of_exited_processes(&active_process_ids);
+
+        let device = match INTEL_HD_AUDIO.get() {
+            Some(device) => device,
+            None => return,
+        };
+        let stream_descriptor_number = device.output_stream_descriptor_number(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR);
+        let owner = stream_registry().lock().streams().iter()
+            .find(|stream| *stream.stream_descriptor_number() == stream_descriptor_number)
+            .map(|stream| *stream.owner());
+        if let Some(StreamOwner::Process(id)) = owner {
+            if !active_process_ids.contains(&id) {
+                debug!("Stopping audio service playback, as process {} exited", id);
+                self.stop();
+            }
+        }
+    }
+
+    // ########## events ##########
+
+    // Queues the event for the subscribed processes and the kernel listeners, which get called by fn dispatch_events.
+    // Can be called while holding any lock of the driver or the audio service.
+    pub fn publish_event(&self, event: AudioEvent) {
+        debug!("Audio event: {:?}", event);
+        self.events.lock().publish(event);
+    }
+
+    pub fn subscribe_events(&self, listener: AudioEventListener) -> AudioEventSubscription {
+        self.events.lock().subscribe(listener)
+    }
+
+    // returns false if the subscription doesn't exist (anymore)
+    pub fn unsubscribe_events(&self, subscription: AudioEventSubscription) -> bool {
+        self.events.lock().unsubscribe(subscription)
+    }
+
+    pub fn set_event_subscription(&self, process_id: usize, enable: bool) {
+        self.events.lock().set_process_subscription(process_id, enable);
+    }
+
+    pub fn poll_event(&self, process_id: usize) -> Option<AudioEvent> {
+        self.events.lock().poll(process_id)
+    }
+
+    // Passes the events published since the last call to the kernel listeners. Gets called periodically.
+    pub fn dispatch_events(&self) {
+        let active_process_ids = process_manager().read().active_process_ids();
+        let (events, listeners) = self.events.lock().take_pending(&active_process_ids);
+        for event in events.iter() {
+            for listener in listeners.iter() {
+                listener(event);
+            }
+        }
+    }
+}
+
+// Called by the refill thread (see audio::refill) with the buffer completion interrupts of every stream descriptor.
+pub fn handle_buffer_completion(completions: &BufferCompletions) {
+    let device = match INTEL_HD_AUDIO.get() {
+        Some(device) => device,
+        None => return,
+    };
+    let completed_buffers = completions.of(device.output_stream_descriptor_number(AUDIO_SERVICE_OUTPUT_STREAM_DESCRIPTOR));
+    if completed_buffers == 0 {
+        return;
+    }
+
+    let audio_service = audio_service();
+    if let Some(Playback::Streaming(scheduler)) = audio_service.playback.lock().as_mut() {
+        scheduler.handle_buffer_completion(completed_buffers);
+        if let Some(source) = audio_service.file.lock().as_mut() {
+            source.queue_ahead(scheduler);
+        }
+    }
+}
+
+// Exit hook of the process manager (see fn ProcessManager::register_exit_hook), which runs on the exiting thread, so the
+// resources of the process get released later by the audio thread in fn AudioService::reclaim_exited_processes.
+pub fn handle_process_exit(_process_id: usize) {
+    PROCESS_EXITED.store(true, Ordering::Relaxed);
+}
+
+fn claim_output() -> Result<(), AudioServiceError> {
+    sessions().lock().claim_output(current_owner()).map_err(|_| AudioServiceError::OutputBusy)
+}
+
+fn current_owner() -> StreamOwner {
+    let process_manager = process_manager().read();
+    let current_process = process_manager.current_process();
+    match process_manager.kernel_process() {
+        Some(kernel_process) if kernel_process.id() == current_process.id() => StreamOwner::Kernel("audio service"),
+        _ => StreamOwner::Process(current_process.id()),
+    }
+}
+
+// recordings can't have the name of a file in the initial ramdisk (see fn record_file), so the order of the lookup doesn't matter
+fn find_file(name: &str) -> Option<&'static [u8]> {
+    initrd().entries()
+        .find(|entry| entry.filename().as_str() == name)
+        .map(|entry| entry.data())
+        .or_else(|| recordings().lock().find(name))
+}
@@ -0,0 +1,449 @@
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use log::{debug, warn};
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::{PhysAddr, VirtAddr};
+use crate::audio::device::{AudioDeviceError, AudioOutputDevice, OutputStreamHandle, SharedRing};
+use crate::audio::format::AudioFormat;
+use crate::audio::output_devices;
+use crate::audio::refill::BufferCompletions;
+use crate::audio::sessions;
+use crate::audio::streams::StreamOwner;
+use crate::device::ihda_api::{BitsPerSample, PlaybackError};
+use crate::memory::dma::{alloc_dma, CachePolicy, DmaRegion};
+use crate::memory::{MemorySpace, PAGE_SIZE};
+use crate::memory::r#virtual::{VirtualMemoryArea, VmaType};
+use crate::process_manager;
+
+// Playback sessions of user processes, which back the audio system calls. Every session plays a stream of interleaved 16 bit
+// samples on the first registered output device supporting its format (see OutputDeviceRegistry::select). The samples get
+// copied from user space into the buffers of the device, so processes never see DMA memory.
+// Processes which run an audio engine of their own can map the ring of a session instead (see fn SessionTable::map) and write
+// their samples into it directly. The process and the kernel then share a control page with the following u32 fields, which
+// must match the audio library:
+// - the write position (head) in frames, which the process advances behind the frames it wrote
+// - the read position (tail) in frames, which the kernel updates to the position of the DMA engine on every buffer completion
+// - the amount of buffer completions, which the kernel increments on every buffer completion
+// The kernel adopts the write position on every buffer completion and when the session gets started or drained, so that
+// draining waits for the frames the process wrote. Frames between the tail and the head must not be changed anymore.
+// A session can be made exclusive (see fn SessionTable::set_mode), so that no other process can start playing while it
+// exists. Whether the kernel can still play (e.g. system sounds) depends on the preemption policy: a kernel request either
+// closes the exclusive session, whose owner learns about it with SessionError::Preempted on its next call, or gets rejected.
+// Sessions of processes which exited get closed periodically (see fn AudioService::reclaim_exited_processes), so that a
+// crashed process can't keep the output for itself.
+
+pub const MAX_SESSION_VOLUME_PERCENT: u8 = 100;
+
+// user space address of the shared ring of the session with handle 0, the regions of the other sessions follow one after another
+const SHARED_RING_REGIONS_START: u64 = 0x310000000000;
+const SHARED_RING_REGION_PAGES: u64 = 256;
+// the control page, the wall clock page and the position page come before the ring
+const SHARED_RING_HEADER_PAGES: u64 = 3;
+// indices of the u32 fields in the control page
+const CONTROL_WRITE_POSITION: usize = 0;
+const CONTROL_READ_POSITION: usize = 1;
+const CONTROL_BUFFER_COMPLETIONS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SessionHandle(usize);
+
+impl SessionHandle {
+    pub fn new(value: usize) -> Self {
+        Self(value)
+    }
+
+    pub fn value(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionError {
+    NoAudioDevice,
+    // no registered output device can play 16 bit PCM with the requested rate and amount of channels
+    UnsupportedFormat,
+    // the selected output device can't play any more streams at the same time
+    NoFreeStreamDescriptor,
+    // sessions of other processes are unknown as well
+    UnknownSession(SessionHandle),
+    // the amount of samples written at once must be a multiple of the amount of channels
+    IncompleteFrame,
+    Playback(PlaybackError),
+    // the output device of the session can't share its ring, or the stream is already running
+    NotMappable,
+    // the ring of the session is mapped, so its samples get written by the process directly
+    Mapped,
+    // the control page of a mapped session holds a write position outside of the ring
+    InvalidWritePosition(u32),
+    // another process holds an exclusive session, or a session can't become exclusive as other processes play as well
+    OutputBusy,
+    // the exclusive session got closed, so that the kernel could play (see PreemptionPolicy)
+    Preempted(SessionHandle),
+}
+
+impl SessionError {
+    fn from_device_error(error: AudioDeviceError, handle: SessionHandle) -> Self {
+        match error {
+            AudioDeviceError::UnsupportedFormat => SessionError::UnsupportedFormat,
+            AudioDeviceError::NoFreeStream => SessionError::NoFreeStreamDescriptor,
+            AudioDeviceError::UnknownStream(_) => SessionError::UnknownSession(handle),
+            AudioDeviceError::IncompleteFrame => SessionError::IncompleteFrame,
+            AudioDeviceError::Playback(error) => SessionError::Playback(error),
+            AudioDeviceError::NotShareable => SessionError::NotMappable,
+            AudioDeviceError::InvalidWritePosition(position) => SessionError::InvalidWritePosition(position),
+        }
+    }
+
+    // error codes returned to user space, which must match the audio library
+    pub fn code(&self) -> usize {
+        match self {
+            SessionError::NoAudioDevice => 1,
+            SessionError::UnsupportedFormat => 2,
+            SessionError::NoFreeStreamDescriptor => 3,
+            SessionError::UnknownSession(_) => 4,
+            SessionError::IncompleteFrame => 5,
+            SessionError::Playback(_) => 6,
+            SessionError::NotMappable => 7,
+            SessionError::Mapped => 8,
+            SessionError::InvalidWritePosition(_) => 9,
+            SessionError::OutputBusy => 10,
+            SessionError::Preempted(_) => 11,
+        }
+    }
+}
+
+// user space addresses of a mapped session and the layout of its ring (see struct SharedRing)
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct SessionMapping {
+    control_address: VirtAddr,
+    ring_address: VirtAddr,
+    wall_clock_address: VirtAddr,
+    position_address: VirtAddr,
+    ring: SharedRing,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionMode {
+    // plays together with the sessions of other processes and the kernel
+    Shared,
+    // other processes can't play while the session exists, the kernel only as allowed by the preemption policy
+    Exclusive,
+}
+
+// what happens to a request of the kernel for output, while a process holds an exclusive session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreemptionPolicy {
+    // the exclusive session gets closed, so that e.g. system sounds are always audible
+    PreemptExclusive,
+    // the request fails with SessionError::OutputBusy, so that the exclusive session is never interrupted
+    RejectRequests,
+}
+
+struct RingMapping {
+    // a single page, which gets freed together with the session
+    control_page: DmaRegion,
+    area: VirtualMemoryArea,
+}
+
+impl RingMapping {
+    fn read(&self, field: usize) -> u32 {
+        unsafe { (self.control_page.virtual_address().as_u64() as *const u32).add(field).read_volatile() }
+    }
+
+    fn write(&self, field: usize, value: u32) {
+        unsafe { (self.control_page.virtual_address().as_u64() as *mut u32).add(field).write_volatile(value) }
+    }
+}
+
+struct Session {
+    handle: SessionHandle,
+    owner: StreamOwner,
+    format: AudioFormat,
+    device: &'static dyn AudioOutputDevice,
+    stream: OutputStreamHandle,
+    ring_mapping: Option<RingMapping>,
+    mode: SessionMode,
+}
+
+impl Session {
+    // Stops the stream and releases it on its device. A mapped ring gets unmapped from the owner before the device releases
+    // its memory, unless the owner already exited, which unmapped it on exit (see Process::release_device_vmas).
+    fn release(self) -> Result<(), AudioDeviceError> {
+        if let (Some(ring_mapping), StreamOwner::Process(id)) = (self.ring_mapping.as_ref(), self.owner) {
+            if let Some(process) = process_manager().read().active_process(id) {
+                if let Some(area) = process.remove_vma(ring_mapping.area) {
+                    process.address_space().unmap(area.range(), false);
+                }
+            }
+        }
+        self.device.close_stream(self.stream)
+    }
+
+    // hands the write position of the control page to the device, so that it knows how many frames are left to play
+    fn adopt_write_position(&self) -> Result<(), SessionError> {
+        match self.ring_mapping.as_ref() {
+            Some(ring_mapping) => self.device.set_write_position(self.stream, ring_mapping.read(CONTROL_WRITE_POSITION))
+                .map_err(|error| SessionError::from_device_error(error, self.handle)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct SessionTable {
+    sessions: Vec<Session>,
+    next_handle: usize,
+    policy: PreemptionPolicy,
+    // exclusive sessions closed by the kernel, which stay known until their owner closes them as well
+    preempted: Vec<(StreamOwner, SessionHandle)>,
+}
+
+impl SessionTable {
+    pub const fn new() -> Self {
+        Self {
+            sessions: Vec::new(),
+            next_handle: 1,
+            policy: PreemptionPolicy::PreemptExclusive,
+            preempted: Vec::new(),
+        }
+    }
+
+    pub fn preemption_policy(&self) -> PreemptionPolicy {
+        self.policy
+    }
+
+    pub fn set_preemption_policy(&mut self, policy: PreemptionPolicy) {
+        self.policy = policy;
+    }
+
+    // Must be called before the owner starts playing anything else than a session (e.g. through the audio service or the
+    // mixer). Fails if another process holds an exclusive session, unless the owner is the kernel and the preemption policy
+    // lets it close the session.
+    pub fn claim_output(&mut self, owner: StreamOwner) -> Result<(), SessionError> {
+        self.close_sessions_of_exited_processes();
+        let index = match self.sessions.iter().position(|session| session.mode == SessionMode::Exclusive && session.owner != owner) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+
+        match (owner, self.policy) {
+            (StreamOwner::Kernel(name), PreemptionPolicy::PreemptExclusive) => {
+                let session = self.sessions.remove(index);
+                let handle = session.handle;
+                warn!("Session {}: preempted by kernel request of [{}]", handle.value(), name);
+                self.preempted.push((session.owner, handle));
+                if let Err(error) = session.release() {
+                    warn!("Session {}: failed to close preempted stream: {:?}", handle.value(), error);
+                }
+                Ok(())
+            }
+            _ => Err(SessionError::OutputBusy),
+        }
+    }
+
+    // Opens a stream on an output device, which starts playing as soon as its buffer is full for the first time.
+    pub fn open(&mut self, owner: StreamOwner, sample_rate: u32, number_of_channels: u8) -> Result<SessionHandle, SessionError> {
+        if number_of_channels == 0 {
+            return Err(SessionError::UnsupportedFormat);
+        }
+        let format = AudioFormat::pcm(number_of_channels, BitsPerSample::Sixteen, sample_rate).ok_or(SessionError::UnsupportedFormat)?;
+        self.claim_output(owner)?;
+
+        let handle = SessionHandle(self.next_handle);
+        let device = {
+            let output_devices = output_devices().lock();
+            if output_devices.devices().is_empty() {
+                return Err(SessionError::NoAudioDevice);
+            }
+            output_devices.select(&format).ok_or(SessionError::UnsupportedFormat)?
+        };
+        let stream = device.open_stream(owner, format).map_err(|error| SessionError::from_device_error(error, handle))?;
+
+        self.next_handle += 1;
+        self.sessions.push(Session {
+            handle,
+            owner,
+            format,
+            device,
+            stream,
+            ring_mapping: None,
+            mode: SessionMode::Shared,
+        });
+
+        Ok(handle)
+    }
+
+    // copies as many whole frames as fit into the buffer of the device and returns the amount of samples copied
+    pub fn write(&mut self, owner: StreamOwner, handle: SessionHandle, samples: &[i16]) -> Result<usize, SessionError> {
+        let session = self.find(owner, handle)?;
+        if session.ring_mapping.is_some() {
+            return Err(SessionError::Mapped);
+        }
+        if samples.len() % *session.format.number_of_channels() as usize != 0 {
+            return Err(SessionError::IncompleteFrame);
+        }
+        session.device.write(session.stream, samples).map_err(|error| SessionError::from_device_error(error, handle))
+    }
+
+    // Only affects samples written afterwards, so samples which are already in the buffer keep their volume. The samples of
+    // mapped sessions never pass the kernel, so the process has to scale them itself.
+    pub fn set_volume(&mut self, owner: StreamOwner, handle: SessionHandle, volume_percent: u8) -> Result<(), SessionError> {
+        let session = self.find(owner, handle)?;
+        if session.ring_mapping.is_some() {
+            return Err(SessionError::Mapped);
+        }
+        session.device.set_volume(session.stream, volume_percent.min(MAX_SESSION_VOLUME_PERCENT)).map_err(|error| SessionError::from_device_error(error, handle))
+    }
+
+    // Starts the stream if the buffer never got full and returns the time in ms until all samples in the buffer have been played.
+    pub fn drain(&mut self, owner: StreamOwner, handle: SessionHandle) -> Result<usize, SessionError> {
+        let session = self.find(owner, handle)?;
+        session.adopt_write_position()?;
+        let frames = session.device.drain(session.stream).map_err(|error| SessionError::from_device_error(error, handle))?;
+        Ok((frames as usize * 1000).div_ceil(session.format.sample_rate() as usize))
+    }
+
+    // Stops the stream immediately and releases it on its device (see fn Session::release). Closing a preempted session
+    // succeeds, so that its handle is forgotten.
+    pub fn close(&mut self, owner: StreamOwner, handle: SessionHandle) -> Result<(), SessionError> {
+        if let Some(index) = self.preempted.iter().position(|preempted| *preempted == (owner, handle)) {
+            self.preempted.remove(index);
+            return Ok(());
+        }
+        let index = self.sessions.iter().position(|session| session.handle == handle && session.owner == owner).ok_or(SessionError::UnknownSession(handle))?;
+        let session = self.sessions.remove(index);
+        session.release().map_err(|error| SessionError::from_device_error(error, handle))
+    }
+
+    // An exclusive session can only be created while no other process has a session, but it doesn't stop the sessions of
+    // its owner or playbacks of the kernel, which are already running.
+    pub fn set_mode(&mut self, owner: StreamOwner, handle: SessionHandle, mode: SessionMode) -> Result<(), SessionError> {
+        self.close_sessions_of_exited_processes();
+        self.find(owner, handle)?;
+        let others_playing = self.sessions.iter().any(|session| session.owner != owner);
+        if mode == SessionMode::Exclusive && others_playing {
+            return Err(SessionError::OutputBusy);
+        }
+        self.find_mut(owner, handle)?.mode = mode;
+        Ok(())
+    }
+
+    // Maps the control page, the wall clock, the position of the stream and the ring of a session into the current process,
+    // which has to be its owner. The wall clock and the position are read-only, the others read-write. Afterwards, the process
+    // writes its samples into the ring directly (see the comment at the top) and fn write is rejected. The ring can only be
+    // mapped once and before the stream gets started.
+    pub fn map(&mut self, owner: StreamOwner, handle: SessionHandle) -> Result<SessionMapping, SessionError> {
+        let session = self.find_mut(owner, handle)?;
+        if session.ring_mapping.is_some() {
+            return Err(SessionError::Mapped);
+        }
+        let ring = session.device.share_ring(session.stream).map_err(|error| SessionError::from_device_error(error, handle))?;
+        let ring_pages = ring.frames().end - ring.frames().start;
+        if SHARED_RING_HEADER_PAGES + ring_pages > SHARED_RING_REGION_PAGES {
+            return Err(SessionError::NotMappable);
+        }
+
+        let control_page = alloc_dma(1, CachePolicy::WriteBack);
+        let region_start = SHARED_RING_REGIONS_START + handle.value() as u64 * SHARED_RING_REGION_PAGES * PAGE_SIZE as u64;
+        let start_page = Page::from_start_address(VirtAddr::new(region_start)).unwrap();
+        let read_write = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let read_only_registers = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_CACHE;
+        // the ring is mapped uncached in the kernel as well, so that the DMA engine sees the samples right away
+        let ring_flags = read_write | PageTableFlags::NO_CACHE;
+
+        let process = process_manager().read().current_process();
+        let address_space = process.address_space();
+        address_space.map_physical(control_page.frames(), PageRange { start: start_page, end: start_page + 1 }, MemorySpace::User, read_write);
+        for (index, address) in [*ring.wall_clock_address(), *ring.position_address()].iter().enumerate() {
+            let frame = PhysFrame::containing_address(*address);
+            let page = start_page + 1 + index as u64;
+            address_space.map_physical(PhysFrameRange { start: frame, end: frame + 1 }, PageRange { start: page, end: page + 1 }, MemorySpace::User, read_only_registers);
+        }
+        let ring_start_page = start_page + SHARED_RING_HEADER_PAGES;
+        address_space.map_physical(*ring.frames(), PageRange { start: ring_start_page, end: ring_start_page + ring_pages }, MemorySpace::User, ring_flags);
+        let area = VirtualMemoryArea::new(PageRange { start: start_page, end: ring_start_page + ring_pages }, VmaType::Device);
+        process.add_vma(area);
+
+        let mapping = SessionMapping {
+            control_address: start_page.start_address(),
+            ring_address: ring_start_page.start_address(),
+            wall_clock_address: (start_page + 1).start_address() + offset_in_page(*ring.wall_clock_address()),
+            position_address: (start_page + 2).start_address() + offset_in_page(*ring.position_address()),
+            ring,
+        };
+        let ring_mapping = RingMapping { control_page, area };
+        for field in [CONTROL_WRITE_POSITION, CONTROL_READ_POSITION, CONTROL_BUFFER_COMPLETIONS] {
+            ring_mapping.write(field, 0);
+        }
+        session.ring_mapping = Some(ring_mapping);
+
+        Ok(mapping)
+    }
+
+    // Publishes the position of the DMA engine to mapped sessions, whose stream completed a buffer, and adopts their write position.
+    fn handle_buffer_completion(&self, completions: &BufferCompletions) {
+        for session in self.sessions.iter() {
+            let ring_mapping = match session.ring_mapping.as_ref() {
+                Some(ring_mapping) => ring_mapping,
+                None => continue,
+            };
+            let completed_buffers = session.device.buffer_completions(session.stream, completions);
+            if completed_buffers == 0 {
+                continue;
+            }
+
+            if let Err(error) = session.adopt_write_position() {
+                warn!("Session {}: write position of the control page rejected: {:?}", session.handle.value(), error);
+            }
+            if let Ok(position) = session.device.position(session.stream) {
+                ring_mapping.write(CONTROL_READ_POSITION, position);
+            }
+            ring_mapping.write(CONTROL_BUFFER_COMPLETIONS, ring_mapping.read(CONTROL_BUFFER_COMPLETIONS).wrapping_add(completed_buffers as u32));
+        }
+    }
+
+    // processes don't close their sessions when they exit, so their streams get released here
+    pub fn close_sessions_of_exited_processes(&mut self) {
+        let active_process_ids = process_manager().read().active_process_ids();
+        let exited = |owner: &StreamOwner| matches!(owner, StreamOwner::Process(id) if !active_process_ids.contains(id));
+        self.preempted.retain(|(owner, _)| !exited(owner));
+
+        let mut index = 0;
+        while index < self.sessions.len() {
+            if !exited(&self.sessions[index].owner) {
+                index += 1;
+                continue;
+            }
+            let session = self.sessions.remove(index);
+            debug!("Session {}: closed, as its process exited", session.handle.value());
+            // the session gets dropped anyway, so a stream the device doesn't know anymore doesn't matter
+            let _ = session.release();
+        }
+    }
+
+    fn find(&self, owner: StreamOwner, handle: SessionHandle) -> Result<&Session, SessionError> {
+        self.sessions.iter().find(|session| session.handle == handle && session.owner == owner).ok_or(self.unknown_session(owner, handle))
+    }
+
+    fn find_mut(&mut self, owner: StreamOwner, handle: SessionHandle) -> Result<&mut Session, SessionError> {
+        let error = self.unknown_session(owner, handle);
+        self.sessions.iter_mut().find(|session| session.handle == handle && session.owner == owner).ok_or(error)
+    }
+
+    fn unknown_session(&self, owner: StreamOwner, handle: SessionHandle) -> SessionError {
+        match self.preempted.contains(&(owner, handle)) {
+            true => SessionError::Preempted(handle),
+            false => SessionError::UnknownSession(handle),
+        }
+    }
+}
+
+// called by the refill thread (see fn audio::refill::refill_completed_buffers)
+pub fn handle_buffer_completion(completions: &BufferCompletions) {
+    sessions().lock().handle_buffer_completion(completions);
+}
+
+fn offset_in_page(address: PhysAddr) -> u64 {
+    address.as_u64() % PAGE_SIZE as u64
+}
@@ -0,0 +1,233 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use derive_getters::Getters;
+
+// The structures in this module describe the audio state as seen by the user (devices, endpoints, volumes, mutes and formats).
+// They are the contract between the kernel and a future sound settings application, so they must stay stable:
+// Any incompatible change to their layout or to the serialized format requires incrementing SOUND_SETTINGS_VERSION.
+pub const SOUND_SETTINGS_VERSION: u16 = 1;
+pub const MAX_VOLUME_PERCENT: u8 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Getters)]
+pub struct EndpointId {
+    codec_address: u8,
+    node_id: u8,
+}
+
+impl EndpointId {
+    pub fn new(codec_address: u8, node_id: u8) -> Self {
+        Self {
+            codec_address,
+            node_id,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointKind {
+    LineOut,
+    Speaker,
+    Headphone,
+    DigitalOut,
+    LineIn,
+    Microphone,
+    DigitalIn,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointDirection {
+    Output,
+    Input,
+}
+
+impl EndpointKind {
+    pub fn direction(&self) -> EndpointDirection {
+        match self {
+            EndpointKind::LineOut | EndpointKind::Speaker | EndpointKind::Headphone | EndpointKind::DigitalOut | EndpointKind::Other => EndpointDirection::Output,
+            EndpointKind::LineIn | EndpointKind::Microphone | EndpointKind::DigitalIn => EndpointDirection::Input,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Getters)]
+pub struct ActiveFormat {
+    sample_rate: u32,
+    bits_per_sample: u8,
+    channels: u8,
+}
+
+impl ActiveFormat {
+    pub fn new(sample_rate: u32, bits_per_sample: u8, channels: u8) -> Self {
+        Self {
+            sample_rate,
+            bits_per_sample,
+            channels,
+        }
+    }
+
+    // samples with more than 16 bits are stored in 32 bit containers (see IHDA specification, section 4.5.1)
+    pub fn bytes_per_frame(&self) -> u32 {
+        let bytes_per_sample = match self.bits_per_sample {
+            0..=8 => 1,
+            9..=16 => 2,
+            _ => 4,
+        };
+        bytes_per_sample * self.channels as u32
+    }
+}
+
+#[derive(Clone, Debug, Getters)]
+pub struct EndpointSettings {
+    id: EndpointId,
+    kind: EndpointKind,
+    volume_percent: u8,
+    muted: bool,
+    // None if no stream is currently routed to the endpoint
+    active_format: Option<ActiveFormat>,
+}
+
+impl EndpointSettings {
+    pub fn new(id: EndpointId, kind: EndpointKind, volume_percent: u8, muted: bool, active_format: Option<ActiveFormat>) -> Self {
+        Self {
+            id,
+            kind,
+            volume_percent,
+            muted,
+            active_format,
+        }
+    }
+
+    pub fn direction(&self) -> EndpointDirection {
+        self.kind.direction()
+    }
+}
+
+#[derive(Clone, Debug, Getters)]
+pub struct DeviceSettings {
+    name: String,
+    endpoints: Vec<EndpointSettings>,
+}
+
+impl DeviceSettings {
+    pub fn new(name: String, endpoints: Vec<EndpointSettings>) -> Self {
+        Self {
+            name,
+            endpoints,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Getters)]
+pub struct SoundSettings {
+    version: u16,
+    devices: Vec<DeviceSettings>,
+}
+
+impl SoundSettings {
+    pub fn new(devices: Vec<DeviceSettings>) -> Self {
+        Self {
+            version: SOUND_SETTINGS_VERSION,
+            devices,
+        }
+    }
+
+    pub fn find_endpoint(&self, id: EndpointId) -> Option<&EndpointSettings> {
+        self.devices.iter()
+            .flat_map(|device| device.endpoints().iter())
+            .find(|endpoint| *endpoint.id() == id)
+    }
+
+    // serializes the settings into a line based text format, one "key=value" list per line:
+    // version=1
+    // device="<name>"
+    // endpoint=<codec>:<node> kind=<kind> direction=<direction> volume=<percent> muted=<bool> format=<rate>/<bits>/<channels>|none
+    // endpoint lines always belong to the last device line before them
+    pub fn serialize(&self) -> String {
+        let mut serialized = String::new();
+        writeln!(serialized, "version={}", self.version).unwrap();
+
+        for device in self.devices.iter() {
+            writeln!(serialized, "device=\"{}\"", device.name()).unwrap();
+            for endpoint in device.endpoints().iter() {
+                let format = match endpoint.active_format() {
+                    Some(format) => format!("{}/{}/{}", format.sample_rate(), format.bits_per_sample(), format.channels()),
+                    None => String::from("none"),
+                };
+                writeln!(serialized, "endpoint={}:{} kind={:?} direction={:?} volume={} muted={} format={}",
+                         endpoint.id().codec_address(),
+                         endpoint.id().node_id(),
+                         endpoint.kind(),
+                         endpoint.direction(),
+                         endpoint.volume_percent(),
+                         endpoint.muted(),
+                         format).unwrap();
+            }
+        }
+
+        serialized
+    }
+}
+
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct EndpointSettingsChange {
+    id: EndpointId,
+    // None leaves the current value untouched
+    volume_percent: Option<u8>,
+    muted: Option<bool>,
+}
+
+impl EndpointSettingsChange {
+    pub fn new(id: EndpointId, volume_percent: Option<u8>, muted: Option<bool>) -> Self {
+        Self {
+            id,
+            volume_percent,
+            muted,
+        }
+    }
+}
+
+// a set of changes a settings application wants to apply on top of the current SoundSettings
+#[derive(Clone, Debug, Getters)]
+pub struct SoundSettingsDiff {
+    version: u16,
+    changes: Vec<EndpointSettingsChange>,
+}
+
+impl SoundSettingsDiff {
+    pub fn new(changes: Vec<EndpointSettingsChange>) -> Self {
+        Self {
+            version: SOUND_SETTINGS_VERSION,
+            changes,
+        }
+    }
+
+    // checks the whole diff against the current settings before anything gets applied, so that a diff is either applied completely or not at all
+    pub fn validate(&self, current_settings: &SoundSettings) -> Result<(), SoundSettingsError> {
+        if self.version != SOUND_SETTINGS_VERSION {
+            return Err(SoundSettingsError::UnsupportedVersion(self.version));
+        }
+
+        for change in self.changes.iter() {
+            if current_settings.find_endpoint(*change.id()).is_none() {
+                return Err(SoundSettingsError::UnknownEndpoint(*change.id()));
+            }
+            if let Some(volume_percent) = change.volume_percent() {
+                if *volume_percent > MAX_VOLUME_PERCENT {
+                    return Err(SoundSettingsError::InvalidVolume(*volume_percent));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundSettingsError {
+    UnsupportedVersion(u16),
+    UnknownEndpoint(EndpointId),
+    InvalidVolume(u8),
+}
@@ -0,0 +1,227 @@
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use crate::audio::settings::{ActiveFormat, EndpointId};
+
+// Central accounting of all streams which currently occupy a stream descriptor of the sound card.
+// Streams get registered by whoever prepares them, so that "what is using my sound device" can be answered at any time.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamOwner {
+    // name of the kernel subsystem
+    Kernel(&'static str),
+    // process id
+    Process(usize),
+}
+
+// Lifecycle of a stream:
+// Reset -> Configured -> Prepared -> Running <-> Paused, Running -> Draining -> Paused
+// Every state can go back to Reset and every state can end in Error, which can only be left through Reset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamState {
+    // stream descriptor registers are cleared
+    Reset,
+    // stream descriptor registers are programmed, but the buffers contain no data yet
+    Configured,
+    // buffers contain data and the stream is ready to run
+    Prepared,
+    Running,
+    // RUN bit cleared, the DMA engine keeps its position
+    Paused,
+    // still running, but the owner won't write any more data
+    Draining,
+    // the DMA engine reported an error
+    Error,
+}
+
+impl StreamState {
+    pub fn can_transition_to(&self, new_state: StreamState) -> bool {
+        match (self, new_state) {
+            (_, StreamState::Reset) => true,
+            (_, StreamState::Error) => true,
+            (StreamState::Reset, StreamState::Configured) => true,
+            (StreamState::Configured | StreamState::Prepared, StreamState::Prepared) => true,
+            (StreamState::Prepared | StreamState::Paused, StreamState::Running) => true,
+            (StreamState::Running | StreamState::Draining, StreamState::Paused) => true,
+            (StreamState::Running, StreamState::Draining) => true,
+            _ => false,
+        }
+    }
+
+    // the DMA engine is fetching data in these states
+    pub fn is_active(&self) -> bool {
+        matches!(self, StreamState::Running | StreamState::Draining)
+    }
+}
+
+// Channels of a stream which an endpoint plays, from lowest_channel to lowest_channel + channel_count - 1.
+// Multichannel streams get split up this way on codecs with one stereo converter per speaker pair.
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct ChannelAssignment {
+    endpoint: EndpointId,
+    lowest_channel: u8,
+    channel_count: u8,
+}
+
+impl ChannelAssignment {
+    pub fn new(endpoint: EndpointId, lowest_channel: u8, channel_count: u8) -> Self {
+        Self {
+            endpoint,
+            lowest_channel,
+            channel_count,
+        }
+    }
+}
+
+// FIFO of the stream descriptor as the controller reports it, so that latency-sensitive owners can choose buffers that the
+// DMA engine doesn't fetch from while they get refilled (see fn Controller::fifo_tuning)
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct FifoTuning {
+    fifo_size_in_bytes: u16,
+    // None if the controller doesn't implement SDFIFOW
+    watermark_in_bytes: Option<u8>,
+    min_buffer_length_in_bytes: u32,
+}
+
+impl FifoTuning {
+    pub fn new(fifo_size_in_bytes: u16, watermark_in_bytes: Option<u8>, min_buffer_length_in_bytes: u32) -> Self {
+        Self { fifo_size_in_bytes, watermark_in_bytes, min_buffer_length_in_bytes }
+    }
+}
+
+#[derive(Clone, Debug, Getters)]
+pub struct StreamInfo {
+    stream_id: u8,
+    stream_descriptor_number: u32,
+    owner: StreamOwner,
+    // None if the stream is not routed to an endpoint yet
+    endpoint: Option<EndpointId>,
+    // further endpoints playing the same stream, see OutputRouting::Mirrored
+    mirrored_endpoints: Vec<EndpointId>,
+    // empty if the endpoint plays all channels of the stream, otherwise the endpoint is the one of the first assignment
+    channel_assignments: Vec<ChannelAssignment>,
+    format: ActiveFormat,
+    state: StreamState,
+    buffer_length_in_bytes: u32,
+    // position behind the last byte written by the owner;
+    // None if the owner doesn't track its writes, which means that the whole cyclic buffer contains valid data (e.g. looping demo buffers)
+    write_position: Option<u32>,
+    // amount of bytes written by the owner, which have not been fetched by the DMA engine yet
+    fill_level_in_bytes: u32,
+    // None until it has been read from the controller (see fn audio::active_streams)
+    fifo_tuning: Option<FifoTuning>,
+}
+
+impl StreamInfo {
+    pub fn new(
+        stream_id: u8,
+        stream_descriptor_number: u32,
+        owner: StreamOwner,
+        endpoint: Option<EndpointId>,
+        format: ActiveFormat,
+        state: StreamState,
+        buffer_length_in_bytes: u32,
+    ) -> Self {
+        Self {
+            stream_id,
+            stream_descriptor_number,
+            owner,
+            endpoint,
+            mirrored_endpoints: Vec::new(),
+            channel_assignments: Vec::new(),
+            format,
+            state,
+            buffer_length_in_bytes,
+            write_position: None,
+            fill_level_in_bytes: buffer_length_in_bytes,
+            fifo_tuning: None,
+        }
+    }
+
+    // the fill level can only be determined together with the current position of the DMA engine in the cyclic buffer
+    pub fn update_fill_level(&mut self, dma_position: u32) {
+        self.fill_level_in_bytes = match self.write_position {
+            Some(write_position) => (write_position + self.buffer_length_in_bytes - dma_position) % self.buffer_length_in_bytes.max(1),
+            None => self.buffer_length_in_bytes,
+        };
+    }
+
+    pub fn update_fifo_tuning(&mut self, fifo_tuning: FifoTuning) {
+        self.fifo_tuning = Some(fifo_tuning);
+    }
+}
+
+pub struct StreamRegistry {
+    streams: Vec<StreamInfo>,
+}
+
+impl StreamRegistry {
+    pub const fn new() -> Self {
+        Self { streams: Vec::new() }
+    }
+
+    pub fn streams(&self) -> &Vec<StreamInfo> {
+        &self.streams
+    }
+
+    // preparing a stream resets its stream descriptor, so a previous stream on the same descriptor is gone and gets replaced
+    pub fn register(&mut self, stream: StreamInfo) {
+        self.unregister(stream.stream_descriptor_number);
+        self.streams.push(stream);
+    }
+
+    pub fn unregister(&mut self, stream_descriptor_number: u32) {
+        self.streams.retain(|stream| stream.stream_descriptor_number != stream_descriptor_number);
+    }
+
+    pub fn set_state(&mut self, stream_descriptor_number: u32, state: StreamState) {
+        if let Some(stream) = self.find_mut(stream_descriptor_number) {
+            stream.state = state;
+        }
+    }
+
+    pub fn set_endpoint(&mut self, stream_descriptor_number: u32, endpoint: EndpointId) {
+        if let Some(stream) = self.find_mut(stream_descriptor_number) {
+            stream.endpoint = Some(endpoint);
+        }
+    }
+
+    // a reconfigured stream keeps its buffers, but starts over with the new format
+    pub fn set_format(&mut self, stream_descriptor_number: u32, format: ActiveFormat) {
+        if let Some(stream) = self.find_mut(stream_descriptor_number) {
+            stream.format = format;
+            stream.write_position = None;
+        }
+    }
+
+    pub fn add_mirrored_endpoint(&mut self, stream_descriptor_number: u32, endpoint: EndpointId) {
+        if let Some(stream) = self.find_mut(stream_descriptor_number) {
+            if !stream.mirrored_endpoints.contains(&endpoint) {
+                stream.mirrored_endpoints.push(endpoint);
+            }
+        }
+    }
+
+    // returns the endpoints which were mirrored until now
+    pub fn clear_mirrored_endpoints(&mut self, stream_descriptor_number: u32) -> Vec<EndpointId> {
+        match self.find_mut(stream_descriptor_number) {
+            Some(stream) => core::mem::take(&mut stream.mirrored_endpoints),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn set_channel_assignments(&mut self, stream_descriptor_number: u32, channel_assignments: Vec<ChannelAssignment>) {
+        if let Some(stream) = self.find_mut(stream_descriptor_number) {
+            stream.channel_assignments = channel_assignments;
+        }
+    }
+
+    pub fn set_write_position(&mut self, stream_descriptor_number: u32, write_position: u32) {
+        if let Some(stream) = self.find_mut(stream_descriptor_number) {
+            stream.write_position = Some(write_position);
+        }
+    }
+
+    fn find_mut(&mut self, stream_descriptor_number: u32) -> Option<&mut StreamInfo> {
+        self.streams.iter_mut().find(|stream| stream.stream_descriptor_number == stream_descriptor_number)
+    }
+}
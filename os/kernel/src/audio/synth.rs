@@ -0,0 +1,237 @@
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use crate::device::ihda_api::BitsPerSample;
+
+// Generates test signals for any sample rate, channel count and bit depth. The position in the wave is kept as a 32 bit phase,
+// where 2^32 is one whole period, so consecutive calls continue the wave exactly where the last one stopped, even if a buffer
+// doesn't hold a whole number of periods. Changing the frequency keeps the phase, so the signal doesn't jump.
+// The kernel doesn't use floating point, so the sine gets approximated with Bhaskara's formula (error below 0.2 %).
+
+const HALF_PERIOD: u64 = 1 << 31;
+const MAX_AMPLITUDE_PERCENT: u8 = 100;
+// any seed except 0 works for xorshift
+const NOISE_SEED: u32 = 0x1234_5678;
+// a sweep rises by this fraction of its frequency every step, which is about an octave every 45 steps
+const SWEEP_STEP_DIVISOR: u64 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Sawtooth,
+    // white noise, doesn't depend on the frequency
+    Noise,
+}
+
+#[derive(Clone, Debug, Getters)]
+pub struct SignalGenerator {
+    waveform: Waveform,
+    frequency: u32,
+    sample_rate: u32,
+    number_of_channels: u8,
+    bits_per_sample: BitsPerSample,
+    amplitude_percent: u8,
+    #[getter(skip)]
+    phase: u32,
+    #[getter(skip)]
+    phase_increment: u32,
+    #[getter(skip)]
+    noise_state: u32,
+}
+
+impl SignalGenerator {
+    pub fn new(waveform: Waveform, frequency: u32, sample_rate: u32, number_of_channels: u8, bits_per_sample: BitsPerSample) -> Self {
+        if sample_rate == 0 || number_of_channels == 0 {
+            panic!("Signal generator needs a sample rate and a channel count greater than 0");
+        }
+
+        let mut generator = Self {
+            waveform,
+            frequency: 0,
+            sample_rate,
+            number_of_channels,
+            bits_per_sample,
+            amplitude_percent: MAX_AMPLITUDE_PERCENT,
+            phase: 0,
+            phase_increment: 0,
+            noise_state: NOISE_SEED,
+        };
+        generator.set_frequency(frequency);
+        generator
+    }
+
+    // frequencies at or above the Nyquist frequency (half the sample rate) can't be represented
+    pub fn set_frequency(&mut self, frequency: u32) {
+        if frequency as u64 * 2 >= self.sample_rate as u64 {
+            panic!("Signal generator can't produce {} Hz at a sample rate of {} Hz", frequency, self.sample_rate);
+        }
+        self.frequency = frequency;
+        self.phase_increment = ((frequency as u64) << 32).div_ceil(self.sample_rate as u64) as u32;
+    }
+
+    // peak value in percent of the largest value of the bit depth
+    pub fn set_amplitude_percent(&mut self, amplitude_percent: u8) {
+        self.amplitude_percent = amplitude_percent.min(MAX_AMPLITUDE_PERCENT);
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    // starts the wave at the beginning of a period again
+    pub fn reset_phase(&mut self) {
+        self.phase = 0;
+    }
+
+    // Returns the interleaved samples of the next frames with the same sample in every channel. The samples are in the range of
+    // the bit depth (e.g. -2^23 to 2^23 - 1 for 24 bit samples), as expected by fn Stream::write_frames.
+    pub fn generate(&mut self, amount_of_frames: usize) -> Vec<i32> {
+        let mut samples = Vec::with_capacity(amount_of_frames * self.number_of_channels as usize);
+        for _ in 0..amount_of_frames {
+            let sample = self.next_sample();
+            for _ in 0..self.number_of_channels {
+                samples.push(sample);
+            }
+        }
+        samples
+    }
+
+    // one sample per frame, e.g. for sources which get mixed or resampled afterwards
+    pub fn generate_mono(&mut self, amount_of_frames: usize) -> Vec<i32> {
+        (0..amount_of_frames).map(|_| self.next_sample()).collect()
+    }
+
+    fn next_sample(&mut self) -> i32 {
+        let peak = self.peak();
+        let phase = self.phase as u64;
+        let sample = match self.waveform {
+            Waveform::Sine => {
+                // Bhaskara: sin(x) = 16x(pi - x) / (5pi^2 - 4x(pi - x)) for x in [0, pi], with the half period scaled to 2^15
+                let half_period = 1i64 << 15;
+                let position = ((phase % HALF_PERIOD) >> 16) as i64;
+                let product = position * (half_period - position);
+                let sine_scaled = 16 * product * half_period / (5 * half_period * half_period - 4 * product);
+                let sine = peak * sine_scaled / half_period;
+                if phase < HALF_PERIOD { sine } else { -sine }
+            }
+            // low for the first half of the period, like the former demo waves
+            Waveform::Square => if phase < HALF_PERIOD { -peak } else { peak },
+            Waveform::Sawtooth => (phase as i64 - HALF_PERIOD as i64) * peak / HALF_PERIOD as i64,
+            Waveform::Noise => {
+                // xorshift32 (see Marsaglia, "Xorshift RNGs")
+                self.noise_state ^= self.noise_state << 13;
+                self.noise_state ^= self.noise_state >> 17;
+                self.noise_state ^= self.noise_state << 5;
+                self.noise_state as i32 as i64 * peak / HALF_PERIOD as i64
+            }
+        };
+
+        self.phase = self.phase.wrapping_add(self.phase_increment);
+        sample as i32
+    }
+
+    fn peak(&self) -> i64 {
+        let max = (1i64 << (self.bits_per_sample.bit_depth() - 1)) - 1;
+        max * self.amplitude_percent as i64 / MAX_AMPLITUDE_PERCENT as i64
+    }
+}
+
+// Test signals with an end, e.g. for diagnostics which play a fixed sequence. Once the signal is over, fn generate_mono returns
+// fewer frames than requested, so that the caller knows when to stop.
+pub trait FiniteSignal {
+    fn generate_mono(&mut self, amount_of_frames: usize) -> Vec<i32>;
+}
+
+// Sine whose frequency rises by a 64th every step, e.g. from 100 Hz to 10 kHz in about 300 steps. The generator keeps its phase
+// when the frequency changes, so the sweep doesn't click between the steps.
+pub struct SineSweep {
+    generator: SignalGenerator,
+    // in 1/256 Hz, so that the steps don't get lost to rounding at low frequencies
+    frequency_in_256th_hz: u64,
+    end_frequency: u32,
+    frames_per_step: usize,
+    frames_left_in_step: usize,
+    finished: bool,
+}
+
+impl SineSweep {
+    // the end frequency gets capped below the Nyquist frequency of the sample rate
+    pub fn new(start_frequency: u32, end_frequency: u32, step_in_ms: usize, sample_rate: u32, bits_per_sample: BitsPerSample) -> Self {
+        let frames_per_step = (sample_rate as usize * step_in_ms / 1000).max(1);
+        let end_frequency = end_frequency.min((sample_rate - 1) / 2);
+        Self {
+            generator: SignalGenerator::new(Waveform::Sine, start_frequency, sample_rate, 1, bits_per_sample),
+            frequency_in_256th_hz: (start_frequency as u64) << 8,
+            end_frequency,
+            frames_per_step,
+            frames_left_in_step: frames_per_step,
+            finished: start_frequency > end_frequency,
+        }
+    }
+
+    pub fn set_amplitude_percent(&mut self, amplitude_percent: u8) {
+        self.generator.set_amplitude_percent(amplitude_percent);
+    }
+}
+
+impl FiniteSignal for SineSweep {
+    fn generate_mono(&mut self, amount_of_frames: usize) -> Vec<i32> {
+        let mut samples = Vec::with_capacity(amount_of_frames);
+        while samples.len() < amount_of_frames && !self.finished {
+            let amount = self.frames_left_in_step.min(amount_of_frames - samples.len());
+            samples.extend(self.generator.generate_mono(amount));
+            self.frames_left_in_step -= amount;
+            if self.frames_left_in_step > 0 {
+                continue;
+            }
+
+            self.frequency_in_256th_hz += self.frequency_in_256th_hz / SWEEP_STEP_DIVISOR;
+            let frequency = (self.frequency_in_256th_hz >> 8) as u32;
+            if frequency > self.end_frequency {
+                self.finished = true;
+            } else {
+                self.generator.set_frequency(frequency);
+                self.frames_left_in_step = self.frames_per_step;
+            }
+        }
+        samples
+    }
+}
+
+// Beeps a given amount of times with a pause as long as a beep after each of them, so that a listener can tell signals apart
+// by counting (e.g. the channels of a speaker setup).
+pub struct BeepSequence {
+    generator: SignalGenerator,
+    beep_count: usize,
+    frames_per_beep: usize,
+    position_in_frames: usize,
+}
+
+impl BeepSequence {
+    pub fn new(frequency: u32, beep_count: usize, beep_length_in_ms: usize, sample_rate: u32, bits_per_sample: BitsPerSample) -> Self {
+        Self {
+            generator: SignalGenerator::new(Waveform::Sine, frequency, sample_rate, 1, bits_per_sample),
+            beep_count,
+            frames_per_beep: (sample_rate as usize * beep_length_in_ms / 1000).max(1),
+            position_in_frames: 0,
+        }
+    }
+
+    pub fn set_amplitude_percent(&mut self, amplitude_percent: u8) {
+        self.generator.set_amplitude_percent(amplitude_percent);
+    }
+}
+
+impl FiniteSignal for BeepSequence {
+    fn generate_mono(&mut self, amount_of_frames: usize) -> Vec<i32> {
+        let length_in_frames = self.beep_count * 2 * self.frames_per_beep;
+        let amount_of_frames = amount_of_frames.min(length_in_frames - self.position_in_frames);
+        let first_frame = self.position_in_frames;
+        self.position_in_frames += amount_of_frames;
+
+        // the generator keeps running during the pauses, which only get silenced
+        self.generator.generate_mono(amount_of_frames).into_iter().enumerate()
+            .map(|(index, sample)| if (first_frame + index) / self.frames_per_beep % 2 == 0 { sample } else { 0 })
+            .collect()
+    }
+}
@@ -0,0 +1,194 @@
+use alloc::vec::Vec;
+use derive_getters::Getters;
+
+// Reader and writer for RIFF WAVE files. Chunks other than "fmt " and "data" (e.g. "bext" written by broadcast software) get
+// skipped. The reader only takes the container apart; the samples get decoded by the decoder registered for the format tag
+// of the file (see audio::decoder), so the data stays in the file and a file doesn't have to fit into the kernel heap.
+// The writer only writes uncompressed 16 bit PCM.
+
+// format tags of the "fmt " chunk
+pub const WAVE_FORMAT_PCM: u16 = 1;
+pub const WAVE_FORMAT_IMA_ADPCM: u16 = 0x11;
+// the "fmt " chunk of WAVE_FORMAT_EXTENSIBLE files carries the actual format tag in the first two bytes of its sub format GUID
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+const WAVE_FORMAT_EXTENSIBLE_SUB_FORMAT_OFFSET: usize = 24;
+const RIFF_HEADER_LENGTH: usize = 12;
+const CHUNK_HEADER_LENGTH: usize = 8;
+const FORMAT_CHUNK_MIN_LENGTH: usize = 16;
+// formats other than PCM append cbSize and as many bytes of format specific information to the "fmt " chunk
+const FORMAT_CHUNK_EXTENSION_OFFSET: usize = 18;
+// RIFF header, "fmt " chunk of FORMAT_CHUNK_MIN_LENGTH bytes and the header of the "data" chunk, as written by WavWriter
+const WAVE_HEADER_LENGTH: usize = RIFF_HEADER_LENGTH + CHUNK_HEADER_LENGTH + FORMAT_CHUNK_MIN_LENGTH + CHUNK_HEADER_LENGTH;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WavError {
+    // the file doesn't start with a RIFF header of the form type WAVE
+    NotAWaveFile,
+    MissingFormatChunk,
+    MissingDataChunk,
+    // a chunk claims to be longer than the rest of the file
+    Truncated,
+    InvalidNumberOfChannels(u16),
+    InvalidSampleRate,
+}
+
+// contents of the "fmt " chunk, which a decoder gets created from
+#[derive(Clone, Debug, PartialEq, Getters)]
+pub struct WavFormat {
+    // format tag, which is the sub format for WAVE_FORMAT_EXTENSIBLE files
+    encoding: u16,
+    number_of_channels: u8,
+    sample_rate: u32,
+    // length of the smallest unit the data can be split into (a frame for PCM, a block for ADPCM)
+    block_align: u16,
+    bits_per_sample: u16,
+    // format specific information behind cbSize, empty for PCM
+    extension: Vec<u8>,
+}
+
+#[derive(Debug, Getters)]
+pub struct WavFile<'a> {
+    format: WavFormat,
+    // exposed with the lifetime of the file instead of the one of the getter
+    #[getter(skip)]
+    data: &'a [u8],
+}
+
+impl<'a> WavFile<'a> {
+    pub fn parse(file: &'a [u8]) -> Result<Self, WavError> {
+        if file.len() < RIFF_HEADER_LENGTH || &file[0..4] != b"RIFF" || &file[8..12] != b"WAVE" {
+            return Err(WavError::NotAWaveFile);
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut offset = RIFF_HEADER_LENGTH;
+        while offset + CHUNK_HEADER_LENGTH <= file.len() && (format.is_none() || data.is_none()) {
+            let id = &file[offset..offset + 4];
+            let length = read_u32(file, offset + 4) as usize;
+            let body = file.get(offset + CHUNK_HEADER_LENGTH..offset + CHUNK_HEADER_LENGTH + length).ok_or(WavError::Truncated)?;
+            match id {
+                b"fmt " => format = Some(body),
+                b"data" => data = Some(body),
+                _ => {}
+            }
+            // chunks are padded to an even length (see RIFF specification)
+            offset += CHUNK_HEADER_LENGTH + length + (length & 1);
+        }
+
+        let format = format.ok_or(WavError::MissingFormatChunk)?;
+        let data = data.ok_or(WavError::MissingDataChunk)?;
+        if format.len() < FORMAT_CHUNK_MIN_LENGTH {
+            return Err(WavError::Truncated);
+        }
+
+        let encoding = match read_u16(format, 0) {
+            WAVE_FORMAT_EXTENSIBLE if format.len() >= WAVE_FORMAT_EXTENSIBLE_SUB_FORMAT_OFFSET + 2 => read_u16(format, WAVE_FORMAT_EXTENSIBLE_SUB_FORMAT_OFFSET),
+            encoding => encoding,
+        };
+        let number_of_channels = read_u16(format, 2);
+        if number_of_channels == 0 || number_of_channels > u8::MAX as u16 {
+            return Err(WavError::InvalidNumberOfChannels(number_of_channels));
+        }
+        let sample_rate = read_u32(format, 4);
+        if sample_rate == 0 {
+            return Err(WavError::InvalidSampleRate);
+        }
+
+        let extension = match format.len() >= FORMAT_CHUNK_EXTENSION_OFFSET {
+            true => {
+                let extension_length = read_u16(format, FORMAT_CHUNK_MIN_LENGTH) as usize;
+                format.get(FORMAT_CHUNK_EXTENSION_OFFSET..FORMAT_CHUNK_EXTENSION_OFFSET + extension_length).ok_or(WavError::Truncated)?.to_vec()
+            }
+            false => Vec::new(),
+        };
+
+        Ok(Self {
+            format: WavFormat {
+                encoding,
+                number_of_channels: number_of_channels as u8,
+                sample_rate,
+                block_align: read_u16(format, 12),
+                bits_per_sample: read_u16(format, 14),
+                extension,
+            },
+            data,
+        })
+    }
+
+    // content of the "data" chunk
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+// Writer for 16 bit PCM files (e.g. recordings), which appends samples as they arrive. The lengths in the RIFF header and the
+// "data" chunk are only known at the end, so they get filled in by fn finish.
+pub struct WavWriter {
+    file: Vec<u8>,
+    number_of_channels: u8,
+}
+
+impl WavWriter {
+    // reserves room for the header and the given amount of frames up front, so that the file doesn't get reallocated while recording
+    pub fn new(sample_rate: u32, number_of_channels: u8, capacity_in_frames: usize) -> Self {
+        if number_of_channels == 0 {
+            panic!("WAVE file: a file needs at least one channel");
+        }
+        let bytes_per_frame = number_of_channels as usize * 2;
+        let mut file = Vec::with_capacity(Self::length_in_bytes(number_of_channels, capacity_in_frames));
+        file.extend_from_slice(b"RIFF");
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(b"WAVE");
+        file.extend_from_slice(b"fmt ");
+        file.extend_from_slice(&(FORMAT_CHUNK_MIN_LENGTH as u32).to_le_bytes());
+        file.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        file.extend_from_slice(&(number_of_channels as u16).to_le_bytes());
+        file.extend_from_slice(&sample_rate.to_le_bytes());
+        file.extend_from_slice(&(sample_rate * bytes_per_frame as u32).to_le_bytes());
+        file.extend_from_slice(&(bytes_per_frame as u16).to_le_bytes());
+        file.extend_from_slice(&16u16.to_le_bytes());
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&0u32.to_le_bytes());
+
+        Self { file, number_of_channels }
+    }
+
+    // size of a file with the given amount of frames
+    pub fn length_in_bytes(number_of_channels: u8, length_in_frames: usize) -> usize {
+        WAVE_HEADER_LENGTH + length_in_frames * number_of_channels as usize * 2
+    }
+
+    // interleaved samples, which must consist of complete frames
+    pub fn write(&mut self, samples: &[i16]) {
+        if samples.len() % self.number_of_channels as usize != 0 {
+            panic!("WAVE file: {} samples don't make up complete frames of {} channels", samples.len(), self.number_of_channels);
+        }
+        for sample in samples {
+            self.file.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+
+    pub fn length_in_frames(&self) -> usize {
+        (self.file.len() - WAVE_HEADER_LENGTH) / (self.number_of_channels as usize * 2)
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let data_length = (self.file.len() - WAVE_HEADER_LENGTH) as u32;
+        write_u32(&mut self.file, 4, data_length + (WAVE_HEADER_LENGTH - CHUNK_HEADER_LENGTH) as u32);
+        write_u32(&mut self.file, WAVE_HEADER_LENGTH - 4, data_length);
+        self.file
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
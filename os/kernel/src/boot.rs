@@ -9,7 +9,7 @@ use core::mem::size_of;
 use core::ops::Deref;
 use core::ptr;
 use chrono::DateTime;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use multiboot2::{BootInformation, BootInformationHeader, EFIMemoryMapTag, MemoryAreaType, MemoryMapTag, Tag};
 use uefi::prelude::*;
 use uefi::table::boot::{MemoryMap, PAGE_SIZE};
@@ -25,8 +25,10 @@ use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
 use x86_64::PrivilegeLevel::Ring0;
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::page::PageRange;
-use crate::{allocator, apic, built_info, efi_system_table, gdt, init_acpi_tables, init_apic, init_efi_system_table, init_ihda, init_initrd, init_keyboard, init_pci, init_serial_port, init_terminal, initrd, logger, memory, process_manager, ps2_devices, scheduler, serial_port, terminal, timer, tss, intel_hd_audio_device};
+use crate::{allocator, apic, built_info, efi_system_table, gdt, init_acpi_tables, init_apic, init_efi_system_table, init_ihda, init_initrd, init_keyboard, init_pci, init_serial_port, init_terminal, initrd, logger, memory, process_manager, ps2_devices, scheduler, serial_port, terminal, timer, tss, INTEL_HD_AUDIO};
 use crate::memory::MemorySpace;
+use crate::device::qemu_cfg;
+use stream::OutputStream;
 
 extern "C" {
     static ___KERNEL_DATA_START__: u64;
@@ -34,6 +36,8 @@ extern "C" {
 }
 
 const INIT_HEAP_PAGES: usize = 0x400;
+// QEMU gets started with "-fw_cfg name=opt/d3os/ihda_self_test,string=1" to run the sound card self test instead of the demo
+const IHDA_SELF_TEST_FILE: &str = "opt/d3os/ihda_self_test";
 
 #[no_mangle]
 pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInformationHeader) {
@@ -207,7 +211,13 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
 
     // Setup Intel HD Audio sound card
     init_ihda();
-    intel_hd_audio_device().demo_bachelor_presentation();
+    if qemu_cfg::read_file(IHDA_SELF_TEST_FILE).is_some() {
+        run_ihda_self_test();
+    } else if let Some(device) = INTEL_HD_AUDIO.get() {
+        if let Err(error) = device.demo_bachelor_presentation() {
+            warn!("IHDA demo failed: {:?}", error);
+        }
+    }
     
     // Load initial ramdisk
     let initrd_tag = multiboot.module_tags()
@@ -241,6 +251,26 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
     scheduler().start();
 }
 
+// Runs the sound card self test and writes a single result line to the serial port, so that a script running QEMU
+// can check whether playback works without listening to it.
+fn run_ihda_self_test() {
+    let passed = match INTEL_HD_AUDIO.get() {
+        Some(device) => {
+            let report = device.self_test();
+            info!("IHDA self test: {:?}", report);
+            report.passed()
+        }
+        None => {
+            error!("IHDA self test: No Intel HD Audio device found");
+            false
+        }
+    };
+
+    if let Some(serial) = serial_port() {
+        serial.write_str(if passed { "IHDA SELF TEST PASSED\n" } else { "IHDA SELF TEST FAILED\n" });
+    }
+}
+
 fn init_gdt() {
     let mut gdt = gdt().lock();
     let tss = tss().lock();
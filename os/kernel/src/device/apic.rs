@@ -208,6 +208,12 @@ impl Apic {
         unsafe { self.io_apic.lock().enable_irq(target); }
     }
 
+    // destination of message signaled interrupts, which get delivered to the local APIC directly
+    pub fn local_apic_id(&self) -> u8 {
+        // Needs to be executed in unsafe block; At this point, the APIC has been initialized successfully, so we can assume, that reading the ID register works.
+        unsafe { self.local_apic.lock().id() as u8 }
+    }
+
     pub fn end_of_interrupt(&self) {
         let mut local_apic = self.local_apic.try_lock();
         while local_apic.is_none() {
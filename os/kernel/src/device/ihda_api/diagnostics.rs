@@ -0,0 +1,725 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+use derive_getters::Getters;
+use log::{debug, info, warn};
+use crate::scheduler;
+use crate::device::ihda_controller::diagnostics::{PositionSourceDiagnostics, VerbTraceEvent};
+use crate::audio::format::AudioFormat;
+use crate::audio::stream_registry;
+use crate::audio::streams::{ChannelAssignment, StreamInfo, StreamOwner, StreamState};
+use crate::audio::settings::{ActiveFormat, EndpointDirection, EndpointId};
+use crate::audio::synth::{BeepSequence, FiniteSignal, SineSweep, Waveform};
+use crate::device::ihda_codec::{Codec, CommandTransport, FunctionGroup, Widget};
+use crate::device::ihda_codec_export::export_codec_graph;
+use crate::device::ihda_verbs::{AmplifierGainMuteResponse, ChannelStreamIdResponse, ConnectionSelectResponse, PinWidgetControlResponse, PowerState, PowerStateResponse, GetAmplifierGainMutePayload, GetAmplifierGainMuteSide, GetAmplifierGainMuteType, NodeAddress, VendorIdResponse, WidgetType};
+use crate::device::ihda_verbs::Command::{GetAmplifierGainMute, GetChannelStreamId, GetConnectionSelect, GetParameter, GetPinWidgetControl, GetPowerState};
+use crate::device::ihda_verbs::Parameter::VendorId;
+use crate::device::ihda_codec_driver::{endpoint_id, endpoint_kind};
+use crate::device::pit::Timer;
+use crate::device::ihda_api::{BitsPerSample, CAPTURE_INPUT_STREAM_DESCRIPTOR, CodecSnapshot, ErrorStatistics, INTERRUPT_COUNT, IntelHDAudioDevice, MAX_SPEAKER_PAIR_CHANNELS, PlaybackError, TEST_TONE_OUTPUT_STREAM_DESCRIPTOR};
+use crate::device::ihda_api::routing::channel_name;
+
+// the loopback test plays a square wave on the test tone stream and expects to capture at least a tenth of its energy
+const LOOPBACK_TEST_FREQUENCY: u32 = 1000;
+const LOOPBACK_TEST_SETTLE_TIME_IN_MS: usize = 50;
+const LOOPBACK_TEST_DURATION_IN_MS: usize = 250;
+const LOOPBACK_TEST_MIN_ENERGY_PERCENT: u64 = 10;
+// the zero crossing rate of the recording may deviate this much from the one of the played signal
+const LOOPBACK_TEST_MAX_ZERO_CROSSING_DEVIATION_PERCENT: u32 = 10;
+// samples below this magnitude count as silence, so that noise around zero doesn't add zero crossings
+const LOOPBACK_TEST_SILENCE_THRESHOLD: i16 = 328;
+const SELF_TEST_FREQUENCY: u32 = 440;
+// the DMA position gets sampled this often while the self test plays its tone; the interval must not be a multiple of the
+// length of the cyclic buffer (about 43 ms), as the position would be the same in every sample then
+const SELF_TEST_POSITION_SAMPLE_COUNT: usize = 10;
+const SELF_TEST_POSITION_SAMPLE_INTERVAL_IN_MS: usize = 20;
+// The speaker test sweeps a sine from 100 Hz to 10 kHz in about 3 seconds and identifies each channel by beeping once on the
+// first channel, twice on the second and so on. Its signals are longer than the ring, so they get streamed into four buffers
+// of 16 KiB, which last 85 ms each for a stereo stream at 48 kHz and 21 ms for 8 channels.
+const SPEAKER_TEST_SWEEP_START_FREQUENCY: u32 = 100;
+const SPEAKER_TEST_SWEEP_END_FREQUENCY: u32 = 10000;
+const SPEAKER_TEST_SWEEP_STEP_IN_MS: usize = 10;
+const SPEAKER_TEST_BEEP_FREQUENCY: u32 = 660;
+const SPEAKER_TEST_BEEP_LENGTH_IN_MS: usize = 250;
+// half of the full scale, so that the sweep doesn't overload small speakers
+const SPEAKER_TEST_AMPLITUDE_PERCENT: u8 = 50;
+const SPEAKER_TEST_BUFFER_AMOUNT: u32 = 4;
+const SPEAKER_TEST_PAGES_PER_BUFFER: u32 = 32;
+const SPEAKER_TEST_POLL_INTERVAL_IN_MS: usize = 10;
+// longer than the whole ring of an 8 channel stream lasts, so a DMA engine that still runs has moved on by then
+const SPEAKER_TEST_STALL_TIMEOUT_IN_MS: usize = 500;
+// WALCLK is a 24 MHz counter (see section 3.3.16 of the specification)
+const WALL_CLOCK_TICKS_PER_US: u32 = 24;
+
+impl IntelHDAudioDevice {
+    // ########## self tests ##########
+
+    // Describes what fn test_speakers plays on an endpoint: the format, which endpoint plays which channels and the widgets from
+    // each of these pins to its converter.
+    pub fn speaker_test_report(&self, endpoint: Option<EndpointId>) -> Result<String, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (stream_format, paths) = self.speaker_test_setup(&codecs, endpoint)?;
+        let number_of_channels = *stream_format.number_of_channels();
+        let mut report = String::new();
+        writeln!(report, "Format: {} Hz/{} bit/{} ch", stream_format.sample_rate(), stream_format.bits_per_sample().bit_depth(), number_of_channels).unwrap();
+
+        for (assignment, path) in paths.iter() {
+            let channels: Vec<String> = (*assignment.lowest_channel()..*assignment.lowest_channel() + *assignment.channel_count())
+                .map(|channel| format!("{} {}", channel, channel_name(channel, number_of_channels)))
+                .collect();
+            writeln!(report, "Endpoint {}:{:#04x} plays channels {}", assignment.endpoint().codec_address(), assignment.endpoint().node_id(), channels.join(", ")).unwrap();
+            for widget in path.iter() {
+                writeln!(report, "  {:#04x} {:?}", widget.address().node_id(), widget.audio_widget_capabilities().widget_type()).unwrap();
+            }
+        }
+        Ok(report)
+    }
+
+    // Plays one signal of the speaker test through the complete path of an output endpoint and blocks until it has been played.
+    // Multichannel setups get tested with all of their speaker pairs (see fn default_channel_assignments), so that wrong wiring
+    // shows up as a channel coming out of the wrong speaker. Without an endpoint, the default output endpoint gets used.
+    pub fn test_speakers(&self, owner: StreamOwner, endpoint: Option<EndpointId>, signal: SpeakerTestSignal) -> Result<(), PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (stream_format, paths) = self.speaker_test_setup(&codecs, endpoint)?;
+        let channel_assignments: Vec<ChannelAssignment> = paths.iter().map(|(assignment, _)| *assignment).collect();
+        let id = *channel_assignments[0].endpoint();
+        let number_of_channels = *stream_format.number_of_channels();
+
+        let (mut source, target_channel): (Box<dyn FiniteSignal>, Option<u8>) = match signal {
+            SpeakerTestSignal::Sweep => {
+                let mut sweep = SineSweep::new(SPEAKER_TEST_SWEEP_START_FREQUENCY, SPEAKER_TEST_SWEEP_END_FREQUENCY, SPEAKER_TEST_SWEEP_STEP_IN_MS, stream_format.sample_rate(), BitsPerSample::Sixteen);
+                sweep.set_amplitude_percent(SPEAKER_TEST_AMPLITUDE_PERCENT);
+                (Box::new(sweep), None)
+            }
+            SpeakerTestSignal::Channel(channel) if channel < number_of_channels => {
+                let mut beeps = BeepSequence::new(SPEAKER_TEST_BEEP_FREQUENCY, channel as usize + 1, SPEAKER_TEST_BEEP_LENGTH_IN_MS, stream_format.sample_rate(), BitsPerSample::Sixteen);
+                beeps.set_amplitude_percent(SPEAKER_TEST_AMPLITUDE_PERCENT);
+                (Box::new(beeps), Some(channel))
+            }
+            SpeakerTestSignal::Channel(channel) => return Err(PlaybackError::InvalidChannel(id, channel)),
+        };
+        debug!("Speaker test plays {:?} on endpoint {:?} with format {:?}", signal, id, stream_format);
+
+        self.stop_demo();
+        let stream = &if channel_assignments.len() > 1 {
+            self.open_multichannel_output_stream(owner, &channel_assignments, stream_format, TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, SPEAKER_TEST_BUFFER_AMOUNT, SPEAKER_TEST_PAGES_PER_BUFFER)?
+        } else {
+            self.open_output_stream(owner, Some(id), stream_format, TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, SPEAKER_TEST_BUFFER_AMOUNT, SPEAKER_TEST_PAGES_PER_BUFFER)?
+        };
+        stream.clear_buffers();
+
+        // Silence gets appended to the signal until it has overwritten the whole ring, as the DMA engine would play the signal
+        // once more otherwise. At that point, the DMA engine must have played the end of the signal.
+        let frame_size_in_bytes = stream_format.frame_size_in_bytes();
+        let ring_length_in_frames = (stream.buffer_length_in_bytes() / frame_size_in_bytes) as usize;
+        let mut silent_frames = 0;
+        let mut last_position_in_bytes = stream.hardware_position_in_bytes();
+        let mut stalled_for_ms = 0;
+        while silent_frames < ring_length_in_frames {
+            let free_frames = (stream.free_space() / frame_size_in_bytes) as usize;
+            let signal_samples = source.generate_mono(free_frames);
+            silent_frames += free_frames - signal_samples.len();
+
+            let mut samples = vec![0i16; free_frames * number_of_channels as usize];
+            for (frame_index, sample) in signal_samples.iter().enumerate() {
+                for channel in (0..number_of_channels).filter(|channel| target_channel.map_or(true, |target_channel| target_channel == *channel)) {
+                    samples[frame_index * number_of_channels as usize + channel as usize] = *sample as i16;
+                }
+            }
+            // the samples fill exactly the free space of the ring, so they can't be refused
+            if let Err(error) = stream.try_write(&samples) {
+                self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream);
+                return Err(PlaybackError::RingWrite(id, error));
+            }
+
+            if !stream.state().is_active() {
+                self.start_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream);
+            }
+            scheduler().sleep(SPEAKER_TEST_POLL_INTERVAL_IN_MS);
+
+            // A DMA engine which stopped on an error or doesn't move anymore never frees up space in the ring, so the loop
+            // would wait forever for the end of the signal.
+            let position_in_bytes = stream.hardware_position_in_bytes();
+            stalled_for_ms = if position_in_bytes == last_position_in_bytes { stalled_for_ms + SPEAKER_TEST_POLL_INTERVAL_IN_MS } else { 0 };
+            last_position_in_bytes = position_in_bytes;
+            if stream.check_for_errors() || stalled_for_ms >= SPEAKER_TEST_STALL_TIMEOUT_IN_MS {
+                warn!("Speaker test stream on endpoint {:?} stopped at position {} of the ring", id, position_in_bytes);
+                self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream);
+                return Err(PlaybackError::StreamStopped(id));
+            }
+        }
+        self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream);
+
+        Ok(())
+    }
+
+    // The speaker test plays 16 bit samples at 48 kHz or 44.1 kHz with as many channels (up to 8) as the endpoint and the other
+    // endpoints of its default association can play together, so that every speaker of a multichannel setup gets tested.
+    fn speaker_test_setup<'c>(&self, codecs: &'c CodecSnapshot, endpoint: Option<EndpointId>) -> Result<(AudioFormat, Vec<(ChannelAssignment, Vec<&'c Widget>)>), PlaybackError> {
+        let (_, _, id) = self.find_output_endpoint(codecs, endpoint)?;
+        for number_of_channels in (1..=MAX_SPEAKER_PAIR_CHANNELS).rev() {
+            let channel_assignments = match self.default_channel_assignments(Some(id), number_of_channels) {
+                Ok(channel_assignments) => channel_assignments,
+                Err(_) => continue,
+            };
+            for sample_rate in [48000, 44100] {
+                let stream_format = match AudioFormat::pcm(number_of_channels, BitsPerSample::Sixteen, sample_rate) {
+                    Some(stream_format) => stream_format,
+                    None => continue,
+                };
+                if let Ok(paths) = self.channel_assignment_paths(codecs, &channel_assignments, &stream_format) {
+                    return Ok((stream_format, paths));
+                }
+            }
+        }
+        Err(PlaybackError::UnsupportedFormat(id))
+    }
+
+    // Verifies the whole audio path without anything plugged in: a square wave gets played on an output converter and captured
+    // again by an input converter, using a mixer widget inside the codec which connects both (see fn find_loopback_path).
+    // The recording has to contain a reasonable share of the played energy and about the same amount of zero crossings.
+    // The test uses the stream descriptors of the test tone and of capturing, so neither of them may run at the same time.
+    pub fn loopback_test(&self, owner: StreamOwner) -> Result<LoopbackReport, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, loopback_path) = codecs.iter()
+            .flat_map(|codec| codec.codec().function_groups().iter())
+            .find_map(|function_group| function_group.find_loopback_path().map(|loopback_path| (function_group, loopback_path)))
+            .ok_or(PlaybackError::NoLoopbackPath)?;
+        let stream_format = [AudioFormat::mono_48khz_16bit(), AudioFormat::mono_44khz_16bit()].into_iter()
+            .find(|stream_format| [loopback_path.output_converter(), loopback_path.input_converter()].iter()
+                .all(|converter| Self::supports_format(function_group, converter, stream_format)))
+            .ok_or(PlaybackError::UnsupportedFormat(endpoint_id(loopback_path.output_converter())))?;
+        debug!("Running loopback test from converter {:?} through mixer {:?} to converter {:?}",
+            loopback_path.output_converter().address(), loopback_path.mixer().address(), loopback_path.input_converter().address());
+
+        self.ensure_powered_up();
+        self.stop_demo();
+        let output_stream = &self.controller.prepare_output_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream_format, 2, 16).map_err(PlaybackError::Device)?;
+        self.register_stream(output_stream, self.controller.output_stream_descriptor_number(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR), owner, None);
+        let input_stream_descriptor_number = self.controller.input_stream_descriptor_number(CAPTURE_INPUT_STREAM_DESCRIPTOR);
+        let input_stream = match self.controller.prepare_input_stream(CAPTURE_INPUT_STREAM_DESCRIPTOR, stream_format, 2, 16) {
+            Ok(stream) => stream,
+            Err(error) => {
+                self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, output_stream);
+                return Err(PlaybackError::Device(error));
+            }
+        };
+        self.register_stream(&input_stream, input_stream_descriptor_number, owner, None);
+
+        output_stream.clear_buffers();
+        output_stream.write_signal(&mut output_stream.signal_generator(Waveform::Square, LOOPBACK_TEST_FREQUENCY));
+        input_stream.clear_buffers();
+        self.controller.configure_loopback_path(&loopback_path, output_stream, &input_stream);
+
+        // the recording starts once the signal went through the mixer, so that it doesn't begin with silence
+        self.start_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, output_stream);
+        Timer::wait(LOOPBACK_TEST_SETTLE_TIME_IN_MS);
+        input_stream.run();
+        self.sync_stream_state(input_stream_descriptor_number, &input_stream);
+        let max_duration_in_ms = (input_stream.buffer_length_in_bytes() as usize * 1000) / stream_format.bytes_per_second() as usize;
+        Timer::wait(LOOPBACK_TEST_DURATION_IN_MS.min(max_duration_in_ms));
+        if !input_stream.check_for_errors() {
+            input_stream.stop();
+        }
+        self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, output_stream);
+
+        // see comment in fn record
+        unsafe { asm!("wbinvd"); }
+
+        let recorded_length_in_samples = (self.controller.stream_position(input_stream_descriptor_number) / 2) as usize;
+        let mut played = Vec::new();
+        let mut recorded = Vec::new();
+        for buffer_index in 0..output_stream.buffer_amount() {
+            played.extend(output_stream.read_data_from_buffer(buffer_index));
+        }
+        for buffer_index in 0..input_stream.buffer_amount() {
+            recorded.extend(input_stream.read_data_from_buffer(buffer_index));
+        }
+        if recorded_length_in_samples > 0 {
+            recorded.truncate(recorded_length_in_samples);
+        }
+
+        Self::reset_stream(&input_stream);
+        stream_registry().lock().unregister(input_stream_descriptor_number);
+        self.record_activity();
+
+        let (played_energy, played_zero_crossing_rate) = Self::signal_statistics(&played);
+        let (recorded_energy, recorded_zero_crossing_rate) = Self::signal_statistics(&recorded);
+        let zero_crossing_deviation = played_zero_crossing_rate.abs_diff(recorded_zero_crossing_rate);
+        let passed = recorded_energy * 100 >= played_energy * LOOPBACK_TEST_MIN_ENERGY_PERCENT
+            && zero_crossing_deviation * 100 <= played_zero_crossing_rate * LOOPBACK_TEST_MAX_ZERO_CROSSING_DEVIATION_PERCENT;
+        let report = LoopbackReport {
+            played_energy,
+            recorded_energy,
+            played_zero_crossing_rate,
+            recorded_zero_crossing_rate,
+            passed,
+        };
+        info!("Loopback test {}: {:?}", if passed { "passed" } else { "failed" }, report);
+
+        Ok(report)
+    }
+
+    // mean square and zero crossings per 1000 samples of a mono signal, ignoring samples around zero
+    fn signal_statistics(samples: &[i16]) -> (u64, u32) {
+        if samples.is_empty() {
+            return (0, 0);
+        }
+        let energy = samples.iter().map(|sample| (*sample as i64 * *sample as i64) as u64).sum::<u64>() / samples.len() as u64;
+
+        let mut zero_crossings = 0u32;
+        let mut last_sign = None;
+        for sample in samples.iter().filter(|sample| sample.unsigned_abs() >= LOOPBACK_TEST_SILENCE_THRESHOLD as u16) {
+            let sign = sample.is_positive();
+            if last_sign.is_some_and(|last_sign| last_sign != sign) {
+                zero_crossings += 1;
+            }
+            last_sign = Some(sign);
+        }
+
+        (energy, (zero_crossings as u64 * 1000 / samples.len() as u64) as u32)
+    }
+
+    // End-to-end check of the driver, which doesn't need anyone listening: every codec has to answer a command, and a tone on the
+    // default output endpoint has to move the DMA position and raise buffer completion interrupts. Uses the stream descriptor of
+    // the test tone, so it must not run at the same time as fn play_test_tone or the demo functions.
+    pub fn self_test(&self) -> SelfTestReport {
+        let codecs = self.all_codecs();
+        let codecs_responding = !codecs.is_empty() && codecs.iter().all(|codec| {
+            let root_node = NodeAddress::new(*codec.codec().codec_address(), 0);
+            match self.controller.try_command(GetParameter(root_node, VendorId)).map(VendorIdResponse::try_from) {
+                Ok(Ok(vendor_id)) => vendor_id.vendor_id() == codec.codec().vendor_id().vendor_id() && vendor_id.device_id() == codec.codec().vendor_id().device_id(),
+                _ => false,
+            }
+        });
+        let mut report = SelfTestReport {
+            codecs_responding,
+            tone_started: false,
+            dma_position_advancing: false,
+            interrupts_delivered: false,
+        };
+
+        // four buffers with 512 frames each, so that an interrupt gets raised about every 11 ms
+        self.stop_demo();
+        let stream = match self.open_output_stream(StreamOwner::Kernel("ihda self test"), None, AudioFormat::mono_48khz_16bit(), TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, 4, 2) {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!("IHDA self test could not open a stream: {:?}", error);
+                return report;
+            }
+        };
+        stream.write_signal(&mut stream.signal_generator(Waveform::Square, SELF_TEST_FREQUENCY));
+        let stream_descriptor_number = self.controller.output_stream_descriptor_number(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR);
+        let interrupts_before = INTERRUPT_COUNT.load(Ordering::Relaxed);
+        self.enable_buffer_completion_interrupt(&stream);
+        self.start_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, &stream);
+        report.tone_started = stream.state() == StreamState::Running;
+
+        let mut positions = Vec::new();
+        for _ in 0..SELF_TEST_POSITION_SAMPLE_COUNT {
+            Timer::wait(SELF_TEST_POSITION_SAMPLE_INTERVAL_IN_MS);
+            positions.push(self.stream_position(stream_descriptor_number));
+        }
+        // the position wraps around at the end of the cyclic buffer, so it only has to change between all samples
+        report.dma_position_advancing = positions.windows(2).all(|pair| pair[0] != pair[1]);
+        report.interrupts_delivered = INTERRUPT_COUNT.load(Ordering::Relaxed) > interrupts_before;
+
+        self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, &stream);
+        debug!("IHDA self test sampled DMA positions {:?}", positions);
+
+        report
+    }
+
+    // ########## debug dumps ##########
+
+    pub fn error_statistics(&self) -> ErrorStatistics {
+        self.error_statistics.lock().clone()
+    }
+
+    // current values of the controller and stream descriptor registers, one register per line
+    pub fn dump_registers(&self) -> String {
+        self.controller.dump_registers()
+    }
+
+    // counters of fn error_statistics, one stream descriptor per line
+    pub fn dump_error_statistics(&self) -> String {
+        let error_statistics = self.error_statistics();
+        let mut dump = String::new();
+        writeln!(dump, "CORB memory errors: {}, RIRB overruns: {}, command ring restarts: {} ({} failed)",
+            error_statistics.corb_memory_errors, error_statistics.response_overruns,
+            error_statistics.command_ring_restarts, error_statistics.failed_command_ring_restarts).unwrap();
+        for counters in error_statistics.streams.iter() {
+            writeln!(dump, "Stream descriptor {}: {} FIFO errors, {} descriptor errors, {} stalls, {} recoveries ({} failed)",
+                counters.stream_descriptor_number, counters.fifo_errors, counters.descriptor_errors, counters.stalls,
+                counters.recoveries, counters.failed_recoveries).unwrap();
+        }
+        dump
+    }
+
+    pub fn set_verb_trace_enabled(&self, enabled: bool) {
+        self.controller.set_verb_trace_enabled(enabled);
+    }
+
+    // The most recent verbs and responses, one per line with the wall clock ticks, the time since the previous entry and the raw
+    // value, so that it can be compared to traces of other drivers. Verbs are additionally decoded.
+    pub fn dump_verb_trace(&self, amount: usize) -> String {
+        let entries = self.controller.last_verb_trace_entries(amount);
+        let state = if self.controller.is_verb_trace_enabled() { "on" } else { "off" };
+        let mut dump = String::new();
+        writeln!(dump, "Verb trace {}, {} entries", state, entries.len()).unwrap();
+
+        let mut previous_wall_clock = entries.first().map(|entry| *entry.wall_clock());
+        for entry in entries.iter() {
+            // WALCLK wraps around after about 179 seconds
+            let elapsed_us = entry.wall_clock().wrapping_sub(previous_wall_clock.unwrap_or(*entry.wall_clock())) / WALL_CLOCK_TICKS_PER_US;
+            previous_wall_clock = Some(*entry.wall_clock());
+            write!(dump, "{:>10} +{:>8} us  ", entry.wall_clock(), elapsed_us).unwrap();
+            match entry.event() {
+                VerbTraceEvent::Command(command) => writeln!(dump, "codec {} -> {:#010x} {:?}", command.codec_address(), command.as_u32(), command),
+                VerbTraceEvent::Response { codec_address, raw_response, unsolicited: false } => writeln!(dump, "codec {} <- {:#010x}", codec_address, raw_response),
+                VerbTraceEvent::Response { codec_address, raw_response, unsolicited: true } => writeln!(dump, "codec {} <- {:#010x} (unsolicited)", codec_address, raw_response),
+                VerbTraceEvent::Timeout(command) => writeln!(dump, "codec {} timeout {:#010x}", command.codec_address(), command.as_u32()),
+            }.unwrap();
+        }
+        dump
+    }
+
+    // Tree of all codecs with their function groups and widgets. Each widget is followed by the node ids in its connection list
+    // and its current power state, if it supports power states. Pin widgets additionally show their configuration default.
+    pub fn dump_codecs(&self) -> String {
+        let mut dump = String::new();
+        for codec in self.all_codecs() {
+            let model_name = codec.codec().model_name().map(|model_name| format!(" ({})", model_name)).unwrap_or_default();
+            let quarantined = if self.controller.is_codec_quarantined(codec.codec_address()) { " (quarantined)" } else { "" };
+            writeln!(dump, "Codec {}: vendor {:#06x}, device {:#06x}{}{}",
+                codec.codec_address(), codec.codec().vendor_id().vendor_id(), codec.codec().vendor_id().device_id(), model_name, quarantined).unwrap();
+            for function_group in codec.codec().function_groups().iter() {
+                writeln!(dump, "  Function group {:#04x} ({:?}), power {}",
+                    function_group.function_group_node_address().node_id(), function_group.function_group_type().node_type(),
+                    self.describe_power_state(*function_group.function_group_node_address())).unwrap();
+                for widget in function_group.widgets().iter() {
+                    write!(dump, "    {:#04x} {:?}, {} ch", widget.address().node_id(), widget.audio_widget_capabilities().widget_type(), widget.max_number_of_channels()).unwrap();
+                    if widget.is_digital() {
+                        write!(dump, ", digital").unwrap();
+                    }
+                    let connection_list = widget.connection_list();
+                    if !connection_list.is_empty() {
+                        write!(dump, ", inputs {:x?}", connection_list).unwrap();
+                    }
+                    if *widget.audio_widget_capabilities().power_cntrl() {
+                        write!(dump, ", power {}", self.describe_power_state(*widget.address())).unwrap();
+                    }
+                    writeln!(dump).unwrap();
+                    if let Some(config_default) = widget.configuration_default() {
+                        writeln!(dump, "         {:?}, {:?}, {:?}, {:?} {:?}, association {}, sequence {}",
+                            config_default.default_device(), config_default.port_connectivity(), config_default.connection_type(),
+                            config_default.gross_location(), config_default.geometric_location(),
+                            config_default.default_association(), config_default.sequence()).unwrap();
+                    }
+                }
+            }
+        }
+        dump
+    }
+
+    // Actual power state of a node, followed by the requested one while a transition is in progress (see specification,
+    // section 7.3.3.10). Codecs which don't respond (e.g. quarantined ones) don't abort the dump.
+    fn describe_power_state(&self, node_address: NodeAddress) -> String {
+        let power_state = match self.controller.try_command(GetPowerState(node_address)).map(PowerStateResponse::try_from) {
+            Ok(Ok(power_state)) => power_state,
+            _ => return String::from("unknown"),
+        };
+        let mut description = format!("{:?}", power_state.actual());
+        if power_state.setting() != power_state.actual() {
+            write!(description, " -> {:?}", power_state.setting()).unwrap();
+        }
+        if *power_state.error() {
+            write!(description, " (error)").unwrap();
+        }
+        if *power_state.settings_reset() {
+            write!(description, " (settings reset)").unwrap();
+        }
+        description
+    }
+
+    // Same graph as fn dump_codecs as JSON for scripts comparing it with dumps of other operating systems, including the amp
+    // capabilities and the current control state of every widget (see device::ihda_codec_export).
+    pub fn export_codec_graph(&self) -> String {
+        let codecs = self.all_codecs();
+        let codecs: Vec<(&Codec, bool)> = codecs.iter()
+            .map(|codec| (codec.codec(), self.controller.is_codec_quarantined(codec.codec_address())))
+            .collect();
+        export_codec_graph(&self.controller, &codecs)
+    }
+
+    // Widget path of every registered output stream, from the pin widget to the audio output converter. The stream id and
+    // format get read back from the converter, so that they show what is actually configured in the codec.
+    pub fn dump_playback_paths(&self) -> String {
+        let codecs = self.codec_snapshot();
+        let mut dump = String::new();
+        let streams: Vec<StreamInfo> = stream_registry().lock().streams().clone();
+        for stream in streams.iter() {
+            let (function_group, pin_widget) = match stream.endpoint().and_then(|endpoint| codecs.find_pin_widget(endpoint)) {
+                Some(pin) => pin,
+                None => continue,
+            };
+            if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Output {
+                continue;
+            }
+
+            writeln!(dump, "Stream {} on stream descriptor {} ({:?}) -> endpoint {}:{:#04x}",
+                stream.stream_id(), stream.stream_descriptor_number(), stream.owner(),
+                pin_widget.address().codec_address().codec_address(), pin_widget.address().node_id()).unwrap();
+            for mirrored_endpoint in stream.mirrored_endpoints() {
+                writeln!(dump, "  mirrored to endpoint {}:{:#04x}", mirrored_endpoint.codec_address(), mirrored_endpoint.node_id()).unwrap();
+            }
+            for assignment in stream.channel_assignments() {
+                writeln!(dump, "  channels {}-{} on endpoint {}:{:#04x}", assignment.lowest_channel(), assignment.lowest_channel() + assignment.channel_count() - 1,
+                    assignment.endpoint().codec_address(), assignment.endpoint().node_id()).unwrap();
+            }
+            for widget in function_group.find_widget_path_from_pin(pin_widget) {
+                write!(dump, "  {:#04x} {:?}", widget.address().node_id(), widget.audio_widget_capabilities().widget_type()).unwrap();
+                if let Some(active_format) = self.active_format_of_converter(widget) {
+                    // codecs which don't respond (e.g. quarantined ones) don't abort the dump
+                    match self.controller.try_command(GetChannelStreamId(*widget.address())).map(ChannelStreamIdResponse::try_from) {
+                        Ok(Ok(channel_stream_id)) => write!(dump, ", stream {}", channel_stream_id.stream()).unwrap(),
+                        _ => write!(dump, ", stream unknown").unwrap(),
+                    }
+                    write!(dump, ", {} Hz/{} bit/{} ch", active_format.sample_rate(), active_format.bits_per_sample(), active_format.channels()).unwrap();
+                }
+                writeln!(dump).unwrap();
+            }
+        }
+
+        if dump.is_empty() {
+            dump.push_str("No playback streams\n");
+        }
+        dump
+    }
+
+    // Reads back the controls of every widget on the paths of the registered output streams and compares them with what
+    // the driver configured, without changing anything. Catches Set verbs which the codec silently ignored.
+    pub fn validate_paths(&self) -> Vec<PathValidationReport> {
+        let codecs = self.codec_snapshot();
+        let check_power_states = !self.power.lock().powered_down;
+        let streams: Vec<StreamInfo> = stream_registry().lock().streams().clone();
+        let mut reports = Vec::new();
+        for stream in streams.iter() {
+            let endpoint = match stream.endpoint() {
+                Some(endpoint) => *endpoint,
+                None => continue,
+            };
+            // the endpoints of a multichannel stream play some of its channels, all other endpoints the whole stream
+            let mut paths: Vec<(EndpointId, u8)> = stream.channel_assignments().iter()
+                .map(|assignment| (*assignment.endpoint(), *assignment.lowest_channel()))
+                .collect();
+            if paths.is_empty() {
+                paths.push((endpoint, 0));
+                paths.extend(stream.mirrored_endpoints().iter().map(|mirrored_endpoint| (*mirrored_endpoint, 0)));
+            }
+
+            for (endpoint, lowest_channel) in paths {
+                let (function_group, pin_widget) = match codecs.find_pin_widget(endpoint) {
+                    Some(pin) => pin,
+                    None => continue,
+                };
+                if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Output {
+                    continue;
+                }
+                let path = function_group.find_widget_path_from_pin(pin_widget);
+                let mismatches = self.validate_path(function_group, &path, stream, lowest_channel, check_power_states);
+                reports.push(PathValidationReport { stream_id: *stream.stream_id(), endpoint, mismatches });
+            }
+        }
+        reports
+    }
+
+    // the path starts at the pin widget and ends at the audio output converter, like the paths of fn find_widget_path_from_pin
+    fn validate_path(&self, function_group: &FunctionGroup, path: &[&Widget], stream: &StreamInfo, lowest_channel: u8, check_power_states: bool) -> Vec<PathMismatch> {
+        let mut mismatches = Vec::new();
+
+        if check_power_states {
+            let function_group_power_state = PowerStateResponse::try_from(self.controller.command(GetPowerState(*function_group.function_group_node_address()))).unwrap();
+            if *function_group_power_state.actual() != PowerState::D0 {
+                mismatches.push(PathMismatch::NotPoweredUp { node_id: *function_group.function_group_node_address().node_id(), actual: *function_group_power_state.actual() });
+            }
+        }
+
+        let mut expected_mute = None;
+        for (index, widget) in path.iter().enumerate() {
+            let node_id = *widget.address().node_id();
+
+            if check_power_states && *widget.audio_widget_capabilities().power_cntrl() {
+                let power_state = PowerStateResponse::try_from(self.controller.command(GetPowerState(*widget.address()))).unwrap();
+                if *power_state.actual() != PowerState::D0 {
+                    mismatches.push(PathMismatch::NotPoweredUp { node_id, actual: *power_state.actual() });
+                }
+            }
+
+            // each widget takes its input from the next widget on the path (see fn select_connections_on_path)
+            if let Some(upstream_widget) = path.get(index + 1) {
+                let connection_index = widget.connection_list().iter()
+                    .position(|node_id| node_id == upstream_widget.address().node_id())
+                    .unwrap() as u8;
+                match widget.audio_widget_capabilities().widget_type() {
+                    WidgetType::AudioMixer => {
+                        if function_group.input_amp_capabilities_of(widget).is_some_and(|caps| *caps.mute_capable()) {
+                            let payload = GetAmplifierGainMutePayload::new(GetAmplifierGainMuteType::Input, GetAmplifierGainMuteSide::Left, connection_index);
+                            let input_amp = AmplifierGainMuteResponse::try_from(self.controller.command(GetAmplifierGainMute(*widget.address(), payload))).unwrap();
+                            if *input_amp.amplifier_mute() {
+                                mismatches.push(PathMismatch::MixerInputMuted { node_id, connection_index });
+                            }
+                        }
+                    }
+                    _ if widget.connection_list().len() > 1 => {
+                        let selected = ConnectionSelectResponse::try_from(self.controller.command(GetConnectionSelect(*widget.address()))).unwrap();
+                        if *selected.currently_set_connection_index() != connection_index {
+                            mismatches.push(PathMismatch::ConnectionSelect { node_id, expected: connection_index, actual: *selected.currently_set_connection_index() });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if function_group.output_amp_capabilities_of(widget).is_some_and(|caps| *caps.mute_capable()) {
+                for side in [GetAmplifierGainMuteSide::Left, GetAmplifierGainMuteSide::Right] {
+                    let muted = *self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, side).amplifier_mute();
+                    let expected = *expected_mute.get_or_insert(muted);
+                    if muted != expected {
+                        mismatches.push(PathMismatch::OutputAmpMute { node_id, expected, actual: muted });
+                        break;
+                    }
+                }
+            }
+
+            match widget.audio_widget_capabilities().widget_type() {
+                WidgetType::AudioOutput => {
+                    let channel_stream_id = ChannelStreamIdResponse::try_from(self.controller.command(GetChannelStreamId(*widget.address()))).unwrap();
+                    if *channel_stream_id.stream() != *stream.stream_id() || *channel_stream_id.channel() != lowest_channel {
+                        mismatches.push(PathMismatch::ChannelStreamId {
+                            node_id,
+                            expected_stream: *stream.stream_id(),
+                            expected_channel: lowest_channel,
+                            actual_stream: *channel_stream_id.stream(),
+                            actual_channel: *channel_stream_id.channel(),
+                        });
+                    }
+                    let active_format = self.active_format_of_converter(widget);
+                    if active_format != Some(*stream.format()) {
+                        mismatches.push(PathMismatch::AudioFormat { node_id, expected: *stream.format(), actual: active_format });
+                    }
+                }
+                WidgetType::PinComplex => {
+                    let pin_widget_control = PinWidgetControlResponse::try_from(self.controller.command(GetPinWidgetControl(*widget.address()))).unwrap();
+                    if !*pin_widget_control.out_enable() {
+                        mismatches.push(PathMismatch::PinOutputDisabled { node_id });
+                    }
+                    if *widget.pin_capabilities().unwrap().eapd_capable() && matches!(self.controller.eapd_enabled(widget), Ok(false)) {
+                        mismatches.push(PathMismatch::EapdDisabled { node_id });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        mismatches
+    }
+
+    // text form of fn validate_paths for the ihda application
+    pub fn dump_path_validation(&self) -> String {
+        let mut dump = String::new();
+        for report in self.validate_paths() {
+            writeln!(dump, "Stream {} -> endpoint {}:{:#04x}: {}", report.stream_id(), report.endpoint().codec_address(), report.endpoint().node_id(),
+                if report.is_valid() { "OK" } else { "MISMATCH" }).unwrap();
+            for mismatch in report.mismatches() {
+                writeln!(dump, "  {:?}", mismatch).unwrap();
+            }
+        }
+
+        if dump.is_empty() {
+            dump.push_str("No playback streams\n");
+        }
+        dump
+    }
+
+    // compares SDLPIB with the DMA position buffer of a running stream and switches the position source of the controller if necessary
+    pub fn diagnose_position_sources(&self, stream_descriptor_number: u32, sample_count: u32, interval_in_ms: usize) -> PositionSourceDiagnostics {
+        self.controller.diagnose_position_sources(stream_descriptor_number, sample_count, interval_in_ms)
+    }
+}
+
+// Result of fn loopback_test. The energy is the mean square of the samples of the first channel and the zero crossing rate
+// is given in zero crossings per 1000 frames, which is enough to tell a square wave of the test frequency from noise or silence.
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct LoopbackReport {
+    played_energy: u64,
+    recorded_energy: u64,
+    played_zero_crossing_rate: u32,
+    recorded_zero_crossing_rate: u32,
+    passed: bool,
+}
+
+// Result of fn self_test, which gets reported over the serial port when the kernel runs in self test mode.
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct SelfTestReport {
+    // every codec answered through the current command transport with the vendor id it reported during enumeration
+    codecs_responding: bool,
+    tone_started: bool,
+    dma_position_advancing: bool,
+    // buffer completion interrupts of the tone stream reached the interrupt handler
+    interrupts_delivered: bool,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.codecs_responding && self.tone_started && self.dma_position_advancing && self.interrupts_delivered
+    }
+}
+
+// Result of fn validate_paths for the widget path of one endpoint a stream plays on
+#[derive(Clone, Debug, Getters)]
+pub struct PathValidationReport {
+    stream_id: u8,
+    endpoint: EndpointId,
+    mismatches: Vec<PathMismatch>,
+}
+
+impl PathValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+// A control of a widget on a playback path which got read back with a Get verb and doesn't hold what the driver configured,
+// usually because the codec ignored a Set verb.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathMismatch {
+    // None if the converter isn't connected to any stream
+    AudioFormat { node_id: u8, expected: ActiveFormat, actual: Option<ActiveFormat> },
+    ChannelStreamId { node_id: u8, expected_stream: u8, expected_channel: u8, actual_stream: u8, actual_channel: u8 },
+    ConnectionSelect { node_id: u8, expected: u8, actual: u8 },
+    // the input amp of a mixer for the connection the signal arrives through is muted
+    MixerInputMuted { node_id: u8, connection_index: u8 },
+    // All mute capable output amps on a path get muted and unmuted together (see fn apply_endpoint_settings_change),
+    // so they are expected to have the mute bit of the first one, which is the amp closest to the pin.
+    OutputAmpMute { node_id: u8, expected: bool, actual: bool },
+    PinOutputDisabled { node_id: u8 },
+    EapdDisabled { node_id: u8 },
+    // only checked while the codecs are not powered down because of inactivity
+    NotPoweredUp { node_id: u8, actual: PowerState },
+}
+
+// signals of the speaker test (see fn IntelHDAudioDevice::test_speakers)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeakerTestSignal {
+    // sine sweep on all channels
+    Sweep,
+    // beeps on a single channel, once for channel 0, twice for channel 1 and so on
+    Channel(u8),
+}
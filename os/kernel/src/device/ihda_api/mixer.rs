@@ -0,0 +1,674 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use log::{debug, info, warn};
+use crate::audio_service;
+use crate::audio::events::AudioEvent;
+use crate::audio::persistence::{PersistedEndpoint, PersistedSettings};
+use crate::audio::stream_registry;
+use crate::audio::settings::{DeviceSettings, EndpointDirection, EndpointId, EndpointSettings, EndpointSettingsChange, SoundSettings, SoundSettingsDiff, SoundSettingsError, MAX_VOLUME_PERCENT};
+use crate::device::ihda_codec::{decode_response, CommandError, CommandTransport, FunctionGroup, SidetonePath, Widget};
+use crate::device::ihda_verbs::{AmpCapabilitiesResponse, AmplifierGainMuteResponse, ConnectionSelectResponse, GetAmplifierGainMutePayload, GetAmplifierGainMuteSide, GetAmplifierGainMuteType, SetAmplifierGainMutePayload, SetAmplifierGainMuteSide, SetAmplifierGainMuteType, WidgetType};
+use crate::device::ihda_verbs::Command::{GetAmplifierGainMute, GetConnectionSelect, SetAmplifierGainMute};
+use crate::device::ihda_codec_driver::{endpoint_id, endpoint_kind, CodecDriver};
+use crate::device::ihda_api::{CodecSnapshot, CoefficientBank, IntelHDAudioDevice, MAX_AMP_INDEX, PlaybackError};
+
+impl IntelHDAudioDevice {
+    // ########## widget controls ##########
+
+    // Sets the gain or mute of a widget on both sides, like a fader of a hardware mixer. Pin widgets of input endpoints use their
+    // input amp, all other widgets their output amp if they have one. Mixers without an output amp get the control on the input
+    // amps of all of their connections. The endpoint settings are read back from the amps, so they follow these changes.
+    pub fn set_widget_control(&self, codec_address: u8, node_id: u8, control: MixerControl) -> Result<(), MixerError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, widget) = Self::find_mixer_widget(&codecs, codec_address, node_id)?;
+        let (amp_type, caps) = Self::mixer_amp_of(function_group, widget).ok_or(MixerError::NoAmplifier(codec_address, node_id))?;
+        if matches!(control, MixerControl::Mute(_)) && !*caps.mute_capable() {
+            return Err(MixerError::NotMuteCapable(codec_address, node_id));
+        }
+        self.ensure_powered_up();
+
+        let indices = match (amp_type, widget.audio_widget_capabilities().widget_type()) {
+            (SetAmplifierGainMuteType::Input, WidgetType::AudioMixer) => 0..(widget.connection_list().len() as u8).min(MAX_AMP_INDEX + 1),
+            _ => 0..1,
+        };
+        let get_amp_type = match amp_type {
+            SetAmplifierGainMuteType::Input => GetAmplifierGainMuteType::Input,
+            _ => GetAmplifierGainMuteType::Output,
+        };
+        for index in indices {
+            let payload = GetAmplifierGainMutePayload::new(get_amp_type, GetAmplifierGainMuteSide::Left, index);
+            let current: AmplifierGainMuteResponse = self.controller.try_command(GetAmplifierGainMute(*widget.address(), payload))
+                .and_then(|response| decode_response(response, *widget.address()))
+                .map_err(MixerError::Command)?;
+            let (gain, mute) = match control {
+                MixerControl::Gain(gain_percent) => (volume_percent_to_gain(gain_percent, *caps.num_steps()), *current.amplifier_mute()),
+                MixerControl::Mute(mute) => (*current.amplifier_gain(), mute),
+            };
+            self.controller.try_command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(amp_type, SetAmplifierGainMuteSide::Both, index, mute, gain)))
+                .map_err(MixerError::Command)?;
+        }
+        Ok(())
+    }
+
+    fn find_mixer_widget(codecs: &CodecSnapshot, codec_address: u8, node_id: u8) -> Result<(&FunctionGroup, &Widget), MixerError> {
+        codecs.codec(codec_address)
+            .and_then(|codec| codec.codec().function_groups().iter()
+                .find_map(|function_group| function_group.widgets().iter()
+                    .find(|widget| *widget.address().node_id() == node_id)
+                    .map(|widget| (function_group, widget))))
+            .ok_or(MixerError::UnknownWidget(codec_address, node_id))
+    }
+
+    // the amp which fn set_widget_control sets on the widget
+    fn mixer_amp_of<'a>(function_group: &'a FunctionGroup, widget: &'a Widget) -> Option<(SetAmplifierGainMuteType, &'a AmpCapabilitiesResponse)> {
+        let input_endpoint = widget.configuration_default()
+            .is_some_and(|config_default| endpoint_kind(config_default.default_device()).direction() == EndpointDirection::Input);
+        let output_amp = function_group.output_amp_capabilities_of(widget).map(|caps| (SetAmplifierGainMuteType::Output, caps));
+        let input_amp = function_group.input_amp_capabilities_of(widget).map(|caps| (SetAmplifierGainMuteType::Input, caps));
+        if input_endpoint {
+            input_amp.or(output_amp)
+        } else {
+            output_amp.or(input_amp)
+        }
+    }
+
+    // ########## processing coefficients ##########
+
+    // Reads a bank of processing coefficients of a widget (e.g. the filter settings of a hardware equalizer). The meaning of the
+    // coefficients is vendor specific, so they are only passed through (see section 7.3.3.9 of the specification).
+    pub fn read_processing_coefficients(&self, codec_address: u8, node_id: u8, first_index: u16, amount: u16) -> Result<Vec<u16>, ProcessingError> {
+        let codecs = self.codec_snapshot();
+        let widget = Self::find_processing_widget(&codecs, codec_address, node_id, first_index, amount)?;
+        self.ensure_powered_up();
+        self.controller.read_processing_coefficients(widget, first_index, amount).map_err(ProcessingError::Command)
+    }
+
+    // Writes a bank of processing coefficients of a widget, which gets written again whenever the codec gets reset.
+    pub fn write_processing_coefficients(&self, codec_address: u8, node_id: u8, first_index: u16, coefficients: &[u16]) -> Result<(), ProcessingError> {
+        let amount = u16::try_from(coefficients.len()).map_err(|_| ProcessingError::IndexOutOfRange(node_id, u16::MAX))?;
+        let codecs = self.codec_snapshot();
+        let widget = Self::find_processing_widget(&codecs, codec_address, node_id, first_index, amount)?;
+        self.ensure_powered_up();
+        self.controller.write_processing_coefficients(widget, first_index, coefficients).map_err(ProcessingError::Command)?;
+
+        let mut coefficient_banks = self.coefficient_banks.lock();
+        // banks which have been overwritten completely don't have to be written again
+        coefficient_banks.retain(|bank| bank.codec_address != codec_address || bank.node_id != node_id
+            || bank.first_index < first_index || bank.first_index as usize + bank.coefficients.len() > first_index as usize + coefficients.len());
+        coefficient_banks.push(CoefficientBank { codec_address, node_id, first_index, coefficients: coefficients.to_vec() });
+        Ok(())
+    }
+
+    // A processing widget reports the amount of its coefficients in its processing capabilities, so banks beyond it get rejected.
+    fn find_processing_widget(codecs: &CodecSnapshot, codec_address: u8, node_id: u8, first_index: u16, amount: u16) -> Result<&Widget, ProcessingError> {
+        let codec = codecs.codec(codec_address).ok_or(ProcessingError::UnknownCodec(codec_address))?;
+        let widget = codec.find_widget(node_id).ok_or(ProcessingError::UnknownWidget(node_id))?;
+        let processing_capabilities = widget.processing_capabilities().ok_or(ProcessingError::NotAProcessingWidget(node_id))?;
+        let end = first_index as usize + amount as usize;
+        if end > *processing_capabilities.num_coeff() as usize {
+            return Err(ProcessingError::IndexOutOfRange(node_id, end.min(u16::MAX as usize) as u16));
+        }
+        Ok(widget)
+    }
+
+    // a codec which got reset or attached again has lost the coefficients written to its widgets
+    pub fn restore_coefficient_banks(&self, codec: &CodecDriver) {
+        let coefficient_banks = self.coefficient_banks.lock();
+        for bank in coefficient_banks.iter().filter(|bank| bank.codec_address == codec.codec_address()) {
+            if let Some(widget) = codec.find_widget(bank.node_id) {
+                if let Err(error) = self.controller.write_processing_coefficients(widget, bank.first_index, &bank.coefficients) {
+                    warn!("Failed to restore the processing coefficients of widget {:?}: {:?}", widget.address(), error);
+                }
+            }
+        }
+    }
+
+    // ########## sidetone ##########
+
+    // Input monitoring: while an input endpoint gets recorded, a mixer widget on the path of the default output endpoint mixes
+    // the signal of its pin into the output, so that the user hears themselves without the latency of the DMA engines.
+    // The level is the gain of the mixer input in percent of its gain steps, where 0 turns the sidetone off. It gets applied
+    // right away to a running recording and otherwise at the start of the next one. Without a running recording, the default
+    // input endpoint gets checked, so that a codec without such a mixer gets reported here instead of being silently ignored.
+    pub fn set_sidetone_level(&self, level_percent: u8) -> Result<(), PlaybackError> {
+        let level_percent = level_percent.min(MAX_VOLUME_PERCENT);
+        let codecs = self.codec_snapshot();
+        let mut sidetone = self.sidetone.lock();
+        let (function_group, sidetone_path) = self.find_sidetone_path(&codecs, sidetone.capture_endpoint)?;
+        if sidetone.capture_endpoint.is_some() {
+            self.apply_sidetone_level(function_group, &sidetone_path, level_percent);
+        }
+        sidetone.level_percent = level_percent;
+        Ok(())
+    }
+
+    // an input endpoint without a sidetone path just gets recorded without sidetone
+    pub fn start_sidetone(&self, capture_endpoint: EndpointId) {
+        let codecs = self.codec_snapshot();
+        let mut sidetone = self.sidetone.lock();
+        sidetone.capture_endpoint = Some(capture_endpoint);
+        if sidetone.level_percent == 0 {
+            return;
+        }
+        match self.find_sidetone_path(&codecs, Some(capture_endpoint)) {
+            Ok((function_group, sidetone_path)) => self.apply_sidetone_level(function_group, &sidetone_path, sidetone.level_percent),
+            Err(error) => debug!("No sidetone for endpoint {:?}: {:?}", capture_endpoint, error),
+        }
+    }
+
+    pub fn stop_sidetone(&self) {
+        let codecs = self.codec_snapshot();
+        let mut sidetone = self.sidetone.lock();
+        let capture_endpoint = sidetone.capture_endpoint.take();
+        if sidetone.level_percent == 0 {
+            return;
+        }
+        if let Ok((function_group, sidetone_path)) = self.find_sidetone_path(&codecs, capture_endpoint) {
+            self.apply_sidetone_level(function_group, &sidetone_path, 0);
+        }
+    }
+
+    // mixer which mixes the input endpoint (the default input endpoint if None) into the path of the default output endpoint
+    fn find_sidetone_path<'c>(&self, codecs: &'c CodecSnapshot, input_endpoint: Option<EndpointId>) -> Result<(&'c FunctionGroup, SidetonePath<'c>), PlaybackError> {
+        let (function_group, input_pin_widget) = match input_endpoint {
+            Some(id) => codecs.find_pin_widget(id).ok_or(PlaybackError::UnknownEndpoint(id))?,
+            None => codecs.find_default_input_pin_widget().ok_or(PlaybackError::NoDefaultEndpoint)?,
+        };
+        let id = endpoint_id(input_pin_widget);
+        if endpoint_kind(input_pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Input {
+            return Err(PlaybackError::NotAnInputEndpoint(id));
+        }
+
+        // the signal can only be mixed into an output path of the same codec
+        let (_, output_pin_widget, _) = self.find_output_endpoint(codecs, None)?;
+        if output_pin_widget.address().codec_address().codec_address() != input_pin_widget.address().codec_address().codec_address() {
+            return Err(PlaybackError::NoSidetonePath(id));
+        }
+        function_group.find_sidetone_path(input_pin_widget, output_pin_widget)
+            .filter(|sidetone_path| function_group.input_amp_capabilities_of(sidetone_path.mixer()).is_some())
+            .map(|sidetone_path| (function_group, sidetone_path))
+            .ok_or(PlaybackError::NoSidetonePath(id))
+    }
+
+    // an input mixer between the pin and the mixer on the output path passes the signal on at 0 dB, so that only the level
+    // of the mixer on the output path matters
+    fn apply_sidetone_level(&self, function_group: &FunctionGroup, sidetone_path: &SidetonePath, level_percent: u8) {
+        let num_steps = *function_group.input_amp_capabilities_of(sidetone_path.mixer()).unwrap().num_steps();
+        let gain = match level_percent {
+            0 => None,
+            level_percent => Some(volume_percent_to_gain(level_percent, num_steps)),
+        };
+        let input_mixer_gain = sidetone_path.input_mixer()
+            .and_then(|(input_mixer, _)| function_group.input_amp_capabilities_of(input_mixer))
+            .map_or(0, |caps| *caps.offset());
+        debug!("Setting sidetone through mixer {:?} to {} %", sidetone_path.mixer().address(), level_percent);
+        self.controller.configure_sidetone_path(sidetone_path, gain, input_mixer_gain);
+    }
+
+    // ########## volumes and sound settings ##########
+
+    // Playback and capture path of every connected endpoint with the current state of the controls on each widget: the gain of
+    // its amps in dB, whether they are muted and which connection it selects. Playback paths start at the pin widget, capture
+    // paths at the converter, so each widget takes its input from the widget below it.
+    pub fn dump_mixer(&self) -> String {
+        let mut dump = String::new();
+        for codec in self.available_codecs() {
+            for function_group in codec.codec().function_groups().iter() {
+                for pin_widget in function_group.find_connected_pin_widgets() {
+                    let kind = endpoint_kind(pin_widget.configuration_default().unwrap().default_device());
+                    let path = match kind.direction() {
+                        EndpointDirection::Output => Some(function_group.find_widget_path_from_pin(pin_widget)),
+                        EndpointDirection::Input => function_group.find_widget_path_for_capture(pin_widget),
+                    };
+                    let label = match kind.direction() {
+                        EndpointDirection::Output => "Playback",
+                        EndpointDirection::Input => "Capture",
+                    };
+                    writeln!(dump, "{} endpoint {}:{:#04x} ({:?})", label, codec.codec_address(), pin_widget.address().node_id(), kind).unwrap();
+                    match path {
+                        Some(path) => {
+                            for (index, widget) in path.iter().enumerate() {
+                                writeln!(dump, "  {}", self.describe_mixer_controls(function_group, widget, path.get(index + 1).copied())).unwrap();
+                            }
+                        }
+                        None => writeln!(dump, "  no converter on path").unwrap(),
+                    }
+                }
+            }
+        }
+
+        if dump.is_empty() {
+            dump.push_str("No endpoints\n");
+        }
+        dump
+    }
+
+    fn describe_mixer_controls(&self, function_group: &FunctionGroup, widget: &Widget, upstream_widget: Option<&Widget>) -> String {
+        let mut description = format!("{:#04x} {:?}", widget.address().node_id(), widget.audio_widget_capabilities().widget_type());
+        let connection_index = upstream_widget.and_then(|upstream_widget| widget.connection_list().iter()
+            .position(|node_id| node_id == upstream_widget.address().node_id())
+            .map(|index| index as u8));
+
+        if let Some(caps) = function_group.output_amp_capabilities_of(widget) {
+            let left = self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Left);
+            let right = self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Right);
+            write!(description, ", out {}", describe_amp(caps, &left, Some(&right))).unwrap();
+        }
+        // only mixers have an input amp per connection, all other widgets have a single one at index 0
+        if let Some(caps) = function_group.input_amp_capabilities_of(widget) {
+            let index = match widget.audio_widget_capabilities().widget_type() {
+                WidgetType::AudioMixer => connection_index.filter(|index| *index <= MAX_AMP_INDEX),
+                _ => Some(0),
+            };
+            if let Some(index) = index {
+                let payload = GetAmplifierGainMutePayload::new(GetAmplifierGainMuteType::Input, GetAmplifierGainMuteSide::Left, index);
+                let input_amp = AmplifierGainMuteResponse::try_from(self.controller.command(GetAmplifierGainMute(*widget.address(), payload))).unwrap();
+                write!(description, ", in[{}] {}", index, describe_amp(caps, &input_amp, None)).unwrap();
+            }
+        }
+
+        let connection_list = widget.connection_list();
+        if connection_list.len() > 1 && !matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioMixer) {
+            let selected = *ConnectionSelectResponse::try_from(self.controller.command(GetConnectionSelect(*widget.address()))).unwrap().currently_set_connection_index();
+            match connection_list.get(selected as usize) {
+                Some(node_id) => write!(description, ", selects {:#04x} of {:x?}", node_id, connection_list).unwrap(),
+                None => write!(description, ", selects invalid connection {} of {:x?}", selected, connection_list).unwrap(),
+            }
+        } else if !connection_list.is_empty() {
+            write!(description, ", inputs {:x?}", connection_list).unwrap();
+        }
+        description
+    }
+
+    // Without an endpoint, the path of the default output endpoint gets returned.
+    pub fn playback_path(&self, endpoint: Option<EndpointId>) -> Result<PlaybackPath, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = self.find_output_endpoint(&codecs, endpoint)?;
+        let path = function_group.find_widget_path_from_pin(pin_widget);
+        let (volume_widget, num_steps) = Self::volume_widget_on_path(function_group, &path).ok_or(PlaybackError::NoVolumeControl(id))?;
+        let codec = codecs.codecs.iter().find(|codec| codec.codec_address() == *id.codec_address()).unwrap().clone();
+        Ok(PlaybackPath { device: self, endpoint: id, codec, volume_widget_node_id: *volume_widget.address().node_id(), num_steps })
+    }
+
+    // snapshot of the current audio state for a (future) sound settings application
+    pub fn sound_settings(&self) -> SoundSettings {
+        let mut devices = Vec::new();
+        let codecs = self.codec_snapshot();
+        for codec in codecs.iter().map(|codec| codec.codec()) {
+            let mut endpoints = Vec::new();
+            for function_group in codec.function_groups().iter() {
+                for pin_widget in function_group.find_connected_pin_widgets() {
+                    endpoints.push(self.endpoint_settings(function_group, pin_widget));
+                }
+            }
+            let name = format!("IHDA codec {} ({:04x}:{:04x})", codec.codec_address().codec_address(), codec.vendor_id().vendor_id(), codec.vendor_id().device_id());
+            devices.push(DeviceSettings::new(name, endpoints));
+        }
+        SoundSettings::new(devices)
+    }
+
+    // applies volume and mute changes requested by a sound settings application
+    // the whole diff gets validated first, so that either all or none of the changes get applied
+    pub fn apply_sound_settings_diff(&self, diff: &SoundSettingsDiff) -> Result<(), SoundSettingsError> {
+        diff.validate(&self.sound_settings())?;
+        let codecs = self.codec_snapshot();
+
+        for change in diff.changes().iter() {
+            let (function_group, pin_widget) = codecs.find_pin_widget(*change.id()).ok_or(SoundSettingsError::UnknownEndpoint(*change.id()))?;
+            self.apply_endpoint_settings_change(function_group, pin_widget, change);
+        }
+        Ok(())
+    }
+
+    // the settings a user has chosen, in the form that gets saved across restarts (see audio::persistence)
+    pub fn persisted_settings(&self) -> PersistedSettings {
+        let endpoints = self.sound_settings().devices().iter()
+            .flat_map(|device| device.endpoints().iter())
+            .map(|endpoint| PersistedEndpoint::new(*endpoint.id(), *endpoint.volume_percent(), *endpoint.muted()))
+            .collect();
+        PersistedSettings::new(*self.default_codec_address.lock(), self.output_routing(), self.pin_roles.lock().clone(), endpoints)
+    }
+
+    // Applies settings saved with fn persisted_settings. Settings of codecs and endpoints that don't exist (anymore) get skipped,
+    // so that a changed hardware configuration doesn't keep the remaining settings from being restored.
+    pub fn restore_persisted_settings(&self, settings: &PersistedSettings) {
+        // pins get retasked first, as retasking rescans the codec and decides which endpoints exist
+        for (endpoint, role) in settings.pin_roles().iter() {
+            if let Err(error) = self.retask_pin(*endpoint, *role) {
+                warn!("Could not restore role {:?} of endpoint {:?}: {:?}", role, endpoint, error);
+            }
+        }
+        if let Some(codec_address) = settings.default_codec_address() {
+            if let Err(error) = self.select_default_codec(*codec_address) {
+                warn!("Could not restore default codec {}: {:?}", codec_address, error);
+            }
+        }
+        self.set_output_routing(*settings.output_routing());
+
+        let current_settings = self.sound_settings();
+        let changes = settings.endpoints().iter()
+            .filter(|endpoint| {
+                let present = current_settings.find_endpoint(*endpoint.id()).is_some();
+                if !present {
+                    warn!("Could not restore volume of endpoint {:?}: Endpoint not found", endpoint.id());
+                }
+                present
+            })
+            .map(|endpoint| EndpointSettingsChange::new(*endpoint.id(), Some((*endpoint.volume_percent()).min(MAX_VOLUME_PERCENT)), Some(*endpoint.muted())))
+            .collect();
+        match self.apply_sound_settings_diff(&SoundSettingsDiff::new(changes)) {
+            Ok(_) => info!("Restored audio settings"),
+            Err(error) => warn!("Could not restore volumes: {:?}", error),
+        }
+    }
+
+    fn endpoint_settings(&self, function_group: &FunctionGroup, pin_widget: &Widget) -> EndpointSettings {
+        let id = EndpointId::new(*pin_widget.address().codec_address().codec_address(), *pin_widget.address().node_id());
+        let kind = endpoint_kind(pin_widget.configuration_default().unwrap().default_device());
+        let mut volume_percent = 0;
+        let mut muted = false;
+        let mut active_format = None;
+
+        match kind.direction() {
+            EndpointDirection::Output => {
+                let path = function_group.find_widget_path_from_pin(pin_widget);
+                // the volume of an output endpoint is controlled by the first amp on the path which actually offers gain steps,
+                // where the louder channel has the volume of the endpoint (see PlaybackPath::set_balance)
+                if let Some((widget, num_steps)) = Self::volume_widget_on_path(function_group, &path) {
+                    let left = self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Left);
+                    let right = self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Right);
+                    volume_percent = gain_to_volume_percent((*left.amplifier_gain()).max(*right.amplifier_gain()), num_steps);
+                }
+                // an output endpoint is muted if any mute capable amp on its path is muted
+                muted = path.iter()
+                    .filter(|widget| function_group.output_amp_capabilities_of(widget).map_or(false, |caps| *caps.mute_capable()))
+                    .any(|widget| *self.amplifier_gain_mute(widget, GetAmplifierGainMuteType::Output).amplifier_mute());
+                active_format = path.last().and_then(|widget| self.active_format_of_converter(widget));
+            }
+            EndpointDirection::Input => {
+                if let Some(input_amp_caps) = function_group.input_amp_capabilities_of(pin_widget) {
+                    let gain_mute = self.amplifier_gain_mute(pin_widget, GetAmplifierGainMuteType::Input);
+                    volume_percent = gain_to_volume_percent(*gain_mute.amplifier_gain(), *input_amp_caps.num_steps());
+                    muted = *input_amp_caps.mute_capable() && *gain_mute.amplifier_mute();
+                }
+            }
+        }
+
+        EndpointSettings::new(id, kind, volume_percent, muted, active_format)
+    }
+
+    fn apply_endpoint_settings_change(&self, function_group: &FunctionGroup, pin_widget: &Widget, change: &EndpointSettingsChange) {
+        let kind = endpoint_kind(pin_widget.configuration_default().unwrap().default_device());
+        match kind.direction() {
+            EndpointDirection::Output => {
+                let path = function_group.find_widget_path_from_pin(pin_widget);
+                let volume_widget = Self::volume_widget_on_path(function_group, &path);
+                for widget in path.iter() {
+                    let caps = match function_group.output_amp_capabilities_of(widget) {
+                        Some(caps) => caps,
+                        None => continue,
+                    };
+                    let current_left = self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Left);
+                    let current_right = self.amplifier_gain_mute_of_side(widget, GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Right);
+                    let mut left_gain = *current_left.amplifier_gain();
+                    let mut right_gain = *current_right.amplifier_gain();
+                    let mut mute = *current_left.amplifier_mute();
+                    if let (Some(volume_percent), Some((volume_widget, num_steps))) = (change.volume_percent(), volume_widget) {
+                        if volume_widget.address().node_id() == widget.address().node_id() {
+                            (left_gain, right_gain) = scale_channel_gains(left_gain, right_gain, volume_percent_to_gain(*volume_percent, num_steps));
+                        }
+                    }
+                    if let Some(muted) = change.muted() {
+                        if *caps.mute_capable() {
+                            mute = *muted;
+                        }
+                    }
+                    if left_gain == right_gain {
+                        self.controller.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Both, 0, mute, left_gain)));
+                    } else {
+                        self.controller.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Left, 0, mute, left_gain)));
+                        self.controller.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Right, 0, mute, right_gain)));
+                    }
+                }
+            }
+            EndpointDirection::Input => {
+                if let Some(caps) = function_group.input_amp_capabilities_of(pin_widget) {
+                    let current = self.amplifier_gain_mute(pin_widget, GetAmplifierGainMuteType::Input);
+                    let gain = change.volume_percent().map_or(*current.amplifier_gain(), |volume_percent| volume_percent_to_gain(volume_percent, *caps.num_steps()));
+                    let mute = if *caps.mute_capable() { change.muted().unwrap_or(*current.amplifier_mute()) } else { false };
+                    self.controller.command(SetAmplifierGainMute(*pin_widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, 0, mute, gain)));
+                }
+            }
+        }
+
+        // the amps round the volume to their gain steps, so the event reports the settings read back from the codec
+        let settings = self.endpoint_settings(function_group, pin_widget);
+        audio_service().publish_event(AudioEvent::VolumeChanged { endpoint: *settings.id(), volume_percent: *settings.volume_percent(), muted: *settings.muted() });
+    }
+
+    pub fn apply_volume_knob_positions(&self, codec: &CodecDriver) {
+        for volume_knob in codec.volume_knobs() {
+            self.apply_volume_knob_position(codec, volume_knob, codec.volume_knob_position(&self.controller, volume_knob));
+        }
+    }
+
+    // The knob acts as master volume of the active playback paths of its codec, which are the paths of all running output
+    // streams on the codec, or the path of the default line out if nothing is playing.
+    pub fn apply_volume_knob_position(&self, codec: &CodecDriver, volume_knob: &Widget, position: u8) {
+        let num_steps = *volume_knob.volume_knob_capabilities().unwrap().num_steps();
+        if num_steps == 0 {
+            return;
+        }
+        let volume_percent = gain_to_volume_percent(position, num_steps);
+
+        let mut endpoints: Vec<EndpointId> = Vec::new();
+        for endpoint in stream_registry().lock().streams().iter().filter(|stream| stream.state().is_active()).filter_map(|stream| *stream.endpoint()) {
+            if *endpoint.codec_address() == codec.codec_address() && !endpoints.contains(&endpoint) {
+                endpoints.push(endpoint);
+            }
+        }
+        if endpoints.is_empty() {
+            endpoints.extend(codec.default_output_pin_widget().map(|(_, pin_widget)| endpoint_id(pin_widget)));
+        }
+
+        for id in endpoints {
+            let (function_group, pin_widget) = match codec.find_pin_widget(*id.node_id()) {
+                Some(pin) => pin,
+                None => continue,
+            };
+            if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() == EndpointDirection::Output {
+                self.apply_endpoint_settings_change(function_group, pin_widget, &EndpointSettingsChange::new(id, Some(volume_percent), None));
+            }
+        }
+    }
+
+    fn volume_widget_on_path<'a>(function_group: &'a FunctionGroup, path: &[&'a Widget]) -> Option<(&'a Widget, u8)> {
+        path.iter()
+            .filter_map(|widget| function_group.output_amp_capabilities_of(widget).map(|caps| (*widget, *caps.num_steps())))
+            .find(|(_, num_steps)| *num_steps > 0)
+    }
+
+    // Only input amps and the mute bits always get set on both sides by this driver, so reading the left side is sufficient for them.
+    // The gain of output amps may differ between both sides (see struct PlaybackPath).
+    fn amplifier_gain_mute(&self, widget: &Widget, amp_type: GetAmplifierGainMuteType) -> AmplifierGainMuteResponse {
+        self.amplifier_gain_mute_of_side(widget, amp_type, GetAmplifierGainMuteSide::Left)
+    }
+
+    pub fn amplifier_gain_mute_of_side(&self, widget: &Widget, amp_type: GetAmplifierGainMuteType, side: GetAmplifierGainMuteSide) -> AmplifierGainMuteResponse {
+        let payload = GetAmplifierGainMutePayload::new(amp_type, side, 0);
+        AmplifierGainMuteResponse::try_from(self.controller.command(GetAmplifierGainMute(*widget.address(), payload))).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoChannel {
+    Left,
+    Right,
+}
+
+impl StereoChannel {
+    fn set_side(&self) -> SetAmplifierGainMuteSide {
+        match self {
+            StereoChannel::Left => SetAmplifierGainMuteSide::Left,
+            StereoChannel::Right => SetAmplifierGainMuteSide::Right,
+        }
+    }
+
+    fn get_side(&self) -> GetAmplifierGainMuteSide {
+        match self {
+            StereoChannel::Left => GetAmplifierGainMuteSide::Left,
+            StereoChannel::Right => GetAmplifierGainMuteSide::Right,
+        }
+    }
+}
+
+// Output path of an endpoint (see fn playback_path). The volume of each channel is set on the same amp as the volume of the
+// endpoint (see fn volume_widget_on_path), with the same mapping of percent to gain steps, but on the left and right amp separately.
+// Changing the volume of the endpoint afterwards keeps the ratio between both channels (see fn apply_endpoint_settings_change).
+pub struct PlaybackPath<'a> {
+    device: &'a IntelHDAudioDevice,
+    endpoint: EndpointId,
+    // keeps the volume widget alive, even if the codec gets replaced by a rescan in the meantime
+    codec: Arc<CodecDriver>,
+    volume_widget_node_id: u8,
+    num_steps: u8,
+}
+
+impl PlaybackPath<'_> {
+    pub fn endpoint(&self) -> EndpointId {
+        self.endpoint
+    }
+
+    fn volume_widget(&self) -> &Widget {
+        self.codec.find_widget(self.volume_widget_node_id).unwrap()
+    }
+
+    pub fn channel_volume(&self, channel: StereoChannel) -> u8 {
+        let gain_mute = self.device.amplifier_gain_mute_of_side(self.volume_widget(), GetAmplifierGainMuteType::Output, channel.get_side());
+        gain_to_volume_percent(*gain_mute.amplifier_gain(), self.num_steps)
+    }
+
+    // the channel keeps its mute state
+    pub fn set_channel_volume(&self, channel: StereoChannel, volume_percent: u8) {
+        let current = self.device.amplifier_gain_mute_of_side(self.volume_widget(), GetAmplifierGainMuteType::Output, channel.get_side());
+        let gain = volume_percent_to_gain(volume_percent, self.num_steps);
+        self.device.controller.command(SetAmplifierGainMute(*self.volume_widget().address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, channel.set_side(), 0, *current.amplifier_mute(), gain)));
+    }
+
+    // -1.0 only plays the left channel, 1.0 only the right one and 0.0 both at the same volume. The louder channel keeps the volume
+    // of the endpoint, while the other one gets attenuated by the balance. Values outside of -1.0 to 1.0 get clamped, NaN centers the balance.
+    pub fn set_balance(&self, balance: f32) {
+        let volume_percent = self.channel_volume(StereoChannel::Left).max(self.channel_volume(StereoChannel::Right)) as i32;
+        let balance_percent = (balance.clamp(-1.0, 1.0) * MAX_VOLUME_PERCENT as f32) as i32;
+        let left_percent = volume_percent * (MAX_VOLUME_PERCENT as i32 - balance_percent.max(0)) / MAX_VOLUME_PERCENT as i32;
+        let right_percent = volume_percent * (MAX_VOLUME_PERCENT as i32 + balance_percent.min(0)) / MAX_VOLUME_PERCENT as i32;
+        self.set_channel_volume(StereoChannel::Left, left_percent as u8);
+        self.set_channel_volume(StereoChannel::Right, right_percent as u8);
+    }
+}
+
+// Controls of a single widget, which the mixer application sets directly on its amps (see fn IntelHDAudioDevice::set_widget_control).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MixerControl {
+    // in percent of the gain steps of the amp
+    Gain(u8),
+    Mute(bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MixerError {
+    NoAudioDevice,
+    // codec address and node id of a widget which doesn't exist or belongs to a quarantined codec
+    UnknownWidget(u8, u8),
+    NoAmplifier(u8, u8),
+    NotMuteCapable(u8, u8),
+    NotAnEndpoint(EndpointId),
+    // the widget is no converter of the direction of the endpoint
+    NotAConverter(u8, u8),
+    // the connection lists don't lead from the endpoint to the converter
+    NoRoute(EndpointId, u8),
+    // the system call got a control it doesn't know
+    UnknownControl(usize),
+    // the codec didn't answer or answered with an unexpected response
+    Command(CommandError),
+}
+
+impl MixerError {
+    // error codes returned to user space, which must match the audio library
+    pub fn code(&self) -> usize {
+        match self {
+            MixerError::NoAudioDevice => 1,
+            MixerError::UnknownWidget(_, _) => 2,
+            MixerError::NoAmplifier(_, _) => 3,
+            MixerError::NotMuteCapable(_, _) => 4,
+            MixerError::NotAnEndpoint(_) => 5,
+            MixerError::NotAConverter(_, _) => 6,
+            MixerError::NoRoute(_, _) => 7,
+            MixerError::UnknownControl(_) => 8,
+            MixerError::Command(_) => 9,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcessingError {
+    // quarantined codecs are unknown as well
+    UnknownCodec(u8),
+    UnknownWidget(u8),
+    // the audio widget capabilities of the widget don't have the processing widget bit set
+    NotAProcessingWidget(u8),
+    // the bank reaches beyond the amount of coefficients reported by the processing capabilities of the widget
+    IndexOutOfRange(u8, u16),
+    Command(CommandError),
+}
+
+fn gain_to_volume_percent(gain: u8, num_steps: u8) -> u8 {
+    if num_steps == 0 {
+        return 0;
+    }
+    ((gain.min(num_steps) as u32 * MAX_VOLUME_PERCENT as u32) / num_steps as u32) as u8
+}
+
+fn volume_percent_to_gain(volume_percent: u8, num_steps: u8) -> u8 {
+    ((volume_percent.min(MAX_VOLUME_PERCENT) as u32 * num_steps as u32) / MAX_VOLUME_PERCENT as u32) as u8
+}
+
+// The offset is the gain step of 0 dB and each step has a size of step_size + 1 in units of 0.25 dB (see section 7.3.4.10
+// of the specification).
+fn gain_in_quarter_db(gain: u8, caps: &AmpCapabilitiesResponse) -> i32 {
+    (gain.min(*caps.num_steps()) as i32 - *caps.offset() as i32) * (*caps.step_size() as i32 + 1)
+}
+
+fn format_quarter_db(quarter_db: i32) -> String {
+    let sign = if quarter_db < 0 { "-" } else { "+" };
+    format!("{}{}.{:02} dB", sign, quarter_db.abs() / 4, quarter_db.abs() % 4 * 25)
+}
+
+// the right side is only shown if it differs from the left one
+fn describe_amp(caps: &AmpCapabilitiesResponse, left: &AmplifierGainMuteResponse, right: Option<&AmplifierGainMuteResponse>) -> String {
+    let mut description = if *caps.num_steps() == 0 {
+        String::from("fixed gain")
+    } else {
+        let left_gain = format_quarter_db(gain_in_quarter_db(*left.amplifier_gain(), caps));
+        match right.filter(|right| right.amplifier_gain() != left.amplifier_gain()) {
+            Some(right) => format!("L {} R {}", left_gain, format_quarter_db(gain_in_quarter_db(*right.amplifier_gain(), caps))),
+            None => left_gain,
+        }
+    };
+    if *caps.mute_capable() && *left.amplifier_mute() {
+        description.push_str(" muted");
+    }
+    description
+}
+
+// the louder channel gets the new gain and the other one keeps its ratio to it, so that a balance survives volume changes
+fn scale_channel_gains(left_gain: u8, right_gain: u8, gain: u8) -> (u8, u8) {
+    let louder_gain = left_gain.max(right_gain) as u32;
+    if louder_gain == 0 {
+        return (gain, gain);
+    }
+    ((left_gain as u32 * gain as u32 / louder_gain) as u8, (right_gain as u32 * gain as u32 / louder_gain) as u8)
+}
@@ -0,0 +1,1601 @@
+pub mod diagnostics;
+pub mod mixer;
+pub mod routing;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicUsize, Ordering};
+use derive_getters::Getters;
+use log::{debug, error, info, warn};
+use pci_types::{EndpointHeader, InterruptLine};
+use spin::{Mutex, RwLock};
+use x86_64::PhysAddr;
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::{apic, audio_service, interrupt_dispatcher, pci_bus, scheduler, timer};
+use crate::device::ihda_controller::Controller;
+// the controller module is private, so streams get exposed to the rest of the kernel through this module
+pub use crate::device::ihda_controller::IhdaError;
+pub use crate::device::ihda_stream::{BufferTopology, RingWriteError, SampleContainer, Stream};
+pub use crate::device::ihda_verbs::{BitsPerSample, CONVERTER_SAMPLE_RATES, SetStreamFormatPayload, StreamFormatResponse, StreamType};
+pub use crate::device::ihda_codec_driver::CodecInfo;
+use crate::audio;
+use crate::audio::device::{AudioDeviceError, AudioOutputDevice, OutputStreamHandle, SharedRing};
+use crate::audio::events::{AudioEvent, DeviceError};
+use crate::audio::format::AudioFormat;
+use crate::audio::refill::BufferCompletions;
+use crate::audio::stream_registry;
+use crate::audio::streams::{FifoTuning, StreamInfo, StreamOwner, StreamState};
+use crate::audio::settings::{ActiveFormat, EndpointId, EndpointKind, EndpointSettingsChange, SoundSettingsDiff};
+use crate::audio::synth::Waveform;
+use crate::device::ihda_codec::{CommandError, CommandTransport, FunctionGroup, Widget, WidgetInfoContainer};
+use crate::device::ihda_verbs::{ChannelStreamIdResponse, MAX_AMOUNT_OF_CODECS, SampleSizeRateCAPsResponse, SetUnsolicitedResponsePayload, SupportedStreamFormatsResponse, WidgetType};
+use crate::device::ihda_verbs::Command::{GetChannelStreamId, GetStreamFormat, SetUnsolicitedResponse};
+use crate::device::ihda_codec_driver::{endpoint_id, CodecDriver, MAX_UNSOLICITED_RESPONSE_TAG};
+use crate::device::ihda_output::OutputStreamTable;
+use crate::device::ihda_pci::{configure_pci, disable_pci, enable_message_signaled_interrupts, find_ihda_device, get_interrupt_line, get_vendor_and_device_id, map_mmio_space, mmio_base_address};
+use crate::device::pci::MsiMessage;
+use crate::device::pci::PciBus;
+use crate::device::pit::Timer;
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::memory::PAGE_SIZE;
+use crate::device::ihda_api::routing::OutputRouting;
+
+// stream descriptor used for test tones; the demo functions use the same one and get stopped by everything else that uses it (see fn stop_demo)
+const TEST_TONE_OUTPUT_STREAM_DESCRIPTOR: usize = 0;
+// the duration of a test tone comes from user space, so it gets capped to keep a caller from holding the stream for too long
+pub const MAX_TEST_TONE_DURATION_IN_MS: usize = 10000;
+const CAPTURE_INPUT_STREAM_DESCRIPTOR: usize = 0;
+// a continuous recording gets copied out of four buffers of 16 KiB (see CyclicBuffer::new for the size of a buffer), which last
+// 85 ms each for a stereo stream at 48 kHz, so polling the position of the DMA engine every 20 ms never misses a buffer
+const CONTINUOUS_CAPTURE_BUFFER_AMOUNT: u32 = 4;
+const CONTINUOUS_CAPTURE_PAGES_PER_BUFFER: u32 = 32;
+const CONTINUOUS_CAPTURE_POLL_INTERVAL_IN_MS: usize = 20;
+pub const CODEC_REPROBE_INTERVAL_IN_MS: usize = 5000;
+pub const JACK_EVENT_POLL_INTERVAL_IN_MS: usize = 100;
+// interval of the Get Pin Sense fallback for jack pins which can't send unsolicited responses
+pub const JACK_PRESENCE_POLL_INTERVAL_IN_MS: usize = 500;
+// The stream watchdog samples the positions of all running DMA engines in this interval. A stream counts as stalled if its
+// position didn't change in STREAM_WATCHDOG_STALL_SAMPLES samples in a row, which rules out a stream that happens to be at
+// the same position after wrapping around its cyclic buffer. After STREAM_WATCHDOG_MAX_RECOVERIES recoveries without any
+// progress, the stream is given up and the audio service gets notified.
+pub const STREAM_WATCHDOG_INTERVAL_IN_MS: usize = 250;
+const STREAM_WATCHDOG_STALL_SAMPLES: u32 = 2;
+const STREAM_WATCHDOG_MAX_RECOVERIES: u32 = 3;
+// codecs get put into D3 after this time without any running stream
+const CODEC_IDLE_TIMEOUT_IN_MS: usize = 10000;
+// every rate of the 44.1 kHz family (11.025 kHz, 22.05 kHz, 44.1 kHz, 88.2 kHz, ...) is a multiple of this rate
+const SAMPLE_RATE_44KHZ_FAMILY_DIVISOR: u32 = 11025;
+const MAX_SPEAKER_PAIR_CHANNELS: u8 = 8;
+// the index of an input amp is a 4 bit field (see section 7.3.3.7 of the specification)
+const MAX_AMP_INDEX: u8 = 15;
+
+// interrupts raised by the sound card since boot, which the self test uses to check that interrupts get delivered at all
+static INTERRUPT_COUNT: AtomicUsize = AtomicUsize::new(0);
+// bit n is set if codec address n signaled a state change, which hasn't been handled by fn handle_codec_state_changes yet
+static PENDING_CODEC_STATE_CHANGES: AtomicU16 = AtomicU16::new(0);
+// bit n is set if stream descriptor n reported an error, which hasn't been handled by fn handle_controller_errors yet
+static PENDING_FIFO_ERRORS: AtomicU32 = AtomicU32::new(0);
+static PENDING_DESCRIPTOR_ERRORS: AtomicU32 = AtomicU32::new(0);
+static PENDING_CORB_MEMORY_ERROR: AtomicBool = AtomicBool::new(false);
+static PENDING_RESPONSE_OVERRUN: AtomicBool = AtomicBool::new(false);
+
+pub struct IntelHDAudioDevice {
+    controller: Controller,
+    // Codecs get replaced when they signal a state change at runtime or when a pin gets retasked. Widgets of a replaced codec
+    // may still be referenced (e.g. by a widget path that is being configured), so the codecs are only looked up in snapshots
+    // of this list (see fn all_codecs), which keep a replaced codec alive until the last of them is gone.
+    codecs: RwLock<Vec<Arc<CodecDriver>>>,
+    // codec whose endpoints get used if no endpoint is given, the first available codec if None
+    default_codec_address: Mutex<Option<u8>>,
+    power: Mutex<CodecPower>,
+    subscriptions: Mutex<Subscriptions>,
+    // streams opened through the AudioOutputDevice trait
+    output_streams: Mutex<OutputStreamTable>,
+    error_statistics: Mutex<ErrorStatistics>,
+    output_routing: Mutex<OutputRouting>,
+    // Roles of retasked pins (see fn retask_pin). A codec reset restores the configuration defaults of its pins,
+    // so the roles get applied again whenever a codec signals a state change.
+    pin_roles: Mutex<Vec<(EndpointId, EndpointKind)>>,
+    // Coefficient banks written with fn write_processing_coefficients in the order they were written, which get written again
+    // after a codec reset, as the codec forgets them just like the roles of retasked pins.
+    coefficient_banks: Mutex<Vec<CoefficientBank>>,
+    // stream of the demo functions, which keeps playing after they return, until fn stop_demo gets called
+    demo_stream: Mutex<Option<Stream<'static>>>,
+    stream_watchdog: Mutex<Vec<WatchedStream>>,
+    sidetone: Mutex<Sidetone>,
+    // dispatch table of the stream interrupts, as pairs of stream descriptor number and handler
+    stream_interrupt_handlers: Mutex<Vec<(u32, StreamInterruptHandler)>>,
+}
+
+// see fn set_sidetone_level
+struct Sidetone {
+    // 0 if the sidetone is off
+    level_percent: u8,
+    // input endpoint which is being recorded, None while nothing gets recorded
+    capture_endpoint: Option<EndpointId>,
+}
+
+// last sampled position of a running stream descriptor, see fn check_stalled_streams
+struct WatchedStream {
+    stream_descriptor_number: u32,
+    last_position: u32,
+    unchanged_samples: u32,
+    // recoveries since the position last advanced
+    recoveries_without_progress: u32,
+    given_up: bool,
+}
+
+// Codecs which were not quarantined when the snapshot got taken (see fn IntelHDAudioDevice::codec_snapshot). Widgets found
+// through a snapshot stay valid while it exists, even if their codec gets replaced by a rescan in the meantime.
+pub struct CodecSnapshot {
+    codecs: Vec<Arc<CodecDriver>>,
+    default_codec_address: Option<u8>,
+}
+
+impl CodecSnapshot {
+    fn codec(&self, codec_address: u8) -> Option<&CodecDriver> {
+        self.codecs.iter().find(|codec| codec.codec_address() == codec_address).map(|codec| &**codec)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &CodecDriver> {
+        self.codecs.iter().map(|codec| &**codec)
+    }
+
+    fn find_pin_widget(&self, id: EndpointId) -> Option<(&FunctionGroup, &Widget)> {
+        self.codec(*id.codec_address())?.find_pin_widget(*id.node_id())
+    }
+
+    // falls back to the first available codec while the selected one is quarantined
+    fn default_codec(&self) -> Option<&CodecDriver> {
+        self.default_codec_address.and_then(|codec_address| self.codec(codec_address))
+            .or_else(|| self.iter().next())
+    }
+
+    fn find_default_input_pin_widget(&self) -> Option<(&FunctionGroup, &Widget)> {
+        self.default_codec()?.default_input_pin_widget()
+    }
+
+    fn find_default_output_pin_widget(&self) -> Option<(&FunctionGroup, &Widget)> {
+        self.default_codec()?.default_output_pin_widget()
+    }
+}
+
+struct CoefficientBank {
+    codec_address: u8,
+    node_id: u8,
+    first_index: u16,
+    coefficients: Vec<u16>,
+}
+
+struct CodecPower {
+    powered_down: bool,
+    // time of the last start or stop of a stream, from which the idle timeout is measured
+    last_activity_in_ms: usize,
+}
+
+struct Subscriptions {
+    entries: Vec<Subscription>,
+    next_handle: usize,
+}
+
+struct Subscription {
+    handle: SubscriptionHandle,
+    codec_address: u8,
+    node_id: u8,
+    // None for headphone pins and volume knobs, which already report their events with a tag assigned by the codec driver
+    tag: Option<u8>,
+    listener: UnsolicitedEventListener,
+}
+
+unsafe impl Sync for IntelHDAudioDevice {}
+unsafe impl Send for IntelHDAudioDevice {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackError {
+    NoDefaultEndpoint,
+    UnknownEndpoint(EndpointId),
+    NotAnOutputEndpoint(EndpointId),
+    NoConverterOnPath(EndpointId),
+    UnsupportedFormat(EndpointId),
+    NotAnInputEndpoint(EndpointId),
+    Device(IhdaError),
+    NoAudioDevice,
+    UnknownCodec(u8),
+    // non-PCM streams can only be passed through to S/PDIF, HDMI and Display Port outputs
+    NotADigitalEndpoint(EndpointId),
+    // no codec has a mixer which routes an output converter into an input converter
+    NoLoopbackPath,
+    // the channels assigned to the endpoint don't fit the stream or its converter, or the converter is assigned twice
+    InvalidChannelAssignment(EndpointId),
+    // the DMA engine of the input stream reported a FIFO or descriptor error while recording
+    CaptureFailed(EndpointId),
+    // no amp on the output path of the endpoint offers gain steps
+    NoVolumeControl(EndpointId),
+    // the speaker test of the endpoint plays fewer channels
+    InvalidChannel(EndpointId, u8),
+    // no mixer on the path of the default output endpoint mixes in the input endpoint, or the mixer input has no amp
+    NoSidetonePath(EndpointId),
+    // the DMA engine of the output stream stopped or stalled before the whole signal has been played
+    StreamStopped(EndpointId),
+    RingWrite(EndpointId, RingWriteError),
+}
+
+impl PlaybackError {
+    // error codes returned to user space, which must match the audio library
+    pub fn code(&self) -> usize {
+        match self {
+            PlaybackError::NoDefaultEndpoint => 1,
+            PlaybackError::UnknownEndpoint(_) => 2,
+            PlaybackError::NotAnOutputEndpoint(_) => 3,
+            PlaybackError::NoConverterOnPath(_) => 4,
+            PlaybackError::UnsupportedFormat(_) => 5,
+            PlaybackError::NotAnInputEndpoint(_) => 6,
+            PlaybackError::Device(_) => 7,
+            PlaybackError::NoAudioDevice => 8,
+            PlaybackError::UnknownCodec(_) => 9,
+            PlaybackError::NotADigitalEndpoint(_) => 10,
+            PlaybackError::NoLoopbackPath => 11,
+            PlaybackError::InvalidChannelAssignment(_) => 12,
+            PlaybackError::CaptureFailed(_) => 13,
+            PlaybackError::NoVolumeControl(_) => 14,
+            PlaybackError::InvalidChannel(_, _) => 15,
+            PlaybackError::NoSidetonePath(_) => 16,
+            PlaybackError::StreamStopped(_) => 17,
+            PlaybackError::RingWrite(_, _) => 18,
+        }
+    }
+}
+
+// Formats the converter of a route accepts and the latencies its streams can get. Every channel count from 1 to max_channels
+// can be played. The latencies are the shortest and longest cyclic buffer (see BufferTopology::for_latency) for the 16 bit
+// format the route negotiates (see fn negotiate_16bit_format), which is the one the audio service and recordings use.
+#[derive(Clone, Debug, Getters)]
+pub struct RouteCapabilities {
+    endpoint: EndpointId,
+    sample_rates: Vec<u32>,
+    bits_per_sample: Vec<u8>,
+    max_channels: u8,
+    min_latency_in_ms: u32,
+    max_latency_in_ms: u32,
+}
+
+// Result of fn capabilities, None for routes without a default endpoint or a PCM capable converter
+#[derive(Clone, Debug, Getters)]
+pub struct DeviceCapabilities {
+    output: Option<RouteCapabilities>,
+    input: Option<RouteCapabilities>,
+}
+
+// Errors a single stream descriptor reported since boot. Only descriptor errors get recovered from, as the DMA engine stops
+// on them, while it keeps running after a FIFO error.
+#[derive(Clone, Copy, Debug, Default, Getters)]
+pub struct StreamErrorCounters {
+    stream_descriptor_number: u32,
+    fifo_errors: u32,
+    descriptor_errors: u32,
+    // the position of the DMA engine stopped advancing while the stream was running, see fn check_stalled_streams
+    stalls: u32,
+    recoveries: u32,
+    failed_recoveries: u32,
+}
+
+// Errors reported by the controller since boot, see fn handle_controller_errors.
+#[derive(Clone, Debug, Default, Getters)]
+pub struct ErrorStatistics {
+    // only stream descriptors which reported an error at least once
+    streams: Vec<StreamErrorCounters>,
+    corb_memory_errors: u32,
+    response_overruns: u32,
+    command_ring_restarts: u32,
+    failed_command_ring_restarts: u32,
+}
+
+impl ErrorStatistics {
+    fn stream_mut(&mut self, stream_descriptor_number: u32) -> &mut StreamErrorCounters {
+        let index = match self.streams.iter().position(|counters| counters.stream_descriptor_number == stream_descriptor_number) {
+            Some(index) => index,
+            None => {
+                self.streams.push(StreamErrorCounters { stream_descriptor_number, ..StreamErrorCounters::default() });
+                self.streams.len() - 1
+            }
+        };
+        &mut self.streams[index]
+    }
+}
+// Streams get played on the default output endpoint through the output stream descriptors, which are not reserved for the
+// test tone, the audio service and the mixer.
+impl AudioOutputDevice for IntelHDAudioDevice {
+    fn name(&self) -> &'static str {
+        "Intel HD Audio"
+    }
+
+    fn supported_formats(&self) -> Vec<AudioFormat> {
+        OutputStreamTable::supported_formats(self)
+    }
+
+    fn open_stream(&'static self, owner: StreamOwner, format: AudioFormat) -> Result<OutputStreamHandle, AudioDeviceError> {
+        self.output_streams.lock().open(self, owner, format)
+    }
+
+    fn write(&self, handle: OutputStreamHandle, samples: &[i16]) -> Result<usize, AudioDeviceError> {
+        self.output_streams.lock().write(self, handle, samples)
+    }
+
+    fn set_volume(&self, handle: OutputStreamHandle, volume_percent: u8) -> Result<(), AudioDeviceError> {
+        self.output_streams.lock().set_volume(handle, volume_percent)
+    }
+
+    fn position(&self, handle: OutputStreamHandle) -> Result<u32, AudioDeviceError> {
+        self.output_streams.lock().position(handle)
+    }
+
+    fn drain(&self, handle: OutputStreamHandle) -> Result<u32, AudioDeviceError> {
+        self.output_streams.lock().drain(self, handle)
+    }
+
+    fn close_stream(&self, handle: OutputStreamHandle) -> Result<(), AudioDeviceError> {
+        self.output_streams.lock().close(self, handle)
+    }
+
+    fn share_ring(&self, handle: OutputStreamHandle) -> Result<SharedRing, AudioDeviceError> {
+        self.output_streams.lock().share_ring(self, handle)
+    }
+
+    fn buffer_completions(&self, handle: OutputStreamHandle, completions: &BufferCompletions) -> usize {
+        self.output_streams.lock().buffer_completions(self, handle, completions).unwrap_or(0)
+    }
+
+    fn set_write_position(&self, handle: OutputStreamHandle, position: u32) -> Result<(), AudioDeviceError> {
+        self.output_streams.lock().set_write_position(self, handle, position)
+    }
+}
+
+#[derive(Default)]
+struct IHDAInterruptHandler;
+
+impl InterruptHandler for IHDAInterruptHandler {
+    fn trigger(&mut self) {
+        INTERRUPT_COUNT.fetch_add(1, Ordering::Relaxed);
+        if let Some(device) = crate::INTEL_HD_AUDIO.get() {
+            // the completed buffers get refilled by the refill thread, so the interrupt handler stays short
+            let stream_interrupts = device.controller.take_stream_interrupts();
+            audio::refill::notify_buffer_completions(*stream_interrupts.buffer_completions());
+            // rescanning a codec takes far too long for an interrupt handler, so it only gets noted for fn handle_codec_state_changes
+            PENDING_CODEC_STATE_CHANGES.fetch_or(device.controller.take_codec_state_changes(), Ordering::Relaxed);
+            // the same goes for the recovery from errors, which is done by fn handle_controller_errors
+            PENDING_FIFO_ERRORS.fetch_or(*stream_interrupts.fifo_errors(), Ordering::Relaxed);
+            PENDING_DESCRIPTOR_ERRORS.fetch_or(*stream_interrupts.descriptor_errors(), Ordering::Relaxed);
+            let errors = device.controller.take_errors();
+            if !errors.is_empty() {
+                PENDING_CORB_MEMORY_ERROR.fetch_or(*errors.corb_memory_error(), Ordering::Relaxed);
+                PENDING_RESPONSE_OVERRUN.fetch_or(*errors.response_overrun(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SubscriptionHandle(usize);
+
+impl SubscriptionHandle {
+    pub fn value(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnsolicitedEventKind {
+    // presence detect bit of a pin widget after something got plugged in or out
+    JackSense(bool),
+    // position of a volume knob widget after it got turned, between 0 and the number of steps of the knob
+    VolumeKnob(u8),
+    // widgets of other types only report the 26 bit payload of the response (see specification, section 3.7.1)
+    Other(u32),
+}
+
+// An unsolicited response of a widget somebody subscribed to, already decoded according to the type of the widget.
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct UnsolicitedEvent {
+    codec_address: u8,
+    node_id: u8,
+    kind: UnsolicitedEventKind,
+}
+
+// Listeners get called by the jack event poll thread, so they should return quickly and must not block on audio resources.
+pub type UnsolicitedEventListener = Arc<dyn Fn(&UnsolicitedEvent) + Send + Sync>;
+
+// interrupt conditions of a single stream descriptor, see fn set_stream_interrupt_handler
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamInterrupt {
+    // amount of buffers the DMA engine completed since the handler got called the last time
+    BufferCompletion(usize),
+    FifoError,
+    // the stream descriptor got reset and programmed again, which failed if recovered is false
+    DescriptorError { recovered: bool },
+}
+
+// Handlers never get called from interrupt context, but by the refill thread and the jack event poll thread, so they should
+// return quickly as well.
+pub type StreamInterruptHandler = Arc<dyn Fn(StreamInterrupt) + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubscriptionError {
+    // quarantined codecs are unknown as well
+    UnknownCodec(u8),
+    UnknownWidget(u8),
+    // the audio widget capabilities of the widget don't have the unsolicited response capable bit set
+    NotUnsolicitedCapable(u8),
+    // all 6 bit tags of the codec are in use
+    NoFreeTag(u8),
+    UnknownSubscription(SubscriptionHandle),
+    Command(CommandError),
+}
+
+// What happened to a codec that signaled a state change at runtime, which gets passed on to the audio service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CodecChange {
+    Attached(u8),
+    // the codec lost its settings, but has the same widget graph as before
+    Reset(u8),
+    Removed(u8),
+}
+
+impl IntelHDAudioDevice {
+    // A sound card that fails to initialize gets disabled in the PCI configuration space again, so that it neither accesses
+    // memory nor raises interrupts while the rest of the system keeps running without sound.
+    pub fn new() -> Result<Self, IhdaError> {
+        let pci_bus = pci_bus();
+
+        let ihda_device = find_ihda_device(pci_bus)?;
+
+        configure_pci(pci_bus, ihda_device);
+        match Self::init(pci_bus, ihda_device) {
+            Ok(device) => {
+                Self::connect_interrupts(pci_bus, ihda_device);
+                Ok(device)
+            }
+            Err(error) => {
+                disable_pci(pci_bus, ihda_device);
+                Err(error)
+            }
+        }
+    }
+
+    fn init(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> Result<Self, IhdaError> {
+        let mmio_base_address = map_mmio_space(pci_bus, ihda_device)?;
+        let (vendor_id, device_id) = get_vendor_and_device_id(pci_bus, ihda_device);
+        let controller = Controller::new(mmio_base_address, vendor_id, device_id)?;
+        debug!("IHDA controller capabilities: {:?}", controller.capabilities());
+
+        // A previous kernel (e.g. before a kexec reboot) might have left the controller running, in which case CRST is still set and
+        // fn reset alone wouldn't reset anything. Its DMA engines get stopped and the controller gets put into reset first.
+        controller.shutdown(true)?;
+        controller.reset()?;
+        info!("IHDA Controller reset complete");
+
+        // the following function call is irrelevant when not using interrupts
+        controller.configure();
+        info!("IHDA configuration space set up");
+
+        let command_transport = controller.select_command_transport()?;
+        info!("IHDA command transport is {:?}", command_transport);
+
+        controller.init_dma_position_buffer();
+        controller.test_dma_position_buffer()?;
+        info!("DMA position buffer set up and running");
+
+        // interview sound card
+        let mut codecs: Vec<CodecDriver> = controller.scan_for_available_codecs()?.into_iter().map(CodecDriver::new).collect();
+        debug!("[{}] codec{} found", codecs.len(), if codecs.len() == 1 { "" } else { "s" });
+
+        for codec in codecs.iter_mut() {
+            // the scan powered up the codec, so the presence detect bits can be measured again
+            codec.execute_pin_sense(&controller);
+            codec.enable_jack_presence_detection(&controller);
+            codec.enable_volume_knobs(&controller);
+        }
+        let jack_sense_pin_amount: usize = codecs.iter().map(|codec| codec.jack_sense_pin_amount()).sum();
+        let polled_jack_pin_amount: usize = codecs.iter().map(|codec| codec.polled_jack_pin_amount()).sum();
+        debug!("[{}] headphone jack{} with presence detection found, [{}] of them without unsolicited responses",
+            jack_sense_pin_amount + polled_jack_pin_amount, if jack_sense_pin_amount + polled_jack_pin_amount == 1 { "" } else { "s" }, polled_jack_pin_amount);
+        // the scan above handled the state changes reported so far, only later ones have to be handled at runtime
+        controller.take_codec_state_changes();
+
+        let device = Self {
+            controller,
+            codecs: RwLock::new(codecs.into_iter().map(Arc::new).collect()),
+            default_codec_address: Mutex::new(None),
+            power: Mutex::new(CodecPower { powered_down: false, last_activity_in_ms: timer().read().systime_ms() }),
+            subscriptions: Mutex::new(Subscriptions { entries: Vec::new(), next_handle: 1 }),
+            output_streams: Mutex::new(OutputStreamTable::new()),
+            error_statistics: Mutex::new(ErrorStatistics::default()),
+            output_routing: Mutex::new(OutputRouting::SinglePin),
+            pin_roles: Mutex::new(Vec::new()),
+            coefficient_banks: Mutex::new(Vec::new()),
+            demo_stream: Mutex::new(None),
+            stream_watchdog: Mutex::new(Vec::new()),
+            sidetone: Mutex::new(Sidetone { level_percent: 0, capture_endpoint: None }),
+            stream_interrupt_handlers: Mutex::new(Vec::new()),
+        };
+        for codec in device.all_codecs() {
+            device.apply_volume_knob_positions(&codec);
+        }
+        Ok(device)
+    }
+
+    // Processes the jack events reported since the last call. Plugging in headphones moves all running output streams
+    // to the headphone pin, unplugging them moves the streams on that pin back to the speaker (or the default line out).
+    // Afterwards, the event gets passed on to the listeners subscribed to the widget which sent the unsolicited response.
+    pub fn handle_jack_events(&self) {
+        for response in self.controller.take_unsolicited_responses() {
+            // quarantined codecs are skipped as well
+            let codec = match self.available_codecs().find(|codec| codec.codec_address() == *response.codec_address()) {
+                Some(codec) => codec,
+                None => continue,
+            };
+
+            if let Some((function_group, pin_widget)) = codec.jack_sense_pin(*response.tag()) {
+                let present = codec.sense_presence(&self.controller, pin_widget);
+                self.handle_jack_sense(&codec, function_group, pin_widget, present);
+                continue;
+            }
+
+            if let Some(volume_knob) = codec.volume_knob(*response.tag()) {
+                let position = codec.volume_knob_position(&self.controller, volume_knob);
+                debug!("Volume knob {} of codec {} turned to position {}", volume_knob.address().node_id(), codec.codec_address(), position);
+                self.apply_volume_knob_position(&codec, volume_knob, position);
+                self.notify_subscribers(codec.codec_address(), *volume_knob.address().node_id(), UnsolicitedEventKind::VolumeKnob(position));
+                continue;
+            }
+
+            let node_id = self.subscriptions.lock().entries.iter()
+                .find(|subscription| subscription.codec_address == codec.codec_address() && subscription.tag == Some(*response.tag()))
+                .map(|subscription| subscription.node_id);
+            let widget = match node_id.and_then(|node_id| codec.find_widget(node_id)) {
+                Some(widget) => widget,
+                None => {
+                    debug!("Ignoring unsolicited response {:?}", response);
+                    continue;
+                }
+            };
+            let kind = match widget.audio_widget_capabilities().widget_type() {
+                WidgetType::PinComplex if *widget.pin_capabilities().unwrap().presence_detect_capable() => UnsolicitedEventKind::JackSense(codec.sense_presence(&self.controller, widget)),
+                WidgetType::VolumeKnobWidget => UnsolicitedEventKind::VolumeKnob(codec.volume_knob_position(&self.controller, widget)),
+                _ => UnsolicitedEventKind::Other(*response.payload()),
+            };
+            self.notify_subscribers(codec.codec_address(), *widget.address().node_id(), kind);
+        }
+    }
+
+    // Polls the presence detect bit of the headphone pins which can't send unsolicited responses and handles every change
+    // like a jack event reported by an unsolicited response. Gets called periodically.
+    pub fn poll_jack_presence(&self) {
+        for codec in self.available_codecs() {
+            for (function_group, pin_widget, present) in codec.poll_jack_presence(&self.controller) {
+                self.handle_jack_sense(&codec, function_group, pin_widget, present);
+            }
+        }
+    }
+
+    fn handle_jack_sense(&self, codec: &CodecDriver, function_group: &FunctionGroup, pin_widget: &Widget, present: bool) {
+        let id = endpoint_id(pin_widget);
+        if present {
+            info!("Headphones plugged into endpoint {:?}", id);
+            audio_service().publish_event(AudioEvent::JackInserted(id));
+            self.reroute_output_streams(|endpoint| endpoint != id, function_group, pin_widget);
+        } else {
+            info!("Headphones unplugged from endpoint {:?}", id);
+            audio_service().publish_event(AudioEvent::JackRemoved(id));
+            if let Some((function_group, fallback_pin_widget)) = codec.unplugged_headphone_fallback(function_group, pin_widget) {
+                self.reroute_output_streams(|endpoint| endpoint == id, function_group, fallback_pin_widget);
+            }
+        }
+        self.notify_subscribers(codec.codec_address(), *pin_widget.address().node_id(), UnsolicitedEventKind::JackSense(present));
+    }
+
+    // ########## unsolicited response subscriptions ##########
+
+    // Lets the widget send unsolicited responses, which get decoded and passed to the listener by fn handle_jack_events.
+    // Headphone pins and volume knobs keep the tag assigned by their codec driver, all other widgets get a tag of their own,
+    // counting down from the highest tag, so that they don't collide with the tags of the codec driver, which count up from 1.
+    pub fn subscribe(&self, codec_address: u8, node_id: u8, listener: UnsolicitedEventListener) -> Result<SubscriptionHandle, SubscriptionError> {
+        let codec = self.available_codecs().find(|codec| codec.codec_address() == codec_address).ok_or(SubscriptionError::UnknownCodec(codec_address))?;
+        let widget = codec.find_widget(node_id).ok_or(SubscriptionError::UnknownWidget(node_id))?;
+        if !*widget.audio_widget_capabilities().unsol_capable() {
+            return Err(SubscriptionError::NotUnsolicitedCapable(node_id));
+        }
+
+        let mut subscriptions = self.subscriptions.lock();
+        let tag = if codec.is_jack_sense_pin(node_id) || codec.is_volume_knob(node_id) {
+            None
+        } else {
+            let tags_of_codec = || subscriptions.entries.iter().filter(|subscription| subscription.codec_address == codec_address);
+            match tags_of_codec().find(|subscription| subscription.node_id == node_id) {
+                // every widget only has one tag, which all of its subscriptions share
+                Some(subscription) => subscription.tag,
+                None => {
+                    let tag = (codec.reserved_tag_amount() + 1..=MAX_UNSOLICITED_RESPONSE_TAG).rev()
+                        .map(|tag| tag as u8)
+                        .find(|tag| !tags_of_codec().any(|subscription| subscription.tag == Some(*tag)))
+                        .ok_or(SubscriptionError::NoFreeTag(codec_address))?;
+                    self.controller.try_command(SetUnsolicitedResponse(*widget.address(), SetUnsolicitedResponsePayload::new(true, tag)))
+                        .map_err(SubscriptionError::Command)?;
+                    Some(tag)
+                }
+            }
+        };
+
+        let handle = SubscriptionHandle(subscriptions.next_handle);
+        subscriptions.next_handle += 1;
+        subscriptions.entries.push(Subscription { handle, codec_address, node_id, tag, listener });
+        Ok(handle)
+    }
+
+    // The widget stops sending unsolicited responses when its last subscription gets removed, unless its tag belongs to the codec driver.
+    pub fn unsubscribe(&self, handle: SubscriptionHandle) -> Result<(), SubscriptionError> {
+        let mut subscriptions = self.subscriptions.lock();
+        let index = subscriptions.entries.iter().position(|subscription| subscription.handle == handle).ok_or(SubscriptionError::UnknownSubscription(handle))?;
+        let subscription = subscriptions.entries.remove(index);
+        if subscription.tag.is_none() || subscriptions.entries.iter().any(|other| other.codec_address == subscription.codec_address && other.node_id == subscription.node_id) {
+            return Ok(());
+        }
+
+        // A codec which is gone has nothing left to disable. If the codec doesn't answer, the subscription stays removed anyway, as
+        // its listener must not get called anymore. Unsolicited responses with the stale tag get dropped, as no subscription matches.
+        if let Some(widget) = self.codec_snapshot().codec(subscription.codec_address).and_then(|codec| codec.find_widget(subscription.node_id)) {
+            if let Err(error) = self.controller.try_command(SetUnsolicitedResponse(*widget.address(), SetUnsolicitedResponsePayload::new(false, 0))) {
+                warn!("Failed to disable the unsolicited responses of widget {:?}: {:?}", widget.address(), error);
+            }
+        }
+        Ok(())
+    }
+
+    // A codec which got reset or attached again has lost the tags of its widgets, so they get assigned again.
+    fn restore_subscriptions(&self, codec: &CodecDriver) {
+        let subscriptions = self.subscriptions.lock();
+        for subscription in subscriptions.entries.iter().filter(|subscription| subscription.codec_address == codec.codec_address()) {
+            if let (Some(tag), Some(widget)) = (subscription.tag, codec.find_widget(subscription.node_id)) {
+                if let Err(error) = self.controller.try_command(SetUnsolicitedResponse(*widget.address(), SetUnsolicitedResponsePayload::new(true, tag))) {
+                    warn!("Failed to restore the unsolicited response tag of widget {:?}: {:?}", widget.address(), error);
+                }
+            }
+        }
+    }
+
+    // the listeners get called without holding the lock, so that they are free to subscribe or unsubscribe
+    fn notify_subscribers(&self, codec_address: u8, node_id: u8, kind: UnsolicitedEventKind) {
+        let listeners: Vec<UnsolicitedEventListener> = self.subscriptions.lock().entries.iter()
+            .filter(|subscription| subscription.codec_address == codec_address && subscription.node_id == node_id)
+            .map(|subscription| subscription.listener.clone())
+            .collect();
+
+        let event = UnsolicitedEvent { codec_address, node_id, kind };
+        for listener in listeners {
+            listener(&event);
+        }
+    }
+
+    // Stops all DMA engines of the sound card and puts the controller into reset, so that it doesn't write into memory anymore,
+    // e.g. before rebooting. The device can't be used afterwards; the streams that are still open only get released.
+    pub fn shutdown(&self) {
+        self.stop_demo();
+        match self.controller.shutdown(true) {
+            Ok(()) => info!("IHDA controller shut down"),
+            Err(error) => error!("IHDA controller could not be shut down: {:?}", error),
+        }
+    }
+
+    // Stops the stream of a running demo and releases its stream descriptor and stream id, so that they can be used again.
+    pub fn stop_demo(&self) {
+        // dropping the stream resets its stream descriptor
+        self.demo_stream.lock().take();
+    }
+
+    pub fn demo(&'static self) -> Result<(), IhdaError> {
+        let stream_format = AudioFormat::mono_48khz_16bit();
+        self.stop_demo();
+        let stream = self.controller.prepare_output_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream_format, 2, 128)?;
+        self.register_stream(&stream, self.controller.output_stream_descriptor_number(0), StreamOwner::Kernel("ihda demo"), self.default_output_endpoint());
+
+        stream.write_signal(&mut stream.signal_generator(Waveform::Sawtooth, 750));
+
+        // without this flush, there is no sound coming out of the line out jack, although all DMA pages used for the stream
+        // (for audio buffers and buffer descriptor list) were allocated with CachePolicy::Uncached by the function "alloc_dma"
+        unsafe { asm!("wbinvd"); }
+
+        let codecs = self.codec_snapshot();
+        let codec = codecs.default_codec().ok_or(IhdaError::NoCodecFound)?;
+        codec.configure_for_line_out_playback(&self.controller, &stream)?;
+
+        debug!("run in one second!");
+        Timer::wait(1000);
+        stream.run();
+        self.sync_stream_state(self.controller.output_stream_descriptor_number(0), &stream);
+        // the demo keeps playing after returning, until its stream descriptor is needed again (see fn stop_demo)
+        *self.demo_stream.lock() = Some(stream);
+        Ok(())
+    }
+
+    pub fn demo_bachelor_presentation(&'static self) -> Result<(), IhdaError> {
+        let stream_format = AudioFormat::stereo_48khz_16bit();
+        self.stop_demo();
+        let stream = self.controller.prepare_output_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream_format, 8, 512)?;
+        self.register_stream(&stream, self.controller.output_stream_descriptor_number(0), StreamOwner::Kernel("ihda demo"), self.default_output_endpoint());
+
+        // the frequency doubles with every buffer
+        let mut generator = stream.signal_generator(Waveform::Sawtooth, 25);
+        for buffer_index in 0..stream.buffer_amount() {
+            generator.set_frequency(25 << buffer_index);
+            stream.write_signal_to_buffer(buffer_index, &mut generator);
+        }
+
+        // without this flush, there is no sound coming out of the line out jack, although all DMA pages used for the stream
+        // (for audio buffers and buffer descriptor list) were allocated with CachePolicy::Uncached by the function "alloc_dma"
+        unsafe { asm!("wbinvd"); }
+
+        let codecs = self.codec_snapshot();
+        let codec = codecs.default_codec().ok_or(IhdaError::NoCodecFound)?;
+        codec.configure_for_line_out_playback(&self.controller, &stream)?;
+
+        debug!("run in one second!");
+        Timer::wait(1000);
+        stream.run();
+        self.sync_stream_state(self.controller.output_stream_descriptor_number(0), &stream);
+        // the demo keeps playing after returning, until its stream descriptor is needed again (see fn stop_demo)
+        *self.demo_stream.lock() = Some(stream);
+        Ok(())
+    }
+
+    // Plays a square wave through the complete path of an output endpoint, including format negotiation with its converter and amp setup.
+    // Without an endpoint, the first line out pin connected to a jack gets used, just like in the demo functions.
+    // The duration gets capped at MAX_TEST_TONE_DURATION_IN_MS and the calling thread sleeps while the tone is playing.
+    pub fn play_test_tone(&self, owner: StreamOwner, endpoint: Option<EndpointId>, frequency: u32, duration_in_ms: usize) -> Result<(), PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = self.find_output_endpoint(&codecs, endpoint)?;
+        let path = function_group.find_widget_path_from_pin(pin_widget);
+        let converter = Self::converter_on_path(&path).ok_or(PlaybackError::NoConverterOnPath(id))?;
+        let stream_format = Self::negotiate_test_tone_format(function_group, converter).ok_or(PlaybackError::UnsupportedFormat(id))?;
+        debug!("Playing test tone with {} Hz on endpoint {:?} with format {:?}", frequency, id, stream_format);
+
+        self.stop_demo();
+        let stream = &self.open_output_stream(owner, Some(id), stream_format, TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, 2, 128)?;
+        stream.write_signal(&mut stream.signal_generator(Waveform::Square, frequency));
+
+        self.start_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream);
+        scheduler().sleep(duration_in_ms.min(MAX_TEST_TONE_DURATION_IN_MS));
+        self.close_stream(TEST_TONE_OUTPUT_STREAM_DESCRIPTOR, stream);
+
+        Ok(())
+    }
+
+    // Records 16 bit samples at 48 kHz or 44.1 kHz from an input endpoint. Without an endpoint, the first mic in or line in pin connected to a jack gets used.
+    // The recording can't be longer than the cyclic buffer of the stream, so the duration gets capped at about one second.
+    pub fn record(&self, owner: StreamOwner, endpoint: Option<EndpointId>, duration_in_ms: usize) -> Result<Vec<i16>, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (id, path, stream_format) = self.find_capture_path(&codecs, endpoint)?;
+
+        self.ensure_powered_up();
+        let stream_descriptor_number = self.controller.input_stream_descriptor_number(CAPTURE_INPUT_STREAM_DESCRIPTOR);
+        let stream = &self.controller.prepare_input_stream(CAPTURE_INPUT_STREAM_DESCRIPTOR, stream_format, 2, 128).map_err(PlaybackError::Device)?;
+        self.register_stream(stream, stream_descriptor_number, owner, Some(id));
+        self.controller.configure_widget_path_for_capture(&path, stream);
+        self.start_sidetone(id);
+        stream.clear_buffers();
+
+        let max_duration_in_ms = (stream.buffer_length_in_bytes() as u64 * 1000 / stream_format.bytes_per_second() as u64) as usize;
+        debug!("Recording {} ms from endpoint {:?} with format {:?}", duration_in_ms.min(max_duration_in_ms), id, stream_format);
+
+        stream.run();
+        self.sync_stream_state(stream_descriptor_number, stream);
+        Timer::wait(duration_in_ms.min(max_duration_in_ms));
+        if !stream.check_for_errors() {
+            stream.stop();
+        }
+
+        // see comment in fn demo, this time the CPU has to see the data written by the DMA engine
+        unsafe { asm!("wbinvd"); }
+
+        // a position of 0 means that the DMA engine has filled the whole cyclic buffer
+        let recorded_length_in_samples = (self.controller.stream_position(stream_descriptor_number) / 2) as usize;
+        let mut samples = Vec::new();
+        for buffer_index in 0..stream.buffer_amount() {
+            samples.extend(stream.read_data_from_buffer(buffer_index));
+        }
+        if recorded_length_in_samples > 0 {
+            samples.truncate(recorded_length_in_samples);
+        }
+
+        self.stop_sidetone();
+        Self::reset_stream(stream);
+        stream_registry().lock().unregister(stream_descriptor_number);
+        self.record_activity();
+
+        Ok(samples)
+    }
+
+    // format fn record and fn record_continuously capture from an input endpoint with
+    pub fn capture_format(&self, endpoint: Option<EndpointId>) -> Result<AudioFormat, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (_, _, stream_format) = self.find_capture_path(&codecs, endpoint)?;
+        Ok(stream_format)
+    }
+
+    // amount of frames fn record_continuously records for the given duration, which gets rounded up to whole buffers
+    pub fn continuous_capture_length_in_frames(stream_format: &AudioFormat, duration_in_ms: usize) -> usize {
+        let buffer_length_in_frames = Self::continuous_capture_buffer_length_in_bytes() / stream_format.frame_size_in_bytes() as u64;
+        Self::continuous_capture_buffer_count(stream_format, duration_in_ms) * buffer_length_in_frames as usize
+    }
+
+    fn continuous_capture_buffer_count(stream_format: &AudioFormat, duration_in_ms: usize) -> usize {
+        let length_in_bytes = duration_in_ms as u64 * stream_format.bytes_per_second() as u64;
+        (length_in_bytes.div_ceil(1000 * Self::continuous_capture_buffer_length_in_bytes()) as usize).max(1)
+    }
+
+    // the audio buffers only use an eighth of the pages they get allocated (see CyclicBuffer::new)
+    fn continuous_capture_buffer_length_in_bytes() -> u64 {
+        CONTINUOUS_CAPTURE_PAGES_PER_BUFFER as u64 * PAGE_SIZE as u64 / 8
+    }
+
+    // Records 16 bit samples from an input endpoint like fn record, but without being limited by the size of the cyclic buffer:
+    // every buffer gets handed to the sink as soon as the DMA engine moved on to the next one. The recording stops on a buffer
+    // boundary, so the duration gets rounded up to whole buffers and the sink always receives complete frames.
+    pub fn record_continuously(&self, owner: StreamOwner, endpoint: Option<EndpointId>, duration_in_ms: usize, sink: &mut dyn FnMut(&[i16])) -> Result<AudioFormat, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (id, path, stream_format) = self.find_capture_path(&codecs, endpoint)?;
+
+        self.ensure_powered_up();
+        let stream_descriptor_number = self.controller.input_stream_descriptor_number(CAPTURE_INPUT_STREAM_DESCRIPTOR);
+        let stream = &self.controller.prepare_input_stream(CAPTURE_INPUT_STREAM_DESCRIPTOR, stream_format, CONTINUOUS_CAPTURE_BUFFER_AMOUNT, CONTINUOUS_CAPTURE_PAGES_PER_BUFFER)
+            .map_err(PlaybackError::Device)?;
+        self.register_stream(stream, stream_descriptor_number, owner, Some(id));
+        self.controller.configure_widget_path_for_capture(&path, stream);
+        self.start_sidetone(id);
+        stream.clear_buffers();
+
+        let buffers_to_record = Self::continuous_capture_buffer_count(&stream_format, duration_in_ms);
+        debug!("Recording {} buffers from endpoint {:?} with format {:?}", buffers_to_record, id, stream_format);
+
+        stream.run();
+        self.sync_stream_state(stream_descriptor_number, stream);
+        let mut next_buffer_index = 0;
+        let mut recorded_buffers = 0;
+        let mut result = Ok(stream_format);
+        while recorded_buffers < buffers_to_record {
+            if stream.check_for_errors() {
+                result = Err(PlaybackError::CaptureFailed(id));
+                break;
+            }
+
+            // the DMA engine fills the current buffer, so every buffer before it is complete
+            let current_buffer_index = stream.current_buffer_index();
+            while next_buffer_index != current_buffer_index && recorded_buffers < buffers_to_record {
+                // see comment in fn record
+                unsafe { asm!("wbinvd"); }
+                sink(&stream.read_data_from_buffer(next_buffer_index));
+                next_buffer_index = (next_buffer_index + 1) % stream.buffer_amount();
+                recorded_buffers += 1;
+            }
+            scheduler().sleep(CONTINUOUS_CAPTURE_POLL_INTERVAL_IN_MS);
+        }
+        if !stream.check_for_errors() {
+            stream.stop();
+        }
+
+        self.stop_sidetone();
+        Self::reset_stream(stream);
+        stream_registry().lock().unregister(stream_descriptor_number);
+        self.record_activity();
+
+        result
+    }
+
+    // powers the codecs up again if they went idle since the stream was opened
+    pub fn start_stream(&self, output_stream_descriptor_index: usize, stream: &Stream) {
+        self.ensure_powered_up();
+        for codec in self.available_codecs() {
+            codec.enable_external_amplifiers(&self.controller);
+        }
+
+        // see comment in fn demo
+        unsafe { asm!("wbinvd"); }
+
+        stream.run();
+        self.sync_stream_state(self.controller.output_stream_descriptor_number(output_stream_descriptor_index), stream);
+    }
+
+    // stops and resets the stream and removes it from the stream registry
+    pub fn close_stream(&self, output_stream_descriptor_index: usize, stream: &Stream) {
+        if !stream.check_for_errors() && stream.state().is_active() {
+            stream.stop();
+        }
+        Self::reset_stream(stream);
+        stream_registry().lock().unregister(self.controller.output_stream_descriptor_number(output_stream_descriptor_index));
+        self.record_activity();
+    }
+
+    // A stream that doesn't leave its reset state in time can't be used anymore, but its owner is done with it anyway,
+    // so the stream descriptor gets released regardless.
+    fn reset_stream(stream: &Stream) {
+        if let Err(error) = stream.reset() {
+            warn!("Stream {} could not be reset: {:?}", stream.id(), error);
+        }
+    }
+
+    // The DMA engine keeps its position, so the stream continues where it stopped when fn resume_stream gets called.
+    // A paused stream doesn't count as active, so the codecs can get powered down while it is paused.
+    pub fn pause_stream(&self, output_stream_descriptor_index: usize, stream: &Stream) -> Result<(), PlaybackError> {
+        if !stream.state().is_active() {
+            return Ok(());
+        }
+        let result = stream.pause();
+        self.sync_stream_state(self.controller.output_stream_descriptor_number(output_stream_descriptor_index), stream);
+        self.record_activity();
+        result.map_err(PlaybackError::Device)
+    }
+
+    // continues a stream paused with fn pause_stream, after powering the codecs up again if they went idle in the meantime
+    pub fn resume_stream(&self, output_stream_descriptor_index: usize, stream: &Stream) {
+        if stream.state() != StreamState::Paused {
+            return;
+        }
+        self.ensure_powered_up();
+        stream.resume();
+        self.sync_stream_state(self.controller.output_stream_descriptor_number(output_stream_descriptor_index), stream);
+    }
+
+    // Switches an open output stream to another format without reallocating its buffers, e.g. for a song with another sample
+    // rate or bit depth. The DMA engine is stopped while SDFMT and the format and stream id of the converter get changed,
+    // so that the converter never receives samples in a format it isn't set up for. Streams without an endpoint in the stream
+    // registry play on the default output endpoint.
+    pub fn reconfigure_stream(&self, output_stream_descriptor_index: usize, stream: &mut Stream, stream_format: AudioFormat) -> Result<(), PlaybackError> {
+        let stream_descriptor_number = self.controller.output_stream_descriptor_number(output_stream_descriptor_index);
+        let endpoint = stream_registry().lock().streams().iter()
+            .find(|info| *info.stream_descriptor_number() == stream_descriptor_number)
+            .and_then(|info| *info.endpoint());
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = self.find_output_endpoint(&codecs, endpoint)?;
+        let path = function_group.find_widget_path_from_pin(pin_widget);
+        let converter = Self::converter_on_path(&path).ok_or(PlaybackError::NoConverterOnPath(id))?;
+        if !stream_format.is_pcm() && !converter.is_digital() {
+            return Err(PlaybackError::NotADigitalEndpoint(id));
+        }
+        if !Self::supports_format(function_group, converter, &stream_format) {
+            return Err(PlaybackError::UnsupportedFormat(id));
+        }
+
+        let was_running = stream.state() == StreamState::Running;
+        self.pause_stream(output_stream_descriptor_index, stream)?;
+        stream.reconfigure(stream_format).map_err(PlaybackError::Device)?;
+        self.ensure_powered_up();
+        self.controller.route_stream_to_widget_path(&path, *stream.id(), &stream_format);
+        stream_registry().lock().set_format(stream_descriptor_number, active_format(&stream_format));
+        if was_running {
+            self.resume_stream(output_stream_descriptor_index, stream);
+        }
+        Ok(())
+    }
+
+    // Puts all codecs into D3 once no stream has been active for CODEC_IDLE_TIMEOUT_IN_MS. Gets called periodically.
+    pub fn power_down_if_idle(&self) {
+        let mut power = self.power.lock();
+        if power.powered_down {
+            return;
+        }
+        let now = timer().read().systime_ms();
+        if stream_registry().lock().streams().iter().any(|stream| stream.state().is_active()) {
+            power.last_activity_in_ms = now;
+            return;
+        }
+        if now < power.last_activity_in_ms + CODEC_IDLE_TIMEOUT_IN_MS {
+            return;
+        }
+
+        for codec in self.available_codecs() {
+            codec.power_down(&self.controller);
+        }
+        power.powered_down = true;
+        debug!("IHDA codecs powered down after {} ms without active streams", now - power.last_activity_in_ms);
+    }
+
+    // Brings all codecs back to D0 if they were powered down. If a codec lost its settings in D3, the paths of all registered
+    // output streams get configured again, so that paused streams can simply be resumed.
+    fn ensure_powered_up(&self) {
+        let mut power = self.power.lock();
+        power.last_activity_in_ms = timer().read().systime_ms();
+        if !power.powered_down {
+            return;
+        }
+
+        let mut settings_reset = false;
+        for codec in self.available_codecs() {
+            settings_reset |= codec.power_up(&self.controller);
+        }
+        power.powered_down = false;
+        debug!("IHDA codecs powered up");
+
+        if settings_reset {
+            self.restore_stream_routing();
+        }
+    }
+
+    fn record_activity(&self) {
+        self.power.lock().last_activity_in_ms = timer().read().systime_ms();
+    }
+
+    // Sample rate the converter of an output endpoint should run at for a source with the given rate.
+    // Sources with a rate the converter doesn't support have to be resampled, preferably to a rate of the same family
+    // (e.g. 22.05 kHz to 44.1 kHz), as its ratio to the source rate is an integer.
+    pub fn negotiate_output_sample_rate(&self, endpoint: Option<EndpointId>, source_rate: u32) -> Result<u32, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = self.find_output_endpoint(&codecs, endpoint)?;
+        let path = function_group.find_widget_path_from_pin(pin_widget);
+        let converter = Self::converter_on_path(&path).ok_or(PlaybackError::NoConverterOnPath(id))?;
+        let (sample_size_rate_caps, _) = Self::converter_format_capabilities(function_group, converter).ok_or(PlaybackError::UnsupportedFormat(id))?;
+
+        let fallback_rates = if source_rate % SAMPLE_RATE_44KHZ_FAMILY_DIVISOR == 0 {
+            [44100, 88200, 48000, 96000, 192000]
+        } else {
+            [48000, 96000, 192000, 44100, 88200]
+        };
+        core::iter::once(source_rate).chain(fallback_rates)
+            .find(|sample_rate| sample_size_rate_caps.supports_sample_rate(*sample_rate))
+            .ok_or(PlaybackError::UnsupportedFormat(id))
+    }
+
+    pub fn output_stream_descriptor_number(&self, output_stream_descriptor_index: usize) -> u32 {
+        self.controller.output_stream_descriptor_number(output_stream_descriptor_index)
+    }
+
+    pub fn output_stream_descriptor_amount(&self) -> usize {
+        self.controller.output_stream_descriptor_amount()
+    }
+
+    pub fn free_output_stream_descriptor(&self, first_output_stream_descriptor_index: usize) -> Result<usize, IhdaError> {
+        self.controller.free_output_stream_descriptor(first_output_stream_descriptor_index)
+    }
+
+    // for streams which get refilled on buffer completion (see fn Controller::enable_low_latency), must be called before starting the stream
+    pub fn enable_low_latency(&self, output_stream_descriptor_index: usize) {
+        self.controller.enable_low_latency(self.controller.output_stream_descriptor_number(output_stream_descriptor_index));
+    }
+
+    // FIFO size, watermark and minimum safe buffer length of a stream descriptor (see fn Controller::fifo_tuning)
+    pub fn fifo_tuning(&self, stream_descriptor_number: u32) -> FifoTuning {
+        self.controller.fifo_tuning(stream_descriptor_number)
+    }
+
+    // Lets the stream raise an interrupt each time the DMA engine finished one of its buffers. The interrupt of its stream
+    // descriptor is already enabled in INTCTL while the stream exists (see StreamAllocation).
+    pub fn enable_buffer_completion_interrupt(&self, stream: &Stream) {
+        stream.enable_interrupt_on_completion();
+    }
+
+    // the audio buffers are allocated in pages, so this is the amount of pages needed to store the given amount of bytes
+    pub fn pages_per_buffer_for(&self, length_in_bytes: u32, buffer_amount: u32) -> u32 {
+        self.controller.pages_per_buffer_for(length_in_bytes, buffer_amount)
+    }
+
+    // codecs which are not quarantined because of repeated command timeouts and can therefore be used for routing
+    fn available_codecs(&self) -> impl Iterator<Item = Arc<CodecDriver>> + '_ {
+        self.all_codecs().into_iter().filter(|codec| !self.controller.is_codec_quarantined(codec.codec_address()))
+    }
+
+    // snapshot, so that the lock isn't held while commands get sent to the codecs
+    fn all_codecs(&self) -> Vec<Arc<CodecDriver>> {
+        self.codecs.read().clone()
+    }
+
+    // scans the widget graph of a codec again and replaces the driver object of the codec with a new one
+    fn rescan_codec(&self, codec_address: u8) -> Result<(), IhdaError> {
+        let mut codec = self.controller.rescan_codec(codec_address)?;
+        if self.restore_pin_roles(&codec) {
+            codec = self.controller.rescan_codec(codec_address)?;
+        }
+        let mut codec = CodecDriver::new(codec);
+        codec.enable_jack_presence_detection(&self.controller);
+        codec.enable_volume_knobs(&self.controller);
+        let codec = Arc::new(codec);
+        self.restore_subscriptions(&codec);
+        self.restore_coefficient_banks(&codec);
+
+        let mut codecs = self.codecs.write();
+        codecs.retain(|known_codec| known_codec.codec_address() != codec_address);
+        codecs.push(codec);
+        Ok(())
+    }
+
+    // Resets a single codec with the function group reset verb and configures it again like after a state change. Unlike
+    // a reset of the controller, the link and the streams on the other codecs keep running. The volume and mute of the
+    // endpoints only live in the amps of the codec, so they get read before the reset and written again afterwards.
+    pub fn reset_codec(&self, codec_address: u8) -> Result<(), IhdaError> {
+        let codec = self.available_codecs().find(|codec| codec.codec_address() == codec_address).ok_or(IhdaError::NoCodecFound)?;
+        self.ensure_powered_up();
+        let endpoint_settings: Vec<EndpointSettingsChange> = self.sound_settings().devices().iter()
+            .flat_map(|device| device.endpoints().iter())
+            .filter(|endpoint| *endpoint.id().codec_address() == codec_address)
+            .map(|endpoint| EndpointSettingsChange::new(*endpoint.id(), Some(*endpoint.volume_percent()), Some(*endpoint.muted())))
+            .collect();
+
+        codec.codec().reset(&self.controller)?;
+        self.rescan_codec(codec_address)?;
+        self.restore_stream_routing();
+        if let Err(error) = self.apply_sound_settings_diff(&SoundSettingsDiff::new(endpoint_settings)) {
+            warn!("Could not restore volumes of IHDA codec {} after its reset: {:?}", codec_address, error);
+        }
+
+        info!("IHDA codec {} reset", codec_address);
+        audio_service().handle_codec_change(CodecChange::Reset(codec_address));
+        Ok(())
+    }
+
+    // Rescans every codec that signaled a state change since the last call and configures the paths of all registered streams
+    // again, as the codec has lost its settings. Gets called periodically, as the interrupt handler only notes the state changes.
+    pub fn handle_codec_state_changes(&self) {
+        let state_changes = PENDING_CODEC_STATE_CHANGES.swap(0, Ordering::Relaxed);
+        if state_changes == 0 {
+            return;
+        }
+
+        let default_output_endpoint = self.default_output_endpoint();
+        for codec_address in (0..MAX_AMOUNT_OF_CODECS).filter(|codec_address| state_changes & (1 << codec_address) != 0) {
+            let known = self.all_codecs().iter().any(|codec| codec.codec_address() == codec_address);
+            let change = match self.rescan_codec(codec_address) {
+                Ok(()) => if known { CodecChange::Reset(codec_address) } else { CodecChange::Attached(codec_address) },
+                Err(error) if known => {
+                    warn!("IHDA codec {} stopped answering after a state change: {:?}", codec_address, error);
+                    self.codecs.write().retain(|known_codec| known_codec.codec_address() != codec_address);
+                    audio_service().publish_event(AudioEvent::DeviceError(DeviceError::CodecRemoved(codec_address)));
+                    CodecChange::Removed(codec_address)
+                }
+                Err(error) => {
+                    debug!("Ignoring state change of unknown IHDA codec {}: {:?}", codec_address, error);
+                    continue;
+                }
+            };
+
+            info!("IHDA codec state change: {:?}", change);
+            self.restore_stream_routing();
+            audio_service().handle_codec_change(change);
+        }
+        self.publish_default_output_change(default_output_endpoint);
+    }
+
+    // Logs and counts the errors the interrupt handler collected and recovers from them. Gets called periodically.
+    // A stream descriptor that reported a descriptor error gets reset and programmed again, and restarted if the stream was
+    // active. Its DMA engine starts over at the first buffer, so samples which haven't been played yet might get skipped.
+    // A CORB memory error stops the CORB DMA engine, so both command rings get set up again. A RIRB overrun only loses
+    // responses, whose commands time out and get retried anyway.
+    pub fn handle_controller_errors(&self) {
+        let fifo_errors = PENDING_FIFO_ERRORS.swap(0, Ordering::Relaxed);
+        let descriptor_errors = PENDING_DESCRIPTOR_ERRORS.swap(0, Ordering::Relaxed);
+        let corb_memory_error = PENDING_CORB_MEMORY_ERROR.swap(false, Ordering::Relaxed);
+        let response_overrun = PENDING_RESPONSE_OVERRUN.swap(false, Ordering::Relaxed);
+
+        for stream_descriptor_number in (0..u32::BITS).filter(|number| fifo_errors & (1 << number) != 0) {
+            warn!("IHDA stream descriptor {} reported a FIFO error", stream_descriptor_number);
+            self.error_statistics.lock().stream_mut(stream_descriptor_number).fifo_errors += 1;
+            audio_service().publish_event(AudioEvent::DeviceError(DeviceError::FifoError(stream_descriptor_number)));
+            self.dispatch_stream_interrupt(stream_descriptor_number, StreamInterrupt::FifoError);
+        }
+
+        for stream_descriptor_number in (0..u32::BITS).filter(|number| descriptor_errors & (1 << number) != 0) {
+            warn!("IHDA stream descriptor {} reported a descriptor error, resetting it", stream_descriptor_number);
+            let active = stream_registry().lock().streams().iter()
+                .any(|stream| *stream.stream_descriptor_number() == stream_descriptor_number && stream.state().is_active());
+            let result = self.controller.recover_stream_descriptor(stream_descriptor_number, active);
+
+            {
+                let mut error_statistics = self.error_statistics.lock();
+                let counters = error_statistics.stream_mut(stream_descriptor_number);
+                counters.descriptor_errors += 1;
+                match result {
+                    Ok(()) => counters.recoveries += 1,
+                    Err(error) => {
+                        counters.failed_recoveries += 1;
+                        error!("IHDA stream descriptor {} could not be recovered: {:?}", stream_descriptor_number, error);
+                    }
+                }
+            }
+            audio_service().publish_event(AudioEvent::DeviceError(DeviceError::DescriptorError { stream_descriptor_number, recovered: result.is_ok() }));
+            self.dispatch_stream_interrupt(stream_descriptor_number, StreamInterrupt::DescriptorError { recovered: result.is_ok() });
+        }
+
+        if response_overrun {
+            warn!("IHDA RIRB overrun, responses got lost");
+            self.error_statistics.lock().response_overruns += 1;
+        }
+
+        if corb_memory_error {
+            warn!("IHDA CORB memory error, restarting CORB and RIRB");
+            self.error_statistics.lock().corb_memory_errors += 1;
+            let result = match self.available_codecs().next() {
+                Some(codec) => self.controller.restart_command_rings(codec.codec_address()),
+                None => Err(IhdaError::NoCodecFound),
+            };
+            audio_service().publish_event(AudioEvent::DeviceError(DeviceError::CommandRingError { recovered: result.is_ok() }));
+            let mut error_statistics = self.error_statistics.lock();
+            match result {
+                Ok(()) => error_statistics.command_ring_restarts += 1,
+                Err(error) => {
+                    error_statistics.failed_command_ring_restarts += 1;
+                    error!("IHDA command rings could not be restarted: {:?}", error);
+                }
+            }
+        }
+    }
+
+    // Watchdog for DMA engines which stopped fetching data without reporting an error, e.g. because the link hangs. Gets called
+    // periodically (see STREAM_WATCHDOG_INTERVAL_IN_MS). A stalled stream descriptor gets reset, programmed again and restarted
+    // like after a descriptor error. If that doesn't get its position moving again, its stream is marked as failed in the stream
+    // registry and the audio service gets notified, so that it can stop a playback nobody can hear anymore.
+    pub fn check_stalled_streams(&self) {
+        let running_stream_descriptors = self.controller.running_stream_descriptors();
+        let mut given_up_stream_descriptors = Vec::new();
+        {
+            let mut watched_streams = self.stream_watchdog.lock();
+            // stopped streams get forgotten, so that they start with a clean record once they get started again
+            watched_streams.retain(|watched| running_stream_descriptors.contains(&watched.stream_descriptor_number));
+
+            for stream_descriptor_number in running_stream_descriptors {
+                let position = self.controller.stream_position(stream_descriptor_number);
+                let watched = match watched_streams.iter_mut().position(|watched| watched.stream_descriptor_number == stream_descriptor_number) {
+                    Some(index) => &mut watched_streams[index],
+                    None => {
+                        watched_streams.push(WatchedStream { stream_descriptor_number, last_position: position, unchanged_samples: 0, recoveries_without_progress: 0, given_up: false });
+                        continue;
+                    }
+                };
+
+                if position != watched.last_position {
+                    watched.last_position = position;
+                    watched.unchanged_samples = 0;
+                    watched.recoveries_without_progress = 0;
+                    continue;
+                }
+                if watched.given_up {
+                    continue;
+                }
+                watched.unchanged_samples += 1;
+                if watched.unchanged_samples < STREAM_WATCHDOG_STALL_SAMPLES {
+                    continue;
+                }
+
+                watched.unchanged_samples = 0;
+                if watched.recoveries_without_progress == STREAM_WATCHDOG_MAX_RECOVERIES {
+                    error!("IHDA stream descriptor {} is still stalled after {} recoveries, giving up", stream_descriptor_number, STREAM_WATCHDOG_MAX_RECOVERIES);
+                    watched.given_up = true;
+                    given_up_stream_descriptors.push(stream_descriptor_number);
+                    continue;
+                }
+
+                warn!("IHDA stream descriptor {} stalled at position {}, resetting it", stream_descriptor_number, position);
+                let result = self.controller.recover_stream_descriptor(stream_descriptor_number, true);
+                watched.recoveries_without_progress += 1;
+                // the stream reset clears the position, so progress gets measured from the position after the recovery
+                watched.last_position = self.controller.stream_position(stream_descriptor_number);
+
+                let mut error_statistics = self.error_statistics.lock();
+                let counters = error_statistics.stream_mut(stream_descriptor_number);
+                counters.stalls += 1;
+                match result {
+                    Ok(()) => counters.recoveries += 1,
+                    Err(error) => {
+                        counters.failed_recoveries += 1;
+                        error!("IHDA stream descriptor {} could not be recovered: {:?}", stream_descriptor_number, error);
+                    }
+                }
+            }
+        }
+
+        // the watchdog lock is released, as the audio service stops streams in reaction, which the watchdog then forgets
+        for stream_descriptor_number in given_up_stream_descriptors {
+            stream_registry().lock().set_state(stream_descriptor_number, StreamState::Error);
+            audio_service().publish_event(AudioEvent::DeviceError(DeviceError::StreamStalled(stream_descriptor_number)));
+            audio_service().handle_stalled_stream(stream_descriptor_number);
+        }
+    }
+
+    // ########## stream interrupt dispatch ##########
+
+    // Lets the handler get called with the interrupts of a stream descriptor, replacing the handler it had before. Buffer
+    // completions get dispatched by the refill thread (see audio::refill) and errors by fn handle_controller_errors after the
+    // recovery. Buffer completions only get reported if the stream raises an interrupt on completion (see
+    // fn enable_buffer_completion_interrupt). The owner of the stream has to remove the handler when it closes the stream.
+    pub fn set_stream_interrupt_handler(&self, stream_descriptor_number: u32, handler: StreamInterruptHandler) {
+        let mut handlers = self.stream_interrupt_handlers.lock();
+        handlers.retain(|(number, _)| *number != stream_descriptor_number);
+        handlers.push((stream_descriptor_number, handler));
+    }
+
+    pub fn remove_stream_interrupt_handler(&self, stream_descriptor_number: u32) {
+        self.stream_interrupt_handlers.lock().retain(|(number, _)| *number != stream_descriptor_number);
+    }
+
+    // Called by the refill thread with the buffer completion interrupts of every stream descriptor. The handlers get called
+    // without holding the lock of the dispatch table, so that they can change it.
+    pub fn dispatch_buffer_completions(&self, completions: &BufferCompletions) {
+        let handlers = self.stream_interrupt_handlers.lock().clone();
+        for (stream_descriptor_number, handler) in handlers {
+            let completed_buffers = completions.of(stream_descriptor_number);
+            if completed_buffers > 0 {
+                handler(StreamInterrupt::BufferCompletion(completed_buffers));
+            }
+        }
+    }
+
+    fn dispatch_stream_interrupt(&self, stream_descriptor_number: u32, interrupt: StreamInterrupt) {
+        let handler = self.stream_interrupt_handlers.lock().iter()
+            .find(|(number, _)| *number == stream_descriptor_number)
+            .map(|(_, handler)| handler.clone());
+        if let Some(handler) = handler {
+            handler(interrupt);
+        }
+    }
+
+    // all codecs found on the controller, including quarantined ones
+    pub fn codecs(&self) -> Vec<CodecInfo> {
+        self.all_codecs().iter()
+            .map(|codec| codec.info(self.controller.is_codec_quarantined(codec.codec_address())))
+            .collect()
+    }
+
+    // Selects the codec whose endpoints get used when no endpoint is given (e.g. by the audio service and the mixer).
+    // Streams that are already running keep their endpoint.
+    pub fn select_default_codec(&self, codec_address: u8) -> Result<(), PlaybackError> {
+        if !self.all_codecs().iter().any(|codec| codec.codec_address() == codec_address) {
+            return Err(PlaybackError::UnknownCodec(codec_address));
+        }
+        let default_output_endpoint = self.default_output_endpoint();
+        *self.default_codec_address.lock() = Some(codec_address);
+        self.publish_default_output_change(default_output_endpoint);
+        Ok(())
+    }
+
+    // the available codecs and the default codec for a single operation, see struct CodecSnapshot
+    fn codec_snapshot(&self) -> CodecSnapshot {
+        CodecSnapshot { codecs: self.available_codecs().collect(), default_codec_address: *self.default_codec_address.lock() }
+    }
+
+    // A codec which stopped answering for a while might have lost its settings or hang halfway through a verb, so a codec
+    // that answers again gets reset and configured again, before its first stream runs into its unknown state. A codec
+    // which fails the reset runs into the quarantine again through the failed commands.
+    pub fn reprobe_quarantined_codecs(&self) {
+        for codec_address in self.controller.reprobe_quarantined_codecs() {
+            if let Err(error) = self.reset_codec(codec_address) {
+                error!("IHDA codec {} could not be reset after leaving quarantine: {:?}", codec_address, error);
+            }
+        }
+    }
+
+    // position of the DMA engine of a stream descriptor in its cyclic buffer
+    pub fn stream_position(&self, stream_descriptor_number: u32) -> u32 {
+        self.controller.stream_position(stream_descriptor_number)
+    }
+
+    // Sample-accurate position of a registered stream, given as the index of the frame the DMA engine is at in the cyclic buffer.
+    // Together with the wall clock, this can be used to synchronize other output (e.g. video) with the audio playback.
+    pub fn stream_position_in_frames(&self, stream_descriptor_number: u32) -> Option<u32> {
+        let bytes_per_frame = stream_registry().lock().streams().iter()
+            .find(|stream| *stream.stream_descriptor_number() == stream_descriptor_number)?
+            .format().bytes_per_frame();
+        Some(self.controller.stream_position(stream_descriptor_number) / bytes_per_frame.max(1))
+    }
+
+    // 24 MHz counter of the controller, which wraps around after about 179 seconds
+    pub fn wall_clock(&self) -> u32 {
+        self.controller.wall_clock_counter()
+    }
+
+    // Physical addresses of the wall clock alias and of the position of a registered stream. Both lie on pages which contain
+    // no other registers and no data besides stream positions, so they can be mapped read-only into user space.
+    pub fn stream_clock_addresses(&self, stream_descriptor_number: u32) -> Option<(PhysAddr, PhysAddr)> {
+        if !stream_registry().lock().streams().iter().any(|stream| *stream.stream_descriptor_number() == stream_descriptor_number) {
+            return None;
+        }
+        Some((self.controller.wall_clock_alias_address(), self.controller.stream_position_address(stream_descriptor_number)?))
+    }
+
+    fn register_stream(&self, stream: &Stream, stream_descriptor_number: u32, owner: StreamOwner, endpoint: Option<EndpointId>) {
+        stream_registry().lock().register(StreamInfo::new(
+            *stream.id(),
+            stream_descriptor_number,
+            owner,
+            endpoint,
+            active_format(stream.stream_format()),
+            stream.state(),
+            stream.buffer_length_in_bytes()));
+    }
+
+    fn sync_stream_state(&self, stream_descriptor_number: u32, stream: &Stream) {
+        stream_registry().lock().set_state(stream_descriptor_number, stream.state());
+    }
+
+    fn default_output_endpoint(&self) -> Option<EndpointId> {
+        let codecs = self.codec_snapshot();
+        let (_, pin_widget) = codecs.find_default_output_pin_widget()?;
+        Some(EndpointId::new(*pin_widget.address().codec_address().codec_address(), *pin_widget.address().node_id()))
+    }
+
+    // publishes an OutputChanged event if the default output endpoint isn't the one it was before a change
+    fn publish_default_output_change(&self, previous_endpoint: Option<EndpointId>) {
+        let endpoint = self.default_output_endpoint();
+        if endpoint != previous_endpoint {
+            audio_service().publish_event(AudioEvent::OutputChanged(endpoint));
+        }
+    }
+
+    // The signal generator produces samples of any bit depth, so the test tone gets played in the highest resolution the converter
+    // supports: 96 kHz and 24 bit if its capabilities report both (support_96000hz and support_24bit), 48 kHz and 24 bit if only
+    // the sample size is supported and 16 bit samples otherwise.
+    fn negotiate_test_tone_format(function_group: &FunctionGroup, converter: &Widget) -> Option<AudioFormat> {
+        let candidates = if converter.max_number_of_channels() >= 2 {
+            [AudioFormat::stereo_96khz_24bit(), AudioFormat::stereo_48khz_24bit()]
+        } else {
+            [AudioFormat::mono_96khz_24bit(), AudioFormat::mono_48khz_24bit()]
+        };
+
+        candidates.into_iter().find(|stream_format| Self::supports_format(function_group, converter, stream_format))
+            .or_else(|| Self::negotiate_16bit_format(function_group, converter))
+    }
+
+    // Recordings get handed out as 16 bit samples, so the converter has to support this sample size at 48 kHz or 44.1 kHz.
+    fn negotiate_16bit_format(function_group: &FunctionGroup, converter: &Widget) -> Option<AudioFormat> {
+        let candidates = if converter.max_number_of_channels() >= 2 {
+            [AudioFormat::stereo_48khz_16bit(), AudioFormat::stereo_44khz_16bit()]
+        } else {
+            [AudioFormat::mono_48khz_16bit(), AudioFormat::mono_44khz_16bit()]
+        };
+
+        candidates.into_iter().find(|stream_format| Self::supports_format(function_group, converter, stream_format))
+    }
+
+    // Converters without the Format Override bit use the formats of their function group (see section 7.3.4.6 of the specification).
+    fn converter_format_capabilities<'f>(function_group: &'f FunctionGroup, converter: &'f Widget) -> Option<(&'f SampleSizeRateCAPsResponse, &'f SupportedStreamFormatsResponse)> {
+        if *converter.audio_widget_capabilities().format_override() {
+            match converter.widget_info() {
+                WidgetInfoContainer::AudioOutputConverter(sample_size_rate_caps, supported_stream_formats, _, _, _) => Some((sample_size_rate_caps, supported_stream_formats)),
+                WidgetInfoContainer::AudioInputConverter(sample_size_rate_caps, supported_stream_formats, _, _, _, _, _) => Some((sample_size_rate_caps, supported_stream_formats)),
+                _ => None,
+            }
+        } else {
+            function_group.sample_size_rate_caps().as_ref().zip(function_group.supported_stream_formats().as_ref())
+        }
+    }
+
+    // Capabilities of the default output and input routes, derived from the capabilities the converters reported while scanning
+    // the codec, so no verbs get sent.
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        let codecs = self.codec_snapshot();
+        let output = self.find_output_endpoint(&codecs, None).ok().and_then(|(function_group, pin_widget, id)| {
+            let converter = Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget))?;
+            Self::route_capabilities(id, function_group, converter, self.max_output_channels())
+        });
+        let input = codecs.find_default_input_pin_widget().and_then(|(function_group, pin_widget)| {
+            let id = EndpointId::new(*pin_widget.address().codec_address().codec_address(), *pin_widget.address().node_id());
+            let converter = function_group.find_widget_path_for_capture(pin_widget)?[0];
+            Self::route_capabilities(id, function_group, converter, converter.max_number_of_channels())
+        });
+        DeviceCapabilities { output, input }
+    }
+
+    fn route_capabilities(endpoint: EndpointId, function_group: &FunctionGroup, converter: &Widget, max_channels: u8) -> Option<RouteCapabilities> {
+        let (sample_size_rate_caps, supported_stream_formats) = Self::converter_format_capabilities(function_group, converter)?;
+        if !*supported_stream_formats.pcm() {
+            return None;
+        }
+        let negotiated_format = Self::negotiate_16bit_format(function_group, converter)?;
+
+        Some(RouteCapabilities {
+            endpoint,
+            sample_rates: sample_size_rate_caps.supported_sample_rates(),
+            bits_per_sample: sample_size_rate_caps.supported_bits_per_sample().iter().map(|bits_per_sample| bits_per_sample.bit_depth()).collect(),
+            max_channels,
+            min_latency_in_ms: *BufferTopology::for_latency(&negotiated_format, 0).latency_in_ms(),
+            max_latency_in_ms: *BufferTopology::for_latency(&negotiated_format, u32::MAX).latency_in_ms(),
+        })
+    }
+
+    // The amount of channels the default output endpoint can play, either on its own converter or split up between the endpoints
+    // of its default association (see fn default_channel_assignments), 0 without a default output endpoint.
+    pub fn max_output_channels(&self) -> u8 {
+        let codecs = self.codec_snapshot();
+        let converter_channels = self.find_output_endpoint(&codecs, None).ok()
+            .and_then(|(function_group, pin_widget, _)| Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget)).map(|converter| converter.max_number_of_channels()))
+            .unwrap_or(0);
+        let speaker_pair_channels = (1..=MAX_SPEAKER_PAIR_CHANNELS).rev()
+            .find(|number_of_channels| self.default_channel_assignments(None, *number_of_channels).is_ok())
+            .unwrap_or(0);
+        converter_channels.max(speaker_pair_channels)
+    }
+
+    pub fn supports_output_format(&self, stream_format: &AudioFormat) -> bool {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, _) = match self.find_output_endpoint(&codecs, None) {
+            Ok(endpoint) => endpoint,
+            Err(_) => return false,
+        };
+        let converter = match Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget)) {
+            Some(converter) => converter,
+            None => return false,
+        };
+
+        if stream_format.is_pcm() && *stream_format.number_of_channels() > converter.max_number_of_channels() {
+            return self.default_channel_assignments(None, *stream_format.number_of_channels())
+                .and_then(|channel_assignments| self.channel_assignment_paths(&codecs, &channel_assignments, stream_format).map(|_| ()))
+                .is_ok();
+        }
+        Self::supports_format(function_group, converter, stream_format)
+    }
+
+    fn supports_format(function_group: &FunctionGroup, converter: &Widget, stream_format: &AudioFormat) -> bool {
+        let (sample_size_rate_caps, supported_stream_formats) = match Self::converter_format_capabilities(function_group, converter) {
+            Some(capabilities) => capabilities,
+            None => return false,
+        };
+
+        let stream_type_supported = if stream_format.is_pcm() {
+            *supported_stream_formats.pcm()
+        } else {
+            *supported_stream_formats.ac3()
+        };
+
+        stream_type_supported
+            && sample_size_rate_caps.supports_sample_rate(stream_format.sample_rate())
+            && sample_size_rate_caps.supports_bits_per_sample(*stream_format.bits_per_sample())
+            && *stream_format.number_of_channels() <= converter.max_number_of_channels()
+    }
+
+    fn active_format_of_converter(&self, widget: &Widget) -> Option<ActiveFormat> {
+        match widget.audio_widget_capabilities().widget_type() {
+            WidgetType::AudioOutput | WidgetType::AudioInput => {}
+            _ => return None,
+        }
+        // stream id 0 is reserved and means that the converter is not connected to any stream,
+        // a converter which doesn't respond has no format that could be reported either
+        let channel_stream_id = ChannelStreamIdResponse::try_from(self.controller.try_command(GetChannelStreamId(*widget.address())).ok()?).ok()?;
+        if *channel_stream_id.stream() == 0 {
+            return None;
+        }
+
+        let stream_format = AudioFormat::from_response(&StreamFormatResponse::try_from(self.controller.try_command(GetStreamFormat(*widget.address())).ok()?).ok()?);
+        Some(ActiveFormat::new(stream_format.sample_rate(), stream_format.bits_per_sample().bit_depth(), *stream_format.number_of_channels()))
+    }
+
+    // Message signaled interrupts don't share their vector with other devices, so they are preferred over the legacy interrupt line.
+    // The handler gets assigned first, as the controller may send a message as soon as they are enabled.
+    fn connect_interrupts(pci_bus: &PciBus, ihda_device: &EndpointHeader) {
+        interrupt_dispatcher().assign(InterruptVector::IhdaMsi, Box::new(IHDAInterruptHandler::default()));
+        let message = MsiMessage::new(apic().local_apic_id(), InterruptVector::IhdaMsi as u8);
+        let delivery = mmio_base_address(pci_bus, ihda_device).ok()
+            .and_then(|mmio_base_address| enable_message_signaled_interrupts(pci_bus, ihda_device, mmio_base_address, message));
+
+        match delivery {
+            Some(delivery) => info!("Connected driver to vector {:#x} via {:?}", InterruptVector::IhdaMsi as u8, delivery),
+            None => Self::connect_device_to_apic(get_interrupt_line(pci_bus, ihda_device)),
+        }
+    }
+
+    fn connect_device_to_apic(interrupt_line: InterruptLine) {
+        const X86_CPU_EXCEPTION_OFFSET: u8 = 32;
+        let interrupt_vector = InterruptVector::try_from(X86_CPU_EXCEPTION_OFFSET + interrupt_line).unwrap();
+        interrupt_dispatcher().assign(interrupt_vector, Box::new(IHDAInterruptHandler::default()));
+        apic().allow(interrupt_vector);
+        info!("Connected driver to interrupt line {} (plus X86_CPU_EXCEPTION_OFFSET of 32)", interrupt_line);
+        /*
+        The sound card on the testing device uses interrupt line 3, so that CPU_EXCEPTION_OFFSET + interrupt_line = 35.
+        A fake interrupt via the call of "unsafe { asm!("int 35"); }" will now result in a call of IHDAInterruptHandler's trigger() function.
+        */
+    }
+}
+
+fn active_format(stream_format: &AudioFormat) -> ActiveFormat {
+    ActiveFormat::new(stream_format.sample_rate(), stream_format.bits_per_sample().bit_depth(), *stream_format.number_of_channels())
+}
+
+// the stream registry only keeps the active format of a stream, which is enough to route the stream again
+fn stream_format(active_format: &ActiveFormat) -> Option<AudioFormat> {
+    let bits_per_sample = BitsPerSample::from_bit_depth(*active_format.bits_per_sample())?;
+    AudioFormat::pcm(*active_format.channels(), bits_per_sample, *active_format.sample_rate())
+}
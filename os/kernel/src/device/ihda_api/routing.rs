@@ -0,0 +1,685 @@
+use alloc::vec::Vec;
+use log::{debug, info, warn};
+use crate::audio_service;
+use crate::audio::events::AudioEvent;
+use crate::audio::format::AudioFormat;
+use crate::audio::stream_registry;
+use crate::audio::streams::{ChannelAssignment, StreamInfo, StreamOwner};
+use crate::audio::settings::{EndpointDirection, EndpointId, EndpointKind};
+use crate::device::ihda_codec::{decode_response, Codec, CommandError, CommandTransport, FunctionGroup, Widget};
+use crate::device::ihda_verbs::{AmplifierGainMuteResponse, GetAmplifierGainMutePayload, GetAmplifierGainMuteSide, GetAmplifierGainMuteType, SetAmplifierGainMutePayload, SetAmplifierGainMuteSide, SetAmplifierGainMuteType, WidgetType};
+use crate::device::ihda_verbs::Command::{GetAmplifierGainMute, SetAmplifierGainMute};
+use crate::device::ihda_codec_driver::{default_device, endpoint_id, endpoint_kind};
+use crate::device::ihda_api::{BufferTopology, CodecSnapshot, IhdaError, IntelHDAudioDevice, MAX_AMP_INDEX, PlaybackError, Stream, stream_format};
+use crate::device::ihda_api::mixer::MixerError;
+
+// The pins of a default association play the speaker pairs in the order of their sequence numbers, as recommended by the
+// pin configuration guidelines for codec vendors. Associations of only two pins are quadrophonic setups with front and rear.
+const SPEAKER_PAIRS_BY_SEQUENCE: [SpeakerPair; 4] = [SpeakerPair::Front, SpeakerPair::CenterLfe, SpeakerPair::Rear, SpeakerPair::Side];
+const QUADROPHONIC_SPEAKER_PAIRS: [SpeakerPair; 2] = [SpeakerPair::Front, SpeakerPair::Rear];
+const CHANNELS_PER_SPEAKER_PAIR: u8 = 2;
+// the lowest channel of a converter is a 4 bit field (see section 7.3.3.11 of the specification)
+const MAX_LOWEST_CHANNEL: u8 = 15;
+
+impl IntelHDAudioDevice {
+    // ########## pin roles ##########
+
+    // Gives a pin another role, e.g. to use a front mic jack as headphone output, as far as the pin capabilities allow it.
+    // The codec gets scanned again afterwards, so that endpoint lists, default endpoints and widget paths follow the new role.
+    // Pins with registered streams can't be retasked, as their widget paths would change under the running stream.
+    pub fn retask_pin(&self, endpoint: EndpointId, role: EndpointKind) -> Result<(), RetaskError> {
+        let default_device = default_device(role).ok_or(RetaskError::UnsupportedRole(role))?;
+        let codecs = self.codec_snapshot();
+        let (_, pin_widget) = codecs.find_pin_widget(endpoint).ok_or(RetaskError::UnknownEndpoint(endpoint))?;
+        if pin_widget.is_digital_display_pin() {
+            return Err(RetaskError::DigitalPin(endpoint));
+        }
+        let pin_capabilities = pin_widget.pin_capabilities().unwrap();
+        let capable = match role.direction() {
+            EndpointDirection::Output => *pin_capabilities.output_capable(),
+            EndpointDirection::Input => *pin_capabilities.input_capable(),
+        };
+        if !capable {
+            return Err(RetaskError::NotCapable(endpoint, role.direction()));
+        }
+        let in_use = stream_registry().lock().streams().iter().any(|stream| *stream.endpoint() == Some(endpoint)
+            || stream.mirrored_endpoints().contains(&endpoint)
+            || stream.channel_assignments().iter().any(|assignment| *assignment.endpoint() == endpoint));
+        if in_use {
+            return Err(RetaskError::InUse(endpoint));
+        }
+
+        self.ensure_powered_up();
+        self.controller.retask_pin_widget(pin_widget, &default_device).map_err(|error| RetaskError::Device(error.into()))?;
+        {
+            let mut pin_roles = self.pin_roles.lock();
+            pin_roles.retain(|(retasked_endpoint, _)| *retasked_endpoint != endpoint);
+            pin_roles.push((endpoint, role));
+        }
+        self.rescan_codec(*endpoint.codec_address()).map_err(RetaskError::Device)?;
+        info!("Retasked endpoint {:?} as {:?}", endpoint, role);
+        Ok(())
+    }
+
+    // Switches the external amplifier of a pin on or off, e.g. to find out which pin a silent speaker is connected to.
+    // Configuring a widget path for playback enables EAPD on its pin widget again.
+    pub fn set_eapd(&self, endpoint: EndpointId, enable: bool) -> Result<(), EapdError> {
+        let codecs = self.codec_snapshot();
+        let pin_widget = Self::find_eapd_pin_widget(&codecs, endpoint)?;
+        self.ensure_powered_up();
+        self.controller.set_eapd(pin_widget, enable).map_err(EapdError::Command)
+    }
+
+    pub fn eapd_enabled(&self, endpoint: EndpointId) -> Result<bool, EapdError> {
+        let codecs = self.codec_snapshot();
+        let pin_widget = Self::find_eapd_pin_widget(&codecs, endpoint)?;
+        self.ensure_powered_up();
+        self.controller.eapd_enabled(pin_widget).map_err(EapdError::Command)
+    }
+
+    fn find_eapd_pin_widget(codecs: &CodecSnapshot, endpoint: EndpointId) -> Result<&Widget, EapdError> {
+        let (_, pin_widget) = codecs.find_pin_widget(endpoint).ok_or(EapdError::UnknownEndpoint(endpoint))?;
+        if !*pin_widget.pin_capabilities().unwrap().eapd_capable() {
+            return Err(EapdError::NotCapable(endpoint));
+        }
+        Ok(pin_widget)
+    }
+
+    // Selects the connections from the pin widget of an endpoint to a converter, which doesn't have to be the one on the default
+    // path. Mixers on the route get the input amp of the connection unmuted. Setting up a stream on the endpoint selects the
+    // default path again (see fn select_connections_on_path).
+    pub fn route_endpoint_to_converter(&self, endpoint: EndpointId, converter_node_id: u8) -> Result<(), MixerError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget) = codecs.find_pin_widget(endpoint).ok_or(MixerError::NotAnEndpoint(endpoint))?;
+        let direction = endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction();
+        let converter = function_group.widgets().iter()
+            .find(|widget| *widget.address().node_id() == converter_node_id)
+            .filter(|widget| matches!((widget.audio_widget_capabilities().widget_type(), direction),
+                (WidgetType::AudioOutput, EndpointDirection::Output) | (WidgetType::AudioInput, EndpointDirection::Input)))
+            .ok_or(MixerError::NotAConverter(*endpoint.codec_address(), converter_node_id))?;
+
+        // playback paths start at the pin widget, capture paths at the converter
+        let path = match direction {
+            EndpointDirection::Output => function_group.find_widget_path_between(pin_widget, converter),
+            EndpointDirection::Input => function_group.find_widget_path_between(converter, pin_widget),
+        }.ok_or(MixerError::NoRoute(endpoint, converter_node_id))?;
+        self.ensure_powered_up();
+
+        self.controller.select_connections_on_path(&path);
+        for pair in path.windows(2) {
+            let (widget, upstream_widget) = (pair[0], pair[1]);
+            if !matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioMixer)
+                || !function_group.input_amp_capabilities_of(widget).is_some_and(|caps| *caps.mute_capable()) {
+                continue;
+            }
+            let index = widget.connection_list().iter().position(|node_id| node_id == upstream_widget.address().node_id()).unwrap() as u8;
+            if index > MAX_AMP_INDEX {
+                continue;
+            }
+            let payload = GetAmplifierGainMutePayload::new(GetAmplifierGainMuteType::Input, GetAmplifierGainMuteSide::Left, index);
+            let current: AmplifierGainMuteResponse = self.controller.try_command(GetAmplifierGainMute(*widget.address(), payload))
+                .and_then(|response| decode_response(response, *widget.address()))
+                .map_err(MixerError::Command)?;
+            self.controller.try_command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, index, false, *current.amplifier_gain())))
+                .map_err(MixerError::Command)?;
+        }
+        info!("Routed endpoint {:?} to converter {:#04x}", endpoint, converter_node_id);
+        Ok(())
+    }
+
+    // A codec reset restores the configuration defaults of its pins, so the roles of its retasked pins get applied again.
+    // Returns whether a pin had lost its role, which means that the codec has to be scanned again.
+    pub fn restore_pin_roles(&self, codec: &Codec) -> bool {
+        let codec_address = *codec.codec_address().codec_address();
+        let pin_roles: Vec<(EndpointId, EndpointKind)> = self.pin_roles.lock().iter()
+            .filter(|(endpoint, _)| *endpoint.codec_address() == codec_address)
+            .copied()
+            .collect();
+
+        let mut restored = false;
+        for (endpoint, role) in pin_roles {
+            let pin_widget = codec.function_groups().iter()
+                .flat_map(|function_group| function_group.widgets().iter())
+                .find(|widget| *widget.address().node_id() == *endpoint.node_id() && widget.configuration_default().is_some());
+            if let (Some(pin_widget), Some(default_device)) = (pin_widget, default_device(role)) {
+                if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()) != role {
+                    match self.controller.retask_pin_widget(pin_widget, &default_device) {
+                        Ok(()) => restored = true,
+                        Err(error) => warn!("Failed to restore the role {:?} of endpoint {:?}: {:?}", role, endpoint, error),
+                    }
+                }
+            }
+        }
+        restored
+    }
+
+    // moves all running output streams whose endpoint matches the filter to the given pin widget
+    pub fn reroute_output_streams(&self, endpoint_filter: impl Fn(EndpointId) -> bool, function_group: &FunctionGroup, target_pin_widget: &Widget) {
+        let codecs = self.codec_snapshot();
+        let target_id = EndpointId::new(*target_pin_widget.address().codec_address().codec_address(), *target_pin_widget.address().node_id());
+        let path = function_group.find_widget_path_from_pin(target_pin_widget);
+        let converter = match Self::converter_on_path(&path) {
+            Some(converter) => converter,
+            None => return,
+        };
+
+        // the registry lock must not be held while sending commands to the codec, so a copy of the stream list gets processed
+        let streams: Vec<StreamInfo> = stream_registry().lock().streams().clone();
+        let mut moved = false;
+        for stream in streams.iter().filter(|stream| stream.state().is_active()) {
+            let (_, old_pin_widget) = match stream.endpoint().and_then(|endpoint| codecs.find_pin_widget(endpoint)) {
+                Some(pin) => pin,
+                None => continue,
+            };
+            let old_id = stream.endpoint().unwrap();
+            if !endpoint_filter(old_id)
+                || endpoint_kind(old_pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Output {
+                continue;
+            }
+            if !stream.channel_assignments().is_empty() {
+                debug!("Stream {} can't be moved to endpoint {:?}, because its channels are split up between several endpoints", stream.stream_id(), target_id);
+                continue;
+            }
+
+            let stream_format = match stream_format(stream.format()) {
+                Some(stream_format) if Self::supports_format(function_group, converter, &stream_format) => stream_format,
+                _ => {
+                    debug!("Stream {} can't be moved to endpoint {:?}, because its format is not supported there", stream.stream_id(), target_id);
+                    continue;
+                }
+            };
+
+            self.controller.route_stream_to_widget_path(&path, *stream.stream_id(), &stream_format);
+            // the old path might use another converter, which would otherwise keep the old pin playing
+            if let Err(error) = self.controller.disable_pin_output(*old_pin_widget.address()) {
+                warn!("Failed to disable the output of pin widget {:?}: {:?}", old_pin_widget.address(), error);
+            }
+            stream_registry().lock().set_endpoint(*stream.stream_descriptor_number(), target_id);
+            info!("Moved stream {} from endpoint {:?} to endpoint {:?}", stream.stream_id(), old_id, target_id);
+            moved = true;
+        }
+        if moved {
+            audio_service().publish_event(AudioEvent::OutputChanged(Some(target_id)));
+        }
+    }
+
+    // ########## output streams ##########
+
+    // Prepares a stream on an output stream descriptor and routes it through the complete widget path of an output endpoint.
+    // The stream gets registered with the given owner and has to be filled with data before it can be started with fn start_stream.
+    // Non-PCM streams (see fn AudioFormat::non_pcm) need one of the endpoints returned by fn digital_output_endpoints.
+    pub fn open_output_stream(
+        &self,
+        owner: StreamOwner,
+        endpoint: Option<EndpointId>,
+        stream_format: AudioFormat,
+        output_stream_descriptor_index: usize,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+    ) -> Result<Stream, PlaybackError> {
+        self.open_routed_output_stream(owner, endpoint, stream_format, output_stream_descriptor_index, buffer_amount, pages_per_buffer, self.output_routing())
+    }
+
+    // Same as fn open_output_stream, but the amount and size of the buffers get derived from a target latency and the stream format
+    // (see BufferTopology::for_latency). Returns the topology as well, as its latency differs a little from the target.
+    pub fn open_output_stream_for_latency(
+        &self,
+        owner: StreamOwner,
+        endpoint: Option<EndpointId>,
+        stream_format: AudioFormat,
+        output_stream_descriptor_index: usize,
+        target_latency_in_ms: u32,
+    ) -> Result<(Stream, BufferTopology), PlaybackError> {
+        let topology = BufferTopology::for_latency(&stream_format, target_latency_in_ms);
+        let stream = self.open_output_stream(owner, endpoint, stream_format, output_stream_descriptor_index, *topology.buffer_amount(), *topology.pages_per_buffer())?;
+        Ok((stream, topology))
+    }
+
+    // Same as fn open_output_stream, but the stream only plays on the given endpoint, regardless of the output routing.
+    // Gets used for the second stream of a mirror endpoint, whose converter can't decode the format of the original stream.
+    pub fn open_mirror_output_stream(
+        &self,
+        owner: StreamOwner,
+        endpoint: EndpointId,
+        stream_format: AudioFormat,
+        output_stream_descriptor_index: usize,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+    ) -> Result<Stream, PlaybackError> {
+        self.open_routed_output_stream(owner, Some(endpoint), stream_format, output_stream_descriptor_index, buffer_amount, pages_per_buffer, OutputRouting::SinglePin)
+    }
+
+    fn open_routed_output_stream(
+        &self,
+        owner: StreamOwner,
+        endpoint: Option<EndpointId>,
+        stream_format: AudioFormat,
+        output_stream_descriptor_index: usize,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+        routing: OutputRouting,
+    ) -> Result<Stream, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = self.find_output_endpoint(&codecs, endpoint)?;
+        let path = function_group.find_widget_path_from_pin(pin_widget);
+        let converter = Self::converter_on_path(&path).ok_or(PlaybackError::NoConverterOnPath(id))?;
+        if !stream_format.is_pcm() && !converter.is_digital() {
+            return Err(PlaybackError::NotADigitalEndpoint(id));
+        }
+        if stream_format.is_pcm() && *stream_format.number_of_channels() > converter.max_number_of_channels() {
+            let channel_assignments = self.default_channel_assignments(Some(id), *stream_format.number_of_channels())?;
+            return self.open_multichannel_output_stream(owner, &channel_assignments, stream_format, output_stream_descriptor_index, buffer_amount, pages_per_buffer);
+        }
+        if !Self::supports_format(function_group, converter, &stream_format) {
+            return Err(PlaybackError::UnsupportedFormat(id));
+        }
+
+        self.ensure_powered_up();
+        let stream = self.controller.prepare_output_stream(output_stream_descriptor_index, stream_format, buffer_amount, pages_per_buffer).map_err(PlaybackError::Device)?;
+        let stream_descriptor_number = self.controller.output_stream_descriptor_number(output_stream_descriptor_index);
+        self.register_stream(&stream, stream_descriptor_number, owner, Some(id));
+        self.controller.configure_widget_path_for_playback(&path, &stream);
+        if routing == OutputRouting::Mirrored {
+            self.mirror_stream(function_group, id, *stream.id(), &stream_format, stream_descriptor_number);
+        }
+
+        Ok(stream)
+    }
+
+    // Prepares a stream whose channels get split up between the converters of several endpoints, e.g. the front, rear and
+    // center/LFE jacks of a 5.1 setup. The assignments usually come from fn default_channel_assignments, but any assignment
+    // of channels works, as long as every converter is used once and supports the rate, bit depth and channel count.
+    // The endpoint of the first assignment is the endpoint of the stream in the stream registry.
+    pub fn open_multichannel_output_stream(
+        &self,
+        owner: StreamOwner,
+        channel_assignments: &[ChannelAssignment],
+        stream_format: AudioFormat,
+        output_stream_descriptor_index: usize,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+    ) -> Result<Stream, PlaybackError> {
+        let first_assignment = match channel_assignments.first() {
+            Some(assignment) => *assignment,
+            None => panic!("A multichannel stream needs at least one channel assignment"),
+        };
+        let codecs = self.codec_snapshot();
+        let paths = self.channel_assignment_paths(&codecs, channel_assignments, &stream_format)?;
+
+        self.ensure_powered_up();
+        let stream = self.controller.prepare_output_stream(output_stream_descriptor_index, stream_format, buffer_amount, pages_per_buffer).map_err(PlaybackError::Device)?;
+        let stream_descriptor_number = self.controller.output_stream_descriptor_number(output_stream_descriptor_index);
+        self.register_stream(&stream, stream_descriptor_number, owner, Some(*first_assignment.endpoint()));
+        if channel_assignments.len() > 1 {
+            stream_registry().lock().set_channel_assignments(stream_descriptor_number, channel_assignments.to_vec());
+        }
+        for (assignment, path) in paths.iter() {
+            self.controller.route_stream_channels_to_widget_path(path, *stream.id(), &stream_format, *assignment.lowest_channel(), *assignment.channel_count());
+        }
+
+        Ok(stream)
+    }
+
+    // ########## output routing ##########
+
+    // Splits a stream with the given amount of channels up between the endpoints of the default association of an endpoint, with
+    // one speaker pair per endpoint. Streams the converter of the endpoint can play on its own are not split up at all.
+    pub fn default_channel_assignments(&self, endpoint: Option<EndpointId>, number_of_channels: u8) -> Result<Vec<ChannelAssignment>, PlaybackError> {
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = self.find_output_endpoint(&codecs, endpoint)?;
+        let converter = Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget)).ok_or(PlaybackError::NoConverterOnPath(id))?;
+        if number_of_channels <= converter.max_number_of_channels() {
+            return Ok(Vec::from([ChannelAssignment::new(id, 0, number_of_channels)]));
+        }
+
+        let pin_widgets = Self::association_pin_widgets(function_group, pin_widget);
+        let speaker_pairs: &[SpeakerPair] = if pin_widgets.len() == QUADROPHONIC_SPEAKER_PAIRS.len() { &QUADROPHONIC_SPEAKER_PAIRS } else { &SPEAKER_PAIRS_BY_SEQUENCE };
+        let channel_assignments: Vec<ChannelAssignment> = pin_widgets.iter().zip(speaker_pairs.iter())
+            .filter(|(_, speaker_pair)| speaker_pair.lowest_channel() < number_of_channels)
+            .map(|(pin_widget, speaker_pair)| ChannelAssignment::new(
+                endpoint_id(pin_widget),
+                speaker_pair.lowest_channel(),
+                (number_of_channels - speaker_pair.lowest_channel()).min(CHANNELS_PER_SPEAKER_PAIR)))
+            .collect();
+
+        let assigned_channels: u8 = channel_assignments.iter().map(|assignment| *assignment.channel_count()).sum();
+        if assigned_channels < number_of_channels {
+            return Err(PlaybackError::InvalidChannelAssignment(id));
+        }
+        Ok(channel_assignments)
+    }
+
+    // Analog output pins in the default association of the pin widget, sorted by their sequence number. The headphone jack
+    // of an association doesn't play a speaker pair, so a stream on it only gets played there.
+    fn association_pin_widgets<'f>(function_group: &'f FunctionGroup, pin_widget: &'f Widget) -> Vec<&'f Widget> {
+        let group = match function_group.association_group_of(pin_widget) {
+            Some(group) if group.pin_widgets().iter().any(|other| other.address().node_id() == pin_widget.address().node_id()) => group,
+            _ => return Vec::from([pin_widget]),
+        };
+
+        group.pin_widgets().iter().copied()
+            .filter(|other| endpoint_kind(other.configuration_default().unwrap().default_device()).direction() == EndpointDirection::Output)
+            .filter(|other| !other.is_digital_display_pin())
+            .collect()
+    }
+
+    // checks all channel assignments of a stream and returns the widget paths of their endpoints
+    pub fn channel_assignment_paths<'c>(&self, codecs: &'c CodecSnapshot, channel_assignments: &[ChannelAssignment], stream_format: &AudioFormat) -> Result<Vec<(ChannelAssignment, Vec<&'c Widget>)>, PlaybackError> {
+        let mut paths: Vec<(ChannelAssignment, Vec<&Widget>)> = Vec::new();
+        for assignment in channel_assignments {
+            let (function_group, pin_widget, id) = self.find_output_endpoint(codecs, Some(*assignment.endpoint()))?;
+            let path = function_group.find_widget_path_from_pin(pin_widget);
+            let converter = Self::converter_on_path(&path).ok_or(PlaybackError::NoConverterOnPath(id))?;
+
+            let last_channel = *assignment.lowest_channel() as usize + *assignment.channel_count() as usize;
+            if *assignment.channel_count() == 0
+                || *assignment.lowest_channel() > MAX_LOWEST_CHANNEL
+                || last_channel > *stream_format.number_of_channels() as usize
+                || (!stream_format.is_pcm() && channel_assignments.len() > 1) {
+                return Err(PlaybackError::InvalidChannelAssignment(id));
+            }
+            let used_twice = paths.iter()
+                .filter_map(|(_, other_path)| Self::converter_on_path(other_path))
+                .any(|other| other.address().node_id() == converter.address().node_id()
+                    && other.address().codec_address().codec_address() == converter.address().codec_address().codec_address());
+            if used_twice {
+                return Err(PlaybackError::InvalidChannelAssignment(id));
+            }
+
+            // the converter has to support the rate and bit depth of the stream, but only has to play its own channels
+            let converter_format = if stream_format.is_pcm() {
+                AudioFormat::pcm(*assignment.channel_count(), *stream_format.bits_per_sample(), stream_format.sample_rate()).ok_or(PlaybackError::UnsupportedFormat(id))?
+            } else {
+                *stream_format
+            };
+            if !stream_format.is_pcm() && !converter.is_digital() {
+                return Err(PlaybackError::NotADigitalEndpoint(id));
+            }
+            if !Self::supports_format(function_group, converter, &converter_format) {
+                return Err(PlaybackError::UnsupportedFormat(id));
+            }
+
+            paths.push((*assignment, path));
+        }
+        Ok(paths)
+    }
+
+    pub fn output_routing(&self) -> OutputRouting {
+        *self.output_routing.lock()
+    }
+
+    // Applies to all registered output streams right away. Switching back to a single pin turns off the output of all mirror
+    // endpoints, while their converters keep their settings until another stream gets routed through them.
+    pub fn set_output_routing(&self, routing: OutputRouting) {
+        let codecs = self.codec_snapshot();
+        *self.output_routing.lock() = routing;
+
+        let streams: Vec<StreamInfo> = stream_registry().lock().streams().clone();
+        for stream in streams.iter() {
+            let (function_group, pin_widget) = match stream.endpoint().and_then(|endpoint| codecs.find_pin_widget(endpoint)) {
+                Some(pin) => pin,
+                None => continue,
+            };
+            if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Output {
+                continue;
+            }
+            // the other pins of a multichannel stream already play their own channels
+            if !stream.channel_assignments().is_empty() {
+                continue;
+            }
+            match routing {
+                OutputRouting::Mirrored => if let Some(stream_format) = stream_format(stream.format()) {
+                    self.mirror_stream(function_group, endpoint_id(pin_widget), *stream.stream_id(), &stream_format, *stream.stream_descriptor_number());
+                },
+                OutputRouting::SinglePin => {
+                    for mirrored_endpoint in stream_registry().lock().clear_mirrored_endpoints(*stream.stream_descriptor_number()) {
+                        if let Some((_, mirrored_pin_widget)) = codecs.find_pin_widget(mirrored_endpoint) {
+                            if let Err(error) = self.controller.disable_pin_output(*mirrored_pin_widget.address()) {
+                                warn!("Failed to disable the output of pin widget {:?}: {:?}", mirrored_pin_widget.address(), error);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Routes a stream to the mirror endpoints of its endpoint, as far as their converters can decode the format of the stream.
+    // Converters of other registered streams are left alone, so that mirroring never takes an endpoint away from a stream
+    // that got opened for it.
+    fn mirror_stream(&self, function_group: &FunctionGroup, endpoint: EndpointId, stream_id: u8, stream_format: &AudioFormat, stream_descriptor_number: u32) {
+        for (mirrored_endpoint, path) in Self::mirror_paths(function_group, endpoint) {
+            let converter = Self::converter_on_path(&path).unwrap();
+            if !Self::supports_format(function_group, converter, stream_format) {
+                debug!("Stream {} can't be mirrored to endpoint {:?}, because its converter doesn't support the format", stream_id, mirrored_endpoint);
+                continue;
+            }
+            if self.converter_used_by_other_stream(converter, stream_descriptor_number) {
+                debug!("Stream {} can't be mirrored to endpoint {:?}, because its converter is used by another stream", stream_id, mirrored_endpoint);
+                continue;
+            }
+
+            self.controller.route_stream_to_widget_path(&path, stream_id, stream_format);
+            stream_registry().lock().add_mirrored_endpoint(stream_descriptor_number, mirrored_endpoint);
+            debug!("Mirrored stream {} from endpoint {:?} to endpoint {:?}", stream_id, endpoint, mirrored_endpoint);
+        }
+    }
+
+    // all other analog output pins of the function group with their paths, which end at an output converter
+    fn mirror_paths(function_group: &FunctionGroup, endpoint: EndpointId) -> Vec<(EndpointId, Vec<&Widget>)> {
+        function_group.find_connected_pin_widgets().into_iter()
+            .filter(|pin_widget| endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() == EndpointDirection::Output)
+            .filter(|pin_widget| endpoint_id(pin_widget) != endpoint && !pin_widget.is_digital_display_pin())
+            .filter_map(|pin_widget| {
+                let path = function_group.find_widget_path_from_pin(pin_widget);
+                match Self::converter_on_path(&path) {
+                    Some(converter) if !converter.is_digital() => Some((endpoint_id(pin_widget), path)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    fn converter_used_by_other_stream(&self, converter: &Widget, stream_descriptor_number: u32) -> bool {
+        let codecs = self.codec_snapshot();
+        let streams: Vec<StreamInfo> = stream_registry().lock().streams().clone();
+        streams.iter()
+            .filter(|stream| *stream.stream_descriptor_number() != stream_descriptor_number)
+            .flat_map(|stream| stream.endpoint().iter().copied().chain(stream.channel_assignments().iter().map(|assignment| *assignment.endpoint())))
+            .filter_map(|endpoint| codecs.find_pin_widget(endpoint))
+            .filter_map(|(function_group, pin_widget)| Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget)).map(|other| *other.address()))
+            .any(|address| address.node_id() == converter.address().node_id()
+                && address.codec_address().codec_address() == converter.address().codec_address().codec_address())
+    }
+
+    // Mirror endpoints of an endpoint whose converters can't decode the given format, each with a format of the same rate and
+    // bit depth its converter supports. Their owner has to feed them with the same samples through a stream of their own
+    // (see fn open_mirror_output_stream). Empty unless the output routing is OutputRouting::Mirrored.
+    pub fn mirror_endpoints_needing_separate_stream(&self, endpoint: Option<EndpointId>, stream_format: &AudioFormat) -> Vec<(EndpointId, AudioFormat)> {
+        if self.output_routing() != OutputRouting::Mirrored {
+            return Vec::new();
+        }
+        let codecs = self.codec_snapshot();
+        let (function_group, pin_widget, id) = match self.find_output_endpoint(&codecs, endpoint) {
+            Ok(endpoint) => endpoint,
+            Err(_) => return Vec::new(),
+        };
+        // the other pins of a multichannel stream play its other channels instead (see fn default_channel_assignments)
+        let splits_channels = Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget))
+            .is_some_and(|converter| *stream_format.number_of_channels() > converter.max_number_of_channels());
+        if splits_channels {
+            return Vec::new();
+        }
+
+        Self::mirror_paths(function_group, id).into_iter()
+            .filter_map(|(mirrored_endpoint, path)| {
+                let converter = Self::converter_on_path(&path).unwrap();
+                if Self::supports_format(function_group, converter, stream_format) {
+                    return None;
+                }
+                [*stream_format.number_of_channels(), 2, 1].into_iter()
+                    .filter_map(|number_of_channels| AudioFormat::pcm(number_of_channels, *stream_format.bits_per_sample(), stream_format.sample_rate()))
+                    .find(|mirror_format| Self::supports_format(function_group, converter, mirror_format))
+                    .map(|mirror_format| (mirrored_endpoint, mirror_format))
+            })
+            .collect()
+    }
+
+    pub fn restore_stream_routing(&self) {
+        let codecs = self.codec_snapshot();
+        let streams: Vec<StreamInfo> = stream_registry().lock().streams().clone();
+        for stream in streams.iter() {
+            let (function_group, pin_widget) = match stream.endpoint().and_then(|endpoint| codecs.find_pin_widget(endpoint)) {
+                Some(pin) => pin,
+                None => continue,
+            };
+            if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Output {
+                continue;
+            }
+            if let Some(stream_format) = stream_format(stream.format()) {
+                if !stream.channel_assignments().is_empty() {
+                    for assignment in stream.channel_assignments() {
+                        if let Some((assigned_function_group, assigned_pin_widget)) = codecs.find_pin_widget(*assignment.endpoint()) {
+                            let assigned_path = assigned_function_group.find_widget_path_from_pin(assigned_pin_widget);
+                            self.controller.route_stream_channels_to_widget_path(&assigned_path, *stream.stream_id(), &stream_format, *assignment.lowest_channel(), *assignment.channel_count());
+                        }
+                    }
+                    continue;
+                }
+                let path = function_group.find_widget_path_from_pin(pin_widget);
+                self.controller.route_stream_to_widget_path(&path, *stream.stream_id(), &stream_format);
+                for (mirrored_function_group, mirrored_pin_widget) in stream.mirrored_endpoints().iter().filter_map(|endpoint| codecs.find_pin_widget(*endpoint)) {
+                    let mirrored_path = mirrored_function_group.find_widget_path_from_pin(mirrored_pin_widget);
+                    self.controller.route_stream_to_widget_path(&mirrored_path, *stream.stream_id(), &stream_format);
+                }
+            }
+        }
+    }
+
+    // Without an endpoint, the first line out pin connected to a jack gets used.
+    pub fn find_output_endpoint<'c>(&self, codecs: &'c CodecSnapshot, endpoint: Option<EndpointId>) -> Result<(&'c FunctionGroup, &'c Widget, EndpointId), PlaybackError> {
+        let (function_group, pin_widget) = match endpoint {
+            Some(id) => codecs.find_pin_widget(id).ok_or(PlaybackError::UnknownEndpoint(id))?,
+            None => codecs.find_default_output_pin_widget().ok_or(PlaybackError::NoDefaultEndpoint)?,
+        };
+        let id = EndpointId::new(*pin_widget.address().codec_address().codec_address(), *pin_widget.address().node_id());
+
+        if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Output {
+            return Err(PlaybackError::NotAnOutputEndpoint(id));
+        }
+
+        Ok((function_group, pin_widget, id))
+    }
+
+    pub fn converter_on_path<'w>(path: &[&'w Widget]) -> Option<&'w Widget> {
+        match path.last() {
+            Some(widget) if matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioOutput) => Some(*widget),
+            _ => None,
+        }
+    }
+
+    // S/PDIF, HDMI and Display Port outputs, which can play PCM streams like any other output, but also pass non-PCM streams through to the receiver
+    pub fn digital_output_endpoints(&self) -> Vec<EndpointId> {
+        let mut endpoints = Vec::new();
+        for codec in self.available_codecs() {
+            for function_group in codec.codec().function_groups().iter() {
+                endpoints.extend(function_group.find_connected_pin_widgets().into_iter()
+                    .filter(|pin_widget| pin_widget.is_digital_display_pin()
+                        || endpoint_kind(pin_widget.configuration_default().unwrap().default_device()) == EndpointKind::DigitalOut)
+                    .filter(|pin_widget| Self::converter_on_path(&function_group.find_widget_path_from_pin(pin_widget)).map_or(false, |converter| converter.is_digital()))
+                    .map(endpoint_id));
+            }
+        }
+        endpoints
+    }
+
+    // Without an endpoint, the first mic in or line in pin connected to a jack gets used.
+    pub fn find_capture_path<'c>(&self, codecs: &'c CodecSnapshot, endpoint: Option<EndpointId>) -> Result<(EndpointId, Vec<&'c Widget>, AudioFormat), PlaybackError> {
+        let (function_group, pin_widget) = match endpoint {
+            Some(id) => codecs.find_pin_widget(id).ok_or(PlaybackError::UnknownEndpoint(id))?,
+            None => codecs.find_default_input_pin_widget().ok_or(PlaybackError::NoDefaultEndpoint)?,
+        };
+        let id = EndpointId::new(*pin_widget.address().codec_address().codec_address(), *pin_widget.address().node_id());
+        if endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() != EndpointDirection::Input {
+            return Err(PlaybackError::NotAnInputEndpoint(id));
+        }
+
+        let path = function_group.find_widget_path_for_capture(pin_widget).ok_or(PlaybackError::NoConverterOnPath(id))?;
+        let stream_format = Self::negotiate_16bit_format(function_group, path[0]).ok_or(PlaybackError::UnsupportedFormat(id))?;
+        Ok((id, path, stream_format))
+    }
+}
+
+// How output streams get routed to the pin widgets of a codec.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputRouting {
+    // every stream only plays on its own endpoint
+    SinglePin,
+    // Streams also play on all other analog output pins of the function group of their endpoint, e.g. on the internal speaker
+    // and the line out jack at the same time. The converters of these pins get the stream id of the stream, so that all of them
+    // decode the samples of the same DMA engine. Pins whose converter can't decode the format need a stream of their own,
+    // see fn mirror_endpoints_needing_separate_stream.
+    Mirrored,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetaskError {
+    UnknownEndpoint(EndpointId),
+    // HDMI and Display Port pins can't be retasked
+    DigitalPin(EndpointId),
+    // only line out, speaker, headphone, line in and microphone are roles a pin can be given
+    UnsupportedRole(EndpointKind),
+    // the pin capabilities lack the output or input capable bit the new role needs
+    NotCapable(EndpointId, EndpointDirection),
+    // a registered stream plays on or records from the pin
+    InUse(EndpointId),
+    Device(IhdaError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EapdError {
+    UnknownEndpoint(EndpointId),
+    // the pin capabilities lack the EAPD capable bit
+    NotCapable(EndpointId),
+    Command(CommandError),
+}
+
+// Stereo pairs of a multichannel stream. Codecs usually have one stereo converter per pair, each of them routed to a pin of
+// its own (e.g. the green, black and orange jacks of a 5.1 setup). The channels of a stream are ordered front left, front right,
+// rear left, rear right, center, LFE, side left, side right.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeakerPair {
+    Front,
+    Rear,
+    CenterLfe,
+    Side,
+}
+
+impl SpeakerPair {
+    pub fn lowest_channel(&self) -> u8 {
+        match self {
+            SpeakerPair::Front => 0,
+            SpeakerPair::Rear => 2,
+            SpeakerPair::CenterLfe => 4,
+            SpeakerPair::Side => 6,
+        }
+    }
+}
+
+// name of a channel in the channel order of the speaker pairs, where mono and stereo streams have no rear, center or side channels
+pub fn channel_name(channel: u8, number_of_channels: u8) -> &'static str {
+    match (number_of_channels, channel) {
+        (1, 0) => "mono",
+        (2, 0) => "left",
+        (2, 1) => "right",
+        (_, 0) => "front left",
+        (_, 1) => "front right",
+        (_, 2) => "rear left",
+        (_, 3) => "rear right",
+        (_, 4) => "center",
+        (_, 5) => "LFE",
+        (_, 6) => "side left",
+        (_, 7) => "side right",
+        _ => "unknown",
+    }
+}
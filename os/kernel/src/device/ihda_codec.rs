@@ -1,53 +1,58 @@
 #![allow(dead_code)]
 
+use alloc::vec;
 use alloc::vec::Vec;
-use core::ops::BitAnd;
 use derive_getters::Getters;
-
-pub const MAX_AMOUNT_OF_CODECS: u8 = 15;
-const MAX_AMOUNT_OF_AMPLIFIERS_IN_AMP_WIDGET: u8 = 16;
-const MAX_AMPLIFIER_GAIN: u8 = u8::MAX;
-
-
-
-// ############################################## IHDA commands ##############################################
-
-#[derive(Clone, Copy, Debug, Getters)]
-pub struct NodeAddress {
-    codec_address: CodecAddress,
-    node_id: u8,
-}
-
-impl NodeAddress {
-    pub fn new(codec_address: CodecAddress, node_id: u8) -> Self {
-        if codec_address.codec_address >= MAX_AMOUNT_OF_CODECS { panic!("IHDA only supports up to {} codecs!", MAX_AMOUNT_OF_CODECS) };
-        NodeAddress {
-            codec_address,
-            node_id,
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug, Getters)]
-pub struct CodecAddress {
-    codec_address: u8,
-}
-
-impl CodecAddress {
-    pub fn new(codec_address: u8) -> Self {
-        if codec_address >= MAX_AMOUNT_OF_CODECS { panic!("IHDA only supports up to {} codecs!", MAX_AMOUNT_OF_CODECS) };
-        CodecAddress {
-            codec_address,
-        }
-    }
-}
+use log::warn;
+use crate::device::ihda_controller::wait_for;
+use crate::device::pit::Timer;
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_stream::Stream;
+use crate::device::ihda_verbs::{AmpCapabilitiesResponse, AudioFunctionGroupCapabilitiesResponse, AudioWidgetCapabilitiesResponse, CodecAddress, Command, ConfigDefDefaultDevice, ConfigDefPortConnectivity, ConfigurationDefaultResponse, ConnectionListEntryResponse, ConnectionListLengthResponse, ConnectionSelectResponse, EAPDBTLEnableResponse, EncodedPacketType, FunctionGroupTypeEnum, FunctionGroupTypeResponse, GPIOCountResponse, GPIOResponse, GetConnectionListEntryPayload, NodeAddress, Parameter, PinCapabilitiesResponse, PinWidgetControlResponse, PowerState, PowerStateResponse, ProcessingCapabilitiesResponse, ProcessingCoefficientResponse, Response, RevisionIdResponse, SampleSizeRateCAPsResponse, SetAmplifierGainMutePayload, SetAmplifierGainMuteSide, SetAmplifierGainMuteType, SetChannelStreamIdPayload, SetCoefficientIndexPayload, SetConfigurationDefault2Payload, SetConnectionSelectPayload, SetConverterChannelCountPayload, SetDigitalConverterControl1Payload, SetDigitalConverterControl2Payload, SetEAPDBTLEnablePayload, SetGPIOPayload, SetPinWidgetControlPayload, SetPowerStatePayload, SetProcessingCoefficientPayload, SubordinateNodeCountResponse, SupportedPowerStatesResponse, SupportedStreamFormatsResponse, VendorIdResponse, VoltageReferenceSignalLevel, VolumeKnobCapabilitiesResponse, WidgetType};
+use crate::device::ihda_verbs::Command::{FunctionGroupReset, GetConfigurationDefault, GetConnectionListEntry, GetConnectionSelect, GetEAPDBTLEnable, GetGPIOData, GetGPIODirection, GetGPIOEnableMask, GetParameter, GetPinWidgetControl, GetPowerState, GetProcessingCoefficient, SetAmplifierGainMute, SetChannelStreamId, SetCoefficientIndex, SetConfigurationDefault2, SetConnectionSelect, SetConverterChannelCount, SetDigitalConverterControl1, SetDigitalConverterControl2, SetEAPDBTLEnable, SetGPIOData, SetGPIODirection, SetGPIOEnableMask, SetPinWidgetControl, SetPowerState, SetProcessingCoefficient, SetStreamFormat};
+use crate::device::ihda_verbs::Parameter::{AudioFunctionGroupCapabilities, AudioWidgetCapabilities, ConnectionListLength, FunctionGroupType, GPIOCount, InputAmpCapabilities, OutputAmpCapabilities, PinCapabilities, ProcessingCapabilities, RevisionId, SampleSizeRateCAPs, SubordinateNodeCount, SupportedPowerStates, SupportedStreamFormats, VolumeKnobCapabilities};
+
+// time a node gets to reach a requested power state, before the transition is considered failed
+const POWER_STATE_TRANSITION_TIMEOUT_IN_MS: usize = 100;
+// Time the analog parts of a codec get to settle after its nodes reached D0, before the configuration of the pins and their
+// presence detect bits are relied upon. Nodes report D0 as soon as their digital part is ready, while real hardware (e.g. charge pumps
+// of headphone amplifiers) needs a little longer. The value is arbitrarily chosen, as the specification doesn't define a delay.
+pub const POWER_UP_SETTLE_TIME_IN_MS: usize = 10;
+// default associations 0 and 15 don't group pins (see section 7.3.3.31 of the specification)
+const NO_ASSOCIATION: [u8; 2] = [0, 15];
+// A headphone pin with the highest sequence number of an output association is the headphone jack of the association instead
+// of one of its speaker pairs, and takes over the playback of the association while headphones are plugged in. This is how
+// the pin configuration guidelines for codec vendors mark the headphone jack of a laptop with its internal speakers.
+const HEADPHONE_SEQUENCE: u8 = 15;
+
+// Known deviations of codec models from the specification, selected by the vendor id read while scanning the codec.
+// The entries are (vendor id, device id, quirk).
+const CODEC_QUIRKS: [(u16, u16, CodecQuirk); 1] = [
+    // Cirrus Logic CS4208 in the MacBook Air 6,x
+    (0x1013, 0x4208, CodecQuirk::ExternalAmplifierGpio(0)),
+];
+// Codecs emulated by QEMU (see hw/audio/hda-codec.c in QEMU) as (vendor id, device id, model). They need no quirks and get
+// driven through the generic path discovery like all other codecs, so the model only gets shown in dumps.
+const QEMU_CODECS: [(u16, u16, &str); 3] = [
+    // line out
+    (0x1af4, 0x0012, "QEMU hda-output"),
+    // line out and line in
+    (0x1af4, 0x0022, "QEMU hda-duplex"),
+    // speaker and microphone
+    (0x1af4, 0x0032, "QEMU hda-micro"),
+];
+
+
+
+// ############################################## widget graph ##############################################
 
 #[derive(Debug, Getters)]
 pub struct Codec {
     codec_address: CodecAddress,
     vendor_id: VendorIdResponse,
     revision_id: RevisionIdResponse,
-    function_groups: Vec<FunctionGroup>
+    function_groups: Vec<FunctionGroup>,
+    quirks: Vec<CodecQuirk>,
 }
 
 impl Codec {
@@ -57,25 +62,63 @@ impl Codec {
         revision_id: RevisionIdResponse,
         function_groups: Vec<FunctionGroup>
     ) -> Self {
+        let quirks = CODEC_QUIRKS.iter()
+            .filter(|(vendor, device, _)| vendor == vendor_id.vendor_id() && device == vendor_id.device_id())
+            .map(|(_, _, quirk)| *quirk)
+            .collect();
         Codec {
             codec_address,
             vendor_id,
             revision_id,
             function_groups,
+            quirks,
+        }
+    }
+
+    // name of the codec model, if it is one of the emulated codecs
+    pub fn model_name(&self) -> Option<&'static str> {
+        QEMU_CODECS.iter()
+            .find(|(vendor, device, _)| vendor == self.vendor_id.vendor_id() && device == self.vendor_id.device_id())
+            .map(|(_, _, model_name)| *model_name)
+    }
+
+    // finds all function group nodes and widgets of the codec at the given root node
+    pub fn scan(transport: &impl CommandTransport, root_node_addr: NodeAddress, vendor_id: VendorIdResponse) -> Result<Self, CommandError> {
+        let revision_id = read_parameter(transport, root_node_addr, RevisionId)?;
+        let function_groups = scan_codec_for_available_function_groups(transport, root_node_addr)?;
+        Ok(Self::new(*root_node_addr.codec_address(), vendor_id, revision_id, function_groups))
+    }
+
+    // Returns all function groups of the codec and their widgets to their power-on state (see section 7.3.3.33 of the
+    // specification), without touching the link or the other codecs. Everything the driver configured on the widgets gets
+    // lost, so the codec has to be scanned and configured again afterwards (see fn IntelHDAudioDevice::reset_codec).
+    pub fn reset(&self, transport: &impl CommandTransport) -> Result<(), CommandError> {
+        for function_group in self.function_groups.iter() {
+            transport.try_command(FunctionGroupReset(function_group.function_group_node_address))?;
         }
+        Ok(())
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CodecQuirk {
+    // the speakers sit behind an external amplifier, which stays off until this GPIO of the audio function group gets raised
+    ExternalAmplifierGpio(u8),
+}
+
+// Parameters a node doesn't provide read as 0 (see section 7.3.4 of the specification), so they are None here. Function groups
+// which aren't audio function groups only get their type and GPIO count read and have no widgets.
 #[derive(Debug, Getters)]
 pub struct FunctionGroup {
     function_group_node_address: NodeAddress,
     function_group_type: FunctionGroupTypeResponse,
-    audio_function_group_caps: AudioFunctionGroupCapabilitiesResponse,
-    sample_size_rate_caps: SampleSizeRateCAPsResponse,
-    supported_stream_formats: SupportedStreamFormatsResponse,
-    input_amp_caps: AmpCapabilitiesResponse,
-    output_amp_caps: AmpCapabilitiesResponse,
-    supported_power_states: SupportedPowerStatesResponse,
+    audio_function_group_caps: Option<AudioFunctionGroupCapabilitiesResponse>,
+    // default formats and amp capabilities of widgets without the Format Override or Amp Param Override bit
+    sample_size_rate_caps: Option<SampleSizeRateCAPsResponse>,
+    supported_stream_formats: Option<SupportedStreamFormatsResponse>,
+    input_amp_caps: Option<AmpCapabilitiesResponse>,
+    output_amp_caps: Option<AmpCapabilitiesResponse>,
+    supported_power_states: Option<SupportedPowerStatesResponse>,
     gpio_count: GPIOCountResponse,
     widgets: Vec<Widget>,
 }
@@ -84,12 +127,12 @@ impl FunctionGroup {
     pub fn new(
         function_group_node_address: NodeAddress,
         function_group_type: FunctionGroupTypeResponse,
-        audio_function_group_caps: AudioFunctionGroupCapabilitiesResponse,
-        sample_size_rate_caps: SampleSizeRateCAPsResponse,
-        supported_stream_formats: SupportedStreamFormatsResponse,
-        input_amp_caps: AmpCapabilitiesResponse,
-        output_amp_caps: AmpCapabilitiesResponse,
-        supported_power_states: SupportedPowerStatesResponse,
+        audio_function_group_caps: Option<AudioFunctionGroupCapabilitiesResponse>,
+        sample_size_rate_caps: Option<SampleSizeRateCAPsResponse>,
+        supported_stream_formats: Option<SupportedStreamFormatsResponse>,
+        input_amp_caps: Option<AmpCapabilitiesResponse>,
+        output_amp_caps: Option<AmpCapabilitiesResponse>,
+        supported_power_states: Option<SupportedPowerStatesResponse>,
         gpio_count: GPIOCountResponse,
         widgets: Vec<Widget>
     ) -> Self {
@@ -107,6 +150,25 @@ impl FunctionGroup {
         }
     }
 
+    // Drives a GPIO of the function group as an output, e.g. to enable an external speaker amplifier (see specification,
+    // sections 7.3.3.21 to 7.3.3.23). The other GPIOs keep their state, as they might be used by the firmware.
+    pub fn set_gpio(&self, transport: &impl CommandTransport, index: u8, level: bool) -> Result<(), CommandError> {
+        if index >= *self.gpio_count.num_gpios() {
+            panic!("Function group {:?} only has {} GPIOs, so GPIO {} doesn't exist", self.function_group_node_address, self.gpio_count.num_gpios(), index);
+        }
+        let address = self.function_group_node_address;
+        let bit = 1u8 << index;
+
+        let enable_mask = *decode_response::<GPIOResponse>(transport.try_command(GetGPIOEnableMask(address))?, address)?.bits();
+        transport.try_command(SetGPIOEnableMask(address, SetGPIOPayload::new(enable_mask | bit)))?;
+        let direction = *decode_response::<GPIOResponse>(transport.try_command(GetGPIODirection(address))?, address)?.bits();
+        transport.try_command(SetGPIODirection(address, SetGPIOPayload::new(direction | bit)))?;
+        let data = *decode_response::<GPIOResponse>(transport.try_command(GetGPIOData(address))?, address)?.bits();
+        let data = if level { data | bit } else { data & !bit };
+        transport.try_command(SetGPIOData(address, SetGPIOPayload::new(data)))?;
+        Ok(())
+    }
+
     pub fn find_line_out_pin_widgets_connected_to_jack(&self) -> Vec<&Widget> {
         let mut pin_widgets_connected_to_jack = Vec::new();
         for widget in self.widgets().iter() {
@@ -139,1654 +201,1105 @@ impl FunctionGroup {
         pin_widgets_connected_to_jack
     }
 
-    pub fn find_widget_path_for_line_out_playback(&self) -> Vec<&Widget> {
-        let mut widgets_on_path = Vec::new();
-        let mut widget = Some(*self.find_line_out_pin_widgets_connected_to_jack().get(0).unwrap());
-        while widget.is_some() {
-            widgets_on_path.push(widget.unwrap());
-            widget = self.get_predecessor(widget.unwrap());
-        }
-        widgets_on_path
-    }
-
-    fn get_predecessor(&self, widget: &Widget) -> Option<&Widget> {
-        let connection_list_entries = match widget.widget_info() {
-            WidgetInfoContainer::AudioOutputConverter(_, _, _, _, _) => { None }
-            WidgetInfoContainer::AudioInputConverter(_, _, _, _, _, _) => { None }
-            WidgetInfoContainer::PinComplex(_, _, _, _, _, _, _, connection_list_entries) => { Some(connection_list_entries) }
-            WidgetInfoContainer::Mixer(_, _, _, _, _, connection_list_entries) => { Some(connection_list_entries) }
-            WidgetInfoContainer::Selector => { None }
-            WidgetInfoContainer::Power => { None }
-            WidgetInfoContainer::VolumeKnob => { None }
-            WidgetInfoContainer::BeepGenerator => { None }
-            WidgetInfoContainer::VendorDefined => { None }
-        };
+    // Connected pins grouped by their default association, which the firmware uses to tell which pins make up one device, e.g.
+    // the front, rear and center jacks of a surround speaker set (see section 7.3.3.31 of the specification). The groups are
+    // ordered by priority, which is the lowest association first, and the pins of a group by their sequence number.
+    // Pins without association don't belong to any group.
+    pub fn association_groups(&self) -> Vec<AssociationGroup> {
+        let mut groups: Vec<AssociationGroup> = Vec::new();
+        for pin_widget in self.find_connected_pin_widgets() {
+            let config_default = pin_widget.configuration_default().unwrap();
+            let association = *config_default.default_association();
+            if NO_ASSOCIATION.contains(&association) {
+                continue;
+            }
 
-        if connection_list_entries.is_some() {
-            let default_predecessor_node_id = *connection_list_entries.unwrap().first_entry();
-            for widget in self.widgets().iter() {
-                if *widget.address().node_id() == default_predecessor_node_id {
-                    return Some(widget);
+            let index = match groups.iter().position(|group| group.association == association) {
+                Some(index) => index,
+                None => {
+                    groups.push(AssociationGroup { association, pin_widgets: Vec::new(), headphone_pin_widget: None });
+                    groups.len() - 1
                 }
+            };
+            if *config_default.sequence() == HEADPHONE_SEQUENCE && matches!(config_default.default_device(), ConfigDefDefaultDevice::HPOut) {
+                groups[index].headphone_pin_widget = Some(pin_widget);
+            } else {
+                groups[index].pin_widgets.push(pin_widget);
             }
         }
 
-        None
-    }
-}
-
-#[derive(Debug, Getters)]
-pub struct Widget {
-    address: NodeAddress,
-    audio_widget_capabilities: AudioWidgetCapabilitiesResponse,
-    widget_info: WidgetInfoContainer,
-}
-
-impl Widget {
-    pub fn new(
-        address: NodeAddress,
-        audio_widget_capabilities: AudioWidgetCapabilitiesResponse,
-        widget_info: WidgetInfoContainer
-    ) -> Self {
-        Widget {
-            address,
-            audio_widget_capabilities,
-            widget_info
+        groups.sort_by_key(|group| group.association);
+        for group in groups.iter_mut() {
+            group.pin_widgets.sort_by_key(|pin_widget| *pin_widget.configuration_default().unwrap().sequence());
         }
+        groups
     }
 
-    pub fn max_number_of_channels(&self) -> u8 {
-        // this formula can be found in section 7.3.4.6, Audio Widget Capabilities of the specification
-        (self.audio_widget_capabilities.chan_count_ext() << 1) + (*self.audio_widget_capabilities.chan_count_lsb() as u8) + 1u8
+    pub fn association_group_of(&self, pin_widget: &Widget) -> Option<AssociationGroup> {
+        let node_id = *pin_widget.address().node_id();
+        self.association_groups().into_iter().find(|group| group.contains(node_id))
     }
-}
 
-#[derive(Debug)]
-pub enum WidgetInfoContainer {
-    AudioOutputConverter(
-        SampleSizeRateCAPsResponse,
-        SupportedStreamFormatsResponse,
-        AmpCapabilitiesResponse,
-        SupportedPowerStatesResponse,
-        ProcessingCapabilitiesResponse,
-    ),
-    AudioInputConverter(
-        SampleSizeRateCAPsResponse,
-        SupportedStreamFormatsResponse,
-        AmpCapabilitiesResponse,
-        ConnectionListLengthResponse,
-        SupportedPowerStatesResponse,
-        ProcessingCapabilitiesResponse,
-    ),
-    // first AmpCapabilitiesInfo is input amp caps and second AmpCapabilitiesInfo is output amp caps
-    PinComplex(
-        PinCapabilitiesResponse,
-        AmpCapabilitiesResponse,
-        AmpCapabilitiesResponse,
-        ConnectionListLengthResponse,
-        SupportedPowerStatesResponse,
-        ProcessingCapabilitiesResponse,
-        ConfigurationDefaultResponse,
-        ConnectionListEntryResponse,
-    ),
-    Mixer(
-        AmpCapabilitiesResponse,
-        AmpCapabilitiesResponse,
-        ConnectionListLengthResponse,
-        SupportedPowerStatesResponse,
-        ProcessingCapabilitiesResponse,
-        ConnectionListEntryResponse,
-    ),
-    Selector,
-    Power,
-    VolumeKnob,
-    BeepGenerator,
-    VendorDefined,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum Command {
-    GetParameter(NodeAddress, Parameter),
-    GetConnectionSelect(NodeAddress),
-    SetConnectionSelect(NodeAddress, SetConnectionSelectPayload),
-    GetConnectionListEntry(NodeAddress, GetConnectionListEntryPayload),
-    GetAmplifierGainMute(NodeAddress, GetAmplifierGainMutePayload),
-    SetAmplifierGainMute(NodeAddress, SetAmplifierGainMutePayload),
-    GetStreamFormat(NodeAddress),
-    SetStreamFormat(NodeAddress, SetStreamFormatPayload),
-    GetChannelStreamId(NodeAddress),
-    SetChannelStreamId(NodeAddress, SetChannelStreamIdPayload),
-    GetPinWidgetControl(NodeAddress),
-    SetPinWidgetControl(NodeAddress, SetPinWidgetControlPayload),
-    GetEAPDBTLEnable(NodeAddress),
-    SetEAPDBTLEnable(NodeAddress, SetEAPDBTLEnablePayload),
-    GetConfigurationDefault(NodeAddress),
-    GetConverterChannelCount(NodeAddress),
-    SetConverterChannelCount(NodeAddress, SetConverterChannelCountPayload),
-}
-
-impl Command {
-    pub fn id(&self) -> u16 {
-        match self {
-            Command::GetParameter(..) => 0xF00,
-            Command::GetConnectionSelect(..) => 0xF01,
-            Command::SetConnectionSelect(..) => 0x701,
-            Command::GetConnectionListEntry(..) => 0xF02,
-            Command::GetAmplifierGainMute(..) => 0xB,
-            Command::SetAmplifierGainMute(..) => 0x3,
-            Command::GetStreamFormat(..) => 0xA,
-            Command::SetStreamFormat(..) => 0x2,
-            Command::GetChannelStreamId(..) => 0xF06,
-            Command::SetChannelStreamId(..) => 0x706,
-            Command::GetPinWidgetControl(..) => 0xF07,
-            Command::SetPinWidgetControl(..) => 0x707,
-            Command::GetEAPDBTLEnable(..) => 0xF0C,
-            Command::SetEAPDBTLEnable(..) => 0x70C,
-            Command::GetConfigurationDefault(..) => 0xF1C,
-            Command::GetConverterChannelCount(..) => 0xF2D,
-            Command::SetConverterChannelCount(..) => 0x72D,
-        }
-    }
-
-    pub fn as_u32(&self) -> u32 {
-        match self {
-            Command::GetParameter(node_address, parameter) => Self::command_with_12bit_identifier_verb(node_address, self.id(), parameter.id()),
-            Command::GetConnectionSelect(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::SetConnectionSelect(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
-            Command::GetConnectionListEntry(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
-            Command::GetAmplifierGainMute(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
-            Command::SetAmplifierGainMute(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
-            Command::GetStreamFormat(node_address) => Self::command_with_4bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::SetStreamFormat(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
-            Command::GetChannelStreamId(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::SetChannelStreamId(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
-            Command::GetPinWidgetControl(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::SetPinWidgetControl(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
-            Command::GetEAPDBTLEnable(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::SetEAPDBTLEnable(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
-            Command::GetConfigurationDefault(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::GetConverterChannelCount(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
-            Command::SetConverterChannelCount(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
-        }
+    // modem and vendor defined function groups get scanned without widgets (see fn scan_codec_for_available_function_groups)
+    pub fn is_audio_function_group(&self) -> bool {
+        matches!(self.function_group_type.node_type(), FunctionGroupTypeEnum::AudioFunctionGroup)
     }
 
-    fn command_with_12bit_identifier_verb(node_address: &NodeAddress, verb_id: u16, payload: u8) -> u32 {
-        (node_address.codec_address().codec_address as u32) << 28
-            | (*node_address.node_id() as u32) << 20
-            | (verb_id as u32) << 8
-            | payload as u32
-    }
+    // Pins which can be the default output of the function group with their paths, ordered by priority: the primary pins of
+    // the analog output associations, lowest association first, followed by the line out pins connected to a jack without
+    // association. Digital outputs are skipped, as they might not be connected to anything that plays sound, and so are pins
+    // whose default path doesn't end at an audio output converter.
+    pub fn output_path_candidates(&self) -> Vec<OutputPathCandidate> {
+        let mut candidates: Vec<OutputPathCandidate> = Vec::new();
+        let association_pin_widgets = self.association_groups().into_iter()
+            .filter_map(|group| group.primary_pin_widget().map(|pin_widget| (pin_widget, group.association)))
+            .filter(|(pin_widget, _)| !pin_widget.is_digital_display_pin()
+                && matches!(pin_widget.configuration_default().unwrap().default_device(), ConfigDefDefaultDevice::LineOut | ConfigDefDefaultDevice::Speaker | ConfigDefDefaultDevice::HPOut))
+            .map(|(pin_widget, association)| (pin_widget, Some(association)));
+        let line_out_pin_widgets = self.find_line_out_pin_widgets_connected_to_jack().into_iter().map(|pin_widget| (pin_widget, None));
 
-    fn command_with_4bit_identifier_verb(node_address: &NodeAddress, verb_id: u16, payload: u16) -> u32 {
-        (node_address.codec_address().codec_address as u32) << 28
-            | (*node_address.node_id() as u32) << 20
-            | (verb_id as u32) << 16
-            | payload as u32
-    }
-}
-
-// compare to table 140 in section 7.3.6 of the specification
-#[derive(Clone, Copy, Debug)]
-pub enum Parameter {
-    VendorId,
-    RevisionId,
-    SubordinateNodeCount,
-    FunctionGroupType,
-    AudioFunctionGroupCapabilities,
-    AudioWidgetCapabilities,
-    SampleSizeRateCAPs,
-    SupportedStreamFormats,
-    PinCapabilities,
-    InputAmpCapabilities,
-    OutputAmpCapabilities,
-    ConnectionListLength,
-    SupportedPowerStates,
-    ProcessingCapabilities,
-    GPIOCount,
-    VolumeKnobCapabilities,
-}
-
-impl Parameter {
-    pub fn id(&self) -> u8 {
-        match self {
-            Parameter::VendorId => 0x00,
-            Parameter::RevisionId => 0x02,
-            Parameter::SubordinateNodeCount => 0x04,
-            Parameter::FunctionGroupType => 0x05,
-            Parameter::AudioFunctionGroupCapabilities => 0x08,
-            Parameter::AudioWidgetCapabilities => 0x09,
-            Parameter::SampleSizeRateCAPs => 0x0A,
-            Parameter::SupportedStreamFormats => 0x0B,
-            Parameter::PinCapabilities => 0x0C,
-            Parameter::InputAmpCapabilities => 0x0D,
-            Parameter::OutputAmpCapabilities => 0x12,
-            Parameter::ConnectionListLength => 0x0E,
-            Parameter::SupportedPowerStates => 0x0F,
-            Parameter::ProcessingCapabilities => 0x10,
-            Parameter::GPIOCount => 0x11,
-            Parameter::VolumeKnobCapabilities => 0x13,
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SetConnectionSelectPayload {
-    connection_index: u8,
-}
-
-impl SetConnectionSelectPayload {
-    pub fn new(connection_index: u8) -> Self {
-        Self {
-            connection_index,
-        }
-    }
-
-    pub fn as_u8(&self) -> u8 {
-        self.connection_index
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct GetConnectionListEntryPayload {
-    offset: u8,
-}
-
-impl GetConnectionListEntryPayload {
-    pub fn new(offset: u8) -> Self {
-        Self {
-            offset,
+        for (pin_widget, association) in association_pin_widgets.chain(line_out_pin_widgets) {
+            if candidates.iter().any(|candidate| candidate.pin_widget.address().node_id() == pin_widget.address().node_id()) {
+                continue;
+            }
+            let path = self.find_widget_path_from_pin(pin_widget);
+            if path.last().is_some_and(|widget| matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioOutput)) {
+                candidates.push(OutputPathCandidate { pin_widget, path, association });
+            }
         }
+        candidates
     }
 
-    pub fn as_u8(&self) -> u8 {
-        self.offset
+    // the pin of the first candidate of fn output_path_candidates, which is the main playback device the firmware describes
+    // (e.g. the internal speakers of a laptop)
+    pub fn default_output_pin_widget(&self) -> Option<&Widget> {
+        self.output_path_candidates().into_iter().next().map(|candidate| candidate.pin_widget)
     }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct GetAmplifierGainMutePayload {
-    amp_type: GetAmplifierGainMuteType,
-    side: GetAmplifierGainMuteSide,
-    index: u8,
-}
 
-impl GetAmplifierGainMutePayload {
-    pub fn new(amp_type: GetAmplifierGainMuteType, side: GetAmplifierGainMuteSide, index: u8) -> Self {
-        if index > MAX_AMOUNT_OF_AMPLIFIERS_IN_AMP_WIDGET { panic!("Index for amplifier out of range") };
-        Self {
-            amp_type,
-            side,
-            index,
+    // all pin widgets which are physically connected to a jack or an internal device according to their configuration default
+    pub fn find_connected_pin_widgets(&self) -> Vec<&Widget> {
+        self.widgets().iter()
+            .filter(|widget| match widget.configuration_default() {
+                Some(config_default) => match config_default.port_connectivity() {
+                    ConfigDefPortConnectivity::NoPhysicalConnection => false,
+                    _ => true,
+                },
+                None => false,
+            })
+            .collect()
+    }
+
+    // pins a microphone or another source can be plugged into
+    pub fn find_input_pin_widgets_connected_to_jack(&self) -> Vec<&Widget> {
+        self.find_connected_pin_widgets().into_iter()
+            .filter(|widget| match widget.configuration_default().unwrap().port_connectivity() {
+                ConfigDefPortConnectivity::Jack | ConfigDefPortConnectivity::JackAndInternalDevice => true,
+                _ => false,
+            })
+            .filter(|widget| matches!(widget.configuration_default().unwrap().default_device(), ConfigDefDefaultDevice::MicIn | ConfigDefDefaultDevice::LineIn))
+            .collect()
+    }
+
+    // pins headphones can be plugged into
+    pub fn find_headphone_pin_widgets_connected_to_jack(&self) -> Vec<&Widget> {
+        self.find_connected_pin_widgets().into_iter()
+            .filter(|widget| match widget.configuration_default().unwrap().port_connectivity() {
+                ConfigDefPortConnectivity::Jack | ConfigDefPortConnectivity::JackAndInternalDevice => true,
+                _ => false,
+            })
+            .filter(|widget| matches!(widget.configuration_default().unwrap().default_device(), ConfigDefDefaultDevice::HPOut))
+            .collect()
+    }
+
+    // built-in speakers are usually connected to their pin widget without a jack
+    pub fn find_speaker_pin_widgets(&self) -> Vec<&Widget> {
+        self.find_connected_pin_widgets().into_iter()
+            .filter(|widget| matches!(widget.configuration_default().unwrap().default_device(), ConfigDefDefaultDevice::Speaker))
+            .collect()
+    }
+
+    // Follows the default connections upstream, starting at each audio input converter, until the pin widget is reached.
+    // The audio input converter is the first widget of the returned path and the pin widget the last one.
+    pub fn find_widget_path_for_capture<'a>(&'a self, pin_widget: &'a Widget) -> Option<Vec<&'a Widget>> {
+        self.widgets().iter()
+            .filter(|widget| matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioInput))
+            .map(|converter| self.find_widget_path_from_pin(converter))
+            .find(|path| path.last().map_or(false, |widget| widget.address().node_id() == pin_widget.address().node_id()))
+    }
+
+    // follows the default connections upstream, starting at the pin widget, so the pin widget is the first widget of the returned path
+    pub fn find_widget_path_from_pin<'a>(&'a self, pin_widget: &'a Widget) -> Vec<&'a Widget> {
+        let mut widgets_on_path = Vec::new();
+        let mut widget = Some(pin_widget);
+        while widget.is_some() {
+            widgets_on_path.push(widget.unwrap());
+            widget = self.get_predecessor(widget.unwrap());
         }
+        widgets_on_path
     }
 
-    fn as_u16(&self) -> u16 {
-        let amp_type: u16 = match self.amp_type  {
-            GetAmplifierGainMuteType::Input => 0,
-            GetAmplifierGainMuteType::Output => 1,
-        };
-        let side: u16 = match self.side  {
-            GetAmplifierGainMuteSide::Right => 0,
-            GetAmplifierGainMuteSide::Left => 1,
-        };
-
-        amp_type << 15 | side << 13 | self.index as u16
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SetAmplifierGainMutePayload {
-    amp_type: SetAmplifierGainMuteType,
-    side: SetAmplifierGainMuteSide,
-    index: u8,
-    mute: bool,
-    gain: u8,
-}
-
-impl SetAmplifierGainMutePayload {
-    pub fn new(amp_type: SetAmplifierGainMuteType, side: SetAmplifierGainMuteSide, index: u8, mute: bool, gain: u8) -> Self {
-        if gain > MAX_AMPLIFIER_GAIN { panic!("gain is a 7 bit parameter, writing 8 bit values will leak into mute bit and are therefore prohibited") }
-        if index > MAX_AMOUNT_OF_AMPLIFIERS_IN_AMP_WIDGET { panic!("Index for amplifier out of range") }
-        Self {
-            amp_type,
-            side,
-            index,
-            mute,
-            gain,
+    // Searches the connection lists upstream from the first widget for the second one, so that the returned path is ordered like
+    // the paths of fn find_widget_path_from_pin, but doesn't have to follow the default connections. Widgets can be connected in
+    // loops (e.g. mixers feeding each other), so every widget gets visited at most once.
+    pub fn find_widget_path_between<'a>(&'a self, downstream_widget: &'a Widget, upstream_widget: &'a Widget) -> Option<Vec<&'a Widget>> {
+        let mut widgets_on_path = vec![downstream_widget];
+        let mut visited_node_ids = vec![*downstream_widget.address().node_id()];
+        if self.extend_path_to(&mut widgets_on_path, &mut visited_node_ids, *upstream_widget.address().node_id()) {
+            Some(widgets_on_path)
+        } else {
+            None
         }
     }
 
-    fn as_u16(&self) -> u16 {
-        let amp_type: u16 = match self.amp_type  {
-            SetAmplifierGainMuteType::Input => 0b01,
-            SetAmplifierGainMuteType::Output => 0b10,
-            SetAmplifierGainMuteType::Both => 0b11,
-        };
-        let side: u16 = match self.side  {
-            SetAmplifierGainMuteSide::Right => 0b01,
-            SetAmplifierGainMuteSide::Left => 0b10,
-            SetAmplifierGainMuteSide::Both => 0b11,
-        };
-
-        amp_type << 14 | side << 12 | (self.index as u16) << 8 | (self.mute as u16) << 7 | self.gain as u16
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum GetAmplifierGainMuteType {
-    Input,
-    Output,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum GetAmplifierGainMuteSide {
-    Right,
-    Left,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum SetAmplifierGainMuteType {
-    Input,
-    Output,
-    Both,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum SetAmplifierGainMuteSide {
-    Right,
-    Left,
-    Both,
-}
-
-
-#[derive(Clone, Copy, Debug, Getters)]
-pub struct SetStreamFormatPayload {
-    number_of_channels: u8,
-    bits_per_sample: BitsPerSample,
-    sample_base_rate_divisor: u8,
-    sample_base_rate_multiple: u8,
-    sample_base_rate: u16,
-    stream_type: StreamType,
-}
-
-impl SetStreamFormatPayload {
-    pub fn new(
-        number_of_channels: u8,
-        bits_per_sample: BitsPerSample,
-        sample_base_rate_divisor: u8,
-        sample_base_rate_multiple: u8,
-        sample_base_rate: u16,
-        stream_type: StreamType,
-    ) -> Self {
-        Self {
-            number_of_channels,
-            bits_per_sample,
-            sample_base_rate_divisor,
-            sample_base_rate_multiple,
-            sample_base_rate,
-            stream_type,
+    fn extend_path_to<'a>(&'a self, widgets_on_path: &mut Vec<&'a Widget>, visited_node_ids: &mut Vec<u8>, upstream_node_id: u8) -> bool {
+        let widget = *widgets_on_path.last().unwrap();
+        if *widget.address().node_id() == upstream_node_id {
+            return true;
         }
-    }
-
-    fn as_u16(&self) -> u16 {
-        let number_of_channels = self.number_of_channels - 1;
-        let bits_per_sample = match self.bits_per_sample {
-            BitsPerSample::Eight => 0b000,
-            BitsPerSample::Sixteen => 0b001,
-            BitsPerSample::Twenty => 0b010,
-            BitsPerSample::Twentyfour => 0b011,
-            BitsPerSample::Thirtytwo => 0b100,
-        };
-        let sample_base_rate_divisor = self.sample_base_rate_divisor - 1;
-        let sample_base_rate_multiple = self.sample_base_rate_multiple - 1;
-        let sample_base_rate = if self.sample_base_rate == 44100 { 1 } else { 0 };
-        let stream_type = match self.stream_type {
-            StreamType::PCM => 0,
-            StreamType::NonPCM => 1,
-        };
-        (stream_type as u16) << 15
-            | (sample_base_rate as u16) << 14
-            | (sample_base_rate_multiple as u16) << 11
-            | (sample_base_rate_divisor as u16) << 8
-            | (bits_per_sample as u16) << 4
-            | number_of_channels as u16
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SetChannelStreamIdPayload {
-    channel: u8,
-    stream: u8,
-}
-
-impl SetChannelStreamIdPayload {
-    pub fn new(channel: u8, stream: u8,) -> Self {
-        Self {
-            channel,
-            stream,
+        for node_id in widget.connection_list().iter() {
+            if visited_node_ids.contains(node_id) {
+                continue;
+            }
+            visited_node_ids.push(*node_id);
+            if let Some(connected_widget) = self.find_widget(*node_id) {
+                widgets_on_path.push(connected_widget);
+                if self.extend_path_to(widgets_on_path, visited_node_ids, upstream_node_id) {
+                    return true;
+                }
+                widgets_on_path.pop();
+            }
         }
+        false
     }
 
-    pub fn as_u8(&self) -> u8 {
-        (self.stream << 4) | self.channel
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SetPinWidgetControlPayload {
-    voltage_reference_enable: VoltageReferenceSignalLevel,
-    in_enable: bool,
-    out_enable: bool,
-    h_phn_enable: bool,
-}
-
-impl SetPinWidgetControlPayload {
-    pub fn new(
-        voltage_reference_enable: VoltageReferenceSignalLevel,
-        in_enable: bool,
-        out_enable: bool,
-        h_phn_enable: bool,
-    ) -> Self {
-        Self {
-            voltage_reference_enable,
-            in_enable,
-            out_enable,
-            h_phn_enable,
+    // widgets without the Amp Param Override bit set use the default amp capabilities of their function group (see section 7.3.4.6 of the specification)
+    pub fn output_amp_capabilities_of<'a>(&'a self, widget: &'a Widget) -> Option<&'a AmpCapabilitiesResponse> {
+        if !*widget.audio_widget_capabilities().out_amp_present() {
+            return None;
         }
-    }
-
-    pub fn enable_input_and_output_amps(pin_widget_control_response: PinWidgetControlResponse) -> Self {
-       Self::new(
-            match pin_widget_control_response.voltage_reference_enable() {
-                VoltageReferenceSignalLevel::HiZ => VoltageReferenceSignalLevel::HiZ,
-                VoltageReferenceSignalLevel::FiftyPercent => VoltageReferenceSignalLevel::FiftyPercent,
-                VoltageReferenceSignalLevel::Ground0V => VoltageReferenceSignalLevel::Ground0V,
-                VoltageReferenceSignalLevel::EightyPercent => VoltageReferenceSignalLevel::EightyPercent,
-                VoltageReferenceSignalLevel::HundredPercent => VoltageReferenceSignalLevel::HundredPercent,
-            },
-            true,
-            true,
-            *pin_widget_control_response.h_phn_enable()
-        )
-    }
-
-    pub fn as_u8(&self) -> u8 {
-        let voltage_reference_enable = match self.voltage_reference_enable {
-            VoltageReferenceSignalLevel::HiZ => 0b000,
-            VoltageReferenceSignalLevel::FiftyPercent => 0b001,
-            VoltageReferenceSignalLevel::Ground0V => 0b010,
-            VoltageReferenceSignalLevel::EightyPercent => 0b100,
-            VoltageReferenceSignalLevel::HundredPercent => 0b101,
-        };
-        (self.h_phn_enable as u8) << 7 | (self.out_enable as u8) << 6 | (self.in_enable as u8) << 5 | voltage_reference_enable
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SetEAPDBTLEnablePayload {
-    btl_enable: bool,
-    eapd_enable: bool,
-    lr_swap: bool,
-}
-
-impl SetEAPDBTLEnablePayload {
-    pub fn new(
-        btl_enable: bool,
-        eapd_enable: bool,
-        lr_swap: bool,
-    ) -> Self {
-        Self {
-            btl_enable,
-            eapd_enable,
-            lr_swap,
+        if *widget.audio_widget_capabilities().amp_param_override() {
+            widget.output_amp_capabilities()
+        } else {
+            self.output_amp_caps().as_ref()
         }
     }
 
-    pub fn as_u8(&self) -> u8 {
-        (self.btl_enable as u8) << 2 | (self.eapd_enable as u8) << 1 | self.lr_swap as u8
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SetConverterChannelCountPayload {
-    converter_channel_count: u8,
-}
-
-impl SetConverterChannelCountPayload {
-    pub fn new(converter_channel_count: u8) -> Self {
-        Self {
-            converter_channel_count,
+    pub fn input_amp_capabilities_of<'a>(&'a self, widget: &'a Widget) -> Option<&'a AmpCapabilitiesResponse> {
+        if !*widget.audio_widget_capabilities().in_amp_present() {
+            return None;
         }
-    }
-
-    pub fn as_u8(&self) -> u8 {
-        self.converter_channel_count
-    }
-}
-
-
-
-// ############################################## IHDA responses ##############################################
-
-pub struct RawResponse {
-    raw_value: u32,
-}
-
-impl RawResponse {
-    pub fn new(response: u32) -> Self {
-        Self {
-            raw_value: response,
+        if *widget.audio_widget_capabilities().amp_param_override() {
+            widget.input_amp_capabilities()
+        } else {
+            self.input_amp_caps().as_ref()
         }
     }
 
-    fn get_bit(&self, index: usize) -> bool {
-        (self.raw_value >> index).bitand(1) != 0
-    }
-}
-
-#[derive(Debug)]
-pub enum Response {
-    VendorId(VendorIdResponse),
-    RevisionId(RevisionIdResponse),
-    SubordinateNodeCount(SubordinateNodeCountResponse),
-    FunctionGroupType(FunctionGroupTypeResponse),
-    AudioFunctionGroupCapabilities(AudioFunctionGroupCapabilitiesResponse),
-    AudioWidgetCapabilities(AudioWidgetCapabilitiesResponse),
-    SampleSizeRateCAPs(SampleSizeRateCAPsResponse),
-    SupportedStreamFormats(SupportedStreamFormatsResponse),
-    PinCapabilities(PinCapabilitiesResponse),
-    InputAmpCapabilities(AmpCapabilitiesResponse),
-    OutputAmpCapabilities(AmpCapabilitiesResponse),
-    ConnectionListLength(ConnectionListLengthResponse),
-    SupportedPowerStates(SupportedPowerStatesResponse),
-    ProcessingCapabilities(ProcessingCapabilitiesResponse),
-    GPIOCount(GPIOCountResponse),
-    VolumeKnobCapabilities(VolumeKnobCapabilitiesResponse),
-
-    ConnectionSelect(ConnectionSelectResponse),
-    ConnectionListEntry(ConnectionListEntryResponse),
-    AmplifierGainMute(AmplifierGainMuteResponse),
-    ChannelStreamId(ChannelStreamIdResponse),
-    StreamFormat(StreamFormatResponse),
-    PinWidgetControl(PinWidgetControlResponse),
-    EAPDBTLEnable(EAPDBTLEnableResponse),
-    ConfigurationDefault(ConfigurationDefaultResponse),
-    ConverterChannelCount(ConverterChannelCountResponse),
-    Zeros,
-}
-
-impl Response {
-    pub fn new(response: RawResponse, associated_command: Command) -> Response {
-        match associated_command {
-            Command::GetParameter(_, parameter) => {
-                match parameter {
-                    Parameter::VendorId => Response::VendorId(VendorIdResponse::new(response)),
-                    Parameter::RevisionId => Response::RevisionId(RevisionIdResponse::new(response)),
-                    Parameter::SubordinateNodeCount => Response::SubordinateNodeCount(SubordinateNodeCountResponse::new(response)),
-                    Parameter::FunctionGroupType => Response::FunctionGroupType(FunctionGroupTypeResponse::new(response)),
-                    Parameter::AudioFunctionGroupCapabilities => Response::AudioFunctionGroupCapabilities(AudioFunctionGroupCapabilitiesResponse::new(response)),
-                    Parameter::AudioWidgetCapabilities => Response::AudioWidgetCapabilities(AudioWidgetCapabilitiesResponse::new(response)),
-                    Parameter::SampleSizeRateCAPs => Response::SampleSizeRateCAPs(SampleSizeRateCAPsResponse::new(response)),
-                    Parameter::SupportedStreamFormats => Response::SupportedStreamFormats(SupportedStreamFormatsResponse::new(response)),
-                    Parameter::PinCapabilities => Response::PinCapabilities(PinCapabilitiesResponse::new(response)),
-                    Parameter::InputAmpCapabilities => Response::InputAmpCapabilities(AmpCapabilitiesResponse::new(response)),
-                    Parameter::OutputAmpCapabilities => Response::OutputAmpCapabilities(AmpCapabilitiesResponse::new(response)),
-                    Parameter::ConnectionListLength => Response::ConnectionListLength(ConnectionListLengthResponse::new(response)),
-                    Parameter::SupportedPowerStates => Response::SupportedPowerStates(SupportedPowerStatesResponse::new(response)),
-                    Parameter::ProcessingCapabilities => Response::ProcessingCapabilities(ProcessingCapabilitiesResponse::new(response)),
-                    Parameter::GPIOCount => Response::GPIOCount(GPIOCountResponse::new(response)),
-                    Parameter::VolumeKnobCapabilities => Response::VolumeKnobCapabilities(VolumeKnobCapabilitiesResponse::new(response)),
+    // Searches a codec-internal loopback, where an audio input converter takes its input from a mixer widget which mixes in
+    // an audio output converter. Codecs without such a mixer (e.g. the one emulated by QEMU) have no loopback path.
+    pub fn find_loopback_path(&self) -> Option<LoopbackPath> {
+        for input_converter in self.widgets().iter().filter(|widget| matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioInput)) {
+            for (mixer_connection_index, mixer_node_id) in input_converter.connection_list().iter().copied().enumerate() {
+                let mixer = match self.find_widget(mixer_node_id) {
+                    Some(widget) if matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioMixer) => widget,
+                    _ => continue,
+                };
+                for (output_converter_connection_index, output_converter_node_id) in mixer.connection_list().iter().copied().enumerate() {
+                    if let Some(output_converter) = self.find_widget(output_converter_node_id)
+                        .filter(|widget| matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioOutput)) {
+                        return Some(LoopbackPath {
+                            output_converter,
+                            mixer,
+                            output_converter_connection_index: output_converter_connection_index as u8,
+                            input_converter,
+                            mixer_connection_index: mixer_connection_index as u8,
+                        });
+                    }
                 }
             }
-            Command::GetConnectionSelect(..) => Response::ConnectionSelect(ConnectionSelectResponse::new(response)),
-            Command::SetConnectionSelect(..) => Response::Zeros,
-            Command::GetConnectionListEntry(..) => Response::ConnectionListEntry(ConnectionListEntryResponse::new(response)),
-            Command::GetAmplifierGainMute(..) => Response::AmplifierGainMute(AmplifierGainMuteResponse::new(response)),
-            Command::SetAmplifierGainMute(..) => Response::Zeros,
-            Command::GetStreamFormat(..) => Response::StreamFormat(StreamFormatResponse::new(response)),
-            Command::SetStreamFormat(..) => Response::Zeros,
-            Command::GetChannelStreamId(..) => Response::ChannelStreamId(ChannelStreamIdResponse::new(response)),
-            Command::SetChannelStreamId(..) => Response::Zeros,
-            Command::GetPinWidgetControl(..) => Response::PinWidgetControl(PinWidgetControlResponse::new(response)),
-            Command::SetPinWidgetControl(..) => Response::Zeros,
-            Command::GetEAPDBTLEnable(..) => Response::EAPDBTLEnable(EAPDBTLEnableResponse::new(response)),
-            Command::SetEAPDBTLEnable(..) => Response::Zeros,
-            Command::GetConfigurationDefault(..) => Response::ConfigurationDefault(ConfigurationDefaultResponse::new(response)),
-            Command::GetConverterChannelCount(..) => Response::ConverterChannelCount(ConverterChannelCountResponse::new(response)),
-            Command::SetConverterChannelCount(..) => Response::Zeros,
         }
+        None
     }
-}
 
-#[derive(Debug, Getters)]
-pub struct VendorIdResponse {
-    device_id: u16,
-    vendor_id: u16,
-}
-
-impl VendorIdResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            device_id: response.raw_value.bitand(0xFFFF) as u16,
-            vendor_id: (response.raw_value >> 16).bitand(0xFFFF) as u16,
+    // Searches a mixer widget on the default output path of the output pin which mixes in the input pin, either directly or
+    // through another mixer (e.g. the input mixer of many Realtek codecs, which collects all analog inputs).
+    pub fn find_sidetone_path<'a>(&'a self, input_pin: &'a Widget, output_pin: &'a Widget) -> Option<SidetonePath<'a>> {
+        let output_path = self.find_widget_path_from_pin(output_pin);
+        let input_pin_node_id = *input_pin.address().node_id();
+        for (position, mixer) in output_path.iter().copied().enumerate() {
+            if !matches!(mixer.audio_widget_capabilities().widget_type(), WidgetType::AudioMixer) {
+                continue;
+            }
+            for (connection_index, node_id) in mixer.connection_list().iter().copied().enumerate() {
+                let input_mixer = if node_id == input_pin_node_id {
+                    None
+                } else {
+                    let input_mixer = match self.find_widget(node_id) {
+                        Some(widget) if matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioMixer) => widget,
+                        _ => continue,
+                    };
+                    match input_mixer.connection_list().iter().position(|node_id| *node_id == input_pin_node_id) {
+                        Some(input_pin_connection_index) => Some((input_mixer, input_pin_connection_index as u8)),
+                        None => continue,
+                    }
+                };
+                return Some(SidetonePath {
+                    output_path: output_path[..=position].to_vec(),
+                    mixer,
+                    connection_index: connection_index as u8,
+                    input_mixer,
+                });
+            }
         }
-
+        None
     }
-}
 
-impl TryFrom<Response> for VendorIdResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-                    Response::VendorId(info) => Ok(info),
-                    e => Err(e),
-                }
+    fn find_widget(&self, node_id: u8) -> Option<&Widget> {
+        self.widgets().iter().find(|widget| *widget.address().node_id() == node_id)
     }
-}
 
-#[derive(Debug, Getters)]
-pub struct RevisionIdResponse {
-    stepping_id: u8,
-    revision_id: u8,
-    minor_revision: u8,
-    major_revision: u8,
-}
+    fn get_predecessor(&self, widget: &Widget) -> Option<&Widget> {
+        let connection_list_entries = match widget.widget_info() {
+            WidgetInfoContainer::AudioOutputConverter(_, _, _, _, _) => { None }
+            WidgetInfoContainer::AudioInputConverter(_, _, _, _, _, _, connection_list_entries) => { Some(connection_list_entries) }
+            WidgetInfoContainer::PinComplex(_, _, _, _, _, _, _, connection_list_entries) => { Some(connection_list_entries) }
+            WidgetInfoContainer::Mixer(_, _, _, _, _, connection_list_entries) => { Some(connection_list_entries) }
+            WidgetInfoContainer::Selector(_, connection_list_entries) => { Some(connection_list_entries) }
+            WidgetInfoContainer::Power => { None }
+            WidgetInfoContainer::VolumeKnob(_) => { None }
+            WidgetInfoContainer::BeepGenerator => { None }
+            WidgetInfoContainer::VendorDefined => { None }
+        };
 
-impl RevisionIdResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            stepping_id: response.raw_value.bitand(0xFF) as u8,
-            revision_id: (response.raw_value >> 8).bitand(0xFF) as u8,
-            minor_revision: (response.raw_value >> 16).bitand(0xF) as u8,
-            major_revision: (response.raw_value >> 20).bitand(0xF) as u8,
+        if connection_list_entries.is_some() {
+            let default_predecessor_node_id = *connection_list_entries.unwrap().first()?;
+            for widget in self.widgets().iter() {
+                if *widget.address().node_id() == default_predecessor_node_id {
+                    return Some(widget);
+                }
+            }
         }
-    }
-}
-
-impl TryFrom<Response> for RevisionIdResponse {
-    type Error = Response;
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::RevisionId(info) => Ok(info),
-            e => Err(e),
-        }
+        None
     }
 }
 
 #[derive(Debug, Getters)]
-pub struct SubordinateNodeCountResponse {
-    total_number_of_nodes: u8,
-    starting_node_number: u8,
+pub struct Widget {
+    address: NodeAddress,
+    audio_widget_capabilities: AudioWidgetCapabilitiesResponse,
+    widget_info: WidgetInfoContainer,
 }
 
-impl SubordinateNodeCountResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            total_number_of_nodes: response.raw_value.bitand(0xFF) as u8,
-            starting_node_number: (response.raw_value >> 16).bitand(0xFF) as u8,
+impl Widget {
+    pub fn new(
+        address: NodeAddress,
+        audio_widget_capabilities: AudioWidgetCapabilitiesResponse,
+        widget_info: WidgetInfoContainer
+    ) -> Self {
+        Widget {
+            address,
+            audio_widget_capabilities,
+            widget_info
         }
-
     }
-}
 
-impl TryFrom<Response> for SubordinateNodeCountResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::SubordinateNodeCount(info) => Ok(info),
-            e => Err(e),
-        }
+    pub fn max_number_of_channels(&self) -> u8 {
+        // this formula can be found in section 7.3.4.6, Audio Widget Capabilities of the specification
+        (self.audio_widget_capabilities.chan_count_ext() << 1) + (*self.audio_widget_capabilities.chan_count_lsb() as u8) + 1u8
     }
-}
-
-#[derive(Debug, Getters)]
-pub struct FunctionGroupTypeResponse {
-    node_type: FunctionGroupTypeEnum,
-    unsolicited_response_capable: bool,
-}
 
-impl FunctionGroupTypeResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            node_type: match response.raw_value.bitand(0xFF) as u8 {
-                0x1 => FunctionGroupTypeEnum::AudioFunctionGroup,
-                0x2 => FunctionGroupTypeEnum::VendorDefinedFunctionGroup,
-                0x80..=0xFF => FunctionGroupTypeEnum::VendorDefinedModemFunctionGroup,
-                _ => panic!("Unknown function group node type!")
-            },
-            unsolicited_response_capable: response.get_bit(8),
+    pub fn configuration_default(&self) -> Option<&ConfigurationDefaultResponse> {
+        match &self.widget_info {
+            WidgetInfoContainer::PinComplex(_, _, _, _, _, _, config_default, _) => Some(config_default),
+            _ => None,
         }
-
     }
-}
-
-impl TryFrom<Response> for FunctionGroupTypeResponse {
-    type Error = Response;
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::FunctionGroupType(info) => Ok(info),
-            e => Err(e),
+    pub fn pin_capabilities(&self) -> Option<&PinCapabilitiesResponse> {
+        match &self.widget_info {
+            WidgetInfoContainer::PinComplex(pin_caps, _, _, _, _, _, _, _) => Some(pin_caps),
+            _ => None,
         }
     }
-}
-
-#[derive(Debug)]
-pub enum FunctionGroupTypeEnum {
-    AudioFunctionGroup,
-    VendorDefinedModemFunctionGroup,
-    VendorDefinedFunctionGroup,
-}
 
-#[derive(Debug, Getters)]
-pub struct AudioFunctionGroupCapabilitiesResponse {
-    output_delay: u8,
-    input_delay: u8,
-    beep_gen: bool,
-}
-
-impl AudioFunctionGroupCapabilitiesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            output_delay: response.raw_value.bitand(0xF) as u8,
-            input_delay: (response.raw_value >> 8).bitand(0xF) as u8,
-            beep_gen: response.get_bit(16),
+    // only read for widgets with the Proc Widget bit set in their audio widget capabilities (see WidgetInfoContainer)
+    pub fn processing_capabilities(&self) -> Option<&ProcessingCapabilitiesResponse> {
+        match &self.widget_info {
+            WidgetInfoContainer::AudioOutputConverter(_, _, _, _, processing_caps) => processing_caps.as_ref(),
+            WidgetInfoContainer::AudioInputConverter(_, _, _, _, _, processing_caps, _) => processing_caps.as_ref(),
+            WidgetInfoContainer::PinComplex(_, _, _, _, _, processing_caps, _, _) => processing_caps.as_ref(),
+            WidgetInfoContainer::Mixer(_, _, _, _, processing_caps, _) => processing_caps.as_ref(),
+            _ => None,
         }
     }
-}
 
-impl TryFrom<Response> for AudioFunctionGroupCapabilitiesResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::AudioFunctionGroupCapabilities(info) => Ok(info),
-            e => Err(e),
+    pub fn volume_knob_capabilities(&self) -> Option<&VolumeKnobCapabilitiesResponse> {
+        match &self.widget_info {
+            WidgetInfoContainer::VolumeKnob(volume_knob_caps) => Some(volume_knob_caps),
+            _ => None,
         }
     }
-}
-
-#[derive(Debug, Getters)]
-pub struct AudioWidgetCapabilitiesResponse {
-    chan_count_lsb: bool,
-    in_amp_present: bool,
-    out_amp_present: bool,
-    amp_param_override: bool,
-    format_override: bool,
-    stripe: bool,
-    proc_widget: bool,
-    unsol_capable: bool,
-    conn_list: bool,
-    digital: bool,
-    power_cntrl: bool,
-    lr_swap: bool,
-    cp_caps: bool,
-    chan_count_ext: u8,
-    delay: u8,
-    widget_type: WidgetType,
-}
 
-impl AudioWidgetCapabilitiesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            chan_count_lsb: response.get_bit(0),
-            in_amp_present: response.get_bit(1),
-            out_amp_present: response.get_bit(2),
-            amp_param_override: response.get_bit(3),
-            format_override: response.get_bit(4),
-            stripe: response.get_bit(5),
-            proc_widget: response.get_bit(6),
-            unsol_capable: response.get_bit(7),
-            conn_list: response.get_bit(8),
-            digital: response.get_bit(9),
-            power_cntrl: response.get_bit(10),
-            lr_swap: response.get_bit(11),
-            cp_caps: response.get_bit(12),
-            chan_count_ext: (response.raw_value >> 13).bitand(0b111) as u8,
-            delay: (response.raw_value >> 16).bitand(0xF) as u8,
-            widget_type: match (response.raw_value >> 20).bitand(0xF) as u8 {
-                0x0 => WidgetType::AudioOutput,
-                0x1 => WidgetType::AudioInput,
-                0x2 => WidgetType::AudioMixer,
-                0x3 => WidgetType::AudioSelector,
-                0x4 => WidgetType::PinComplex,
-                0x5 => WidgetType::PowerWidget,
-                0x6 => WidgetType::VolumeKnobWidget,
-                0x7 => WidgetType::BeepGeneratorWidget,
-                0xF => WidgetType::VendorDefinedAudioWidget,
-                _ => panic!("Unsupported widget type!")
-            }
-        }
+    // converters and pins which transport S/PDIF or HDMI streams (see section 7.3.4.6 of the specification)
+    pub fn is_digital(&self) -> bool {
+        *self.audio_widget_capabilities.digital()
     }
-}
 
-impl TryFrom<Response> for AudioWidgetCapabilitiesResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::AudioWidgetCapabilities(info) => Ok(info),
-            e => Err(e),
-        }
+    // HDMI and Display Port pins use bits [2:0] of their pin widget control for the Encoded Packet Type instead of the Voltage Reference Enable
+    pub fn is_digital_display_pin(&self) -> bool {
+        self.pin_capabilities().map_or(false, |pin_capabilities| *pin_capabilities.hdmi() || *pin_capabilities.display_port())
     }
-}
-
-#[derive(Debug)]
-pub enum WidgetType {
-    AudioOutput,
-    AudioInput,
-    AudioMixer,
-    AudioSelector,
-    PinComplex,
-    PowerWidget,
-    VolumeKnobWidget,
-    BeepGeneratorWidget,
-    VendorDefinedAudioWidget,
-}
 
-#[derive(Debug, Getters)]
-pub struct SampleSizeRateCAPsResponse {
-    support_8000hz: bool,
-    support_11025hz: bool,
-    support_16000hz: bool,
-    support_22050hz: bool,
-    support_32000hz: bool,
-    support_44100hz: bool,
-    support_48000hz: bool,
-    support_88200hz: bool,
-    support_96000hz: bool,
-    support_176400hz: bool,
-    support_192000hz: bool,
-    support_384000hz: bool,
-    support_8bit: bool,
-    support_16bit: bool,
-    support_20bit: bool,
-    support_24bit: bool,
-    support_32bit: bool,
-}
-
-impl SampleSizeRateCAPsResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            support_8000hz: response.get_bit(0),
-            support_11025hz: response.get_bit(1),
-            support_16000hz: response.get_bit(2),
-            support_22050hz: response.get_bit(3),
-            support_32000hz: response.get_bit(4),
-            support_44100hz: response.get_bit(5),
-            support_48000hz: response.get_bit(6),
-            support_88200hz: response.get_bit(7),
-            support_96000hz: response.get_bit(8),
-            support_176400hz: response.get_bit(9),
-            support_192000hz: response.get_bit(10),
-            support_384000hz: response.get_bit(11),
-            support_8bit: response.get_bit(16),
-            support_16bit: response.get_bit(17),
-            support_20bit: response.get_bit(18),
-            support_24bit: response.get_bit(19),
-            support_32bit: response.get_bit(20),
+    // Node ids of the widgets this widget can take its input from, with ranges already expanded. The position of a node id
+    // in this list is the index used by the Connection Select and Amplifier Gain/Mute commands.
+    pub fn connection_list(&self) -> &[u8] {
+        match &self.widget_info {
+            WidgetInfoContainer::AudioInputConverter(_, _, _, _, _, _, connection_list) => connection_list,
+            WidgetInfoContainer::PinComplex(_, _, _, _, _, _, _, connection_list) => connection_list,
+            WidgetInfoContainer::Mixer(_, _, _, _, _, connection_list) => connection_list,
+            WidgetInfoContainer::Selector(_, connection_list) => connection_list,
+            _ => &[],
         }
     }
-}
 
-impl TryFrom<Response> for SampleSizeRateCAPsResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::SampleSizeRateCAPs(info) => Ok(info),
-            e => Err(e),
+    fn output_amp_capabilities(&self) -> Option<&AmpCapabilitiesResponse> {
+        match &self.widget_info {
+            WidgetInfoContainer::AudioOutputConverter(_, _, output_amp_caps, _, _) => output_amp_caps.as_ref(),
+            WidgetInfoContainer::PinComplex(_, _, output_amp_caps, _, _, _, _, _) => output_amp_caps.as_ref(),
+            WidgetInfoContainer::Mixer(_, output_amp_caps, _, _, _, _) => output_amp_caps.as_ref(),
+            _ => None,
         }
     }
-}
-
-#[derive(Debug, Getters)]
-pub struct SupportedStreamFormatsResponse {
-    pcm: bool,
-    float32: bool,
-    ac3: bool,
-}
 
-impl SupportedStreamFormatsResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            pcm: response.get_bit(0),
-            float32: response.get_bit(1),
-            ac3: response.get_bit(2),
+    fn input_amp_capabilities(&self) -> Option<&AmpCapabilitiesResponse> {
+        match &self.widget_info {
+            WidgetInfoContainer::AudioInputConverter(_, _, input_amp_caps, _, _, _, _) => input_amp_caps.as_ref(),
+            WidgetInfoContainer::PinComplex(_, input_amp_caps, _, _, _, _, _, _) => input_amp_caps.as_ref(),
+            WidgetInfoContainer::Mixer(input_amp_caps, _, _, _, _, _) => input_amp_caps.as_ref(),
+            _ => None,
         }
     }
 }
 
-impl TryFrom<Response> for SupportedStreamFormatsResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::SupportedStreamFormats(info) => Ok(info),
-            e => Err(e),
-        }
-    }
+// widgets which route the output of an audio output converter back into an audio input converter (see fn find_loopback_path)
+#[derive(Debug, Getters)]
+pub struct LoopbackPath<'a> {
+    output_converter: &'a Widget,
+    mixer: &'a Widget,
+    // index of the output converter in the connection list of the mixer, which is also the index of the mixer's input amp for it
+    output_converter_connection_index: u8,
+    input_converter: &'a Widget,
+    // index of the mixer in the connection list of the input converter
+    mixer_connection_index: u8,
 }
 
+// widgets which mix the signal of an input pin into an output path (see fn find_sidetone_path)
 #[derive(Debug, Getters)]
-pub struct PinCapabilitiesResponse {
-    impedence_sense_capable: bool,
-    trigger_required: bool,
-    presence_detect_capable: bool,
-    headphone_drive_capable: bool,
-    output_capable: bool,
-    input_capable: bool,
-    balanced_io_pins: bool,
-    hdmi: bool,
-    vref_control: u8,
-    eapd_capable: bool,
-    display_port: bool,
-    high_bit_rate: bool,
+pub struct SidetonePath<'a> {
+    // default output path from the output pin up to the mixer, so the pin widget is the first widget and the mixer the last one
+    output_path: Vec<&'a Widget>,
+    mixer: &'a Widget,
+    // index of the input pin or of the input mixer in the connection list of the mixer, which is also the index of its input amp
+    connection_index: u8,
+    // mixer between the input pin and the mixer on the output path with the index of the input pin in its connection list, if any
+    input_mixer: Option<(&'a Widget, u8)>,
 }
 
-impl PinCapabilitiesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            impedence_sense_capable: response.get_bit(0),
-            trigger_required: response.get_bit(1),
-            presence_detect_capable: response.get_bit(2),
-            headphone_drive_capable: response.get_bit(3),
-            output_capable: response.get_bit(4),
-            input_capable: response.get_bit(5),
-            balanced_io_pins: response.get_bit(6),
-            hdmi: response.get_bit(7),
-            vref_control: (response.raw_value >> 8).bitand(0xFF) as u8,
-            eapd_capable: response.get_bit(16),
-            display_port: response.get_bit(24),
-            high_bit_rate: response.get_bit(27),
-        }
-    }
+// output pin with its default path up to the audio output converter (see fn FunctionGroup::output_path_candidates)
+#[derive(Debug)]
+pub struct OutputPathCandidate<'a> {
+    pin_widget: &'a Widget,
+    path: Vec<&'a Widget>,
+    // None for line out pins without association, which come after all associations
+    association: Option<u8>,
 }
 
-impl TryFrom<Response> for PinCapabilitiesResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::PinCapabilities(info) => Ok(info),
-            e => Err(e),
-        }
+impl<'a> OutputPathCandidate<'a> {
+    // the widgets outlive the candidate, as they belong to the function group
+    pub fn pin_widget(&self) -> &'a Widget {
+        self.pin_widget
     }
-}
-
-#[derive(Debug, Getters)]
-pub struct AmpCapabilitiesResponse {
-    offset: u8,
-    num_steps: u8,
-    step_size: u8,
-    mute_capable: bool,
-}
 
-impl AmpCapabilitiesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            offset: response.raw_value.bitand(0b0111_1111) as u8,
-            num_steps: (response.raw_value >> 8).bitand(0b0111_1111) as u8,
-            step_size: (response.raw_value >> 16).bitand(0b0111_1111) as u8,
-            mute_capable: response.get_bit(31),
-        }
+    pub fn path(&self) -> &[&'a Widget] {
+        &self.path
     }
-}
 
-impl TryFrom<Response> for AmpCapabilitiesResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::InputAmpCapabilities(info) => Ok(info),
-            Response::OutputAmpCapabilities(info) => Ok(info),
-            e => Err(e),
-        }
+    pub fn association(&self) -> Option<u8> {
+        self.association
     }
 }
 
+// pins of a function group which share a default association (see fn FunctionGroup::association_groups)
 #[derive(Debug, Getters)]
-pub struct ConnectionListLengthResponse {
-    connection_list_length: u8,
-    long_form: bool,
+pub struct AssociationGroup<'a> {
+    association: u8,
+    // sorted by sequence number, so the first pin is the primary pin of the group (e.g. the front speakers)
+    pin_widgets: Vec<&'a Widget>,
+    // headphone jack which takes over the playback of the group (see HEADPHONE_SEQUENCE)
+    headphone_pin_widget: Option<&'a Widget>,
 }
 
-impl ConnectionListLengthResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            connection_list_length: response.raw_value.bitand(0b0111_1111) as u8,
-            long_form: response.get_bit(7),
-        }
+impl<'a> AssociationGroup<'a> {
+    // a group which only consists of a headphone jack is played through the headphone jack
+    pub fn primary_pin_widget(&self) -> Option<&'a Widget> {
+        self.pin_widgets.first().copied().or(self.headphone_pin_widget)
     }
-}
 
-impl TryFrom<Response> for ConnectionListLengthResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ConnectionListLength(info) => Ok(info),
-            e => Err(e),
-        }
+    pub fn contains(&self, node_id: u8) -> bool {
+        self.pin_widgets.iter().chain(self.headphone_pin_widget.iter())
+            .any(|pin_widget| *pin_widget.address().node_id() == node_id)
     }
 }
 
-#[derive(Debug, Getters)]
-pub struct SupportedPowerStatesResponse {
-    d0_sup: bool,
-    d1_sup: bool,
-    d2_sup: bool,
-    d3_sup: bool,
-    d3cold_sup: bool,
-    s3d3cold_sup: bool,
-    clkstop: bool,
-    epss: bool,
+// Amp capabilities, supported power states and processing capabilities only get read if the audio widget capabilities say that
+// the widget has them (Amp Param Override, Power Cntrl and Proc Widget bit), so they are None otherwise.
+#[derive(Debug)]
+pub enum WidgetInfoContainer {
+    AudioOutputConverter(
+        SampleSizeRateCAPsResponse,
+        SupportedStreamFormatsResponse,
+        Option<AmpCapabilitiesResponse>,
+        Option<SupportedPowerStatesResponse>,
+        Option<ProcessingCapabilitiesResponse>,
+    ),
+    AudioInputConverter(
+        SampleSizeRateCAPsResponse,
+        SupportedStreamFormatsResponse,
+        Option<AmpCapabilitiesResponse>,
+        ConnectionListLengthResponse,
+        Option<SupportedPowerStatesResponse>,
+        Option<ProcessingCapabilitiesResponse>,
+        // complete connection list (see fn Widget::connection_list)
+        Vec<u8>,
+    ),
+    // first AmpCapabilitiesInfo is input amp caps and second AmpCapabilitiesInfo is output amp caps
+    PinComplex(
+        PinCapabilitiesResponse,
+        Option<AmpCapabilitiesResponse>,
+        Option<AmpCapabilitiesResponse>,
+        ConnectionListLengthResponse,
+        Option<SupportedPowerStatesResponse>,
+        Option<ProcessingCapabilitiesResponse>,
+        ConfigurationDefaultResponse,
+        Vec<u8>,
+    ),
+    Mixer(
+        Option<AmpCapabilitiesResponse>,
+        Option<AmpCapabilitiesResponse>,
+        ConnectionListLengthResponse,
+        Option<SupportedPowerStatesResponse>,
+        Option<ProcessingCapabilitiesResponse>,
+        Vec<u8>,
+    ),
+    Selector(
+        ConnectionListLengthResponse,
+        Vec<u8>,
+    ),
+    Power,
+    VolumeKnob(VolumeKnobCapabilitiesResponse),
+    BeepGenerator,
+    VendorDefined,
 }
 
-impl SupportedPowerStatesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            d0_sup: response.get_bit(0),
-            d1_sup: response.get_bit(1),
-            d2_sup: response.get_bit(2),
-            d3_sup: response.get_bit(3),
-            d3cold_sup: response.get_bit(4),
-            s3d3cold_sup: response.get_bit(29),
-            clkstop: response.get_bit(30),
-            epss: response.get_bit(31),
-        }
-    }
-}
 
-impl TryFrom<Response> for SupportedPowerStatesResponse {
-    type Error = Response;
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::SupportedPowerStates(info) => Ok(info),
-            e => Err(e),
-        }
-    }
-}
+// ############################################## command transport ##############################################
 
-#[derive(Debug, Getters)]
-pub struct ProcessingCapabilitiesResponse {
-    benign: bool,
-    num_coeff: u8,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandError {
+    // codec address of the codec which didn't respond in time
+    Timeout(u8),
+    CodecQuarantined(u8),
+    // codec address of the codec which kept answering with responses no working codec gives (see fn Response::is_implausible)
+    InvalidResponse(u8),
 }
 
-impl ProcessingCapabilitiesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            benign: response.get_bit(0),
-            num_coeff: (response.raw_value >> 8).bitand(0xFF) as u8,
-        }
-    }
+// Converts a response into the response type of its verb. The response type only depends on the verb that got sent
+// (see fn Response::new), so this only fails if a response got matched to the wrong command.
+pub fn decode_response<T: TryFrom<Response, Error = Response>>(response: Response, node_address: NodeAddress) -> Result<T, CommandError> {
+    T::try_from(response).map_err(|response| {
+        warn!("IHDA node {:?} answered with unexpected response {:?}", node_address, response);
+        CommandError::InvalidResponse(*node_address.codec_address().codec_address())
+    })
 }
 
-impl TryFrom<Response> for ProcessingCapabilitiesResponse {
-    type Error = Response;
+// Sends verbs to the codecs and returns their responses, no matter whether they travel through the CORB and RIRB or the
+// immediate command registers. The widget graph of a codec gets built and configured only through this trait, so the
+// codec side of the driver doesn't depend on the registers of the controller.
+pub trait CommandTransport {
+    fn try_command(&self, command: Command) -> Result<Response, CommandError>;
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ProcessingCapabilities(info) => Ok(info),
-            e => Err(e),
-        }
+    // Sends a whole set of commands and returns their results in the same order. Transports which can have several commands
+    // in flight (see fn Controller::try_commands) send them at once, which saves most of the waiting while codecs get scanned.
+    fn try_commands(&self, commands: &[Command]) -> Vec<Result<Response, CommandError>> {
+        commands.iter().map(|command| self.try_command(*command)).collect()
     }
-}
 
-#[derive(Debug, Getters)]
-pub struct GPIOCountResponse {
-    num_gpios: u8,
-    num_gpos: u8,
-    num_gpis: u8,
-    gpi_unsol: bool,
-    gpi_wake: bool,
-}
-
-impl GPIOCountResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            num_gpios: response.raw_value.bitand(0xFF) as u8,
-            num_gpos: (response.raw_value >> 8).bitand(0xFF) as u8,
-            num_gpis: (response.raw_value >> 16).bitand(0xFF) as u8,
-            gpi_unsol: response.get_bit(30),
-            gpi_wake: response.get_bit(31),
+    // for verbs whose failure would leave the driver in an unknown state
+    fn command(&self, command: Command) -> Response {
+        match self.try_command(command) {
+            Ok(response) => response,
+            Err(error) => panic!("IHDA command {:?} failed: {:?}", command, error)
         }
     }
-}
 
-impl TryFrom<Response> for GPIOCountResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::GPIOCount(info) => Ok(info),
-            e => Err(e),
-        }
+    fn configure_widget_for_playback(&self, widget: &Widget, stream_id: u8, stream_format: &AudioFormat) {
+        self.configure_widget_for_playback_channels(widget, stream_id, stream_format, 0, *stream_format.number_of_channels());
     }
-}
-
-#[derive(Debug, Getters)]
-pub struct VolumeKnobCapabilitiesResponse {
-    num_steps: u8,
-    delta: bool,
-}
 
-impl VolumeKnobCapabilitiesResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            num_steps: response.raw_value.bitand(0b0111_1111) as u8,
-            delta: response.get_bit(7),
-        }
-    }
-}
+    // An output converter decodes the channels lowest_channel to lowest_channel + converter_channel_count - 1 of its stream,
+    // so that several converters can share a multichannel stream (see section 7.3.3.11 of the specification).
+    // Its stream format still describes the whole stream, as the converter has to know where each frame of the stream ends.
+    fn configure_widget_for_playback_channels(&self, widget: &Widget, stream_id: u8, stream_format: &AudioFormat, lowest_channel: u8, converter_channel_count: u8) {
+        match widget.audio_widget_capabilities().widget_type() {
+            WidgetType::AudioOutput => {
+                // set gain/mute for audio output converter widget (observation: audio output converter widget only owns output amp; mute stays false, no matter what value gets set, but gain reacts to set commands)
+                // careful: the gain register is only 7 bits long (bits [6:0]), so the max gain value is 127; writing higher numbers into the u8 for gain will overwrite the mute bit at position 7
+                // default gain value is 87
+                self.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Both, SetAmplifierGainMuteSide::Both, 0, false, 100)));
 
-impl TryFrom<Response> for VolumeKnobCapabilitiesResponse {
-    type Error = Response;
+                // set stream id and the first channel of the stream the converter decodes
+                self.command(SetChannelStreamId(*widget.address(), SetChannelStreamIdPayload::new(lowest_channel, stream_id)));
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::VolumeKnobCapabilities(info) => Ok(info),
-            e => Err(e),
-        }
-    }
-}
+                // set stream format
+                self.command(SetStreamFormat(*widget.address(), stream_format.to_converter_payload()));
 
-#[derive(Debug, Getters)]
-pub struct ConnectionSelectResponse {
-    currently_set_connection_index: u8,
-}
+                if widget.is_digital() {
+                    self.configure_digital_converter(widget, stream_format, converter_channel_count);
+                }
+            }
+            WidgetType::AudioInput => {}
+            WidgetType::AudioMixer => {
+                self.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, 0, false, 60)));
+            }
+            WidgetType::AudioSelector => {}
+            WidgetType::PinComplex => {
+                // set gain/mute for pin widget (observation: pin widget owns input and output amp; for both, gain stays at 0, no matter what value gets set, but mute reacts to set commands)
+                self.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Both, SetAmplifierGainMuteSide::Both, 0, false, 100)));
+
+                if widget.is_digital_display_pin() {
+                    // compressed formats up to AC3 and DTS fit into native audio sample packets
+                    self.command(SetPinWidgetControl(*widget.address(), SetPinWidgetControlPayload::new_digital(EncodedPacketType::NativePacket, true)));
+                } else {
+                    // activate input and output for pin widget
+                    let pin_widget_control_response = PinWidgetControlResponse::try_from(self.command(GetPinWidgetControl(*widget.address()))).unwrap();
+                    /* after the following command, plugging headphones in and out the jack should make an audible noise */
+                    self.command(SetPinWidgetControl(*widget.address(), SetPinWidgetControlPayload::enable_input_and_output_amps(pin_widget_control_response)));
+                }
 
-impl ConnectionSelectResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            currently_set_connection_index: response.raw_value.bitand(0xFF) as u8,
+                // many machines drive their speakers through an external amplifier, which stays off until EAPD gets asserted
+                if *widget.pin_capabilities().unwrap().eapd_capable() {
+                    if let Err(error) = self.set_eapd(widget, true) {
+                        warn!("Failed to enable EAPD of pin widget {:?}: {:?}", widget.address(), error);
+                    }
+                }
+            }
+            WidgetType::PowerWidget => {}
+            WidgetType::VolumeKnobWidget => {}
+            WidgetType::BeepGeneratorWidget => {}
+            WidgetType::VendorDefinedAudioWidget => {}
+            WidgetType::Reserved(_) => {}
         }
     }
-}
 
-impl TryFrom<Response> for ConnectionSelectResponse {
-    type Error = Response;
+    // Turns on the S/PDIF or HDMI transmitter of a digital converter and marks non-PCM streams as non-audio,
+    // so that the receiver passes them on to its decoder (see section 7.3.3.9 of the specification).
+    fn configure_digital_converter(&self, converter: &Widget, stream_format: &AudioFormat, converter_channel_count: u8) {
+        self.command(SetDigitalConverterControl1(*converter.address(), SetDigitalConverterControl1Payload::enable(*stream_format.stream_type())));
+        // category code 0 means "general", which every receiver accepts
+        self.command(SetDigitalConverterControl2(*converter.address(), SetDigitalConverterControl2Payload::new(0)));
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ConnectionSelect(info) => Ok(info),
-            e => Err(e),
+        // HDMI converters with more than two channels need to know how many channels of the stream they carry
+        if converter.max_number_of_channels() > 2 {
+            self.command(SetConverterChannelCount(*converter.address(), SetConverterChannelCountPayload::new(converter_channel_count - 1)));
         }
     }
-}
-
 
-// temporarily only short form implemented (see section 7.3.3.3 of the specification)
-#[derive(Debug, Getters)]
-pub struct ConnectionListEntryResponse {
-    first_entry: u8,
-    second_entry: u8,
-    third_entry: u8,
-    fourth_entry: u8,
-}
+    fn configure_widget_for_capture(&self, widget: &Widget, stream: &Stream) {
+        match widget.audio_widget_capabilities().widget_type() {
+            WidgetType::AudioInput => {
+                // the audio input converter only owns an input amp
+                self.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, 0, false, 60)));
 
-impl ConnectionListEntryResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            first_entry: response.raw_value.bitand(0xFF) as u8,
-            second_entry: (response.raw_value >> 8).bitand(0xFF) as u8,
-            third_entry: (response.raw_value >> 16).bitand(0xFF) as u8,
-            fourth_entry: (response.raw_value >> 24).bitand(0xFF) as u8,
-        }
-    }
-}
+                // channel number for now hard coded to 0
+                self.command(SetChannelStreamId(*widget.address(), SetChannelStreamIdPayload::new(0, *stream.id())));
 
-impl TryFrom<Response> for ConnectionListEntryResponse {
-    type Error = Response;
+                self.command(SetStreamFormat(*widget.address(), stream.stream_format().to_converter_payload()));
+            }
+            WidgetType::AudioMixer => {
+                self.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, 0, false, 60)));
+            }
+            WidgetType::PinComplex => {
+                // the signal enters the codec through the input amp of the pin widget
+                self.command(SetAmplifierGainMute(*widget.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, 0, false, 60)));
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ConnectionListEntry(info) => Ok(info),
-            e => Err(e),
+                let pin_widget_control_response = PinWidgetControlResponse::try_from(self.command(GetPinWidgetControl(*widget.address()))).unwrap();
+                self.command(SetPinWidgetControl(*widget.address(), SetPinWidgetControlPayload::enable_input_amp(pin_widget_control_response)));
+            }
+            _ => {}
         }
     }
-}
-
-#[derive(Debug, Getters)]
-pub struct AmplifierGainMuteResponse {
-    amplifier_gain: u8,
-    amplifier_mute: bool,
-}
 
-impl AmplifierGainMuteResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            amplifier_gain: (response.raw_value & 0b0111_1111) as u8,
-            amplifier_mute: response.get_bit(7),
+    // configures all widgets on an input path, starting at the audio input converter and ending at the pin widget
+    fn configure_widget_path_for_capture(&self, widgets_on_input_path: &[&Widget], stream: &Stream) {
+        self.select_connections_on_path(widgets_on_input_path);
+        for widget in widgets_on_input_path {
+            self.configure_widget_for_capture(widget, stream);
         }
     }
-}
-
-impl TryFrom<Response> for AmplifierGainMuteResponse {
-    type Error = Response;
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::AmplifierGainMute(info) => Ok(info),
-            e => Err(e),
+    // In both playback and capture paths, each widget takes its input from the widget following it on the path. Widgets with more
+    // than one connection might default to another input, so the connection to the next widget gets selected explicitly.
+    // Mixers sum up all of their inputs and have no connection select control (see section 7.3.3.2 of the specification).
+    fn select_connections_on_path(&self, widgets_on_path: &[&Widget]) {
+        for pair in widgets_on_path.windows(2) {
+            let (widget, upstream_widget) = (pair[0], pair[1]);
+            if widget.connection_list().len() < 2 || matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::AudioMixer) {
+                continue;
+            }
+            let connection_index = match widget.connection_list().iter().position(|node_id| node_id == upstream_widget.address().node_id()) {
+                Some(index) => index as u8,
+                None => panic!("Widget {:?} on path has no connection to widget {:?}", widget.address(), upstream_widget.address()),
+            };
+
+            self.command(SetConnectionSelect(*widget.address(), SetConnectionSelectPayload::new(connection_index)));
+            let selected = ConnectionSelectResponse::try_from(self.command(GetConnectionSelect(*widget.address()))).unwrap();
+            if *selected.currently_set_connection_index() != connection_index {
+                warn!("Widget {:?} selected connection {} instead of connection {} to widget {:?}",
+                    widget.address(), selected.currently_set_connection_index(), connection_index, upstream_widget.address());
+            }
         }
     }
-}
 
-#[derive(Debug, Getters)]
-pub struct StreamFormatResponse {
-    number_of_channels: u8,
-    bits_per_sample: BitsPerSample,
-    sample_base_rate_divisor: u8,
-    sample_base_rate_multiple: u8,
-    sample_base_rate: u16,
-    stream_type: StreamType,
-}
+    // Lets the input converter of a loopback path capture what its output converter plays. Only the input amp of the mixer
+    // which belongs to the output converter gets unmuted, so that no signal of a pin widget ends up in the recording.
+    fn configure_loopback_path(&self, loopback_path: &LoopbackPath, output_stream: &Stream, input_stream: &Stream) {
+        self.configure_widget_for_playback(loopback_path.output_converter(), *output_stream.id(), output_stream.stream_format());
 
-impl StreamFormatResponse {
-    pub fn new(response: RawResponse) -> Self {
-        let sample_base_rate_multiple = (response.raw_value >> 11).bitand(0b111) as u8 + 1;
-        if sample_base_rate_multiple > 4 {
-            panic!("Unsupported sample rate base multiple, see table 53 in section 3.7.1: Stream Format Structure of the specification");
-        }
-        let number_of_channels = (response.raw_value.bitand(0xF) as u8) + 1;
-        let bits_per_sample = match (response.raw_value >> 4).bitand(0b111) {
-            0b000 => BitsPerSample::Eight,
-            0b001 => BitsPerSample::Sixteen,
-            0b010 => BitsPerSample::Twenty,
-            0b011 => BitsPerSample::Twentyfour,
-            0b100 => BitsPerSample::Thirtytwo,
-            // 0b101 to 0b111 reserved
-            _ => panic!("Unsupported bit depth, see table 53 in section 3.7.1: Stream Format Structure of the specification")
-        };
-        let sample_base_rate_divisor = (response.raw_value >> 8).bitand(0b111) as u8 + 1;
-        let sample_base_rate = if response.get_bit(14) { 44100 } else { 48000 };
-        let stream_type = if response.get_bit(15) { StreamType::NonPCM } else { StreamType::PCM };
-
-        Self {
-            number_of_channels,
-            bits_per_sample,
-            sample_base_rate_divisor,
-            sample_base_rate_multiple,
-            sample_base_rate,
-            stream_type
+        let mixer_address = *loopback_path.mixer().address();
+        for index in 0..loopback_path.mixer().connection_list().len() as u8 {
+            let mute = index != *loopback_path.output_converter_connection_index();
+            self.command(SetAmplifierGainMute(mixer_address, SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, index, mute, 60)));
         }
-    }
-}
-
-impl TryFrom<Response> for StreamFormatResponse {
-    type Error = Response;
+        self.command(SetAmplifierGainMute(mixer_address, SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Both, 0, false, 60)));
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::StreamFormat(info) => Ok(info),
-            e => Err(e),
-        }
+        let input_converter_address = *loopback_path.input_converter().address();
+        self.command(SetConnectionSelect(input_converter_address, SetConnectionSelectPayload::new(*loopback_path.mixer_connection_index())));
+        self.configure_widget_for_capture(loopback_path.input_converter(), input_stream);
     }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum BitsPerSample {
-    Eight,
-    Sixteen,
-    Twenty,
-    Twentyfour,
-    Thirtytwo,
-}
 
-#[derive(Clone, Copy, Debug)]
-pub enum StreamType {
-    PCM,
-    NonPCM,
-}
-
-#[derive(Debug, Getters)]
-pub struct ChannelStreamIdResponse {
-    channel: u8,
-    stream: u8,
-}
-
-impl ChannelStreamIdResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            channel: response.raw_value.bitand(0xF) as u8,
-            stream: (response.raw_value >> 4).bitand(0xF) as u8,
+    // Mixes the signal of the input pin of a sidetone path into its output path with the given gain of the mixer input, or mutes
+    // it again if gain is None. Only the inputs of the path get changed, so a stream playing on the output path keeps its own
+    // mixer input. The output pin gets enabled, as the sidetone has to be audible even while nothing gets played.
+    fn configure_sidetone_path(&self, sidetone_path: &SidetonePath, gain: Option<u8>, input_mixer_gain: u8) {
+        let mute = gain.is_none();
+        if let Some((input_mixer, input_pin_connection_index)) = sidetone_path.input_mixer() {
+            self.command(SetAmplifierGainMute(*input_mixer.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, *input_pin_connection_index, mute, input_mixer_gain)));
         }
-    }
-}
-
-impl TryFrom<Response> for ChannelStreamIdResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ChannelStreamId(info) => Ok(info),
-            e => Err(e),
+        self.command(SetAmplifierGainMute(*sidetone_path.mixer().address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, *sidetone_path.connection_index(), mute, gain.unwrap_or(0))));
+        if mute {
+            return;
         }
-    }
-}
 
-#[derive(Debug, Getters)]
-pub struct PinWidgetControlResponse {
-    // Voltage Reference Enable applies only to non-digital pin widgets (see section 7.3.3.13 of the specification)
-    // for digital pin widgets (e.g. HDMI and Display Port), the same bits represent Encoded Packet Type instead
-    // but a case distinction is not implemented yet so this code will fail for digital pin widgets
-    voltage_reference_enable: VoltageReferenceSignalLevel,
-    in_enable: bool,
-    out_enable: bool,
-    h_phn_enable: bool,
-}
-
-impl PinWidgetControlResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            voltage_reference_enable: match response.raw_value.bitand(0b111) {
-                0b000 => VoltageReferenceSignalLevel::HiZ,
-                0b001 => VoltageReferenceSignalLevel::FiftyPercent,
-                0b010 => VoltageReferenceSignalLevel::Ground0V,
-                // 0b010 reserved
-                0b100 => VoltageReferenceSignalLevel::EightyPercent,
-                0b101 => VoltageReferenceSignalLevel::HundredPercent,
-                // 0b110 and 0b111 reserved
-                _ => panic!("Unsupported type of voltage reference signal level")
-            },
-            in_enable: response.get_bit(5),
-            out_enable: response.get_bit(6),
-            h_phn_enable: response.get_bit(7),
-        }
+        self.select_connections_on_path(sidetone_path.output_path());
+        let output_pin = sidetone_path.output_path()[0];
+        self.command(SetAmplifierGainMute(*output_pin.address(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Both, 0, false, 100)));
+        let pin_widget_control_response = PinWidgetControlResponse::try_from(self.command(GetPinWidgetControl(*output_pin.address()))).unwrap();
+        self.command(SetPinWidgetControl(*output_pin.address(), SetPinWidgetControlPayload::enable_input_and_output_amps(pin_widget_control_response)));
     }
-}
-
-impl TryFrom<Response> for PinWidgetControlResponse {
-    type Error = Response;
 
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::PinWidgetControl(info) => Ok(info),
-            e => Err(e),
-        }
+    // configures all widgets on an output path, starting at the pin widget and ending at the audio output converter
+    fn configure_widget_path_for_playback(&self, widgets_on_output_path: &[&Widget], stream: &Stream) {
+        self.route_stream_to_widget_path(widgets_on_output_path, *stream.id(), stream.stream_format());
     }
-}
 
-#[derive(Clone, Copy, Debug)]
-pub enum VoltageReferenceSignalLevel {
-    HiZ,
-    FiftyPercent,
-    Ground0V,
-    EightyPercent,
-    HundredPercent,
-}
-
-#[derive(Debug, Getters)]
-pub struct EAPDBTLEnableResponse {
-    btl_enable: bool,
-    eapd_enable: bool,
-    lr_swap: bool,
-}
+    // same as fn configure_widget_path_for_playback, but only needs the id and format of a stream that is already running,
+    // e.g. to move it to another pin widget after a jack event
+    fn route_stream_to_widget_path(&self, widgets_on_output_path: &[&Widget], stream_id: u8, stream_format: &AudioFormat) {
+        self.route_stream_channels_to_widget_path(widgets_on_output_path, stream_id, stream_format, 0, *stream_format.number_of_channels());
+    }
 
-impl EAPDBTLEnableResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            btl_enable: response.get_bit(0),
-            eapd_enable: response.get_bit(1),
-            lr_swap: response.get_bit(2),
+    // same as fn route_stream_to_widget_path, but the converter of the path only plays some channels of the stream
+    fn route_stream_channels_to_widget_path(&self, widgets_on_output_path: &[&Widget], stream_id: u8, stream_format: &AudioFormat, lowest_channel: u8, converter_channel_count: u8) {
+        self.select_connections_on_path(widgets_on_output_path);
+        for widget in widgets_on_output_path {
+            self.configure_widget_for_playback_channels(widget, stream_id, stream_format, lowest_channel, converter_channel_count);
         }
     }
-}
 
-impl TryFrom<Response> for EAPDBTLEnableResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::EAPDBTLEnable(info) => Ok(info),
-            e => Err(e),
+    // Requests a power state for a function group or widget and waits until the node has actually reached it.
+    // Widgets follow the power state of their function group, unless it's lower than their own setting (see specification, section 7.3.3.10).
+    fn set_power_state(&self, node_address: NodeAddress, power_state: PowerState) -> PowerStateResponse {
+        match self.try_set_power_state(node_address, power_state) {
+            Ok(response) => response,
+            Err(error) => panic!("IHDA node {:?} failed to change to power state {:?}: {:?}", node_address, power_state, error)
         }
     }
-}
 
-#[derive(Debug, Getters)]
-pub struct ConfigurationDefaultResponse {
-    sequence: u8,
-    default_association: u8,
-    jack_detect_override: bool,
-    color: ConfigDefColor,
-    connection_type: ConfigDefConnectionType,
-    default_device: ConfigDefDefaultDevice,
-    geometric_location: ConfigDefGeometricLocation,
-    gross_location: ConfigDefGrossLocation,
-    port_connectivity: ConfigDefPortConnectivity,
-}
-
-impl ConfigurationDefaultResponse {
-    pub fn new(response: RawResponse) -> Self {
-        let gross_location = match (response.raw_value >> 28).bitand(0b11) {
-            0b00 => ConfigDefGrossLocation::ExternalOnPrimaryChassis,
-            0b01 => ConfigDefGrossLocation::Internal,
-            0b10 => ConfigDefGrossLocation::SeparateChassis,
-            0b11 => ConfigDefGrossLocation::Other,
-            _ => panic!("This arm can never be reached as all cases are covered")
-        };
+    // same as fn set_power_state, but fails instead of panicking if the node doesn't answer, e.g. while the codec gets scanned
+    fn try_set_power_state(&self, node_address: NodeAddress, power_state: PowerState) -> Result<PowerStateResponse, CommandError> {
+        self.try_command(SetPowerState(node_address, SetPowerStatePayload::new(power_state)))?;
 
-        Self {
-            sequence: response.raw_value.bitand(0xF) as u8,
-            default_association: (response.raw_value >> 4).bitand(0xF) as u8,
-            jack_detect_override: response.get_bit(8),
-            color: match (response.raw_value >> 12).bitand(0xF) {
-                0x0 => ConfigDefColor::Unknown,
-                0x1 => ConfigDefColor::Black,
-                0x2 => ConfigDefColor::Grey,
-                0x3 => ConfigDefColor::Blue,
-                0x4 => ConfigDefColor::Green,
-                0x5 => ConfigDefColor::Red,
-                0x6 => ConfigDefColor::Orange,
-                0x7 => ConfigDefColor::Yellow,
-                0x8 => ConfigDefColor::Purple,
-                0x9 => ConfigDefColor::Pink,
-                // 0xA to 0xD are reserved
-                0xE => ConfigDefColor::White,
-                0xF => ConfigDefColor::Other,
-
-                // I first threw a panic here but the pyhsical sound card in my testing device returned the reserved value 0xC...
-                _ => ConfigDefColor::Unknown,
-            },
-            connection_type: match (response.raw_value >> 16).bitand(0xF) {
-                0x0 => ConfigDefConnectionType::Unknown,
-                0x1 => ConfigDefConnectionType::EighthInchStereoMono,
-                0x2 => ConfigDefConnectionType::QuarterInchStereoMono,
-                0x3 => ConfigDefConnectionType::ATAPIInternal,
-                0x4 => ConfigDefConnectionType::RCA,
-                0x5 => ConfigDefConnectionType::Optical,
-                0x6 => ConfigDefConnectionType::OtherDigital,
-                0x7 => ConfigDefConnectionType::OtherAnalog,
-                0x8 => ConfigDefConnectionType::MultichannelAnalogDIN,
-                0x9 => ConfigDefConnectionType::XLRProfessional,
-                0xA => ConfigDefConnectionType::RJ11Modem,
-                0xB => ConfigDefConnectionType::Combination,
-                // 0xC to 0xE are not defined in specification
-                0xF => ConfigDefConnectionType::Other,
-                _ => panic!("Unsupported connection type")
-            },
-            default_device: match (response.raw_value >> 20).bitand(0xF) {
-                0x0 => ConfigDefDefaultDevice::LineOut,
-                0x1 => ConfigDefDefaultDevice::Speaker,
-                0x2 => ConfigDefDefaultDevice::HPOut,
-                0x3 => ConfigDefDefaultDevice::CD,
-                0x4 => ConfigDefDefaultDevice::SPDIFOut,
-                0x5 => ConfigDefDefaultDevice::DigitalOtherOut,
-                0x6 => ConfigDefDefaultDevice::ModemLineSide,
-                0x7 => ConfigDefDefaultDevice::ModemHandsetSide,
-                0x8 => ConfigDefDefaultDevice::LineIn,
-                0x9 => ConfigDefDefaultDevice::AUX,
-                0xA => ConfigDefDefaultDevice::MicIn,
-                0xB => ConfigDefDefaultDevice::Telephony,
-                0xC => ConfigDefDefaultDevice::SPDIFIn,
-                0xD => ConfigDefDefaultDevice::DigitalOtherIn,
-                // 0xE is reserved
-                0xF => ConfigDefDefaultDevice::Other,
-                _ => panic!("Unsupported Type of Default Device")
-            },
-            geometric_location: match (response.raw_value >> 24).bitand(0xF) {
-                0x0 => ConfigDefGeometricLocation::NotAvailable,
-                0x1 => ConfigDefGeometricLocation::Rear,
-                0x2 => ConfigDefGeometricLocation::Front,
-                0x3 => ConfigDefGeometricLocation::Left,
-                0x4 => ConfigDefGeometricLocation::Right,
-                0x5 => ConfigDefGeometricLocation::Top,
-                0x6 => ConfigDefGeometricLocation::Bottom,
-                0x7 => match gross_location {
-                    ConfigDefGrossLocation::ExternalOnPrimaryChassis => ConfigDefGeometricLocation::RearPanel,
-                    ConfigDefGrossLocation::Internal => ConfigDefGeometricLocation::Riser,
-                    ConfigDefGrossLocation::Other => ConfigDefGeometricLocation::MobileLidInside,
-                    _ => panic!("Unsupported type of geometric location")
-                },
-                0x8 => match gross_location {
-                    ConfigDefGrossLocation::ExternalOnPrimaryChassis => ConfigDefGeometricLocation::DriveBay,
-                    ConfigDefGrossLocation::Internal => ConfigDefGeometricLocation::DigitalDisplay,
-                    ConfigDefGrossLocation::Other => ConfigDefGeometricLocation::MobileLidOutside,
-                    _ => panic!("Unsupported type of geometric location")
+        let mut response: PowerStateResponse = decode_response(self.try_command(GetPowerState(node_address))?, node_address)?;
+        let mut command_error = None;
+        let result = wait_for(|| {
+            match self.try_command(GetPowerState(node_address)).and_then(|response| decode_response::<PowerStateResponse>(response, node_address)) {
+                Ok(power_state_response) => {
+                    response = power_state_response;
+                    *response.actual() == power_state || *response.error()
                 }
-                0x9 => match gross_location {
-                    ConfigDefGrossLocation::Internal => ConfigDefGeometricLocation::ATAPI,
-                    _ => panic!("Unsupported type of geometric location")
+                Err(error) => {
+                    command_error = Some(error);
+                    true
                 }
-                _ => panic!("Unsupported type of geometric location")
-            },
-            gross_location,
-            port_connectivity: match (response.raw_value >> 30).bitand(0b11) {
-                0b00 => ConfigDefPortConnectivity::Jack,
-                0b01 => ConfigDefPortConnectivity::NoPhysicalConnection,
-                0b10 => ConfigDefPortConnectivity::InternalDevice,
-                0b11 => ConfigDefPortConnectivity::JackAndInternalDevice,
-                _ => panic!("This arm can never be reached as all cases are covered")
-            },
-        }
-    }
-}
-
-impl TryFrom<Response> for ConfigurationDefaultResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ConfigurationDefault(info) => Ok(info),
-            e => Err(e),
-        }
-    }
-}
-
-#[derive(Debug)]
-pub enum ConfigDefPortConnectivity {
-    Jack,
-    NoPhysicalConnection,
-    InternalDevice,
-    JackAndInternalDevice,
-}
-
-#[derive(Debug)]
-pub enum ConfigDefGrossLocation {
-    ExternalOnPrimaryChassis,
-    Internal,
-    SeparateChassis,
-    Other,
-}
-
-#[derive(Debug)]
-pub enum ConfigDefGeometricLocation {
-    NotAvailable,
-    Rear,
-    Front,
-    Left,
-    Right,
-    Top,
-    Bottom,
-    RearPanel,
-    Riser,
-    MobileLidInside,
-    DriveBay,
-    DigitalDisplay,
-    MobileLidOutside,
-    ATAPI,
-    //Specials of table 110 in section 7.3.3.31 not implemented
-}
-
-#[derive(Debug)]
-pub enum ConfigDefDefaultDevice {
-    LineOut,
-    Speaker,
-    HPOut,
-    CD,
-    SPDIFOut,
-    DigitalOtherOut,
-    ModemLineSide,
-    ModemHandsetSide,
-    LineIn,
-    AUX,
-    MicIn,
-    Telephony,
-    SPDIFIn,
-    DigitalOtherIn,
-    Other,
-}
-
-#[derive(Debug)]
-pub enum ConfigDefConnectionType {
-    Unknown,
-    EighthInchStereoMono,
-    QuarterInchStereoMono,
-    ATAPIInternal,
-    RCA,
-    Optical,
-    OtherDigital,
-    OtherAnalog,
-    MultichannelAnalogDIN,
-    XLRProfessional,
-    RJ11Modem,
-    Combination,
-    Other,
-}
-
-#[derive(Debug)]
-pub enum ConfigDefColor {
-    Unknown,
-    Black,
-    Grey,
-    Blue,
-    Green,
-    Red,
-    Orange,
-    Yellow,
-    Purple,
-    Pink,
-    White,
-    Other
-}
-
-#[derive(Debug, Getters)]
-pub struct ConverterChannelCountResponse {
-    converter_channel_count: u8,
-}
-
-impl ConverterChannelCountResponse {
-    pub fn new(response: RawResponse) -> Self {
-        Self {
-            converter_channel_count: response.raw_value.bitand(0xFF) as u8,
+            }
+        }, POWER_STATE_TRANSITION_TIMEOUT_IN_MS);
+        if let Some(error) = command_error {
+            return Err(error);
+        }
+        if result.is_err() {
+            warn!("IHDA node {:?} didn't reach power state {:?} in time and is still in {:?}", node_address, power_state, response.actual());
+        }
+        Ok(response)
+    }
+
+    // Turns a pin widget into another kind of endpoint, e.g. a front mic jack into a headphone output. The default device in the
+    // configuration default gets rewritten, so that the widget graph of the codec shows the new role after the codec has been
+    // scanned again, and the pin widget control enables the amp of the new direction. Microphones get the highest bias
+    // voltage the pin supports up to 80 %, which is what most electret microphones need.
+    fn retask_pin_widget(&self, pin_widget: &Widget, default_device: &ConfigDefDefaultDevice) -> Result<(), CommandError> {
+        let config_default = pin_widget.configuration_default().unwrap();
+        let pin_capabilities = pin_widget.pin_capabilities().unwrap();
+        self.try_command(SetConfigurationDefault2(*pin_widget.address(), SetConfigurationDefault2Payload::new(default_device, config_default.connection_type())))?;
+
+        let payload = match default_device {
+            ConfigDefDefaultDevice::MicIn => {
+                let voltage_reference = [VoltageReferenceSignalLevel::EightyPercent, VoltageReferenceSignalLevel::FiftyPercent]
+                    .into_iter()
+                    .find(|voltage_reference| pin_capabilities.supports_voltage_reference(*voltage_reference))
+                    .unwrap_or(VoltageReferenceSignalLevel::HiZ);
+                SetPinWidgetControlPayload::new(voltage_reference, true, false, false)
+            }
+            ConfigDefDefaultDevice::LineIn => SetPinWidgetControlPayload::new(VoltageReferenceSignalLevel::HiZ, true, false, false),
+            ConfigDefDefaultDevice::HPOut => SetPinWidgetControlPayload::new(VoltageReferenceSignalLevel::HiZ, false, true, *pin_capabilities.headphone_drive_capable()),
+            _ => SetPinWidgetControlPayload::new(VoltageReferenceSignalLevel::HiZ, false, true, false),
+        };
+        self.try_command(SetPinWidgetControl(*pin_widget.address(), payload))?;
+        Ok(())
+    }
+
+    // Reads consecutive processing coefficients of a widget, starting at first_index. The codec increments the coefficient index
+    // after each Get Processing Coefficient command, so the index only has to be set once (see specification, section 7.3.3.8).
+    fn read_processing_coefficients(&self, widget: &Widget, first_index: u16, amount: u16) -> Result<Vec<u16>, CommandError> {
+        self.try_command(SetCoefficientIndex(*widget.address(), SetCoefficientIndexPayload::new(first_index)))?;
+        (0..amount)
+            .map(|_| decode_response::<ProcessingCoefficientResponse>(self.try_command(GetProcessingCoefficient(*widget.address()))?, *widget.address())
+                .map(|response| *response.coefficient()))
+            .collect()
+    }
+
+    // same as fn read_processing_coefficients, but the other way round
+    fn write_processing_coefficients(&self, widget: &Widget, first_index: u16, coefficients: &[u16]) -> Result<(), CommandError> {
+        self.try_command(SetCoefficientIndex(*widget.address(), SetCoefficientIndexPayload::new(first_index)))?;
+        for coefficient in coefficients {
+            self.try_command(SetProcessingCoefficient(*widget.address(), SetProcessingCoefficientPayload::new(*coefficient)))?;
+        }
+        Ok(())
+    }
+
+    // Sets the EAPD pin of a pin widget, which controls an external amplifier (see specification, section 7.3.3.16).
+    // The BTL and L-R swap bits keep their values.
+    fn set_eapd(&self, pin_widget: &Widget, enable: bool) -> Result<(), CommandError> {
+        if !pin_widget.pin_capabilities().is_some_and(|pin_capabilities| *pin_capabilities.eapd_capable()) {
+            panic!("Widget {:?} has no EAPD pin", pin_widget.address());
+        }
+        let current: EAPDBTLEnableResponse = decode_response(self.try_command(GetEAPDBTLEnable(*pin_widget.address()))?, *pin_widget.address())?;
+        self.try_command(SetEAPDBTLEnable(*pin_widget.address(), SetEAPDBTLEnablePayload::new(*current.btl_enable(), enable, *current.lr_swap())))?;
+        Ok(())
+    }
+
+    fn eapd_enabled(&self, pin_widget: &Widget) -> Result<bool, CommandError> {
+        let current: EAPDBTLEnableResponse = decode_response(self.try_command(GetEAPDBTLEnable(*pin_widget.address()))?, *pin_widget.address())?;
+        Ok(*current.eapd_enable())
+    }
+
+    // mutes the output of a pin widget that no longer carries a stream, while the rest of its former path stays configured
+    fn disable_pin_output(&self, pin_widget_address: NodeAddress) -> Result<(), CommandError> {
+        let pin_widget_control_response: PinWidgetControlResponse = decode_response(self.try_command(GetPinWidgetControl(pin_widget_address))?, pin_widget_address)?;
+        self.try_command(SetPinWidgetControl(pin_widget_address, SetPinWidgetControlPayload::disable_output_amp(pin_widget_control_response)))?;
+        Ok(())
+    }
+}
+
+fn scan_codec_for_available_function_groups(transport: &impl CommandTransport, root_node_addr: NodeAddress) -> Result<Vec<FunctionGroup>, CommandError> {
+    let mut function_groups: Vec<FunctionGroup> = Vec::new();
+
+    let subordinate_node_count: SubordinateNodeCountResponse = read_parameter(transport, root_node_addr, SubordinateNodeCount)?;
+    for node_id in *subordinate_node_count.starting_node_number()..(*subordinate_node_count.starting_node_number() + *subordinate_node_count.total_number_of_nodes()) {
+        let function_group_node_address = NodeAddress::new(*root_node_addr.codec_address(), node_id);
+        let mut parameters = read_parameters(transport, function_group_node_address, &[FunctionGroupType, GPIOCount])?;
+        let function_group_type: FunctionGroupTypeResponse = parameters.take(FunctionGroupType)?;
+        let gpio_count = parameters.take(GPIOCount)?;
+        if !matches!(function_group_type.node_type(), FunctionGroupTypeEnum::AudioFunctionGroup) {
+            // the audio parameters and widgets of other function groups aren't defined by the specification
+            warn!("Function group {:?} is no audio function group, but {:?}", function_group_node_address, function_group_type.node_type());
+            function_groups.push(FunctionGroup::new(
+                function_group_node_address,
+                function_group_type,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                gpio_count,
+                Vec::new()));
+            continue;
+        }
+
+        // the pin configuration and the presence detect bits are only reliable while the function group and its widgets are in D0
+        power_up_function_group(transport, function_group_node_address)?;
+
+        let mut parameters = read_parameters(transport, function_group_node_address, &[
+            AudioFunctionGroupCapabilities, SampleSizeRateCAPs, SupportedStreamFormats, InputAmpCapabilities, OutputAmpCapabilities, SupportedPowerStates,
+        ])?;
+        let audio_function_group_caps = parameters.take(AudioFunctionGroupCapabilities)?;
+        let sample_size_rate_caps = parameters.take::<SampleSizeRateCAPsResponse>(SampleSizeRateCAPs)?;
+        let supported_stream_formats = parameters.take::<SupportedStreamFormatsResponse>(SupportedStreamFormats)?;
+        let input_amp_caps = parameters.take::<AmpCapabilitiesResponse>(InputAmpCapabilities)?;
+        let output_amp_caps = parameters.take::<AmpCapabilitiesResponse>(OutputAmpCapabilities)?;
+        let supported_power_states = parameters.take::<SupportedPowerStatesResponse>(SupportedPowerStates)?;
+
+        let widgets = scan_function_group_for_available_widgets(transport, function_group_node_address)?;
+
+        function_groups.push(FunctionGroup::new(
+            function_group_node_address,
+            function_group_type,
+            Some(audio_function_group_caps),
+            Some(sample_size_rate_caps).filter(|caps| !caps.is_empty()),
+            Some(supported_stream_formats).filter(|formats| !formats.is_empty()),
+            Some(input_amp_caps).filter(|caps| !caps.is_empty()),
+            Some(output_amp_caps).filter(|caps| !caps.is_empty()),
+            Some(supported_power_states).filter(|power_states| !power_states.is_empty()),
+            gpio_count,
+            widgets));
+    }
+    Ok(function_groups)
+}
+
+// Brings an audio function group and all of its widgets with power control into D0 before they get scanned, as a codec might
+// come out of reset in a lower power state. Widgets can't be in a higher power state than their function group, so the function
+// group wakes up first (see specification, section 7.3.3.10). Widgets which don't respond in time are left to the widget scan.
+fn power_up_function_group(transport: &impl CommandTransport, fg_address: NodeAddress) -> Result<(), CommandError> {
+    transport.try_set_power_state(fg_address, PowerState::D0)?;
+
+    for (widget_address, capabilities) in read_widget_capabilities(transport, fg_address)? {
+        let result = capabilities
+            .and_then(|capabilities| if *capabilities.power_cntrl() {
+                transport.try_set_power_state(widget_address, PowerState::D0).map(|_| ())
+            } else {
+                Ok(())
+            });
+        match result {
+            Ok(()) => {}
+            Err(CommandError::Timeout(_) | CommandError::InvalidResponse(_)) => warn!("Widget {:?} didn't respond properly while powering up", widget_address),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Timer::wait(POWER_UP_SETTLE_TIME_IN_MS);
+    Ok(())
+}
+
+// A widget which doesn't respond in time gets left out, so that the rest of the codec can still be used.
+// Only if the whole codec got quarantined, scanning stops.
+fn scan_function_group_for_available_widgets(transport: &impl CommandTransport, fg_address: NodeAddress) -> Result<Vec<Widget>, CommandError> {
+    let mut widgets: Vec<Widget> = Vec::new();
+
+    for (widget_address, capabilities) in read_widget_capabilities(transport, fg_address)? {
+        match capabilities.and_then(|capabilities| scan_widget(transport, widget_address, capabilities)) {
+            Ok(Some(widget)) => widgets.push(widget),
+            Ok(None) => {}
+            Err(CommandError::Timeout(_) | CommandError::InvalidResponse(_)) => warn!("Widget {:?} didn't respond properly while scanning, leaving it out", widget_address),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(widgets)
+}
+
+// All parameters of a widget get read in one batch (see fn CommandTransport::try_commands). Returns None for widgets of
+// reserved types, as their parameters are unknown.
+fn scan_widget(transport: &impl CommandTransport, widget_address: NodeAddress, audio_widget_capabilities_info: AudioWidgetCapabilitiesResponse) -> Result<Option<Widget>, CommandError> {
+    // amp capabilities of widgets without the Amp Param Override bit are the ones of their function group
+    let amp_param_override = *audio_widget_capabilities_info.amp_param_override();
+    let read_input_amp_caps = amp_param_override && *audio_widget_capabilities_info.in_amp_present();
+    let read_output_amp_caps = amp_param_override && *audio_widget_capabilities_info.out_amp_present();
+    let read_power_states = *audio_widget_capabilities_info.power_cntrl();
+    let read_processing_caps = *audio_widget_capabilities_info.proc_widget();
+
+    let (required_parameters, input_amp, output_amp, power_and_processing): (&[Parameter], bool, bool, bool) = match audio_widget_capabilities_info.widget_type() {
+        WidgetType::AudioOutput => (&[SampleSizeRateCAPs, SupportedStreamFormats], false, true, true),
+        WidgetType::AudioInput => (&[ConnectionListLength, SampleSizeRateCAPs, SupportedStreamFormats], true, false, true),
+        WidgetType::AudioMixer => (&[ConnectionListLength], true, true, true),
+        WidgetType::AudioSelector => (&[ConnectionListLength], false, false, false),
+        WidgetType::PinComplex => (&[ConnectionListLength, PinCapabilities], true, true, true),
+        WidgetType::VolumeKnobWidget => (&[VolumeKnobCapabilities], false, false, false),
+        _ => (&[], false, false, false),
+    };
+    // only parameters the widget has get read, as some codecs don't answer queries for parameters they don't have
+    let optional_parameters = [
+        (InputAmpCapabilities, input_amp && read_input_amp_caps),
+        (OutputAmpCapabilities, output_amp && read_output_amp_caps),
+        (SupportedPowerStates, power_and_processing && read_power_states),
+        (ProcessingCapabilities, power_and_processing && read_processing_caps),
+    ];
+    let parameters: Vec<Parameter> = required_parameters.iter().copied()
+        .chain(optional_parameters.iter().filter(|(_, present)| *present).map(|(parameter, _)| *parameter))
+        .collect();
+    let mut parameters = read_parameters(transport, widget_address, &parameters)?;
+
+    let widget_info = match audio_widget_capabilities_info.widget_type() {
+        WidgetType::AudioOutput => {
+            WidgetInfoContainer::AudioOutputConverter(
+                parameters.take(SampleSizeRateCAPs)?,
+                parameters.take(SupportedStreamFormats)?,
+                parameters.take_optional(OutputAmpCapabilities)?,
+                parameters.take_optional(SupportedPowerStates)?,
+                parameters.take_optional(ProcessingCapabilities)?,
+            )
+        }
+        WidgetType::AudioInput => {
+            let connection_list_length = parameters.take(ConnectionListLength)?;
+            let connection_list = read_connection_list(transport, widget_address, &connection_list_length)?;
+            WidgetInfoContainer::AudioInputConverter(
+                parameters.take(SampleSizeRateCAPs)?,
+                parameters.take(SupportedStreamFormats)?,
+                parameters.take_optional(InputAmpCapabilities)?,
+                connection_list_length,
+                parameters.take_optional(SupportedPowerStates)?,
+                parameters.take_optional(ProcessingCapabilities)?,
+                connection_list,
+            )
+        }
+        WidgetType::AudioMixer => {
+            let connection_list_length = parameters.take(ConnectionListLength)?;
+            let connection_list = read_connection_list(transport, widget_address, &connection_list_length)?;
+            WidgetInfoContainer::Mixer(
+                parameters.take_optional(InputAmpCapabilities)?,
+                parameters.take_optional(OutputAmpCapabilities)?,
+                connection_list_length,
+                parameters.take_optional(SupportedPowerStates)?,
+                parameters.take_optional(ProcessingCapabilities)?,
+                connection_list,
+            )
+        }
+        WidgetType::AudioSelector => {
+            let connection_list_length = parameters.take(ConnectionListLength)?;
+            let connection_list = read_connection_list(transport, widget_address, &connection_list_length)?;
+            WidgetInfoContainer::Selector(connection_list_length, connection_list)
+        }
+
+        WidgetType::PinComplex => {
+            let connection_list_length = parameters.take(ConnectionListLength)?;
+            let connection_list = read_connection_list(transport, widget_address, &connection_list_length)?;
+            WidgetInfoContainer::PinComplex(
+                parameters.take(PinCapabilities)?,
+                parameters.take_optional(InputAmpCapabilities)?,
+                parameters.take_optional(OutputAmpCapabilities)?,
+                connection_list_length,
+                parameters.take_optional(SupportedPowerStates)?,
+                parameters.take_optional(ProcessingCapabilities)?,
+                decode_response(transport.try_command(GetConfigurationDefault(widget_address))?, widget_address)?,
+                connection_list,
+            )
+        }
+        WidgetType::PowerWidget => WidgetInfoContainer::Power,
+        WidgetType::VolumeKnobWidget => WidgetInfoContainer::VolumeKnob(parameters.take(VolumeKnobCapabilities)?),
+        WidgetType::BeepGeneratorWidget => WidgetInfoContainer::BeepGenerator,
+        WidgetType::VendorDefinedAudioWidget => WidgetInfoContainer::VendorDefined,
+        WidgetType::Reserved(widget_type) => {
+            warn!("Widget {:?} has reserved widget type {:#x}, leaving it out", widget_address, widget_type);
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(Widget::new(widget_address, audio_widget_capabilities_info, widget_info)))
+}
+
+fn read_parameter<T: TryFrom<Response, Error = Response>>(transport: &impl CommandTransport, node_address: NodeAddress, parameter: Parameter) -> Result<T, CommandError> {
+    decode_response(transport.try_command(GetParameter(node_address, parameter))?, node_address)
+}
+
+// Reads several parameters of a node in one batch. Fails with the error of the first parameter which couldn't be read.
+fn read_parameters(transport: &impl CommandTransport, node_address: NodeAddress, parameters: &[Parameter]) -> Result<ParameterResponses, CommandError> {
+    let commands: Vec<Command> = parameters.iter().map(|parameter| GetParameter(node_address, *parameter)).collect();
+    let responses = transport.try_commands(&commands).into_iter().collect::<Result<Vec<Response>, CommandError>>()?;
+    Ok(ParameterResponses { node_address, responses: parameters.iter().copied().zip(responses).collect() })
+}
+
+// Audio widget capabilities of all widgets of a function group, which get read in one batch. A widget which doesn't respond
+// gets its own error, so that the caller can leave it out.
+fn read_widget_capabilities(transport: &impl CommandTransport, fg_address: NodeAddress) -> Result<Vec<(NodeAddress, Result<AudioWidgetCapabilitiesResponse, CommandError>)>, CommandError> {
+    let subordinate_node_count: SubordinateNodeCountResponse = read_parameter(transport, fg_address, SubordinateNodeCount)?;
+    let widget_addresses: Vec<NodeAddress> = (*subordinate_node_count.starting_node_number()..(*subordinate_node_count.starting_node_number() + *subordinate_node_count.total_number_of_nodes()))
+        .map(|node_id| NodeAddress::new(*fg_address.codec_address(), node_id))
+        .collect();
+    let commands: Vec<Command> = widget_addresses.iter().map(|widget_address| GetParameter(*widget_address, AudioWidgetCapabilities)).collect();
+    let responses = transport.try_commands(&commands);
+    Ok(widget_addresses.into_iter().zip(responses)
+        .map(|(widget_address, response)| (widget_address, response.and_then(|response| decode_response(response, widget_address))))
+        .collect())
+}
+
+// responses of a batch of parameters (see fn read_parameters), which get decoded into the response types of their parameters
+struct ParameterResponses {
+    node_address: NodeAddress,
+    responses: Vec<(Parameter, Response)>,
+}
+
+impl ParameterResponses {
+    fn take<T: TryFrom<Response, Error = Response>>(&mut self, parameter: Parameter) -> Result<T, CommandError> {
+        match self.take_optional(parameter)? {
+            Some(response) => Ok(response),
+            None => panic!("Parameter {:?} of node {:?} has not been read", parameter, self.node_address),
+        }
+    }
+
+    // None if the parameter wasn't part of the batch
+    fn take_optional<T: TryFrom<Response, Error = Response>>(&mut self, parameter: Parameter) -> Result<Option<T>, CommandError> {
+        match self.responses.iter().position(|(read_parameter, _)| *read_parameter == parameter) {
+            Some(index) => decode_response(self.responses.remove(index).1, self.node_address).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// Reads all entries of the connection list of a widget in short or long form and expands ranges into single node ids
+// (see section 7.3.3.3 of the specification). Node ids which don't fit into 8 bits can't be addressed by verbs, so they get dropped.
+fn read_connection_list(transport: &impl CommandTransport, widget_address: NodeAddress, connection_list_length: &ConnectionListLengthResponse) -> Result<Vec<u8>, CommandError> {
+    let long_form = *connection_list_length.long_form();
+    let entries_per_response = ConnectionListEntryResponse::entries_per_response(long_form);
+    let mut connection_list = Vec::new();
+    let mut previous_node_id: Option<u16> = None;
+
+    for offset in (0..*connection_list_length.connection_list_length() as usize).step_by(entries_per_response) {
+        let response: ConnectionListEntryResponse = decode_response(transport.try_command(GetConnectionListEntry(widget_address, GetConnectionListEntryPayload::new(offset as u8)))?, widget_address)?;
+        let remaining_entries = *connection_list_length.connection_list_length() as usize - offset;
+        for entry in response.entries(long_form).into_iter().take(remaining_entries) {
+            let node_ids = match previous_node_id {
+                Some(previous_node_id) if *entry.range() && previous_node_id < *entry.node_id() => (previous_node_id + 1)..=*entry.node_id(),
+                _ => *entry.node_id()..=*entry.node_id(),
+            };
+            for node_id in node_ids {
+                match u8::try_from(node_id) {
+                    Ok(node_id) if node_id != 0 => connection_list.push(node_id),
+                    _ => warn!("Widget {:?} has invalid node id {:#x} in its connection list", widget_address, node_id),
+                }
+            }
+            previous_node_id = Some(*entry.node_id());
         }
     }
-}
 
-impl TryFrom<Response> for ConverterChannelCountResponse {
-    type Error = Response;
-
-    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
-        match wrapped_response {
-            Response::ConverterChannelCount(info) => Ok(info),
-            e => Err(e),
-        }
-    }
+    Ok(connection_list)
 }
@@ -0,0 +1,358 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use derive_getters::Getters;
+use log::warn;
+use crate::audio::settings::{EndpointDirection, EndpointId, EndpointKind};
+use crate::device::ihda_codec::{Codec, CodecQuirk, CommandTransport, FunctionGroup, OutputPathCandidate, Widget, POWER_UP_SETTLE_TIME_IN_MS};
+use crate::device::ihda_verbs::{ConfigDefDefaultDevice, NodeAddress, PinSenseResponse, PowerState, SetUnsolicitedResponsePayload, SetVolumeKnobPayload, VolumeKnobResponse, WidgetType};
+use crate::device::ihda_verbs::Command::{ExecutePinSense, GetPinSense, GetVolumeKnob, SetUnsolicitedResponse, SetVolumeKnob};
+use crate::device::ihda_controller::{CommandTransportKind, Controller, IhdaError};
+use crate::device::ihda_stream::Stream;
+use crate::device::pit::Timer;
+
+// unsolicited response tags are only 6 bits long (see specification, section 7.3.3.14)
+pub const MAX_UNSOLICITED_RESPONSE_TAG: usize = 0x3F;
+
+// Driver object of a single codec. Every codec has its own widget graph, so pins and paths get looked up per codec,
+// while all verbs still get sent through the controller the codecs share.
+pub struct CodecDriver {
+    codec: Codec,
+    // headphone pins which report jack events through unsolicited responses; the tag of a pin is its index plus 1
+    jack_sense_pins: Vec<NodeAddress>,
+    // Headphone pins with presence detection which can't report jack events through unsolicited responses, either because the pin
+    // isn't unsolicited capable or because the controller uses the immediate command transport, where unsolicited responses never
+    // arrive. Their presence detect bit gets polled instead (see fn poll_jack_presence), next to the last state that was seen.
+    polled_jack_pins: Vec<(NodeAddress, AtomicBool)>,
+    // volume knobs which report turns through unsolicited responses; their tags follow the ones of the jack sense pins
+    volume_knobs: Vec<NodeAddress>,
+}
+
+// what callers need to know about a codec to choose the codec or endpoint they want to use
+#[derive(Clone, Debug, Getters)]
+pub struct CodecInfo {
+    codec_address: u8,
+    vendor_id: u16,
+    device_id: u16,
+    // codecs get quarantined after repeated command timeouts and can't be used until they recover
+    quarantined: bool,
+    outputs: Vec<EndpointId>,
+    inputs: Vec<EndpointId>,
+}
+
+impl CodecDriver {
+    pub fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            jack_sense_pins: Vec::new(),
+            polled_jack_pins: Vec::new(),
+            volume_knobs: Vec::new(),
+        }
+    }
+
+    pub fn codec(&self) -> &Codec {
+        &self.codec
+    }
+
+    pub fn codec_address(&self) -> u8 {
+        *self.codec.codec_address().codec_address()
+    }
+
+    // Codecs may expose more than one audio function group (e.g. one per output on some docking station and HDMI codecs),
+    // while modem and vendor defined function groups have no widgets the driver knows how to use.
+    pub fn audio_function_groups(&self) -> impl Iterator<Item = &FunctionGroup> {
+        self.codec.function_groups().iter().filter(|function_group| function_group.is_audio_function_group())
+    }
+
+    pub fn info(&self, quarantined: bool) -> CodecInfo {
+        let mut outputs = Vec::new();
+        let mut inputs = Vec::new();
+        for function_group in self.audio_function_groups() {
+            for pin_widget in function_group.find_connected_pin_widgets() {
+                match endpoint_kind(pin_widget.configuration_default().unwrap().default_device()).direction() {
+                    EndpointDirection::Output => outputs.push(endpoint_id(pin_widget)),
+                    EndpointDirection::Input => inputs.push(endpoint_id(pin_widget)),
+                }
+            }
+        }
+
+        CodecInfo {
+            codec_address: self.codec_address(),
+            vendor_id: *self.codec.vendor_id().vendor_id(),
+            device_id: *self.codec.vendor_id().device_id(),
+            quarantined,
+            outputs,
+            inputs,
+        }
+    }
+
+    // any widget of the codec, not only pins connected to a jack
+    pub fn find_widget(&self, node_id: u8) -> Option<&Widget> {
+        self.audio_function_groups()
+            .flat_map(|function_group| function_group.widgets().iter())
+            .find(|widget| *widget.address().node_id() == node_id)
+    }
+
+    pub fn find_pin_widget(&self, node_id: u8) -> Option<(&FunctionGroup, &Widget)> {
+        for function_group in self.audio_function_groups() {
+            if let Some(pin_widget) = function_group.find_connected_pin_widgets().into_iter().find(|widget| *widget.address().node_id() == node_id) {
+                return Some((function_group, pin_widget));
+            }
+        }
+        None
+    }
+
+    // Each audio function group contributes its best output (see fn FunctionGroup::output_path_candidates) and the one with
+    // the lowest association wins, so that the output of a group without a usable path doesn't hide the ones of the other groups.
+    // Groups only providing line outs without association come last, ties go to the group with the lowest node id.
+    pub fn default_output_path(&self) -> Option<(&FunctionGroup, OutputPathCandidate)> {
+        self.audio_function_groups()
+            .filter_map(|function_group| function_group.output_path_candidates().into_iter().next().map(|candidate| (function_group, candidate)))
+            .min_by_key(|(_, candidate)| candidate.association().unwrap_or(u8::MAX))
+    }
+
+    pub fn default_output_pin_widget(&self) -> Option<(&FunctionGroup, &Widget)> {
+        self.default_output_path().map(|(function_group, candidate)| (function_group, candidate.pin_widget()))
+    }
+
+    // the first mic in or line in pin connected to a jack which has a path to an audio input converter, searched in all audio function groups
+    pub fn default_input_pin_widget(&self) -> Option<(&FunctionGroup, &Widget)> {
+        self.audio_function_groups().find_map(|function_group| function_group.find_input_pin_widgets_connected_to_jack().into_iter()
+            .find(|pin_widget| function_group.find_widget_path_for_capture(pin_widget).is_some())
+            .map(|pin_widget| (function_group, pin_widget)))
+    }
+
+    // The pin a headphone jack hands the playback back to once headphones get unplugged. A headphone jack of an association
+    // returns it to the primary pin of its association, any other falls back to fn speaker_pin_widget.
+    pub fn unplugged_headphone_fallback<'f>(&'f self, function_group: &'f FunctionGroup, headphone_pin_widget: &Widget) -> Option<(&'f FunctionGroup, &'f Widget)> {
+        let association_pin_widget = function_group.association_group_of(headphone_pin_widget)
+            .filter(|group| group.headphone_pin_widget().is_some_and(|pin_widget| pin_widget.address().node_id() == headphone_pin_widget.address().node_id()))
+            .and_then(|group| group.pin_widgets().first().copied());
+        match association_pin_widget {
+            Some(pin_widget) => Some((function_group, pin_widget)),
+            None => self.speaker_pin_widget(function_group),
+        }
+    }
+
+    // falls back to the default output of the codec if it has no built-in speaker
+    pub fn speaker_pin_widget<'f>(&'f self, function_group: &'f FunctionGroup) -> Option<(&'f FunctionGroup, &'f Widget)> {
+        match function_group.find_speaker_pin_widgets().get(0) {
+            Some(pin_widget) => Some((function_group, *pin_widget)),
+            None => self.default_output_pin_widget(),
+        }
+    }
+
+    // Routes the stream to the default output of this codec, which is how the demo functions play sound.
+    pub fn configure_for_line_out_playback(&self, controller: &Controller, stream: &Stream) -> Result<(), IhdaError> {
+        let (_, candidate) = self.default_output_path().ok_or(IhdaError::UnsupportedCodec {
+            vendor_id: *self.codec.vendor_id().vendor_id(),
+            device_id: *self.codec.vendor_id().device_id(),
+        })?;
+        controller.configure_widget_path_for_playback(candidate.path(), stream);
+        self.enable_external_amplifiers(controller);
+        Ok(())
+    }
+
+    // Raises the amplifier enable GPIO of codecs with the CodecQuirk::ExternalAmplifierGpio quirk. Gets called whenever playback starts,
+    // as a codec reset or a transition to D3 clears the GPIOs. Does nothing for all other codecs.
+    pub fn enable_external_amplifiers(&self, transport: &impl CommandTransport) {
+        let gpio_index = match self.codec.quirks().iter().find_map(|quirk| match quirk {
+            CodecQuirk::ExternalAmplifierGpio(gpio_index) => Some(*gpio_index),
+        }) {
+            Some(gpio_index) => gpio_index,
+            None => return,
+        };
+
+        for function_group in self.codec.function_groups().iter().filter(|function_group| *function_group.gpio_count().num_gpios() > gpio_index) {
+            if let Err(error) = function_group.set_gpio(transport, gpio_index, true) {
+                warn!("Failed to enable the external amplifier on GPIO {} of function group {:?}: {:?}", gpio_index, function_group.function_group_node_address(), error);
+            }
+        }
+    }
+
+    // Lets every headphone pin with presence detection send an unsolicited response when something gets plugged in or out.
+    // Pins which can't send unsolicited responses get polled instead.
+    pub fn enable_jack_presence_detection(&mut self, controller: &Controller) {
+        let unsolicited_responses_available = controller.command_transport() == CommandTransportKind::CorbRirb;
+        let mut jack_sense_pins = Vec::new();
+        let mut polled_jack_pins = Vec::new();
+        for function_group in self.codec.function_groups().iter() {
+            for pin_widget in function_group.find_headphone_pin_widgets_connected_to_jack() {
+                if !*pin_widget.pin_capabilities().unwrap().presence_detect_capable() {
+                    continue;
+                }
+                if !unsolicited_responses_available || !*pin_widget.audio_widget_capabilities().unsol_capable() {
+                    let present = self.sense_presence(controller, pin_widget);
+                    polled_jack_pins.push((*pin_widget.address(), AtomicBool::new(present)));
+                    continue;
+                }
+                if jack_sense_pins.len() >= MAX_UNSOLICITED_RESPONSE_TAG {
+                    continue;
+                }
+                jack_sense_pins.push(*pin_widget.address());
+                let tag = jack_sense_pins.len() as u8;
+                controller.command(SetUnsolicitedResponse(*pin_widget.address(), SetUnsolicitedResponsePayload::new(true, tag)));
+            }
+        }
+        self.jack_sense_pins = jack_sense_pins;
+        self.polled_jack_pins = polled_jack_pins;
+    }
+
+    pub fn jack_sense_pin_amount(&self) -> usize {
+        self.jack_sense_pins.len()
+    }
+
+    pub fn polled_jack_pin_amount(&self) -> usize {
+        self.polled_jack_pins.len()
+    }
+
+    // Sends Get Pin Sense to every polled jack pin and returns the pins whose presence detect bit changed since the last call,
+    // together with their new state.
+    pub fn poll_jack_presence(&self, controller: &Controller) -> Vec<(&FunctionGroup, &Widget, bool)> {
+        let mut changed_pins = Vec::new();
+        for (address, last_present) in self.polled_jack_pins.iter() {
+            let (function_group, pin_widget) = match self.find_pin_widget(*address.node_id()) {
+                Some(pin) => pin,
+                None => continue,
+            };
+            let present = self.sense_presence(controller, pin_widget);
+            if last_present.swap(present, Ordering::Relaxed) != present {
+                changed_pins.push((function_group, pin_widget, present));
+            }
+        }
+        changed_pins
+    }
+
+    // Lets every volume knob which is able to send unsolicited responses report its turns to the software instead of
+    // changing the amps of its slave widgets by itself. Must be called after fn enable_jack_presence_detection, as the tags
+    // of the knobs follow the ones of the jack sense pins.
+    pub fn enable_volume_knobs(&mut self, controller: &Controller) {
+        let mut volume_knobs = Vec::new();
+        for function_group in self.codec.function_groups().iter() {
+            for widget in function_group.widgets().iter().filter(|widget| matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::VolumeKnobWidget)) {
+                if !*widget.audio_widget_capabilities().unsol_capable() || self.jack_sense_pins.len() + volume_knobs.len() >= MAX_UNSOLICITED_RESPONSE_TAG {
+                    continue;
+                }
+                // the knob keeps its current position, only the direct bit gets cleared
+                let volume = *VolumeKnobResponse::try_from(controller.command(GetVolumeKnob(*widget.address()))).unwrap().volume();
+                controller.command(SetVolumeKnob(*widget.address(), SetVolumeKnobPayload::new(false, volume)));
+                volume_knobs.push(*widget.address());
+                let tag = (self.jack_sense_pins.len() + volume_knobs.len()) as u8;
+                controller.command(SetUnsolicitedResponse(*widget.address(), SetUnsolicitedResponsePayload::new(true, tag)));
+            }
+        }
+        self.volume_knobs = volume_knobs;
+    }
+
+    // volume knob widgets set up by fn enable_volume_knobs
+    pub fn volume_knobs(&self) -> impl Iterator<Item=&Widget> {
+        self.volume_knobs.iter().filter_map(|address| self.find_widget(*address.node_id()))
+    }
+
+    // the volume knob widget which sent an unsolicited response with the given tag
+    pub fn volume_knob(&self, tag: u8) -> Option<&Widget> {
+        let address = (tag as usize).checked_sub(self.jack_sense_pins.len() + 1).and_then(|index| self.volume_knobs.get(index))?;
+        self.find_widget(*address.node_id())
+    }
+
+    pub fn is_volume_knob(&self, node_id: u8) -> bool {
+        self.volume_knobs.iter().any(|address| *address.node_id() == node_id)
+    }
+
+    // tags from 1 up to this amount are used by jack sense pins and volume knobs
+    pub fn reserved_tag_amount(&self) -> usize {
+        self.jack_sense_pins.len() + self.volume_knobs.len()
+    }
+
+    // the pin widget which sent an unsolicited response with the given tag
+    pub fn jack_sense_pin(&self, tag: u8) -> Option<(&FunctionGroup, &Widget)> {
+        let address = (tag as usize).checked_sub(1).and_then(|index| self.jack_sense_pins.get(index))?;
+        self.find_pin_widget(*address.node_id())
+    }
+
+    pub fn is_jack_sense_pin(&self, node_id: u8) -> bool {
+        self.jack_sense_pins.iter().any(|address| *address.node_id() == node_id)
+    }
+
+    pub fn sense_presence(&self, controller: &Controller, pin_widget: &Widget) -> bool {
+        // pins which require a trigger only update their presence detect bit after an Execute Pin Sense command
+        if *pin_widget.pin_capabilities().unwrap().trigger_required() {
+            controller.command(ExecutePinSense(*pin_widget.address()));
+        }
+        *PinSenseResponse::try_from(controller.command(GetPinSense(*pin_widget.address()))).unwrap().presence_detect()
+    }
+
+    // Lets all pins which only update their presence detect bit on request measure it again, e.g. after the codec got powered up,
+    // as the bit may still show the state from before the codec went into D3 or came out of reset.
+    pub fn execute_pin_sense(&self, controller: &Controller) {
+        for function_group in self.codec.function_groups().iter() {
+            for pin_widget in function_group.widgets().iter().filter(|widget| matches!(widget.audio_widget_capabilities().widget_type(), WidgetType::PinComplex)) {
+                let pin_capabilities = pin_widget.pin_capabilities().unwrap();
+                if *pin_capabilities.presence_detect_capable() && *pin_capabilities.trigger_required() {
+                    controller.command(ExecutePinSense(*pin_widget.address()));
+                }
+            }
+        }
+    }
+
+    pub fn volume_knob_position(&self, controller: &Controller, volume_knob_widget: &Widget) -> u8 {
+        *VolumeKnobResponse::try_from(controller.command(GetVolumeKnob(*volume_knob_widget.address()))).unwrap().volume()
+    }
+
+    // Function groups which don't report D3 as supported power state stay in D0, as the codecs emulated by QEMU don't report
+    // any power states and answer every Get Power State verb with D0, so a transition to D3 would only run into a timeout.
+    pub fn power_down(&self, controller: &Controller) {
+        for function_group in self.codec.function_groups().iter() {
+            if !function_group.supported_power_states().as_ref().is_some_and(|power_states| *power_states.d3_sup()) {
+                continue;
+            }
+            for widget in function_group.widgets().iter().filter(|widget| *widget.audio_widget_capabilities().power_cntrl()) {
+                controller.set_power_state(*widget.address(), PowerState::D3);
+            }
+            controller.set_power_state(*function_group.function_group_node_address(), PowerState::D3);
+        }
+    }
+
+    // returns whether the codec lost its settings while it was powered down
+    pub fn power_up(&self, controller: &Controller) -> bool {
+        let mut settings_reset = false;
+        for function_group in self.codec.function_groups().iter() {
+            // widgets can't be in a higher power state than their function group, so the function group has to wake up first
+            settings_reset |= *controller.set_power_state(*function_group.function_group_node_address(), PowerState::D0).settings_reset();
+            for widget in function_group.widgets().iter().filter(|widget| *widget.audio_widget_capabilities().power_cntrl()) {
+                settings_reset |= *controller.set_power_state(*widget.address(), PowerState::D0).settings_reset();
+            }
+        }
+        Timer::wait(POWER_UP_SETTLE_TIME_IN_MS);
+        self.execute_pin_sense(controller);
+        settings_reset
+    }
+}
+
+pub fn endpoint_id(pin_widget: &Widget) -> EndpointId {
+    EndpointId::new(*pin_widget.address().codec_address().codec_address(), *pin_widget.address().node_id())
+}
+
+// the default device a pin gets retasked to for the given role, None for roles a pin can't be retasked to
+pub fn default_device(kind: EndpointKind) -> Option<ConfigDefDefaultDevice> {
+    match kind {
+        EndpointKind::LineOut => Some(ConfigDefDefaultDevice::LineOut),
+        EndpointKind::Speaker => Some(ConfigDefDefaultDevice::Speaker),
+        EndpointKind::Headphone => Some(ConfigDefDefaultDevice::HPOut),
+        EndpointKind::LineIn => Some(ConfigDefDefaultDevice::LineIn),
+        EndpointKind::Microphone => Some(ConfigDefDefaultDevice::MicIn),
+        EndpointKind::DigitalOut | EndpointKind::DigitalIn | EndpointKind::Other => None,
+    }
+}
+
+pub fn endpoint_kind(default_device: &ConfigDefDefaultDevice) -> EndpointKind {
+    match default_device {
+        ConfigDefDefaultDevice::LineOut => EndpointKind::LineOut,
+        ConfigDefDefaultDevice::Speaker => EndpointKind::Speaker,
+        ConfigDefDefaultDevice::HPOut => EndpointKind::Headphone,
+        ConfigDefDefaultDevice::SPDIFOut | ConfigDefDefaultDevice::DigitalOtherOut => EndpointKind::DigitalOut,
+        ConfigDefDefaultDevice::LineIn | ConfigDefDefaultDevice::AUX | ConfigDefDefaultDevice::CD => EndpointKind::LineIn,
+        ConfigDefDefaultDevice::MicIn => EndpointKind::Microphone,
+        ConfigDefDefaultDevice::SPDIFIn | ConfigDefDefaultDevice::DigitalOtherIn => EndpointKind::DigitalIn,
+        _ => EndpointKind::Other,
+    }
+}
@@ -0,0 +1,252 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_codec::{Codec, CommandTransport, FunctionGroup, Widget, WidgetInfoContainer};
+use crate::device::ihda_verbs::{AmpCapabilitiesResponse, AmplifierGainMuteResponse, ChannelStreamIdResponse, ConfigurationDefaultResponse, ConnectionSelectResponse, GetAmplifierGainMutePayload, GetAmplifierGainMuteSide, GetAmplifierGainMuteType, PinCapabilitiesResponse, PinWidgetControlResponse, PowerStateResponse, SampleSizeRateCAPsResponse, StreamFormatResponse, WidgetType};
+use crate::device::ihda_verbs::Command::{GetAmplifierGainMute, GetChannelStreamId, GetConnectionSelect, GetPinWidgetControl, GetPowerState, GetStreamFormat};
+
+// Exports the widget graph of all codecs as JSON, so that it can be compared with dumps of other operating systems (e.g. alsa-info
+// on Linux) by scripts when bringing up new hardware. Besides everything the driver learned while scanning the codec (connection
+// lists, configuration defaults, amp capabilities), the current control state of every widget gets read from the codec.
+// Values the codec doesn't answer (e.g. because it got unplugged) are exported as null, and quarantined codecs don't get asked at all.
+// Enum values are exported with their names in the driver, numbers in decimal.
+
+// codecs paired with the information whether they are quarantined
+pub fn export_codec_graph(transport: &impl CommandTransport, codecs: &[(&Codec, bool)]) -> String {
+    let codecs = codecs.iter()
+        .map(|(codec, quarantined)| codec_to_json(transport, codec, *quarantined))
+        .collect();
+    let mut export = object(&[("codecs", array(codecs))]);
+    export.push('\n');
+    export
+}
+
+fn codec_to_json(transport: &impl CommandTransport, codec: &Codec, quarantined: bool) -> String {
+    let function_groups = codec.function_groups().iter()
+        .map(|function_group| function_group_to_json(transport, function_group, !quarantined))
+        .collect();
+    object(&[
+        ("address", codec.codec_address().codec_address().to_string()),
+        ("vendor_id", hex(*codec.vendor_id().vendor_id() as u32)),
+        ("device_id", hex(*codec.vendor_id().device_id() as u32)),
+        ("revision", string(&format!("{}.{}", codec.revision_id().major_revision(), codec.revision_id().minor_revision()))),
+        ("quarantined", quarantined.to_string()),
+        ("function_groups", array(function_groups)),
+    ])
+}
+
+fn function_group_to_json(transport: &impl CommandTransport, function_group: &FunctionGroup, read_state: bool) -> String {
+    let widgets = function_group.widgets().iter()
+        .map(|widget| widget_to_json(transport, function_group, widget, read_state))
+        .collect();
+    object(&[
+        ("node_id", function_group.function_group_node_address().node_id().to_string()),
+        ("type", debug(function_group.function_group_type().node_type())),
+        ("formats", function_group.sample_size_rate_caps().as_ref().map_or(null(), formats_to_json)),
+        ("input_amp_caps", amp_capabilities_to_json(function_group.input_amp_caps().as_ref())),
+        ("output_amp_caps", amp_capabilities_to_json(function_group.output_amp_caps().as_ref())),
+        ("widgets", array(widgets)),
+    ])
+}
+
+fn widget_to_json(transport: &impl CommandTransport, function_group: &FunctionGroup, widget: &Widget, read_state: bool) -> String {
+    let capabilities = widget.audio_widget_capabilities();
+    let mut fields = Vec::from([
+        ("node_id", widget.address().node_id().to_string()),
+        ("type", debug(capabilities.widget_type())),
+        ("channels", widget.max_number_of_channels().to_string()),
+        ("digital", widget.is_digital().to_string()),
+        ("connections", array(widget.connection_list().iter().map(|node_id| node_id.to_string()).collect())),
+        ("input_amp_caps", amp_capabilities_to_json(function_group.input_amp_capabilities_of(widget))),
+        ("output_amp_caps", amp_capabilities_to_json(function_group.output_amp_capabilities_of(widget))),
+    ]);
+
+    match widget.widget_info() {
+        WidgetInfoContainer::AudioOutputConverter(sample_size_rate_caps, _, _, _, _)
+        | WidgetInfoContainer::AudioInputConverter(sample_size_rate_caps, _, _, _, _, _, _) => {
+            fields.push(("formats", formats_to_json(sample_size_rate_caps)));
+        }
+        _ => {}
+    }
+    if let Some(pin_capabilities) = widget.pin_capabilities() {
+        fields.push(("pin_caps", pin_capabilities_to_json(pin_capabilities)));
+    }
+    if let Some(configuration_default) = widget.configuration_default() {
+        fields.push(("config_default", configuration_default_to_json(configuration_default)));
+    }
+    if read_state {
+        fields.push(("state", widget_state_to_json(transport, function_group, widget)));
+    }
+
+    object(&fields)
+}
+
+// current values of the controls of a widget, as far as it has them
+fn widget_state_to_json(transport: &impl CommandTransport, function_group: &FunctionGroup, widget: &Widget) -> String {
+    let capabilities = widget.audio_widget_capabilities();
+    let mut fields = Vec::new();
+
+    if *capabilities.power_cntrl() {
+        let power_state = transport.try_command(GetPowerState(*widget.address())).ok()
+            .and_then(|response| PowerStateResponse::try_from(response).ok());
+        fields.push(("power_state", power_state.map_or(null(), |power_state| object(&[
+            ("setting", debug(power_state.setting())),
+            ("actual", debug(power_state.actual())),
+        ]))));
+    }
+
+    if function_group.output_amp_capabilities_of(widget).is_some() {
+        fields.push(("output_amp", amp_state_to_json(transport, widget, GetAmplifierGainMuteType::Output, 0)));
+    }
+    if function_group.input_amp_capabilities_of(widget).is_some() {
+        // a mixer has one input amp per connection, all other widgets only have one input amp
+        let amp_amount = match capabilities.widget_type() {
+            WidgetType::AudioMixer => widget.connection_list().len().max(1),
+            _ => 1,
+        };
+        let input_amps = (0..amp_amount)
+            .map(|index| amp_state_to_json(transport, widget, GetAmplifierGainMuteType::Input, index as u8))
+            .collect();
+        fields.push(("input_amps", array(input_amps)));
+    }
+
+    // mixers use all of their connections at once, so only other widgets with more than one connection have a selection
+    if widget.connection_list().len() > 1 && !matches!(capabilities.widget_type(), WidgetType::AudioMixer) {
+        let selected = transport.try_command(GetConnectionSelect(*widget.address())).ok()
+            .and_then(|response| ConnectionSelectResponse::try_from(response).ok());
+        fields.push(("connection_select", selected.map_or(null(), |selected| selected.currently_set_connection_index().to_string())));
+    }
+
+    match capabilities.widget_type() {
+        WidgetType::AudioOutput | WidgetType::AudioInput => {
+            let channel_stream_id = transport.try_command(GetChannelStreamId(*widget.address())).ok()
+                .and_then(|response| ChannelStreamIdResponse::try_from(response).ok());
+            fields.push(("stream", channel_stream_id.map_or(null(), |channel_stream_id| object(&[
+                ("stream_id", channel_stream_id.stream().to_string()),
+                ("channel", channel_stream_id.channel().to_string()),
+            ]))));
+
+            let stream_format = transport.try_command(GetStreamFormat(*widget.address())).ok()
+                .and_then(|response| StreamFormatResponse::try_from(response).ok())
+                .map(|response| AudioFormat::from_response(&response));
+            fields.push(("stream_format", stream_format.map_or(null(), |stream_format| object(&[
+                ("sample_rate", stream_format.sample_rate().to_string()),
+                ("bits_per_sample", stream_format.bits_per_sample().bit_depth().to_string()),
+                ("channels", stream_format.number_of_channels().to_string()),
+                ("type", debug(stream_format.stream_type())),
+            ]))));
+        }
+        WidgetType::PinComplex => {
+            let pin_widget_control = transport.try_command(GetPinWidgetControl(*widget.address())).ok()
+                .and_then(|response| PinWidgetControlResponse::try_from(response).ok());
+            fields.push(("pin_control", pin_widget_control.map_or(null(), |pin_widget_control| {
+                // the lowest bits mean something different for digital display pins (see PinWidgetControlResponse)
+                let (name, value) = if widget.is_digital_display_pin() {
+                    ("encoded_packet_type", debug(&pin_widget_control.encoded_packet_type()))
+                } else {
+                    ("voltage_reference", debug(&pin_widget_control.voltage_reference_enable()))
+                };
+                object(&[
+                    ("in_enable", pin_widget_control.in_enable().to_string()),
+                    ("out_enable", pin_widget_control.out_enable().to_string()),
+                    ("headphone_enable", pin_widget_control.h_phn_enable().to_string()),
+                    (name, value),
+                ])
+            })));
+        }
+        _ => {}
+    }
+
+    object(&fields)
+}
+
+// left and right amp are always set together by this driver, so only the left one gets read
+fn amp_state_to_json(transport: &impl CommandTransport, widget: &Widget, amp_type: GetAmplifierGainMuteType, index: u8) -> String {
+    let payload = GetAmplifierGainMutePayload::new(amp_type, GetAmplifierGainMuteSide::Left, index);
+    let gain_mute = transport.try_command(GetAmplifierGainMute(*widget.address(), payload)).ok()
+        .and_then(|response| AmplifierGainMuteResponse::try_from(response).ok());
+    gain_mute.map_or(null(), |gain_mute| object(&[
+        ("gain", gain_mute.amplifier_gain().to_string()),
+        ("mute", gain_mute.amplifier_mute().to_string()),
+    ]))
+}
+
+fn amp_capabilities_to_json(amp_capabilities: Option<&AmpCapabilitiesResponse>) -> String {
+    amp_capabilities.map_or(null(), |amp_capabilities| object(&[
+        ("offset", amp_capabilities.offset().to_string()),
+        ("num_steps", amp_capabilities.num_steps().to_string()),
+        ("step_size", amp_capabilities.step_size().to_string()),
+        ("mute_capable", amp_capabilities.mute_capable().to_string()),
+    ]))
+}
+
+fn formats_to_json(sample_size_rate_caps: &SampleSizeRateCAPsResponse) -> String {
+    let sample_rates = sample_size_rate_caps.supported_sample_rates().iter()
+        .map(|sample_rate| sample_rate.to_string())
+        .collect();
+    let bits_per_sample = sample_size_rate_caps.supported_bits_per_sample().iter()
+        .map(|bits_per_sample| bits_per_sample.bit_depth().to_string())
+        .collect();
+    object(&[
+        ("sample_rates", array(sample_rates)),
+        ("bits_per_sample", array(bits_per_sample)),
+    ])
+}
+
+fn pin_capabilities_to_json(pin_capabilities: &PinCapabilitiesResponse) -> String {
+    object(&[
+        ("presence_detect", pin_capabilities.presence_detect_capable().to_string()),
+        ("headphone_drive", pin_capabilities.headphone_drive_capable().to_string()),
+        ("output", pin_capabilities.output_capable().to_string()),
+        ("input", pin_capabilities.input_capable().to_string()),
+        ("hdmi", pin_capabilities.hdmi().to_string()),
+        ("display_port", pin_capabilities.display_port().to_string()),
+        ("eapd", pin_capabilities.eapd_capable().to_string()),
+        ("vref_control", pin_capabilities.vref_control().to_string()),
+    ])
+}
+
+fn configuration_default_to_json(configuration_default: &ConfigurationDefaultResponse) -> String {
+    object(&[
+        ("default_device", debug(configuration_default.default_device())),
+        ("port_connectivity", debug(configuration_default.port_connectivity())),
+        ("connection_type", debug(configuration_default.connection_type())),
+        ("color", debug(configuration_default.color())),
+        ("gross_location", debug(configuration_default.gross_location())),
+        ("geometric_location", debug(configuration_default.geometric_location())),
+        ("default_association", configuration_default.default_association().to_string()),
+        ("sequence", configuration_default.sequence().to_string()),
+        ("jack_detect_override", configuration_default.jack_detect_override().to_string()),
+    ])
+}
+
+// ########## JSON values ##########
+
+// the values have to be JSON already
+fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{}:{}", string(name), value)).collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn array(values: Vec<String>) -> String {
+    format!("[{}]", values.join(","))
+}
+
+// names and Debug output of the driver never contain characters which would have to be escaped besides quotes and backslashes
+fn string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn debug(value: &impl core::fmt::Debug) -> String {
+    string(&format!("{:?}", value))
+}
+
+// JSON has no hex numbers, so they get exported as strings
+fn hex(value: u32) -> String {
+    string(&format!("{:#06x}", value))
+}
+
+fn null() -> String {
+    String::from("null")
+}
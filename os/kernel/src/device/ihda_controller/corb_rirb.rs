@@ -0,0 +1,858 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use log::{debug, error, info, warn};
+use x86_64::structures::paging::PhysFrame;
+use crate::device::ihda_controller::{wait_for, CommandTransportKind, Controller, IhdaError, RegisterName, BIT_ASSERTION_TIMEOUT_IN_MS,
+    RING_BUFFER_ADDRESS_RESERVED_BITS, RING_BUFFER_ALIGNMENT_IN_BYTES};
+use crate::device::ihda_controller::diagnostics::VerbTraceEvent;
+use crate::device::ihda_controller::registers::{Corbctl, Corbrp, Corbsts, RingSize, RingWritePointer, Rintcnt, Rirbctl, Rirbsts};
+use crate::device::ihda_verbs::{CodecAddress, Command, MAX_AMOUNT_OF_CODECS, NodeAddress, RawResponse, Response};
+use crate::device::ihda_verbs::Command::GetParameter;
+use crate::device::ihda_verbs::Parameter::VendorId;
+use crate::device::pit::Timer;
+use crate::memory::dma::{alloc_dma, CachePolicy};
+
+// arbitrarily chosen, like the other timeouts of the controller
+const CORB_COMMAND_TIMEOUT_IN_MS: usize = 100;
+// RINTCNT can't count more responses (see specification, section 3.3.28)
+const MAX_RESPONSE_INTERRUPT_COUNT: u16 = 256;
+const CORB_ENTRY_SIZE_IN_BYTES: u64 = 4;
+const RIRB_ENTRY_SIZE_IN_BYTES: u64 = 8;
+
+impl Controller {
+    // ########## CORBLBASE and CORBUBASE ##########
+
+     fn set_corb_address(&self, start_frame: PhysFrame) {
+        // writing to CORBLBASE and CORBUBASE while the CORB DMA engine is running is not allowed (see specification, section 3.3.18 and 3.3.19)
+        assert!(!self.corbctl.is_set(Corbctl::DMA_RUN), "Trying to write to CORB address registers while CORB DMA engine is running");
+        let start_address = start_frame.start_address().as_u64();
+        assert_ring_buffer_alignment(start_address, "CORB");
+        let lbase = (start_address & 0xFFFFFFFF) as u32;
+        let ubase = ((start_address & 0xFFFFFFFF_00000000) >> 32) as u32;
+
+        // preserve reserved bits [6:0] of CORBLBASE when writing address
+        self.corblbase.write(lbase | (self.corblbase.read() & RING_BUFFER_ADDRESS_RESERVED_BITS));
+        self.corbubase.write(ubase);
+    }
+
+     fn corb_address(&self) -> u64 {
+        // bits [6:0] of CORBLBASE are reserved and not part of the address
+        (self.corbubase.read() as u64) << 32 | (self.corblbase.read() & !RING_BUFFER_ADDRESS_RESERVED_BITS) as u64
+    }
+
+    // ########## CORBWP ##########
+
+    fn corb_write_pointer(&self) -> u8 {
+        self.corbwp.field(RingWritePointer::WRITE_POINTER) as u8
+    }
+
+    fn set_corb_write_pointer(&self, offset: u8) -> Result<(), IhdaError> {
+        // bits [15:8] of CORBWP are reserved and must be preserved (see specification, section 3.3.20)
+        self.corbwp.set_field(RingWritePointer::WRITE_POINTER, offset as u32)
+    }
+
+    fn reset_corb_write_pointer(&self) {
+        self.corbwp.clear_all_bits();
+    }
+
+    // ########## CORBRP ##########
+
+    fn corb_read_pointer(&self) -> u8 {
+        self.corbrp.field(Corbrp::READ_POINTER) as u8
+    }
+
+    // software must read back a 1 after setting the reset bit and a 0 after clearing it (see specification, section 3.3.21)
+    fn reset_corb_read_pointer(&self) -> Result<(), IhdaError> {
+        self.corbrp.set(Corbrp::READ_POINTER_RESET);
+        if self.capabilities.corb_read_pointer_reset_self_clearing {
+            // the bit might already be cleared again before it could be read, so the reset is done once the pointer reads 0
+            wait_for(|| self.corb_read_pointer() == 0, BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Corbrp))?;
+        } else {
+            wait_for(|| self.corbrp.is_set(Corbrp::READ_POINTER_RESET), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Corbrp))?;
+        }
+
+        self.corbrp.clear(Corbrp::READ_POINTER_RESET);
+        wait_for(|| !self.corbrp.is_set(Corbrp::READ_POINTER_RESET), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Corbrp))
+    }
+
+    // ########## CORBCTL ##########
+
+     fn corb_memory_error_interrupt_enable_bit(&self) -> bool {
+        self.corbctl.is_set(Corbctl::MEMORY_ERROR_INTERRUPT_ENABLE)
+    }
+
+     fn set_corb_memory_error_interrupt_enable_bit(&self) {
+        self.corbctl.set(Corbctl::MEMORY_ERROR_INTERRUPT_ENABLE);
+    }
+
+     pub fn clear_corb_memory_error_interrupt_enable_bit(&self) {
+        self.corbctl.clear(Corbctl::MEMORY_ERROR_INTERRUPT_ENABLE);
+    }
+
+     fn start_corb_dma(&self) -> Result<(), IhdaError> {
+        self.corbctl.set(Corbctl::DMA_RUN);
+        
+        // software must read back value (see specification, section 3.3.22)
+        wait_for(|| self.corbctl.is_set(Corbctl::DMA_RUN), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Corbctl))
+    }
+
+     pub fn stop_corb_dma(&self) -> Result<(), IhdaError> {
+        self.corbctl.clear(Corbctl::DMA_RUN);
+
+        // software must read back value (see specification, section 3.3.22)
+        wait_for(|| !self.corbctl.is_set(Corbctl::DMA_RUN), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Corbctl))
+    }
+
+    // ########## CORBSTS ##########
+
+     fn corb_memory_error_indication_bit(&self) -> bool {
+        self.corbsts.is_set(Corbsts::MEMORY_ERROR_INDICATION)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.10)
+     fn clear_corb_memory_error_indication_bit(&self) {
+        self.corbsts.acknowledge(Corbsts::MEMORY_ERROR_INDICATION);
+    }
+
+    // ########## CORBSIZE ##########
+
+     fn corb_size_in_entries(&self) -> Result<RingbufferSize, IhdaError> {
+        match self.corbsize.field(RingSize::SIZE) {
+            0b00 => Ok(RingbufferSize::TwoEntries),
+            0b01 => Ok(RingbufferSize::SixteenEntries),
+            0b10 => Ok(RingbufferSize::TwoHundredFiftySixEntries),
+            size => Err(IhdaError::InvalidRingbufferSize("CORB", size as u8)),
+        }
+    }
+
+     fn set_corb_size_in_entries(&self, corb_size: RingbufferSize) -> Result<(), IhdaError> {
+        match corb_size {
+            RingbufferSize::TwoEntries => self.corbsize.set_field(RingSize::SIZE, 0b00),
+            RingbufferSize::SixteenEntries => self.corbsize.set_field(RingSize::SIZE, 0b01),
+            RingbufferSize::TwoHundredFiftySixEntries => self.corbsize.set_field(RingSize::SIZE, 0b10),
+        }
+    }
+
+     fn corb_size_capability(&self) -> RingbufferCapability {
+        RingbufferCapability::new(
+            self.corbsize.is_set(RingSize::TWO_ENTRIES_CAPABILITY),
+            self.corbsize.is_set(RingSize::SIXTEEN_ENTRIES_CAPABILITY),
+            self.corbsize.is_set(RingSize::TWO_HUNDRED_FIFTY_SIX_ENTRIES_CAPABILITY),
+        )
+    }
+
+    // ########## CORB pointer arithmetic ##########
+
+    // CORBSIZE gets validated in fn init_corb, but a controller that got reset or removed meanwhile reads back an invalid size
+    fn corb_entries(&self) -> Result<u16, IhdaError> {
+        Ok(self.corb_size_in_entries()?.as_u16())
+    }
+
+    // amount of commands written to the CORB that have not been fetched by the controller yet
+    fn corb_occupancy(&self) -> Result<u16, IhdaError> {
+        Ok(ring_distance(self.corb_read_pointer(), self.corb_write_pointer(), self.corb_entries()?))
+    }
+
+    // one entry always stays unused, as CORBWP == CORBRP indicates an empty ring and a completely filled ring would look the same
+    fn corb_free_entries(&self) -> Result<u16, IhdaError> {
+        Ok(self.corb_entries()? - 1 - self.corb_occupancy()?)
+    }
+
+    fn corb_is_empty(&self) -> Result<bool, IhdaError> {
+        Ok(self.corb_occupancy()? == 0)
+    }
+
+    fn corb_is_full(&self) -> Result<bool, IhdaError> {
+        Ok(self.corb_free_entries()? == 0)
+    }
+
+    // writes the command to the entry following CORBWP and then advances CORBWP, so that the controller starts fetching it
+    // returns the index of the CORB entry that was written, a full CORB is refused instead of overwriting commands not yet
+    // fetched by the controller
+    fn write_command_to_corb(&self, command: Command) -> Result<u8, IhdaError> {
+        if self.corb_is_full()? {
+            return Err(IhdaError::CorbFull);
+        }
+
+        let index = next_ring_index(self.corb_write_pointer(), self.corb_entries()?);
+        unsafe { ((self.corb_address() + (index as u64 * CORB_ENTRY_SIZE_IN_BYTES)) as *mut u32).write(command.as_u32()); }
+        self.set_corb_write_pointer(index)?;
+        Ok(index)
+    }
+
+    pub fn init_corb(&self) -> Result<(), IhdaError> {
+        // disable CORB DMA engine (CORBRUN) and CORB memory error interrupt (CMEIE)
+        self.clear_corb_memory_error_interrupt_enable_bit();
+        self.stop_corb_dma()?;
+
+        // use the largest CORB size the controller supports (IHDA specification, section 3.3.24: "There is no requirement to support
+        // more than one CORB Size."), the memory below always fits 256 entries
+        let corb_size = self.corb_size_capability().largest_supported_size().ok_or(IhdaError::UnsupportedRingbufferSize("CORB", 0))?;
+        self.set_corb_size_in_entries(corb_size)?;
+        self.corb_size_in_entries()?;
+
+        // setup MMIO space for Command Outbound Ring Buffer – CORB
+        // the memory of a previous CORB gets freed here, after its DMA engine has been stopped above
+        let corb_memory = alloc_dma(2, CachePolicy::Uncached);
+        self.set_corb_address(corb_memory.start_frame());
+        *self.corb_memory.lock() = Some(corb_memory);
+
+        self.reset_corb_write_pointer();
+        self.reset_corb_read_pointer()
+    }
+
+    pub fn start_corb(&self) -> Result<(), IhdaError> {
+        // catch misaligned or uninitialized rings before the DMA engine starts fetching commands from them
+        assert_ring_buffer_alignment(self.corb_address(), "CORB");
+        assert_ne!(self.corb_address(), 0, "CORB address not set before starting CORB DMA engine");
+
+        // set CORBRUN and CMEIE bits
+        self.set_controller_interrupt_enable_bit();
+        self.set_corb_memory_error_interrupt_enable_bit();
+        self.start_corb_dma()
+    }
+
+    // ########## RIRBLBASE and RIRBUBASE ##########
+
+     fn set_rirb_address(&self, start_frame: PhysFrame) {
+        // writing to RIRBLBASE and RIRBUBASE while the RIRB DMA engine is running is not allowed (see specification, section 3.3.25 and 3.3.26)
+        assert!(!self.rirbctl.is_set(Rirbctl::DMA_ENABLE), "Trying to write to RIRB address registers while RIRB DMA engine is running");
+        let start_address = start_frame.start_address().as_u64();
+        assert_ring_buffer_alignment(start_address, "RIRB");
+        let lbase = (start_address & 0xFFFFFFFF) as u32;
+        let ubase = ((start_address & 0xFFFFFFFF_00000000) >> 32) as u32;
+
+        // preserve reserved bits [6:0] of RIRBLBASE when writing address
+        self.rirblbase.write(lbase | (self.rirblbase.read() & RING_BUFFER_ADDRESS_RESERVED_BITS));
+        self.rirbubase.write(ubase);
+    }
+
+     fn rirb_address(&self) -> u64 {
+        // bits [6:0] of RIRBLBASE are reserved and not part of the address
+        (self.rirbubase.read() as u64) << 32 | (self.rirblbase.read() & !RING_BUFFER_ADDRESS_RESERVED_BITS) as u64
+    }
+
+    // ########## RIRBWP ##########
+
+    fn rirb_write_pointer(&self) -> u8 {
+        self.rirbwp.field(RingWritePointer::WRITE_POINTER) as u8
+    }
+
+    fn reset_rirb_write_pointer(&self) {
+        // resetting RIRBWP while the RIRB DMA engine is running is not allowed (see specification, section 3.3.27)
+        assert!(!self.rirb_dma_enable_bit(), "Trying to reset RIRB write pointer while RIRB DMA engine is running");
+        self.rirbwp.set(RingWritePointer::WRITE_POINTER_RESET);
+    }
+
+    // ########## RINTCNT ##########
+
+    // The controller raises a response interrupt after this amount of responses, or as soon as no codec sends a response in a frame,
+    // so a burst of responses only raises a single interrupt (see specification, section 3.3.28).
+    fn set_response_interrupt_count(&self, response_count: u16) -> Result<(), IhdaError> {
+        if response_count == 0 || response_count > MAX_RESPONSE_INTERRUPT_COUNT {
+            return Err(IhdaError::InvalidResponseInterruptCount(response_count));
+        }
+        self.rintcnt.set_field(Rintcnt::RESPONSE_INTERRUPT_COUNT, (response_count % MAX_RESPONSE_INTERRUPT_COUNT) as u32)
+    }
+
+    fn response_interrupt_count(&self) -> u16 {
+        match self.rintcnt.field(Rintcnt::RESPONSE_INTERRUPT_COUNT) {
+            0 => 256,
+            count => count as u16,
+        }
+    }
+
+    // ########## RIRBCTL ##########
+
+     fn response_interrupt_control_bit(&self) -> bool {
+        self.rirbctl.is_set(Rirbctl::RESPONSE_INTERRUPT_CONTROL)
+    }
+
+     fn set_response_interrupt_control_bit(&self) {
+        self.rirbctl.set(Rirbctl::RESPONSE_INTERRUPT_CONTROL);
+    }
+
+     pub fn clear_response_interrupt_control_bit(&self) {
+        self.rirbctl.clear(Rirbctl::RESPONSE_INTERRUPT_CONTROL);
+    }
+
+     pub fn rirb_dma_enable_bit(&self) -> bool {
+        self.rirbctl.is_set(Rirbctl::DMA_ENABLE)
+    }
+
+     fn start_rirb_dma(&self) {
+        self.rirbctl.set(Rirbctl::DMA_ENABLE);
+    }
+
+     pub fn stop_rirb_dma(&self) {
+        self.rirbctl.clear(Rirbctl::DMA_ENABLE);
+    }
+
+     fn response_overrun_interrupt_control_bit(&self) -> bool {
+        self.rirbctl.is_set(Rirbctl::RESPONSE_OVERRUN_INTERRUPT_CONTROL)
+    }
+
+     fn set_response_overrun_interrupt_control_bit(&self) {
+        self.rirbctl.set(Rirbctl::RESPONSE_OVERRUN_INTERRUPT_CONTROL);
+    }
+
+     pub fn clear_response_overrun_interrupt_control_bit(&self) {
+        self.rirbctl.clear(Rirbctl::RESPONSE_OVERRUN_INTERRUPT_CONTROL);
+    }
+
+    // ########## RIRBSTS ##########
+
+    fn response_interrupt_flag(&self) -> bool {
+        self.rirbsts.is_set(Rirbsts::RESPONSE_INTERRUPT)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.30)
+    fn clear_response_interrupt_flag(&self) {
+        self.rirbsts.acknowledge(Rirbsts::RESPONSE_INTERRUPT);
+    }
+
+    fn response_overrun_interrupt_status_bit(&self) -> bool {
+        self.rirbsts.is_set(Rirbsts::RESPONSE_OVERRUN_INTERRUPT_STATUS)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.30)
+    fn clear_response_overrun_interrupt_status_bit(&self) {
+        self.rirbsts.acknowledge(Rirbsts::RESPONSE_OVERRUN_INTERRUPT_STATUS);
+    }
+
+    // ########## RIRBSIZE ##########
+
+     fn rirb_size_in_entries(&self) -> Result<RingbufferSize, IhdaError> {
+        // RIRBSIZE uses the same encoding as CORBSIZE (see specification, section 3.3.31)
+        match self.rirbsize.field(RingSize::SIZE) {
+            0b00 => Ok(RingbufferSize::TwoEntries),
+            0b01 => Ok(RingbufferSize::SixteenEntries),
+            0b10 => Ok(RingbufferSize::TwoHundredFiftySixEntries),
+            size => Err(IhdaError::InvalidRingbufferSize("RIRB", size as u8)),
+        }
+    }
+
+     fn set_rirb_size_in_entries(&self, rirb_size: RingbufferSize) -> Result<(), IhdaError> {
+        match rirb_size {
+            RingbufferSize::TwoEntries => self.rirbsize.set_field(RingSize::SIZE, 0b00),
+            RingbufferSize::SixteenEntries => self.rirbsize.set_field(RingSize::SIZE, 0b01),
+            RingbufferSize::TwoHundredFiftySixEntries => self.rirbsize.set_field(RingSize::SIZE, 0b10),
+        }
+    }
+
+     fn rirb_size_capability(&self) -> RingbufferCapability {
+        RingbufferCapability::new(
+            self.rirbsize.is_set(RingSize::TWO_ENTRIES_CAPABILITY),
+            self.rirbsize.is_set(RingSize::SIXTEEN_ENTRIES_CAPABILITY),
+            self.rirbsize.is_set(RingSize::TWO_HUNDRED_FIFTY_SIX_ENTRIES_CAPABILITY),
+        )
+    }
+
+    // ########## RIRB pointer arithmetic ##########
+
+    // RIRBSIZE gets validated in fn init_rirb, but a controller that got reset or removed meanwhile reads back an invalid size
+    fn rirb_entries(&self) -> Result<u16, IhdaError> {
+        Ok(self.rirb_size_in_entries()?.as_u16())
+    }
+
+    // the RIRB has no read pointer register, so software needs to remember the index of the last entry it has read
+    // RIRBWP points to the last entry written by the controller, so all entries after last_read_index up to RIRBWP are unread
+    fn rirb_unread_entries(&self, last_read_index: u8, rirb_entries: u16) -> u16 {
+        ring_distance(last_read_index, self.rirb_write_pointer(), rirb_entries)
+    }
+
+    fn read_response_from_rirb(&self, index: u8) -> u64 {
+        unsafe { ((self.rirb_address() + (index as u64 * RIRB_ENTRY_SIZE_IN_BYTES)) as *mut u64).read() }
+    }
+
+    pub fn init_rirb(&self) -> Result<(), IhdaError> {
+        self.stop_rirb_dma();
+        self.clear_response_interrupt_control_bit();
+        self.clear_response_overrun_interrupt_control_bit();
+
+        // use the largest RIRB size the controller supports, the pointer arithmetic on the ring relies on a valid RIRB size
+        let rirb_size = self.rirb_size_capability().largest_supported_size().ok_or(IhdaError::UnsupportedRingbufferSize("RIRB", 0))?;
+        self.set_rirb_size_in_entries(rirb_size)?;
+        let rirb_entries = self.rirb_size_in_entries()?.as_u16();
+
+        // interrupt at the latest when half of the ring is filled, so that the interrupt handler can consume the responses before
+        // the controller overruns the ring
+        self.set_response_interrupt_count((rirb_entries / 2).clamp(1, MAX_RESPONSE_INTERRUPT_COUNT))?;
+        debug!("RIRB with {} entries raises a response interrupt after at most {} responses", rirb_entries, self.response_interrupt_count());
+
+        // setup MMIO space for Response Inbound Ring Buffer – RIRB
+        // the memory of a previous RIRB gets freed here, after its DMA engine has been stopped above
+        let rirb_memory = alloc_dma(4, CachePolicy::Uncached);
+        self.set_rirb_address(rirb_memory.start_frame());
+        *self.rirb_memory.lock() = Some(rirb_memory);
+
+        self.reset_rirb_write_pointer();
+        Ok(())
+    }
+
+    pub fn start_rirb(&self) {
+        // catch misaligned or uninitialized rings before the DMA engine starts writing responses into them
+        assert_ring_buffer_alignment(self.rirb_address(), "RIRB");
+        assert_ne!(self.rirb_address(), 0, "RIRB address not set before starting RIRB DMA engine");
+
+        self.set_response_interrupt_control_bit();
+        self.set_response_overrun_interrupt_control_bit();
+        self.start_rirb_dma();
+    }
+
+    // Sends the same command twice through the CORB and checks that both responses arrived in the RIRB and match.
+    pub fn test_corb_and_rirb(&self) -> Result<(), CorbRirbTestError> {
+        self.dump_corb_and_rirb_entries();
+
+        // place two commands in CORB
+        // CAREFUL: the very first command sent via CORB must be placed at index 1 (not index 0!), see specification, section 4.4.1
+        // write_command_to_corb() always writes to the entry following CORBWP, which takes care of this after a CORBWP reset
+        let last_read_rirb_index = self.rirb_write_pointer();
+        self.write_command_to_corb(GetParameter(NodeAddress::new(CodecAddress::new(0), 0), VendorId))?;
+        self.write_command_to_corb(GetParameter(NodeAddress::new(CodecAddress::new(0), 0), VendorId))?;
+        Timer::wait(200);
+
+        // both commands should have been fetched by the controller and both responses should have arrived
+        if !self.corb_is_empty()? {
+            return Err(CorbRirbTestError::CommandsNotFetched);
+        }
+        let rirb_entries = self.rirb_entries()?;
+        let unread_entries = self.rirb_unread_entries(last_read_rirb_index, rirb_entries);
+        if unread_entries != 2 {
+            return Err(CorbRirbTestError::UnexpectedResponseCount(unread_entries));
+        }
+
+        // read responses from RIRB
+        let first_response_index = next_ring_index(last_read_rirb_index, rirb_entries);
+        let first_response = self.read_response_from_rirb(first_response_index);
+        let second_response = self.read_response_from_rirb(next_ring_index(first_response_index, rirb_entries));
+        self.dump_corb_and_rirb_entries();
+
+        // as the commands sent were identical, the responses should be as well
+        if first_response != second_response {
+            return Err(CorbRirbTestError::ResponsesDiffer(first_response, second_response));
+        }
+        // as the command sent (get parameter vendor ID) was a legit command for the root node of a codec, the response should not be 0
+        if first_response == 0 {
+            return Err(CorbRirbTestError::EmptyResponse);
+        }
+
+        Ok(())
+    }
+
+    fn dump_corb_and_rirb_entries(&self) {
+        for index in 0..4 {
+            unsafe { debug!("CORB entry {}: {:#x}", index, ((self.corb_address() + index * CORB_ENTRY_SIZE_IN_BYTES) as *mut u32).read()); }
+        }
+        for index in 0..4 {
+            unsafe { debug!("RIRB entry {}: {:#x}", index, ((self.rirb_address() + index * RIRB_ENTRY_SIZE_IN_BYTES) as *mut u64).read()); }
+        }
+        self.corbwp.dump();
+        self.corbrp.dump();
+        self.rirbwp.dump();
+    }
+
+    // ########## command ring (CORB/RIRB based command transport) ##########
+
+    // Selects the command transport all verbs get sent through, which is the same for the codec scan and everything afterwards.
+    // CORB/RIRB is preferred, as unsolicited responses only arrive through the RIRB. If the rings can't be started or the first
+    // codec reporting its presence in STATESTS doesn't answer through them, the optional immediate command registers get used,
+    // as long as that codec answers through them. Without any codec, the transport can't be verified and stays immediate.
+    pub fn select_command_transport(&self) -> Result<CommandTransportKind, IhdaError> {
+        *self.transport.lock() = CommandTransportKind::Immediate;
+        let rings_running = match self.start_command_rings() {
+            Ok(()) => true,
+            Err(error) => {
+                warn!("IHDA CORB/RIRB could not be started ({:?}), falling back to immediate commands", error);
+                false
+            }
+        };
+
+        let probe_codec_address = match (0..MAX_AMOUNT_OF_CODECS).find(|codec_address| self.sdin_state_change_status_bit(*codec_address)) {
+            Some(codec_address) => codec_address,
+            None => {
+                warn!("No IHDA codec present, command transport could not be verified");
+                return Ok(CommandTransportKind::Immediate);
+            }
+        };
+        if rings_running && self.verify_command_ring(probe_codec_address) {
+            return Ok(CommandTransportKind::CorbRirb);
+        }
+
+        let probe = GetParameter(NodeAddress::new(CodecAddress::new(probe_codec_address), 0), VendorId);
+        if self.send_immediate_command(probe).is_none() {
+            error!("IHDA codec {} answers neither through CORB/RIRB nor through the immediate command registers", probe_codec_address);
+            return Err(IhdaError::NoCommandTransport);
+        }
+        info!("IHDA commands get sent through the immediate command registers");
+        Ok(CommandTransportKind::Immediate)
+    }
+
+    fn start_command_rings(&self) -> Result<(), IhdaError> {
+        // waits for a command that is still being sent through the CORB
+        let mut command_ring = self.command_ring.lock();
+        self.init_corb()?;
+        self.init_rirb()?;
+        self.start_corb()?;
+        self.start_rirb();
+        *command_ring = CommandRing::new();
+        command_ring.last_read_rirb_index = self.rirb_write_pointer();
+        Ok(())
+    }
+
+    // Stops and sets up CORB and RIRB again, e.g. after the controller reported a CORB memory error (see specification, section 3.3.23).
+    // Commands get sent through the immediate command registers meanwhile. STATESTS has been cleared at runtime, so the command ring
+    // gets verified with a codec known to be present.
+    pub fn restart_command_rings(&self, probe_codec_address: u8) -> Result<(), IhdaError> {
+        *self.transport.lock() = CommandTransportKind::Immediate;
+        self.start_command_rings()?;
+        self.verify_command_ring(probe_codec_address);
+        Ok(())
+    }
+
+    // switches the command transport to CORB/RIRB if the probe verb gets answered through it
+    fn verify_command_ring(&self, probe_codec_address: u8) -> bool {
+        match self.send_command_through_corb(GetParameter(NodeAddress::new(CodecAddress::new(probe_codec_address), 0), VendorId)) {
+            Some(_) => {
+                *self.transport.lock() = CommandTransportKind::CorbRirb;
+                info!("IHDA commands get sent through CORB/RIRB");
+                true
+            }
+            None => {
+                warn!("IHDA codec {} didn't answer through CORB/RIRB, falling back to immediate commands", probe_codec_address);
+                false
+            }
+        }
+    }
+
+    pub fn command_transport(&self) -> CommandTransportKind {
+        *self.transport.lock()
+    }
+
+    // single attempt, returns None on timeout
+    pub fn send_command_through_corb(&self, command: Command) -> Option<Response> {
+        let mut command_ring = self.command_ring.lock();
+        // the controller fetches commands on its own, so a full CORB only stays full if its DMA engine stopped
+        if wait_for(|| self.corb_is_full() != Ok(true), CORB_COMMAND_TIMEOUT_IN_MS).is_err() {
+            warn!("IHDA CORB stays full, {:?}", IhdaError::Timeout(RegisterName::Corbrp));
+            return None;
+        }
+        if let Err(error) = self.write_command_to_corb(command) {
+            warn!("IHDA command {:?} could not be written to the CORB, {:?}", command, error);
+            return None;
+        }
+        self.trace_verb(VerbTraceEvent::Command(command));
+        let sequence_number = command_ring.submit(command);
+
+        let mut response = None;
+        let result = wait_for(|| {
+            response = self.consume_rirb_entries(&mut command_ring, &[sequence_number]).pop().map(|(_, response)| response);
+            response.is_some()
+        }, CORB_COMMAND_TIMEOUT_IN_MS);
+        if result.is_err() {
+            // a response arriving after this point won't be matched to a later command of the same codec
+            command_ring.abandon(sequence_number);
+            self.trace_verb(VerbTraceEvent::Timeout(command));
+        }
+        response
+    }
+
+    // Writes as many commands into the CORB at once as the CORB and the RIRB have room for and waits for all of their responses
+    // together, instead of waiting for each response before sending the next command. Meanwhile, RINTCNT holds the size of the
+    // batch, so that the controller raises a single response interrupt per batch. Returns the responses in the order of the
+    // commands, with None for every command whose response didn't arrive in time (single attempt, like fn send_command_through_corb).
+    pub fn send_commands_through_corb(&self, commands: &[Command]) -> Vec<Option<Response>> {
+        let mut command_ring = self.command_ring.lock();
+        let mut responses = Vec::with_capacity(commands.len());
+        let response_interrupt_count = self.response_interrupt_count();
+        let ring_entries = match (self.corb_entries(), self.rirb_entries()) {
+            (Ok(corb_entries), Ok(rirb_entries)) => corb_entries.min(rirb_entries),
+            (Err(error), _) | (_, Err(error)) => {
+                warn!("IHDA commands could not be sent through CORB/RIRB, {:?}", error);
+                return commands.iter().map(|_| None).collect();
+            }
+        };
+        let max_batch_length = (ring_entries - 1).min(MAX_RESPONSE_INTERRUPT_COUNT) as usize;
+
+        for batch in commands.chunks(max_batch_length) {
+            // the controller fetches commands on its own, so the CORB only stays occupied if its DMA engine stopped
+            if wait_for(|| self.corb_is_empty() != Ok(false), CORB_COMMAND_TIMEOUT_IN_MS).is_err() {
+                warn!("IHDA CORB doesn't get empty, {:?}", IhdaError::Timeout(RegisterName::Corbrp));
+                responses.extend(batch.iter().map(|_| None));
+                continue;
+            }
+
+            // the batch fits into the CORB, which is empty, so the commands can only be refused if the controller went away
+            let written = self.set_response_interrupt_count(batch.len() as u16)
+                .and_then(|_| batch.iter().try_for_each(|command| self.write_command_to_corb(*command).map(|_| ())));
+            if let Err(error) = written {
+                warn!("IHDA commands could not be written to the CORB, {:?}", error);
+                responses.extend(batch.iter().map(|_| None));
+                continue;
+            }
+            let sequence_numbers: Vec<u32> = batch.iter().map(|command| {
+                self.trace_verb(VerbTraceEvent::Command(*command));
+                command_ring.submit(*command)
+            }).collect();
+
+            let mut batch_responses: Vec<Option<Response>> = batch.iter().map(|_| None).collect();
+            let mut missing_responses = batch.len();
+            let result = wait_for(|| {
+                for (sequence_number, response) in self.consume_rirb_entries(&mut command_ring, &sequence_numbers) {
+                    let index = sequence_numbers.iter().position(|number| *number == sequence_number).unwrap();
+                    batch_responses[index] = Some(response);
+                    missing_responses -= 1;
+                }
+                missing_responses == 0
+            }, CORB_COMMAND_TIMEOUT_IN_MS);
+            if result.is_err() {
+                // responses arriving after this point won't be matched to later commands of the same codecs
+                for ((sequence_number, response), command) in sequence_numbers.iter().zip(batch_responses.iter()).zip(batch.iter()) {
+                    if response.is_none() {
+                        command_ring.abandon(*sequence_number);
+                        self.trace_verb(VerbTraceEvent::Timeout(*command));
+                    }
+                }
+            }
+            responses.extend(batch_responses);
+        }
+
+        if let Err(error) = self.set_response_interrupt_count(response_interrupt_count) {
+            warn!("IHDA response interrupt count could not be restored, {:?}", error);
+        }
+        responses
+    }
+
+    // Reads all RIRB entries written since the last call. Solicited responses get matched to the oldest outstanding command of the
+    // same codec, as each codec answers its commands in order (see specification, section 4.4.2). Unsolicited responses get queued.
+    // Returns the responses of the commands with the given sequence numbers, which were among the consumed entries.
+    fn consume_rirb_entries(&self, command_ring: &mut CommandRing, sequence_numbers: &[u32]) -> Vec<(u32, Response)> {
+        if self.response_overrun_interrupt_status_bit() {
+            warn!("IHDA RIRB overrun, responses got lost");
+            self.clear_response_overrun_interrupt_status_bit();
+        }
+
+        let mut matching_responses = Vec::new();
+        let rirb_entries = match self.rirb_entries() {
+            Ok(rirb_entries) => rirb_entries,
+            Err(error) => {
+                warn!("IHDA RIRB could not be read, {:?}", error);
+                return matching_responses;
+            }
+        };
+        while self.rirb_unread_entries(command_ring.last_read_rirb_index, rirb_entries) > 0 {
+            let index = next_ring_index(command_ring.last_read_rirb_index, rirb_entries);
+            let entry = self.read_response_from_rirb(index);
+            command_ring.last_read_rirb_index = index;
+
+            let raw_response = entry as u32;
+            let response_extended = (entry >> 32) as u32;
+            let codec_address = (response_extended & 0xF) as u8;
+            let unsolicited = (response_extended >> 4) & 1 == 1;
+            self.trace_verb(VerbTraceEvent::Response { codec_address, raw_response, unsolicited });
+            if unsolicited {
+                command_ring.unsolicited_responses.push_back(UnsolicitedResponse::new(codec_address, raw_response));
+                continue;
+            }
+
+            match command_ring.complete(codec_address) {
+                Some(outstanding) if sequence_numbers.contains(&outstanding.sequence_number) => {
+                    matching_responses.push((outstanding.sequence_number, Response::new(RawResponse::new(raw_response), outstanding.command)));
+                }
+                Some(outstanding) => debug!("Discarding IHDA response {:#x} to command {:?}", raw_response, outstanding.command),
+                None => debug!("Discarding IHDA response {:#x} of codec {} without outstanding command", raw_response, codec_address),
+            }
+        }
+        if self.response_interrupt_flag() {
+            self.clear_response_interrupt_flag();
+        }
+
+        matching_responses
+    }
+
+    // returns all unsolicited responses received since the last call
+    pub fn take_unsolicited_responses(&self) -> Vec<UnsolicitedResponse> {
+        let mut command_ring = self.command_ring.lock();
+        if self.command_transport() == CommandTransportKind::CorbRirb {
+            self.consume_rirb_entries(&mut command_ring, &[]);
+        }
+        command_ring.unsolicited_responses.drain(..).collect()
+    }
+
+    // ########## error status ##########
+
+    // Reads and clears the error status bits of the CORB (CORBSTS) and the RIRB (RIRBSTS). Gets called by the interrupt handler,
+    // so it only collects the errors and leaves the recovery to the caller. Errors of the stream descriptors get collected by
+    // fn take_stream_interrupts. May be called from interrupt context, as it doesn't take any locks.
+    pub fn take_errors(&self) -> ControllerErrors {
+        let mut errors = ControllerErrors::default();
+        if self.corb_memory_error_indication_bit() {
+            self.clear_corb_memory_error_indication_bit();
+            errors.corb_memory_error = true;
+        }
+        if self.response_overrun_interrupt_status_bit() {
+            self.clear_response_overrun_interrupt_status_bit();
+            errors.response_overrun = true;
+        }
+        errors
+    }
+}
+
+// failed check of fn test_corb_and_rirb
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CorbRirbTestError {
+    // the controller didn't fetch both commands from the CORB
+    CommandsNotFetched,
+    // amount of responses which arrived in the RIRB instead of 2
+    UnexpectedResponseCount(u16),
+    ResponsesDiffer(u64, u64),
+    EmptyResponse,
+    Device(IhdaError),
+}
+
+impl From<IhdaError> for CorbRirbTestError {
+    fn from(error: IhdaError) -> Self {
+        CorbRirbTestError::Device(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct OutstandingCommand {
+    sequence_number: u32,
+    command: Command,
+}
+
+// software side of the CORB/RIRB command path
+pub struct CommandRing {
+    // the RIRB has no read pointer register, so software needs to remember the index of the last entry it has read
+    last_read_rirb_index: u8,
+    next_sequence_number: u32,
+    // commands written to the CORB whose responses have not arrived yet, in the order they were sent
+    outstanding_commands: VecDeque<OutstandingCommand>,
+    unsolicited_responses: VecDeque<UnsolicitedResponse>,
+}
+
+impl CommandRing {
+    pub const fn new() -> Self {
+        Self {
+            last_read_rirb_index: 0,
+            next_sequence_number: 0,
+            outstanding_commands: VecDeque::new(),
+            unsolicited_responses: VecDeque::new(),
+        }
+    }
+
+    fn submit(&mut self, command: Command) -> u32 {
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number = self.next_sequence_number.wrapping_add(1);
+        self.outstanding_commands.push_back(OutstandingCommand { sequence_number, command });
+        sequence_number
+    }
+
+    // removes the oldest outstanding command of the codec which sent a response
+    fn complete(&mut self, codec_address: u8) -> Option<OutstandingCommand> {
+        let index = self.outstanding_commands.iter().position(|outstanding| outstanding.command.codec_address() == codec_address)?;
+        self.outstanding_commands.remove(index)
+    }
+
+    fn abandon(&mut self, sequence_number: u32) {
+        self.outstanding_commands.retain(|outstanding| outstanding.sequence_number != sequence_number);
+    }
+}
+
+// Unsolicited responses carry a tag chosen by software in bits [31:26] to identify the widget that sent them (see specification, section 7.3.3.14).
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct UnsolicitedResponse {
+    codec_address: u8,
+    tag: u8,
+    payload: u32,
+}
+
+impl UnsolicitedResponse {
+    fn new(codec_address: u8, raw_response: u32) -> Self {
+        Self {
+            codec_address,
+            tag: (raw_response >> 26) as u8,
+            payload: raw_response & 0x3FF_FFFF,
+        }
+    }
+}
+
+// error conditions of the command rings collected by fn take_errors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Getters)]
+pub struct ControllerErrors {
+    // the CORB DMA engine couldn't read a command from memory (see specification, section 3.3.23)
+    corb_memory_error: bool,
+    // the RIRB was full, so responses got lost (see specification, section 3.3.30)
+    response_overrun: bool,
+}
+
+impl ControllerErrors {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RingbufferSize {
+    TwoEntries,
+    SixteenEntries,
+    TwoHundredFiftySixEntries,
+}
+
+impl RingbufferSize {
+    fn as_u16(&self) -> u16 {
+        match self {
+            RingbufferSize::TwoEntries => 2,
+            RingbufferSize::SixteenEntries => 16,
+            RingbufferSize::TwoHundredFiftySixEntries => 256,
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+struct RingbufferCapability {
+    support_2_entries: bool,
+    support_16_entries: bool,
+    support_256_entries: bool,
+}
+
+impl RingbufferCapability {
+    fn new(support_two_entries: bool, support_sixteen_entries: bool, support_two_hundred_fifty_six_entries: bool) -> Self {
+        Self {
+            support_2_entries: support_two_entries,
+            support_16_entries: support_sixteen_entries,
+            support_256_entries: support_two_hundred_fifty_six_entries,
+        }
+    }
+
+    // bigger rings let more commands be in flight and more responses be buffered between two interrupts
+    fn largest_supported_size(&self) -> Option<RingbufferSize> {
+        if self.support_256_entries {
+            Some(RingbufferSize::TwoHundredFiftySixEntries)
+        } else if self.support_16_entries {
+            Some(RingbufferSize::SixteenEntries)
+        } else if self.support_2_entries {
+            Some(RingbufferSize::TwoEntries)
+        } else {
+            None
+        }
+    }
+}
+
+// index of the entry following index in a ring buffer with the given amount of entries
+fn next_ring_index(index: u8, entries: u16) -> u8 {
+    ((index as u16 + 1) % entries) as u8
+}
+
+// amount of entries to advance from index "from" until index "to" is reached in a ring buffer with the given amount of entries
+fn ring_distance(from: u8, to: u8, entries: u16) -> u16 {
+    (to as u16 + entries - from as u16) % entries
+}
+
+pub fn assert_ring_buffer_alignment(address: u64, name: &str) {
+    if address % RING_BUFFER_ALIGNMENT_IN_BYTES != 0 {
+        panic!("{} base address {:#x} is not {}-byte aligned", name, address, RING_BUFFER_ALIGNMENT_IN_BYTES);
+    }
+}
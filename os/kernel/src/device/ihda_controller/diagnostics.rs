@@ -0,0 +1,319 @@
+use alloc::string::String;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+use derive_getters::Getters;
+use log::{debug, info};
+use crate::device::pit::Timer;
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_controller::{Controller, IhdaError};
+use crate::device::ihda_controller::stream_descriptor::PositionSource;
+use crate::device::ihda_stream::Stream;
+use crate::device::ihda_verbs::Command;
+
+// entries of the verb trace, the oldest entry gets dropped when it is full
+const VERB_TRACE_CAPACITY: usize = 1024;
+// amount of samples and the pause between them, that get used to compare SDLPIB with the DMA position buffer during controller setup
+const POSITION_DIAGNOSTICS_SAMPLE_COUNT: u32 = 16;
+const POSITION_DIAGNOSTICS_INTERVAL_IN_MS: usize = 10;
+
+impl Controller {
+    // ########## DMA position buffer test ##########
+
+    pub fn test_dma_position_buffer(&self) -> Result<(), IhdaError> {
+        // start first output dma engine
+        let stream = Stream::new(
+            self.output_stream_descriptors.get(0).ok_or(IhdaError::NoOutputStreamDescriptor)?,
+            self.allocate_stream(self.output_stream_descriptor_number(0))?,
+            AudioFormat::stereo_48khz_16bit(),
+            2,
+            512)?;
+        stream.run();
+
+        Timer::wait(100);
+
+        for i in 0..self.number_of_output_streams_supported() {
+            debug!("dma_position_in_buffer of output stream descriptor [{}]: {:#x}", i, self.stream_descriptor_position_in_current_buffer((self.number_of_input_streams_supported() + i) as u32));
+        }
+
+        // monitor position of first dma engine two times with a little pause in between
+        let stream_position_a = self.stream_descriptor_position_in_current_buffer(self.number_of_input_streams_supported() as u32);
+        Timer::wait(100);
+        let stream_position_b = self.stream_descriptor_position_in_current_buffer(self.number_of_input_streams_supported() as u32);
+
+        for i in 0..self.number_of_output_streams_supported() {
+            debug!("dma_position_in_buffer of output stream descriptor [{}]: {:#x}", i, self.stream_descriptor_position_in_current_buffer((self.number_of_input_streams_supported() + i) as u32));
+        }
+
+        // only the first dma engine should be running
+        assert_ne!(stream_position_a, 0);
+        assert_ne!(stream_position_a, stream_position_b);
+        // the positions of all other dma engines should be 0
+        for i in 1..self.number_of_output_streams_supported() {
+            assert_eq!(self.stream_descriptor_position_in_current_buffer((self.number_of_input_streams_supported() + i) as u32), 0);
+        }
+
+        self.diagnose_position_sources(
+            self.number_of_input_streams_supported() as u32,
+            POSITION_DIAGNOSTICS_SAMPLE_COUNT,
+            POSITION_DIAGNOSTICS_INTERVAL_IN_MS);
+
+        stream.reset()
+    }
+
+    // ########## register dump ##########
+
+    // current values of all controller registers, followed by the registers of each stream descriptor
+    pub fn dump_registers(&self) -> String {
+        let mut dump = String::new();
+        writeln!(dump, "Controller").unwrap();
+        self.gcap.dump_to(&mut dump);
+        self.vmin.dump_to(&mut dump);
+        self.vmaj.dump_to(&mut dump);
+        self.outpay.dump_to(&mut dump);
+        self.inpay.dump_to(&mut dump);
+        self.gctl.dump_to(&mut dump);
+        self.wakeen.dump_to(&mut dump);
+        self.wakests.dump_to(&mut dump);
+        self.gsts.dump_to(&mut dump);
+        if let Some(gcap2) = self.gcap2.as_ref() {
+            gcap2.dump_to(&mut dump);
+        }
+        self.outstrmpay.dump_to(&mut dump);
+        self.instrmpay.dump_to(&mut dump);
+        self.intctl.dump_to(&mut dump);
+        self.intsts.dump_to(&mut dump);
+        self.walclk.dump_to(&mut dump);
+        self.ssync.dump_to(&mut dump);
+        self.corblbase.dump_to(&mut dump);
+        self.corbubase.dump_to(&mut dump);
+        self.corbwp.dump_to(&mut dump);
+        self.corbrp.dump_to(&mut dump);
+        self.corbctl.dump_to(&mut dump);
+        self.corbsts.dump_to(&mut dump);
+        self.corbsize.dump_to(&mut dump);
+        self.rirblbase.dump_to(&mut dump);
+        self.rirbubase.dump_to(&mut dump);
+        self.rirbwp.dump_to(&mut dump);
+        self.rintcnt.dump_to(&mut dump);
+        self.rirbctl.dump_to(&mut dump);
+        self.rirbsts.dump_to(&mut dump);
+        self.rirbsize.dump_to(&mut dump);
+        self.icoi.dump_to(&mut dump);
+        self.icii.dump_to(&mut dump);
+        self.icsts.dump_to(&mut dump);
+        self.dpiblbase.dump_to(&mut dump);
+        self.dpibubase.dump_to(&mut dump);
+
+        let stream_descriptors = self.input_stream_descriptors.iter().map(|registers| ("input", registers))
+            .chain(self.output_stream_descriptors.iter().map(|registers| ("output", registers)))
+            .chain(self.bidirectional_stream_descriptors.iter().map(|registers| ("bidirectional", registers)));
+        for (stream_descriptor_number, (direction, registers)) in stream_descriptors.enumerate() {
+            match registers.bidirectional_stream_direction() {
+                Some(current_direction) => writeln!(dump, "\nStream descriptor {} ({}, currently {:?})", stream_descriptor_number, direction, current_direction).unwrap(),
+                None => writeln!(dump, "\nStream descriptor {} ({})", stream_descriptor_number, direction).unwrap(),
+            }
+            registers.dump_to(&mut dump);
+        }
+
+        dump
+    }
+
+    // ########## position source diagnostics ##########
+
+    // Samples SDLPIB and the DMA position buffer entry of a running stream and compares both position sources.
+    // The specification expects both values to be (nearly) equal, but some chipsets don't update the DMA position buffer
+    // reliably or report positions which are off by more than the FIFO size, so that the other source should be trusted instead.
+    // The result gets used to set the position source of the controller.
+    pub fn diagnose_position_sources(&self, stream_descriptor_number: u32, sample_count: u32, interval_in_ms: usize) -> PositionSourceDiagnostics {
+        let sd_registers = self.stream_descriptor_registers(stream_descriptor_number);
+        if !sd_registers.stream_run_bit() {
+            panic!("Position sources can only be diagnosed for a running stream, but stream descriptor [{}] is stopped", stream_descriptor_number);
+        }
+        if sample_count < 2 {
+            panic!("At least two samples are needed to diagnose position sources, but only {} requested", sample_count);
+        }
+
+        let cyclic_buffer_length = sd_registers.cyclic_buffer_lenght();
+        let mut lpib_samples = Vec::new();
+        let mut dpib_samples = Vec::new();
+        for _ in 0..sample_count {
+            lpib_samples.push(sd_registers.link_position_in_buffer());
+            dpib_samples.push(self.stream_descriptor_position_in_current_buffer(stream_descriptor_number));
+            Timer::wait(interval_in_ms);
+        }
+
+        let divergences: Vec<u32> = lpib_samples.iter().zip(dpib_samples.iter())
+            .map(|(lpib, dpib)| position_divergence(*lpib, *dpib, cyclic_buffer_length))
+            .collect();
+        let lpib_deltas = position_deltas(&lpib_samples, cyclic_buffer_length);
+        let dpib_deltas = position_deltas(&dpib_samples, cyclic_buffer_length);
+
+        // a DMA position buffer entry that doesn't move while SDLPIB does is the most common quirk
+        let dpib_stalls = lpib_deltas.iter().zip(dpib_deltas.iter()).filter(|(lpib_delta, dpib_delta)| **lpib_delta != 0 && **dpib_delta == 0).count() as u32;
+        let lpib_stalls = lpib_deltas.iter().zip(dpib_deltas.iter()).filter(|(lpib_delta, dpib_delta)| **lpib_delta == 0 && **dpib_delta != 0).count() as u32;
+        let max_divergence_in_bytes = *divergences.iter().max().unwrap();
+
+        // both sources may legitimately differ by the amount of data currently held in the FIFO of the DMA engine
+        let tolerated_divergence_in_bytes = sd_registers.fifo_size() as u32 + 1;
+        let recommended_source = if dpib_stalls > 0 || (max_divergence_in_bytes > tolerated_divergence_in_bytes && lpib_stalls == 0) {
+            PositionSource::LinkPositionInBuffer
+        } else {
+            PositionSource::DmaPositionBuffer
+        };
+
+        let diagnostics = PositionSourceDiagnostics {
+            stream_descriptor_number,
+            sample_count,
+            interval_in_ms,
+            max_divergence_in_bytes,
+            average_divergence_in_bytes: divergences.iter().sum::<u32>() / sample_count,
+            tolerated_divergence_in_bytes,
+            lpib_jitter_in_bytes: jitter(&lpib_deltas),
+            dpib_jitter_in_bytes: jitter(&dpib_deltas),
+            lpib_stalls,
+            dpib_stalls,
+            recommended_source,
+        };
+        debug!("Position source diagnostics: {:?}", diagnostics);
+
+        if recommended_source != self.position_source() {
+            info!("Switching stream position source from {:?} to {:?}", self.position_source(), recommended_source);
+            self.set_position_source(recommended_source);
+        }
+
+        diagnostics
+    }
+
+    // ########## verb trace ##########
+
+    pub fn is_verb_trace_enabled(&self) -> bool {
+        self.verb_trace_enabled.load(Ordering::Relaxed)
+    }
+
+    // Enabling the trace drops the entries of an earlier trace, disabling it keeps them for fn last_verb_trace_entries.
+    pub fn set_verb_trace_enabled(&self, enabled: bool) {
+        let mut verb_trace = self.verb_trace.lock();
+        if enabled && !self.is_verb_trace_enabled() {
+            verb_trace.clear();
+        }
+        self.verb_trace_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    // up to the given amount of the most recent entries, oldest first
+    pub fn last_verb_trace_entries(&self, amount: usize) -> Vec<VerbTraceEntry> {
+        self.verb_trace.lock().last_entries(amount)
+    }
+
+    pub fn trace_verb(&self, event: VerbTraceEvent) {
+        if self.is_verb_trace_enabled() {
+            let entry = VerbTraceEntry { wall_clock: self.wall_clock_counter(), event };
+            self.verb_trace.lock().record(entry);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum VerbTraceEvent {
+    // verb written to the CORB or to ICOI
+    Command(Command),
+    // entry read from the RIRB or response read from ICII
+    Response { codec_address: u8, raw_response: u32, unsolicited: bool },
+    // the response to the verb didn't arrive in time, so it won't be matched to the verb anymore
+    Timeout(Command),
+}
+
+// event of the verb trace with the value of WALCLK at the time it happened
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct VerbTraceEntry {
+    wall_clock: u32,
+    event: VerbTraceEvent,
+}
+
+// Ring of the most recent verbs and responses of all codecs, to compare the communication with a codec to traces of other
+// drivers (e.g. the hda_send_cmd and hda_get_response trace events of Linux).
+pub struct VerbTrace {
+    entries: VecDeque<VerbTraceEntry>,
+}
+
+impl VerbTrace {
+    pub const fn new() -> Self {
+        Self { entries: VecDeque::new() }
+    }
+
+    fn record(&mut self, entry: VerbTraceEntry) {
+        if self.entries.len() == VERB_TRACE_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    fn last_entries(&self, amount: usize) -> Vec<VerbTraceEntry> {
+        self.entries.iter().skip(self.entries.len().saturating_sub(amount)).copied().collect()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// All values in bytes are distances inside the cyclic buffer of the diagnosed stream.
+// Jitter is the difference between the largest and the smallest position change between two consecutive samples.
+#[derive(Debug, Getters)]
+pub struct PositionSourceDiagnostics {
+    stream_descriptor_number: u32,
+    sample_count: u32,
+    interval_in_ms: usize,
+    max_divergence_in_bytes: u32,
+    average_divergence_in_bytes: u32,
+    tolerated_divergence_in_bytes: u32,
+    lpib_jitter_in_bytes: u32,
+    dpib_jitter_in_bytes: u32,
+    lpib_stalls: u32,
+    dpib_stalls: u32,
+    recommended_source: PositionSource,
+}
+
+// shortest distance between two positions in a cyclic buffer, as one source might already have wrapped around while the other has not
+fn position_divergence(a: u32, b: u32, cyclic_buffer_length: u32) -> u32 {
+    let distance = a.abs_diff(b) % cyclic_buffer_length.max(1);
+    distance.min(cyclic_buffer_length - distance)
+}
+
+// distance moved forward between consecutive samples; the samples get reduced into the cyclic buffer first, as a register
+// might report a position beyond its length
+fn position_deltas(samples: &[u32], cyclic_buffer_length: u32) -> Vec<u32> {
+    let length = cyclic_buffer_length.max(1);
+    samples.windows(2)
+        .map(|pair| {
+            let (from, to) = (pair[0] % length, pair[1] % length);
+            if to >= from { to - from } else { length - from + to }
+        })
+        .collect()
+}
+
+fn jitter(deltas: &[u32]) -> u32 {
+    match (deltas.iter().max(), deltas.iter().min()) {
+        (Some(max), Some(min)) => max - min,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_deltas_wrap_around() {
+        assert_eq!(position_deltas(&[0, 100, 300], 4096), vec![100, 200]);
+        assert_eq!(position_deltas(&[4000, 104], 4096), vec![200]);
+    }
+
+    #[test]
+    fn position_deltas_with_positions_beyond_the_buffer() {
+        assert_eq!(position_deltas(&[5000, 4096], 4096), vec![3192]);
+        assert_eq!(position_deltas(&[u32::MAX, 10], u32::MAX), vec![10]);
+        assert_eq!(position_deltas(&[100, 200], 0), vec![0]);
+    }
+}
@@ -0,0 +1,919 @@
+#![allow(dead_code)]
+
+pub mod corb_rirb;
+pub mod diagnostics;
+pub mod registers;
+pub mod stream_descriptor;
+
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::AtomicBool;
+use log::{debug, info, warn};
+use spin::Mutex;
+use derive_getters::Getters;
+use x86_64::{PhysAddr, VirtAddr};
+use crate::device::pit::Timer;
+use crate::timer;
+use crate::device::ihda_codec::{Codec, CommandError, CommandTransport};
+use crate::device::ihda_verbs::{CodecAddress, Command, MAX_AMOUNT_OF_CODECS, NodeAddress, RawResponse, Response, VendorIdResponse};
+use crate::device::ihda_stream::{BufferDescriptorListError, StreamBackend};
+use crate::device::ihda_verbs::Command::GetParameter;
+use crate::device::ihda_verbs::Parameter::VendorId;
+use crate::memory::dma::DmaRegion;
+use crate::device::ihda_controller::corb_rirb::CommandRing;
+use crate::device::ihda_controller::diagnostics::{VerbTrace, VerbTraceEvent};
+use crate::device::ihda_controller::registers::{Corbctl, Corbrp, Corbsts, Dplbase, Gcap, Gcap2, Gctl, Gsts, Icsts, Intctl, Intsts, Register, RingSize, RingWritePointer,
+    Rintcnt, Rirbctl, Rirbsts, Wakeen, Wakests};
+use crate::device::ihda_controller::stream_descriptor::{PositionSource, StreamAllocator, StreamDescriptorRegisters};
+
+const SOUND_DESCRIPTOR_REGISTERS_LENGTH_IN_BYTES: u64 = 0x20;
+const OFFSET_OF_FIRST_SOUND_DESCRIPTOR: u64 = 0x80;
+const OFFSET_OF_SDLPIB_ALIASES: u64 = 0x2084;
+const MAX_AMOUNT_OF_BIDIRECTIONAL_STREAMS: u8 = 30;
+const MAX_AMOUNT_OF_SDIN_SIGNALS: u8 = 15;
+const MAX_AMOUNT_OF_CHANNELS_PER_STREAM: u8 = 16;
+// TIMEOUT values arbitrarily chosen
+const BIT_ASSERTION_TIMEOUT_IN_MS: usize = 10000;
+const IMMEDIATE_COMMAND_TIMEOUT_IN_MS: usize = 100;
+// a command gets sent this many times before it counts as failed
+const COMMAND_ATTEMPTS: u8 = 3;
+// pause before a command gets sent again after an implausible response (see fn Response::is_implausible), giving the codec
+// time to finish whatever kept it from answering properly, e.g. coming out of reset
+const INVALID_RESPONSE_RETRY_DELAY_IN_MS: usize = 1;
+// a codec gets quarantined after this many consecutive failed commands, so that further commands to it fail fast instead of timing out
+const CODEC_QUARANTINE_THRESHOLD: u8 = 3;
+const DMA_POSITION_IN_BUFFER_ENTRY_SIZE_IN_BYTES: u64 = 4;
+// CORB, RIRB and DMA position buffer base addresses must be 128-byte aligned, the low 7 bits of the lower base registers are reserved
+// (see specification, sections 3.3.18, 3.3.24 and 3.3.32)
+const RING_BUFFER_ALIGNMENT_IN_BYTES: u64 = 128;
+const RING_BUFFER_ADDRESS_RESERVED_BITS: u32 = 0x7F;
+
+// (vendor id, device id) of controllers known to implement the chipset specific SDFIFOW register
+const CONTROLLERS_WITH_FIFO_WATERMARK_REGISTER: [(u16, u16); 1] = [
+    // Intel 8 Series/C220 Series Chipset (see 8-series-chipset-pch-datasheet.pdf)
+    (0x8086, 0x8c20),
+];
+
+// (vendor id, device id) of controllers known to implement the chipset specific GCAP2 register, which other controllers
+// (e.g. the ones emulated by QEMU) don't decode at all
+const CONTROLLERS_WITH_GCAP2_REGISTER: [(u16, u16); 1] = [
+    // Intel 8 Series/C220 Series Chipset (see 8-series-chipset-pch-datasheet.pdf)
+    (0x8086, 0x8c20),
+];
+
+// (vendor id, device id) of controllers which clear the Read Pointer Reset bit of CORBRP on their own as soon as the reset is done,
+// so that software never reads back the 1 the specification asks for (see section 3.3.21 and fn reset_corb_read_pointer)
+const CONTROLLERS_WITH_SELF_CLEARING_CORB_READ_POINTER_RESET: [(u16, u16); 2] = [
+    // Intel 100 Series/C230 Series Chipset (Sunrise Point-H), see AZX_DCAPS_CORBRP_SELF_CLEAR in the Linux driver
+    (0x8086, 0xa170),
+    // Intel Sunrise Point-LP
+    (0x8086, 0x9d70),
+];
+
+// capabilities of the controller which can't all be read from the registers defined in the IHDA specification,
+// as some registers are chipset specific and only exist on controllers known from their datasheets
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct ControllerCaps {
+    vendor_id: u16,
+    device_id: u16,
+    supports_64bit_bdl_addresses: bool,
+    fifo_watermark_register_available: bool,
+    gcap2_register_available: bool,
+    corb_read_pointer_reset_self_clearing: bool,
+}
+
+impl ControllerCaps {
+    fn detect(vendor_id: u16, device_id: u16, gcap: &Register<'_, u16, Gcap>) -> Self {
+        Self {
+            vendor_id,
+            device_id,
+            supports_64bit_bdl_addresses: gcap.is_set(Gcap::SUPPORTS_64BIT_ADDRESSES),
+            fifo_watermark_register_available: CONTROLLERS_WITH_FIFO_WATERMARK_REGISTER.contains(&(vendor_id, device_id)),
+            gcap2_register_available: CONTROLLERS_WITH_GCAP2_REGISTER.contains(&(vendor_id, device_id)),
+            corb_read_pointer_reset_self_clearing: CONTROLLERS_WITH_SELF_CLEARING_CORB_READ_POINTER_RESET.contains(&(vendor_id, device_id)),
+        }
+    }
+}
+
+// Representation of all IHDA registers, which may be shared between threads:
+// - commands get sent through either the immediate command registers or CORB/RIRB, each of them guarded by a lock that is held
+//   from writing a command until its response has been read, so concurrent commands can't get their responses mixed up
+// - the registers of a stream descriptor only get programmed by the stream holding its claim (see fn allocate_stream),
+//   while registers shared by all streams and changed bit by bit (INTCTL) are guarded by a lock of their own
+// - fn take_stream_interrupts, fn take_errors and fn take_codec_state_changes may be called from interrupt context,
+//   as they don't take any locks and only clear status bits by writing a 1 to them; all other functions may block on a lock
+//   held by the interrupted thread and must not be called from interrupt context
+#[derive(Getters)]
+pub struct Controller {
+    gcap: Register<'static, u16, Gcap>,
+    vmin: Register<'static, u8>,
+    vmaj: Register<'static, u8>,
+    outpay: Register<'static, u16>,
+    inpay: Register<'static, u16>,
+    gctl: Register<'static, u32, Gctl>,
+    wakeen: Register<'static, u16, Wakeen>,
+    wakests: Register<'static, u16, Wakests>,
+    gsts: Register<'static, u16, Gsts>,
+    // The register GCAP2 is only defined in 8-series-chipset-pch-datasheet.pdf for the chipset on the used testing device.
+    // As the IHDA specification doesn't mention this register at all, it only gets accessed on controllers known to implement it.
+    gcap2: Option<Register<'static, u16, Gcap2>>,
+    outstrmpay: Register<'static, u16>,
+    instrmpay: Register<'static, u16>,
+    intctl: Register<'static, u32, Intctl>,
+    intsts: Register<'static, u32, Intsts>,
+    walclk: Register<'static, u32>,
+    ssync: Register<'static, u32>,
+    corblbase: Register<'static, u32>,
+    corbubase: Register<'static, u32>,
+    corbwp: Register<'static, u16, RingWritePointer>,
+    corbrp: Register<'static, u16, Corbrp>,
+    corbctl: Register<'static, u8, Corbctl>,
+    corbsts: Register<'static, u8, Corbsts>,
+    corbsize: Register<'static, u8, RingSize>,
+    rirblbase: Register<'static, u32>,
+    rirbubase: Register<'static, u32>,
+    rirbwp: Register<'static, u16, RingWritePointer>,
+    rintcnt: Register<'static, u16, Rintcnt>,
+    rirbctl: Register<'static, u8, Rirbctl>,
+    rirbsts: Register<'static, u8, Rirbsts>,
+    rirbsize: Register<'static, u8, RingSize>,
+    icoi: Register<'static, u32>,
+    icii: Register<'static, u32>,
+    icsts: Register<'static, u16, Icsts>,
+    dpiblbase: Register<'static, u32, Dplbase>,
+    dpibubase: Register<'static, u32>,
+
+    input_stream_descriptors: Vec<StreamDescriptorRegisters>,
+    output_stream_descriptors: Vec<StreamDescriptorRegisters>,
+    bidirectional_stream_descriptors: Vec<StreamDescriptorRegisters>,
+
+    // the aliases at high adresses are used to pass information to user level applications instead of the actual registers,
+    // so that more sensible registers don't get accidentally passed, because they are on the same kernel page
+    walclk_alias: Register<'static, u32>,
+    sdlpiba_aliases: Vec<Register<'static, u32>>,
+
+    capabilities: ControllerCaps,
+    // source which gets used to determine the position of a DMA engine in its cyclic buffer (see fn stream_position)
+    stream_position_source: Mutex<PositionSource>,
+    codec_health: Mutex<[CodecHealth; MAX_AMOUNT_OF_CODECS as usize]>,
+    // verbs get sent through the CORB if it works and through the immediate command registers otherwise
+    transport: Mutex<CommandTransportKind>,
+    command_ring: Mutex<CommandRing>,
+    // held while a command is sent through the immediate command registers
+    immediate_command_interface: Mutex<()>,
+    // held while bits of INTCTL get changed, as the register is shared by all stream descriptors
+    interrupt_control: Mutex<()>,
+    // checked before taking the lock of the trace, so that verbs don't contend for it while tracing is off
+    verb_trace_enabled: AtomicBool,
+    verb_trace: Mutex<VerbTrace>,
+    // DMA memory of CORB, RIRB and the DMA position buffer, which gets replaced when the rings are initialized again
+    corb_memory: Mutex<Option<DmaRegion>>,
+    rirb_memory: Mutex<Option<DmaRegion>>,
+    dma_position_buffer_memory: Mutex<Option<DmaRegion>>,
+    stream_allocator: Mutex<StreamAllocator>,
+}
+
+impl Controller {
+    pub fn new(mmio_base_address: VirtAddr, vendor_id: u16, device_id: u16) -> Result<Self, IhdaError> {
+        let mmio_base_address = mmio_base_address.as_u64();
+
+        // gcap contains amount of input, output and bidirectional stream descriptors of the specific IHDA controller (see section 3.3.2 of the specification)
+        let gcap: Register<u16, Gcap> = Register::new(mmio_base_address as *mut u16, "GCAP");
+        let capabilities = ControllerCaps::detect(vendor_id, device_id, &gcap);
+        let input_stream_descriptor_amount = gcap.field(Gcap::INPUT_STREAMS_SUPPORTED);
+        let output_stream_descriptor_amount = gcap.field(Gcap::OUTPUT_STREAMS_SUPPORTED);
+        let bidirectional_stream_descriptor_amount = gcap.field(Gcap::BIDIRECTIONAL_STREAMS_SUPPORTED);
+        if bidirectional_stream_descriptor_amount > MAX_AMOUNT_OF_BIDIRECTIONAL_STREAMS as u32 {
+            return Err(IhdaError::InvalidBidirectionalStreamAmount(bidirectional_stream_descriptor_amount as u8));
+        }
+        if gcap.field(Gcap::NUMBER_OF_SERIAL_DATA_OUT_SIGNALS) == 0b11 {
+            return Err(IhdaError::InvalidSerialDataOutSignals);
+        }
+
+        let mut input_stream_descriptors = Vec::new();
+        for index in 0..input_stream_descriptor_amount {
+            input_stream_descriptors.push(StreamDescriptorRegisters::new(
+                mmio_base_address
+                    + OFFSET_OF_FIRST_SOUND_DESCRIPTOR
+                    + (SOUND_DESCRIPTOR_REGISTERS_LENGTH_IN_BYTES * index as u64),
+                capabilities.fifo_watermark_register_available,
+                false
+            ));
+        }
+
+        let mut output_stream_descriptors = Vec::new();
+        for index in 0..output_stream_descriptor_amount {
+            output_stream_descriptors.push(StreamDescriptorRegisters::new(
+                mmio_base_address
+                    + OFFSET_OF_FIRST_SOUND_DESCRIPTOR
+                    + (SOUND_DESCRIPTOR_REGISTERS_LENGTH_IN_BYTES * (input_stream_descriptor_amount + index) as u64),
+                capabilities.fifo_watermark_register_available,
+                false
+            ));
+        }
+
+        let mut bidirectional_stream_descriptors = Vec::new();
+        for index in 0..bidirectional_stream_descriptor_amount {
+            bidirectional_stream_descriptors.push(StreamDescriptorRegisters::new(
+                mmio_base_address
+                    + OFFSET_OF_FIRST_SOUND_DESCRIPTOR
+                    + (SOUND_DESCRIPTOR_REGISTERS_LENGTH_IN_BYTES * (input_stream_descriptor_amount + output_stream_descriptor_amount + index) as u64),
+                capabilities.fifo_watermark_register_available,
+                true
+            ));
+        }
+
+        let mut sdlpiba_aliases = Vec::new();
+        for index in 0..(input_stream_descriptor_amount + output_stream_descriptor_amount + bidirectional_stream_descriptor_amount) {
+            sdlpiba_aliases.push(Register::new(
+                (mmio_base_address
+                    + OFFSET_OF_SDLPIB_ALIASES
+                    + (SOUND_DESCRIPTOR_REGISTERS_LENGTH_IN_BYTES * index as u64)) as *mut u32,
+                "SDLPIBA"
+            ));
+        }
+
+        Ok(Self {
+            gcap,
+            vmin: Register::new((mmio_base_address + 0x2) as *mut u8, "VMIN"),
+            vmaj: Register::new((mmio_base_address + 0x3) as *mut u8, "VMAJ"),
+            outpay: Register::new((mmio_base_address + 0x4) as *mut u16, "OUTPAY"),
+            inpay: Register::new((mmio_base_address + 0x6) as *mut u16, "INPAY"),
+            gctl: Register::new((mmio_base_address + 0x8) as *mut u32, "GCTL"),
+            wakeen: Register::new((mmio_base_address + 0xC) as *mut u16, "WAKEEN"),
+            wakests: Register::new((mmio_base_address + 0xE) as *mut u16, "WAKESTS"),
+            gsts: Register::new((mmio_base_address + 0x10) as *mut u16, "GSTS"),
+            // gcap2 only specified in phc-spec, not in IHDA-spec
+            gcap2: if capabilities.gcap2_register_available { Some(Register::new((mmio_base_address + 0x12) as *mut u16, "GCAP2")) } else { None },
+            // bytes with offset 0x14 to 0x17 are reserved
+            outstrmpay: Register::new((mmio_base_address + 0x18) as *mut u16, "OUTSTRMPAY"),
+            instrmpay: Register::new((mmio_base_address + 0x1A) as *mut u16, "INSTRMPAY"),
+            // bytes with offset 0x1C to 0x1F are reserved
+            intctl: Register::new((mmio_base_address + 0x20) as *mut u32, "INTCTL"),
+            intsts: Register::new((mmio_base_address + 0x24) as *mut u32, "INTSTS"),
+            // bytes with offset 0x28 to 0x2F are reserved
+            walclk: Register::new((mmio_base_address + 0x30) as *mut u32, "WALCLK"),
+            // bytes with offset 0x34 to 0x37 are reserved
+            ssync: Register::new((mmio_base_address + 0x38) as *mut u32, "SSYNC"),
+            // bytes with offset 0x3C to 0x3F are reserved
+            corblbase: Register::new((mmio_base_address + 0x40) as *mut u32, "CORBLBASE"),
+            corbubase: Register::new((mmio_base_address + 0x44) as *mut u32, "CORBUBASE"),
+            corbwp: Register::new((mmio_base_address + 0x48) as *mut u16, "CORBWP"),
+            corbrp: Register::new((mmio_base_address + 0x4A) as *mut u16, "CORBRP"),
+            corbctl: Register::new((mmio_base_address + 0x4C) as *mut u8, "CORBCTL"),
+            corbsts: Register::new((mmio_base_address + 0x4D) as *mut u8, "CORBSTS"),
+            corbsize: Register::new((mmio_base_address + 0x4E) as *mut u8, "CORBSIZE"),
+            // byte with offset 0x4F is reserved
+            rirblbase: Register::new((mmio_base_address + 0x50) as *mut u32, "RIRBLBASE"),
+            rirbubase: Register::new((mmio_base_address + 0x54) as *mut u32, "RIRBUBASE"),
+            rirbwp: Register::new((mmio_base_address + 0x58) as *mut u16, "RIRBWP"),
+            rintcnt: Register::new((mmio_base_address + 0x5A) as *mut u16, "RINTCNT"),
+            rirbctl: Register::new((mmio_base_address + 0x5C) as *mut u8, "RIRBCTL"),
+            rirbsts: Register::new((mmio_base_address + 0x5D) as *mut u8, "RIRBSTS"),
+            rirbsize: Register::new((mmio_base_address + 0x5E) as *mut u8, "RIRBSIZE"),
+            // byte with offset 0x5F is reserved
+            // the following three immediate command registers from bytes 0x60 to 0x69 are optional
+            icoi: Register::new((mmio_base_address + 0x60) as *mut u32, "ICOI"),
+            icii: Register::new((mmio_base_address + 0x64) as *mut u32, "ICII"),
+            icsts: Register::new((mmio_base_address + 0x68) as *mut u16, "ICSTS"),
+            // bytes with offset 0x6A to 0x6F are reserved
+            dpiblbase: Register::new((mmio_base_address + 0x70) as *mut u32, "DPIBLBASE"),
+            dpibubase: Register::new((mmio_base_address + 0x74) as *mut u32, "DPIBUBASE"),
+            // bytes with offset 0x78 to 0x7F are reserved
+
+            input_stream_descriptors,
+            output_stream_descriptors,
+            bidirectional_stream_descriptors,
+
+            walclk_alias: Register::new((mmio_base_address + 0x2030) as *mut u32, "WALCLKA"),
+            sdlpiba_aliases,
+
+            capabilities,
+            stream_position_source: Mutex::new(PositionSource::DmaPositionBuffer),
+            codec_health: Mutex::new([CodecHealth::default(); MAX_AMOUNT_OF_CODECS as usize]),
+            transport: Mutex::new(CommandTransportKind::Immediate),
+            command_ring: Mutex::new(CommandRing::new()),
+            immediate_command_interface: Mutex::new(()),
+            interrupt_control: Mutex::new(()),
+            verb_trace_enabled: AtomicBool::new(false),
+            verb_trace: Mutex::new(VerbTrace::new()),
+            corb_memory: Mutex::new(None),
+            rirb_memory: Mutex::new(None),
+            dma_position_buffer_memory: Mutex::new(None),
+            stream_allocator: Mutex::new(StreamAllocator::new()),
+        })
+    }
+
+    // ########## GCAP ##########
+    fn supports_64bit_bdl_addresses(&self) -> bool {
+        self.gcap.is_set(Gcap::SUPPORTS_64BIT_ADDRESSES)
+    }
+
+    // the reserved value 0b11 gets rejected in fn new
+    fn number_of_serial_data_out_signals(&self) -> u8 {
+        match self.gcap.field(Gcap::NUMBER_OF_SERIAL_DATA_OUT_SIGNALS) {
+            0b00 => 1,
+            0b01 => 2,
+            _ => 4,
+        }
+    }
+
+    // values above MAX_AMOUNT_OF_BIDIRECTIONAL_STREAMS get rejected in fn new
+    fn number_of_bidirectional_streams_supported(&self) -> u8 {
+        self.gcap.field(Gcap::BIDIRECTIONAL_STREAMS_SUPPORTED) as u8
+    }
+
+    fn number_of_input_streams_supported(&self) -> u8 {
+        self.gcap.field(Gcap::INPUT_STREAMS_SUPPORTED) as u8
+    }
+
+    fn number_of_output_streams_supported(&self) -> u8 {
+        self.gcap.field(Gcap::OUTPUT_STREAMS_SUPPORTED) as u8
+    }
+
+    // ########## VMIN and VMAJ ##########
+    fn specification_version(&self) -> (u8, u8) {
+        (self.vmaj.read(), self.vmin.read())
+    }
+
+    // ########## OUTPAY ##########
+    fn output_payload_capacity_in_words(&self) -> u16 {
+        self.outpay.read()
+    }
+
+    // ########## INPAY ##########
+    fn input_payload_capacity_in_words(&self) -> u16 {
+        self.inpay.read()
+    }
+
+    // ########## GCTL ##########
+    pub fn reset(&self) -> Result<(), IhdaError> {
+        self.gctl.set(Gctl::CONTROLLER_RESET);
+        wait_for(|| self.gctl.is_set(Gctl::CONTROLLER_RESET), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Gctl))?;
+
+        // according to IHDA specification (section 4.3 Codec Discovery), the system should at least wait .521 ms after reading CRST as 1, so that the codecs have time to self-initialize
+        Timer::wait(1);
+        Ok(())
+    }
+
+    fn is_in_reset(&self) -> bool {
+        !self.gctl.is_set(Gctl::CONTROLLER_RESET)
+    }
+
+    // puts the controller into reset, which stops the link to the codecs; only fn reset takes it out again
+    fn enter_reset(&self) -> Result<(), IhdaError> {
+        self.gctl.clear(Gctl::CONTROLLER_RESET);
+        wait_for(|| !self.gctl.is_set(Gctl::CONTROLLER_RESET), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Gctl))
+    }
+
+    // fn initiate_flush();
+
+    fn unsolicited_response_enable_bit(&self) -> bool {
+        self.gctl.is_set(Gctl::UNSOLICITED_RESPONSE_ENABLE)
+    }
+
+    fn set_unsolicited_response_enable_bit(&self) {
+        self.gctl.set(Gctl::UNSOLICITED_RESPONSE_ENABLE);
+    }
+
+    fn clear_unsolicited_response_enable_bit(&self) {
+        self.gctl.clear(Gctl::UNSOLICITED_RESPONSE_ENABLE);
+    }
+
+    // ########## shutdown ##########
+
+    // Quiesces the controller, so that it doesn't write into memory anymore, e.g. before a reboot hands the memory to the next kernel:
+    // stops the DMA engines of all stream descriptors, CORB and RIRB, disables all interrupts and clears the addresses of the buffer
+    // descriptor lists, the rings and the DMA position buffer. If requested, the controller gets put into reset afterwards.
+    // Streams and the DMA memory of the rings are left alone, so the controller has to be set up from scratch before it can be used again.
+    // A controller which is already in reset doesn't access memory, so nothing has to be done in this case.
+    pub fn shutdown(&self, enter_reset: bool) -> Result<(), IhdaError> {
+        if self.is_in_reset() {
+            return Ok(());
+        }
+
+        // no command may be in flight while the rings get stopped
+        let _command_ring = self.command_ring.lock();
+        let _immediate_command_interface = self.immediate_command_interface.lock();
+        {
+            let _interrupt_control = self.interrupt_control.lock();
+            self.intctl.clear_all_bits();
+        }
+        self.clear_unsolicited_response_enable_bit();
+
+        let stream_descriptors = self.input_stream_descriptors.iter().chain(self.output_stream_descriptors.iter()).chain(self.bidirectional_stream_descriptors.iter());
+        for sd_registers in stream_descriptors {
+            // the reset also stops the DMA engine and clears the interrupt settings of the stream descriptor
+            sd_registers.reset_stream()?;
+            sd_registers.set_bdl_pointer_address(0);
+            sd_registers.set_cyclic_buffer_lenght(0);
+        }
+
+        self.clear_corb_memory_error_interrupt_enable_bit();
+        self.stop_corb_dma()?;
+        self.corblbase.write(self.corblbase.read() & RING_BUFFER_ADDRESS_RESERVED_BITS);
+        self.corbubase.write(0);
+
+        self.clear_response_interrupt_control_bit();
+        self.clear_response_overrun_interrupt_control_bit();
+        self.stop_rirb_dma();
+        wait_for(|| !self.rirb_dma_enable_bit(), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Rirbctl))?;
+        self.rirblbase.write(self.rirblbase.read() & RING_BUFFER_ADDRESS_RESERVED_BITS);
+        self.rirbubase.write(0);
+        *self.transport.lock() = CommandTransportKind::Immediate;
+
+        self.disable_dma_position_buffer();
+        self.dpiblbase.write(0);
+        self.dpibubase.write(0);
+
+        if enter_reset {
+            self.enter_reset()?;
+        }
+        Ok(())
+    }
+
+    // ########## WAKEEN ##########
+
+    fn sdin_wake_enable_bit(&self, sdin_index: u8) -> bool {
+        if sdin_index > MAX_AMOUNT_OF_SDIN_SIGNALS - 1 { panic!("index of SDIN signal out of range") }
+        self.wakeen.is_set(Wakeen::sdin_wake_enable(sdin_index))
+    }
+
+    fn set_sdin_wake_enable_bit(&self, sdin_index : u8) {
+        if sdin_index > MAX_AMOUNT_OF_SDIN_SIGNALS - 1 { panic!("index of SDIN signal out of range") }
+        self.wakeen.set(Wakeen::sdin_wake_enable(sdin_index));
+    }
+
+    fn clear_sdin_wake_enable_bit(&self, sdin_index : u8) {
+        if sdin_index > MAX_AMOUNT_OF_SDIN_SIGNALS - 1 { panic!("index of SDIN signal out of range") }
+        self.wakeen.clear(Wakeen::sdin_wake_enable(sdin_index));
+    }
+
+    // ########## WAKESTS ##########
+
+    fn sdin_state_change_status_bit(&self, sdin_index: u8) -> bool {
+        if sdin_index > MAX_AMOUNT_OF_SDIN_SIGNALS - 1 { panic!("index of SDIN signal out of range") }
+        self.wakests.is_set(Wakests::sdin_state_change_status(sdin_index))
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.9)
+    fn clear_sdin_state_change_status_bit(&self, sdin_index : u8) {
+        if sdin_index > MAX_AMOUNT_OF_SDIN_SIGNALS - 1 { panic!("index of SDIN signal out of range") }
+        self.wakests.acknowledge(Wakests::sdin_state_change_status(sdin_index));
+    }
+
+    // ########## GSTS ##########
+
+     fn flush_status_bit(&self) -> bool {
+        self.gsts.is_set(Gsts::FLUSH_STATUS)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.10)
+     fn clear_flush_status_bit(&self) {
+        self.gsts.acknowledge(Gsts::FLUSH_STATUS);
+    }
+
+    // ########## GCAP2 ##########
+     fn energy_efficient_audio_capability(&self) -> bool {
+        self.gcap2.as_ref().is_some_and(|gcap2| gcap2.is_set(Gcap2::ENERGY_EFFICIENT_AUDIO_CAPABILITY))
+    }
+
+    // ########## OUTSTRMPAY ##########
+     fn output_stream_payload_capability_in_words(&self) -> u16 {
+        self.outstrmpay.read()
+    }
+
+    // ########## INSTRMPAY ##########
+     fn input_stream_payload_capability_in_words(&self) -> u16 {
+        self.instrmpay.read()
+    }
+
+    // ########## INTCTL ##########
+
+    // bits [29:0] enable the interrupts of the stream descriptors in the order input, output, bidirectional (see specification, section 3.3.14)
+    fn stream_interrupt_enable_bit(&self, stream_descriptor_number: u32) -> bool {
+        self.intctl.is_set(Intctl::stream_interrupt_enable(stream_descriptor_number))
+    }
+
+    fn set_stream_interrupt_enable_bit(&self, stream_descriptor_number: u32) {
+        let _interrupt_control = self.interrupt_control.lock();
+        self.intctl.set(Intctl::stream_interrupt_enable(stream_descriptor_number));
+    }
+
+    fn clear_stream_interrupt_enable_bit(&self, stream_descriptor_number: u32) {
+        let _interrupt_control = self.interrupt_control.lock();
+        self.intctl.clear(Intctl::stream_interrupt_enable(stream_descriptor_number));
+    }
+
+     fn controller_interrupt_enable_bit(&self) -> bool {
+        self.intctl.is_set(Intctl::CONTROLLER_INTERRUPT_ENABLE)
+    }
+
+     fn set_controller_interrupt_enable_bit(&self) {
+        let _interrupt_control = self.interrupt_control.lock();
+        self.intctl.set(Intctl::CONTROLLER_INTERRUPT_ENABLE);
+    }
+
+     fn clear_controller_interrupt_enable_bit(&self) {
+        let _interrupt_control = self.interrupt_control.lock();
+        self.intctl.clear(Intctl::CONTROLLER_INTERRUPT_ENABLE);
+    }
+
+     fn global_interrupt_enable_bit(&self) -> bool {
+        self.intctl.is_set(Intctl::GLOBAL_INTERRUPT_ENABLE)
+    }
+
+     fn set_global_interrupt_enable_bit(&self) {
+        let _interrupt_control = self.interrupt_control.lock();
+        self.intctl.set(Intctl::GLOBAL_INTERRUPT_ENABLE);
+    }
+
+     fn clear_global_interrupt_enable_bit(&self) {
+        let _interrupt_control = self.interrupt_control.lock();
+        self.intctl.clear(Intctl::GLOBAL_INTERRUPT_ENABLE);
+    }
+
+    // ########## INTSTS ##########
+
+    // read only, the bits get cleared through the status registers of the stream descriptors (see specification, section 3.3.15)
+    fn stream_interrupt_status_bit(&self, stream_descriptor_number: u32) -> bool {
+        self.intsts.is_set(Intsts::stream_interrupt_status(stream_descriptor_number))
+    }
+
+    fn controller_interrupt_status_bit(&self) -> bool {
+        self.intsts.is_set(Intsts::CONTROLLER_INTERRUPT_STATUS)
+    }
+
+    fn global_interrupt_status_bit(&self) -> bool {
+        self.intsts.is_set(Intsts::GLOBAL_INTERRUPT_STATUS)
+    }
+
+    // ########## WALCLK ##########
+
+    // 24 MHz counter, which keeps running as long as the link is up
+    pub fn wall_clock_counter(&self) -> u32 {
+        self.walclk.read()
+    }
+
+    // ########## WALCLKA and SDLPIBA ##########
+
+    // The aliases are the only registers on their page, so it can be mapped into user space without exposing any other register.
+    // MMIO space is identity mapped, so the virtual address of a register is also its physical address.
+    pub fn wall_clock_alias_address(&self) -> PhysAddr {
+        PhysAddr::new(self.walclk_alias.address())
+    }
+
+    // physical address the position of a stream can be read from in user space, depending on the position source of the controller
+    // None if the controller has no stream descriptor with this number
+    pub fn stream_position_address(&self, stream_descriptor_number: u32) -> Option<PhysAddr> {
+        let sdlpiba_alias = self.sdlpiba_aliases.get(stream_descriptor_number as usize)?;
+        Some(match self.position_source() {
+            PositionSource::LinkPositionInBuffer => PhysAddr::new(sdlpiba_alias.address()),
+            PositionSource::DmaPositionBuffer => PhysAddr::new(self.dma_position_buffer_address() + (stream_descriptor_number as u64 * (2 * DMA_POSITION_IN_BUFFER_ENTRY_SIZE_IN_BYTES))),
+        })
+    }
+
+    // ########## SSYNC ##########
+
+    // not implemented yet
+
+
+    // ########## ICOI - Immediate Command Output Interface ##########
+
+    fn write_command_to_icoi(&self, command: Command) {
+        self.icoi.write(command.as_u32());
+    }
+
+    // ########## ICII - Immediate Command Input Interface ##########
+
+    fn read_response_from_icii(&self) -> u32 {
+        self.icii.read()
+    }
+
+    // ########## ICSTS - Immediate Command Status ##########
+
+    fn immediate_command_busy_bit(&self) -> bool {
+        self.icsts.is_set(Icsts::IMMEDIATE_COMMAND_BUSY)
+    }
+
+    fn set_immediate_command_busy_bit(&self) {
+        self.icsts.set(Icsts::IMMEDIATE_COMMAND_BUSY);
+    }
+
+    fn clear_immediate_command_busy_bit(&self) {
+        self.icsts.clear(Icsts::IMMEDIATE_COMMAND_BUSY);
+    }
+
+    fn immediate_result_valid_bit(&self) -> bool {
+        self.icsts.is_set(Icsts::IMMEDIATE_RESULT_VALID)
+    }
+
+    fn set_immediate_result_ready_bit(&self) {
+        self.icsts.set(Icsts::IMMEDIATE_RESULT_VALID);
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.4.3)
+    fn clear_immediate_result_ready_bit(&self) {
+        self.icsts.acknowledge(Icsts::IMMEDIATE_RESULT_VALID);
+    }
+
+    // single attempt, returns None on timeout
+    fn send_command(&self, command: Command) -> Option<Response> {
+        match self.command_transport() {
+            CommandTransportKind::CorbRirb => self.send_command_through_corb(command),
+            CommandTransportKind::Immediate => self.send_immediate_command(command),
+        }
+    }
+
+    // single attempt, returns None on timeout
+    fn send_immediate_command(&self, command: Command) -> Option<Response> {
+        let _immediate_command_interface = self.immediate_command_interface.lock();
+        self.write_command_to_icoi(command);
+        self.set_immediate_command_busy_bit();
+        self.trace_verb(VerbTraceEvent::Command(command));
+        if wait_for(|| self.immediate_result_valid_bit(), IMMEDIATE_COMMAND_TIMEOUT_IN_MS).is_err() {
+            // abort the pending command, so that the interface is free for the next one
+            self.clear_immediate_command_busy_bit();
+            self.trace_verb(VerbTraceEvent::Timeout(command));
+            return None;
+        }
+        let raw_response = self.read_response_from_icii();
+        // ICII doesn't report the codec that answered, but only one command can be pending at a time
+        self.trace_verb(VerbTraceEvent::Response { codec_address: command.codec_address(), raw_response, unsolicited: false });
+        let raw_response = RawResponse::new(raw_response);
+        // otherwise the next command would find the bit still set and read the response to this one
+        self.clear_immediate_result_ready_bit();
+        Some(Response::new(raw_response, command))
+    }
+
+    // ########## codec quarantine ##########
+
+    pub fn is_codec_quarantined(&self, codec_address: u8) -> bool {
+        self.codec_health.lock()[codec_address as usize].quarantined
+    }
+
+    pub fn quarantined_codecs(&self) -> Vec<u8> {
+        self.codec_health.lock().iter()
+            .enumerate()
+            .filter(|(_, health)| health.quarantined)
+            .map(|(codec_address, _)| codec_address as u8)
+            .collect()
+    }
+
+    fn record_command_success(&self, codec_address: u8) {
+        self.codec_health.lock()[codec_address as usize].consecutive_failures = 0;
+    }
+
+    fn record_command_failure(&self, codec_address: u8) {
+        let mut codec_health = self.codec_health.lock();
+        let health = &mut codec_health[codec_address as usize];
+        health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+        if !health.quarantined && health.consecutive_failures >= CODEC_QUARANTINE_THRESHOLD {
+            health.quarantined = true;
+            warn!("IHDA codec {} quarantined after {} consecutive failed commands", codec_address, health.consecutive_failures);
+        }
+    }
+
+    // Sends the cheapest possible verb to each quarantined codec and lifts the quarantine of all codecs that respond again.
+    // Returns the addresses of the recovered codecs.
+    pub fn reprobe_quarantined_codecs(&self) -> Vec<u8> {
+        let mut recovered_codecs = Vec::new();
+        for codec_address in self.quarantined_codecs() {
+            let root_node_addr = NodeAddress::new(CodecAddress::new(codec_address), 0);
+            if self.send_command(GetParameter(root_node_addr, VendorId)).is_some() {
+                let mut codec_health = self.codec_health.lock();
+                codec_health[codec_address as usize] = CodecHealth::default();
+                info!("IHDA codec {} responds again and left quarantine", codec_address);
+                recovered_codecs.push(codec_address);
+            }
+        }
+        recovered_codecs
+    }
+
+    pub fn configure(&self) {
+        // set Accept Unsolicited Response Enable (UNSOL) bit, so that codecs can report jack events through the RIRB
+        self.set_unsolicited_response_enable_bit();
+
+        self.set_global_interrupt_enable_bit();
+        self.set_controller_interrupt_enable_bit();
+
+        // enable wake events and interrupts for all SDIN (actually, only one bit needs to be set, but this works for now...)
+        self.wakeen.set_all_bits();
+    }
+
+    // check the bitmask from bits 0 to 14 of the WAKESTS (in the specification also called STATESTS) indicating available codecs
+    // then find all function group nodes and widgets associated with a codec
+    // Codecs which fail to answer during the scan get skipped, but a controller without any usable codec is an error.
+    pub fn scan_for_available_codecs(&self) -> Result<Vec<Codec>, IhdaError> {
+        let mut codecs: Vec<Codec> = Vec::new();
+
+        for codec_address in 0..MAX_AMOUNT_OF_CODECS {
+            if self.sdin_state_change_status_bit(codec_address) {
+                let codec_address = CodecAddress::new(codec_address);
+                let root_node_addr = NodeAddress::new(codec_address, 0);
+                // a codec that doesn't even answer the first verb gets skipped instead of aborting the whole scan
+                let vendor_id = match self.read_vendor_id(root_node_addr) {
+                    Ok(vendor_id) => vendor_id,
+                    Err(error) => {
+                        warn!("Skipping IHDA codec {}: {:?}", codec_address.codec_address(), error);
+                        continue;
+                    }
+                };
+                // the same goes for a codec that stops answering in the middle of the scan
+                match Codec::scan(self, root_node_addr, vendor_id) {
+                    Ok(codec) => codecs.push(codec),
+                    Err(error) => warn!("Skipping IHDA codec {}: {:?}", codec_address.codec_address(), error),
+                }
+            }
+        }
+
+        if codecs.is_empty() {
+            return Err(IhdaError::NoCodecFound);
+        }
+        Ok(codecs)
+    }
+
+    // Reads and clears the STATESTS bits of all codecs, which get set whenever a codec signals a state change on its SDIN line,
+    // e.g. after it got attached or reset itself (see specification, section 3.3.10). Bit n of the result belongs to codec address n.
+    // May be called from interrupt context.
+    pub fn take_codec_state_changes(&self) -> u16 {
+        let mut state_changes = 0;
+        for codec_address in 0..MAX_AMOUNT_OF_CODECS {
+            if self.sdin_state_change_status_bit(codec_address) {
+                self.clear_sdin_state_change_status_bit(codec_address);
+                state_changes |= 1 << codec_address;
+            }
+        }
+        state_changes
+    }
+
+    // scans the widget graph of a single codec again, e.g. after it signaled a state change at runtime
+    pub fn rescan_codec(&self, codec_address: u8) -> Result<Codec, IhdaError> {
+        let root_node_addr = NodeAddress::new(CodecAddress::new(codec_address), 0);
+        let vendor_id = self.read_vendor_id(root_node_addr)?;
+        Ok(Codec::scan(self, root_node_addr, vendor_id)?)
+    }
+
+    // the vendor id selects the quirks of a codec (see CODEC_QUIRKS), so it gets read before anything else of the codec
+    fn read_vendor_id(&self, root_node_addr: NodeAddress) -> Result<VendorIdResponse, CommandError> {
+        VendorIdResponse::try_from(self.try_command(GetParameter(root_node_addr, VendorId))?)
+            .map_err(|_| CommandError::InvalidResponse(*root_node_addr.codec_address().codec_address()))
+    }
+}
+
+// The MMIO registers are only accessed through volatile reads and writes, and everything that has to be changed consistently
+// by several threads is guarded by a lock (see struct Controller).
+unsafe impl Sync for Controller {}
+unsafe impl Send for Controller {}
+
+impl CommandTransport for Controller {
+    // Sends a command up to COMMAND_ATTEMPTS times through the current command transport, until the codec answers in time with
+    // a plausible response (see fn Response::is_implausible). Commands to quarantined codecs fail immediately without touching the hardware.
+    // Only timeouts count towards the quarantine, as a codec giving implausible responses still answers.
+    fn try_command(&self, command: Command) -> Result<Response, CommandError> {
+        let codec_address = command.codec_address();
+        if self.is_codec_quarantined(codec_address) {
+            return Err(CommandError::CodecQuarantined(codec_address));
+        }
+
+        let mut answered = false;
+        for attempt in 1..=COMMAND_ATTEMPTS {
+            match self.send_command(command) {
+                Some(response) if !response.is_implausible() => {
+                    self.record_command_success(codec_address);
+                    return Ok(response);
+                }
+                Some(response) => {
+                    answered = true;
+                    debug!("IHDA command {:?} got implausible response {:?} (attempt {} of {})", command, response, attempt, COMMAND_ATTEMPTS);
+                    Timer::wait(INVALID_RESPONSE_RETRY_DELAY_IN_MS);
+                }
+                None => debug!("IHDA command {:?} timed out (attempt {} of {})", command, attempt, COMMAND_ATTEMPTS)
+            }
+        }
+
+        if answered {
+            self.record_command_success(codec_address);
+            return Err(CommandError::InvalidResponse(codec_address));
+        }
+        self.record_command_failure(codec_address);
+        Err(CommandError::Timeout(codec_address))
+    }
+
+    // Sends the commands to codecs which aren't quarantined through the CORB in batches (see fn send_commands_through_corb).
+    // Commands whose response didn't arrive in time or is implausible get repeated one at a time by fn try_command, which
+    // also takes care of the quarantine, so the results are the same as sending every command with fn try_command.
+    fn try_commands(&self, commands: &[Command]) -> Vec<Result<Response, CommandError>> {
+        if self.command_transport() != CommandTransportKind::CorbRirb {
+            return commands.iter().map(|command| self.try_command(*command)).collect();
+        }
+
+        // a codec might get quarantined while failed commands get repeated, so the batch is decided upfront
+        let batched: Vec<bool> = commands.iter().map(|command| !self.is_codec_quarantined(command.codec_address())).collect();
+        let batched_commands: Vec<Command> = commands.iter().zip(batched.iter())
+            .filter(|(_, batched)| **batched)
+            .map(|(command, _)| *command)
+            .collect();
+        let mut batched_responses = self.send_commands_through_corb(&batched_commands).into_iter();
+        commands.iter().zip(batched.iter()).map(|(command, batched)| {
+            let response = match batched {
+                true => batched_responses.next().flatten(),
+                false => None,
+            };
+            match response {
+                Some(response) if !response.is_implausible() => {
+                    self.record_command_success(command.codec_address());
+                    Ok(response)
+                }
+                _ => self.try_command(*command),
+            }
+        }).collect()
+    }
+}
+
+// Failures of the sound card, which make the current operation or the whole driver fail without taking down the OS.
+// Violations of the driver's own preconditions (e.g. writing to stream registers of a running stream) still panic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IhdaError {
+    NoDeviceFound,
+    UnsupportedDevice,
+    // BAR 0 of the PCI configuration space doesn't describe a page aligned memory space
+    NoMemorySpaceBar,
+    // register whose status bit didn't reach the expected value in time
+    Timeout(RegisterName),
+    InvalidSerialDataOutSignals,
+    InvalidBidirectionalStreamAmount(u8),
+    NoOutputStreamDescriptor,
+    InvalidStreamNumber,
+    InvalidFifoWatermark(u8),
+    // name of the ring buffer and the raw value of its size register
+    InvalidRingbufferSize(&'static str, u8),
+    // name of the ring buffer and its size in entries, 0 if the controller doesn't report any supported size
+    UnsupportedRingbufferSize(&'static str, u16),
+    InvalidBufferDescriptorList(BufferDescriptorListError),
+    NoCodecFound,
+    // the codecs answer neither through CORB/RIRB nor through the immediate command registers
+    NoCommandTransport,
+    UnsupportedCodec { vendor_id: u16, device_id: u16 },
+    Command(CommandError),
+    // another stream still holds the stream descriptor with this number
+    StreamDescriptorBusy(u32),
+    NoFreeStreamDescriptor,
+    // all stream ids from 1 to 15 are used by other streams
+    NoFreeStreamId,
+    // the controller hasn't fetched enough commands to make room for another one
+    CorbFull,
+    // RINTCNT only counts from 1 to 256 responses (see specification, section 3.3.28)
+    InvalidResponseInterruptCount(u16),
+    // name of the register and the value which doesn't fit into the field it was written to
+    FieldOverflow(&'static str, u32),
+}
+
+// registers the driver polls until one of their bits changes, named in IhdaError::Timeout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegisterName {
+    Gctl,
+    Corbrp,
+    Corbctl,
+    Rirbctl,
+    Sdctl,
+}
+
+// the condition of fn wait_for didn't hold within the timeout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeout;
+
+// Polls a condition until it holds or the timeout expires. The condition gets checked once more after the timeout has expired,
+// so that a thread which didn't get scheduled for longer than the timeout doesn't fail although the hardware was fast enough.
+pub fn wait_for(mut condition: impl FnMut() -> bool, timeout_in_ms: usize) -> Result<(), Timeout> {
+    let start_timer = timer().read().systime_ms();
+    loop {
+        if condition() {
+            return Ok(());
+        }
+        if timer().read().systime_ms() > start_timer + timeout_in_ms {
+            return if condition() { Ok(()) } else { Err(Timeout) };
+        }
+        spin_loop();
+    }
+}
+
+impl From<CommandError> for IhdaError {
+    fn from(error: CommandError) -> Self {
+        IhdaError::Command(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandTransportKind {
+    // Command Outbound Ring Buffer and Response Inbound Ring Buffer (see specification, section 4.4.1 and 4.4.2)
+    CorbRirb,
+    // optional Immediate Command Input and Output registers (see specification, section 3.4)
+    Immediate,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct CodecHealth {
+    consecutive_failures: u8,
+    quarantined: bool,
+}
@@ -0,0 +1,584 @@
+use alloc::string::String;
+use core::fmt::{LowerHex, Write};
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use log::debug;
+use num_traits::int::PrimInt;
+use num_traits::One;
+use volatile::VolatilePtr;
+use crate::device::ihda_controller::IhdaError;
+
+// representation of an IHDA register
+// All accesses are volatile, so that the compiler can neither reorder nor elide them. The lifetime ties the register to the
+// mapping of the MMIO space, and B is the set of bitfields (see below), so that only the bits of this register can be accessed.
+// The set of bitfields also determines the width of the register, so a bit outside of the register doesn't compile.
+pub struct Register<'a, T: LowerHex + PrimInt, B: Bitfields<Width = T> = NoBitfields<T>> {
+    ptr: VolatilePtr<'a, T>,
+    name: &'static str,
+    bitfields: PhantomData<B>,
+}
+
+// the LowerHex type bound is only necessary because of the dump function which displays T as a hex value
+// the PrimeInt type bound is necessary because of the bit operations | and <<
+impl<'a, T: LowerHex + PrimInt, B: Bitfields<Width = T>> Register<'a, T, B> {
+    // the pointer must point into the MMIO space of the controller, which stays mapped for at least 'a
+    pub fn new(ptr: *mut T, name: &'static str) -> Self {
+        Self {
+            ptr: unsafe { VolatilePtr::new(NonNull::new(ptr).expect("IHDA register at null pointer")) },
+            name,
+            bitfields: PhantomData,
+        }
+    }
+    pub fn read(&self) -> T {
+        self.ptr.read()
+    }
+    pub fn write(&self, value: T) {
+        self.ptr.write(value);
+    }
+    pub fn set(&self, bit: Bit<B>) {
+        self.write(self.read() | bit.mask());
+    }
+    pub fn clear(&self, bit: Bit<B>) {
+        self.write(self.read() & !bit.mask());
+    }
+    // status bits which get cleared by writing a 1 to them; all other bits are written as 0, so that they don't get cleared as well
+    pub fn acknowledge(&self, bit: Bit<B>) {
+        self.write(bit.mask());
+    }
+    pub fn is_set(&self, bit: Bit<B>) -> bool {
+        self.read() & bit.mask() != T::zero()
+    }
+    pub fn field(&self, field: Field<B>) -> u32 {
+        field.extract(self.read().to_u32().unwrap())
+    }
+    // a value which doesn't fit into the field is refused, so that it neither gets cut off nor spills into the neighbouring bits
+    pub fn set_field(&self, field: Field<B>, value: u32) -> Result<(), IhdaError> {
+        let register_value = field.insert(self.read().to_u32().unwrap(), value).ok_or(IhdaError::FieldOverflow(self.name, value))?;
+        self.write(Self::from_u32(register_value));
+        Ok(())
+    }
+    pub fn set_all_bits(&self) {
+        self.write(!T::zero());
+    }
+    pub fn clear_all_bits(&self) {
+        self.write(T::zero());
+    }
+    // MMIO space is identity mapped, so the virtual address of a register is also its physical address
+    pub fn address(&self) -> u64 {
+        self.ptr.as_raw_ptr().as_ptr() as u64
+    }
+    pub fn dump(&self) {
+        debug!("Value read from register {}: {:#x}", self.name, self.read());
+    }
+    // same as fn dump, but appends a line to a text shown to the user instead of logging it
+    pub fn dump_to(&self, dump: &mut String) {
+        writeln!(dump, "{:<10} {:#x}", self.name, self.read()).unwrap();
+    }
+    // only used for the values of fields, which fit into the register, as fn Field::new checks their range
+    fn from_u32(value: u32) -> T {
+        T::from(value).expect("Field value exceeds register width")
+    }
+}
+
+// representation of the 3 byte wide SDCTL register of a stream descriptor (see specification, section 3.3.35)
+// There is no 3 byte access, and a 4 byte access would also cover the SDSTS register in the byte after SDCTL. Writing back
+// such a read value clears every SDSTS status bit that happens to be set, as these get cleared by writing a 1 to them.
+// SDCTL is therefore only ever accessed as the 2 bytes of bits [15:0] and the single byte of bits [23:16], so that a
+// read-modify-write cycle on SDCTL can't touch SDSTS at all.
+pub struct ThreeByteRegister<'a, B: Bitfields<Width = u32>> {
+    low: VolatilePtr<'a, u16>,
+    high: VolatilePtr<'a, u8>,
+    name: &'static str,
+    bitfields: PhantomData<B>,
+}
+
+impl<'a, B: Bitfields<Width = u32>> ThreeByteRegister<'a, B> {
+    const VALUE_MASK: u32 = 0xFF_FFFF;
+
+    // the pointer must point into the MMIO space of the controller, which stays mapped for at least 'a
+    pub fn new(ptr: *mut u8, name: &'static str) -> Self {
+        assert_eq!(B::WIDTH_IN_BITS, 24, "Bitfields of a three byte register must be 24 bits wide");
+        let low = NonNull::new(ptr as *mut u16).expect("IHDA register at null pointer");
+        let high = NonNull::new(ptr.wrapping_add(2)).expect("IHDA register at null pointer");
+        Self {
+            low: unsafe { VolatilePtr::new(low) },
+            high: unsafe { VolatilePtr::new(high) },
+            name,
+            bitfields: PhantomData,
+        }
+    }
+    fn read(&self) -> u32 {
+        Self::combine(self.low.read(), self.high.read())
+    }
+    // only the bytes which actually change get written, so that a write never repeats a value the hardware changed meanwhile
+    fn write(&self, value: u32) {
+        let (low, high) = Self::split(value);
+        let (current_low, current_high) = Self::split(self.read());
+        if low != current_low {
+            self.low.write(low);
+        }
+        if high != current_high {
+            self.high.write(high);
+        }
+    }
+    pub fn set(&self, bit: Bit<B>) {
+        self.write(self.read() | bit.mask());
+    }
+    pub fn clear(&self, bit: Bit<B>) {
+        self.write(self.read() & !bit.mask());
+    }
+    pub fn is_set(&self, bit: Bit<B>) -> bool {
+        self.read() & bit.mask() != 0
+    }
+    pub fn field(&self, field: Field<B>) -> u32 {
+        Self::extract_field(self.read(), field)
+    }
+    // same policy as fn Register::set_field
+    pub fn set_field(&self, field: Field<B>, value: u32) -> Result<(), IhdaError> {
+        self.write(Self::insert_field(self.read(), field, value).ok_or(IhdaError::FieldOverflow(self.name, value))?);
+        Ok(())
+    }
+    pub fn dump_to(&self, dump: &mut String) {
+        writeln!(dump, "{:<10} {:#x}", self.name, self.read()).unwrap();
+    }
+
+    // The bit arithmetic is kept apart from the accesses, so that it works on plain values. No value ever leaves the
+    // 24 bits of the register, which fn write relies on, as it has no fourth byte to put anything into.
+    fn combine(low: u16, high: u8) -> u32 {
+        low as u32 | (high as u32) << 16
+    }
+    fn split(value: u32) -> (u16, u8) {
+        assert_eq!(value & !Self::VALUE_MASK, 0, "Value {:#x} exceeds the three bytes of register", value);
+        (value as u16, (value >> 16) as u8)
+    }
+    fn extract_field(value: u32, field: Field<B>) -> u32 {
+        field.extract(value)
+    }
+    fn insert_field(value: u32, field: Field<B>, field_value: u32) -> Option<u32> {
+        field.insert(value, field_value)
+    }
+}
+
+// Set of bitfields of a register, which knows the width of the register. Bits and fields outside of this width get rejected
+// by fn Bit::new and fn Field::new, which fails to compile for the constants below, as they get evaluated at compile time.
+pub trait Bitfields {
+    type Width: LowerHex + PrimInt;
+    const WIDTH_IN_BITS: u8 = (size_of::<Self::Width>() * 8) as u8;
+}
+
+// a single bit of a register with the set of bitfields B
+pub struct Bit<B> {
+    index: u8,
+    register: PhantomData<B>,
+}
+
+impl<B: Bitfields> Bit<B> {
+    pub const fn new(index: u8) -> Self {
+        assert!(index < B::WIDTH_IN_BITS, "Bit is out of the range of its register");
+        Self { index, register: PhantomData }
+    }
+
+    pub fn mask(&self) -> B::Width {
+        B::Width::one() << self.index as usize
+    }
+}
+
+impl<B> Clone for Bit<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for Bit<B> {}
+
+// several adjacent bits of a register with the set of bitfields B, which form one value
+pub struct Field<B> {
+    shift: u8,
+    width: u8,
+    register: PhantomData<B>,
+}
+
+impl<B: Bitfields> Field<B> {
+    pub const fn new(shift: u8, width: u8) -> Self {
+        assert!(width > 0 && shift + width <= B::WIDTH_IN_BITS, "Field is out of the range of its register");
+        Self { shift, width, register: PhantomData }
+    }
+
+    // a field can span all 32 bits of a register, where shifting 1 by the width would overflow
+    fn mask(&self) -> u32 {
+        1u32.checked_shl(self.width as u32).map_or(u32::MAX, |bit| bit - 1)
+    }
+
+    fn extract(&self, register_value: u32) -> u32 {
+        (register_value >> self.shift) & self.mask()
+    }
+
+    // the register value with the field replaced by field_value, None if field_value doesn't fit into the field
+    fn insert(&self, register_value: u32, field_value: u32) -> Option<u32> {
+        if field_value & !self.mask() != 0 {
+            return None;
+        }
+        Some((register_value & !(self.mask() << self.shift)) | (field_value << self.shift))
+    }
+}
+
+impl<B> Clone for Field<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for Field<B> {}
+
+// ########## bitfields of the registers (see specification, section 3.3) ##########
+
+// registers which are only read and written as a whole value
+pub struct NoBitfields<T>(PhantomData<T>);
+
+impl<T: LowerHex + PrimInt> Bitfields for NoBitfields<T> {
+    type Width = T;
+}
+
+pub struct Gcap;
+
+impl Bitfields for Gcap {
+    type Width = u16;
+}
+
+impl Gcap {
+    pub const SUPPORTS_64BIT_ADDRESSES: Bit<Gcap> = Bit::new(0);
+    pub const NUMBER_OF_SERIAL_DATA_OUT_SIGNALS: Field<Gcap> = Field::new(1, 2);
+    pub const BIDIRECTIONAL_STREAMS_SUPPORTED: Field<Gcap> = Field::new(3, 5);
+    pub const INPUT_STREAMS_SUPPORTED: Field<Gcap> = Field::new(8, 4);
+    pub const OUTPUT_STREAMS_SUPPORTED: Field<Gcap> = Field::new(12, 4);
+}
+
+pub struct Gctl;
+
+impl Bitfields for Gctl {
+    type Width = u32;
+}
+
+impl Gctl {
+    pub const CONTROLLER_RESET: Bit<Gctl> = Bit::new(0);
+    pub const FLUSH_CONTROL: Bit<Gctl> = Bit::new(1);
+    pub const UNSOLICITED_RESPONSE_ENABLE: Bit<Gctl> = Bit::new(8);
+}
+
+pub struct Wakeen;
+
+impl Bitfields for Wakeen {
+    type Width = u16;
+}
+
+impl Wakeen {
+    pub fn sdin_wake_enable(sdin_index: u8) -> Bit<Wakeen> {
+        Bit::new(sdin_index)
+    }
+}
+
+pub struct Wakests;
+
+impl Bitfields for Wakests {
+    type Width = u16;
+}
+
+impl Wakests {
+    pub fn sdin_state_change_status(sdin_index: u8) -> Bit<Wakests> {
+        Bit::new(sdin_index)
+    }
+}
+
+pub struct Gsts;
+
+impl Bitfields for Gsts {
+    type Width = u16;
+}
+
+impl Gsts {
+    pub const FLUSH_STATUS: Bit<Gsts> = Bit::new(1);
+}
+
+pub struct Gcap2;
+
+impl Bitfields for Gcap2 {
+    type Width = u16;
+}
+
+impl Gcap2 {
+    pub const ENERGY_EFFICIENT_AUDIO_CAPABILITY: Bit<Gcap2> = Bit::new(0);
+}
+
+pub struct Intctl;
+
+impl Bitfields for Intctl {
+    type Width = u32;
+}
+
+impl Intctl {
+    pub const CONTROLLER_INTERRUPT_ENABLE: Bit<Intctl> = Bit::new(30);
+    pub const GLOBAL_INTERRUPT_ENABLE: Bit<Intctl> = Bit::new(31);
+
+    // bits [29:0] enable the interrupts of the stream descriptors in the order input, output, bidirectional
+    pub fn stream_interrupt_enable(stream_descriptor_number: u32) -> Bit<Intctl> {
+        Bit::new(stream_descriptor_number as u8)
+    }
+}
+
+pub struct Intsts;
+
+impl Bitfields for Intsts {
+    type Width = u32;
+}
+
+impl Intsts {
+    pub const CONTROLLER_INTERRUPT_STATUS: Bit<Intsts> = Bit::new(30);
+    pub const GLOBAL_INTERRUPT_STATUS: Bit<Intsts> = Bit::new(31);
+
+    pub fn stream_interrupt_status(stream_descriptor_number: u32) -> Bit<Intsts> {
+        Bit::new(stream_descriptor_number as u8)
+    }
+}
+
+// CORBWP and RIRBWP
+pub struct RingWritePointer;
+
+impl Bitfields for RingWritePointer {
+    type Width = u16;
+}
+
+impl RingWritePointer {
+    pub const WRITE_POINTER: Field<RingWritePointer> = Field::new(0, 8);
+    // only defined for RIRBWP, the bit is reserved in CORBWP
+    pub const WRITE_POINTER_RESET: Bit<RingWritePointer> = Bit::new(15);
+}
+
+pub struct Corbrp;
+
+impl Bitfields for Corbrp {
+    type Width = u16;
+}
+
+impl Corbrp {
+    pub const READ_POINTER: Field<Corbrp> = Field::new(0, 8);
+    pub const READ_POINTER_RESET: Bit<Corbrp> = Bit::new(15);
+}
+
+pub struct Corbctl;
+
+impl Bitfields for Corbctl {
+    type Width = u8;
+}
+
+impl Corbctl {
+    pub const MEMORY_ERROR_INTERRUPT_ENABLE: Bit<Corbctl> = Bit::new(0);
+    pub const DMA_RUN: Bit<Corbctl> = Bit::new(1);
+}
+
+pub struct Corbsts;
+
+impl Bitfields for Corbsts {
+    type Width = u8;
+}
+
+impl Corbsts {
+    pub const MEMORY_ERROR_INDICATION: Bit<Corbsts> = Bit::new(0);
+}
+
+// CORBSIZE and RIRBSIZE share the same layout
+pub struct RingSize;
+
+impl Bitfields for RingSize {
+    type Width = u8;
+}
+
+impl RingSize {
+    pub const SIZE: Field<RingSize> = Field::new(0, 2);
+    pub const TWO_ENTRIES_CAPABILITY: Bit<RingSize> = Bit::new(4);
+    pub const SIXTEEN_ENTRIES_CAPABILITY: Bit<RingSize> = Bit::new(5);
+    pub const TWO_HUNDRED_FIFTY_SIX_ENTRIES_CAPABILITY: Bit<RingSize> = Bit::new(6);
+}
+
+pub struct Rintcnt;
+
+impl Bitfields for Rintcnt {
+    type Width = u16;
+}
+
+impl Rintcnt {
+    // a value of 0 means 256 responses (see specification, section 3.3.28)
+    pub const RESPONSE_INTERRUPT_COUNT: Field<Rintcnt> = Field::new(0, 8);
+}
+
+pub struct Rirbctl;
+
+impl Bitfields for Rirbctl {
+    type Width = u8;
+}
+
+impl Rirbctl {
+    pub const RESPONSE_INTERRUPT_CONTROL: Bit<Rirbctl> = Bit::new(0);
+    pub const DMA_ENABLE: Bit<Rirbctl> = Bit::new(1);
+    pub const RESPONSE_OVERRUN_INTERRUPT_CONTROL: Bit<Rirbctl> = Bit::new(2);
+}
+
+pub struct Rirbsts;
+
+impl Bitfields for Rirbsts {
+    type Width = u8;
+}
+
+impl Rirbsts {
+    pub const RESPONSE_INTERRUPT: Bit<Rirbsts> = Bit::new(0);
+    pub const RESPONSE_OVERRUN_INTERRUPT_STATUS: Bit<Rirbsts> = Bit::new(2);
+}
+
+// see specification, section 3.4.3
+pub struct Icsts;
+
+impl Bitfields for Icsts {
+    type Width = u16;
+}
+
+impl Icsts {
+    pub const IMMEDIATE_COMMAND_BUSY: Bit<Icsts> = Bit::new(0);
+    pub const IMMEDIATE_RESULT_VALID: Bit<Icsts> = Bit::new(1);
+}
+
+pub struct Dplbase;
+
+impl Bitfields for Dplbase {
+    type Width = u32;
+}
+
+impl Dplbase {
+    pub const DMA_POSITION_BUFFER_ENABLE: Bit<Dplbase> = Bit::new(0);
+}
+
+pub struct Sdctl;
+
+// the register is only 3 bytes wide (see struct ThreeByteRegister), so bits of the following SDSTS register are rejected
+impl Bitfields for Sdctl {
+    type Width = u32;
+    const WIDTH_IN_BITS: u8 = 24;
+}
+
+impl Sdctl {
+    pub const STREAM_RESET: Bit<Sdctl> = Bit::new(0);
+    pub const STREAM_RUN: Bit<Sdctl> = Bit::new(1);
+    pub const INTERRUPT_ON_COMPLETION_ENABLE: Bit<Sdctl> = Bit::new(2);
+    pub const FIFO_ERROR_INTERRUPT_ENABLE: Bit<Sdctl> = Bit::new(3);
+    pub const DESCRIPTOR_ERROR_INTERRUPT_ENABLE: Bit<Sdctl> = Bit::new(4);
+    pub const TRAFFIC_PRIORITY_ENABLE: Bit<Sdctl> = Bit::new(18);
+    // only implemented by bidirectional stream descriptors, set for output and cleared for input
+    pub const BIDIRECTIONAL_DIRECTION_CONTROL: Bit<Sdctl> = Bit::new(19);
+    pub const STREAM_NUMBER: Field<Sdctl> = Field::new(20, 4);
+}
+
+pub struct Sdsts;
+
+impl Bitfields for Sdsts {
+    type Width = u8;
+}
+
+impl Sdsts {
+    pub const BUFFER_COMPLETION_INTERRUPT_STATUS: Bit<Sdsts> = Bit::new(2);
+    pub const FIFO_ERROR: Bit<Sdsts> = Bit::new(3);
+    pub const DESCRIPTOR_ERROR: Bit<Sdsts> = Bit::new(4);
+    pub const FIFO_READY: Bit<Sdsts> = Bit::new(5);
+}
+
+pub struct Sdlvi;
+
+impl Bitfields for Sdlvi {
+    type Width = u16;
+}
+
+impl Sdlvi {
+    pub const LAST_VALID_INDEX: Field<Sdlvi> = Field::new(0, 8);
+}
+
+pub struct Sdfifow;
+
+impl Bitfields for Sdfifow {
+    type Width = u16;
+}
+
+impl Sdfifow {
+    pub const FIFO_WATERMARK: Field<Sdfifow> = Field::new(0, 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Sdctl3 = ThreeByteRegister<'static, Sdctl>;
+
+    #[test]
+    fn stream_id_set_and_get() {
+        for stream_id in 0..=15 {
+            let value = Sdctl3::insert_field(0, Sdctl::STREAM_NUMBER, stream_id).unwrap();
+            assert_eq!(value, stream_id << 20);
+            assert_eq!(Sdctl3::extract_field(value, Sdctl::STREAM_NUMBER), stream_id);
+        }
+    }
+
+    #[test]
+    fn stream_id_keeps_the_neighbouring_bits() {
+        assert_eq!(Sdctl3::insert_field(0xFF_FFFF, Sdctl::STREAM_NUMBER, 0x5), Some(0x5F_FFFF));
+        assert_eq!(Sdctl3::insert_field(0xFF_FFFF, Sdctl::STREAM_NUMBER, 0), Some(0x0F_FFFF));
+        // run bit, traffic priority and bidirectional direction control stay set, the old stream number gets replaced
+        assert_eq!(Sdctl3::insert_field(0xAC_0002, Sdctl::STREAM_NUMBER, 0x3), Some(0x3C_0002));
+        assert_eq!(Sdctl3::extract_field(0x3C_0002 | 0xF_FFFF, Sdctl::STREAM_NUMBER), 0x3);
+    }
+
+    #[test]
+    fn stream_id_above_4_bits_is_refused() {
+        assert_eq!(Sdctl3::insert_field(0, Sdctl::STREAM_NUMBER, 16), None);
+    }
+
+    #[test]
+    fn field_spanning_the_whole_register() {
+        let field = Field::<NoBitfields<u32>>::new(0, 32);
+        assert_eq!(field.mask(), u32::MAX);
+        assert_eq!(field.insert(0x1234_5678, 0xDEAD_BEEF), Some(0xDEAD_BEEF));
+        assert_eq!(field.extract(0xDEAD_BEEF), 0xDEAD_BEEF);
+    }
+
+    #[test]
+    fn register_field_refuses_values_instead_of_cutting_them_off() {
+        let mut value: u8 = 0xA5;
+        let register = Register::<u8, RingSize>::new(&mut value as *mut u8, "CORBSIZE");
+        assert_eq!(register.set_field(RingSize::SIZE, 0b100), Err(IhdaError::FieldOverflow("CORBSIZE", 0b100)));
+        assert_eq!(value, 0xA5);
+        assert_eq!(register.set_field(RingSize::SIZE, 0b10), Ok(()));
+        assert_eq!(value, 0xA6);
+    }
+
+    #[test]
+    fn three_bytes_get_split_into_word_and_byte() {
+        assert_eq!(Sdctl3::combine(0xBEEF, 0x5A), 0x5A_BEEF);
+        assert_eq!(Sdctl3::split(0x5A_BEEF), (0xBEEF, 0x5A));
+        assert_eq!(Sdctl3::split(Sdctl3::combine(0xFFFF, 0xFF)), (0xFFFF, 0xFF));
+    }
+
+    #[test]
+    #[should_panic]
+    fn value_above_3_bytes_panics() {
+        Sdctl3::split(0x100_0000);
+    }
+
+    #[test]
+    fn sdctl_accesses_leave_sdsts_alone() {
+        // SDCTL with the run bit and traffic priority set, followed by SDSTS with its status bits set
+        let mut stream_descriptor: u32 = 0x1C04_0002;
+        let sdctl = ThreeByteRegister::<Sdctl>::new(&mut stream_descriptor as *mut u32 as *mut u8, "SDCTL");
+        sdctl.set_field(Sdctl::STREAM_NUMBER, 7).unwrap();
+        sdctl.set(Sdctl::INTERRUPT_ON_COMPLETION_ENABLE);
+        sdctl.clear(Sdctl::STREAM_RUN);
+        assert_eq!(sdctl.field(Sdctl::STREAM_NUMBER), 7);
+        assert!(sdctl.is_set(Sdctl::TRAFFIC_PRIORITY_ENABLE));
+        assert!(!sdctl.is_set(Sdctl::STREAM_RUN));
+        assert_eq!(stream_descriptor, 0x1C74_0004);
+    }
+}
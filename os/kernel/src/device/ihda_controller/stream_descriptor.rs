@@ -0,0 +1,791 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use derive_getters::Getters;
+use log::debug;
+use spin::Mutex;
+use x86_64::structures::paging::PhysFrame;
+use crate::audio::format::AudioFormat;
+use crate::audio::streams::FifoTuning;
+use crate::device::ihda_controller::{wait_for, Controller, IhdaError, RegisterName, BIT_ASSERTION_TIMEOUT_IN_MS, DMA_POSITION_IN_BUFFER_ENTRY_SIZE_IN_BYTES,
+    RING_BUFFER_ADDRESS_RESERVED_BITS};
+use crate::device::ihda_controller::corb_rirb::assert_ring_buffer_alignment;
+use crate::device::ihda_controller::registers::{Dplbase, Intsts, Register, Sdctl, Sdfifow, Sdlvi, Sdsts, ThreeByteRegister};
+use crate::device::ihda_stream::{Stream, StreamBackend, MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES};
+use crate::memory::dma::{alloc_dma, CachePolicy};
+use crate::memory::PAGE_SIZE;
+
+// stream ids are 4 bits long and 0 is reserved for converters which don't belong to a stream (see specification, section 3.3.35)
+const MIN_STREAM_ID: u8 = 1;
+const MAX_STREAM_ID: u8 = 15;
+// Low latency streams with a higher bandwidth get a FIFO watermark of 64 bytes instead of 32 bytes (see fn enable_low_latency).
+// The limit is the bandwidth of a stereo stream with 32 bit containers at 48 kHz.
+const MAX_BANDWIDTH_FOR_32_BYTE_FIFO_WATERMARK: u32 = 384000;
+
+// direction of a bidirectional stream descriptor (see specification, section 3.3.35)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamDirection {
+    Input,
+    Output,
+}
+
+// representation of a register set for each stream descriptor (starting at offset 0x80)
+#[derive(Getters)]
+pub struct StreamDescriptorRegisters {
+    // SDCTL is only 3 bytes long and directly followed by SDSTS, see struct ThreeByteRegister
+    sdctl: ThreeByteRegister<'static, Sdctl>,
+    sdsts: Register<'static, u8, Sdsts>,
+    sdlpib: Register<'static, u32>,
+    sdcbl: Register<'static, u32>,
+    sdlvi: Register<'static, u16, Sdlvi>,
+    // The register SDFIFOW is only defined in 8-series-chipset-pch-datasheet.pdf for the chipset on the used testing device.
+    // As the IHDA specification doesn't mention this register at all, it might not exist for other IHDA sound cards.
+    // It is therefore only present if the controller is known to implement it (see ControllerCaps).
+    sdfifow: Option<Register<'static, u16, Sdfifow>>,
+    sdfifod: Register<'static, u16>,
+    sdfmt: Register<'static, u16>,
+    sdbdpl: Register<'static, u32>,
+    sdbdpu: Register<'static, u32>,
+    // Only present for bidirectional stream descriptors. A stream reset sets the direction control bit back to input,
+    // so the direction chosen for the current stream gets remembered and set again after every reset.
+    direction: Option<Mutex<StreamDirection>>,
+}
+
+impl StreamDescriptorRegisters {
+    pub fn new(sd_base_address: u64, fifo_watermark_register_available: bool, bidirectional: bool) -> Self {
+        Self {
+            sdctl: ThreeByteRegister::new(sd_base_address as *mut u8, "SDCTL"),
+            sdsts: Register::new((sd_base_address + 0x3) as *mut u8, "SDSTS"),
+            sdlpib: Register::new((sd_base_address + 0x4) as *mut u32, "SDLPIB"),
+            sdcbl: Register::new((sd_base_address + 0x8) as *mut u32, "SDCBL"),
+            sdlvi: Register::new((sd_base_address + 0xC) as *mut u16, "SDLVI"),
+            sdfifow: if fifo_watermark_register_available { Some(Register::new((sd_base_address + 0xE) as *mut u16, "SDFIFOW")) } else { None },
+            // bytes with offset 0x8E to 0x8F are reserved
+            sdfifod: Register::new((sd_base_address + 0x10) as *mut u16, "SDFIFOD"),
+            sdfmt: Register::new((sd_base_address + 0x12) as *mut u16, "SDFMT"),
+            // bytes with offset 0x94 to 0x97 are reserved
+            sdbdpl: Register::new((sd_base_address + 0x18) as *mut u32, "SDDPL"),
+            sdbdpu: Register::new((sd_base_address + 0x1C) as *mut u32, "SDDPU"),
+            direction: if bidirectional { Some(Mutex::new(StreamDirection::Input)) } else { None },
+        }
+    }
+
+    pub fn dump_to(&self, dump: &mut String) {
+        self.sdctl.dump_to(dump);
+        self.sdsts.dump_to(dump);
+        self.sdlpib.dump_to(dump);
+        self.sdcbl.dump_to(dump);
+        self.sdlvi.dump_to(dump);
+        if let Some(sdfifow) = self.sdfifow.as_ref() {
+            sdfifow.dump_to(dump);
+        }
+        self.sdfifod.dump_to(dump);
+        self.sdfmt.dump_to(dump);
+        self.sdbdpl.dump_to(dump);
+        self.sdbdpu.dump_to(dump);
+    }
+
+    // ########## SDCTL ##########
+    fn reset_stream(&self) -> Result<(), IhdaError> {
+        // the DMA engine has to be stopped before the stream descriptor gets reset (see specification, section 3.3.35)
+        self.clear_stream_run_bit();
+        wait_for(|| !self.stream_run_bit(), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Sdctl))?;
+
+        self.sdctl.set(Sdctl::STREAM_RESET);
+        wait_for(|| self.sdctl.is_set(Sdctl::STREAM_RESET), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Sdctl))?;
+
+        self.sdctl.clear(Sdctl::STREAM_RESET);
+        wait_for(|| !self.sdctl.is_set(Sdctl::STREAM_RESET), BIT_ASSERTION_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Sdctl))?;
+
+        if let Some(direction) = self.direction.as_ref() {
+            let direction = *direction.lock();
+            self.write_bidirectional_direction_control_bit(direction);
+        }
+        Ok(())
+    }
+
+    pub fn stream_run_bit(&self) -> bool {
+        self.sdctl.is_set(Sdctl::STREAM_RUN)
+    }
+
+    fn set_stream_run_bit(&self) {
+        self.sdctl.set(Sdctl::STREAM_RUN);
+    }
+
+    fn clear_stream_run_bit(&self) {
+        self.sdctl.clear(Sdctl::STREAM_RUN);
+    }
+
+    fn interrupt_on_completion_bit(&self) -> bool {
+        self.sdctl.is_set(Sdctl::INTERRUPT_ON_COMPLETION_ENABLE)
+    }
+
+    fn set_interrupt_on_completion_enable_bit(&self) {
+        self.sdctl.set(Sdctl::INTERRUPT_ON_COMPLETION_ENABLE);
+    }
+
+    fn clear_interrupt_on_completion_bit(&self) {
+        self.sdctl.clear(Sdctl::INTERRUPT_ON_COMPLETION_ENABLE);
+    }
+
+    fn fifo_error_interrupt_enable_bit(&self) -> bool {
+        self.sdctl.is_set(Sdctl::FIFO_ERROR_INTERRUPT_ENABLE)
+    }
+
+    fn set_fifo_error_interrupt_enable_bit(&self) {
+        self.sdctl.set(Sdctl::FIFO_ERROR_INTERRUPT_ENABLE);
+    }
+
+    fn clear_fifo_error_interrupt_enable_bit(&self) {
+        self.sdctl.clear(Sdctl::FIFO_ERROR_INTERRUPT_ENABLE);
+    }
+
+    fn descriptor_error_interrupt_enable_bit(&self) -> bool {
+        self.sdctl.is_set(Sdctl::DESCRIPTOR_ERROR_INTERRUPT_ENABLE)
+    }
+
+    fn set_descriptor_error_interrupt_enable_bit(&self) {
+        self.sdctl.set(Sdctl::DESCRIPTOR_ERROR_INTERRUPT_ENABLE);
+    }
+
+    fn clear_descriptor_error_interrupt_enable_bit(&self) {
+        self.sdctl.clear(Sdctl::DESCRIPTOR_ERROR_INTERRUPT_ENABLE);
+    }
+
+    // fn stripe_control();
+    // fn set_stripe_control();
+
+    fn traffic_priority_enable_bit(&self) -> bool {
+        self.sdctl.is_set(Sdctl::TRAFFIC_PRIORITY_ENABLE)
+    }
+
+    fn set_traffic_priority_enable_bit(&self) {
+        self.sdctl.set(Sdctl::TRAFFIC_PRIORITY_ENABLE);
+    }
+
+    fn clear_traffic_priority_enable_bit(&self) {
+        self.sdctl.clear(Sdctl::TRAFFIC_PRIORITY_ENABLE);
+    }
+
+    pub fn bidirectional_stream_direction(&self) -> Option<StreamDirection> {
+        self.direction.as_ref().map(|direction| *direction.lock())
+    }
+
+    fn set_bidirectional_stream_direction(&self, direction: StreamDirection) {
+        let current_direction = match self.direction.as_ref() {
+            Some(current_direction) => current_direction,
+            None => panic!("Trying to set the direction of a stream descriptor which is not bidirectional"),
+        };
+        if self.stream_run_bit() {
+            panic!("Trying to change the direction of a bidirectional stream while it is running is not allowed (see specification, section 3.3.35)");
+        }
+        *current_direction.lock() = direction;
+        self.write_bidirectional_direction_control_bit(direction);
+    }
+
+    fn write_bidirectional_direction_control_bit(&self, direction: StreamDirection) {
+        match direction {
+            StreamDirection::Input => self.sdctl.clear(Sdctl::BIDIRECTIONAL_DIRECTION_CONTROL),
+            StreamDirection::Output => self.sdctl.set(Sdctl::BIDIRECTIONAL_DIRECTION_CONTROL),
+        }
+    }
+
+    fn stream_id(&self) -> Result<u8, IhdaError> {
+        match self.sdctl.field(Sdctl::STREAM_NUMBER) {
+            0 => Err(IhdaError::InvalidStreamNumber),
+            stream_number => Ok(stream_number as u8),
+        }
+    }
+
+    fn set_stream_id(&self, stream_id: u8) -> Result<(), IhdaError> {
+        self.sdctl.set_field(Sdctl::STREAM_NUMBER, stream_id as u32)
+    }
+
+    // ########## SDSTS ##########
+    fn buffer_completion_interrupt_status_bit(&self) -> bool {
+        self.sdsts.is_set(Sdsts::BUFFER_COMPLETION_INTERRUPT_STATUS)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.9)
+    fn clear_buffer_completion_interrupt_status_bit(&self) {
+        self.sdsts.acknowledge(Sdsts::BUFFER_COMPLETION_INTERRUPT_STATUS);
+    }
+
+    fn fifo_error_bit(&self) -> bool {
+        self.sdsts.is_set(Sdsts::FIFO_ERROR)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.9)
+    fn clear_fifo_error_bit(&self) {
+        self.sdsts.acknowledge(Sdsts::FIFO_ERROR);
+    }
+
+    fn descriptor_error_bit(&self) -> bool {
+        self.sdsts.is_set(Sdsts::DESCRIPTOR_ERROR)
+    }
+
+    // bit gets cleared by writing a 1 to it (see specification, section 3.3.9)
+    fn clear_descriptor_error_bit(&self) {
+        self.sdsts.acknowledge(Sdsts::DESCRIPTOR_ERROR);
+    }
+
+    fn fifo_ready(&self) -> bool {
+        self.sdsts.is_set(Sdsts::FIFO_READY)
+    }
+
+    // ########## SDLPIB ##########
+    pub fn link_position_in_buffer(&self) -> u32 {
+        self.sdlpib.read()
+    }
+
+    // ########## SDCBL ##########
+    pub fn cyclic_buffer_lenght(&self) -> u32 {
+        self.sdcbl.read()
+    }
+
+    pub fn set_cyclic_buffer_lenght(&self, length: u32) {
+        if self.stream_run_bit() {
+            panic!("Trying to write to SDCBL register while stream running is not allowed (see specification, section 3.3.38)");
+        }
+        self.sdcbl.write(length);
+    }
+
+    // ########## SDLVI ##########
+    fn last_valid_index(&self) -> u8 {
+        self.sdlvi.field(Sdlvi::LAST_VALID_INDEX) as u8
+    }
+
+    fn set_last_valid_index(&self, length: u8) -> Result<(), IhdaError> {
+        if self.stream_run_bit() {
+            panic!("Trying to write to SDLVI register while stream running is not allowed (see specification, section 3.3.38)");
+        }
+        self.sdlvi.set_field(Sdlvi::LAST_VALID_INDEX, length as u32)
+    }
+
+    // ########## SDFIFOW ##########
+    fn has_fifo_watermark_register(&self) -> bool {
+        self.sdfifow.is_some()
+    }
+
+    // returns None if the controller doesn't implement SDFIFOW, so that the register never gets read on those controllers
+    fn fifo_watermark(&self) -> Option<Result<FIFOWatermark, IhdaError>> {
+        self.sdfifow.as_ref().map(|sdfifow| match sdfifow.field(Sdfifow::FIFO_WATERMARK) {
+            0b100 => Ok(FIFOWatermark::Bit32),
+            0b101 => Ok(FIFOWatermark::Bit64),
+            watermark => Err(IhdaError::InvalidFifoWatermark(watermark as u8)),
+        })
+    }
+
+    fn set_fifo_watermark(&self, watermark: FIFOWatermark) {
+        let sdfifow = match &self.sdfifow {
+            Some(sdfifow) => sdfifow,
+            None => panic!("Trying to set FIFO watermark on a controller without SDFIFOW register (check ControllerCaps before)"),
+        };
+        match watermark {
+            FIFOWatermark::Bit32 => sdfifow.write(0b100),
+            FIFOWatermark::Bit64 => sdfifow.write(0b101),
+        }
+    }
+
+    // ########## SDFIFOD ##########
+    pub fn fifo_size(&self) -> u16 {
+        self.sdfifod.read()
+    }
+
+    // ########## SDFMT ##########
+    fn stream_format(&self) -> AudioFormat {
+        AudioFormat::from_sdfmt_bits(self.sdfmt.read())
+    }
+
+    fn set_stream_format(&self, stream_format: AudioFormat) {
+        self.sdfmt.write(stream_format.to_sdfmt_bits());
+    }
+
+    // ########## SDBDPL and SDBDPU ##########
+    fn set_bdl_pointer_address(&self, address: u64) {
+        if self.stream_run_bit() {
+            panic!("Trying to write to BDL address registers while stream running is not allowed (see specification, section 3.3.38)");
+        }
+
+        self.sdbdpl.write((address & 0xFFFFFFFF) as u32);
+        self.sdbdpu.write(((address & 0xFFFFFFFF_00000000) >> 32) as u32);
+    }
+
+    fn bdl_pointer_address(&self) -> u64 {
+        ((self.sdbdpu.read() as u64) << 32) | self.sdbdpl.read() as u64
+    }
+
+    // Resets the stream descriptor and programs the buffer descriptor list, the cyclic buffer, the format and the interrupt
+    // settings again, e.g. after the DMA engine stopped because of a descriptor error (see specification, section 3.3.36).
+    // The DMA engine starts over at the first buffer, as the stream reset clears SDLPIB.
+    fn reprogram(&self, restart: bool) -> Result<(), IhdaError> {
+        let bdl_pointer_address = self.bdl_pointer_address();
+        let cyclic_buffer_length = self.cyclic_buffer_lenght();
+        let last_valid_index = self.last_valid_index();
+        let stream_format = self.stream_format();
+        let stream_id = self.stream_id()?;
+        let interrupt_on_completion = self.interrupt_on_completion_bit();
+        let traffic_priority = self.traffic_priority_enable_bit();
+        let fifo_watermark = self.fifo_watermark();
+
+        self.reset_stream()?;
+
+        self.set_bdl_pointer_address(bdl_pointer_address);
+        self.set_cyclic_buffer_lenght(cyclic_buffer_length);
+        self.set_last_valid_index(last_valid_index)?;
+        self.set_stream_format(stream_format);
+        self.set_stream_id(stream_id)?;
+        if interrupt_on_completion {
+            self.set_interrupt_on_completion_enable_bit();
+        }
+        self.set_fifo_error_interrupt_enable_bit();
+        self.set_descriptor_error_interrupt_enable_bit();
+        if traffic_priority {
+            self.set_traffic_priority_enable_bit();
+        }
+        if let Some(Ok(fifo_watermark)) = fifo_watermark {
+            self.set_fifo_watermark(fifo_watermark);
+        }
+
+        if restart {
+            self.set_stream_run_bit();
+        }
+        Ok(())
+    }
+}
+
+
+impl StreamBackend for StreamDescriptorRegisters {
+    fn reset_stream(&self) -> Result<(), IhdaError> {
+        StreamDescriptorRegisters::reset_stream(self)
+    }
+
+    fn set_bdl_pointer_address(&self, address: u64) {
+        StreamDescriptorRegisters::set_bdl_pointer_address(self, address)
+    }
+
+    fn set_cyclic_buffer_length(&self, length_in_bytes: u32) {
+        self.set_cyclic_buffer_lenght(length_in_bytes)
+    }
+
+    fn set_last_valid_index(&self, last_valid_index: u8) -> Result<(), IhdaError> {
+        StreamDescriptorRegisters::set_last_valid_index(self, last_valid_index)
+    }
+
+    fn set_stream_format(&self, stream_format: AudioFormat) {
+        StreamDescriptorRegisters::set_stream_format(self, stream_format)
+    }
+
+    fn set_stream_id(&self, stream_id: u8) -> Result<(), IhdaError> {
+        StreamDescriptorRegisters::set_stream_id(self, stream_id)
+    }
+
+    fn stream_run_bit(&self) -> bool {
+        StreamDescriptorRegisters::stream_run_bit(self)
+    }
+
+    fn set_stream_run_bit(&self) {
+        StreamDescriptorRegisters::set_stream_run_bit(self)
+    }
+
+    fn clear_stream_run_bit(&self) {
+        StreamDescriptorRegisters::clear_stream_run_bit(self)
+    }
+
+    fn set_interrupt_on_completion_enable_bit(&self) {
+        StreamDescriptorRegisters::set_interrupt_on_completion_enable_bit(self)
+    }
+
+    fn buffer_completion_interrupt_status_bit(&self) -> bool {
+        StreamDescriptorRegisters::buffer_completion_interrupt_status_bit(self)
+    }
+
+    fn clear_buffer_completion_interrupt_status_bit(&self) {
+        StreamDescriptorRegisters::clear_buffer_completion_interrupt_status_bit(self)
+    }
+
+    fn fifo_error_bit(&self) -> bool {
+        StreamDescriptorRegisters::fifo_error_bit(self)
+    }
+
+    fn descriptor_error_bit(&self) -> bool {
+        StreamDescriptorRegisters::descriptor_error_bit(self)
+    }
+
+    fn link_position_in_buffer(&self) -> u32 {
+        StreamDescriptorRegisters::link_position_in_buffer(self)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum FIFOWatermark {
+    Bit32,
+    Bit64,
+}
+
+impl FIFOWatermark {
+    fn in_bytes(&self) -> u8 {
+        match self {
+            FIFOWatermark::Bit32 => 32,
+            FIFOWatermark::Bit64 => 64,
+        }
+    }
+}
+
+impl Controller {
+    // ########## DPLBASE and DPUBASE ##########
+
+    fn enable_dma_position_buffer(&self) {
+        self.dpiblbase.set(Dplbase::DMA_POSITION_BUFFER_ENABLE);
+    }
+
+    pub fn disable_dma_position_buffer(&self) {
+        self.dpiblbase.clear(Dplbase::DMA_POSITION_BUFFER_ENABLE);
+    }
+
+    pub fn dma_position_buffer_address(&self) -> u64 {
+        // bit 0 of DPLBASE is the DMA Position Buffer Enable bit and bits [6:1] are reserved, so none of them are part of the address
+        (self.dpibubase.read() as u64) << 32 | (self.dpiblbase.read() & !RING_BUFFER_ADDRESS_RESERVED_BITS) as u64
+    }
+
+    fn set_dma_position_buffer_address(&self, start_frame: PhysFrame) {
+        // writing to DPLBASE and DPUBASE while a stream DMA engine is running is not allowed (see specification, section 3.3.32 and 3.3.33)
+        let mut stream_descriptors = self.input_stream_descriptors.iter().chain(self.output_stream_descriptors.iter()).chain(self.bidirectional_stream_descriptors.iter());
+        assert!(!stream_descriptors.any(|stream_descriptor| stream_descriptor.stream_run_bit()), "Trying to write to DMA position buffer address registers while a stream DMA engine is running");
+        let start_address = start_frame.start_address().as_u64();
+        assert_ring_buffer_alignment(start_address, "DMA position buffer");
+        let lbase = (start_address & 0xFFFFFFFF) as u32;
+        let ubase = ((start_address & 0xFFFFFFFF_00000000) >> 32) as u32;
+
+        // preserve DMA Position Buffer Enable bit at position 0 when writing address
+        self.dpiblbase.write(lbase | (self.dpiblbase.is_set(Dplbase::DMA_POSITION_BUFFER_ENABLE) as u32));
+        self.dpibubase.write(ubase);
+    }
+
+     pub fn init_dma_position_buffer(&self) {
+        let dmapib_memory = alloc_dma(1, CachePolicy::Uncached);
+
+        // the controller must not write positions into the memory of a previous buffer while it gets freed
+        self.disable_dma_position_buffer();
+        self.set_dma_position_buffer_address(dmapib_memory.start_frame());
+        *self.dma_position_buffer_memory.lock() = Some(dmapib_memory);
+        self.enable_dma_position_buffer();
+    }
+
+     pub fn stream_descriptor_position_in_current_buffer(&self, stream_descriptor_number: u32) -> u32 {
+        unsafe { (self.dma_position_buffer_entry_address(stream_descriptor_number) as *mut u32).read() }
+    }
+
+    fn dma_position_buffer_entry_address(&self, stream_descriptor_number: u32) -> u64 {
+        // see specification section 3.6.1
+        self.dma_position_buffer_address() + (stream_descriptor_number as u64 * (2 * DMA_POSITION_IN_BUFFER_ENTRY_SIZE_IN_BYTES))
+    }
+
+    // ########## stream descriptors ##########
+
+    // stream descriptors are numbered in the order input, output, bidirectional (see specification, section 3.3)
+    pub fn stream_descriptor_registers(&self, stream_descriptor_number: u32) -> &StreamDescriptorRegisters {
+        self.input_stream_descriptors.iter()
+            .chain(self.output_stream_descriptors.iter())
+            .chain(self.bidirectional_stream_descriptors.iter())
+            .nth(stream_descriptor_number as usize)
+            .unwrap_or_else(|| panic!("Stream descriptor [{}] does not exist on this controller", stream_descriptor_number))
+    }
+
+    // Input stream descriptors come first (see specification, section 3.3). Indices beyond the input stream descriptors
+    // refer to the bidirectional stream descriptors, which come after the output stream descriptors (see fn input_stream_descriptor).
+    pub fn input_stream_descriptor_number(&self, input_stream_descriptor_index: usize) -> u32 {
+        match input_stream_descriptor_index.checked_sub(self.input_stream_descriptors.len()) {
+            None => input_stream_descriptor_index as u32,
+            Some(bidirectional_index) => (self.input_stream_descriptors.len() + self.output_stream_descriptors.len() + bidirectional_index) as u32,
+        }
+    }
+
+    // Decodes INTSTS and clears the status bits of every stream descriptor it reports, so that the interrupt line gets deasserted
+    // again. Only stream descriptors with their bit set in INTSTS get their SDSTS read, which is the case as soon as one of the
+    // status bits got set whose interrupt is enabled in SDCTL (see specification, section 3.3.15). Buffer completions of streams
+    // which poll them (see fn Stream::acknowledge_buffer_completion) are therefore left alone. May be called from interrupt context.
+    pub fn take_stream_interrupts(&self) -> StreamInterrupts {
+        let mut interrupts = StreamInterrupts::default();
+        let interrupt_status = self.intsts.read();
+        if interrupt_status & Intsts::GLOBAL_INTERRUPT_STATUS.mask() == 0 {
+            return interrupts;
+        }
+
+        let stream_descriptors = self.input_stream_descriptors.iter().chain(self.output_stream_descriptors.iter()).chain(self.bidirectional_stream_descriptors.iter());
+        for (stream_descriptor_number, sd_registers) in stream_descriptors.enumerate() {
+            if interrupt_status & Intsts::stream_interrupt_status(stream_descriptor_number as u32).mask() == 0 {
+                continue;
+            }
+            if sd_registers.buffer_completion_interrupt_status_bit() {
+                sd_registers.clear_buffer_completion_interrupt_status_bit();
+                interrupts.buffer_completions |= 1 << stream_descriptor_number;
+            }
+            if sd_registers.fifo_error_bit() {
+                sd_registers.clear_fifo_error_bit();
+                interrupts.fifo_errors |= 1 << stream_descriptor_number;
+            }
+            if sd_registers.descriptor_error_bit() {
+                sd_registers.clear_descriptor_error_bit();
+                interrupts.descriptor_errors |= 1 << stream_descriptor_number;
+            }
+        }
+        interrupts
+    }
+
+    // Lets FIFO and descriptor errors of a stream descriptor raise an interrupt, so that fn take_stream_interrupts notices them
+    // even while the stream doesn't raise any buffer completion interrupts. A stream reset clears the enable bits in SDCTL again,
+    // while the stream interrupt enable bit in INTCTL stays set until the stream releases its claim (see StreamAllocation).
+    fn enable_stream_error_interrupts(&self, stream_descriptor_number: u32) {
+        let sd_registers = self.stream_descriptor_registers(stream_descriptor_number);
+        sd_registers.set_fifo_error_interrupt_enable_bit();
+        sd_registers.set_descriptor_error_interrupt_enable_bit();
+        self.set_stream_interrupt_enable_bit(stream_descriptor_number);
+    }
+
+    // resets a stream descriptor and programs it with its former settings again, restarting the DMA engine if requested
+    pub fn recover_stream_descriptor(&self, stream_descriptor_number: u32, restart: bool) -> Result<(), IhdaError> {
+        self.stream_descriptor_registers(stream_descriptor_number).reprogram(restart)?;
+        self.set_stream_interrupt_enable_bit(stream_descriptor_number);
+        Ok(())
+    }
+
+    // numbers of all stream descriptors whose DMA engine has been started, in the order of fn stream_descriptor_registers
+    pub fn running_stream_descriptors(&self) -> Vec<u32> {
+        self.input_stream_descriptors.iter()
+            .chain(self.output_stream_descriptors.iter())
+            .chain(self.bidirectional_stream_descriptors.iter())
+            .enumerate()
+            .filter(|(_, sd_registers)| sd_registers.stream_run_bit())
+            .map(|(stream_descriptor_number, _)| stream_descriptor_number as u32)
+            .collect()
+    }
+
+    // Lets the DMA engine of a stream get preferred by the controller and fetch data as soon as 32 bytes of its FIFO are free,
+    // so that streams which get refilled shortly before the DMA engine reaches the data don't run dry. Streams above
+    // MAX_BANDWIDTH_FOR_32_BYTE_FIFO_WATERMARK drain their FIFO too fast for requests of 32 bytes, so they fetch 64 bytes at once.
+    // The stream must not be running, as the stream reset clears these settings anyway, and its format must already be programmed.
+    pub fn enable_low_latency(&self, stream_descriptor_number: u32) {
+        let sd_registers = self.stream_descriptor_registers(stream_descriptor_number);
+        if sd_registers.stream_run_bit() {
+            panic!("Trying to change traffic priority and FIFO watermark of running stream descriptor [{}]", stream_descriptor_number);
+        }
+
+        sd_registers.set_traffic_priority_enable_bit();
+        if sd_registers.has_fifo_watermark_register() {
+            let watermark = if sd_registers.stream_format().bytes_per_second() > MAX_BANDWIDTH_FOR_32_BYTE_FIFO_WATERMARK {
+                FIFOWatermark::Bit64
+            } else {
+                FIFOWatermark::Bit32
+            };
+            sd_registers.set_fifo_watermark(watermark);
+        }
+        debug!("Low latency enabled on stream descriptor [{}]: {:?}", stream_descriptor_number, self.fifo_tuning(stream_descriptor_number));
+    }
+
+    // FIFO size and watermark of a stream descriptor, which are only meaningful once its format has been programmed, as the
+    // controller sizes the FIFO of output streams according to the format (see specification, section 3.3.40).
+    // The DMA engine fetches up to the FIFO size ahead of the link position, so the data of a buffer is already in the FIFO
+    // shortly before the link reaches it. A buffer therefore has to be longer than the FIFO size plus the watermark, so that a
+    // refill on buffer completion can still write the next buffer before the DMA engine fetches from it.
+    pub fn fifo_tuning(&self, stream_descriptor_number: u32) -> FifoTuning {
+        let sd_registers = self.stream_descriptor_registers(stream_descriptor_number);
+        let fifo_size_in_bytes = sd_registers.fifo_size();
+        let watermark_in_bytes = match sd_registers.fifo_watermark() {
+            Some(Ok(watermark)) => Some(watermark.in_bytes()),
+            _ => None,
+        };
+        let unaligned_length = fifo_size_in_bytes as u32 + watermark_in_bytes.unwrap_or(0) as u32 + 1;
+        let min_buffer_length_in_bytes = unaligned_length.div_ceil(MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES) * MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES;
+        FifoTuning::new(fifo_size_in_bytes, watermark_in_bytes, min_buffer_length_in_bytes)
+    }
+
+    // the bidirectional stream descriptors directly follow the output stream descriptors, so this also holds for indices beyond them
+    pub fn output_stream_descriptor_number(&self, output_stream_descriptor_index: usize) -> u32 {
+        self.input_stream_descriptors.len() as u32 + output_stream_descriptor_index as u32
+    }
+
+    // includes the bidirectional stream descriptors, which get used once all output stream descriptors are taken (see fn output_stream_descriptor)
+    pub fn output_stream_descriptor_amount(&self) -> usize {
+        self.output_stream_descriptors.len() + self.bidirectional_stream_descriptors.len()
+    }
+
+    // Indices beyond the output stream descriptors refer to the bidirectional stream descriptors, which then get switched to output.
+    // A bidirectional stream descriptor can either be used for input or for output, so it must not be running in the other direction.
+    fn output_stream_descriptor(&self, output_stream_descriptor_index: usize) -> &StreamDescriptorRegisters {
+        match output_stream_descriptor_index.checked_sub(self.output_stream_descriptors.len()) {
+            None => &self.output_stream_descriptors[output_stream_descriptor_index],
+            Some(bidirectional_index) => self.bidirectional_stream_descriptor(bidirectional_index, StreamDirection::Output),
+        }
+    }
+
+    fn input_stream_descriptor(&self, input_stream_descriptor_index: usize) -> &StreamDescriptorRegisters {
+        match input_stream_descriptor_index.checked_sub(self.input_stream_descriptors.len()) {
+            None => &self.input_stream_descriptors[input_stream_descriptor_index],
+            Some(bidirectional_index) => self.bidirectional_stream_descriptor(bidirectional_index, StreamDirection::Input),
+        }
+    }
+
+    fn bidirectional_stream_descriptor(&self, bidirectional_index: usize, direction: StreamDirection) -> &StreamDescriptorRegisters {
+        let registers = self.bidirectional_stream_descriptors.get(bidirectional_index)
+            .unwrap_or_else(|| panic!("Bidirectional stream descriptor [{}] does not exist on this controller", bidirectional_index));
+        if registers.bidirectional_stream_direction() != Some(direction) {
+            if registers.stream_run_bit() {
+                panic!("Bidirectional stream descriptor [{}] is still running in the other direction", bidirectional_index);
+            }
+            debug!("Switching bidirectional stream descriptor [{}] to {:?}", bidirectional_index, direction);
+            registers.set_bidirectional_stream_direction(direction);
+        }
+        registers
+    }
+
+    pub fn position_source(&self) -> PositionSource {
+        *self.stream_position_source.lock()
+    }
+
+    pub fn set_position_source(&self, position_source: PositionSource) {
+        *self.stream_position_source.lock() = position_source;
+    }
+
+    // position of a DMA engine in its cyclic buffer, read from the source which got chosen by fn diagnose_position_sources
+    pub fn stream_position(&self, stream_descriptor_number: u32) -> u32 {
+        match self.position_source() {
+            PositionSource::LinkPositionInBuffer => self.stream_descriptor_registers(stream_descriptor_number).link_position_in_buffer(),
+            PositionSource::DmaPositionBuffer => self.stream_descriptor_position_in_current_buffer(stream_descriptor_number),
+        }
+    }
+
+    // ########## stream allocation ##########
+
+    // Claims a stream descriptor together with the lowest stream id no other stream uses. Both get released once the stream
+    // holding the allocation gets dropped, so two owners can't program the same stream descriptor or send the same stream id.
+    pub fn allocate_stream(&self, stream_descriptor_number: u32) -> Result<StreamAllocation, IhdaError> {
+        let stream_id = self.stream_allocator.lock().claim(stream_descriptor_number)?;
+        Ok(StreamAllocation { controller: self, stream_descriptor_number, stream_id })
+    }
+
+    // index of the first output stream descriptor from the given one on, which isn't claimed by a stream
+    pub fn free_output_stream_descriptor(&self, first_output_stream_descriptor_index: usize) -> Result<usize, IhdaError> {
+        let allocator = self.stream_allocator.lock();
+        (first_output_stream_descriptor_index..self.output_stream_descriptor_amount())
+            .find(|index| !allocator.is_claimed(self.output_stream_descriptor_number(*index)))
+            .ok_or(IhdaError::NoFreeStreamDescriptor)
+    }
+
+    // The stream gets the lowest free stream id (see fn allocate_stream). Fails with IhdaError::StreamDescriptorBusy
+    // if another stream still holds the stream descriptor.
+    pub fn prepare_output_stream(
+        &self,
+        output_sound_descriptor_number: usize,
+        stream_format: AudioFormat,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+    ) -> Result<Stream, IhdaError> {
+        let allocation = self.allocate_stream(self.output_stream_descriptor_number(output_sound_descriptor_number))?;
+        let stream = Stream::new(self.output_stream_descriptor(output_sound_descriptor_number), allocation, stream_format, buffer_amount, pages_per_buffer)?;
+        self.enable_stream_error_interrupts(self.output_stream_descriptor_number(output_sound_descriptor_number));
+        // ring writes and latency reports track the DMA engine through the same source as fn stream_position
+        if self.position_source() == PositionSource::DmaPositionBuffer {
+            stream.read_position_from(self.dma_position_buffer_entry_address(self.output_stream_descriptor_number(output_sound_descriptor_number)));
+        }
+        Ok(stream)
+    }
+
+    pub fn prepare_input_stream(
+        &self,
+        input_sound_descriptor_number: usize,
+        stream_format: AudioFormat,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+    ) -> Result<Stream, IhdaError> {
+        let allocation = self.allocate_stream(self.input_stream_descriptor_number(input_sound_descriptor_number))?;
+        let stream = Stream::new(self.input_stream_descriptor(input_sound_descriptor_number), allocation, stream_format, buffer_amount, pages_per_buffer)?;
+        self.enable_stream_error_interrupts(self.input_stream_descriptor_number(input_sound_descriptor_number));
+        if self.position_source() == PositionSource::DmaPositionBuffer {
+            stream.read_position_from(self.dma_position_buffer_entry_address(self.input_stream_descriptor_number(input_sound_descriptor_number)));
+        }
+        Ok(stream)
+    }
+
+    // the audio buffers only use an eighth of the pages they get allocated (see CyclicBuffer::new)
+    pub fn pages_per_buffer_for(&self, length_in_bytes: u32, buffer_amount: u32) -> u32 {
+        let bytes_per_page = PAGE_SIZE as u32 / 8;
+        let pages = length_in_bytes.div_ceil(buffer_amount * bytes_per_page);
+        // every buffer must at least be 128 bytes long (see BufferDescriptorList::new)
+        pages.max(1)
+    }
+}
+
+// Stream descriptors and stream ids claimed by streams, as pairs of stream descriptor number and stream id
+pub struct StreamAllocator {
+    claims: Vec<(u32, u8)>,
+}
+
+impl StreamAllocator {
+    pub const fn new() -> Self {
+        Self { claims: Vec::new() }
+    }
+
+    fn is_claimed(&self, stream_descriptor_number: u32) -> bool {
+        self.claims.iter().any(|(claimed_number, _)| *claimed_number == stream_descriptor_number)
+    }
+
+    fn claim(&mut self, stream_descriptor_number: u32) -> Result<u8, IhdaError> {
+        if self.is_claimed(stream_descriptor_number) {
+            return Err(IhdaError::StreamDescriptorBusy(stream_descriptor_number));
+        }
+        let stream_id = (MIN_STREAM_ID..=MAX_STREAM_ID)
+            .find(|stream_id| !self.claims.iter().any(|(_, claimed_id)| claimed_id == stream_id))
+            .ok_or(IhdaError::NoFreeStreamId)?;
+        self.claims.push((stream_descriptor_number, stream_id));
+        Ok(stream_id)
+    }
+
+    fn release(&mut self, stream_descriptor_number: u32) {
+        self.claims.retain(|(claimed_number, _)| *claimed_number != stream_descriptor_number);
+    }
+}
+
+// Claim of a stream descriptor and a stream id, which every stream holds (see Controller::allocate_stream).
+// It gets released when the stream gets dropped, after the stream reset its stream descriptor. The interrupt of the stream
+// descriptor stays enabled in INTCTL as long as the claim is held, so that no interrupt of a released stream descriptor can
+// reach the interrupt handler.
+pub struct StreamAllocation<'a> {
+    controller: &'a Controller,
+    stream_descriptor_number: u32,
+    stream_id: u8,
+}
+
+impl StreamAllocation<'_> {
+    pub fn stream_descriptor_number(&self) -> u32 {
+        self.stream_descriptor_number
+    }
+
+    pub fn stream_id(&self) -> u8 {
+        self.stream_id
+    }
+}
+
+impl Drop for StreamAllocation<'_> {
+    fn drop(&mut self) {
+        self.controller.clear_stream_interrupt_enable_bit(self.stream_descriptor_number);
+        self.controller.stream_allocator.lock().release(self.stream_descriptor_number);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PositionSource {
+    // SDLPIB register of the stream descriptor (see specification, section 3.3.37)
+    LinkPositionInBuffer,
+    // entry of the stream descriptor in the DMA position buffer (see specification, section 3.6.1)
+    DmaPositionBuffer,
+}
+
+// interrupt conditions of the stream descriptors collected by fn take_stream_interrupts, bit n belongs to stream descriptor n
+#[derive(Clone, Copy, Debug, Default, PartialEq, Getters)]
+pub struct StreamInterrupts {
+    buffer_completions: u32,
+    // the FIFO of the DMA engine ran empty (output) or full (input), so samples got lost (see specification, section 3.3.36)
+    fifo_errors: u32,
+    // the DMA engine fetched an invalid buffer descriptor and stopped
+    descriptor_errors: u32,
+}
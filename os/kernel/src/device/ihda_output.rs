@@ -0,0 +1,289 @@
+use alloc::vec::Vec;
+use log::warn;
+use crate::audio::device::{AudioDeviceError, OutputStreamHandle, SharedRing};
+use crate::audio::format::AudioFormat;
+use crate::audio::refill::BufferCompletions;
+use crate::audio::stream_registry;
+use crate::audio::streams::StreamOwner;
+use crate::device::ihda_api::IntelHDAudioDevice;
+use crate::device::ihda_stream::{RingWriteError, Stream};
+use crate::device::ihda_verbs::BitsPerSample;
+
+// Output streams opened through the AudioOutputDevice trait. Every stream owns an output stream descriptor, whose cyclic buffer
+// gets used as a ring (see fn Stream::try_write): the owner appends interleaved 16 bit samples and the DMA engine consumes them.
+// If the owner doesn't keep up, the DMA engine plays the old content of the ring once more, as with any cyclic buffer.
+// While the output routing is OutputRouting::Mirrored, a mirror endpoint whose converter can't decode the format of a stream
+// gets a second stream with the same rate, which is fed with the same samples converted to its channel count.
+// A stream whose ring has been shared (see fn OutputStreamTable::share_ring) gets written by its owner directly, so the table
+// only follows the write position the owner reports and neither scales the samples nor mirrors them anymore.
+
+// output stream descriptors 0 to 2 are used by the test tone, the audio service and the mixer
+const FIRST_OUTPUT_STREAM_DESCRIPTOR: usize = 3;
+// four buffers with 8 KiB each, so the ring holds about 170 ms of 16 bit stereo samples at 48 kHz
+const OUTPUT_BUFFER_AMOUNT: u32 = 4;
+const OUTPUT_PAGES_PER_BUFFER: u32 = 16;
+const MAX_VOLUME_PERCENT: u8 = 100;
+// rates a converter might support (see specification, section 7.3.4.7)
+const STANDARD_SAMPLE_RATES: [u32; 11] = [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000];
+
+struct OutputStream {
+    handle: OutputStreamHandle,
+    output_stream_descriptor_index: usize,
+    stream: Stream<'static>,
+    volume_percent: u8,
+    mirror: Option<MirrorStream>,
+    shared: bool,
+}
+
+// second stream of a mirror endpoint, which runs in lockstep with the original stream, as both have the same rate and ring size
+struct MirrorStream {
+    output_stream_descriptor_index: usize,
+    stream: Stream<'static>,
+}
+
+impl OutputStream {
+    // both streams get started right after each other, so the mirror lags behind by a few frames at most
+    fn start(&self, device: &IntelHDAudioDevice) {
+        device.start_stream(self.output_stream_descriptor_index, &self.stream);
+        if let Some(mirror) = self.mirror.as_ref() {
+            device.start_stream(mirror.output_stream_descriptor_index, &mirror.stream);
+        }
+    }
+}
+
+pub struct OutputStreamTable {
+    streams: Vec<OutputStream>,
+    next_handle: usize,
+}
+
+// the streams only get accessed while holding the lock of the table
+unsafe impl Send for OutputStreamTable {}
+
+impl OutputStreamTable {
+    pub const fn new() -> Self {
+        Self {
+            streams: Vec::new(),
+            next_handle: 1,
+        }
+    }
+
+    // 16 bit PCM formats the converter of the default output endpoint supports
+    pub fn supported_formats(device: &IntelHDAudioDevice) -> Vec<AudioFormat> {
+        let max_number_of_channels = device.max_output_channels();
+        let mut formats = Vec::new();
+        for sample_rate in STANDARD_SAMPLE_RATES {
+            for number_of_channels in 1..=max_number_of_channels {
+                if let Some(format) = AudioFormat::pcm(number_of_channels, BitsPerSample::Sixteen, sample_rate) {
+                    if device.supports_output_format(&format) {
+                        formats.push(format);
+                    }
+                }
+            }
+        }
+        formats
+    }
+
+    pub fn open(&mut self, device: &'static IntelHDAudioDevice, owner: StreamOwner, format: AudioFormat) -> Result<OutputStreamHandle, AudioDeviceError> {
+        // the samples get written as 16 bit PCM (see fn write)
+        if *format.number_of_channels() == 0 || *format.bits_per_sample() != BitsPerSample::Sixteen || !format.is_pcm() {
+            return Err(AudioDeviceError::UnsupportedFormat);
+        }
+        let output_stream_descriptor_index = Self::free_stream_descriptor(device)?;
+
+        let stream = device.open_output_stream(
+            owner,
+            None,
+            format,
+            output_stream_descriptor_index,
+            OUTPUT_BUFFER_AMOUNT,
+            OUTPUT_PAGES_PER_BUFFER,
+        ).map_err(AudioDeviceError::Playback)?;
+        stream.clear_buffers();
+        stream_registry().lock().set_write_position(device.output_stream_descriptor_number(output_stream_descriptor_index), 0);
+        let mirror = self.open_mirror(device, owner, output_stream_descriptor_index, &format);
+
+        let handle = OutputStreamHandle::new(self.next_handle);
+        self.next_handle += 1;
+        self.streams.push(OutputStream {
+            handle,
+            output_stream_descriptor_index,
+            stream,
+            volume_percent: MAX_VOLUME_PERCENT,
+            mirror,
+            shared: false,
+        });
+
+        Ok(handle)
+    }
+
+    // Only the first mirror endpoint which needs a stream of its own gets one, as there are only few stream descriptors.
+    // Mirroring is optional, so the original stream works without a mirror if there is no free stream descriptor left.
+    fn open_mirror(&self, device: &'static IntelHDAudioDevice, owner: StreamOwner, output_stream_descriptor_index: usize, stream_format: &AudioFormat) -> Option<MirrorStream> {
+        let (endpoint, mirror_format) = *device.mirror_endpoints_needing_separate_stream(None, stream_format).first()?;
+        let mirror_stream_descriptor_index = Self::free_stream_descriptor(device).ok()?;
+
+        let stream = match device.open_mirror_output_stream(
+            owner,
+            endpoint,
+            mirror_format,
+            mirror_stream_descriptor_index,
+            OUTPUT_BUFFER_AMOUNT,
+            OUTPUT_PAGES_PER_BUFFER,
+        ) {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!("Endpoint {:?} can't mirror the stream on stream descriptor index {}: {:?}", endpoint, output_stream_descriptor_index, error);
+                return None;
+            }
+        };
+        stream.clear_buffers();
+        stream_registry().lock().set_write_position(device.output_stream_descriptor_number(mirror_stream_descriptor_index), 0);
+
+        Some(MirrorStream { output_stream_descriptor_index: mirror_stream_descriptor_index, stream })
+    }
+
+    // the first output stream descriptor which is neither used by a stream of the table nor reserved, with its stream id
+    // The stream descriptor gets claimed when the stream is opened and released when the stream is dropped (see fn Controller::allocate_stream),
+    // so descriptors of streams in this table, their mirrors and streams opened elsewhere are all skipped.
+    fn free_stream_descriptor(device: &IntelHDAudioDevice) -> Result<usize, AudioDeviceError> {
+        device.free_output_stream_descriptor(FIRST_OUTPUT_STREAM_DESCRIPTOR).map_err(|_| AudioDeviceError::NoFreeStream)
+    }
+
+    pub fn write(&mut self, device: &IntelHDAudioDevice, handle: OutputStreamHandle, samples: &[i16]) -> Result<usize, AudioDeviceError> {
+        let output_stream = self.find_mut(handle)?;
+        let stream = &output_stream.stream;
+        let number_of_channels = *stream.stream_format().number_of_channels() as usize;
+        if samples.len() % number_of_channels != 0 {
+            return Err(AudioDeviceError::IncompleteFrame);
+        }
+
+        // only the samples which fit into the ring get scaled
+        let free_in_samples = (stream.free_space() / stream.stream_format().frame_size_in_bytes()) as usize * number_of_channels;
+        let volume_percent = output_stream.volume_percent as i32;
+        let scaled: Vec<i16> = samples[..samples.len().min(free_in_samples)].iter()
+            .map(|sample| (*sample as i32 * volume_percent / MAX_VOLUME_PERCENT as i32) as i16)
+            .collect();
+        let amount = match stream.try_write(&scaled) {
+            Ok(amount) => amount,
+            Err(RingWriteError::WouldBlock) => 0,
+            Err(RingWriteError::IncompleteFrame) => return Err(AudioDeviceError::IncompleteFrame),
+        };
+
+        let stream_descriptor_number = device.output_stream_descriptor_number(output_stream.output_stream_descriptor_index);
+        stream_registry().lock().set_write_position(stream_descriptor_number, stream.write_position_in_bytes());
+        if let Some(mirror) = output_stream.mirror.as_ref() {
+            Self::write_to_mirror(device, mirror, &scaled[..amount], number_of_channels);
+        }
+        if amount < samples.len() && !stream.state().is_active() {
+            output_stream.start(device);
+        }
+
+        Ok(amount)
+    }
+
+    // The mirror consumes its ring as fast as the original stream, so it has room for the samples as well. If it doesn't
+    // (e.g. because it started a little later), the samples are dropped for the mirror only.
+    fn write_to_mirror(device: &IntelHDAudioDevice, mirror: &MirrorStream, samples: &[i16], number_of_channels: usize) {
+        let mirror_number_of_channels = *mirror.stream.stream_format().number_of_channels() as usize;
+        let mut converted = Vec::with_capacity(samples.len() / number_of_channels * mirror_number_of_channels);
+        for frame in samples.chunks_exact(number_of_channels) {
+            for channel in 0..mirror_number_of_channels {
+                converted.push(frame[channel.min(number_of_channels - 1)]);
+            }
+        }
+
+        if let Err(RingWriteError::IncompleteFrame) = mirror.stream.try_write(&converted) {
+            warn!("Mirror stream dropped {} samples, which don't make up whole frames", converted.len());
+        }
+        let stream_descriptor_number = device.output_stream_descriptor_number(mirror.output_stream_descriptor_index);
+        stream_registry().lock().set_write_position(stream_descriptor_number, mirror.stream.write_position_in_bytes());
+    }
+
+    // samples which are already in the ring keep their volume
+    pub fn set_volume(&mut self, handle: OutputStreamHandle, volume_percent: u8) -> Result<(), AudioDeviceError> {
+        self.find_mut(handle)?.volume_percent = volume_percent.min(MAX_VOLUME_PERCENT);
+        Ok(())
+    }
+
+    pub fn position(&mut self, handle: OutputStreamHandle) -> Result<u32, AudioDeviceError> {
+        Ok(self.find_mut(handle)?.stream.hardware_position())
+    }
+
+    // starts the stream if the ring never got full
+    pub fn drain(&mut self, device: &IntelHDAudioDevice, handle: OutputStreamHandle) -> Result<u32, AudioDeviceError> {
+        let output_stream = self.find_mut(handle)?;
+        if !output_stream.stream.state().is_active() {
+            output_stream.start(device);
+        }
+        Ok(output_stream.stream.latency_frames())
+    }
+
+    // stops the stream immediately and releases its stream descriptor
+    pub fn close(&mut self, device: &IntelHDAudioDevice, handle: OutputStreamHandle) -> Result<(), AudioDeviceError> {
+        let index = self.streams.iter().position(|stream| stream.handle == handle).ok_or(AudioDeviceError::UnknownStream(handle))?;
+        let output_stream = self.streams.remove(index);
+        device.close_stream(output_stream.output_stream_descriptor_index, &output_stream.stream);
+        if let Some(mirror) = output_stream.mirror.as_ref() {
+            device.close_stream(mirror.output_stream_descriptor_index, &mirror.stream);
+        }
+        Ok(())
+    }
+
+    // The owner of a shared ring needs to know when the DMA engine finished a buffer, so the stream raises buffer completion
+    // interrupts from now on. A mirror would stay silent without fn write, so it gets closed. The buffer descriptor list
+    // can only be changed before the stream runs, so the ring has to be shared before the stream gets started.
+    pub fn share_ring(&mut self, device: &IntelHDAudioDevice, handle: OutputStreamHandle) -> Result<SharedRing, AudioDeviceError> {
+        let output_stream = self.find_mut(handle)?;
+        if output_stream.stream.state().is_active() {
+            return Err(AudioDeviceError::NotShareable);
+        }
+        let stream_descriptor_number = device.output_stream_descriptor_number(output_stream.output_stream_descriptor_index);
+        let (wall_clock_address, position_address) = device.stream_clock_addresses(stream_descriptor_number).ok_or(AudioDeviceError::UnknownStream(handle))?;
+
+        if let Some(mirror) = output_stream.mirror.take() {
+            device.close_stream(mirror.output_stream_descriptor_index, &mirror.stream);
+        }
+        if !output_stream.shared {
+            device.enable_buffer_completion_interrupt(&output_stream.stream);
+            output_stream.shared = true;
+        }
+
+        let stream = &output_stream.stream;
+        Ok(SharedRing::new(
+            stream.cyclic_buffer_frames(),
+            stream.buffer_amount(),
+            stream.buffer_length_in_bytes() / stream.buffer_amount() as u32,
+            stream.buffer_stride_in_bytes(),
+            wall_clock_address,
+            position_address,
+        ))
+    }
+
+    // streams which aren't shared don't raise buffer completion interrupts
+    pub fn buffer_completions(&mut self, device: &IntelHDAudioDevice, handle: OutputStreamHandle, completions: &BufferCompletions) -> Result<usize, AudioDeviceError> {
+        let output_stream = self.find_mut(handle)?;
+        Ok(completions.of(device.output_stream_descriptor_number(output_stream.output_stream_descriptor_index)))
+    }
+
+    // position is given in frames and has to lie inside of the ring
+    pub fn set_write_position(&mut self, device: &IntelHDAudioDevice, handle: OutputStreamHandle, position: u32) -> Result<(), AudioDeviceError> {
+        let output_stream = self.find_mut(handle)?;
+        if !output_stream.shared {
+            return Err(AudioDeviceError::NotShareable);
+        }
+        let stream = &output_stream.stream;
+        let frame_size_in_bytes = stream.stream_format().frame_size_in_bytes();
+        if position >= stream.buffer_length_in_bytes() / frame_size_in_bytes {
+            return Err(AudioDeviceError::InvalidWritePosition(position));
+        }
+
+        stream.set_write_position_in_bytes(position * frame_size_in_bytes);
+        let stream_descriptor_number = device.output_stream_descriptor_number(output_stream.output_stream_descriptor_index);
+        stream_registry().lock().set_write_position(stream_descriptor_number, stream.write_position_in_bytes());
+        Ok(())
+    }
+
+    fn find_mut(&mut self, handle: OutputStreamHandle) -> Result<&mut OutputStream, AudioDeviceError> {
+        self.streams.iter_mut().find(|stream| stream.handle == handle).ok_or(AudioDeviceError::UnknownStream(handle))
+    }
+}
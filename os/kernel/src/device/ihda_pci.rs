@@ -2,16 +2,17 @@
 
 use core::ops::BitOr;
 use log::{info};
-use pci_types::{Bar, BaseClass, CommandRegister, EndpointHeader, InterruptLine, SubClass};
+use pci_types::{Bar, BaseClass, CommandRegister, DeviceId, EndpointHeader, InterruptLine, SubClass, VendorId};
 use x86_64::structures::paging::{Page, PageTableFlags};
 use x86_64::structures::paging::page::PageRange;
 use x86_64::VirtAddr;
 use crate::process_manager;
-use crate::device::pci::PciBus;
+use crate::device::ihda_controller::IhdaError;
+use crate::device::pci::{MsiMessage, PciBus};
 use crate::device::qemu_cfg;
 use crate::memory::{MemorySpace, PAGE_SIZE};
 
-pub fn find_ihda_device(pci_bus: &PciBus) -> &EndpointHeader {
+pub fn find_ihda_device(pci_bus: &PciBus) -> Result<&EndpointHeader, IhdaError> {
     const PCI_MULTIMEDIA_DEVICE:  BaseClass = 4;
     const PCI_IHDA_DEVICE:  SubClass = 3;
 
@@ -35,21 +36,21 @@ pub fn find_ihda_device(pci_bus: &PciBus) -> &EndpointHeader {
         vendor id / device id combinations, so that the driver can explicitly filter devices by these ids.
         */
         if qemu_cfg::is_available() {
-            ihda_devices[0]
+            Ok(ihda_devices[0])
         } else {
             for device in ihda_devices {
                 match device.header().id(pci_bus.config_space()) {
                     (vendor_id, device_id) => {
                         if vendor_id == 0x8086 && device_id == 0x8c20 {
-                            return device;
+                            return Ok(device);
                         }
                     }
                 }
             }
-            panic!("None of the found IHDA devices is supported by the driver.")
+            Err(IhdaError::UnsupportedDevice)
         }
     } else {
-        panic!("No IHDA device found!");
+        Err(IhdaError::NoDeviceFound)
     }
 }
 
@@ -66,35 +67,73 @@ pub fn configure_pci(pci_bus: &PciBus, ihda_device: &EndpointHeader) {
     info!("Set Bus Master bit and Memory Space bit in PCI configuration space");
 }
 
+// reverts fn configure_pci for a device the driver failed to initialize, so that it stops responding to memory space accesses and DMA
+pub fn disable_pci(pci_bus: &PciBus, ihda_device: &EndpointHeader) {
+    ihda_device.update_command(pci_bus.config_space(), |command| {
+        command.difference(CommandRegister::BUS_MASTER_ENABLE | CommandRegister::MEMORY_ENABLE)
+    });
+    info!("Cleared Bus Master bit and Memory Space bit in PCI configuration space");
+}
+
+pub fn get_vendor_and_device_id(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> (VendorId, DeviceId) {
+    ihda_device.header().id(pci_bus.config_space())
+}
+
 pub fn get_interrupt_line(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> InterruptLine {
     let (_, interrupt_line) = ihda_device.interrupt(pci_bus.config_space());
     interrupt_line
 }
 
-pub fn map_mmio_space(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> VirtAddr {
-    // IHDA-MMIO address is always placed in bar 0 of the device's PCI configuration space
-    let bar0 = ihda_device.bar(0, pci_bus.config_space()).unwrap();
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterruptDelivery {
+    Msi,
+    MsiX,
+}
 
-    let mmio_base_address: u64;
-    let mmio_size: u64;
+// Lets the controller deliver its interrupts as messages instead of through the shared legacy interrupt line, preferring MSI over MSI-X.
+// MSI-X only gets used if its table lies in bar 0, as that is the only memory space of the device which gets mapped (see fn map_mmio_space).
+// Returns None if the device supports neither, in which case the legacy interrupt line stays in use.
+pub fn enable_message_signaled_interrupts(pci_bus: &PciBus, ihda_device: &EndpointHeader, mmio_base_address: VirtAddr, message: MsiMessage) -> Option<InterruptDelivery> {
+    let delivery = if let Some(msi) = pci_bus.msi_capability(ihda_device) {
+        msi.enable(pci_bus.config_space(), message);
+        InterruptDelivery::Msi
+    } else {
+        let msi_x = pci_bus.msi_x_capability(ihda_device).filter(|msi_x| msi_x.table_bar() == 0)?;
+        msi_x.enable(pci_bus.config_space(), mmio_base_address, 0, message);
+        InterruptDelivery::MsiX
+    };
 
-    match bar0 {
-        Bar::Memory32 { address, size, prefetchable: _ } => {
-            mmio_base_address = address as u64;
-            mmio_size = size as u64;
-        }
-        Bar::Memory64 { address, size, prefetchable: _ } => {
-            mmio_base_address = address;
-            mmio_size = size;
-        }
-        Bar::Io { .. } => {
-            panic!("This arm should never be reached as IHDA never uses I/O space bars")
-        }
+    // the controller would otherwise assert the legacy interrupt line in addition to sending messages
+    ihda_device.update_command(pci_bus.config_space(), |command| {
+        command.bitor(CommandRegister::INTERRUPT_DISABLE)
+    });
+    info!("Enabled {:?} interrupt delivery and disabled the legacy interrupt line", delivery);
+
+    Some(delivery)
+}
+
+// physical address and size of the MMIO space, which is always placed in bar 0 of the device's PCI configuration space
+fn mmio_region(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> Result<(u64, u64), IhdaError> {
+    match ihda_device.bar(0, pci_bus.config_space()).ok_or(IhdaError::NoMemorySpaceBar)? {
+        Bar::Memory32 { address, size, prefetchable: _ } => Ok((address as u64, size as u64)),
+        Bar::Memory64 { address, size, prefetchable: _ } => Ok((address, size)),
+        // IHDA never uses I/O space bars (see specification, section 3.3)
+        Bar::Io { .. } => Err(IhdaError::NoMemorySpaceBar),
     }
+}
+
+// the MMIO space gets mapped one-to-one, so this is also the virtual address returned by fn map_mmio_space
+pub fn mmio_base_address(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> Result<VirtAddr, IhdaError> {
+    let (mmio_base_address, _) = mmio_region(pci_bus, ihda_device)?;
+    Ok(VirtAddr::new(mmio_base_address))
+}
+
+pub fn map_mmio_space(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> Result<VirtAddr, IhdaError> {
+    let (mmio_base_address, mmio_size) = mmio_region(pci_bus, ihda_device)?;
 
     // set up MMIO space (in current state of D3OS one-to-one mapping from physical address space to virtual address space of kernel)
     let pages = mmio_size / (PAGE_SIZE as u64);
-    let mmio_page = Page::from_start_address(VirtAddr::new(mmio_base_address)).expect("IHDA MMIO address is not page aligned!");
+    let mmio_page = Page::from_start_address(VirtAddr::new(mmio_base_address)).map_err(|_| IhdaError::NoMemorySpaceBar)?;
     let address_space = process_manager().read().kernel_process().unwrap().address_space();
     address_space.map(
         PageRange { start: mmio_page, end: mmio_page + pages },
@@ -103,7 +142,7 @@ pub fn map_mmio_space(pci_bus: &PciBus, ihda_device: &EndpointHeader) -> VirtAdd
     );
     info!("Mapped MMIO registers to address {:#x}", mmio_base_address);
 
-    VirtAddr::new(mmio_base_address)
+    Ok(VirtAddr::new(mmio_base_address))
 }
 
 // Probably all functionality in this module could be useful in other contexts than initialising an ihda device.
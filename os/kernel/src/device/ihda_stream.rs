@@ -0,0 +1,1407 @@
+#![allow(dead_code)]
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::ptr::NonNull;
+use x86_64::structures::paging::frame::PhysFrameRange;
+use derive_getters::Getters;
+use log::warn;
+use volatile::VolatilePtr;
+use crate::{audio_service, scheduler};
+use crate::audio::format::AudioFormat;
+use crate::device::ihda_verbs::BitsPerSample;
+use crate::device::ihda_controller::{wait_for, IhdaError, RegisterName};
+use crate::device::ihda_controller::stream_descriptor::StreamAllocation;
+use crate::memory::dma::{alloc_dma, CachePolicy, DmaRegion};
+use crate::memory::PAGE_SIZE;
+use crate::audio::resampler::LinearResampler;
+use crate::audio::events::AudioEvent;
+use crate::audio::streams::StreamState;
+use crate::audio::synth::{SignalGenerator, Waveform};
+
+const BUFFER_DESCRIPTOR_LIST_ENTRY_SIZE_IN_BYTES: u64 = 16;
+// see specification, section 3.6.2 and 3.6.3
+const MAX_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES: usize = 256;
+const MIN_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES: usize = 2;
+// the low 7 bits of SDnBDPL are reserved, so the list itself must start on a 128 byte boundary (see specification, section 3.3.38)
+const BUFFER_DESCRIPTOR_LIST_ALIGNMENT_IN_BYTES: u64 = 128;
+const BUFFER_DESCRIPTOR_LIST_BUFFER_ALIGNMENT_IN_BYTES: u64 = 128;
+pub const MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES: u32 = 128;
+// time a blocking ring write waits for the DMA engine to free up space, before it checks again
+const RING_WRITE_RETRY_INTERVAL_IN_MS: usize = 1;
+// the DMA engine finishes the current transfer before it stops, which takes far less than a millisecond
+const RUN_BIT_CLEAR_TIMEOUT_IN_MS: usize = 10;
+const CONTAINER_8BIT_SIZE_IN_BYTES: u32 = 1;
+const CONTAINER_16BIT_SIZE_IN_BYTES: u32 = 2;
+const CONTAINER_32BIT_SIZE_IN_BYTES: u32 = 4;
+// a buffer topology uses between four and eight buffers, so that an owner can refill some of them while the others play
+const MIN_TOPOLOGY_BUFFER_AMOUNT: u32 = 4;
+const MAX_TOPOLOGY_BUFFER_AMOUNT: u32 = 8;
+// caps the cyclic buffer of a topology at 128 KiB of samples (see CyclicBuffer::new for the eighth of a page)
+const MAX_TOPOLOGY_PAGES_PER_BUFFER: u64 = 32;
+
+// Buffer descriptor lists, the cyclic buffers they describe and the streams built on top of them (see specification, section 3.6).
+// A stream only manages its DMA memory and its state, the stream descriptor registers get programmed through a StreamBackend.
+
+// The stream descriptor registers of a single stream (see specification, section 3.3.35 to 3.3.43), as far as a stream needs them
+// to configure, start, stop and track its DMA engine.
+pub trait StreamBackend {
+    fn reset_stream(&self) -> Result<(), IhdaError>;
+    fn set_bdl_pointer_address(&self, address: u64);
+    fn set_cyclic_buffer_length(&self, length_in_bytes: u32);
+    fn set_last_valid_index(&self, last_valid_index: u8) -> Result<(), IhdaError>;
+    fn set_stream_format(&self, stream_format: AudioFormat);
+    fn set_stream_id(&self, stream_id: u8) -> Result<(), IhdaError>;
+    fn stream_run_bit(&self) -> bool;
+    fn set_stream_run_bit(&self);
+    fn clear_stream_run_bit(&self);
+    fn set_interrupt_on_completion_enable_bit(&self);
+    fn buffer_completion_interrupt_status_bit(&self) -> bool;
+    fn clear_buffer_completion_interrupt_status_bit(&self);
+    fn fifo_error_bit(&self) -> bool;
+    fn descriptor_error_bit(&self) -> bool;
+    fn link_position_in_buffer(&self) -> u32;
+}
+
+// how the samples of a mono source get packed into a stream with more than one channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MonoPolicy {
+    DuplicateToAllChannels,
+    // all other channels get silenced
+    LeftOnly,
+}
+
+#[derive(Debug, Getters)]
+struct BufferDescriptorListEntry {
+    address: u64,
+    length_in_bytes: u32,
+    interrupt_on_completion: bool,
+}
+
+impl BufferDescriptorListEntry {
+    fn new(address: u64, length_in_bytes: u32, interrupt_on_completion: bool) -> Self {
+        Self {
+            address,
+            length_in_bytes,
+            interrupt_on_completion,
+        }
+    }
+
+    fn from(raw_data: u128) -> Self {
+        Self {
+            address: (raw_data & 0xFFFF_FFFF_FFFF_FFFF) as u64,
+            length_in_bytes: ((raw_data >> 64) & 0xFFFF_FFFF) as u32,
+            // probably better use get_bit() function from ihda_node_communication, after moving it to a better place
+            // or even better: use a proper library for all the bit operations on unsigned integers
+            interrupt_on_completion: ((raw_data >> 96) & 1) == 1,
+        }
+    }
+
+    fn as_u128(&self) -> u128 {
+        (self.interrupt_on_completion as u128) << 96 | (self.length_in_bytes as u128) << 64 | self.address as u128
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferDescriptorListError {
+    TooFewEntries(usize),
+    TooManyEntries(usize),
+    ListNotAligned(u64),
+    BufferNotAligned { index: usize, address: u64 },
+    BufferTooShort { index: usize, length_in_bytes: u32 },
+    BufferLengthNotMultipleOf128 { index: usize, length_in_bytes: u32 },
+}
+
+// The hardware behaviour is undefined for lists which violate one of these rules (see specification, section 3.6.2 and 3.6.3):
+// - a list must contain at least two and at most 256 entries
+// - each buffer must start on a 128 byte boundary
+// - each buffer must be at least 128 bytes long and its length must be a multiple of 128 bytes,
+//   so that no sample block gets split between two buffers
+fn validate_buffer_descriptor_list_entries(entries: &[BufferDescriptorListEntry]) -> Result<(), BufferDescriptorListError> {
+    if entries.len() < MIN_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES {
+        return Err(BufferDescriptorListError::TooFewEntries(entries.len()));
+    }
+    if entries.len() > MAX_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES {
+        return Err(BufferDescriptorListError::TooManyEntries(entries.len()));
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.address % BUFFER_DESCRIPTOR_LIST_BUFFER_ALIGNMENT_IN_BYTES != 0 {
+            return Err(BufferDescriptorListError::BufferNotAligned { index, address: entry.address });
+        }
+        if entry.length_in_bytes < MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES {
+            return Err(BufferDescriptorListError::BufferTooShort { index, length_in_bytes: entry.length_in_bytes });
+        }
+        if entry.length_in_bytes % MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES != 0 {
+            return Err(BufferDescriptorListError::BufferLengthNotMultipleOf128 { index, length_in_bytes: entry.length_in_bytes });
+        }
+    }
+
+    Ok(())
+}
+
+// one entry per buffer, which all raise an interrupt on completion until fn set_interrupt_on_completion_interval changes that
+fn buffer_descriptor_list_entries(audio_buffers: &[AudioBuffer]) -> Vec<BufferDescriptorListEntry> {
+    audio_buffers.iter()
+        .map(|buffer| BufferDescriptorListEntry::new(*buffer.start_address(), *buffer.length_in_bytes(), true))
+        .collect()
+}
+
+#[derive(Debug, Getters)]
+struct BufferDescriptorList {
+    base_address: u64,
+    entries: Vec<BufferDescriptorListEntry>,
+    last_valid_index: u8,
+    #[getter(skip)]
+    memory: DmaRegion,
+}
+
+impl BufferDescriptorList {
+    fn new(cyclic_buffer: &CyclicBuffer) -> Result<Self, BufferDescriptorListError> {
+        let entries = buffer_descriptor_list_entries(cyclic_buffer.audio_buffers());
+        validate_buffer_descriptor_list_entries(&entries)?;
+
+        // setup MMIO space for buffer descriptor list
+        // each entry is 128 bit long, so a single page holds 256 entries, but the list gets sized to its entries anyway,
+        // as the allocated frames are physically contiguous and the list may therefore span more than one page
+        let length_in_bytes = entries.len() as u64 * BUFFER_DESCRIPTOR_LIST_ENTRY_SIZE_IN_BYTES;
+        let memory = alloc_dma(length_in_bytes.div_ceil(PAGE_SIZE as u64) as usize, CachePolicy::Uncached);
+
+        let base_address = memory.virtual_address().as_u64();
+        if base_address % BUFFER_DESCRIPTOR_LIST_ALIGNMENT_IN_BYTES != 0 {
+            return Err(BufferDescriptorListError::ListNotAligned(base_address));
+        }
+
+        Ok(Self {
+            base_address,
+            last_valid_index: (entries.len() - 1) as u8,
+            entries,
+            memory,
+        })
+    }
+
+    fn get_entry(&self, index: u64) -> BufferDescriptorListEntry {
+        unsafe {
+            let address = VolatilePtr::new(NonNull::new((self.base_address + (index * BUFFER_DESCRIPTOR_LIST_ENTRY_SIZE_IN_BYTES)) as *mut u128).unwrap());
+            let raw_data = address.read();
+            BufferDescriptorListEntry::from(raw_data)
+        }
+    }
+
+    fn set_entry(&self, index: u64, entry: &BufferDescriptorListEntry) {
+        unsafe {
+            let address = VolatilePtr::new(NonNull::new((self.base_address + (index * BUFFER_DESCRIPTOR_LIST_ENTRY_SIZE_IN_BYTES)) as *mut u128).unwrap());
+            address.write(entry.as_u128())
+        };
+    }
+
+    // sets the IOC bit only in the last entry of every group of interval entries
+    fn set_interrupt_on_completion_interval(&self, interval: usize) {
+        for index in 0..=*self.last_valid_index() as u64 {
+            let entry = self.get_entry(index);
+            let interrupt_on_completion = (index as usize + 1) % interval == 0;
+            self.set_entry(index, &BufferDescriptorListEntry::new(entry.address, entry.length_in_bytes, interrupt_on_completion));
+        }
+    }
+}
+
+
+#[derive(Debug, Getters)]
+struct AudioBuffer {
+    start_address: u64,
+    length_in_bytes: u32,
+}
+
+impl AudioBuffer {
+    fn new(start_address: u64, length_in_bytes: u32) -> Self {
+        Self {
+            start_address,
+            length_in_bytes,
+        }
+    }
+
+    // the sample at the given index gets read from its container and scaled down to 16 bit
+    fn read_16bit_sample_from_buffer(&self, index: u64, bits_per_sample: BitsPerSample) -> i16 {
+        let offset_in_bytes = index as u32 * SampleContainer::size_in_bytes(bits_per_sample);
+        self.read_sample_container(offset_in_bytes, bits_per_sample).unpack_16bit(bits_per_sample)
+    }
+
+    fn length_in_samples(&self, bits_per_sample: BitsPerSample) -> usize {
+        (self.length_in_bytes / SampleContainer::size_in_bytes(bits_per_sample)) as usize
+    }
+
+    // The sample gets scaled up or down to the bit depth of the stream, so that 16 bit sources can be played on any stream.
+    // Writes of whole frames go through a FrameWriter instead.
+    fn write_16bit_sample_to_buffer(&self, sample: i16, index: usize, bits_per_sample: BitsPerSample) -> Result<(), BufferWriteError> {
+        let length_in_samples = self.length_in_samples(bits_per_sample);
+        if index >= length_in_samples {
+            return Err(BufferWriteError::SampleOutOfRange { sample_index: index, length_in_samples });
+        }
+        let offset_in_bytes = index as u32 * SampleContainer::size_in_bytes(bits_per_sample);
+        self.write_sample_container(SampleContainer::pack_16bit(sample, bits_per_sample), offset_in_bytes);
+        Ok(())
+    }
+
+    fn write_sample_container(&self, container: SampleContainer, offset_in_bytes: u32) {
+        if offset_in_bytes + container.length_in_bytes() > self.length_in_bytes {
+            panic!("Trying to write a sample at offset {} behind the end of a buffer with {} bytes", offset_in_bytes, self.length_in_bytes);
+        }
+        let address = self.start_address + offset_in_bytes as u64;
+        unsafe {
+            match container {
+                SampleContainer::Container8Bit(value) => (address as *mut u8).write(value),
+                SampleContainer::Container16Bit(value) => (address as *mut i16).write(value),
+                SampleContainer::Container32Bit(value) => (address as *mut i32).write(value),
+            }
+        }
+    }
+
+    fn length_in_frames_of(&self, stream_format: &AudioFormat) -> u32 {
+        self.length_in_bytes / stream_format.frame_size_in_bytes()
+    }
+
+    fn read_frames(&self, stream_format: &AudioFormat) -> Vec<i32> {
+        let container_size_in_bytes = SampleContainer::size_in_bytes(*stream_format.bits_per_sample());
+        let amount_of_samples = self.length_in_frames_of(stream_format) * *stream_format.number_of_channels() as u32;
+        (0..amount_of_samples)
+            .map(|index| self.read_sample_container(index * container_size_in_bytes, *stream_format.bits_per_sample()).unpack(*stream_format.bits_per_sample()))
+            .collect()
+    }
+
+    fn read_sample_container(&self, offset_in_bytes: u32, bits_per_sample: BitsPerSample) -> SampleContainer {
+        let address = self.start_address + offset_in_bytes as u64;
+        unsafe {
+            match SampleContainer::size_in_bytes(bits_per_sample) {
+                CONTAINER_8BIT_SIZE_IN_BYTES => SampleContainer::Container8Bit((address as *const u8).read()),
+                CONTAINER_16BIT_SIZE_IN_BYTES => SampleContainer::Container16Bit((address as *const i16).read()),
+                _ => SampleContainer::Container32Bit((address as *const i32).read()),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferWriteError {
+    FrameOutOfRange { frame_index: usize, length_in_frames: usize },
+    SampleOutOfRange { sample_index: usize, length_in_samples: usize },
+}
+
+// Writes whole frames into a single audio buffer. The writer knows the length of the buffer, the channels and the container size
+// of the stream format, so frames behind the end of the buffer get refused instead of ending up in the memory behind it, which
+// either belongs to the next buffer the DMA engine might be playing or isn't part of the cyclic buffer at all.
+// The samples of a frame are interleaved, so the first sample belongs to the first channel of the first frame, the second sample
+// to the second channel of the first frame and so on (see specification, section 4.5.1).
+struct FrameWriter<'b> {
+    buffer: &'b AudioBuffer,
+    stream_format: &'b AudioFormat,
+    length_in_frames: usize,
+}
+
+impl<'b> FrameWriter<'b> {
+    fn new(buffer: &'b AudioBuffer, stream_format: &'b AudioFormat) -> Self {
+        Self { buffer, stream_format, length_in_frames: buffer.length_in_frames_of(stream_format) as usize }
+    }
+
+    fn length_in_frames(&self) -> usize {
+        self.length_in_frames
+    }
+
+    fn number_of_channels(&self) -> usize {
+        *self.stream_format.number_of_channels() as usize
+    }
+
+    fn write_frame_containers(&self, frame_index: usize, containers: impl Iterator<Item = SampleContainer>) -> Result<(), BufferWriteError> {
+        if frame_index >= self.length_in_frames {
+            return Err(BufferWriteError::FrameOutOfRange { frame_index, length_in_frames: self.length_in_frames });
+        }
+        let frame_offset_in_bytes = frame_index as u32 * self.stream_format.frame_size_in_bytes();
+        let container_size_in_bytes = SampleContainer::size_in_bytes(*self.stream_format.bits_per_sample());
+        for (channel, container) in containers.take(self.number_of_channels()).enumerate() {
+            self.buffer.write_sample_container(container, frame_offset_in_bytes + channel as u32 * container_size_in_bytes);
+        }
+        Ok(())
+    }
+
+    fn write_16bit_frame(&self, frame_index: usize, frame: &[i16]) -> Result<(), BufferWriteError> {
+        if frame.len() != self.number_of_channels() {
+            panic!("Frame writer: frame with {} samples written into a stream with {} channels", frame.len(), self.number_of_channels());
+        }
+        let bits_per_sample = *self.stream_format.bits_per_sample();
+        self.write_frame_containers(frame_index, frame.iter().map(|sample| SampleContainer::pack_16bit(*sample, bits_per_sample)))
+    }
+
+    // Writes interleaved 16 bit samples frame by frame, starting at the given frame. Returns the amount of frames written,
+    // which is less than the samples make up if they don't fit into the buffer. An incomplete frame at the end gets left out.
+    fn write_16bit_frames(&self, first_frame_index: usize, samples: &[i16]) -> Result<usize, BufferWriteError> {
+        let bits_per_sample = *self.stream_format.bits_per_sample();
+        self.write_packed_frames(first_frame_index, samples, |sample| SampleContainer::pack_16bit(*sample, bits_per_sample))
+    }
+
+    // same as fn write_16bit_frames for samples in the range of the bit depth of the stream (see fn SampleContainer::pack)
+    fn write_frames(&self, first_frame_index: usize, samples: &[i32]) -> Result<usize, BufferWriteError> {
+        let bits_per_sample = *self.stream_format.bits_per_sample();
+        self.write_packed_frames(first_frame_index, samples, |sample| SampleContainer::pack(*sample, bits_per_sample))
+    }
+
+    fn write_packed_frames<T>(&self, first_frame_index: usize, samples: &[T], pack: impl Fn(&T) -> SampleContainer) -> Result<usize, BufferWriteError> {
+        if first_frame_index >= self.length_in_frames {
+            return Err(BufferWriteError::FrameOutOfRange { frame_index: first_frame_index, length_in_frames: self.length_in_frames });
+        }
+        let frames = samples.chunks_exact(self.number_of_channels()).take(self.length_in_frames - first_frame_index);
+        let amount = frames.len();
+        for (index, frame) in frames.enumerate() {
+            self.write_frame_containers(first_frame_index + index, frame.iter().map(&pack))?;
+        }
+        Ok(amount)
+    }
+}
+
+#[derive(Debug, Getters)]
+struct CyclicBuffer {
+    length_in_bytes: u32,
+    audio_buffers: Vec<AudioBuffer>,
+    #[getter(skip)]
+    memory: DmaRegion,
+}
+
+impl CyclicBuffer {
+    fn new(buffer_amount: u32, pages_per_buffer: u32) -> Self {
+        let memory = alloc_dma((buffer_amount * pages_per_buffer) as usize, CachePolicy::Uncached);
+        let buffer_size_in_bits = pages_per_buffer * PAGE_SIZE as u32;
+        let buffer_size_in_bytes = buffer_size_in_bits / 8;
+        let start_address = memory.virtual_address().as_u64();
+        let mut audio_buffers = Vec::new();
+        for index in 0..buffer_amount {
+            let buffer = AudioBuffer::new(start_address + (index * buffer_size_in_bits) as u64, buffer_size_in_bytes);
+            audio_buffers.push(buffer);
+        }
+        Self {
+            length_in_bytes: buffer_amount * buffer_size_in_bytes,
+            audio_buffers,
+            memory,
+        }
+    }
+
+    // The functions below take 16 bit samples, which get stored in the containers of the bit depth of the stream, so that sources
+    // with 16 bit samples can be played on streams with any bit depth (e.g. 24 bit samples in 32 bit containers).
+    // Packs every sample of a mono source into all channels of a frame, according to the mono policy of the stream.
+    // Returns the amount of frames written, which is less than the amount of samples if they don't fit into the buffer.
+    fn write_16bit_mono_samples_to_buffer(&self, buffer_index: usize, samples: &[i16], stream_format: &AudioFormat, mono_policy: MonoPolicy) -> usize {
+        let writer = FrameWriter::new(self.audio_buffers().get(buffer_index).unwrap(), stream_format);
+        let mut frame = vec![0i16; writer.number_of_channels()];
+        let amount = samples.len().min(writer.length_in_frames());
+        for (frame_index, sample) in samples[..amount].iter().enumerate() {
+            for (channel, channel_sample) in frame.iter_mut().enumerate() {
+                *channel_sample = match mono_policy {
+                    MonoPolicy::DuplicateToAllChannels => *sample,
+                    MonoPolicy::LeftOnly => if channel == 0 { *sample } else { 0 },
+                };
+            }
+            writer.write_16bit_frame(frame_index, &frame).unwrap();
+        }
+        amount
+    }
+
+    fn read_16bit_samples_from_buffer(&self, buffer_index: usize, bits_per_sample: BitsPerSample) -> Vec<i16> {
+        let buffer = self.audio_buffers().get(buffer_index).unwrap();
+        (0..(*buffer.length_in_bytes() / SampleContainer::size_in_bytes(bits_per_sample)) as u64)
+            .map(|index| buffer.read_16bit_sample_from_buffer(index, bits_per_sample))
+            .collect()
+    }
+
+    fn clear_buffer(&self, buffer_index: usize, bits_per_sample: BitsPerSample) {
+        self.clear_buffer_from(buffer_index, 0, bits_per_sample);
+    }
+
+    // silences the samples of a buffer from first_sample_index to its end
+    fn clear_buffer_from(&self, buffer_index: usize, first_sample_index: usize, bits_per_sample: BitsPerSample) {
+        let buffer = self.audio_buffers().get(buffer_index).unwrap();
+        for index in first_sample_index..buffer.length_in_samples(bits_per_sample) {
+            buffer.write_16bit_sample_to_buffer(0, index, bits_per_sample).unwrap();
+        }
+    }
+
+    // the resampler gets asked for every frame of the buffer, frames after the end of the source are silent
+    fn write_resampled_16bit_samples_to_buffer(&self, buffer_index: usize, samples: &[i16], first_frame_index: usize, stream_format: &AudioFormat, resampler: &LinearResampler) {
+        let writer = FrameWriter::new(self.audio_buffers().get(buffer_index).unwrap(), stream_format);
+        let mut frame = vec![0i16; writer.number_of_channels()];
+        for frame_index in 0..writer.length_in_frames() {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = resampler.sample_at(samples, first_frame_index + frame_index, channel as u8);
+            }
+            writer.write_16bit_frame(frame_index, &frame).unwrap();
+        }
+    }
+
+    fn write_16bit_samples_at(&self, position_in_bytes: u32, samples: &[i16], bits_per_sample: BitsPerSample) {
+        write_16bit_samples_to_ring(self.audio_buffers(), position_in_bytes, samples, bits_per_sample);
+    }
+
+    // returns the amount of frames written, see fn FrameWriter::write_16bit_frames
+    fn write_16bit_frames_to_buffer(&self, buffer_index: usize, samples: &[i16], stream_format: &AudioFormat) -> usize {
+        let writer = FrameWriter::new(self.audio_buffers().get(buffer_index).unwrap(), stream_format);
+        writer.write_16bit_frames(0, samples).unwrap()
+    }
+}
+
+// Treats the buffers as one ring, so the samples continue at the start of the first buffer when they reach the end of the last one.
+// All buffers have the same length, as they do in a cyclic buffer.
+fn write_16bit_samples_to_ring(audio_buffers: &[AudioBuffer], position_in_bytes: u32, samples: &[i16], bits_per_sample: BitsPerSample) {
+    let container_size_in_bytes = SampleContainer::size_in_bytes(bits_per_sample);
+    let buffer_length_in_bytes = *audio_buffers.first().unwrap().length_in_bytes();
+    let ring_length_in_bytes = buffer_length_in_bytes * audio_buffers.len() as u32;
+    for (index, sample) in samples.iter().enumerate() {
+        let position = (position_in_bytes + index as u32 * container_size_in_bytes) % ring_length_in_bytes;
+        let buffer = audio_buffers.get((position / buffer_length_in_bytes) as usize).unwrap();
+        buffer.write_16bit_sample_to_buffer(*sample, ((position % buffer_length_in_bytes) / container_size_in_bytes) as usize, bits_per_sample).unwrap();
+    }
+}
+
+// Amount and size of the buffers of a stream, derived from a target latency instead of raw page counts. The latency is the
+// length of the whole cyclic buffer, as a sample written behind the DMA engine waits for one pass through all buffers.
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct BufferTopology {
+    buffer_amount: u32,
+    pages_per_buffer: u32,
+    // latency the buffers actually achieve, which differs from the target as buffers only grow in steps of a page
+    latency_in_ms: u32,
+}
+
+impl BufferTopology {
+    // The amount of buffers is the largest power of two between MIN_TOPOLOGY_BUFFER_AMOUNT and MAX_TOPOLOGY_BUFFER_AMOUNT
+    // at which every buffer still gets a page, so that any smaller power of two divides it as an interrupt on completion interval.
+    // Every buffer gets as many pages as come closest to the target latency, but at least one, which keeps it above the
+    // minimum length of a buffer descriptor list entry (see specification, section 3.6.3), and at most MAX_TOPOLOGY_PAGES_PER_BUFFER.
+    pub fn for_latency(stream_format: &AudioFormat, target_latency_in_ms: u32) -> Self {
+        // the audio buffers only use an eighth of the pages they get allocated (see CyclicBuffer::new)
+        let bytes_per_page = PAGE_SIZE as u64 / 8;
+        let bytes_per_second = stream_format.bytes_per_second() as u64;
+        let target_length_in_bytes = target_latency_in_ms as u64 * bytes_per_second / 1000;
+
+        let mut buffer_amount = MAX_TOPOLOGY_BUFFER_AMOUNT;
+        while buffer_amount > MIN_TOPOLOGY_BUFFER_AMOUNT && target_length_in_bytes < buffer_amount as u64 * bytes_per_page {
+            buffer_amount /= 2;
+        }
+        let pages_per_buffer = ((target_length_in_bytes + buffer_amount as u64 * bytes_per_page / 2) / (buffer_amount as u64 * bytes_per_page)).clamp(1, MAX_TOPOLOGY_PAGES_PER_BUFFER);
+        let latency_in_ms = buffer_amount as u64 * pages_per_buffer * bytes_per_page * 1000 / bytes_per_second;
+
+        Self {
+            buffer_amount,
+            pages_per_buffer: pages_per_buffer as u32,
+            latency_in_ms: latency_in_ms as u32,
+        }
+    }
+}
+
+#[derive(Getters)]
+pub struct Stream<'a> {
+    // can't be exposed as a getter, as the backend is a trait object
+    #[getter(skip)]
+    backend: &'a dyn StreamBackend,
+    buffer_descriptor_list: BufferDescriptorList,
+    cyclic_buffer: CyclicBuffer,
+    stream_format: AudioFormat,
+    id: u8,
+    // can be switched at runtime, so it is not exposed as a getter for the cell
+    #[getter(skip)]
+    mono_policy: Cell<MonoPolicy>,
+    #[getter(skip)]
+    state: Cell<StreamState>,
+    // address of the entry of the stream descriptor in the DMA position buffer, SDLPIB gets used if None (e.g. while the DMA position buffer is disabled)
+    #[getter(skip)]
+    dma_position_entry: Cell<Option<u64>>,
+    // byte offset in the cyclic buffer behind the last sample written with fn try_write or fn write
+    #[getter(skip)]
+    write_position_in_bytes: Cell<u32>,
+    // times the DMA engine overtook the refills of the owner (see fn detect_underrun)
+    #[getter(skip)]
+    underrun_count: Cell<usize>,
+    // buffers with the IOC bit set, which the DMA engine had completed at the last call of fn detect_underrun, but whose interrupt hadn't been counted yet
+    #[getter(skip)]
+    outstanding_completions: Cell<usize>,
+    // the stream descriptor and the stream id are released once the stream got dropped and its stream descriptor got reset
+    #[getter(skip)]
+    allocation: StreamAllocation<'a>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RingWriteError {
+    // the ring of the output stream has no space left for a single frame
+    WouldBlock,
+    // the samples don't make up whole frames of the stream
+    IncompleteFrame,
+}
+
+// A Stream shouldn't live longer than the stream descriptor registers, through which it gets controlled
+// This gets expressed by the lifetime specifier 'a
+impl<'a> Stream<'a> {
+
+    pub fn new(
+        backend: &'a dyn StreamBackend,
+        allocation: StreamAllocation<'a>,
+        stream_format: AudioFormat,
+        buffer_amount: u32,
+        pages_per_buffer: u32,
+    ) -> Result<Self, IhdaError> {
+        // ########## allocate data buffers and bdl ##########
+
+        let cyclic_buffer = CyclicBuffer::new(buffer_amount, pages_per_buffer);
+
+        let bdl = BufferDescriptorList::new(&cyclic_buffer).map_err(IhdaError::InvalidBufferDescriptorList)?;
+
+
+        // ########## construct bdl ##########
+
+        for index in 0..=*bdl.last_valid_index() {
+            bdl.set_entry(index as u64, bdl.entries().get(index as usize).unwrap());
+        }
+
+
+        // ########## allocate and configure stream descriptor ##########
+
+        backend.reset_stream()?;
+
+        let stream = Self {
+            backend,
+            buffer_descriptor_list: bdl,
+            cyclic_buffer,
+            stream_format,
+            id: allocation.stream_id(),
+            mono_policy: Cell::new(MonoPolicy::DuplicateToAllChannels),
+            state: Cell::new(StreamState::Reset),
+            dma_position_entry: Cell::new(None),
+            write_position_in_bytes: Cell::new(0),
+            underrun_count: Cell::new(0),
+            outstanding_completions: Cell::new(0),
+            allocation,
+        };
+        stream.configure()?;
+        Ok(stream)
+    }
+
+    pub fn state(&self) -> StreamState {
+        self.state.get()
+    }
+
+    // every public operation of a stream has to go through this function, so that the order of operations demanded by the
+    // hardware is enforced (e.g. SDCBL, SDLVI and SDFMT must not be written while the stream is running, see specification, section 3.3.35)
+    fn transition_to(&self, new_state: StreamState) {
+        let current_state = self.state.get();
+        if !current_state.can_transition_to(new_state) {
+            panic!("Stream {}: invalid state transition from {:?} to {:?}", self.id, current_state, new_state);
+        }
+        self.state.set(new_state);
+    }
+
+    // writing data doesn't change the state of a running or paused stream, as refilling buffers is part of normal playback
+    fn prepare_for_write(&self) {
+        match self.state.get() {
+            StreamState::Running | StreamState::Paused => {}
+            _ => self.transition_to(StreamState::Prepared),
+        }
+    }
+
+    // programs the stream descriptor registers; only possible after a reset
+    pub fn configure(&self) -> Result<(), IhdaError> {
+        if self.state.get() != StreamState::Reset {
+            panic!("Stream {}: can only be configured in state Reset, but is in state {:?}", self.id, self.state.get());
+        }
+
+        self.backend.set_bdl_pointer_address(*self.buffer_descriptor_list.base_address());
+        // the DMA engine starts at the beginning of the cyclic buffer again after a reset
+        self.write_position_in_bytes.set(0);
+        self.outstanding_completions.set(0);
+
+        self.backend.set_cyclic_buffer_length(*self.cyclic_buffer.length_in_bytes());
+
+        self.backend.set_last_valid_index(*self.buffer_descriptor_list.last_valid_index())?;
+
+        self.backend.set_stream_format(self.stream_format);
+
+        self.backend.set_stream_id(self.id)?;
+
+        // backend.set_interrupt_on_completion_enable_bit();
+        // backend.set_fifo_error_interrupt_enable_bit();
+        // backend.set_descriptor_error_interrupt_enable_bit();
+
+        self.transition_to(StreamState::Configured);
+        Ok(())
+    }
+
+    // moves the stream into state Error if the DMA engine reported a FIFO or descriptor error (see specification, section 3.3.36)
+    pub fn check_for_errors(&self) -> bool {
+        if self.backend.fifo_error_bit() || self.backend.descriptor_error_bit() {
+            if self.state.get() != StreamState::Error {
+                self.backend.clear_stream_run_bit();
+                self.transition_to(StreamState::Error);
+            }
+            return true;
+        }
+        false
+    }
+
+    pub fn mono_policy(&self) -> MonoPolicy {
+        self.mono_policy.get()
+    }
+
+    // only affects data written after the switch
+    pub fn set_mono_policy(&self, mono_policy: MonoPolicy) {
+        self.mono_policy.set(mono_policy);
+    }
+
+    // Writes samples of a mono source, which get packed into all channels of the stream format according to the mono policy.
+    // Returns the amount of frames written, samples which don't fit into the buffer get dropped.
+    pub fn write_mono_data_to_buffer(&self, buffer_index: usize, samples: &[i16]) -> usize {
+        self.prepare_for_write();
+        self.cyclic_buffer.write_16bit_mono_samples_to_buffer(buffer_index, samples, &self.stream_format, self.mono_policy())
+    }
+
+    // fn write_data_to_buffer(&self, buffer_index: usize, samples: Vec<u16>) {
+    //     self.cyclic_buffer().write_samples_to_buffer(buffer_index, samples);
+    // }
+
+    pub fn buffer_length_in_bytes(&self) -> u32 {
+        *self.cyclic_buffer.length_in_bytes()
+    }
+
+    // page frames of the cyclic buffer, e.g. for mapping the ring into a process which writes its samples directly
+    pub fn cyclic_buffer_frames(&self) -> PhysFrameRange {
+        self.cyclic_buffer.memory.frames()
+    }
+
+    // Distance between the starts of two buffers in the page frames of the cyclic buffer. Every buffer starts on a page boundary
+    // of its own, so the stride can be larger than the length of a buffer and the buffers don't form one contiguous block.
+    pub fn buffer_stride_in_bytes(&self) -> u32 {
+        (self.cyclic_buffer.memory.length_in_bytes() / self.buffer_amount()) as u32
+    }
+
+    // Writes interleaved samples into a buffer and returns the amount of frames written. Frames which don't fit into the
+    // buffer get dropped, and so does an incomplete frame at the end.
+    pub fn write_data_to_buffer(&self, buffer_index: usize, samples: &[i16]) -> usize {
+        self.prepare_for_write();
+        self.cyclic_buffer().write_16bit_frames_to_buffer(buffer_index, samples, &self.stream_format)
+    }
+
+    // writes interleaved samples at a byte position of the cyclic buffer, e.g. behind the samples a process has written before
+    pub fn write_data_at(&self, position_in_bytes: u32, samples: &[i16]) {
+        self.prepare_for_write();
+        self.cyclic_buffer().write_16bit_samples_at(position_in_bytes, samples, *self.stream_format.bits_per_sample());
+    }
+
+    // Scatter-gather version of fn write_data_to_buffer: fills a buffer with the samples of all slices one after another, as if
+    // they were a single slice, so that chunks of any size don't have to be copied into one Vec first. Stops as soon as the
+    // buffer is full and returns the amount of samples written; the rest of a buffer that didn't get full is silenced.
+    pub fn write_iov(&self, buffer_index: usize, slices: &[&[i16]]) -> usize {
+        self.prepare_for_write();
+        let buffer_length_in_bytes = self.buffer_length_in_bytes() / self.buffer_amount() as u32;
+        let length_in_samples = self.buffer_length_in_frames() * *self.stream_format.number_of_channels() as usize;
+        let start_in_bytes = buffer_index as u32 * buffer_length_in_bytes;
+
+        let container_size_in_bytes = SampleContainer::size_in_bytes(*self.stream_format.bits_per_sample());
+
+        let mut written = 0;
+        for slice in slices {
+            let amount = slice.len().min(length_in_samples - written);
+            self.cyclic_buffer().write_16bit_samples_at(start_in_bytes + written as u32 * container_size_in_bytes, &slice[..amount], *self.stream_format.bits_per_sample());
+            written += amount;
+            if written == length_in_samples {
+                break;
+            }
+        }
+
+        self.cyclic_buffer().clear_buffer_from(buffer_index, written, *self.stream_format.bits_per_sample());
+        written
+    }
+
+    // the controller raises an interrupt each time the DMA engine finished a buffer with the IOC bit set in its BDL entry,
+    // which is every buffer unless fn set_interrupt_on_completion_interval has been called
+    pub fn enable_interrupt_on_completion(&self) {
+        self.backend.set_interrupt_on_completion_enable_bit();
+    }
+
+    // Lets only every interval-th buffer raise an interrupt on completion instead of every buffer, e.g. so that an interrupt
+    // handler can refill several buffers at once. The buffer descriptor list must not be changed while the DMA engine is
+    // fetching from it (see specification, section 3.6.2).
+    pub fn set_interrupt_on_completion_interval(&self, interval: usize) {
+        if self.state.get().is_active() {
+            panic!("Stream {}: the buffer descriptor list can't be changed while the stream is running", self.id);
+        }
+        if interval == 0 || interval > self.buffer_amount() || self.buffer_amount() % interval != 0 {
+            panic!("Stream {}: an interrupt on completion interval of {} doesn't divide {} buffers", self.id, interval, self.buffer_amount());
+        }
+        self.buffer_descriptor_list.set_interrupt_on_completion_interval(interval);
+    }
+
+    // returns true and clears the status bit if a buffer got completed since the last call
+    pub fn acknowledge_buffer_completion(&self) -> bool {
+        if self.backend.buffer_completion_interrupt_status_bit() {
+            self.backend.clear_buffer_completion_interrupt_status_bit();
+            return true;
+        }
+        false
+    }
+
+    pub fn buffer_amount(&self) -> usize {
+        self.cyclic_buffer().audio_buffers().len()
+    }
+
+    // Converts samples of another sample rate to the rate of the stream while filling the buffer.
+    // first_frame_index is the index of the first frame of the buffer in the resampled output.
+    pub fn write_resampled_data_to_buffer(&self, buffer_index: usize, samples: &[i16], first_frame_index: usize, resampler: &LinearResampler) {
+        if resampler.target_rate() != self.stream_format.sample_rate() {
+            panic!("Stream {}: resampler converts to {} Hz, but the stream runs at {} Hz", self.id, resampler.target_rate(), self.stream_format.sample_rate());
+        }
+        self.prepare_for_write();
+        self.cyclic_buffer().write_resampled_16bit_samples_to_buffer(buffer_index, samples, first_frame_index, &self.stream_format, resampler);
+    }
+
+    pub fn buffer_length_in_frames(&self) -> usize {
+        self.cyclic_buffer().audio_buffers().get(0).unwrap().length_in_frames_of(&self.stream_format) as usize
+    }
+
+    // Packs interleaved samples of any bit depth into the containers of the stream format, so that each channel of a
+    // stereo or surround stream ends up in its own slot of the frame. Every sample has to be in the range of the bit depth
+    // of the stream (e.g. -2^23 to 2^23 - 1 for 24 bit samples), values outside of it get clamped.
+    pub fn write_frames(&self, buffer_index: usize, samples: &[i32]) {
+        let number_of_channels = *self.stream_format.number_of_channels() as usize;
+        if samples.len() % number_of_channels != 0 {
+            panic!("Stream {}: {} samples don't make up whole frames of {} channels", self.id, samples.len(), number_of_channels);
+        }
+        if samples.len() / number_of_channels > self.buffer_length_in_frames() {
+            panic!("Stream {}: {} frames don't fit into a buffer of {} frames", self.id, samples.len() / number_of_channels, self.buffer_length_in_frames());
+        }
+        self.prepare_for_write();
+        FrameWriter::new(self.cyclic_buffer().audio_buffers().get(buffer_index).unwrap(), &self.stream_format).write_frames(0, samples).unwrap();
+    }
+
+    // interleaved samples of a buffer, unpacked from the containers of the stream format
+    pub fn read_frames(&self, buffer_index: usize) -> Vec<i32> {
+        self.cyclic_buffer().audio_buffers().get(buffer_index).unwrap().read_frames(&self.stream_format)
+    }
+
+    // ########## underruns ##########
+
+    // index of the buffer the DMA engine is currently playing
+    pub fn current_buffer_index(&self) -> usize {
+        let buffer_length_in_bytes = self.buffer_length_in_bytes() / self.buffer_amount() as u32;
+        (self.hardware_position_in_bytes() / buffer_length_in_bytes) as usize % self.buffer_amount()
+    }
+
+    // For owners which refill the buffers behind the DMA engine on buffer completion interrupts: the write cursor is the next
+    // buffer to refill, so the DMA engine has played every buffer from it up to the current buffer once. Each of these buffers
+    // with the IOC bit set raised one interrupt. If more interrupts have been counted since the last refill, the DMA engine wrapped
+    // around and replayed buffers that didn't get refilled, which is an underrun. The owner then has to silence the overtaken
+    // buffers from the current buffer up to the write cursor, before refilling the others.
+    // Completions which raise their interrupt while the refill is already running get counted for the next call, so at most one
+    // is carried over; if the interrupt handler merges several completions into one interrupt, the underrun goes unnoticed.
+    pub fn detect_underrun(&self, write_cursor: usize, current_buffer_index: usize, completed_buffers: usize) -> bool {
+        let mut expected_completions = self.outstanding_completions.get();
+        let mut buffer_index = write_cursor;
+        while buffer_index != current_buffer_index {
+            if *self.buffer_descriptor_list.get_entry(buffer_index as u64).interrupt_on_completion() {
+                expected_completions += 1;
+            }
+            buffer_index = (buffer_index + 1) % self.buffer_amount();
+        }
+
+        if completed_buffers > expected_completions {
+            self.outstanding_completions.set(0);
+            self.underrun_count.set(self.underrun_count.get() + 1);
+            audio_service().publish_event(AudioEvent::Underrun(self.allocation.stream_descriptor_number()));
+            return true;
+        }
+        self.outstanding_completions.set((expected_completions - completed_buffers).min(1));
+        false
+    }
+
+    pub fn underrun_count(&self) -> usize {
+        self.underrun_count.get()
+    }
+
+    // fills a single buffer with silence, e.g. one that has been overtaken by the DMA engine
+    pub fn clear_buffer(&self, buffer_index: usize) {
+        self.prepare_for_write();
+        self.cyclic_buffer().clear_buffer(buffer_index, *self.stream_format.bits_per_sample());
+    }
+
+    // ########## ring writes ##########
+
+    // The cyclic buffer gets used as one ring: the producer appends samples behind its write position, while the DMA engine
+    // consumes them. Writes never pass the position of the DMA engine, so samples which haven't been played yet can't get overwritten.
+
+    pub fn read_position_from(&self, dma_position_entry_address: u64) {
+        self.dma_position_entry.set(Some(dma_position_entry_address));
+    }
+
+    // position of the DMA engine in the cyclic buffer
+    pub fn hardware_position_in_bytes(&self) -> u32 {
+        let position = match self.dma_position_entry.get() {
+            Some(address) => unsafe { (address as *const u32).read_volatile() },
+            None => self.backend.link_position_in_buffer(),
+        };
+        position % self.buffer_length_in_bytes()
+    }
+
+    // position of the DMA engine in the cyclic buffer in frames of the stream format
+    pub fn hardware_position(&self) -> u32 {
+        self.hardware_position_in_bytes() / self.stream_format.frame_size_in_bytes()
+    }
+
+    // Frames between the DMA engine and the write position, which is how long a sample written now takes until the DMA engine
+    // fetches it. Samples in the FIFO of the controller and the codec add a few more frames, which aren't covered.
+    pub fn latency_frames(&self) -> u32 {
+        self.fill_level_in_bytes() / self.stream_format.frame_size_in_bytes()
+    }
+
+    pub fn write_position_in_bytes(&self) -> u32 {
+        self.write_position_in_bytes.get()
+    }
+
+    // For owners which write their samples into the ring themselves instead of through fn try_write (e.g. a process the ring
+    // is mapped into): the position behind the last sample they wrote, which decides the fill level and latency of the stream.
+    pub fn set_write_position_in_bytes(&self, position_in_bytes: u32) {
+        if position_in_bytes >= self.buffer_length_in_bytes() || position_in_bytes % self.stream_format.frame_size_in_bytes() != 0 {
+            panic!("Stream {}: write position {} is no frame boundary in a ring of {} bytes", self.id, position_in_bytes, self.buffer_length_in_bytes());
+        }
+        self.prepare_for_write();
+        self.write_position_in_bytes.set(position_in_bytes);
+    }
+
+    // bytes written to the ring, which the DMA engine hasn't fetched yet
+    pub fn fill_level_in_bytes(&self) -> u32 {
+        ring_fill_level_in_bytes(self.write_position_in_bytes.get(), self.hardware_position_in_bytes(), self.buffer_length_in_bytes())
+    }
+
+    // Bytes which can be written to the ring right now. One frame always stays free, so that a full ring can be told apart
+    // from an empty one.
+    pub fn free_space(&self) -> u32 {
+        ring_free_space_in_bytes(self.write_position_in_bytes.get(), self.hardware_position_in_bytes(), self.buffer_length_in_bytes(), self.stream_format.frame_size_in_bytes())
+    }
+
+    // Appends as many whole frames of interleaved 16 bit samples as fit into the ring and returns the amount of samples written.
+    // Wraps around to the start of the first buffer at the end of the last one.
+    pub fn try_write(&self, samples: &[i16]) -> Result<usize, RingWriteError> {
+        self.try_write_iov(&[samples])
+    }
+
+    // Scatter-gather version of fn try_write, which appends the samples of all slices one after another. Only the slices together
+    // have to make up whole frames, so a frame may start in one slice and end in the next one.
+    pub fn try_write_iov(&self, slices: &[&[i16]]) -> Result<usize, RingWriteError> {
+        let number_of_channels = *self.stream_format.number_of_channels() as usize;
+        let length_in_samples = slices.iter().map(|slice| slice.len()).sum::<usize>();
+        if length_in_samples % number_of_channels != 0 {
+            return Err(RingWriteError::IncompleteFrame);
+        }
+
+        let free_frames = (self.free_space() / self.stream_format.frame_size_in_bytes()) as usize;
+        if free_frames == 0 && length_in_samples > 0 {
+            return Err(RingWriteError::WouldBlock);
+        }
+        let free_samples = free_frames * number_of_channels;
+
+        let container_size_in_bytes = SampleContainer::size_in_bytes(*self.stream_format.bits_per_sample());
+        let mut written = 0;
+        for slice in slices {
+            let amount = slice.len().min(free_samples - written);
+            self.write_data_at(self.write_position_in_bytes.get() + written as u32 * container_size_in_bytes, &slice[..amount]);
+            written += amount;
+            if written == free_samples {
+                break;
+            }
+        }
+
+        let written_bytes = written as u32 * container_size_in_bytes;
+        self.write_position_in_bytes.set((self.write_position_in_bytes.get() + written_bytes) % self.buffer_length_in_bytes());
+        Ok(written)
+    }
+
+    // Writes all samples, waiting for the DMA engine to free up space whenever the ring is full. A stopped DMA engine never frees
+    // up space, so the write fails with RingWriteError::WouldBlock if the ring is full and the stream isn't running.
+    pub fn write(&self, samples: &[i16]) -> Result<(), RingWriteError> {
+        let mut written = 0;
+        while written < samples.len() {
+            match self.try_write(&samples[written..]) {
+                Ok(amount) => written += amount,
+                Err(RingWriteError::WouldBlock) if self.state.get() == StreamState::Running => scheduler().sleep(RING_WRITE_RETRY_INTERVAL_IN_MS),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    // samples recorded by an input stream
+    pub fn read_data_from_buffer(&self, buffer_index: usize) -> Vec<i16> {
+        self.cyclic_buffer().read_16bit_samples_from_buffer(buffer_index, *self.stream_format.bits_per_sample())
+    }
+
+    // fills all buffers with silence, so that an input stream doesn't return stale data of an earlier recording
+    pub fn clear_buffers(&self) {
+        self.prepare_for_write();
+        for buffer_index in 0..self.buffer_amount() {
+            self.cyclic_buffer().clear_buffer(buffer_index, *self.stream_format.bits_per_sample());
+        }
+    }
+
+    pub fn run(&self) {
+        self.transition_to(StreamState::Running);
+        self.backend.set_stream_run_bit();
+    }
+
+    // clears the RUN bit, which keeps the position of the DMA engine, so that the stream can continue with fn run
+    pub fn stop(&self) {
+        self.transition_to(StreamState::Paused);
+        self.backend.clear_stream_run_bit();
+    }
+
+    // Stops the DMA engine without resetting the stream, so SDLPIB, the DMA position entry and the write position keep their
+    // values and fn resume continues with the next frame. The RUN bit only reads 0 once the DMA engine actually stopped,
+    // which has to be awaited before the stream descriptor can be modified or started again (see specification, section 3.3.35).
+    pub fn pause(&self) -> Result<(), IhdaError> {
+        self.transition_to(StreamState::Paused);
+        self.backend.clear_stream_run_bit();
+
+        wait_for(|| !self.backend.stream_run_bit(), RUN_BIT_CLEAR_TIMEOUT_IN_MS).map_err(|_| IhdaError::Timeout(RegisterName::Sdctl))
+    }
+
+    // continues a paused stream at the position the DMA engine stopped at
+    pub fn resume(&self) {
+        if self.state.get() != StreamState::Paused {
+            panic!("Stream {}: can only be resumed in state Paused, but is in state {:?}", self.id, self.state.get());
+        }
+        self.run();
+    }
+
+    // Moves the write position to the given amount of frames ahead of the DMA engine. Frames between the DMA engine and the new
+    // write position get played with whatever the buffers hold, so seeking forward skips data and seeking to 0 discards
+    // everything written but not played yet.
+    pub fn seek(&self, frames: u32) {
+        let frame_size_in_bytes = self.stream_format.frame_size_in_bytes();
+        let length_in_frames = self.buffer_length_in_bytes() / frame_size_in_bytes;
+        if frames >= length_in_frames {
+            panic!("Stream {}: can't seek {} frames ahead in a ring of {} frames", self.id, frames, length_in_frames);
+        }
+        self.write_position_in_bytes.set(((self.hardware_position() + frames) % length_in_frames) * frame_size_in_bytes);
+    }
+
+    // Switches the stream to another format while keeping its buffers and buffer descriptor list. SDFMT must not be written
+    // while the DMA engine is running (see specification, section 3.3.35), so a running stream gets paused for the switch and
+    // resumed afterwards. The buffers still hold samples in the old format, so they get silenced and the write position
+    // starts at the DMA engine again. The converter widget has to be switched to the same format by the owner of the stream.
+    pub fn reconfigure(&mut self, stream_format: AudioFormat) -> Result<(), IhdaError> {
+        let was_running = match self.state.get() {
+            StreamState::Running | StreamState::Draining => {
+                self.pause()?;
+                true
+            }
+            StreamState::Configured | StreamState::Prepared | StreamState::Paused => false,
+            state => panic!("Stream {}: can't be reconfigured in state {:?}", self.id, state),
+        };
+
+        self.backend.set_stream_format(stream_format);
+        self.stream_format = stream_format;
+        for buffer_index in 0..self.buffer_amount() {
+            self.cyclic_buffer.clear_buffer(buffer_index, *self.stream_format.bits_per_sample());
+        }
+        self.seek(0);
+
+        if was_running {
+            self.resume();
+        }
+        Ok(())
+    }
+
+    // the owner won't write any more data, but the stream keeps running until it gets stopped
+    pub fn drain(&self) {
+        self.transition_to(StreamState::Draining);
+    }
+
+    // all stream descriptor registers lose their values, so the stream has to be configured again before it can be used
+    pub fn reset(&self) -> Result<(), IhdaError> {
+        self.backend.reset_stream()?;
+        self.clear_buffer_registers();
+        self.transition_to(StreamState::Reset);
+        Ok(())
+    }
+
+    // not every controller clears SDBDPL, SDBDPU and SDCBL on a stream reset, so they get cleared explicitly
+    fn clear_buffer_registers(&self) {
+        self.backend.set_bdl_pointer_address(0);
+        self.backend.set_cyclic_buffer_length(0);
+    }
+
+    // a generator which produces samples in the format of the stream
+    pub fn signal_generator(&self, waveform: Waveform, frequency: u32) -> SignalGenerator {
+        SignalGenerator::new(waveform, frequency, self.stream_format.sample_rate(), *self.stream_format.number_of_channels(), *self.stream_format.bits_per_sample())
+    }
+
+    // Fills all buffers with consecutive frames of the generator, so the wave continues seamlessly from one buffer into the next.
+    pub fn write_signal(&self, generator: &mut SignalGenerator) {
+        for buffer_index in 0..self.buffer_amount() {
+            self.write_signal_to_buffer(buffer_index, generator);
+        }
+    }
+
+    // only the first channel carries the signal if the mono policy is LeftOnly
+    pub fn write_signal_to_buffer(&self, buffer_index: usize, generator: &mut SignalGenerator) {
+        if *generator.sample_rate() != self.stream_format.sample_rate()
+            || *generator.number_of_channels() != *self.stream_format.number_of_channels()
+            || *generator.bits_per_sample() != *self.stream_format.bits_per_sample() {
+            panic!("Stream {}: signal generator doesn't produce samples in the stream format {:?}", self.id, self.stream_format);
+        }
+
+        let mut samples = generator.generate(self.buffer_length_in_frames());
+        if self.mono_policy() == MonoPolicy::LeftOnly {
+            for (index, sample) in samples.iter_mut().enumerate() {
+                if index % *self.stream_format.number_of_channels() as usize != 0 {
+                    *sample = 0;
+                }
+            }
+        }
+        self.write_frames(buffer_index, &samples);
+    }
+}
+
+// bytes from the position of the DMA engine up to the write position, both of them being offsets in the ring
+fn ring_fill_level_in_bytes(write_position_in_bytes: u32, hardware_position_in_bytes: u32, ring_length_in_bytes: u32) -> u32 {
+    (write_position_in_bytes + ring_length_in_bytes - hardware_position_in_bytes) % ring_length_in_bytes
+}
+
+// The position of the DMA engine doesn't have to be at a frame boundary, so it gets aligned down to the frame it is in. After an
+// underrun, the DMA engine may even have passed the write position, which leaves no space instead of wrapping around.
+fn ring_free_space_in_bytes(write_position_in_bytes: u32, hardware_position_in_bytes: u32, ring_length_in_bytes: u32, frame_size_in_bytes: u32) -> u32 {
+    let hardware_position_in_bytes = hardware_position_in_bytes - hardware_position_in_bytes % frame_size_in_bytes;
+    ring_length_in_bytes
+        .saturating_sub(ring_fill_level_in_bytes(write_position_in_bytes, hardware_position_in_bytes, ring_length_in_bytes))
+        .saturating_sub(frame_size_in_bytes)
+}
+
+// The stream owns its cyclic buffer and buffer descriptor list, whose DMA memory gets freed right after this. The DMA engine
+// therefore gets stopped and the stream descriptor must not point to the memory anymore. If the engine can't be stopped,
+// the memory gets leaked instead, as the engine might still read from it or, for input streams, write into it.
+impl Drop for Stream<'_> {
+    fn drop(&mut self) {
+        match self.backend.reset_stream() {
+            Ok(()) => self.clear_buffer_registers(),
+            Err(error) => {
+                warn!("Stream {}: could not be reset, leaking its DMA memory: {:?}", self.id, error);
+                self.buffer_descriptor_list.memory.leak();
+                self.cyclic_buffer.memory.leak();
+            }
+        }
+    }
+}
+
+// A sample in the container the specification demands for its bit depth (see specification, section 4.5.1):
+// 8 bit samples are unsigned, all other samples are signed; 20 and 24 bit samples are stored in the most significant bits
+// of a 32 bit container and the remaining bits are zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleContainer {
+    Container8Bit(u8),
+    Container16Bit(i16),
+    Container32Bit(i32),
+}
+
+impl SampleContainer {
+    // the sample is a signed value in the range of its bit depth, e.g. -2^23 to 2^23 - 1 for 24 bit samples
+    fn pack(sample: i32, bits_per_sample: BitsPerSample) -> Self {
+        match bits_per_sample {
+            BitsPerSample::Eight => SampleContainer::Container8Bit((sample.clamp(i8::MIN as i32, i8::MAX as i32) + 128) as u8),
+            BitsPerSample::Sixteen => SampleContainer::Container16Bit(sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16),
+            BitsPerSample::Twenty | BitsPerSample::Twentyfour => {
+                let unused_bits = 32 - bits_per_sample.bit_depth() as u32;
+                let max = i32::MAX >> unused_bits;
+                SampleContainer::Container32Bit(sample.clamp(-max - 1, max) << unused_bits)
+            }
+            BitsPerSample::Thirtytwo => SampleContainer::Container32Bit(sample),
+        }
+    }
+
+    fn unpack(&self, bits_per_sample: BitsPerSample) -> i32 {
+        match self {
+            SampleContainer::Container8Bit(value) => *value as i32 - 128,
+            SampleContainer::Container16Bit(value) => *value as i32,
+            SampleContainer::Container32Bit(value) => *value >> (32 - bits_per_sample.bit_depth() as u32),
+        }
+    }
+
+    // 16 bit samples get shifted to the bit depth of the container, so that they keep their loudness relative to full scale
+    fn pack_16bit(sample: i16, bits_per_sample: BitsPerSample) -> Self {
+        let bit_depth = bits_per_sample.bit_depth() as u32;
+        let sample = if bit_depth >= 16 { (sample as i32) << (bit_depth - 16) } else { (sample as i32) >> (16 - bit_depth) };
+        Self::pack(sample, bits_per_sample)
+    }
+
+    fn unpack_16bit(&self, bits_per_sample: BitsPerSample) -> i16 {
+        let bit_depth = bits_per_sample.bit_depth() as u32;
+        let sample = self.unpack(bits_per_sample);
+        (if bit_depth >= 16 { sample >> (bit_depth - 16) } else { sample << (16 - bit_depth) }) as i16
+    }
+
+    fn length_in_bytes(&self) -> u32 {
+        match self {
+            SampleContainer::Container8Bit(_) => CONTAINER_8BIT_SIZE_IN_BYTES,
+            SampleContainer::Container16Bit(_) => CONTAINER_16BIT_SIZE_IN_BYTES,
+            SampleContainer::Container32Bit(_) => CONTAINER_32BIT_SIZE_IN_BYTES,
+        }
+    }
+
+    pub fn size_in_bytes(bits_per_sample: BitsPerSample) -> u32 {
+        match bits_per_sample {
+            BitsPerSample::Eight => CONTAINER_8BIT_SIZE_IN_BYTES,
+            BitsPerSample::Sixteen => CONTAINER_16BIT_SIZE_IN_BYTES,
+            BitsPerSample::Twenty | BitsPerSample::Twentyfour | BitsPerSample::Thirtytwo => CONTAINER_32BIT_SIZE_IN_BYTES,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // audio buffers over ordinary memory instead of a DMA region, laid out back to back like the buffers of a cyclic buffer
+    fn audio_buffers(memory: &mut [u32], buffer_amount: u32) -> Vec<AudioBuffer> {
+        let buffer_length_in_bytes = (memory.len() * 4) as u32 / buffer_amount;
+        let start_address = memory.as_mut_ptr() as u64;
+        (0..buffer_amount)
+            .map(|index| AudioBuffer::new(start_address + (index * buffer_length_in_bytes) as u64, buffer_length_in_bytes))
+            .collect()
+    }
+
+    fn entries(amount: usize, length_in_bytes: u32) -> Vec<BufferDescriptorListEntry> {
+        (0..amount as u64).map(|index| BufferDescriptorListEntry::new(0x10_0000 + index * 0x1000, length_in_bytes, true)).collect()
+    }
+
+    #[test]
+    fn buffer_descriptor_list_entry_layout() {
+        let entry = BufferDescriptorListEntry::new(0x1_2345_6780, 0x1000, true);
+        let raw_data = entry.as_u128();
+        assert_eq!(raw_data, 1 << 96 | 0x1000 << 64 | 0x1_2345_6780);
+
+        let decoded = BufferDescriptorListEntry::from(raw_data);
+        assert_eq!((*decoded.address(), *decoded.length_in_bytes(), *decoded.interrupt_on_completion()), (0x1_2345_6780, 0x1000, true));
+        // the reserved bits above the IOC bit get ignored
+        let decoded = BufferDescriptorListEntry::from(u128::MAX << 97 | BufferDescriptorListEntry::new(0x80, 0x80, false).as_u128());
+        assert_eq!((*decoded.address(), *decoded.length_in_bytes(), *decoded.interrupt_on_completion()), (0x80, 0x80, false));
+    }
+
+    #[test]
+    fn buffer_descriptor_list_describes_every_buffer() {
+        let mut memory = vec![0u32; 512];
+        let buffers = audio_buffers(&mut memory, 4);
+        let entries = buffer_descriptor_list_entries(&buffers);
+        assert_eq!(entries.len(), 4);
+        for (entry, buffer) in entries.iter().zip(buffers.iter()) {
+            assert_eq!((*entry.address(), *entry.length_in_bytes(), *entry.interrupt_on_completion()), (*buffer.start_address(), 512, true));
+        }
+    }
+
+    #[test]
+    fn buffer_descriptor_list_validation() {
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(4, 0x200)), Ok(()));
+        assert_eq!(validate_buffer_descriptor_list_entries(&[]), Err(BufferDescriptorListError::TooFewEntries(0)));
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(257, 0x200)), Err(BufferDescriptorListError::TooManyEntries(257)));
+
+        let mut unaligned = entries(4, 0x200);
+        unaligned[2] = BufferDescriptorListEntry::new(0x10_2040, 0x200, true);
+        assert_eq!(validate_buffer_descriptor_list_entries(&unaligned), Err(BufferDescriptorListError::BufferNotAligned { index: 2, address: 0x10_2040 }));
+    }
+
+    #[test]
+    fn buffer_descriptor_list_needs_two_entries() {
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(1, 0x1000)), Err(BufferDescriptorListError::TooFewEntries(1)));
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(2, 0x80)), Ok(()));
+    }
+
+    #[test]
+    fn buffer_descriptor_list_takes_at_most_256_entries() {
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(MAX_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES, 0x80)), Ok(()));
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(MAX_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES + 1, 0x80)),
+            Err(BufferDescriptorListError::TooManyEntries(MAX_AMOUNT_OF_BUFFER_DESCRIPTOR_LIST_ENTRIES + 1)));
+    }
+
+    #[test]
+    fn buffer_shorter_than_128_bytes_gets_refused() {
+        let mut list = entries(4, 0x200);
+        list[3] = BufferDescriptorListEntry::new(*list[3].address(), 0x7F, true);
+        assert_eq!(validate_buffer_descriptor_list_entries(&list), Err(BufferDescriptorListError::BufferTooShort { index: 3, length_in_bytes: 0x7F }));
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(4, 0)), Err(BufferDescriptorListError::BufferTooShort { index: 0, length_in_bytes: 0 }));
+    }
+
+    #[test]
+    fn buffer_length_has_to_be_a_multiple_of_128_bytes() {
+        assert_eq!(validate_buffer_descriptor_list_entries(&entries(4, 0xC8)), Err(BufferDescriptorListError::BufferLengthNotMultipleOf128 { index: 0, length_in_bytes: 0xC8 }));
+        let mut list = entries(4, 0x100);
+        list[1] = BufferDescriptorListEntry::new(*list[1].address(), 0x101, true);
+        assert_eq!(validate_buffer_descriptor_list_entries(&list), Err(BufferDescriptorListError::BufferLengthNotMultipleOf128 { index: 1, length_in_bytes: 0x101 }));
+    }
+
+    #[test]
+    fn one_sample_buffers_get_refused() {
+        for bits_per_sample in [BitsPerSample::Eight, BitsPerSample::Sixteen, BitsPerSample::Twentyfour] {
+            let length_in_bytes = SampleContainer::size_in_bytes(bits_per_sample);
+            assert_eq!(validate_buffer_descriptor_list_entries(&entries(2, length_in_bytes)), Err(BufferDescriptorListError::BufferTooShort { index: 0, length_in_bytes }));
+        }
+    }
+
+    #[test]
+    fn ring_fill_level() {
+        assert_eq!(ring_fill_level_in_bytes(0, 0, 4096), 0);
+        assert_eq!(ring_fill_level_in_bytes(1000, 200, 4096), 800);
+        // the write position already wrapped around, while the DMA engine hasn't yet
+        assert_eq!(ring_fill_level_in_bytes(200, 1000, 4096), 3296);
+        assert_eq!(ring_fill_level_in_bytes(4092, 0, 4096), 4092);
+        assert_eq!(ring_fill_level_in_bytes(1000, 1000, 4096), 0);
+    }
+
+    #[test]
+    fn ring_free_space_keeps_one_frame_free() {
+        assert_eq!(ring_free_space_in_bytes(0, 0, 4096, 4), 4092);
+        assert_eq!(ring_free_space_in_bytes(1000, 200, 4096, 4), 3292);
+        assert_eq!(ring_free_space_in_bytes(196, 200, 4096, 4), 0);
+    }
+
+    #[test]
+    fn ring_free_space_aligns_the_hardware_position_to_a_frame() {
+        // the DMA engine is in the middle of the frame at the write position
+        assert_eq!(ring_free_space_in_bytes(1000, 1002, 4096, 4), 4092);
+        assert_eq!(ring_free_space_in_bytes(1000, 203, 4096, 4), 3292);
+    }
+
+    #[test]
+    fn ring_free_space_after_an_underrun_does_not_wrap() {
+        // the DMA engine passed the write position by less than a frame, then by one frame
+        assert_eq!(ring_free_space_in_bytes(996, 998, 4096, 6), 4090);
+        assert_eq!(ring_free_space_in_bytes(996, 1003, 4096, 6), 0);
+        // the DMA engine stopped in the last partial frame of a ring which isn't a whole number of frames long
+        assert_eq!(ring_free_space_in_bytes(0, 4095, 4096, 6), 4086);
+    }
+
+    #[test]
+    fn ring_write_wraps_around_to_the_first_buffer() {
+        // four buffers with eight 16 bit samples each
+        let mut memory = vec![0u32; 16];
+        let buffers = audio_buffers(&mut memory, 4);
+        let samples: Vec<i16> = (1..=8).collect();
+        write_16bit_samples_to_ring(&buffers, 56, &samples, BitsPerSample::Sixteen);
+
+        let read = |buffer: usize, index: u64| buffers[buffer].read_16bit_sample_from_buffer(index, BitsPerSample::Sixteen);
+        assert_eq!((4..8).map(|index| read(3, index)).collect::<Vec<i16>>(), [1, 2, 3, 4]);
+        assert_eq!((0..4).map(|index| read(0, index)).collect::<Vec<i16>>(), [5, 6, 7, 8]);
+        assert_eq!((0..8).map(|index| read(1, index)).collect::<Vec<i16>>(), [0; 8]);
+        assert_eq!(read(0, 4), 0);
+    }
+
+    #[test]
+    fn ring_write_uses_the_container_size_of_the_stream() {
+        // two buffers with four 32 bit containers each
+        let mut memory = vec![0u32; 8];
+        let buffers = audio_buffers(&mut memory, 2);
+        write_16bit_samples_to_ring(&buffers, 24, &[0x1234, -2, 0x7FFF], BitsPerSample::Twentyfour);
+        assert_eq!(memory[6..8], [0x1234_0000, 0xFFFE_0000]);
+        assert_eq!(memory[0], 0x7FFF_0000);
+    }
+
+    #[test]
+    fn frame_writer_stops_at_the_end_of_the_buffer() {
+        // four stereo frames of 16 bit samples
+        let mut memory = vec![0u32; 4];
+        let buffers = audio_buffers(&mut memory, 1);
+        let stream_format = AudioFormat::stereo_48khz_16bit();
+        let writer = FrameWriter::new(&buffers[0], &stream_format);
+        assert_eq!(writer.length_in_frames(), 4);
+
+        assert_eq!(writer.write_16bit_frames(2, &[1, 2, 3, 4, 5, 6, 7, 8]), Ok(2));
+        assert_eq!(writer.write_16bit_frames(4, &[1, 2]), Err(BufferWriteError::FrameOutOfRange { frame_index: 4, length_in_frames: 4 }));
+        // the incomplete frame at the end gets left out
+        assert_eq!(writer.write_16bit_frames(0, &[9, 10, 11]), Ok(1));
+        let samples: Vec<i16> = (0..8).map(|index| buffers[0].read_16bit_sample_from_buffer(index, BitsPerSample::Sixteen)).collect();
+        assert_eq!(samples, [9, 10, 0, 0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn sample_containers_of_every_bit_depth() {
+        assert_eq!(SampleContainer::pack(0, BitsPerSample::Eight), SampleContainer::Container8Bit(128));
+        assert_eq!(SampleContainer::pack(-128, BitsPerSample::Eight), SampleContainer::Container8Bit(0));
+        assert_eq!(SampleContainer::pack(127, BitsPerSample::Eight), SampleContainer::Container8Bit(255));
+        assert_eq!(SampleContainer::pack(-1, BitsPerSample::Sixteen), SampleContainer::Container16Bit(-1));
+        assert_eq!(SampleContainer::pack(1, BitsPerSample::Twenty), SampleContainer::Container32Bit(1 << 12));
+        assert_eq!(SampleContainer::pack(1, BitsPerSample::Twentyfour), SampleContainer::Container32Bit(1 << 8));
+        assert_eq!(SampleContainer::pack(-1, BitsPerSample::Twentyfour), SampleContainer::Container32Bit(-256));
+        assert_eq!(SampleContainer::pack(i32::MIN, BitsPerSample::Thirtytwo), SampleContainer::Container32Bit(i32::MIN));
+
+        assert_eq!(SampleContainer::size_in_bytes(BitsPerSample::Eight), 1);
+        assert_eq!(SampleContainer::size_in_bytes(BitsPerSample::Sixteen), 2);
+        assert_eq!(SampleContainer::size_in_bytes(BitsPerSample::Twenty), 4);
+        assert_eq!(SampleContainer::size_in_bytes(BitsPerSample::Twentyfour), 4);
+        assert_eq!(SampleContainer::size_in_bytes(BitsPerSample::Thirtytwo), 4);
+    }
+
+    #[test]
+    fn samples_out_of_range_get_clamped() {
+        assert_eq!(SampleContainer::pack(200, BitsPerSample::Eight), SampleContainer::Container8Bit(255));
+        assert_eq!(SampleContainer::pack(-200, BitsPerSample::Eight), SampleContainer::Container8Bit(0));
+        assert_eq!(SampleContainer::pack(40000, BitsPerSample::Sixteen), SampleContainer::Container16Bit(i16::MAX));
+        assert_eq!(SampleContainer::pack(1 << 23, BitsPerSample::Twentyfour), SampleContainer::Container32Bit(0x7FFF_FF00));
+        assert_eq!(SampleContainer::pack(-(1 << 23) - 1, BitsPerSample::Twentyfour), SampleContainer::Container32Bit(i32::MIN));
+        assert_eq!(SampleContainer::pack(1 << 19, BitsPerSample::Twenty), SampleContainer::Container32Bit(0x7FFF_F000));
+    }
+
+    #[test]
+    fn samples_survive_pack_and_unpack() {
+        let depths = [BitsPerSample::Eight, BitsPerSample::Sixteen, BitsPerSample::Twenty, BitsPerSample::Twentyfour, BitsPerSample::Thirtytwo];
+        for bits_per_sample in depths {
+            let max = (1i64 << (bits_per_sample.bit_depth() - 1)) - 1;
+            for sample in [-max - 1, -max / 3, -1, 0, 1, max / 2, max] {
+                let container = SampleContainer::pack(sample as i32, bits_per_sample);
+                assert_eq!(container.length_in_bytes(), SampleContainer::size_in_bytes(bits_per_sample));
+                assert_eq!(container.unpack(bits_per_sample), sample as i32, "{:?}", bits_per_sample);
+            }
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_samples_keep_their_loudness() {
+        assert_eq!(SampleContainer::pack_16bit(0x1234, BitsPerSample::Twentyfour), SampleContainer::Container32Bit(0x1234_0000));
+        assert_eq!(SampleContainer::pack_16bit(0x1234, BitsPerSample::Twenty), SampleContainer::Container32Bit(0x1234_0000));
+        assert_eq!(SampleContainer::pack_16bit(i16::MIN, BitsPerSample::Thirtytwo), SampleContainer::Container32Bit(i32::MIN));
+        assert_eq!(SampleContainer::pack_16bit(i16::MIN, BitsPerSample::Eight), SampleContainer::Container8Bit(0));
+        assert_eq!(SampleContainer::pack_16bit(i16::MAX, BitsPerSample::Eight), SampleContainer::Container8Bit(255));
+
+        for bits_per_sample in [BitsPerSample::Sixteen, BitsPerSample::Twenty, BitsPerSample::Twentyfour, BitsPerSample::Thirtytwo] {
+            for sample in [i16::MIN, -1234, -1, 0, 1, 4321, i16::MAX] {
+                assert_eq!(SampleContainer::pack_16bit(sample, bits_per_sample).unpack_16bit(bits_per_sample), sample);
+            }
+        }
+        // 8 bit containers drop the low byte
+        assert_eq!(SampleContainer::pack_16bit(0x1234, BitsPerSample::Eight).unpack_16bit(BitsPerSample::Eight), 0x1200);
+    }
+
+    #[test]
+    fn buffer_topology_for_latency() {
+        let stereo = AudioFormat::stereo_48khz_16bit();
+        assert_eq!(BufferTopology::for_latency(&stereo, 20), BufferTopology { buffer_amount: 4, pages_per_buffer: 2, latency_in_ms: 21 });
+        assert_eq!(BufferTopology::for_latency(&stereo, 100), BufferTopology { buffer_amount: 8, pages_per_buffer: 5, latency_in_ms: 106 });
+        let hi_res = AudioFormat::stereo_96khz_24bit();
+        assert_eq!(BufferTopology::for_latency(&hi_res, 50), BufferTopology { buffer_amount: 8, pages_per_buffer: 9, latency_in_ms: 48 });
+    }
+
+    #[test]
+    fn buffer_topology_stays_within_its_bounds() {
+        let stereo = AudioFormat::stereo_48khz_16bit();
+        assert_eq!(BufferTopology::for_latency(&stereo, 0), BufferTopology { buffer_amount: 4, pages_per_buffer: 1, latency_in_ms: 10 });
+        assert_eq!(BufferTopology::for_latency(&stereo, 10_000), BufferTopology { buffer_amount: 8, pages_per_buffer: 32, latency_in_ms: 682 });
+
+        for target_latency_in_ms in [0, 1, 5, 10, 21, 42, 85, 170, 500, 1000] {
+            for stream_format in [AudioFormat::mono_44khz_16bit(), stereo, AudioFormat::stereo_96khz_24bit()] {
+                let topology = BufferTopology::for_latency(&stream_format, target_latency_in_ms);
+                assert!([4, 8].contains(topology.buffer_amount()));
+                assert!((1..=MAX_TOPOLOGY_PAGES_PER_BUFFER as u32).contains(topology.pages_per_buffer()));
+                // every buffer has to be a valid buffer descriptor list entry
+                let buffer_length_in_bytes = *topology.pages_per_buffer() * PAGE_SIZE as u32 / 8;
+                assert_eq!(buffer_length_in_bytes % MIN_BUFFER_DESCRIPTOR_LIST_BUFFER_LENGTH_IN_BYTES, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,2890 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+use core::ops::BitAnd;
+use derive_getters::Getters;
+
+// Encoding of the verbs sent to the codecs and decoding of their responses (see specification, section 7.3). Everything in
+// here only depends on core and alloc and doesn't touch any hardware, so it can be built for the host as well.
+
+pub const MAX_AMOUNT_OF_CODECS: u8 = 15;
+const MAX_AMOUNT_OF_AMPLIFIERS_IN_AMP_WIDGET: u8 = 16;
+const MAX_AMPLIFIER_GAIN: u8 = 0x7F;
+// rates and sample sizes a converter might support, in the order of their bits in the response (see specification, section 7.3.4.7)
+pub const CONVERTER_SAMPLE_RATES: [u32; 12] = [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000, 384000];
+pub const CONVERTER_BITS_PER_SAMPLE: [BitsPerSample; 5] = [BitsPerSample::Eight, BitsPerSample::Sixteen, BitsPerSample::Twenty, BitsPerSample::Twentyfour, BitsPerSample::Thirtytwo];
+
+
+
+// ############################################## IHDA commands ##############################################
+
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct NodeAddress {
+    codec_address: CodecAddress,
+    node_id: u8,
+}
+
+impl NodeAddress {
+    pub fn new(codec_address: CodecAddress, node_id: u8) -> Self {
+        if codec_address.codec_address >= MAX_AMOUNT_OF_CODECS { panic!("IHDA only supports up to {} codecs!", MAX_AMOUNT_OF_CODECS) };
+        NodeAddress {
+            codec_address,
+            node_id,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct CodecAddress {
+    codec_address: u8,
+}
+
+impl CodecAddress {
+    pub fn new(codec_address: u8) -> Self {
+        if codec_address >= MAX_AMOUNT_OF_CODECS { panic!("IHDA only supports up to {} codecs!", MAX_AMOUNT_OF_CODECS) };
+        CodecAddress {
+            codec_address,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Command {
+    GetParameter(NodeAddress, Parameter),
+    GetConnectionSelect(NodeAddress),
+    SetConnectionSelect(NodeAddress, SetConnectionSelectPayload),
+    GetConnectionListEntry(NodeAddress, GetConnectionListEntryPayload),
+    GetAmplifierGainMute(NodeAddress, GetAmplifierGainMutePayload),
+    SetAmplifierGainMute(NodeAddress, SetAmplifierGainMutePayload),
+    GetStreamFormat(NodeAddress),
+    SetStreamFormat(NodeAddress, SetStreamFormatPayload),
+    GetChannelStreamId(NodeAddress),
+    SetChannelStreamId(NodeAddress, SetChannelStreamIdPayload),
+    GetPinWidgetControl(NodeAddress),
+    SetPinWidgetControl(NodeAddress, SetPinWidgetControlPayload),
+    GetEAPDBTLEnable(NodeAddress),
+    SetEAPDBTLEnable(NodeAddress, SetEAPDBTLEnablePayload),
+    GetDigitalConverterControl(NodeAddress),
+    SetDigitalConverterControl1(NodeAddress, SetDigitalConverterControl1Payload),
+    SetDigitalConverterControl2(NodeAddress, SetDigitalConverterControl2Payload),
+    GetConfigurationDefault(NodeAddress),
+    SetConfigurationDefault2(NodeAddress, SetConfigurationDefault2Payload),
+    GetConverterChannelCount(NodeAddress),
+    SetConverterChannelCount(NodeAddress, SetConverterChannelCountPayload),
+    GetUnsolicitedResponse(NodeAddress),
+    SetUnsolicitedResponse(NodeAddress, SetUnsolicitedResponsePayload),
+    GetPinSense(NodeAddress),
+    ExecutePinSense(NodeAddress),
+    GetVolumeKnob(NodeAddress),
+    SetVolumeKnob(NodeAddress, SetVolumeKnobPayload),
+    GetPowerState(NodeAddress),
+    SetPowerState(NodeAddress, SetPowerStatePayload),
+    GetCoefficientIndex(NodeAddress),
+    SetCoefficientIndex(NodeAddress, SetCoefficientIndexPayload),
+    GetProcessingCoefficient(NodeAddress),
+    SetProcessingCoefficient(NodeAddress, SetProcessingCoefficientPayload),
+    GetGPIOData(NodeAddress),
+    SetGPIOData(NodeAddress, SetGPIOPayload),
+    GetGPIOEnableMask(NodeAddress),
+    SetGPIOEnableMask(NodeAddress, SetGPIOPayload),
+    GetGPIODirection(NodeAddress),
+    SetGPIODirection(NodeAddress, SetGPIOPayload),
+    FunctionGroupReset(NodeAddress),
+}
+
+impl Command {
+    pub fn id(&self) -> u16 {
+        match self {
+            Command::GetParameter(..) => 0xF00,
+            Command::GetConnectionSelect(..) => 0xF01,
+            Command::SetConnectionSelect(..) => 0x701,
+            Command::GetConnectionListEntry(..) => 0xF02,
+            Command::GetAmplifierGainMute(..) => 0xB,
+            Command::SetAmplifierGainMute(..) => 0x3,
+            Command::GetStreamFormat(..) => 0xA,
+            Command::SetStreamFormat(..) => 0x2,
+            Command::GetChannelStreamId(..) => 0xF06,
+            Command::SetChannelStreamId(..) => 0x706,
+            Command::GetPinWidgetControl(..) => 0xF07,
+            Command::SetPinWidgetControl(..) => 0x707,
+            Command::GetEAPDBTLEnable(..) => 0xF0C,
+            Command::SetEAPDBTLEnable(..) => 0x70C,
+            Command::GetDigitalConverterControl(..) => 0xF0D,
+            Command::SetDigitalConverterControl1(..) => 0x70D,
+            Command::SetDigitalConverterControl2(..) => 0x70E,
+            Command::GetConfigurationDefault(..) => 0xF1C,
+            Command::SetConfigurationDefault2(..) => 0x71E,
+            Command::GetConverterChannelCount(..) => 0xF2D,
+            Command::SetConverterChannelCount(..) => 0x72D,
+            Command::GetUnsolicitedResponse(..) => 0xF08,
+            Command::SetUnsolicitedResponse(..) => 0x708,
+            Command::GetPinSense(..) => 0xF09,
+            Command::ExecutePinSense(..) => 0x709,
+            Command::GetVolumeKnob(..) => 0xF0F,
+            Command::SetVolumeKnob(..) => 0x70F,
+            Command::GetPowerState(..) => 0xF05,
+            Command::SetPowerState(..) => 0x705,
+            Command::GetCoefficientIndex(..) => 0xD,
+            Command::SetCoefficientIndex(..) => 0x5,
+            Command::GetProcessingCoefficient(..) => 0xC,
+            Command::SetProcessingCoefficient(..) => 0x4,
+            Command::GetGPIOData(..) => 0xF15,
+            Command::SetGPIOData(..) => 0x715,
+            Command::GetGPIOEnableMask(..) => 0xF16,
+            Command::SetGPIOEnableMask(..) => 0x716,
+            Command::GetGPIODirection(..) => 0xF17,
+            Command::SetGPIODirection(..) => 0x717,
+            Command::FunctionGroupReset(..) => 0x7FF,
+        }
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            Command::GetParameter(node_address, parameter) => Self::command_with_12bit_identifier_verb(node_address, self.id(), parameter.id()),
+            Command::GetConnectionSelect(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetConnectionSelect(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetConnectionListEntry(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetAmplifierGainMute(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
+            Command::SetAmplifierGainMute(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
+            Command::GetStreamFormat(node_address) => Self::command_with_4bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetStreamFormat(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
+            Command::GetChannelStreamId(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetChannelStreamId(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetPinWidgetControl(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetPinWidgetControl(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetEAPDBTLEnable(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetEAPDBTLEnable(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetDigitalConverterControl(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetDigitalConverterControl1(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::SetDigitalConverterControl2(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetConfigurationDefault(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetConfigurationDefault2(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetConverterChannelCount(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetConverterChannelCount(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetUnsolicitedResponse(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetUnsolicitedResponse(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetPinSense(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            // bit 0 of the payload selects the right channel for impedance sensing, the left channel is sensed by default
+            Command::ExecutePinSense(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::GetVolumeKnob(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetVolumeKnob(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetPowerState(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetPowerState(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetCoefficientIndex(node_address) => Self::command_with_4bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetCoefficientIndex(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
+            Command::GetProcessingCoefficient(node_address) => Self::command_with_4bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetProcessingCoefficient(node_address, payload) => Self::command_with_4bit_identifier_verb(node_address, self.id(), payload.as_u16()),
+            Command::GetGPIOData(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetGPIOData(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetGPIOEnableMask(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetGPIOEnableMask(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            Command::GetGPIODirection(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+            Command::SetGPIODirection(node_address, payload) => Self::command_with_12bit_identifier_verb(node_address, self.id(), payload.as_u8()),
+            // only valid for function group nodes (see section 7.3.3.33 of the specification)
+            Command::FunctionGroupReset(node_address) => Self::command_with_12bit_identifier_verb(node_address, self.id(), 0x0),
+        }
+    }
+
+    // the codec address is always encoded in bits [31:28] of a command (see specification, section 7.1.2)
+    pub fn codec_address(&self) -> u8 {
+        (self.as_u32() >> 28) as u8
+    }
+
+    fn command_with_12bit_identifier_verb(node_address: &NodeAddress, verb_id: u16, payload: u8) -> u32 {
+        (node_address.codec_address().codec_address as u32) << 28
+            | (*node_address.node_id() as u32) << 20
+            | (verb_id as u32) << 8
+            | payload as u32
+    }
+
+    fn command_with_4bit_identifier_verb(node_address: &NodeAddress, verb_id: u16, payload: u16) -> u32 {
+        (node_address.codec_address().codec_address as u32) << 28
+            | (*node_address.node_id() as u32) << 20
+            | (verb_id as u32) << 16
+            | payload as u32
+    }
+}
+
+// Splits an encoded verb back into its fields, the inverse of Command::as_u32. Verb ids starting with 0x7 or 0xF are 12 bits
+// long and carry an 8 bit payload, all others are 4 bits long and carry a 16 bit payload (see specification, section 7.1.2).
+#[derive(Clone, Copy, Debug, PartialEq, Getters)]
+pub struct VerbFields {
+    codec_address: u8,
+    node_id: u8,
+    verb_id: u16,
+    payload: u16,
+}
+
+impl VerbFields {
+    pub fn decode(verb: u32) -> Self {
+        let (verb_id, payload) = match (verb >> 16) & 0xF {
+            0x7 | 0xF => ((verb >> 8) & 0xFFF, verb & 0xFF),
+            _ => ((verb >> 16) & 0xF, verb & 0xFFFF),
+        };
+        Self {
+            codec_address: (verb >> 28) as u8,
+            node_id: (verb >> 20) as u8,
+            verb_id: verb_id as u16,
+            payload: payload as u16,
+        }
+    }
+}
+
+// compare to table 140 in section 7.3.6 of the specification
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    VendorId,
+    RevisionId,
+    SubordinateNodeCount,
+    FunctionGroupType,
+    AudioFunctionGroupCapabilities,
+    AudioWidgetCapabilities,
+    SampleSizeRateCAPs,
+    SupportedStreamFormats,
+    PinCapabilities,
+    InputAmpCapabilities,
+    OutputAmpCapabilities,
+    ConnectionListLength,
+    SupportedPowerStates,
+    ProcessingCapabilities,
+    GPIOCount,
+    VolumeKnobCapabilities,
+}
+
+impl Parameter {
+    pub fn id(&self) -> u8 {
+        match self {
+            Parameter::VendorId => 0x00,
+            Parameter::RevisionId => 0x02,
+            Parameter::SubordinateNodeCount => 0x04,
+            Parameter::FunctionGroupType => 0x05,
+            Parameter::AudioFunctionGroupCapabilities => 0x08,
+            Parameter::AudioWidgetCapabilities => 0x09,
+            Parameter::SampleSizeRateCAPs => 0x0A,
+            Parameter::SupportedStreamFormats => 0x0B,
+            Parameter::PinCapabilities => 0x0C,
+            Parameter::InputAmpCapabilities => 0x0D,
+            Parameter::OutputAmpCapabilities => 0x12,
+            Parameter::ConnectionListLength => 0x0E,
+            Parameter::SupportedPowerStates => 0x0F,
+            Parameter::ProcessingCapabilities => 0x10,
+            Parameter::GPIOCount => 0x11,
+            Parameter::VolumeKnobCapabilities => 0x13,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetConnectionSelectPayload {
+    connection_index: u8,
+}
+
+impl SetConnectionSelectPayload {
+    pub fn new(connection_index: u8) -> Self {
+        Self {
+            connection_index,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.connection_index
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GetConnectionListEntryPayload {
+    offset: u8,
+}
+
+impl GetConnectionListEntryPayload {
+    pub fn new(offset: u8) -> Self {
+        Self {
+            offset,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.offset
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GetAmplifierGainMutePayload {
+    amp_type: GetAmplifierGainMuteType,
+    side: GetAmplifierGainMuteSide,
+    index: u8,
+}
+
+impl GetAmplifierGainMutePayload {
+    pub fn new(amp_type: GetAmplifierGainMuteType, side: GetAmplifierGainMuteSide, index: u8) -> Self {
+        if index >= MAX_AMOUNT_OF_AMPLIFIERS_IN_AMP_WIDGET { panic!("Index for amplifier out of range") };
+        Self {
+            amp_type,
+            side,
+            index,
+        }
+    }
+
+    fn as_u16(&self) -> u16 {
+        let amp_type: u16 = match self.amp_type  {
+            GetAmplifierGainMuteType::Input => 0,
+            GetAmplifierGainMuteType::Output => 1,
+        };
+        let side: u16 = match self.side  {
+            GetAmplifierGainMuteSide::Right => 0,
+            GetAmplifierGainMuteSide::Left => 1,
+        };
+
+        amp_type << 15 | side << 13 | self.index as u16
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetAmplifierGainMutePayload {
+    amp_type: SetAmplifierGainMuteType,
+    side: SetAmplifierGainMuteSide,
+    index: u8,
+    mute: bool,
+    gain: u8,
+}
+
+impl SetAmplifierGainMutePayload {
+    pub fn new(amp_type: SetAmplifierGainMuteType, side: SetAmplifierGainMuteSide, index: u8, mute: bool, gain: u8) -> Self {
+        if gain > MAX_AMPLIFIER_GAIN { panic!("gain is a 7 bit parameter, writing 8 bit values will leak into mute bit and are therefore prohibited") }
+        if index >= MAX_AMOUNT_OF_AMPLIFIERS_IN_AMP_WIDGET { panic!("Index for amplifier out of range") }
+        Self {
+            amp_type,
+            side,
+            index,
+            mute,
+            gain,
+        }
+    }
+
+    fn as_u16(&self) -> u16 {
+        let amp_type: u16 = match self.amp_type  {
+            SetAmplifierGainMuteType::Input => 0b01,
+            SetAmplifierGainMuteType::Output => 0b10,
+            SetAmplifierGainMuteType::Both => 0b11,
+        };
+        let side: u16 = match self.side  {
+            SetAmplifierGainMuteSide::Right => 0b01,
+            SetAmplifierGainMuteSide::Left => 0b10,
+            SetAmplifierGainMuteSide::Both => 0b11,
+        };
+
+        amp_type << 14 | side << 12 | (self.index as u16) << 8 | (self.mute as u16) << 7 | self.gain as u16
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GetAmplifierGainMuteType {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GetAmplifierGainMuteSide {
+    Right,
+    Left,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SetAmplifierGainMuteType {
+    Input,
+    Output,
+    Both,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SetAmplifierGainMuteSide {
+    Right,
+    Left,
+    Both,
+}
+
+
+// The stream format verb shares its 16 bit layout with SDFMT, so the payload gets encoded by AudioFormat::to_converter_payload
+#[derive(Clone, Copy, Debug)]
+pub struct SetStreamFormatPayload {
+    raw_value: u16,
+}
+
+impl SetStreamFormatPayload {
+    pub fn new(raw_value: u16) -> Self {
+        Self {
+            raw_value,
+        }
+    }
+
+    fn as_u16(&self) -> u16 {
+        self.raw_value
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetChannelStreamIdPayload {
+    channel: u8,
+    stream: u8,
+}
+
+impl SetChannelStreamIdPayload {
+    pub fn new(channel: u8, stream: u8,) -> Self {
+        Self {
+            channel,
+            stream,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        (self.stream << 4) | self.channel
+    }
+}
+
+// Bits [2:0] hold the Voltage Reference Enable for analog pin widgets and the Encoded Packet Type for digital display pin widgets
+// (HDMI and Display Port, see section 7.3.3.13 of the specification). The helpers below keep these bits as they are,
+// so that they work for both kinds of pin widgets.
+#[derive(Clone, Copy, Debug)]
+pub struct SetPinWidgetControlPayload {
+    voltage_reference_or_encoded_packet_type: u8,
+    in_enable: bool,
+    out_enable: bool,
+    h_phn_enable: bool,
+}
+
+impl SetPinWidgetControlPayload {
+    pub fn new(
+        voltage_reference_enable: VoltageReferenceSignalLevel,
+        in_enable: bool,
+        out_enable: bool,
+        h_phn_enable: bool,
+    ) -> Self {
+        Self {
+            voltage_reference_or_encoded_packet_type: voltage_reference_enable.as_u8(),
+            in_enable,
+            out_enable,
+            h_phn_enable,
+        }
+    }
+
+    // digital display pin widgets only carry output streams and have no headphone amp
+    pub fn new_digital(encoded_packet_type: EncodedPacketType, out_enable: bool) -> Self {
+        Self {
+            voltage_reference_or_encoded_packet_type: encoded_packet_type.as_u8(),
+            in_enable: false,
+            out_enable,
+            h_phn_enable: false,
+        }
+    }
+
+    // the output gets disabled, so that a retaskable pin only works as input
+    pub fn enable_input_amp(pin_widget_control_response: PinWidgetControlResponse) -> Self {
+        Self {
+            voltage_reference_or_encoded_packet_type: pin_widget_control_response.voltage_reference_or_encoded_packet_type,
+            in_enable: true,
+            out_enable: false,
+            h_phn_enable: pin_widget_control_response.h_phn_enable,
+        }
+    }
+
+    // keeps the input as it is, so that only the output of the pin goes silent
+    pub fn disable_output_amp(pin_widget_control_response: PinWidgetControlResponse) -> Self {
+        Self {
+            voltage_reference_or_encoded_packet_type: pin_widget_control_response.voltage_reference_or_encoded_packet_type,
+            in_enable: pin_widget_control_response.in_enable,
+            out_enable: false,
+            h_phn_enable: pin_widget_control_response.h_phn_enable,
+        }
+    }
+
+    pub fn enable_input_and_output_amps(pin_widget_control_response: PinWidgetControlResponse) -> Self {
+        Self {
+            voltage_reference_or_encoded_packet_type: pin_widget_control_response.voltage_reference_or_encoded_packet_type,
+            in_enable: true,
+            out_enable: true,
+            h_phn_enable: pin_widget_control_response.h_phn_enable,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        (self.h_phn_enable as u8) << 7 | (self.out_enable as u8) << 6 | (self.in_enable as u8) << 5 | self.voltage_reference_or_encoded_packet_type
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetEAPDBTLEnablePayload {
+    btl_enable: bool,
+    eapd_enable: bool,
+    lr_swap: bool,
+}
+
+impl SetEAPDBTLEnablePayload {
+    pub fn new(
+        btl_enable: bool,
+        eapd_enable: bool,
+        lr_swap: bool,
+    ) -> Self {
+        Self {
+            btl_enable,
+            eapd_enable,
+            lr_swap,
+        }
+    }
+
+    // same layout as the response (see specification, section 7.3.3.16)
+    pub fn as_u8(&self) -> u8 {
+        (self.lr_swap as u8) << 2 | (self.eapd_enable as u8) << 1 | self.btl_enable as u8
+    }
+}
+
+// Controls the S/PDIF or HDMI transmitter of a digital converter, the bits are sent as channel status bits
+// along with the stream (see section 7.3.3.9 of the specification and IEC 60958-3).
+#[derive(Clone, Copy, Debug)]
+pub struct SetDigitalConverterControl1Payload {
+    digital_enable: bool,
+    validity: bool,
+    validity_config: bool,
+    pre_emphasis: bool,
+    copy: bool,
+    non_audio: bool,
+    professional: bool,
+    generation_level: bool,
+}
+
+impl SetDigitalConverterControl1Payload {
+    pub fn new(
+        digital_enable: bool,
+        validity: bool,
+        validity_config: bool,
+        pre_emphasis: bool,
+        copy: bool,
+        non_audio: bool,
+        professional: bool,
+        generation_level: bool,
+    ) -> Self {
+        Self {
+            digital_enable,
+            validity,
+            validity_config,
+            pre_emphasis,
+            copy,
+            non_audio,
+            professional,
+            generation_level,
+        }
+    }
+
+    // Consumer format without copy protection. Non-PCM streams (e.g. AC3 passthrough) have to set the non-audio bit,
+    // so that the receiver decodes the stream instead of playing it as PCM samples.
+    pub fn enable(stream_type: StreamType) -> Self {
+        Self::new(true, false, false, false, true, matches!(stream_type, StreamType::NonPCM), false, false)
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        (self.generation_level as u8) << 7
+            | (self.professional as u8) << 6
+            | (self.non_audio as u8) << 5
+            | (self.copy as u8) << 4
+            | (self.pre_emphasis as u8) << 3
+            | (self.validity_config as u8) << 2
+            | (self.validity as u8) << 1
+            | self.digital_enable as u8
+    }
+}
+
+// the category code is only 7 bits long (see section 7.3.3.9 of the specification)
+#[derive(Clone, Copy, Debug)]
+pub struct SetDigitalConverterControl2Payload {
+    category_code: u8,
+}
+
+impl SetDigitalConverterControl2Payload {
+    pub fn new(category_code: u8) -> Self {
+        if category_code > 0x7F {
+            panic!("Category code {:#x} doesn't fit into 7 bits, see section 7.3.3.9 of the specification", category_code);
+        }
+        Self {
+            category_code,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.category_code
+    }
+}
+
+// the tag gets reported in bits [31:26] of each unsolicited response of the widget (see specification, section 7.3.3.14)
+#[derive(Clone, Copy, Debug)]
+pub struct SetUnsolicitedResponsePayload {
+    enable: bool,
+    tag: u8,
+}
+
+impl SetUnsolicitedResponsePayload {
+    pub fn new(enable: bool, tag: u8) -> Self {
+        if tag > 0x3F {
+            panic!("Unsolicited response tag {:#x} doesn't fit into 6 bits, see section 7.3.3.14 of the specification", tag);
+        }
+        Self {
+            enable,
+            tag,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        (self.enable as u8) << 7 | self.tag
+    }
+}
+
+// Without the direct bit, turning the knob doesn't change any amp by itself, but only gets reported to the software
+// (see specification, section 7.3.3.29).
+#[derive(Clone, Copy, Debug)]
+pub struct SetVolumeKnobPayload {
+    direct: bool,
+    volume: u8,
+}
+
+impl SetVolumeKnobPayload {
+    pub fn new(direct: bool, volume: u8) -> Self {
+        if volume > 0x7F {
+            panic!("Volume knob position {:#x} doesn't fit into 7 bits, see section 7.3.3.29 of the specification", volume);
+        }
+        Self {
+            direct,
+            volume,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        (self.direct as u8) << 7 | self.volume
+    }
+}
+
+// Selects the coefficient the Get and Set Processing Coefficient commands access. The index gets incremented after each of
+// these commands, so a bank of coefficients can be transferred without setting the index again (see specification, section 7.3.3.8).
+#[derive(Clone, Copy, Debug)]
+pub struct SetCoefficientIndexPayload {
+    coefficient_index: u16,
+}
+
+impl SetCoefficientIndexPayload {
+    pub fn new(coefficient_index: u16) -> Self {
+        Self {
+            coefficient_index,
+        }
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.coefficient_index
+    }
+}
+
+// the meaning of a coefficient is vendor specific (see specification, section 7.3.3.9)
+#[derive(Clone, Copy, Debug)]
+pub struct SetProcessingCoefficientPayload {
+    coefficient: u16,
+}
+
+impl SetProcessingCoefficientPayload {
+    pub fn new(coefficient: u16) -> Self {
+        Self {
+            coefficient,
+        }
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.coefficient
+    }
+}
+
+// Bit n of the payload belongs to GPIO n of the function group. The GPIO Data, Enable Mask and Direction commands all take
+// such a bit mask (see specification, sections 7.3.3.21 to 7.3.3.23).
+#[derive(Clone, Copy, Debug)]
+pub struct SetGPIOPayload {
+    bits: u8,
+}
+
+impl SetGPIOPayload {
+    pub fn new(bits: u8) -> Self {
+        Self {
+            bits,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.bits
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetPowerStatePayload {
+    power_state: PowerState,
+}
+
+impl SetPowerStatePayload {
+    pub fn new(power_state: PowerState) -> Self {
+        Self {
+            power_state,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.power_state.as_u8()
+    }
+}
+
+// see section 7.3.3.10 of the specification
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerState {
+    // fully on
+    D0,
+    D1,
+    D2,
+    // lowest power state from which the node can wake up without a reset of the link
+    D3,
+    D3Cold,
+}
+
+impl PowerState {
+    fn from_u8(power_state: u8) -> Self {
+        match power_state {
+            0b000 => PowerState::D0,
+            0b001 => PowerState::D1,
+            0b010 => PowerState::D2,
+            0b011 => PowerState::D3,
+            0b100 => PowerState::D3Cold,
+            _ => panic!("Unknown power state {:#x}, see section 7.3.3.10 of the specification", power_state),
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            PowerState::D0 => 0b000,
+            PowerState::D1 => 0b001,
+            PowerState::D2 => 0b010,
+            PowerState::D3 => 0b011,
+            PowerState::D3Cold => 0b100,
+        }
+    }
+}
+
+// Byte 2 of the configuration default (bits [23:16]), which holds the default device and the connection type. The configuration
+// default is written one byte per verb (see section 7.3.3.31 of the specification), so the other bytes keep their values.
+#[derive(Clone, Copy, Debug)]
+pub struct SetConfigurationDefault2Payload {
+    default_device: u8,
+    connection_type: u8,
+}
+
+impl SetConfigurationDefault2Payload {
+    pub fn new(default_device: &ConfigDefDefaultDevice, connection_type: &ConfigDefConnectionType) -> Self {
+        Self {
+            default_device: default_device.as_u8(),
+            connection_type: connection_type.as_u8(),
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        (self.default_device << 4) | self.connection_type
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetConverterChannelCountPayload {
+    converter_channel_count: u8,
+}
+
+impl SetConverterChannelCountPayload {
+    pub fn new(converter_channel_count: u8) -> Self {
+        Self {
+            converter_channel_count,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.converter_channel_count
+    }
+}
+
+
+
+// ############################################## IHDA responses ##############################################
+
+pub struct RawResponse {
+    raw_value: u32,
+}
+
+impl RawResponse {
+    pub fn new(response: u32) -> Self {
+        Self {
+            raw_value: response,
+        }
+    }
+
+    fn get_bit(&self, index: usize) -> bool {
+        (self.raw_value >> index).bitand(1) != 0
+    }
+}
+
+#[derive(Debug)]
+pub enum Response {
+    VendorId(VendorIdResponse),
+    RevisionId(RevisionIdResponse),
+    SubordinateNodeCount(SubordinateNodeCountResponse),
+    FunctionGroupType(FunctionGroupTypeResponse),
+    AudioFunctionGroupCapabilities(AudioFunctionGroupCapabilitiesResponse),
+    AudioWidgetCapabilities(AudioWidgetCapabilitiesResponse),
+    SampleSizeRateCAPs(SampleSizeRateCAPsResponse),
+    SupportedStreamFormats(SupportedStreamFormatsResponse),
+    PinCapabilities(PinCapabilitiesResponse),
+    InputAmpCapabilities(AmpCapabilitiesResponse),
+    OutputAmpCapabilities(AmpCapabilitiesResponse),
+    ConnectionListLength(ConnectionListLengthResponse),
+    SupportedPowerStates(SupportedPowerStatesResponse),
+    ProcessingCapabilities(ProcessingCapabilitiesResponse),
+    GPIOCount(GPIOCountResponse),
+    VolumeKnobCapabilities(VolumeKnobCapabilitiesResponse),
+
+    ConnectionSelect(ConnectionSelectResponse),
+    ConnectionListEntry(ConnectionListEntryResponse),
+    AmplifierGainMute(AmplifierGainMuteResponse),
+    ChannelStreamId(ChannelStreamIdResponse),
+    StreamFormat(StreamFormatResponse),
+    PinWidgetControl(PinWidgetControlResponse),
+    EAPDBTLEnable(EAPDBTLEnableResponse),
+    DigitalConverterControl(DigitalConverterControlResponse),
+    ConfigurationDefault(ConfigurationDefaultResponse),
+    ConverterChannelCount(ConverterChannelCountResponse),
+    UnsolicitedResponse(UnsolicitedResponseControlResponse),
+    PinSense(PinSenseResponse),
+    VolumeKnob(VolumeKnobResponse),
+    PowerState(PowerStateResponse),
+    CoefficientIndex(CoefficientIndexResponse),
+    ProcessingCoefficient(ProcessingCoefficientResponse),
+    GPIOData(GPIOResponse),
+    GPIOEnableMask(GPIOResponse),
+    GPIODirection(GPIOResponse),
+    Zeros,
+}
+
+impl Response {
+    pub fn new(response: RawResponse, associated_command: Command) -> Response {
+        match associated_command {
+            Command::GetParameter(_, parameter) => {
+                match parameter {
+                    Parameter::VendorId => Response::VendorId(VendorIdResponse::new(response)),
+                    Parameter::RevisionId => Response::RevisionId(RevisionIdResponse::new(response)),
+                    Parameter::SubordinateNodeCount => Response::SubordinateNodeCount(SubordinateNodeCountResponse::new(response)),
+                    Parameter::FunctionGroupType => Response::FunctionGroupType(FunctionGroupTypeResponse::new(response)),
+                    Parameter::AudioFunctionGroupCapabilities => Response::AudioFunctionGroupCapabilities(AudioFunctionGroupCapabilitiesResponse::new(response)),
+                    Parameter::AudioWidgetCapabilities => Response::AudioWidgetCapabilities(AudioWidgetCapabilitiesResponse::new(response)),
+                    Parameter::SampleSizeRateCAPs => Response::SampleSizeRateCAPs(SampleSizeRateCAPsResponse::new(response)),
+                    Parameter::SupportedStreamFormats => Response::SupportedStreamFormats(SupportedStreamFormatsResponse::new(response)),
+                    Parameter::PinCapabilities => Response::PinCapabilities(PinCapabilitiesResponse::new(response)),
+                    Parameter::InputAmpCapabilities => Response::InputAmpCapabilities(AmpCapabilitiesResponse::new(response)),
+                    Parameter::OutputAmpCapabilities => Response::OutputAmpCapabilities(AmpCapabilitiesResponse::new(response)),
+                    Parameter::ConnectionListLength => Response::ConnectionListLength(ConnectionListLengthResponse::new(response)),
+                    Parameter::SupportedPowerStates => Response::SupportedPowerStates(SupportedPowerStatesResponse::new(response)),
+                    Parameter::ProcessingCapabilities => Response::ProcessingCapabilities(ProcessingCapabilitiesResponse::new(response)),
+                    Parameter::GPIOCount => Response::GPIOCount(GPIOCountResponse::new(response)),
+                    Parameter::VolumeKnobCapabilities => Response::VolumeKnobCapabilities(VolumeKnobCapabilitiesResponse::new(response)),
+                }
+            }
+            Command::GetConnectionSelect(..) => Response::ConnectionSelect(ConnectionSelectResponse::new(response)),
+            Command::SetConnectionSelect(..) => Response::Zeros,
+            Command::GetConnectionListEntry(..) => Response::ConnectionListEntry(ConnectionListEntryResponse::new(response)),
+            Command::GetAmplifierGainMute(..) => Response::AmplifierGainMute(AmplifierGainMuteResponse::new(response)),
+            Command::SetAmplifierGainMute(..) => Response::Zeros,
+            Command::GetStreamFormat(..) => Response::StreamFormat(StreamFormatResponse::new(response)),
+            Command::SetStreamFormat(..) => Response::Zeros,
+            Command::GetChannelStreamId(..) => Response::ChannelStreamId(ChannelStreamIdResponse::new(response)),
+            Command::SetChannelStreamId(..) => Response::Zeros,
+            Command::GetPinWidgetControl(..) => Response::PinWidgetControl(PinWidgetControlResponse::new(response)),
+            Command::SetPinWidgetControl(..) => Response::Zeros,
+            Command::GetEAPDBTLEnable(..) => Response::EAPDBTLEnable(EAPDBTLEnableResponse::new(response)),
+            Command::SetEAPDBTLEnable(..) => Response::Zeros,
+            Command::GetDigitalConverterControl(..) => Response::DigitalConverterControl(DigitalConverterControlResponse::new(response)),
+            Command::SetDigitalConverterControl1(..) => Response::Zeros,
+            Command::SetDigitalConverterControl2(..) => Response::Zeros,
+            Command::GetConfigurationDefault(..) => Response::ConfigurationDefault(ConfigurationDefaultResponse::new(response)),
+            Command::SetConfigurationDefault2(..) => Response::Zeros,
+            Command::GetConverterChannelCount(..) => Response::ConverterChannelCount(ConverterChannelCountResponse::new(response)),
+            Command::SetConverterChannelCount(..) => Response::Zeros,
+            Command::GetUnsolicitedResponse(..) => Response::UnsolicitedResponse(UnsolicitedResponseControlResponse::new(response)),
+            Command::SetUnsolicitedResponse(..) => Response::Zeros,
+            Command::GetPinSense(..) => Response::PinSense(PinSenseResponse::new(response)),
+            Command::ExecutePinSense(..) => Response::Zeros,
+            Command::GetVolumeKnob(..) => Response::VolumeKnob(VolumeKnobResponse::new(response)),
+            Command::SetVolumeKnob(..) => Response::Zeros,
+            Command::GetPowerState(..) => Response::PowerState(PowerStateResponse::new(response)),
+            Command::SetPowerState(..) => Response::Zeros,
+            Command::GetCoefficientIndex(..) => Response::CoefficientIndex(CoefficientIndexResponse::new(response)),
+            Command::SetCoefficientIndex(..) => Response::Zeros,
+            Command::GetProcessingCoefficient(..) => Response::ProcessingCoefficient(ProcessingCoefficientResponse::new(response)),
+            Command::SetProcessingCoefficient(..) => Response::Zeros,
+            Command::GetGPIOData(..) => Response::GPIOData(GPIOResponse::new(response)),
+            Command::SetGPIOData(..) => Response::Zeros,
+            Command::GetGPIOEnableMask(..) => Response::GPIOEnableMask(GPIOResponse::new(response)),
+            Command::SetGPIOEnableMask(..) => Response::Zeros,
+            Command::GetGPIODirection(..) => Response::GPIODirection(GPIOResponse::new(response)),
+            Command::SetGPIODirection(..) => Response::Zeros,
+            Command::FunctionGroupReset(..) => Response::Zeros,
+        }
+    }
+
+    // Responses no working codec can give, which show up when a response got dropped or read before the codec wrote it:
+    // every codec has a vendor id and at least one function group, and a function group type of 0 is reserved.
+    // An all-ones vendor id is what a codec returns while it is still coming out of reset.
+    pub fn is_implausible(&self) -> bool {
+        match self {
+            Response::VendorId(info) => (info.vendor_id == 0 && info.device_id == 0) || (info.vendor_id == 0xFFFF && info.device_id == 0xFFFF),
+            Response::SubordinateNodeCount(info) => info.total_number_of_nodes == 0,
+            Response::FunctionGroupType(info) => matches!(info.node_type, FunctionGroupTypeEnum::Reserved(0)),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct VendorIdResponse {
+    device_id: u16,
+    vendor_id: u16,
+}
+
+impl VendorIdResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            device_id: response.raw_value.bitand(0xFFFF) as u16,
+            vendor_id: (response.raw_value >> 16).bitand(0xFFFF) as u16,
+        }
+
+    }
+}
+
+impl TryFrom<Response> for VendorIdResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+                    Response::VendorId(info) => Ok(info),
+                    e => Err(e),
+                }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct RevisionIdResponse {
+    stepping_id: u8,
+    revision_id: u8,
+    minor_revision: u8,
+    major_revision: u8,
+}
+
+impl RevisionIdResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            stepping_id: response.raw_value.bitand(0xFF) as u8,
+            revision_id: (response.raw_value >> 8).bitand(0xFF) as u8,
+            minor_revision: (response.raw_value >> 16).bitand(0xF) as u8,
+            major_revision: (response.raw_value >> 20).bitand(0xF) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for RevisionIdResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::RevisionId(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct SubordinateNodeCountResponse {
+    total_number_of_nodes: u8,
+    starting_node_number: u8,
+}
+
+impl SubordinateNodeCountResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            total_number_of_nodes: response.raw_value.bitand(0xFF) as u8,
+            starting_node_number: (response.raw_value >> 16).bitand(0xFF) as u8,
+        }
+
+    }
+}
+
+impl TryFrom<Response> for SubordinateNodeCountResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::SubordinateNodeCount(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct FunctionGroupTypeResponse {
+    node_type: FunctionGroupTypeEnum,
+    unsolicited_response_capable: bool,
+}
+
+impl FunctionGroupTypeResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            // see section 7.3.4.4 of the specification
+            node_type: match response.raw_value.bitand(0xFF) as u8 {
+                0x1 => FunctionGroupTypeEnum::AudioFunctionGroup,
+                0x2 => FunctionGroupTypeEnum::VendorDefinedModemFunctionGroup,
+                0x80..=0xFF => FunctionGroupTypeEnum::VendorDefinedFunctionGroup,
+                node_type => FunctionGroupTypeEnum::Reserved(node_type),
+            },
+            unsolicited_response_capable: response.get_bit(8),
+        }
+
+    }
+}
+
+impl TryFrom<Response> for FunctionGroupTypeResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::FunctionGroupType(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FunctionGroupTypeEnum {
+    AudioFunctionGroup,
+    VendorDefinedModemFunctionGroup,
+    VendorDefinedFunctionGroup,
+    // 0x00 and 0x03 to 0x7F, e.g. reported by nodes which don't answer the parameter at all
+    Reserved(u8),
+}
+
+#[derive(Debug, Getters)]
+pub struct AudioFunctionGroupCapabilitiesResponse {
+    output_delay: u8,
+    input_delay: u8,
+    beep_gen: bool,
+}
+
+impl AudioFunctionGroupCapabilitiesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            output_delay: response.raw_value.bitand(0xF) as u8,
+            input_delay: (response.raw_value >> 8).bitand(0xF) as u8,
+            beep_gen: response.get_bit(16),
+        }
+    }
+}
+
+impl TryFrom<Response> for AudioFunctionGroupCapabilitiesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::AudioFunctionGroupCapabilities(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct AudioWidgetCapabilitiesResponse {
+    chan_count_lsb: bool,
+    in_amp_present: bool,
+    out_amp_present: bool,
+    amp_param_override: bool,
+    format_override: bool,
+    stripe: bool,
+    proc_widget: bool,
+    unsol_capable: bool,
+    conn_list: bool,
+    digital: bool,
+    power_cntrl: bool,
+    lr_swap: bool,
+    cp_caps: bool,
+    chan_count_ext: u8,
+    delay: u8,
+    widget_type: WidgetType,
+}
+
+impl AudioWidgetCapabilitiesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            chan_count_lsb: response.get_bit(0),
+            in_amp_present: response.get_bit(1),
+            out_amp_present: response.get_bit(2),
+            amp_param_override: response.get_bit(3),
+            format_override: response.get_bit(4),
+            stripe: response.get_bit(5),
+            proc_widget: response.get_bit(6),
+            unsol_capable: response.get_bit(7),
+            conn_list: response.get_bit(8),
+            digital: response.get_bit(9),
+            power_cntrl: response.get_bit(10),
+            lr_swap: response.get_bit(11),
+            cp_caps: response.get_bit(12),
+            chan_count_ext: (response.raw_value >> 13).bitand(0b111) as u8,
+            delay: (response.raw_value >> 16).bitand(0xF) as u8,
+            widget_type: match (response.raw_value >> 20).bitand(0xF) as u8 {
+                0x0 => WidgetType::AudioOutput,
+                0x1 => WidgetType::AudioInput,
+                0x2 => WidgetType::AudioMixer,
+                0x3 => WidgetType::AudioSelector,
+                0x4 => WidgetType::PinComplex,
+                0x5 => WidgetType::PowerWidget,
+                0x6 => WidgetType::VolumeKnobWidget,
+                0x7 => WidgetType::BeepGeneratorWidget,
+                0xF => WidgetType::VendorDefinedAudioWidget,
+                widget_type => WidgetType::Reserved(widget_type),
+            }
+        }
+    }
+}
+
+impl TryFrom<Response> for AudioWidgetCapabilitiesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::AudioWidgetCapabilities(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WidgetType {
+    AudioOutput,
+    AudioInput,
+    AudioMixer,
+    AudioSelector,
+    PinComplex,
+    PowerWidget,
+    VolumeKnobWidget,
+    BeepGeneratorWidget,
+    VendorDefinedAudioWidget,
+    // 0x8 to 0xE (see section 7.3.4.6 of the specification)
+    Reserved(u8),
+}
+
+#[derive(Debug, Getters)]
+pub struct SampleSizeRateCAPsResponse {
+    support_8000hz: bool,
+    support_11025hz: bool,
+    support_16000hz: bool,
+    support_22050hz: bool,
+    support_32000hz: bool,
+    support_44100hz: bool,
+    support_48000hz: bool,
+    support_88200hz: bool,
+    support_96000hz: bool,
+    support_176400hz: bool,
+    support_192000hz: bool,
+    support_384000hz: bool,
+    support_8bit: bool,
+    support_16bit: bool,
+    support_20bit: bool,
+    support_24bit: bool,
+    support_32bit: bool,
+}
+
+impl SampleSizeRateCAPsResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            support_8000hz: response.get_bit(0),
+            support_11025hz: response.get_bit(1),
+            support_16000hz: response.get_bit(2),
+            support_22050hz: response.get_bit(3),
+            support_32000hz: response.get_bit(4),
+            support_44100hz: response.get_bit(5),
+            support_48000hz: response.get_bit(6),
+            support_88200hz: response.get_bit(7),
+            support_96000hz: response.get_bit(8),
+            support_176400hz: response.get_bit(9),
+            support_192000hz: response.get_bit(10),
+            support_384000hz: response.get_bit(11),
+            support_8bit: response.get_bit(16),
+            support_16bit: response.get_bit(17),
+            support_20bit: response.get_bit(18),
+            support_24bit: response.get_bit(19),
+            support_32bit: response.get_bit(20),
+        }
+    }
+
+    // nodes without a format of their own (e.g. function groups whose converters all override it) return 0
+    pub fn is_empty(&self) -> bool {
+        !(self.support_8000hz || self.support_11025hz || self.support_16000hz || self.support_22050hz || self.support_32000hz
+            || self.support_44100hz || self.support_48000hz || self.support_88200hz || self.support_96000hz || self.support_176400hz
+            || self.support_192000hz || self.support_384000hz)
+    }
+
+    pub fn supports_sample_rate(&self, sample_rate: u32) -> bool {
+        match sample_rate {
+            8000 => self.support_8000hz,
+            11025 => self.support_11025hz,
+            16000 => self.support_16000hz,
+            22050 => self.support_22050hz,
+            32000 => self.support_32000hz,
+            44100 => self.support_44100hz,
+            48000 => self.support_48000hz,
+            88200 => self.support_88200hz,
+            96000 => self.support_96000hz,
+            176400 => self.support_176400hz,
+            192000 => self.support_192000hz,
+            384000 => self.support_384000hz,
+            _ => false,
+        }
+    }
+
+    pub fn supports_bits_per_sample(&self, bits_per_sample: BitsPerSample) -> bool {
+        match bits_per_sample {
+            BitsPerSample::Eight => self.support_8bit,
+            BitsPerSample::Sixteen => self.support_16bit,
+            BitsPerSample::Twenty => self.support_20bit,
+            BitsPerSample::Twentyfour => self.support_24bit,
+            BitsPerSample::Thirtytwo => self.support_32bit,
+        }
+    }
+
+    pub fn supported_sample_rates(&self) -> Vec<u32> {
+        CONVERTER_SAMPLE_RATES.iter().copied().filter(|sample_rate| self.supports_sample_rate(*sample_rate)).collect()
+    }
+
+    pub fn supported_bits_per_sample(&self) -> Vec<BitsPerSample> {
+        CONVERTER_BITS_PER_SAMPLE.iter().copied().filter(|bits_per_sample| self.supports_bits_per_sample(*bits_per_sample)).collect()
+    }
+}
+
+impl TryFrom<Response> for SampleSizeRateCAPsResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::SampleSizeRateCAPs(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct SupportedStreamFormatsResponse {
+    pcm: bool,
+    float32: bool,
+    ac3: bool,
+}
+
+impl SupportedStreamFormatsResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            pcm: response.get_bit(0),
+            float32: response.get_bit(1),
+            ac3: response.get_bit(2),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.pcm || self.float32 || self.ac3)
+    }
+}
+
+impl TryFrom<Response> for SupportedStreamFormatsResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::SupportedStreamFormats(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct PinCapabilitiesResponse {
+    impedence_sense_capable: bool,
+    trigger_required: bool,
+    presence_detect_capable: bool,
+    headphone_drive_capable: bool,
+    output_capable: bool,
+    input_capable: bool,
+    balanced_io_pins: bool,
+    hdmi: bool,
+    vref_control: u8,
+    eapd_capable: bool,
+    display_port: bool,
+    high_bit_rate: bool,
+}
+
+impl PinCapabilitiesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            impedence_sense_capable: response.get_bit(0),
+            trigger_required: response.get_bit(1),
+            presence_detect_capable: response.get_bit(2),
+            headphone_drive_capable: response.get_bit(3),
+            output_capable: response.get_bit(4),
+            input_capable: response.get_bit(5),
+            balanced_io_pins: response.get_bit(6),
+            hdmi: response.get_bit(7),
+            vref_control: (response.raw_value >> 8).bitand(0xFF) as u8,
+            eapd_capable: response.get_bit(16),
+            display_port: response.get_bit(24),
+            high_bit_rate: response.get_bit(27),
+        }
+    }
+
+    // bit n of the VRef control capabilities stands for the voltage reference with the encoding n (see section 7.3.4.9 of the specification)
+    pub fn supports_voltage_reference(&self, voltage_reference: VoltageReferenceSignalLevel) -> bool {
+        (self.vref_control >> voltage_reference.as_u8()) & 1 != 0
+    }
+}
+
+impl TryFrom<Response> for PinCapabilitiesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::PinCapabilities(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct AmpCapabilitiesResponse {
+    offset: u8,
+    num_steps: u8,
+    step_size: u8,
+    mute_capable: bool,
+}
+
+impl AmpCapabilitiesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            offset: response.raw_value.bitand(0b0111_1111) as u8,
+            num_steps: (response.raw_value >> 8).bitand(0b0111_1111) as u8,
+            step_size: (response.raw_value >> 16).bitand(0b0111_1111) as u8,
+            mute_capable: response.get_bit(31),
+        }
+    }
+
+    // nodes without default amp capabilities return 0
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0 && self.num_steps == 0 && self.step_size == 0 && !self.mute_capable
+    }
+}
+
+impl TryFrom<Response> for AmpCapabilitiesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::InputAmpCapabilities(info) => Ok(info),
+            Response::OutputAmpCapabilities(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct ConnectionListLengthResponse {
+    connection_list_length: u8,
+    long_form: bool,
+}
+
+impl ConnectionListLengthResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            connection_list_length: response.raw_value.bitand(0b0111_1111) as u8,
+            long_form: response.get_bit(7),
+        }
+    }
+}
+
+impl TryFrom<Response> for ConnectionListLengthResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ConnectionListLength(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct SupportedPowerStatesResponse {
+    d0_sup: bool,
+    d1_sup: bool,
+    d2_sup: bool,
+    d3_sup: bool,
+    d3cold_sup: bool,
+    s3d3cold_sup: bool,
+    clkstop: bool,
+    epss: bool,
+}
+
+impl SupportedPowerStatesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            d0_sup: response.get_bit(0),
+            d1_sup: response.get_bit(1),
+            d2_sup: response.get_bit(2),
+            d3_sup: response.get_bit(3),
+            d3cold_sup: response.get_bit(4),
+            s3d3cold_sup: response.get_bit(29),
+            clkstop: response.get_bit(30),
+            epss: response.get_bit(31),
+        }
+    }
+
+    // some function groups (e.g. in QEMU) don't report their power states at all
+    pub fn is_empty(&self) -> bool {
+        !(self.d0_sup || self.d1_sup || self.d2_sup || self.d3_sup || self.d3cold_sup || self.s3d3cold_sup || self.clkstop || self.epss)
+    }
+}
+
+impl TryFrom<Response> for SupportedPowerStatesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::SupportedPowerStates(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct ProcessingCapabilitiesResponse {
+    benign: bool,
+    num_coeff: u8,
+}
+
+impl ProcessingCapabilitiesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            benign: response.get_bit(0),
+            num_coeff: (response.raw_value >> 8).bitand(0xFF) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for ProcessingCapabilitiesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ProcessingCapabilities(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct GPIOCountResponse {
+    num_gpios: u8,
+    num_gpos: u8,
+    num_gpis: u8,
+    gpi_unsol: bool,
+    gpi_wake: bool,
+}
+
+impl GPIOCountResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            num_gpios: response.raw_value.bitand(0xFF) as u8,
+            num_gpos: (response.raw_value >> 8).bitand(0xFF) as u8,
+            num_gpis: (response.raw_value >> 16).bitand(0xFF) as u8,
+            gpi_unsol: response.get_bit(30),
+            gpi_wake: response.get_bit(31),
+        }
+    }
+}
+
+impl TryFrom<Response> for GPIOCountResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::GPIOCount(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct VolumeKnobCapabilitiesResponse {
+    num_steps: u8,
+    delta: bool,
+}
+
+impl VolumeKnobCapabilitiesResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            num_steps: response.raw_value.bitand(0b0111_1111) as u8,
+            delta: response.get_bit(7),
+        }
+    }
+}
+
+impl TryFrom<Response> for VolumeKnobCapabilitiesResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::VolumeKnobCapabilities(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct ConnectionSelectResponse {
+    currently_set_connection_index: u8,
+}
+
+impl ConnectionSelectResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            currently_set_connection_index: response.raw_value.bitand(0xFF) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for ConnectionSelectResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ConnectionSelect(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+
+// The response doesn't tell its own form, so the entries have to be decoded with the form reported by the Connection List Length
+// parameter of the widget. A short form response contains four entries with 8 bits each and a long form response two entries
+// with 16 bits each, where the most significant bit of each entry marks a range (see section 7.3.3.3 of the specification).
+#[derive(Debug)]
+pub struct ConnectionListEntryResponse {
+    raw_value: u32,
+}
+
+impl ConnectionListEntryResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            raw_value: response.raw_value,
+        }
+    }
+
+    pub fn entries_per_response(long_form: bool) -> usize {
+        if long_form { 2 } else { 4 }
+    }
+
+    // entries in the order of the connection list
+    pub fn entries(&self, long_form: bool) -> Vec<ConnectionListEntry> {
+        let entry_length_in_bits = if long_form { 16 } else { 8 };
+        let node_id_mask = (1u32 << (entry_length_in_bits - 1)) - 1;
+        (0..Self::entries_per_response(long_form))
+            .map(|index| {
+                let entry = (self.raw_value >> (index * entry_length_in_bits)) & ((1u32 << entry_length_in_bits) - 1);
+                ConnectionListEntry {
+                    node_id: entry.bitand(node_id_mask) as u16,
+                    range: entry & !node_id_mask != 0,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct ConnectionListEntry {
+    node_id: u16,
+    // the entry stands for all node ids after the previous entry up to and including its own node id
+    range: bool,
+}
+
+impl TryFrom<Response> for ConnectionListEntryResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ConnectionListEntry(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct AmplifierGainMuteResponse {
+    amplifier_gain: u8,
+    amplifier_mute: bool,
+}
+
+impl AmplifierGainMuteResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            amplifier_gain: (response.raw_value & 0b0111_1111) as u8,
+            amplifier_mute: response.get_bit(7),
+        }
+    }
+}
+
+impl TryFrom<Response> for AmplifierGainMuteResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::AmplifierGainMute(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+// gets decoded by AudioFormat::from_response, as it shares its layout with SDFMT
+#[derive(Debug, Getters)]
+pub struct StreamFormatResponse {
+    raw_value: u16,
+}
+
+impl StreamFormatResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            raw_value: response.raw_value.bitand(0xFFFF) as u16,
+        }
+    }
+}
+
+impl TryFrom<Response> for StreamFormatResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::StreamFormat(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitsPerSample {
+    Eight,
+    Sixteen,
+    Twenty,
+    Twentyfour,
+    Thirtytwo,
+}
+
+impl BitsPerSample {
+    pub fn from_bit_depth(bit_depth: u8) -> Option<Self> {
+        match bit_depth {
+            8 => Some(BitsPerSample::Eight),
+            16 => Some(BitsPerSample::Sixteen),
+            20 => Some(BitsPerSample::Twenty),
+            24 => Some(BitsPerSample::Twentyfour),
+            32 => Some(BitsPerSample::Thirtytwo),
+            _ => None,
+        }
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        match self {
+            BitsPerSample::Eight => 8,
+            BitsPerSample::Sixteen => 16,
+            BitsPerSample::Twenty => 20,
+            BitsPerSample::Twentyfour => 24,
+            BitsPerSample::Thirtytwo => 32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamType {
+    PCM,
+    NonPCM,
+}
+
+#[derive(Debug, Getters)]
+pub struct ChannelStreamIdResponse {
+    channel: u8,
+    stream: u8,
+}
+
+impl ChannelStreamIdResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            channel: response.raw_value.bitand(0xF) as u8,
+            stream: (response.raw_value >> 4).bitand(0xF) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for ChannelStreamIdResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ChannelStreamId(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct PinWidgetControlResponse {
+    // Voltage Reference Enable for analog pin widgets and Encoded Packet Type for digital display pin widgets (see section 7.3.3.13 of the specification),
+    // only the caller knows the kind of the pin widget, so the bits get interpreted by fn voltage_reference_enable or fn encoded_packet_type
+    #[getter(skip)]
+    voltage_reference_or_encoded_packet_type: u8,
+    in_enable: bool,
+    out_enable: bool,
+    h_phn_enable: bool,
+}
+
+impl PinWidgetControlResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            voltage_reference_or_encoded_packet_type: response.raw_value.bitand(0b111) as u8,
+            in_enable: response.get_bit(5),
+            out_enable: response.get_bit(6),
+            h_phn_enable: response.get_bit(7),
+        }
+    }
+
+    pub fn voltage_reference_enable(&self) -> VoltageReferenceSignalLevel {
+        VoltageReferenceSignalLevel::from_u8(self.voltage_reference_or_encoded_packet_type)
+    }
+
+    pub fn encoded_packet_type(&self) -> EncodedPacketType {
+        EncodedPacketType::from_u8(self.voltage_reference_or_encoded_packet_type)
+    }
+}
+
+impl TryFrom<Response> for PinWidgetControlResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::PinWidgetControl(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum VoltageReferenceSignalLevel {
+    HiZ,
+    FiftyPercent,
+    Ground0V,
+    EightyPercent,
+    HundredPercent,
+}
+
+impl VoltageReferenceSignalLevel {
+    fn from_u8(voltage_reference_enable: u8) -> Self {
+        match voltage_reference_enable {
+            0b000 => VoltageReferenceSignalLevel::HiZ,
+            0b001 => VoltageReferenceSignalLevel::FiftyPercent,
+            0b010 => VoltageReferenceSignalLevel::Ground0V,
+            // 0b011 reserved
+            0b100 => VoltageReferenceSignalLevel::EightyPercent,
+            0b101 => VoltageReferenceSignalLevel::HundredPercent,
+            // 0b110 and 0b111 reserved
+            _ => panic!("Unsupported type of voltage reference signal level")
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            VoltageReferenceSignalLevel::HiZ => 0b000,
+            VoltageReferenceSignalLevel::FiftyPercent => 0b001,
+            VoltageReferenceSignalLevel::Ground0V => 0b010,
+            VoltageReferenceSignalLevel::EightyPercent => 0b100,
+            VoltageReferenceSignalLevel::HundredPercent => 0b101,
+        }
+    }
+}
+
+// packet type an HDMI or Display Port pin widget sends its stream in (see section 7.3.3.13 of the specification)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncodedPacketType {
+    // audio sample packets, used for PCM and for compressed formats like AC3
+    NativePacket,
+    // only needed for compressed formats above 6.144 Mbit/s (e.g. Dolby TrueHD and DTS-HD MA), requires the High Bit Rate pin capability
+    HighBitRate,
+}
+
+impl EncodedPacketType {
+    fn from_u8(encoded_packet_type: u8) -> Self {
+        match encoded_packet_type.bitand(0b11) {
+            0b00 => EncodedPacketType::NativePacket,
+            0b11 => EncodedPacketType::HighBitRate,
+            // 0b01 and 0b10 reserved
+            _ => panic!("Unsupported encoded packet type, see section 7.3.3.13 of the specification")
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            EncodedPacketType::NativePacket => 0b00,
+            EncodedPacketType::HighBitRate => 0b11,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct DigitalConverterControlResponse {
+    digital_enable: bool,
+    validity: bool,
+    validity_config: bool,
+    pre_emphasis: bool,
+    copy: bool,
+    non_audio: bool,
+    professional: bool,
+    generation_level: bool,
+    category_code: u8,
+}
+
+impl DigitalConverterControlResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            digital_enable: response.get_bit(0),
+            validity: response.get_bit(1),
+            validity_config: response.get_bit(2),
+            pre_emphasis: response.get_bit(3),
+            copy: response.get_bit(4),
+            non_audio: response.get_bit(5),
+            professional: response.get_bit(6),
+            generation_level: response.get_bit(7),
+            category_code: (response.raw_value >> 8).bitand(0x7F) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for DigitalConverterControlResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::DigitalConverterControl(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct EAPDBTLEnableResponse {
+    btl_enable: bool,
+    eapd_enable: bool,
+    lr_swap: bool,
+}
+
+impl EAPDBTLEnableResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            btl_enable: response.get_bit(0),
+            eapd_enable: response.get_bit(1),
+            lr_swap: response.get_bit(2),
+        }
+    }
+}
+
+impl TryFrom<Response> for EAPDBTLEnableResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::EAPDBTLEnable(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct UnsolicitedResponseControlResponse {
+    enable: bool,
+    tag: u8,
+}
+
+impl UnsolicitedResponseControlResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            enable: response.get_bit(7),
+            tag: (response.raw_value & 0x3F) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for UnsolicitedResponseControlResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::UnsolicitedResponse(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+// see section 7.3.3.15 of the specification
+#[derive(Debug, Getters)]
+pub struct PinSenseResponse {
+    presence_detect: bool,
+    // 0x7FFF_FFFF means that the impedance is unknown or the measurement is still in progress
+    impedance: u32,
+}
+
+impl PinSenseResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            presence_detect: response.get_bit(31),
+            impedance: response.raw_value & 0x7FFF_FFFF,
+        }
+    }
+}
+
+impl TryFrom<Response> for PinSenseResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::PinSense(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+// see section 7.3.3.29 of the specification
+#[derive(Debug, Getters)]
+pub struct VolumeKnobResponse {
+    // the knob controls the amps of its slave widgets directly, instead of only reporting its position to the software
+    direct: bool,
+    // between 0 and the number of steps reported by the volume knob capabilities
+    volume: u8,
+}
+
+impl VolumeKnobResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            direct: response.get_bit(7),
+            volume: (response.raw_value & 0x7F) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for VolumeKnobResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::VolumeKnob(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+// see section 7.3.3.8 of the specification
+#[derive(Debug, Getters)]
+pub struct CoefficientIndexResponse {
+    coefficient_index: u16,
+}
+
+impl CoefficientIndexResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            coefficient_index: response.raw_value.bitand(0xFFFF) as u16,
+        }
+    }
+}
+
+impl TryFrom<Response> for CoefficientIndexResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::CoefficientIndex(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+// see section 7.3.3.9 of the specification
+#[derive(Debug, Getters)]
+pub struct ProcessingCoefficientResponse {
+    coefficient: u16,
+}
+
+impl ProcessingCoefficientResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            coefficient: response.raw_value.bitand(0xFFFF) as u16,
+        }
+    }
+}
+
+impl TryFrom<Response> for ProcessingCoefficientResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ProcessingCoefficient(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+// bit mask of the GPIO Data, Enable Mask or Direction of a function group, depending on the command (see SetGPIOPayload)
+#[derive(Debug, Getters)]
+pub struct GPIOResponse {
+    bits: u8,
+}
+
+impl GPIOResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            bits: response.raw_value.bitand(0xFF) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for GPIOResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::GPIOData(info) | Response::GPIOEnableMask(info) | Response::GPIODirection(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct PowerStateResponse {
+    // power state requested by the last Set Power State command
+    setting: PowerState,
+    // power state the node is actually in, which can lag behind the setting while a transition is in progress
+    actual: PowerState,
+    error: bool,
+    clock_stop_ok: bool,
+    // the node lost its settings (e.g. stream ids and amp gains) while being in a low power state
+    settings_reset: bool,
+}
+
+impl PowerStateResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            setting: PowerState::from_u8((response.raw_value & 0x7) as u8),
+            actual: PowerState::from_u8(((response.raw_value >> 4) & 0x7) as u8),
+            error: response.get_bit(8),
+            clock_stop_ok: response.get_bit(9),
+            settings_reset: response.get_bit(10),
+        }
+    }
+}
+
+impl TryFrom<Response> for PowerStateResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::PowerState(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Getters)]
+pub struct ConfigurationDefaultResponse {
+    sequence: u8,
+    default_association: u8,
+    jack_detect_override: bool,
+    color: ConfigDefColor,
+    connection_type: ConfigDefConnectionType,
+    default_device: ConfigDefDefaultDevice,
+    geometric_location: ConfigDefGeometricLocation,
+    gross_location: ConfigDefGrossLocation,
+    port_connectivity: ConfigDefPortConnectivity,
+}
+
+impl ConfigurationDefaultResponse {
+    pub fn new(response: RawResponse) -> Self {
+        let gross_location = match (response.raw_value >> 28).bitand(0b11) {
+            0b00 => ConfigDefGrossLocation::ExternalOnPrimaryChassis,
+            0b01 => ConfigDefGrossLocation::Internal,
+            0b10 => ConfigDefGrossLocation::SeparateChassis,
+            0b11 => ConfigDefGrossLocation::Other,
+            _ => panic!("This arm can never be reached as all cases are covered")
+        };
+
+        Self {
+            sequence: response.raw_value.bitand(0xF) as u8,
+            default_association: (response.raw_value >> 4).bitand(0xF) as u8,
+            jack_detect_override: response.get_bit(8),
+            color: match (response.raw_value >> 12).bitand(0xF) {
+                0x0 => ConfigDefColor::Unknown,
+                0x1 => ConfigDefColor::Black,
+                0x2 => ConfigDefColor::Grey,
+                0x3 => ConfigDefColor::Blue,
+                0x4 => ConfigDefColor::Green,
+                0x5 => ConfigDefColor::Red,
+                0x6 => ConfigDefColor::Orange,
+                0x7 => ConfigDefColor::Yellow,
+                0x8 => ConfigDefColor::Purple,
+                0x9 => ConfigDefColor::Pink,
+                // 0xA to 0xD are reserved
+                0xE => ConfigDefColor::White,
+                0xF => ConfigDefColor::Other,
+
+                // I first threw a panic here but the pyhsical sound card in my testing device returned the reserved value 0xC...
+                _ => ConfigDefColor::Unknown,
+            },
+            connection_type: match (response.raw_value >> 16).bitand(0xF) {
+                0x0 => ConfigDefConnectionType::Unknown,
+                0x1 => ConfigDefConnectionType::EighthInchStereoMono,
+                0x2 => ConfigDefConnectionType::QuarterInchStereoMono,
+                0x3 => ConfigDefConnectionType::ATAPIInternal,
+                0x4 => ConfigDefConnectionType::RCA,
+                0x5 => ConfigDefConnectionType::Optical,
+                0x6 => ConfigDefConnectionType::OtherDigital,
+                0x7 => ConfigDefConnectionType::OtherAnalog,
+                0x8 => ConfigDefConnectionType::MultichannelAnalogDIN,
+                0x9 => ConfigDefConnectionType::XLRProfessional,
+                0xA => ConfigDefConnectionType::RJ11Modem,
+                0xB => ConfigDefConnectionType::Combination,
+                // 0xC to 0xE are not defined in specification
+                0xF => ConfigDefConnectionType::Other,
+                _ => panic!("Unsupported connection type")
+            },
+            default_device: match (response.raw_value >> 20).bitand(0xF) {
+                0x0 => ConfigDefDefaultDevice::LineOut,
+                0x1 => ConfigDefDefaultDevice::Speaker,
+                0x2 => ConfigDefDefaultDevice::HPOut,
+                0x3 => ConfigDefDefaultDevice::CD,
+                0x4 => ConfigDefDefaultDevice::SPDIFOut,
+                0x5 => ConfigDefDefaultDevice::DigitalOtherOut,
+                0x6 => ConfigDefDefaultDevice::ModemLineSide,
+                0x7 => ConfigDefDefaultDevice::ModemHandsetSide,
+                0x8 => ConfigDefDefaultDevice::LineIn,
+                0x9 => ConfigDefDefaultDevice::AUX,
+                0xA => ConfigDefDefaultDevice::MicIn,
+                0xB => ConfigDefDefaultDevice::Telephony,
+                0xC => ConfigDefDefaultDevice::SPDIFIn,
+                0xD => ConfigDefDefaultDevice::DigitalOtherIn,
+                // 0xE is reserved
+                0xF => ConfigDefDefaultDevice::Other,
+                _ => panic!("Unsupported Type of Default Device")
+            },
+            geometric_location: match (response.raw_value >> 24).bitand(0xF) {
+                0x0 => ConfigDefGeometricLocation::NotAvailable,
+                0x1 => ConfigDefGeometricLocation::Rear,
+                0x2 => ConfigDefGeometricLocation::Front,
+                0x3 => ConfigDefGeometricLocation::Left,
+                0x4 => ConfigDefGeometricLocation::Right,
+                0x5 => ConfigDefGeometricLocation::Top,
+                0x6 => ConfigDefGeometricLocation::Bottom,
+                0x7 => match gross_location {
+                    ConfigDefGrossLocation::ExternalOnPrimaryChassis => ConfigDefGeometricLocation::RearPanel,
+                    ConfigDefGrossLocation::Internal => ConfigDefGeometricLocation::Riser,
+                    ConfigDefGrossLocation::Other => ConfigDefGeometricLocation::MobileLidInside,
+                    _ => panic!("Unsupported type of geometric location")
+                },
+                0x8 => match gross_location {
+                    ConfigDefGrossLocation::ExternalOnPrimaryChassis => ConfigDefGeometricLocation::DriveBay,
+                    ConfigDefGrossLocation::Internal => ConfigDefGeometricLocation::DigitalDisplay,
+                    ConfigDefGrossLocation::Other => ConfigDefGeometricLocation::MobileLidOutside,
+                    _ => panic!("Unsupported type of geometric location")
+                }
+                0x9 => match gross_location {
+                    ConfigDefGrossLocation::Internal => ConfigDefGeometricLocation::ATAPI,
+                    _ => panic!("Unsupported type of geometric location")
+                }
+                _ => panic!("Unsupported type of geometric location")
+            },
+            gross_location,
+            port_connectivity: match (response.raw_value >> 30).bitand(0b11) {
+                0b00 => ConfigDefPortConnectivity::Jack,
+                0b01 => ConfigDefPortConnectivity::NoPhysicalConnection,
+                0b10 => ConfigDefPortConnectivity::InternalDevice,
+                0b11 => ConfigDefPortConnectivity::JackAndInternalDevice,
+                _ => panic!("This arm can never be reached as all cases are covered")
+            },
+        }
+    }
+}
+
+impl TryFrom<Response> for ConfigurationDefaultResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ConfigurationDefault(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigDefPortConnectivity {
+    Jack,
+    NoPhysicalConnection,
+    InternalDevice,
+    JackAndInternalDevice,
+}
+
+#[derive(Debug)]
+pub enum ConfigDefGrossLocation {
+    ExternalOnPrimaryChassis,
+    Internal,
+    SeparateChassis,
+    Other,
+}
+
+#[derive(Debug)]
+pub enum ConfigDefGeometricLocation {
+    NotAvailable,
+    Rear,
+    Front,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    RearPanel,
+    Riser,
+    MobileLidInside,
+    DriveBay,
+    DigitalDisplay,
+    MobileLidOutside,
+    ATAPI,
+    //Specials of table 110 in section 7.3.3.31 not implemented
+}
+
+#[derive(Debug)]
+pub enum ConfigDefDefaultDevice {
+    LineOut,
+    Speaker,
+    HPOut,
+    CD,
+    SPDIFOut,
+    DigitalOtherOut,
+    ModemLineSide,
+    ModemHandsetSide,
+    LineIn,
+    AUX,
+    MicIn,
+    Telephony,
+    SPDIFIn,
+    DigitalOtherIn,
+    Other,
+}
+
+impl ConfigDefDefaultDevice {
+    fn as_u8(&self) -> u8 {
+        match self {
+            ConfigDefDefaultDevice::LineOut => 0x0,
+            ConfigDefDefaultDevice::Speaker => 0x1,
+            ConfigDefDefaultDevice::HPOut => 0x2,
+            ConfigDefDefaultDevice::CD => 0x3,
+            ConfigDefDefaultDevice::SPDIFOut => 0x4,
+            ConfigDefDefaultDevice::DigitalOtherOut => 0x5,
+            ConfigDefDefaultDevice::ModemLineSide => 0x6,
+            ConfigDefDefaultDevice::ModemHandsetSide => 0x7,
+            ConfigDefDefaultDevice::LineIn => 0x8,
+            ConfigDefDefaultDevice::AUX => 0x9,
+            ConfigDefDefaultDevice::MicIn => 0xA,
+            ConfigDefDefaultDevice::Telephony => 0xB,
+            ConfigDefDefaultDevice::SPDIFIn => 0xC,
+            ConfigDefDefaultDevice::DigitalOtherIn => 0xD,
+            ConfigDefDefaultDevice::Other => 0xF,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigDefConnectionType {
+    Unknown,
+    EighthInchStereoMono,
+    QuarterInchStereoMono,
+    ATAPIInternal,
+    RCA,
+    Optical,
+    OtherDigital,
+    OtherAnalog,
+    MultichannelAnalogDIN,
+    XLRProfessional,
+    RJ11Modem,
+    Combination,
+    Other,
+}
+
+impl ConfigDefConnectionType {
+    fn as_u8(&self) -> u8 {
+        match self {
+            ConfigDefConnectionType::Unknown => 0x0,
+            ConfigDefConnectionType::EighthInchStereoMono => 0x1,
+            ConfigDefConnectionType::QuarterInchStereoMono => 0x2,
+            ConfigDefConnectionType::ATAPIInternal => 0x3,
+            ConfigDefConnectionType::RCA => 0x4,
+            ConfigDefConnectionType::Optical => 0x5,
+            ConfigDefConnectionType::OtherDigital => 0x6,
+            ConfigDefConnectionType::OtherAnalog => 0x7,
+            ConfigDefConnectionType::MultichannelAnalogDIN => 0x8,
+            ConfigDefConnectionType::XLRProfessional => 0x9,
+            ConfigDefConnectionType::RJ11Modem => 0xA,
+            ConfigDefConnectionType::Combination => 0xB,
+            ConfigDefConnectionType::Other => 0xF,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigDefColor {
+    Unknown,
+    Black,
+    Grey,
+    Blue,
+    Green,
+    Red,
+    Orange,
+    Yellow,
+    Purple,
+    Pink,
+    White,
+    Other
+}
+
+#[derive(Debug, Getters)]
+pub struct ConverterChannelCountResponse {
+    converter_channel_count: u8,
+}
+
+impl ConverterChannelCountResponse {
+    pub fn new(response: RawResponse) -> Self {
+        Self {
+            converter_channel_count: response.raw_value.bitand(0xFF) as u8,
+        }
+    }
+}
+
+impl TryFrom<Response> for ConverterChannelCountResponse {
+    type Error = Response;
+
+    fn try_from(wrapped_response: Response) -> Result<Self, Self::Error> {
+        match wrapped_response {
+            Response::ConverterChannelCount(info) => Ok(info),
+            e => Err(e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node() -> NodeAddress {
+        NodeAddress::new(CodecAddress::new(2), 0x1A)
+    }
+
+    fn decode(verb: u32) -> (u8, u8, u16, u16) {
+        let fields = VerbFields::decode(verb);
+        (*fields.codec_address(), *fields.node_id(), *fields.verb_id(), *fields.payload())
+    }
+
+    // every command has to come out of VerbFields::decode with the address, id and payload it was built from
+    fn assert_verb(command: Command, expected: u32) {
+        assert_eq!(command.as_u32(), expected, "{:?}", command);
+        let (codec_address, node_id, verb_id, payload) = decode(expected);
+        assert_eq!((codec_address, node_id, verb_id), (command.codec_address(), 0x1A, command.id()), "{:?}", command);
+        let payload_bits = if verb_id > 0xF { 0xFF } else { 0xFFFF };
+        assert_eq!(payload as u32, expected & payload_bits, "{:?}", command);
+    }
+
+    fn payload(command: Command) -> u32 {
+        *VerbFields::decode(command.as_u32()).payload() as u32
+    }
+
+    #[test]
+    fn get_parameter_verbs() {
+        let parameters = [
+            (Parameter::VendorId, 0x00),
+            (Parameter::RevisionId, 0x02),
+            (Parameter::SubordinateNodeCount, 0x04),
+            (Parameter::FunctionGroupType, 0x05),
+            (Parameter::AudioFunctionGroupCapabilities, 0x08),
+            (Parameter::AudioWidgetCapabilities, 0x09),
+            (Parameter::SampleSizeRateCAPs, 0x0A),
+            (Parameter::SupportedStreamFormats, 0x0B),
+            (Parameter::PinCapabilities, 0x0C),
+            (Parameter::InputAmpCapabilities, 0x0D),
+            (Parameter::ConnectionListLength, 0x0E),
+            (Parameter::SupportedPowerStates, 0x0F),
+            (Parameter::ProcessingCapabilities, 0x10),
+            (Parameter::GPIOCount, 0x11),
+            (Parameter::OutputAmpCapabilities, 0x12),
+            (Parameter::VolumeKnobCapabilities, 0x13),
+        ];
+        for (parameter, id) in parameters {
+            assert_verb(Command::GetParameter(node(), parameter), 0x21AF_0000 | id);
+        }
+    }
+
+    #[test]
+    fn address_fields_use_their_full_width() {
+        let command = Command::GetParameter(NodeAddress::new(CodecAddress::new(14), 0xFF), Parameter::VendorId);
+        assert_eq!(command.as_u32(), 0xEFFF_0000);
+        assert_eq!(command.codec_address(), 14);
+        assert_eq!(decode(command.as_u32()), (14, 0xFF, 0xF00, 0x00));
+    }
+
+    #[test]
+    #[should_panic]
+    fn codec_address_above_14_panics() {
+        CodecAddress::new(MAX_AMOUNT_OF_CODECS);
+    }
+
+    #[test]
+    fn connection_verbs() {
+        assert_verb(Command::GetConnectionSelect(node()), 0x21AF_0100);
+        assert_verb(Command::SetConnectionSelect(node(), SetConnectionSelectPayload::new(3)), 0x21A7_0103);
+        assert_verb(Command::GetConnectionListEntry(node(), GetConnectionListEntryPayload::new(4)), 0x21AF_0204);
+    }
+
+    #[test]
+    fn amplifier_verbs() {
+        let get_output_left = GetAmplifierGainMutePayload::new(GetAmplifierGainMuteType::Output, GetAmplifierGainMuteSide::Left, 0);
+        assert_verb(Command::GetAmplifierGainMute(node(), get_output_left), 0x21AB_A000);
+        let get_input_right = GetAmplifierGainMutePayload::new(GetAmplifierGainMuteType::Input, GetAmplifierGainMuteSide::Right, 2);
+        assert_verb(Command::GetAmplifierGainMute(node(), get_input_right), 0x21AB_0002);
+
+        let set_output_both = SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Both, 0, true, 0x3F);
+        assert_verb(Command::SetAmplifierGainMute(node(), set_output_both), 0x21A3_B0BF);
+        let set_input_left = SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Left, 5, false, 0x7F);
+        assert_verb(Command::SetAmplifierGainMute(node(), set_input_left), 0x21A3_657F);
+        let set_both_right = SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Both, SetAmplifierGainMuteSide::Right, 15, false, 0);
+        assert_verb(Command::SetAmplifierGainMute(node(), set_both_right), 0x21A3_DF00);
+    }
+
+    #[test]
+    #[should_panic]
+    fn amplifier_gain_above_7_bits_panics() {
+        SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Both, 0, false, 0x80);
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_amplifier_index_above_15_panics() {
+        SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Input, SetAmplifierGainMuteSide::Both, 16, false, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn get_amplifier_index_above_15_panics() {
+        GetAmplifierGainMutePayload::new(GetAmplifierGainMuteType::Input, GetAmplifierGainMuteSide::Left, 16);
+    }
+
+    #[test]
+    fn set_amplifier_payload_round_trips_through_response() {
+        let command = Command::SetAmplifierGainMute(node(), SetAmplifierGainMutePayload::new(SetAmplifierGainMuteType::Output, SetAmplifierGainMuteSide::Both, 0, true, 0x55));
+        let response = AmplifierGainMuteResponse::new(RawResponse::new(payload(command)));
+        assert_eq!(*response.amplifier_gain(), 0x55);
+        assert!(*response.amplifier_mute());
+    }
+
+    #[test]
+    fn stream_verbs() {
+        assert_verb(Command::GetStreamFormat(node()), 0x21AA_0000);
+        assert_verb(Command::SetStreamFormat(node(), SetStreamFormatPayload::new(0x4011)), 0x21A2_4011);
+        assert_verb(Command::GetChannelStreamId(node()), 0x21AF_0600);
+        let command = Command::SetChannelStreamId(node(), SetChannelStreamIdPayload::new(2, 5));
+        assert_verb(command, 0x21A7_0652);
+
+        let response = ChannelStreamIdResponse::new(RawResponse::new(payload(command)));
+        assert_eq!((*response.channel(), *response.stream()), (2, 5));
+        let format = StreamFormatResponse::new(RawResponse::new(payload(Command::SetStreamFormat(node(), SetStreamFormatPayload::new(0x4011)))));
+        assert_eq!(*format.raw_value(), 0x4011);
+    }
+
+    #[test]
+    fn pin_widget_control_verbs() {
+        assert_verb(Command::GetPinWidgetControl(node()), 0x21AF_0700);
+        let input = Command::SetPinWidgetControl(node(), SetPinWidgetControlPayload::new(VoltageReferenceSignalLevel::EightyPercent, true, false, false));
+        assert_verb(input, 0x21A7_0724);
+        let headphones = Command::SetPinWidgetControl(node(), SetPinWidgetControlPayload::new(VoltageReferenceSignalLevel::HiZ, false, true, true));
+        assert_verb(headphones, 0x21A7_07C0);
+        assert_eq!(SetPinWidgetControlPayload::new_digital(EncodedPacketType::HighBitRate, true).as_u8(), 0x43);
+
+        let response = PinWidgetControlResponse::new(RawResponse::new(payload(input)));
+        assert!(matches!(response.voltage_reference_enable(), VoltageReferenceSignalLevel::EightyPercent));
+        assert_eq!((*response.in_enable(), *response.out_enable(), *response.h_phn_enable()), (true, false, false));
+        let response = PinWidgetControlResponse::new(RawResponse::new(payload(headphones)));
+        assert!(matches!(response.voltage_reference_enable(), VoltageReferenceSignalLevel::HiZ));
+        assert_eq!((*response.in_enable(), *response.out_enable(), *response.h_phn_enable()), (false, true, true));
+        let response = PinWidgetControlResponse::new(RawResponse::new(0x43));
+        assert_eq!(response.encoded_packet_type(), EncodedPacketType::HighBitRate);
+    }
+
+    #[test]
+    fn pin_widget_control_helpers_keep_the_other_bits() {
+        // 100% VRef, input and headphone amp enabled
+        let current = || PinWidgetControlResponse::new(RawResponse::new(0xA5));
+        assert_eq!(SetPinWidgetControlPayload::enable_input_amp(current()).as_u8(), 0xA5);
+        assert_eq!(SetPinWidgetControlPayload::disable_output_amp(current()).as_u8(), 0xA5);
+        assert_eq!(SetPinWidgetControlPayload::enable_input_and_output_amps(current()).as_u8(), 0xE5);
+        assert_eq!(SetPinWidgetControlPayload::enable_input_amp(PinWidgetControlResponse::new(RawResponse::new(0x40))).as_u8(), 0x20);
+    }
+
+    #[test]
+    fn eapd_verbs() {
+        assert_verb(Command::GetEAPDBTLEnable(node()), 0x21AF_0C00);
+        let command = Command::SetEAPDBTLEnable(node(), SetEAPDBTLEnablePayload::new(false, true, false));
+        assert_verb(command, 0x21A7_0C02);
+
+        let response = EAPDBTLEnableResponse::new(RawResponse::new(payload(command)));
+        assert_eq!((*response.btl_enable(), *response.eapd_enable(), *response.lr_swap()), (false, true, false));
+        let response = EAPDBTLEnableResponse::new(RawResponse::new(SetEAPDBTLEnablePayload::new(true, false, true).as_u8() as u32));
+        assert_eq!((*response.btl_enable(), *response.eapd_enable(), *response.lr_swap()), (true, false, true));
+    }
+
+    #[test]
+    fn digital_converter_verbs() {
+        assert_verb(Command::GetDigitalConverterControl(node()), 0x21AF_0D00);
+        assert_verb(Command::SetDigitalConverterControl1(node(), SetDigitalConverterControl1Payload::enable(StreamType::PCM)), 0x21A7_0D11);
+        assert_eq!(SetDigitalConverterControl1Payload::enable(StreamType::NonPCM).as_u8(), 0x31);
+        assert_verb(Command::SetDigitalConverterControl2(node(), SetDigitalConverterControl2Payload::new(0x7F)), 0x21A7_0E7F);
+
+        // the response holds both bytes, the category code of control 2 in bits [14:8]
+        let control1 = SetDigitalConverterControl1Payload::new(true, false, true, false, true, false, true, false).as_u8() as u32;
+        let control2 = SetDigitalConverterControl2Payload::new(0x1A).as_u8() as u32;
+        let response = DigitalConverterControlResponse::new(RawResponse::new(control2 << 8 | control1));
+        assert!(*response.digital_enable() && !*response.validity() && *response.validity_config() && !*response.pre_emphasis());
+        assert!(*response.copy() && !*response.non_audio() && *response.professional() && !*response.generation_level());
+        assert_eq!(*response.category_code(), 0x1A);
+    }
+
+    #[test]
+    #[should_panic]
+    fn category_code_above_7_bits_panics() {
+        SetDigitalConverterControl2Payload::new(0x80);
+    }
+
+    #[test]
+    fn configuration_default_verbs() {
+        assert_verb(Command::GetConfigurationDefault(node()), 0x21AF_1C00);
+        let payload = SetConfigurationDefault2Payload::new(&ConfigDefDefaultDevice::MicIn, &ConfigDefConnectionType::EighthInchStereoMono);
+        assert_verb(Command::SetConfigurationDefault2(node(), payload), 0x21A7_1EA1);
+
+        // the payload is byte 2 of the configuration default
+        let response = ConfigurationDefaultResponse::new(RawResponse::new((payload.as_u8() as u32) << 16));
+        assert!(matches!(response.default_device(), ConfigDefDefaultDevice::MicIn));
+        assert!(matches!(response.connection_type(), ConfigDefConnectionType::EighthInchStereoMono));
+    }
+
+    #[test]
+    fn converter_channel_count_verbs() {
+        assert_verb(Command::GetConverterChannelCount(node()), 0x21AF_2D00);
+        let command = Command::SetConverterChannelCount(node(), SetConverterChannelCountPayload::new(7));
+        assert_verb(command, 0x21A7_2D07);
+        assert_eq!(*ConverterChannelCountResponse::new(RawResponse::new(payload(command))).converter_channel_count(), 7);
+    }
+
+    #[test]
+    fn unsolicited_response_verbs() {
+        assert_verb(Command::GetUnsolicitedResponse(node()), 0x21AF_0800);
+        let command = Command::SetUnsolicitedResponse(node(), SetUnsolicitedResponsePayload::new(true, 0x3F));
+        assert_verb(command, 0x21A7_08BF);
+
+        let response = UnsolicitedResponseControlResponse::new(RawResponse::new(payload(command)));
+        assert_eq!((*response.enable(), *response.tag()), (true, 0x3F));
+        let response = UnsolicitedResponseControlResponse::new(RawResponse::new(SetUnsolicitedResponsePayload::new(false, 0x05).as_u8() as u32));
+        assert_eq!((*response.enable(), *response.tag()), (false, 0x05));
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsolicited_response_tag_above_6_bits_panics() {
+        SetUnsolicitedResponsePayload::new(true, 0x40);
+    }
+
+    #[test]
+    fn pin_sense_verbs() {
+        assert_verb(Command::GetPinSense(node()), 0x21AF_0900);
+        assert_verb(Command::ExecutePinSense(node()), 0x21A7_0900);
+
+        let response = PinSenseResponse::new(RawResponse::new(0x8000_1234));
+        assert_eq!((*response.presence_detect(), *response.impedance()), (true, 0x1234));
+        let response = PinSenseResponse::new(RawResponse::new(0x7FFF_FFFF));
+        assert_eq!((*response.presence_detect(), *response.impedance()), (false, 0x7FFF_FFFF));
+    }
+
+    #[test]
+    fn volume_knob_verbs() {
+        assert_verb(Command::GetVolumeKnob(node()), 0x21AF_0F00);
+        let command = Command::SetVolumeKnob(node(), SetVolumeKnobPayload::new(true, 0x7F));
+        assert_verb(command, 0x21A7_0FFF);
+
+        let response = VolumeKnobResponse::new(RawResponse::new(payload(command)));
+        assert_eq!((*response.direct(), *response.volume()), (true, 0x7F));
+        let response = VolumeKnobResponse::new(RawResponse::new(SetVolumeKnobPayload::new(false, 0x20).as_u8() as u32));
+        assert_eq!((*response.direct(), *response.volume()), (false, 0x20));
+    }
+
+    #[test]
+    #[should_panic]
+    fn volume_knob_position_above_7_bits_panics() {
+        SetVolumeKnobPayload::new(false, 0x80);
+    }
+
+    #[test]
+    fn power_state_verbs() {
+        assert_verb(Command::GetPowerState(node()), 0x21AF_0500);
+        assert_verb(Command::SetPowerState(node(), SetPowerStatePayload::new(PowerState::D3)), 0x21A7_0503);
+
+        for power_state in [PowerState::D0, PowerState::D1, PowerState::D2, PowerState::D3, PowerState::D3Cold] {
+            let response = PowerStateResponse::new(RawResponse::new(payload(Command::SetPowerState(node(), SetPowerStatePayload::new(power_state)))));
+            assert_eq!(*response.setting(), power_state);
+        }
+
+        // D3 requested, still in D0, with clock stop ok and settings reset
+        let response = PowerStateResponse::new(RawResponse::new(0x0603));
+        assert_eq!((*response.setting(), *response.actual()), (PowerState::D3, PowerState::D0));
+        assert_eq!((*response.error(), *response.clock_stop_ok(), *response.settings_reset()), (false, true, true));
+        let response = PowerStateResponse::new(RawResponse::new(0x0133));
+        assert_eq!((*response.setting(), *response.actual()), (PowerState::D3, PowerState::D3));
+        assert_eq!((*response.error(), *response.clock_stop_ok(), *response.settings_reset()), (true, false, false));
+    }
+
+    #[test]
+    fn coefficient_verbs() {
+        assert_verb(Command::GetCoefficientIndex(node()), 0x21AD_0000);
+        let index = Command::SetCoefficientIndex(node(), SetCoefficientIndexPayload::new(0x1234));
+        assert_verb(index, 0x21A5_1234);
+        assert_verb(Command::GetProcessingCoefficient(node()), 0x21AC_0000);
+        let coefficient = Command::SetProcessingCoefficient(node(), SetProcessingCoefficientPayload::new(0xBEEF));
+        assert_verb(coefficient, 0x21A4_BEEF);
+
+        assert_eq!(*CoefficientIndexResponse::new(RawResponse::new(payload(index))).coefficient_index(), 0x1234);
+        assert_eq!(*ProcessingCoefficientResponse::new(RawResponse::new(payload(coefficient))).coefficient(), 0xBEEF);
+    }
+
+    #[test]
+    fn gpio_verbs() {
+        assert_verb(Command::GetGPIOData(node()), 0x21AF_1500);
+        assert_verb(Command::SetGPIOData(node(), SetGPIOPayload::new(0x81)), 0x21A7_1581);
+        assert_verb(Command::GetGPIOEnableMask(node()), 0x21AF_1600);
+        assert_verb(Command::SetGPIOEnableMask(node(), SetGPIOPayload::new(0x01)), 0x21A7_1601);
+        assert_verb(Command::GetGPIODirection(node()), 0x21AF_1700);
+        assert_verb(Command::SetGPIODirection(node(), SetGPIOPayload::new(0xFF)), 0x21A7_17FF);
+
+        assert_eq!(*GPIOResponse::new(RawResponse::new(payload(Command::SetGPIOData(node(), SetGPIOPayload::new(0x81))))).bits(), 0x81);
+    }
+
+    #[test]
+    fn function_group_reset_verb() {
+        assert_verb(Command::FunctionGroupReset(node()), 0x21A7_FF00);
+    }
+
+    #[test]
+    fn response_is_wrapped_by_the_command_it_answers() {
+        let response = Response::new(RawResponse::new(0x8086_2668), Command::GetParameter(node(), Parameter::VendorId));
+        let vendor_id = VendorIdResponse::try_from(response).unwrap();
+        assert_eq!((*vendor_id.vendor_id(), *vendor_id.device_id()), (0x8086, 0x2668));
+
+        let response = Response::new(RawResponse::new(0x8000_0000), Command::GetParameter(node(), Parameter::OutputAmpCapabilities));
+        assert!(AmpCapabilitiesResponse::try_from(response).is_ok());
+        let response = Response::new(RawResponse::new(0), Command::SetPowerState(node(), SetPowerStatePayload::new(PowerState::D0)));
+        assert!(matches!(response, Response::Zeros));
+        let response = Response::new(RawResponse::new(0x1F), Command::GetGPIODirection(node()));
+        assert!(VendorIdResponse::try_from(response).is_err());
+    }
+
+    #[test]
+    fn implausible_responses() {
+        let vendor_id = |raw| Response::new(RawResponse::new(raw), Command::GetParameter(node(), Parameter::VendorId));
+        assert!(vendor_id(0).is_implausible());
+        assert!(vendor_id(0xFFFF_FFFF).is_implausible());
+        assert!(!vendor_id(0x1AF4_0022).is_implausible());
+        assert!(Response::new(RawResponse::new(0x0001_0000), Command::GetParameter(node(), Parameter::SubordinateNodeCount)).is_implausible());
+        assert!(Response::new(RawResponse::new(0x100), Command::GetParameter(node(), Parameter::FunctionGroupType)).is_implausible());
+    }
+
+    #[test]
+    fn decode_parameter_responses() {
+        let revision = RevisionIdResponse::new(RawResponse::new(0x0010_0302));
+        assert_eq!((*revision.major_revision(), *revision.minor_revision(), *revision.revision_id(), *revision.stepping_id()), (1, 0, 3, 2));
+
+        let node_count = SubordinateNodeCountResponse::new(RawResponse::new(0x0002_0015));
+        assert_eq!((*node_count.starting_node_number(), *node_count.total_number_of_nodes()), (0x02, 0x15));
+
+        let function_group = FunctionGroupTypeResponse::new(RawResponse::new(0x101));
+        assert!(matches!(function_group.node_type(), FunctionGroupTypeEnum::AudioFunctionGroup));
+        assert!(*function_group.unsolicited_response_capable());
+        assert!(matches!(FunctionGroupTypeResponse::new(RawResponse::new(0x02)).node_type(), FunctionGroupTypeEnum::VendorDefinedModemFunctionGroup));
+        assert!(matches!(FunctionGroupTypeResponse::new(RawResponse::new(0x85)).node_type(), FunctionGroupTypeEnum::VendorDefinedFunctionGroup));
+        assert!(matches!(FunctionGroupTypeResponse::new(RawResponse::new(0x03)).node_type(), FunctionGroupTypeEnum::Reserved(0x03)));
+
+        let afg_caps = AudioFunctionGroupCapabilitiesResponse::new(RawResponse::new(0x0001_0D0B));
+        assert_eq!((*afg_caps.output_delay(), *afg_caps.input_delay(), *afg_caps.beep_gen()), (0xB, 0xD, true));
+
+        let processing = ProcessingCapabilitiesResponse::new(RawResponse::new(0x0000_2001));
+        assert_eq!((*processing.benign(), *processing.num_coeff()), (true, 0x20));
+
+        let gpio_count = GPIOCountResponse::new(RawResponse::new(0xC003_0201));
+        assert_eq!((*gpio_count.num_gpios(), *gpio_count.num_gpos(), *gpio_count.num_gpis()), (1, 2, 3));
+        assert!(*gpio_count.gpi_unsol() && *gpio_count.gpi_wake());
+
+        let volume_knob = VolumeKnobCapabilitiesResponse::new(RawResponse::new(0xFF));
+        assert_eq!((*volume_knob.num_steps(), *volume_knob.delta()), (0x7F, true));
+    }
+
+    #[test]
+    fn decode_audio_widget_capabilities() {
+        // pin complex with connection list, unsolicited responses, output amp and stereo
+        let caps = AudioWidgetCapabilitiesResponse::new(RawResponse::new(0x0040_0185));
+        assert!(matches!(caps.widget_type(), WidgetType::PinComplex));
+        assert!(*caps.chan_count_lsb() && !*caps.in_amp_present() && *caps.out_amp_present() && *caps.unsol_capable() && *caps.conn_list());
+        assert!(!*caps.digital() && !*caps.power_cntrl() && !*caps.lr_swap() && !*caps.cp_caps());
+        assert_eq!((*caps.delay(), *caps.chan_count_ext()), (0, 0));
+
+        let caps = AudioWidgetCapabilitiesResponse::new(RawResponse::new(0x00DB_FFFE));
+        assert!(matches!(caps.widget_type(), WidgetType::Reserved(0xD)));
+        assert_eq!((*caps.delay(), *caps.chan_count_ext()), (0xB, 0b111));
+        assert!(!*caps.chan_count_lsb() && *caps.in_amp_present() && *caps.amp_param_override() && *caps.format_override());
+        assert!(*caps.stripe() && *caps.proc_widget() && *caps.digital() && *caps.power_cntrl() && *caps.lr_swap() && *caps.cp_caps());
+
+        let widget_types = [(0x0, 0), (0x1, 1), (0x2, 2), (0x3, 3), (0x5, 5), (0x6, 6), (0x7, 7), (0xF, 0xF)];
+        for (raw_type, expected) in widget_types {
+            let caps = AudioWidgetCapabilitiesResponse::new(RawResponse::new(raw_type << 20));
+            let decoded = match caps.widget_type() {
+                WidgetType::AudioOutput => 0,
+                WidgetType::AudioInput => 1,
+                WidgetType::AudioMixer => 2,
+                WidgetType::AudioSelector => 3,
+                WidgetType::PinComplex => 4,
+                WidgetType::PowerWidget => 5,
+                WidgetType::VolumeKnobWidget => 6,
+                WidgetType::BeepGeneratorWidget => 7,
+                WidgetType::VendorDefinedAudioWidget => 0xF,
+                WidgetType::Reserved(widget_type) => *widget_type,
+            };
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn decode_sample_size_rate_capabilities() {
+        // 44.1 kHz, 48 kHz and 96 kHz with 16 and 24 bits
+        let caps = SampleSizeRateCAPsResponse::new(RawResponse::new(0x000A_0160));
+        assert_eq!(caps.supported_sample_rates(), [44100, 48000, 96000]);
+        assert_eq!(caps.supported_bits_per_sample(), [BitsPerSample::Sixteen, BitsPerSample::Twentyfour]);
+        assert!(!caps.supports_sample_rate(44000));
+        assert!(!caps.is_empty());
+        assert!(SampleSizeRateCAPsResponse::new(RawResponse::new(0x001F_0000)).is_empty());
+
+        let all = SampleSizeRateCAPsResponse::new(RawResponse::new(0x001F_0FFF));
+        assert_eq!(all.supported_sample_rates(), CONVERTER_SAMPLE_RATES);
+        assert_eq!(all.supported_bits_per_sample(), CONVERTER_BITS_PER_SAMPLE);
+
+        let formats = SupportedStreamFormatsResponse::new(RawResponse::new(0b101));
+        assert_eq!((*formats.pcm(), *formats.float32(), *formats.ac3()), (true, false, true));
+        assert!(SupportedStreamFormatsResponse::new(RawResponse::new(0)).is_empty());
+    }
+
+    #[test]
+    fn decode_pin_capabilities() {
+        let caps = PinCapabilitiesResponse::new(RawResponse::new(0x0901_373D));
+        assert!(*caps.impedence_sense_capable() && !*caps.trigger_required() && *caps.presence_detect_capable() && *caps.headphone_drive_capable());
+        assert!(*caps.output_capable() && *caps.input_capable() && !*caps.balanced_io_pins() && !*caps.hdmi());
+        assert!(*caps.eapd_capable() && *caps.display_port() && *caps.high_bit_rate());
+        assert_eq!(*caps.vref_control(), 0x37);
+        assert!(caps.supports_voltage_reference(VoltageReferenceSignalLevel::HiZ));
+        assert!(caps.supports_voltage_reference(VoltageReferenceSignalLevel::FiftyPercent));
+        assert!(caps.supports_voltage_reference(VoltageReferenceSignalLevel::Ground0V));
+        assert!(caps.supports_voltage_reference(VoltageReferenceSignalLevel::EightyPercent));
+        assert!(caps.supports_voltage_reference(VoltageReferenceSignalLevel::HundredPercent));
+        assert!(!PinCapabilitiesResponse::new(RawResponse::new(0x0000_1000)).supports_voltage_reference(VoltageReferenceSignalLevel::HiZ));
+    }
+
+    #[test]
+    fn decode_amp_capabilities() {
+        let caps = AmpCapabilitiesResponse::new(RawResponse::new(0x8005_7F4A));
+        assert_eq!((*caps.offset(), *caps.num_steps(), *caps.step_size(), *caps.mute_capable()), (0x4A, 0x7F, 0x05, true));
+        assert!(!caps.is_empty());
+        assert!(AmpCapabilitiesResponse::new(RawResponse::new(0)).is_empty());
+        // reserved bits don't show up in the fields
+        let caps = AmpCapabilitiesResponse::new(RawResponse::new(0x7F80_8080));
+        assert!(caps.is_empty());
+    }
+
+    #[test]
+    fn decode_connection_list() {
+        let length = ConnectionListLengthResponse::new(RawResponse::new(0x85));
+        assert_eq!((*length.connection_list_length(), *length.long_form()), (5, true));
+
+        let short_form = ConnectionListEntryResponse::new(RawResponse::new(0x0C8B_0302));
+        let entries: Vec<(u16, bool)> = short_form.entries(false).iter().map(|entry| (*entry.node_id(), *entry.range())).collect();
+        assert_eq!(entries, [(0x02, false), (0x03, false), (0x0B, true), (0x0C, false)]);
+
+        let long_form = ConnectionListEntryResponse::new(RawResponse::new(0x8123_0042));
+        let entries: Vec<(u16, bool)> = long_form.entries(true).iter().map(|entry| (*entry.node_id(), *entry.range())).collect();
+        assert_eq!(entries, [(0x0042, false), (0x0123, true)]);
+
+        assert_eq!(*ConnectionSelectResponse::new(RawResponse::new(0x03)).currently_set_connection_index(), 3);
+    }
+
+    #[test]
+    fn decode_supported_power_states() {
+        let power_states = SupportedPowerStatesResponse::new(RawResponse::new(0xE000_0019));
+        assert!(*power_states.d0_sup() && !*power_states.d1_sup() && !*power_states.d2_sup() && *power_states.d3_sup() && *power_states.d3cold_sup());
+        assert!(*power_states.s3d3cold_sup() && *power_states.clkstop() && *power_states.epss());
+        assert!(SupportedPowerStatesResponse::new(RawResponse::new(0x0FFF_FFE0)).is_empty());
+    }
+
+    #[test]
+    fn decode_configuration_default() {
+        // green 1/8" line out jack at the rear, association 1, sequence 0 (as reported by QEMU)
+        let config = ConfigurationDefaultResponse::new(RawResponse::new(0x0101_4010));
+        assert_eq!((*config.sequence(), *config.default_association(), *config.jack_detect_override()), (0, 1, false));
+        assert!(matches!(config.color(), ConfigDefColor::Green));
+        assert!(matches!(config.connection_type(), ConfigDefConnectionType::EighthInchStereoMono));
+        assert!(matches!(config.default_device(), ConfigDefDefaultDevice::LineOut));
+        assert!(matches!(config.geometric_location(), ConfigDefGeometricLocation::Rear));
+        assert!(matches!(config.gross_location(), ConfigDefGrossLocation::ExternalOnPrimaryChassis));
+        assert!(matches!(config.port_connectivity(), ConfigDefPortConnectivity::Jack));
+
+        // internal speaker without a jack, with the reserved color 0xC
+        let config = ConfigurationDefaultResponse::new(RawResponse::new(0x9017_C1F3));
+        assert_eq!((*config.sequence(), *config.default_association(), *config.jack_detect_override()), (3, 0xF, true));
+        assert!(matches!(config.color(), ConfigDefColor::Unknown));
+        assert!(matches!(config.connection_type(), ConfigDefConnectionType::OtherAnalog));
+        assert!(matches!(config.default_device(), ConfigDefDefaultDevice::Speaker));
+        assert!(matches!(config.geometric_location(), ConfigDefGeometricLocation::NotAvailable));
+        assert!(matches!(config.gross_location(), ConfigDefGrossLocation::Internal));
+        assert!(matches!(config.port_connectivity(), ConfigDefPortConnectivity::InternalDevice));
+
+        // the meaning of the special geometric locations depends on the gross location
+        assert!(matches!(ConfigurationDefaultResponse::new(RawResponse::new(0x1800_0000)).geometric_location(), ConfigDefGeometricLocation::DigitalDisplay));
+        assert!(matches!(ConfigurationDefaultResponse::new(RawResponse::new(0x0800_0000)).geometric_location(), ConfigDefGeometricLocation::DriveBay));
+        assert!(matches!(ConfigurationDefaultResponse::new(RawResponse::new(0x3700_0000)).geometric_location(), ConfigDefGeometricLocation::MobileLidInside));
+    }
+
+    #[test]
+    fn decode_verbs_from_the_wire() {
+        assert_eq!(decode(0x0017_0500), (0, 0x01, 0x705, 0x00));
+        assert_eq!(decode(0x1023_B07F), (1, 0x02, 0x3, 0xB07F));
+        assert_eq!(decode(0x00F7_FF00), (0, 0x0F, 0x7FF, 0x00));
+        assert_eq!(decode(0xE0CD_0000), (14, 0x0C, 0xD, 0x0000));
+    }
+}
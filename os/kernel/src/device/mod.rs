@@ -11,4 +11,9 @@ pub mod pci;
 pub mod ihda_api;
 mod ihda_controller;
 mod ihda_codec;
+mod ihda_verbs;
+mod ihda_output;
+mod ihda_codec_driver;
+mod ihda_codec_export;
+mod ihda_stream;
 mod ihda_pci;
@@ -3,16 +3,74 @@ use log::{info};
 use pci_types::{BaseClass, ConfigRegionAccess, EndpointHeader, HeaderType, PciAddress, PciHeader, PciPciBridgeHeader, SubClass};
 use spin::Mutex;
 use x86_64::instructions::port::{Port, PortWriteOnly};
+use x86_64::VirtAddr;
 
 const MAX_DEVICES_PER_BUS: u8 = 32;
 const MAX_FUNCTIONS_PER_DEVICE: u8 = 8;
 const INVALID: u16 = 0xffff;
 
+// see PCI Local Bus Specification 3.0, section 6.7 (capabilities list) and 6.8 (MSI and MSI-X)
+const COMMAND_AND_STATUS_OFFSET: u16 = 0x04;
+const STATUS_CAPABILITIES_LIST: u32 = 1 << 20;
+const CAPABILITIES_POINTER_OFFSET: u16 = 0x34;
+// the lowest two bits of a capability pointer are reserved
+const CAPABILITY_POINTER_MASK: u32 = 0xfc;
+// guards against malformed lists which point back to an earlier capability; a list can't hold more entries than fit behind the header
+const MAX_CAPABILITIES: usize = 48;
+const CAPABILITY_ID_MSI: u8 = 0x05;
+const CAPABILITY_ID_MSI_X: u8 = 0x11;
+// the message control register is the upper half of the first dword of the MSI and MSI-X capabilities
+const MSI_ENABLE: u32 = 1 << 16;
+const MSI_MULTIPLE_MESSAGE_ENABLE: u32 = 0b111 << 20;
+const MSI_64_BIT_ADDRESS_CAPABLE: u32 = 1 << 23;
+const MSI_X_TABLE_SIZE: u32 = 0x7ff << 16;
+const MSI_X_FUNCTION_MASK: u32 = 1 << 30;
+const MSI_X_ENABLE: u32 = 1 << 31;
+const MSI_X_BAR_INDICATOR: u32 = 0b111;
+const MSI_X_TABLE_ENTRY_SIZE_IN_BYTES: u64 = 16;
+const MSI_X_VECTOR_CONTROL_MASK_BIT: u32 = 1;
+// messages get written to the local APIC, whose id is part of the address (see Intel SDM Vol. 3, section 11.11.1)
+const MSI_ADDRESS_BASE: u64 = 0xfee0_0000;
+const MSI_ADDRESS_DESTINATION_ID_SHIFT: u64 = 12;
+
 pub struct PciBus {
     config_space: ConfigurationSpace,
     devices: Vec<EndpointHeader>
 }
 
+// Address and data a device writes to raise an interrupt, which bypasses the IO APIC and arrives at the local APIC directly.
+// The data only contains the vector, so the interrupt gets delivered in fixed mode and is edge triggered (see Intel SDM Vol. 3, section 11.11.2).
+#[derive(Clone, Copy, Debug)]
+pub struct MsiMessage {
+    address: u64,
+    data: u32,
+}
+
+impl MsiMessage {
+    pub fn new(local_apic_id: u8, vector: u8) -> Self {
+        Self {
+            address: MSI_ADDRESS_BASE | (local_apic_id as u64) << MSI_ADDRESS_DESTINATION_ID_SHIFT,
+            data: vector as u32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MsiCapability {
+    address: PciAddress,
+    offset: u16,
+    is_64_bit: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MsiXCapability {
+    address: PciAddress,
+    offset: u16,
+    table_size: u16,
+    table_bar: u8,
+    table_offset: u32,
+}
+
 pub struct ConfigurationSpace {
     ports: Mutex<ConfigurationPorts>
 }
@@ -64,6 +122,86 @@ impl ConfigRegionAccess for ConfigurationSpace {
     }
 }
 
+impl MsiCapability {
+    // Only a single message gets enabled, so the device uses the same vector for all of its interrupt sources.
+    pub fn enable(&self, config_space: &ConfigurationSpace, message: MsiMessage) {
+        unsafe {
+            config_space.write(self.address, self.offset + 0x04, message.address as u32);
+            if self.is_64_bit {
+                config_space.write(self.address, self.offset + 0x08, (message.address >> 32) as u32);
+                config_space.write(self.address, self.offset + 0x0c, message.data);
+            } else {
+                config_space.write(self.address, self.offset + 0x08, message.data);
+            }
+
+            let control = config_space.read(self.address, self.offset);
+            config_space.write(self.address, self.offset, (control & !MSI_MULTIPLE_MESSAGE_ENABLE) | MSI_ENABLE);
+        }
+    }
+
+    pub fn disable(&self, config_space: &ConfigurationSpace) {
+        unsafe {
+            let control = config_space.read(self.address, self.offset);
+            config_space.write(self.address, self.offset, control & !MSI_ENABLE);
+        }
+    }
+}
+
+impl MsiXCapability {
+    pub fn table_size(&self) -> u16 {
+        self.table_size
+    }
+
+    // index of the base address register, whose memory space holds the table
+    pub fn table_bar(&self) -> u8 {
+        self.table_bar
+    }
+
+    pub fn table_offset(&self) -> u32 {
+        self.table_offset
+    }
+
+    // Programs and unmasks a single entry of the table, which must already be mapped at bar_address, and masks all other entries.
+    pub fn enable(&self, config_space: &ConfigurationSpace, bar_address: VirtAddr, entry: u16, message: MsiMessage) {
+        if entry >= self.table_size {
+            panic!("MSI-X table entry {} does not exist, the table only has {} entries", entry, self.table_size);
+        }
+
+        // the entries can only be written safely while all of them are masked (see PCI Local Bus Specification 3.0, section 6.8.2.9)
+        unsafe {
+            let control = config_space.read(self.address, self.offset);
+            config_space.write(self.address, self.offset, control | MSI_X_ENABLE | MSI_X_FUNCTION_MASK);
+        }
+
+        let table_address = bar_address.as_u64() + self.table_offset as u64;
+        for index in 0..self.table_size {
+            let entry_address = (table_address + index as u64 * MSI_X_TABLE_ENTRY_SIZE_IN_BYTES) as *mut u32;
+            unsafe {
+                if index == entry {
+                    entry_address.write_volatile(message.address as u32);
+                    entry_address.add(1).write_volatile((message.address >> 32) as u32);
+                    entry_address.add(2).write_volatile(message.data);
+                    entry_address.add(3).write_volatile(0);
+                } else {
+                    entry_address.add(3).write_volatile(MSI_X_VECTOR_CONTROL_MASK_BIT);
+                }
+            }
+        }
+
+        unsafe {
+            let control = config_space.read(self.address, self.offset);
+            config_space.write(self.address, self.offset, control & !MSI_X_FUNCTION_MASK);
+        }
+    }
+
+    pub fn disable(&self, config_space: &ConfigurationSpace) {
+        unsafe {
+            let control = config_space.read(self.address, self.offset);
+            config_space.write(self.address, self.offset, control & !MSI_X_ENABLE);
+        }
+    }
+}
+
 impl PciBus {
     pub fn scan() -> Self {
         let mut pci = Self { config_space: ConfigurationSpace::new(), devices: Vec::new() };
@@ -109,6 +247,56 @@ impl PciBus {
             .collect()
     }
 
+    pub fn msi_capability(&self, device: &EndpointHeader) -> Option<MsiCapability> {
+        let address = device.header().address();
+        let offset = self.find_capability(address, CAPABILITY_ID_MSI)?;
+        let control = unsafe { self.config_space.read(address, offset) };
+
+        Some(MsiCapability {
+            address,
+            offset,
+            is_64_bit: control & MSI_64_BIT_ADDRESS_CAPABLE != 0,
+        })
+    }
+
+    pub fn msi_x_capability(&self, device: &EndpointHeader) -> Option<MsiXCapability> {
+        let address = device.header().address();
+        let offset = self.find_capability(address, CAPABILITY_ID_MSI_X)?;
+        let control = unsafe { self.config_space.read(address, offset) };
+        let table = unsafe { self.config_space.read(address, offset + 0x04) };
+
+        Some(MsiXCapability {
+            address,
+            offset,
+            // the table size is encoded as N - 1
+            table_size: ((control & MSI_X_TABLE_SIZE) >> 16) as u16 + 1,
+            table_bar: (table & MSI_X_BAR_INDICATOR) as u8,
+            table_offset: table & !MSI_X_BAR_INDICATOR,
+        })
+    }
+
+    // offset of the capability with the given id in the configuration space of the device
+    fn find_capability(&self, address: PciAddress, capability_id: u8) -> Option<u16> {
+        let status = unsafe { self.config_space.read(address, COMMAND_AND_STATUS_OFFSET) };
+        if status & STATUS_CAPABILITIES_LIST == 0 {
+            return None;
+        }
+
+        let mut offset = (unsafe { self.config_space.read(address, CAPABILITIES_POINTER_OFFSET) } & CAPABILITY_POINTER_MASK) as u16;
+        for _ in 0..MAX_CAPABILITIES {
+            if offset == 0 {
+                return None;
+            }
+            let header = unsafe { self.config_space.read(address, offset) };
+            if (header & 0xff) as u8 == capability_id {
+                return Some(offset);
+            }
+            offset = ((header >> 8) & CAPABILITY_POINTER_MASK) as u16;
+        }
+
+        None
+    }
+
     fn scan_bus(&mut self, address: PciAddress) {
         assert_eq!(address.device(), 0);
         assert_eq!(address.function(), 0);
@@ -1,8 +1,10 @@
-use crate::device::qemu_cfg::Selector::Signature;
+use alloc::vec::Vec;
+use crate::device::qemu_cfg::Selector::{RootDirectory, Signature};
 use x86_64::instructions::port::{PortReadOnly, PortWriteOnly};
 
 const SELECTOR_PORT: u16 = 0x510;
 const DATA_PORT: u16 = 0x511;
+const FILE_NAME_LENGTH: usize = 56;
 
 #[allow(dead_code)]
 #[repr(u16)]
@@ -29,3 +31,36 @@ pub fn is_available() -> bool {
 
     return id[0] == 'Q' as u8 && id[1] == 'E' as u8 && id[2] == 'M' as u8 && id[3] == 'U' as u8;
 }
+
+// Returns the content of a file passed to QEMU with "-fw_cfg name=<name>,string=<content>" or "-fw_cfg name=<name>,file=<path>".
+// The file directory starts with the amount of files, followed by one entry per file with its size, selector and name,
+// where all numbers are big endian (see docs/specs/fw_cfg.rst in the QEMU sources).
+pub fn read_file(name: &str) -> Option<Vec<u8>> {
+    if !is_available() {
+        return None;
+    }
+
+    let mut selector_port = PortWriteOnly::<u16>::new(SELECTOR_PORT);
+    let mut data_port = PortReadOnly::<u8>::new(DATA_PORT);
+    let mut read_bytes = |amount: usize| -> Vec<u8> { (0..amount).map(|_| unsafe { data_port.read() }).collect() };
+
+    unsafe { selector_port.write(RootDirectory as u16); }
+    let file_count = u32::from_be_bytes(read_bytes(4).try_into().unwrap());
+    let mut file = None;
+    for _ in 0..file_count {
+        let size = u32::from_be_bytes(read_bytes(4).try_into().unwrap());
+        let selector = u16::from_be_bytes(read_bytes(2).try_into().unwrap());
+        // two reserved bytes
+        read_bytes(2);
+        let file_name = read_bytes(FILE_NAME_LENGTH);
+        let file_name_length = file_name.iter().position(|byte| *byte == 0).unwrap_or(FILE_NAME_LENGTH);
+        if &file_name[..file_name_length] == name.as_bytes() {
+            file = Some((selector, size));
+            break;
+        }
+    }
+
+    let (selector, size) = file?;
+    unsafe { selector_port.write(selector); }
+    Some(read_bytes(size as usize))
+}
@@ -59,6 +59,9 @@ pub enum InterruptVector {
     SecondaryAta = 0x2f,
     // Possibly some other interrupts supported by IO APICs
 
+    // Message signaled interrupts, which bypass the IO APIC
+    IhdaMsi = 0xf0,
+
     // Local APIC interrupts (247 - 254)
     Cmci = 0xf8,
     ApicTimer = 0xf9,
@@ -159,6 +162,8 @@ impl TryFrom<u8> for InterruptVector {
                 Ok(InterruptVector::SecondaryAta)
             }
 
+            value if value == InterruptVector::IhdaMsi as u8 => Ok(InterruptVector::IhdaMsi),
+
             value if value == InterruptVector::Cmci as u8 => Ok(InterruptVector::Cmci),
             value if value == InterruptVector::ApicTimer as u8 => Ok(InterruptVector::ApicTimer),
             value if value == InterruptVector::Thermal as u8 => Ok(InterruptVector::Thermal),
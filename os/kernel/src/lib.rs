@@ -9,26 +9,34 @@
 #![feature(abi_x86_interrupt)]
 #![feature(trait_upcasting)]
 #![allow(internal_features)]
-#![no_std]
+// the unit tests run on the host, where the test harness needs std
+#![cfg_attr(not(test), no_std)]
 
 use crate::device::apic::Apic;
 use crate::device::lfb_terminal::{CursorThread, LFBTerminal};
 use crate::device::pit::Timer;
+use crate::device::qemu_cfg;
 use crate::device::ps2::PS2;
 use crate::device::serial;
 use crate::device::serial::{BaudRate, ComPort, SerialPort};
 use crate::device::speaker::Speaker;
 use crate::device::terminal::Terminal;
-use crate::device::ihda_api::IntelHDAudioDevice;
+use crate::device::ihda_api::{IntelHDAudioDevice, CODEC_REPROBE_INTERVAL_IN_MS, JACK_EVENT_POLL_INTERVAL_IN_MS, JACK_PRESENCE_POLL_INTERVAL_IN_MS, STREAM_WATCHDOG_INTERVAL_IN_MS};
+use crate::audio::persistence::{PersistedSettings, AUDIO_SETTINGS_FILE};
+use crate::audio::service::AudioService;
 use crate::memory::alloc::{AcpiHandler, KernelAllocator};
 use crate::interrupt::interrupt_dispatcher::InterruptDispatcher;
 use crate::log::Logger;
 use crate::process::scheduler::Scheduler;
 use crate::process::thread::Thread;
 use alloc::boxed::Box;
+#[cfg(not(test))]
 use core::fmt::Arguments;
+#[cfg(not(test))]
 use core::panic::PanicInfo;
-use ::log::{error, Level, Log, Record};
+use ::log::error;
+#[cfg(not(test))]
+use ::log::{Level, Log, Record};
 use acpi::AcpiTables;
 use multiboot2::ModuleTag;
 use spin::{Mutex, Once, RwLock};
@@ -48,6 +56,9 @@ extern crate alloc;
 
 #[macro_use]
 pub mod device;
+pub mod audio;
+// entry point of the kernel, which refers to symbols of the linker script and of boot.asm, so it is left out of the tests
+#[cfg(not(test))]
 pub mod boot;
 pub mod interrupt;
 pub mod memory;
@@ -60,6 +71,8 @@ pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
+// the panic handler and the allocator of the kernel would clash with those of std in the tests
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     if terminal_initialized() {
@@ -100,7 +113,7 @@ static EFI_SYSTEM_TABLE: Once<EfiSystemTable> = Once::new();
 static ACPI_TABLES: Once<Mutex<AcpiTables<AcpiHandler>>> = Once::new();
 static INIT_RAMDISK: Once<TarArchiveRef> = Once::new();
 
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 static ALLOCATOR: KernelAllocator = KernelAllocator::new();
 static LOGGER: Mutex<Logger> = Mutex::new(Logger::new());
 static PROCESS_MANAGER: RwLock<ProcessManager> = RwLock::new(ProcessManager::new());
@@ -115,6 +128,7 @@ static TERMINAL: Once<LFBTerminal> = Once::new();
 static PS2: Once<PS2> = Once::new();
 static PCI: Once<PciBus> = Once::new();
 static INTEL_HD_AUDIO: Once<IntelHDAudioDevice> = Once::new();
+static AUDIO_SERVICE: AudioService = AudioService::new();
 
 pub fn init_efi_system_table(table: SystemTable<Runtime>) {
     EFI_SYSTEM_TABLE.call_once(|| EfiSystemTable::new(table));
@@ -189,7 +203,76 @@ pub fn init_pci() {
 }
 
 pub fn init_ihda() {
-    INTEL_HD_AUDIO.call_once(|| IntelHDAudioDevice::new());
+    // the sound card is optional, so the system keeps running without sound if it can't be initialized
+    match IntelHDAudioDevice::new() {
+        Ok(device) => {
+            INTEL_HD_AUDIO.call_once(|| device);
+            // playback sessions pick their device from the registry, so they don't depend on the IHDA driver
+            audio::output_devices().lock().register(intel_hd_audio_device());
+            audio::refill::start_refill_thread();
+            // processes don't close their sessions when they crash, so the audio service releases their streams afterwards
+            process_manager().write().register_exit_hook(audio::service::handle_process_exit);
+            restore_audio_settings();
+        }
+        Err(error) => {
+            error!("Intel HD Audio device disabled: {:?}", error);
+            return;
+        }
+    }
+
+    // codecs get quarantined after repeated command timeouts and are periodically checked for recovery,
+    // idle codecs get powered down in the same interval
+    scheduler().ready(Thread::new_kernel_thread(Box::new(|| {
+        loop {
+            scheduler().sleep(CODEC_REPROBE_INTERVAL_IN_MS);
+            intel_hd_audio_device().reprobe_quarantined_codecs();
+            intel_hd_audio_device().power_down_if_idle();
+        }
+    })));
+
+    // jack events arrive as unsolicited responses in the RIRB, which gets polled instead of waiting for the response interrupt;
+    // codec state changes and controller errors get noted by the interrupt handler and are handled in the same interval,
+    // afterwards the streams of exited processes get released and the audio events published meanwhile get passed to the kernel listeners
+    scheduler().ready(Thread::new_kernel_thread(Box::new(|| {
+        loop {
+            scheduler().sleep(JACK_EVENT_POLL_INTERVAL_IN_MS);
+            intel_hd_audio_device().handle_jack_events();
+            intel_hd_audio_device().handle_codec_state_changes();
+            intel_hd_audio_device().handle_controller_errors();
+            audio_service().reclaim_exited_processes();
+            audio_service().dispatch_events();
+        }
+    })));
+
+    // headphone pins which can't send unsolicited responses get polled with a lower frequency instead
+    scheduler().ready(Thread::new_kernel_thread(Box::new(|| {
+        loop {
+            scheduler().sleep(JACK_PRESENCE_POLL_INTERVAL_IN_MS);
+            intel_hd_audio_device().poll_jack_presence();
+        }
+    })));
+
+    // DMA engines which stop advancing without reporting an error only get noticed by sampling their positions
+    scheduler().ready(Thread::new_kernel_thread(Box::new(|| {
+        loop {
+            scheduler().sleep(STREAM_WATCHDOG_INTERVAL_IN_MS);
+            intel_hd_audio_device().check_stalled_streams();
+        }
+    })));
+}
+
+// The settings get saved by the host (see audio::persistence), so there is nothing to restore if QEMU hasn't been given a settings file.
+fn restore_audio_settings() {
+    let file = match qemu_cfg::read_file(AUDIO_SETTINGS_FILE) {
+        Some(file) => file,
+        None => return,
+    };
+
+    match core::str::from_utf8(&file).map(PersistedSettings::parse) {
+        Ok(Ok(settings)) => intel_hd_audio_device().restore_persisted_settings(&settings),
+        Ok(Err(error)) => error!("Invalid audio settings file: {:?}", error),
+        Err(_) => error!("Audio settings file is not valid UTF-8"),
+    }
 }
 
 pub fn init_initrd(module: &ModuleTag) {
@@ -290,6 +373,10 @@ pub fn intel_hd_audio_device() -> &'static IntelHDAudioDevice {
     INTEL_HD_AUDIO.get().expect("Trying to access Intel HD Audio device bus before initialization!")
 }
 
+pub fn audio_service() -> &'static AudioService {
+    &AUDIO_SERVICE
+}
+
 #[no_mangle]
 pub extern "C" fn tss_set_rsp0(rsp0: u64) {
     tss().lock().privilege_stack_table[0] = VirtAddr::new(rsp0);
@@ -0,0 +1,96 @@
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
+use x86_64::{PhysAddr, VirtAddr};
+use crate::memory::{physical, PAGE_SIZE};
+use crate::process_manager;
+
+/// How the CPU caches a DMA region.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CachePolicy {
+    /// Regular kernel mapping. Only suitable for devices which snoop the CPU caches.
+    WriteBack,
+    /// Mapped with the NO_CACHE flag, so that the CPU and the device always see the same data.
+    Uncached,
+}
+
+/// Physically contiguous page frames, which get accessed by the DMA engine of a device.
+/// The frames go back to the frame allocator with their regular kernel mapping, once the region gets dropped.
+/// So the owner has to make sure that no device accesses them anymore at that point, or leak the region (see `DmaRegion::leak`).
+#[derive(Debug)]
+pub struct DmaRegion {
+    frames: PhysFrameRange,
+    cache_policy: CachePolicy,
+    leaked: bool,
+}
+
+/// Allocate `frame_count` physically contiguous page frames for DMA and map them with the given cache policy.
+pub fn alloc_dma(frame_count: usize, cache_policy: CachePolicy) -> DmaRegion {
+    let frames = physical::alloc(frame_count);
+    if cache_policy == CachePolicy::Uncached {
+        set_kernel_page_flags(frames, PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE);
+    }
+
+    DmaRegion { frames, cache_policy, leaked: false }
+}
+
+impl DmaRegion {
+    /// First page frame of the region, e.g. for registers which take a physical frame.
+    pub fn start_frame(&self) -> PhysFrame {
+        self.frames.start
+    }
+
+    /// Physical address, which gets programmed into the device.
+    pub fn physical_address(&self) -> PhysAddr {
+        self.frames.start.start_address()
+    }
+
+    /// Virtual address, through which the kernel accesses the region.
+    /// Physical memory is identity mapped in the kernel address space, so this is the same as the physical address.
+    pub fn virtual_address(&self) -> VirtAddr {
+        VirtAddr::new(self.physical_address().as_u64())
+    }
+
+    /// All page frames of the region, e.g. for mapping them into another address space.
+    pub fn frames(&self) -> PhysFrameRange {
+        self.frames
+    }
+
+    pub fn frame_count(&self) -> usize {
+        (self.frames.end - self.frames.start) as usize
+    }
+
+    pub fn length_in_bytes(&self) -> usize {
+        self.frame_count() * PAGE_SIZE
+    }
+
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.cache_policy
+    }
+
+    /// Keep the page frames allocated and mapped when the region gets dropped, because a device might still access them
+    /// (e.g. a DMA engine which could not be stopped). The frames are lost until the next boot.
+    pub fn leak(&mut self) {
+        self.leaked = true;
+    }
+}
+
+impl Drop for DmaRegion {
+    fn drop(&mut self) {
+        if self.leaked {
+            return;
+        }
+        if self.cache_policy == CachePolicy::Uncached {
+            set_kernel_page_flags(self.frames, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        }
+        unsafe { physical::free(self.frames); }
+    }
+}
+
+/// Change the flags of the identity mapping of the given frames in the kernel address space.
+fn set_kernel_page_flags(frames: PhysFrameRange, flags: PageTableFlags) {
+    let kernel_address_space = process_manager().read().kernel_process().unwrap().address_space();
+    let start_page = Page::from_start_address(VirtAddr::new(frames.start.start_address().as_u64())).unwrap();
+    let end_page = Page::from_start_address(VirtAddr::new(frames.end.start_address().as_u64())).unwrap();
+    kernel_address_space.set_flags(PageRange { start: start_page, end: end_page }, flags);
+}
@@ -22,7 +22,7 @@ pub struct VirtualMemoryArea {
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum VmaType {
-    Code, Heap, Stack
+    Code, Heap, Stack, Device
 }
 
 unsafe impl Send for AddressSpace {}
@@ -393,7 +393,8 @@ impl AddressSpace {
     fn map_user_physical(table: &mut PageTable, frames: PhysFrameRange, pages: PageRange, flags: PageTableFlags) -> usize {
         let start_index = usize::from(page_table_index(pages.start.start_address(), 1));
         let alloc_count = min((pages.end - pages.start) as usize, 512 - start_index);
-        let mut frame_iter = frames.into_iter().skip(start_index);
+        // the frames have already been advanced by the pages mapped in previous tables, so the first frame belongs to the first page
+        let mut frame_iter = frames.into_iter();
 
         for (count, entry) in table.iter_mut().skip(start_index).enumerate() {
             if count >= alloc_count {
@@ -1,3 +1,4 @@
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::sync::atomic::AtomicUsize;
@@ -19,12 +20,13 @@ fn next_process_id() -> usize {
 
 pub struct ProcessManager {
     active_processes: Vec<Arc<Process>>,
-    exited_processes: Vec<Arc<Process>>
+    exited_processes: Vec<Arc<Process>>,
+    exit_hooks: Vec<fn(usize)>
 }
 
 impl ProcessManager {
     pub const fn new() -> Self {
-        Self { active_processes: Vec::new(), exited_processes: Vec::new() }
+        Self { active_processes: Vec::new(), exited_processes: Vec::new(), exit_hooks: Vec::new() }
     }
 
     pub fn create_process(&mut self) -> Arc<Process> {
@@ -52,6 +54,17 @@ impl ProcessManager {
         self.active_processes.iter().map(|process| process.id()).collect()
     }
 
+    pub fn active_process(&self, id: usize) -> Option<Arc<Process>> {
+        self.active_processes.iter().find(|process| process.id == id).map(Arc::clone)
+    }
+
+    // Lets a subsystem release the resources of exiting processes (e.g. audio streams), which processes don't release themselves.
+    // Hooks get called with the process id on the exiting thread, after the process has been removed from the active processes.
+    // Neither the process manager nor the scheduler is locked at that point (see Process::exit() and Scheduler::exit()).
+    pub fn register_exit_hook(&mut self, hook: fn(usize)) {
+        self.exit_hooks.push(hook);
+    }
+
     pub fn kernel_process(&self) -> Option<Arc<Process>> {
         match self.active_processes.get(0) {
             Some(kernel_process) => Some(Arc::clone(kernel_process)),
@@ -67,7 +80,8 @@ impl ProcessManager {
         }
     }
 
-    pub fn exit(&mut self, id: usize) {
+    // returns the exit hooks, which the caller has to run once it released the process manager
+    pub fn exit(&mut self, id: usize) -> Vec<fn(usize)> {
         let index = self.active_processes.iter()
             .position(|process| process.id == id)
             .expect("Process: Trying to exit a non-existent process!");
@@ -75,6 +89,8 @@ impl ProcessManager {
         let process = Arc::clone(&self.active_processes[index]);
         self.active_processes.swap_remove(index);
         self.exited_processes.push(process);
+
+        self.exit_hooks.clone()
     }
 
     pub fn drop_exited_process(&mut self) {
@@ -85,11 +101,13 @@ impl ProcessManager {
 pub struct Process {
     id: usize,
     address_space: Arc<AddressSpace>,
-    memory_areas: RwLock<Vec<VirtualMemoryArea>>
+    memory_areas: RwLock<Vec<VirtualMemoryArea>>,
+    arguments: RwLock<Vec<String>>
 }
 
 impl Drop for Process {
     fn drop(&mut self) {
+        self.release_device_vmas();
         for vma in self.memory_areas.read().iter() {
             self.address_space.unmap(vma.range(), true);
         }
@@ -98,7 +116,7 @@ impl Drop for Process {
 
 impl Process {
     fn new(address_space: Arc<AddressSpace>) -> Self {
-        Self { id: next_process_id(), address_space, memory_areas: RwLock::new(Vec::new()) }
+        Self { id: next_process_id(), address_space, memory_areas: RwLock::new(Vec::new()), arguments: RwLock::new(Vec::new()) }
     }
 
     pub fn id(&self) -> usize {
@@ -109,6 +127,14 @@ impl Process {
         Arc::clone(&self.address_space)
     }
 
+    pub fn arguments(&self) -> Vec<String> {
+        self.arguments.read().clone()
+    }
+
+    pub fn set_arguments(&self, arguments: Vec<String>) {
+        *self.arguments.write() = arguments;
+    }
+
     pub fn add_vma(&self, new_area: VirtualMemoryArea) {
         let mut areas = self.memory_areas.write();
         match areas.iter().find(|area| area.overlaps_with(&new_area)) {
@@ -133,7 +159,30 @@ impl Process {
         }
     }
 
+    // returns the removed VMA, or None if the process has no such VMA (e.g. because it already exited)
+    pub fn remove_vma(&self, vma: VirtualMemoryArea) -> Option<VirtualMemoryArea> {
+        let mut areas = self.memory_areas.write();
+        let index = areas.iter().position(|area| *area == vma)?;
+        Some(areas.remove(index))
+    }
+
+    // Device memory (e.g. registers or DMA buffers mapped by a driver) is still owned by its driver, so it only gets unmapped
+    // from the process, but not freed. This already happens when the process exits, as the driver may free the memory as soon
+    // as an exit hook told it about the exit, while the address space lives on until the process gets dropped.
+    fn release_device_vmas(&self) {
+        let mut areas = self.memory_areas.write();
+        for vma in areas.iter().filter(|area| area.typ() == VmaType::Device) {
+            self.address_space.unmap(vma.range(), false);
+        }
+        areas.retain(|area| area.typ() != VmaType::Device);
+    }
+
     pub fn exit(&self) {
-        process_manager().write().exit(self.id);
+        // the process manager is unlocked again before the hooks run, so that they may access it
+        let exit_hooks = process_manager().write().exit(self.id);
+        self.release_device_vmas();
+        for hook in exit_hooks {
+            hook(self.id);
+        }
     }
 }
\ No newline at end of file
@@ -19,12 +19,13 @@ pub fn next_thread_id() -> usize {
 struct ReadyState {
     initialized: bool,
     current_thread: Option<Rc<Thread>>,
-    ready_queue: VecDeque<Rc<Thread>>
+    ready_queue: VecDeque<Rc<Thread>>,
+    prioritized_threads: Vec<usize>
 }
 
 impl ReadyState {
     pub fn new() -> Self {
-        Self { initialized: false, current_thread: None, ready_queue: VecDeque::new() }
+        Self { initialized: false, current_thread: None, ready_queue: VecDeque::new(), prioritized_threads: Vec::new() }
     }
 }
 
@@ -77,10 +78,19 @@ impl Scheduler {
         let mut state = self.state.lock();
         let mut join_map = self.join_map.lock();
 
-        state.ready_queue.push_front(thread);
+        Scheduler::enqueue(&mut state, thread);
         join_map.insert(id, Vec::new());
     }
 
+    // Lets a thread run ahead of all other ready threads whenever it becomes ready, e.g. a thread which has to react to interrupts
+    // in time. Prioritized threads still get preempted like any other thread, so they can't starve the others if they run too long.
+    pub fn prioritize(&self, thread_id: usize) {
+        let mut state = self.state.lock();
+        if !state.prioritized_threads.contains(&thread_id) {
+            state.prioritized_threads.push(thread_id);
+        }
+    }
+
     pub fn sleep(&self, ms: usize) {
         let mut state = self.state.lock();
         let thread = Scheduler::current(&state);
@@ -94,6 +104,28 @@ impl Scheduler {
         self.block(&mut state);
     }
 
+    // Ends the sleep of a thread early, so that a prioritized thread runs at the next thread switch (see Scheduler::prioritize()).
+    // May be called from interrupt handlers, so it gives up instead of waiting if the scheduler is locked.
+    pub fn wake_up(&self, thread_id: usize) -> bool {
+        let mut state = match self.state.try_lock() {
+            Some(state) => state,
+            None => return false,
+        };
+        let mut sleep_list = match self.sleep_list.try_lock() {
+            Some(sleep_list) => sleep_list,
+            None => return false,
+        };
+
+        match sleep_list.iter().position(|entry| entry.0.id() == thread_id) {
+            Some(index) => {
+                let (thread, _) = sleep_list.remove(index);
+                Scheduler::enqueue(&mut state, thread);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn switch_thread(&self) {
         if let Some(mut state) = self.state.try_lock() {
             if !state.initialized {
@@ -119,7 +151,7 @@ impl Scheduler {
             let next_ptr = ptr::from_ref(next.as_ref());
 
             state.current_thread = Some(next);
-            state.ready_queue.push_front(current);
+            Scheduler::enqueue(&mut state, current);
 
             apic().end_of_interrupt();
             unsafe { Thread::switch(current_ptr, next_ptr); }
@@ -140,6 +172,13 @@ impl Scheduler {
     }
 
     pub fn exit(&self) {
+        { // Execute in own block, so that the process exits and runs its exit hooks before the scheduler gets locked
+            let current = self.current_thread();
+            if !current.is_kernel_thread() {
+                current.process().exit();
+            }
+        }
+
         let mut state = self.state.lock();
         let current = Scheduler::current(&state);
 
@@ -148,15 +187,13 @@ impl Scheduler {
             let join_list = join_map.get_mut(&current.id()).expect(format!("Scheduler: Missing join_map entry for thread id {}!", current.id()).as_str());
 
             for thread in join_list {
-                state.ready_queue.push_front(Rc::clone(thread));
+                Scheduler::enqueue(&mut state, Rc::clone(thread));
             }
 
             join_map.remove(&current.id());
         }
 
-        if !current.is_kernel_thread() {
-            current.process().exit();
-        }
+        state.prioritized_threads.retain(|id| *id != current.id());
 
         drop(current); // Decrease Rc manually, because block() does not return
         self.block(&mut state);
@@ -190,6 +227,16 @@ impl Scheduler {
         unsafe { Thread::switch(current_ptr, next_ptr); }
     }
 
+    // The next thread gets taken from the back of the ready queue, so prioritized threads get enqueued there and all other threads
+    // at the front.
+    fn enqueue(state: &mut ReadyState, thread: Rc<Thread>) {
+        if state.prioritized_threads.contains(&thread.id()) {
+            state.ready_queue.push_back(thread);
+        } else {
+            state.ready_queue.push_front(thread);
+        }
+    }
+
     fn current(state: &ReadyState) -> Rc<Thread> {
         return Rc::clone(state.current_thread.as_ref().expect("Scheduler: Trying to access current thread before initialization!"));
     }
@@ -200,7 +247,7 @@ impl Scheduler {
 
             sleep_list.retain(|entry| {
                 if time >= entry.1 {
-                    state.ready_queue.push_front(Rc::clone(&entry.0));
+                    Scheduler::enqueue(state, Rc::clone(&entry.0));
                     return false;
                 }
 
@@ -1,19 +1,42 @@
 use alloc::format;
 use alloc::rc::Rc;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use core::ptr;
 use core::ptr::slice_from_raw_parts;
 use core::str::from_utf8;
 use chrono::{Datelike, DateTime, TimeDelta, Timelike};
+use stream::OutputStream;
 use uefi::table::runtime::{Time, TimeParams};
-use x86_64::structures::paging::PageTableFlags;
-use crate::{efi_system_table, initrd, process_manager, scheduler, terminal, timer};
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::VirtAddr;
+use crate::{audio, audio_service, efi_system_table, initrd, process_manager, scheduler, serial_port, terminal, timer, INTEL_HD_AUDIO};
+use crate::audio::events::{AudioEvent, DeviceError};
+use crate::audio::service::AudioServiceError;
+use crate::audio::session::{SessionError, SessionHandle, SessionMode};
+use crate::audio::settings::EndpointId;
+use crate::device::ihda_api::{PlaybackError, RouteCapabilities, CONVERTER_SAMPLE_RATES};
+use crate::device::ihda_api::diagnostics::SpeakerTestSignal;
+use crate::device::ihda_api::mixer::{MixerControl, MixerError};
+use crate::audio::streams::{StreamOwner, StreamState};
 use crate::memory::{MemorySpace, PAGE_SIZE};
 use crate::memory::r#virtual::{VirtualMemoryArea, VmaType};
 use crate::process::thread::Thread;
 
 pub mod syscall_dispatcher;
 
+// user space address of the two read-only pages mapped by sys_audio_map_stream_clock
+const AUDIO_CLOCK_PAGES_START: u64 = 0x300000000000;
+// time sys_audio_write waits for the DMA engine to free up space in the ring of a session, before it tries again
+const AUDIO_WRITE_RETRY_INTERVAL_IN_MS: usize = 10;
+// fields written by sys_audio_capabilities for each route
+const AUDIO_ROUTE_CAPABILITY_FIELDS: usize = 7;
+// fields written by sys_audio_map_session
+const AUDIO_SESSION_MAPPING_FIELDS: usize = 7;
+// fields written by sys_audio_poll_event
+const AUDIO_EVENT_FIELDS: usize = 4;
+
 #[no_mangle]
 pub extern "C" fn sys_read() -> usize {
     let terminal = terminal();
@@ -74,11 +97,19 @@ pub extern "C" fn sys_thread_exit() {
 }
 
 #[no_mangle]
-pub extern "C" fn sys_application_start(name_buffer: *const u8, name_length: usize) -> usize {
-    let app_name = from_utf8(unsafe { slice_from_raw_parts(name_buffer, name_length).as_ref().unwrap() }).unwrap();
+pub extern "C" fn sys_application_start(command_buffer: *const u8, command_length: usize) -> usize {
+    // the first word of the command is the name of the application, all following words are passed to it as arguments
+    let command = from_utf8(unsafe { slice_from_raw_parts(command_buffer, command_length).as_ref().unwrap() }).unwrap();
+    let mut words = command.split_whitespace();
+    let app_name = match words.next() {
+        Some(app_name) => app_name,
+        None => return 0
+    };
+
     match initrd().entries().find(|entry| entry.filename().as_str() == app_name) {
         Some(app) => {
             let thread = Thread::new_user_thread(app.data());
+            thread.process().set_arguments(words.map(|word| word.to_string()).collect());
             scheduler().ready(Rc::clone(&thread));
             thread.id()
         }
@@ -86,6 +117,29 @@ pub extern "C" fn sys_application_start(name_buffer: *const u8, name_length: usi
     }
 }
 
+// Copies a string into the given user buffer (as far as it fits) and returns its full length,
+// so that the caller can retry with a larger buffer.
+fn copy_string_to_user(string: &str, buffer: *mut u8, buffer_length: usize) -> usize {
+    let length = string.len().min(buffer_length);
+    if length > 0 {
+        unsafe { buffer.copy_from(string.as_ptr(), length); }
+    }
+
+    return string.len();
+}
+
+// string in a user buffer, None if the buffer doesn't hold valid UTF-8
+fn string_from_user<'a>(buffer: *const u8, buffer_length: usize) -> Option<&'a str> {
+    from_utf8(unsafe { slice_from_raw_parts(buffer, buffer_length).as_ref()? }).ok()
+}
+
+// arguments of the current process, separated by spaces
+#[no_mangle]
+pub extern "C" fn sys_process_arguments(buffer: *mut u8, buffer_length: usize) -> usize {
+    let arguments = process_manager().read().current_process().arguments().join(" ");
+    copy_string_to_user(arguments.as_str(), buffer, buffer_length)
+}
+
 #[no_mangle]
 pub extern "C" fn sys_get_system_time() -> usize {
     timer().read().systime_ms()
@@ -153,4 +207,518 @@ pub extern "C" fn sys_set_date(date_ms: usize) -> usize {
     }
 
     return false as usize;
-}
\ No newline at end of file
+}
+
+// The endpoint is encoded as (codec_address << 8 | node_id) and usize::MAX selects the default line out endpoint.
+// The duration is capped at MAX_TEST_TONE_DURATION_IN_MS (see device/ihda_api/mod.rs).
+// Returns 0 on success and the code of the error otherwise (see PlaybackError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_play_test_tone(endpoint: usize, frequency: usize, duration_ms: usize) -> usize {
+    let endpoint = decode_endpoint(endpoint);
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    let result = match INTEL_HD_AUDIO.get() {
+        Some(device) => device.play_test_tone(owner, endpoint, frequency as u32, duration_ms),
+        None => Err(PlaybackError::NoAudioDevice),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Describes the format, channels and widget paths the speaker test plays on an endpoint, which is encoded like for
+// sys_audio_play_test_tone. Returns the full length of the report, which is empty if the endpoint can't be tested.
+#[no_mangle]
+pub extern "C" fn sys_audio_speaker_test_report(endpoint: usize, buffer: *mut u8, buffer_length: usize) -> usize {
+    let report = INTEL_HD_AUDIO.get()
+        .and_then(|device| device.speaker_test_report(decode_endpoint(endpoint)).ok())
+        .unwrap_or_default();
+    copy_string_to_user(report.as_str(), buffer, buffer_length)
+}
+
+// Plays the sine sweep of the speaker test if signal is 0 and the beeps identifying channel signal - 1 otherwise. The endpoint
+// is encoded like for sys_audio_play_test_tone. Returns 0 on success and the code of the error otherwise (see PlaybackError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_test_speakers(endpoint: usize, signal: usize) -> usize {
+    let signal = match signal {
+        0 => SpeakerTestSignal::Sweep,
+        channel => SpeakerTestSignal::Channel((channel - 1).min(u8::MAX as usize) as u8)
+    };
+
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    let result = match INTEL_HD_AUDIO.get() {
+        Some(device) => device.test_speakers(owner, decode_endpoint(endpoint), signal),
+        None => Err(PlaybackError::NoAudioDevice),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// level in percent, 0 turns the sidetone off (see fn IntelHDAudioDevice::set_sidetone_level)
+#[no_mangle]
+pub extern "C" fn sys_audio_set_sidetone_level(level_percent: usize) -> usize {
+    let result = match INTEL_HD_AUDIO.get() {
+        Some(device) => device.set_sidetone_level(level_percent.min(u8::MAX as usize) as u8),
+        None => Err(PlaybackError::NoAudioDevice),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Lets the audio service queue events for the current process, which it fetches with sys_audio_poll_event.
+// A process that doesn't subscribe doesn't receive any events.
+#[no_mangle]
+pub extern "C" fn sys_audio_subscribe_events(enable: usize) -> usize {
+    audio_service().set_event_subscription(process_manager().read().current_process().id(), enable != 0);
+    0
+}
+
+// Writes the oldest queued event of the current process as the fields <event code> <a> <b> <c> and returns true,
+// or returns false if there is none. The fields a, b and c depend on the event (see AudioEvent::code and audio library):
+// - OutputChanged, JackInserted, JackRemoved: a is the endpoint, where usize::MAX stands for no endpoint
+// - Underrun: a is the stream descriptor
+// - VolumeChanged: a is the endpoint, b the volume in percent and c is 1 if the endpoint is muted
+// - DeviceError: a is the code of the error (see DeviceError::code), b the stream descriptor or codec address and c is 1
+//   if the device recovered from the error
+#[no_mangle]
+pub extern "C" fn sys_audio_poll_event(event: *mut usize) -> usize {
+    let audio_event = match audio_service().poll_event(process_manager().read().current_process().id()) {
+        Some(audio_event) => audio_event,
+        None => return false as usize
+    };
+
+    let fields = unsafe { core::slice::from_raw_parts_mut(event, AUDIO_EVENT_FIELDS) };
+    fields.fill(0);
+    fields[0] = audio_event.code();
+    match audio_event {
+        AudioEvent::OutputChanged(endpoint) => fields[1] = endpoint.map_or(usize::MAX, encode_endpoint),
+        AudioEvent::JackInserted(endpoint) | AudioEvent::JackRemoved(endpoint) => fields[1] = encode_endpoint(endpoint),
+        AudioEvent::Underrun(stream_descriptor_number) => fields[1] = stream_descriptor_number as usize,
+        AudioEvent::VolumeChanged { endpoint, volume_percent, muted } => {
+            fields[1] = encode_endpoint(endpoint);
+            fields[2] = volume_percent as usize;
+            fields[3] = muted as usize;
+        }
+        AudioEvent::DeviceError(error) => {
+            fields[1] = error.code();
+            match error {
+                DeviceError::FifoError(stream_descriptor_number) | DeviceError::StreamStalled(stream_descriptor_number) => fields[2] = stream_descriptor_number as usize,
+                DeviceError::DescriptorError { stream_descriptor_number, recovered } => {
+                    fields[2] = stream_descriptor_number as usize;
+                    fields[3] = recovered as usize;
+                }
+                DeviceError::CodecRemoved(codec_address) => fields[2] = codec_address as usize,
+                DeviceError::CommandRingError { recovered } => fields[3] = recovered as usize,
+            }
+        }
+    }
+    true as usize
+}
+
+// endpoints are encoded as (codec_address << 8 | node_id), where usize::MAX selects the default endpoint
+fn decode_endpoint(endpoint: usize) -> Option<EndpointId> {
+    match endpoint {
+        usize::MAX => None,
+        endpoint => Some(EndpointId::new((endpoint >> 8) as u8, endpoint as u8))
+    }
+}
+
+fn encode_endpoint(endpoint: EndpointId) -> usize {
+    (*endpoint.codec_address() as usize) << 8 | *endpoint.node_id() as usize
+}
+
+// One line per stream with the space separated fields
+// <stream descriptor> <stream id> <owner> <endpoint> <sample rate> <bits per sample> <channels> <state> <buffer length> <fill level>
+// <FIFO size> <FIFO watermark> <minimum buffer length>, where owner is either "kernel:<subsystem>" or "process:<id>", endpoint is either
+// "<codec address>:<node id>" or "-" and the FIFO watermark is "-" if the controller doesn't report it (see audio library).
+#[no_mangle]
+pub extern "C" fn sys_audio_active_streams(buffer: *mut u8, buffer_length: usize) -> usize {
+    let mut streams = String::new();
+    for stream in audio::active_streams() {
+        let owner = match stream.owner() {
+            StreamOwner::Kernel(subsystem) => format!("kernel:{}", subsystem.replace(' ', "_")),
+            StreamOwner::Process(id) => format!("process:{}", id)
+        };
+        let endpoint = match stream.endpoint() {
+            Some(endpoint) => format!("{}:{}", endpoint.codec_address(), endpoint.node_id()),
+            None => "-".to_string()
+        };
+        let state = match stream.state() {
+            StreamState::Reset => "reset",
+            StreamState::Configured => "configured",
+            StreamState::Prepared => "prepared",
+            StreamState::Running => "running",
+            StreamState::Paused => "paused",
+            StreamState::Draining => "draining",
+            StreamState::Error => "error"
+        };
+
+        let (fifo_size, fifo_watermark, min_buffer_length) = match stream.fifo_tuning() {
+            Some(fifo_tuning) => (
+                fifo_tuning.fifo_size_in_bytes().to_string(),
+                fifo_tuning.watermark_in_bytes().map_or("-".to_string(), |watermark| watermark.to_string()),
+                fifo_tuning.min_buffer_length_in_bytes().to_string()
+            ),
+            None => ("0".to_string(), "-".to_string(), "0".to_string())
+        };
+
+        streams.push_str(format!("{} {} {} {} {} {} {} {} {} {} {} {} {}\n",
+            stream.stream_descriptor_number(), stream.stream_id(), owner, endpoint,
+            stream.format().sample_rate(), stream.format().bits_per_sample(), stream.format().channels(),
+            state, stream.buffer_length_in_bytes(), stream.fill_level_in_bytes(),
+            fifo_size, fifo_watermark, min_buffer_length).as_str());
+    }
+
+    copy_string_to_user(streams.as_str(), buffer, buffer_length)
+}
+
+// Maps the page with the wall clock alias and the page with the position of a stream read-only into the calling process
+// and writes the user space addresses of the wall clock and of the stream position into addresses[0] and addresses[1].
+// Returns false if there is no sound card or no stream on the given stream descriptor.
+#[no_mangle]
+pub extern "C" fn sys_audio_map_stream_clock(stream_descriptor_number: usize, addresses: *mut usize) -> usize {
+    let device = match INTEL_HD_AUDIO.get() {
+        Some(device) => device,
+        None => return false as usize,
+    };
+    let (wall_clock_address, position_address) = match device.stream_clock_addresses(stream_descriptor_number as u32) {
+        Some(addresses) => addresses,
+        None => return false as usize,
+    };
+
+    let process = process_manager().read().current_process();
+    let start_page = Page::from_start_address(VirtAddr::new(AUDIO_CLOCK_PAGES_START)).unwrap();
+    // mapped sessions add device areas as well, so the area of the clock pages is recognized by its mapping
+    let mapped_before = process.address_space().translate(start_page.start_address()).is_some();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_CACHE;
+    for (index, address) in [wall_clock_address, position_address].iter().enumerate() {
+        let frame = PhysFrame::containing_address(*address);
+        let page = start_page + index as u64;
+        // mapping again only replaces the frames, which is necessary if the position source of the controller changed
+        process.address_space().map_physical(PhysFrameRange { start: frame, end: frame + 1 }, PageRange { start: page, end: page + 1 }, MemorySpace::User, flags);
+    }
+    if !mapped_before {
+        process.add_vma(VirtualMemoryArea::new(PageRange { start: start_page, end: start_page + 2 }, VmaType::Device));
+    }
+
+    let addresses = unsafe { core::slice::from_raw_parts_mut(addresses, 2) };
+    addresses[0] = (AUDIO_CLOCK_PAGES_START + wall_clock_address.as_u64() % PAGE_SIZE as u64) as usize;
+    addresses[1] = (AUDIO_CLOCK_PAGES_START + PAGE_SIZE as u64 + position_address.as_u64() % PAGE_SIZE as u64) as usize;
+
+    true as usize
+}
+
+// Returns the index of the frame the DMA engine of a stream is at in its cyclic buffer and usize::MAX if there is no such stream.
+#[no_mangle]
+pub extern "C" fn sys_audio_stream_position(stream_descriptor_number: usize) -> usize {
+    match INTEL_HD_AUDIO.get().and_then(|device| device.stream_position_in_frames(stream_descriptor_number as u32)) {
+        Some(position) => position as usize,
+        None => usize::MAX,
+    }
+}
+
+// Opens a playback session for 16 bit PCM samples on the default output endpoint and writes its handle into handle.
+// Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_open(sample_rate: usize, number_of_channels: usize, handle: *mut usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    match audio::sessions().lock().open(owner, sample_rate as u32, number_of_channels as u8) {
+        Ok(session) => {
+            unsafe { handle.write(session.value()); }
+            0
+        }
+        Err(error) => error.code()
+    }
+}
+
+// Copies interleaved samples into the ring of a session and blocks until all of them fit in.
+// Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_write(handle: usize, samples: *const i16, sample_count: usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    let samples = unsafe { slice_from_raw_parts(samples, sample_count).as_ref().unwrap() };
+    let mut written = 0;
+    loop {
+        // the lock must not be held while sleeping, as the session table is shared by all processes
+        match audio::sessions().lock().write(owner, SessionHandle::new(handle), &samples[written..]) {
+            Ok(amount) => written += amount,
+            Err(error) => return error.code()
+        }
+        if written == samples.len() {
+            return 0;
+        }
+        scheduler().sleep(AUDIO_WRITE_RETRY_INTERVAL_IN_MS);
+    }
+}
+
+// The volume is given in percent and only applies to samples written afterwards.
+// Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_set_volume(handle: usize, volume_percent: usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    match audio::sessions().lock().set_volume(owner, SessionHandle::new(handle), volume_percent.min(u8::MAX as usize) as u8) {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Blocks until the samples left in the ring of a session have been played and closes the session afterwards.
+// Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_close(handle: usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    let remaining_ms = match audio::sessions().lock().drain(owner, SessionHandle::new(handle)) {
+        Ok(remaining_ms) => remaining_ms,
+        // the stream of a preempted session is gone already, but its handle still has to be closed
+        Err(SessionError::Preempted(_)) => 0,
+        Err(error) => return error.code()
+    };
+    if remaining_ms > 0 {
+        scheduler().sleep(remaining_ms);
+    }
+
+    match audio::sessions().lock().close(owner, SessionHandle::new(handle)) {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Maps the ring of a session into the calling process, which writes its samples directly from then on (see audio::session).
+// Writes the addresses of the control page and the ring, the amount of buffers in the ring, the length of a buffer in bytes,
+// the distance between the starts of two buffers in bytes and the addresses of the wall clock and the position of the stream
+// into mapping[0] to mapping[6]. Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_map_session(handle: usize, mapping: *mut usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    let session_mapping = match audio::sessions().lock().map(owner, SessionHandle::new(handle)) {
+        Ok(session_mapping) => session_mapping,
+        Err(error) => return error.code()
+    };
+
+    let mapping = unsafe { core::slice::from_raw_parts_mut(mapping, AUDIO_SESSION_MAPPING_FIELDS) };
+    mapping[0] = session_mapping.control_address().as_u64() as usize;
+    mapping[1] = session_mapping.ring_address().as_u64() as usize;
+    mapping[2] = *session_mapping.ring().buffer_amount();
+    mapping[3] = *session_mapping.ring().buffer_length_in_bytes() as usize;
+    mapping[4] = *session_mapping.ring().buffer_stride_in_bytes() as usize;
+    mapping[5] = session_mapping.wall_clock_address().as_u64() as usize;
+    mapping[6] = session_mapping.position_address().as_u64() as usize;
+
+    0
+}
+
+// Starts the stream of a session, which is needed for mapped sessions, as their ring never gets full through sys_audio_write.
+// Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_start(handle: usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    match audio::sessions().lock().drain(owner, SessionHandle::new(handle)) {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Debug dumps of the sound card for the ihda application, where kind 0 selects the controller and stream descriptor registers,
+// kind 1 the codec graph, kind 2 the configured playback paths and kind 3 the codec graph as JSON. Kind 4 is the same as kind 3,
+// but the JSON also gets written to the serial port, so that it can be captured on another machine. Kind 5 selects the error
+// statistics of the controller and kind 6 the audio settings which get restored at boot (see audio::persistence), where kind 7
+// also writes them to the serial port, so that the host can save them. Kind 8 compares the widgets on the playback paths with
+// what the driver configured (see IntelHDAudioDevice::validate_paths). Returns the full length of the dump, which is empty if
+// there is no sound card or the kind is unknown.
+#[no_mangle]
+pub extern "C" fn sys_audio_dump(kind: usize, buffer: *mut u8, buffer_length: usize) -> usize {
+    let dump = match (INTEL_HD_AUDIO.get(), kind) {
+        (Some(device), 0) => device.dump_registers(),
+        (Some(device), 1) => device.dump_codecs(),
+        (Some(device), 2) => device.dump_playback_paths(),
+        (Some(device), 3) => device.export_codec_graph(),
+        (Some(device), 4) => {
+            let export = device.export_codec_graph();
+            if let Some(serial) = serial_port() {
+                serial.write_str(&export);
+            }
+            export
+        }
+        (Some(device), 5) => device.dump_error_statistics(),
+        (Some(device), 6) => device.persisted_settings().serialize(),
+        (Some(device), 7) => {
+            let settings = device.persisted_settings().serialize();
+            if let Some(serial) = serial_port() {
+                serial.write_str(&settings);
+            }
+            settings
+        }
+        (Some(device), 8) => device.dump_path_validation(),
+        (Some(device), 9) => device.dump_mixer(),
+        _ => String::new()
+    };
+    copy_string_to_user(dump.as_str(), buffer, buffer_length)
+}
+
+// Streams a WAVE file from the initial ramdisk through the audio service, replacing its current playback.
+// Returns 0 on success and the code of the error otherwise (see AudioServiceError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_play_file(name_buffer: *const u8, name_length: usize) -> usize {
+    let name = match string_from_user(name_buffer, name_length) {
+        Some(name) => name,
+        None => return AudioServiceError::InvalidFileName.code()
+    };
+    match audio_service().play_file(name) {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Writes the played frames, the total frames, the sample rate and whether the playback is paused (0 or 1) of the
+// audio service into progress[0] to progress[3]. Returns false if the audio service isn't streaming.
+#[no_mangle]
+pub extern "C" fn sys_audio_playback_progress(progress: *mut usize) -> usize {
+    let playback_progress = match audio_service().progress() {
+        Some(playback_progress) => playback_progress,
+        None => return false as usize
+    };
+
+    let progress = unsafe { core::slice::from_raw_parts_mut(progress, 4) };
+    progress[0] = *playback_progress.played_frames();
+    progress[1] = *playback_progress.total_frames();
+    progress[2] = *playback_progress.sample_rate() as usize;
+    progress[3] = *playback_progress.paused() as usize;
+    true as usize
+}
+
+// Returns 0 on success and the code of the error otherwise (see AudioServiceError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_pause_playback() -> usize {
+    match audio_service().pause() {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Returns 0 on success and the code of the error otherwise (see AudioServiceError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_resume_playback() -> usize {
+    match audio_service().resume() {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Stops and closes the playback of the audio service, does nothing if there is none.
+#[no_mangle]
+pub extern "C" fn sys_audio_stop_playback() {
+    audio_service().stop();
+}
+
+// Records the given duration from the default input endpoint into a WAVE file, which can be played with sys_audio_play_file.
+// Returns 0 on success and the code of the error otherwise (see AudioServiceError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_record_file(name_buffer: *const u8, name_length: usize, duration_ms: usize) -> usize {
+    let name = match string_from_user(name_buffer, name_length) {
+        Some(name) => name,
+        None => return AudioServiceError::InvalidFileName.code()
+    };
+    match audio_service().record_file(name, duration_ms) {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Writes the capabilities of the default output route into capabilities[0] to capabilities[6] and the ones of the default input route
+// into capabilities[7] to capabilities[13]. The fields of a route are whether it exists (0 or 1), its endpoint (codec_address << 8 | node_id),
+// the supported sample rates as a bitmask in the order of CONVERTER_SAMPLE_RATES, the supported bit depths as a bitmask with bit n
+// for n bits per sample, the maximum amount of channels and the minimum and maximum latency in ms.
+// Returns false if there is no sound card.
+#[no_mangle]
+pub extern "C" fn sys_audio_capabilities(capabilities: *mut usize) -> usize {
+    let device_capabilities = match audio_service().capabilities() {
+        Ok(device_capabilities) => device_capabilities,
+        Err(_) => return false as usize
+    };
+
+    let capabilities = unsafe { core::slice::from_raw_parts_mut(capabilities, 2 * AUDIO_ROUTE_CAPABILITY_FIELDS) };
+    let (output, input) = capabilities.split_at_mut(AUDIO_ROUTE_CAPABILITY_FIELDS);
+    write_route_capabilities(device_capabilities.output().as_ref(), output);
+    write_route_capabilities(device_capabilities.input().as_ref(), input);
+    true as usize
+}
+
+fn write_route_capabilities(route: Option<&RouteCapabilities>, fields: &mut [usize]) {
+    fields.fill(0);
+    let route = match route {
+        Some(route) => route,
+        None => return
+    };
+
+    fields[0] = true as usize;
+    fields[1] = (*route.endpoint().codec_address() as usize) << 8 | *route.endpoint().node_id() as usize;
+    fields[2] = CONVERTER_SAMPLE_RATES.iter().enumerate()
+        .filter(|(_, sample_rate)| route.sample_rates().contains(*sample_rate))
+        .fold(0usize, |mask, (index, _)| mask | 1 << index);
+    fields[3] = route.bits_per_sample().iter().fold(0usize, |mask, bit_depth| mask | 1 << *bit_depth);
+    fields[4] = *route.max_channels() as usize;
+    fields[5] = *route.min_latency_in_ms() as usize;
+    fields[6] = *route.max_latency_in_ms() as usize;
+}
+
+// Makes a session exclusive (exclusive != 0), so that other processes can't play while it exists, or shared again.
+// Whether system sounds of the kernel interrupt an exclusive session depends on the preemption policy (see audio::session).
+// Returns 0 on success and the code of the error otherwise (see SessionError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_set_session_mode(handle: usize, exclusive: usize) -> usize {
+    let owner = StreamOwner::Process(process_manager().read().current_process().id());
+    let mode = match exclusive {
+        0 => SessionMode::Shared,
+        _ => SessionMode::Exclusive,
+    };
+    match audio::sessions().lock().set_mode(owner, SessionHandle::new(handle), mode) {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Sets a control of a single widget, which is encoded like an endpoint (codec_address << 8 | node_id): the gain in percent
+// (control 0), mute (control 1, value != 0 mutes) or the route from the endpoint to the converter with the node id given
+// by value (control 2). Returns 0 on success and the code of the error otherwise (see MixerError::code).
+#[no_mangle]
+pub extern "C" fn sys_audio_mixer_control(widget: usize, control: usize, value: usize) -> usize {
+    let (codec_address, node_id) = ((widget >> 8) as u8, widget as u8);
+    let result = match (INTEL_HD_AUDIO.get(), control) {
+        (Some(device), 0) => device.set_widget_control(codec_address, node_id, MixerControl::Gain(value.min(u8::MAX as usize) as u8)),
+        (Some(device), 1) => device.set_widget_control(codec_address, node_id, MixerControl::Mute(value != 0)),
+        (Some(device), 2) => device.route_endpoint_to_converter(EndpointId::new(codec_address, node_id), value as u8),
+        (Some(_), control) => Err(MixerError::UnknownControl(control)),
+        (None, _) => Err(MixerError::NoAudioDevice),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(error) => error.code()
+    }
+}
+
+// Turns the verb trace of the sound card on (enabled != 0) or off. Turning it on drops the entries of the previous trace.
+// Returns false if there is no sound card.
+#[no_mangle]
+pub extern "C" fn sys_audio_set_verb_trace(enabled: usize) -> usize {
+    match INTEL_HD_AUDIO.get() {
+        Some(device) => {
+            device.set_verb_trace_enabled(enabled != 0);
+            true as usize
+        }
+        None => false as usize
+    }
+}
+
+// Copies up to the given amount of the most recent verbs and responses of the sound card into the buffer (see
+// fn IntelHDAudioDevice::dump_verb_trace). Returns the full length of the trace, which is empty if there is no sound card.
+#[no_mangle]
+pub extern "C" fn sys_audio_verb_trace(amount: usize, buffer: *mut u8, buffer_length: usize) -> usize {
+    let trace = INTEL_HD_AUDIO.get()
+        .map(|device| device.dump_verb_trace(amount))
+        .unwrap_or_default();
+    copy_string_to_user(trace.as_str(), buffer, buffer_length)
+}
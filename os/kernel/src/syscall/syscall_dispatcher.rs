@@ -4,7 +4,7 @@ use x86_64::registers::model_specific::{LStar, Star};
 use x86_64::structures::gdt::SegmentSelector;
 use x86_64::{PrivilegeLevel, VirtAddr};
 use syscall::NUM_SYSCALLS;
-use crate::syscall::{sys_write, sys_thread_exit, sys_thread_sleep, sys_thread_switch, sys_process_id, sys_thread_id, sys_read, sys_map_user_heap, sys_thread_join, sys_application_start, sys_get_system_time, sys_get_date, sys_set_date};
+use crate::syscall::{sys_write, sys_thread_exit, sys_thread_sleep, sys_thread_switch, sys_process_id, sys_thread_id, sys_read, sys_map_user_heap, sys_thread_join, sys_application_start, sys_get_system_time, sys_get_date, sys_set_date, sys_process_arguments, sys_audio_play_test_tone, sys_audio_active_streams, sys_audio_map_stream_clock, sys_audio_stream_position, sys_audio_open, sys_audio_write, sys_audio_set_volume, sys_audio_close, sys_audio_dump, sys_audio_play_file, sys_audio_playback_progress, sys_audio_pause_playback, sys_audio_resume_playback, sys_audio_stop_playback, sys_audio_record_file, sys_audio_capabilities, sys_audio_map_session, sys_audio_start, sys_audio_speaker_test_report, sys_audio_test_speakers, sys_audio_set_sidetone_level, sys_audio_subscribe_events, sys_audio_poll_event, sys_audio_set_session_mode, sys_audio_mixer_control, sys_audio_set_verb_trace, sys_audio_verb_trace};
 
 
 pub fn init() {
@@ -53,7 +53,35 @@ impl SyscallTable {
                 sys_application_start as *const _,
                 sys_get_system_time as *const _,
                 sys_get_date as *const _,
-                sys_set_date as *const _
+                sys_set_date as *const _,
+                sys_process_arguments as *const _,
+                sys_audio_play_test_tone as *const _,
+                sys_audio_active_streams as *const _,
+                sys_audio_map_stream_clock as *const _,
+                sys_audio_stream_position as *const _,
+                sys_audio_open as *const _,
+                sys_audio_write as *const _,
+                sys_audio_set_volume as *const _,
+                sys_audio_close as *const _,
+                sys_audio_dump as *const _,
+                sys_audio_play_file as *const _,
+                sys_audio_playback_progress as *const _,
+                sys_audio_pause_playback as *const _,
+                sys_audio_resume_playback as *const _,
+                sys_audio_stop_playback as *const _,
+                sys_audio_record_file as *const _,
+                sys_audio_capabilities as *const _,
+                sys_audio_map_session as *const _,
+                sys_audio_start as *const _,
+                sys_audio_speaker_test_report as *const _,
+                sys_audio_test_speakers as *const _,
+                sys_audio_set_sidetone_level as *const _,
+                sys_audio_subscribe_events as *const _,
+                sys_audio_poll_event as *const _,
+                sys_audio_set_session_mode as *const _,
+                sys_audio_mixer_control as *const _,
+                sys_audio_set_verb_trace as *const _,
+                sys_audio_verb_trace as *const _
             ],
         }
     }
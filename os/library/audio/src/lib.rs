@@ -0,0 +1,811 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::str::from_utf8;
+use syscall::{syscall0, syscall1, syscall2, syscall3, SystemCall};
+
+// selects the default line out endpoint in the kernel
+const NO_ENDPOINT: usize = usize::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Endpoint {
+    codec_address: u8,
+    node_id: u8,
+}
+
+impl Endpoint {
+    pub const fn new(codec_address: u8, node_id: u8) -> Self {
+        Self { codec_address, node_id }
+    }
+
+    pub fn codec_address(&self) -> u8 {
+        self.codec_address
+    }
+
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    fn as_usize(&self) -> usize {
+        (self.codec_address as usize) << 8 | self.node_id as usize
+    }
+}
+
+// the numbering must match TestToneError::code() in the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestToneError {
+    NoDefaultEndpoint,
+    UnknownEndpoint,
+    NotAnOutputEndpoint,
+    NoConverterOnPath,
+    UnsupportedFormat,
+    NotAnInputEndpoint,
+    // the sound card reported an error while setting up the stream
+    DeviceError,
+    NoAudioDevice,
+    // the speaker test of the endpoint plays fewer channels
+    InvalidChannel,
+    // the codec can't mix the default input endpoint into the default output endpoint
+    NoSidetonePath,
+    // the sound card stopped the stream while the test was still playing
+    StreamStopped,
+    Unknown(usize),
+}
+
+impl TestToneError {
+    fn from_code(code: usize) -> Self {
+        match code {
+            1 => TestToneError::NoDefaultEndpoint,
+            2 => TestToneError::UnknownEndpoint,
+            3 => TestToneError::NotAnOutputEndpoint,
+            4 => TestToneError::NoConverterOnPath,
+            5 => TestToneError::UnsupportedFormat,
+            6 => TestToneError::NotAnInputEndpoint,
+            7 => TestToneError::DeviceError,
+            8 => TestToneError::NoAudioDevice,
+            15 => TestToneError::InvalidChannel,
+            16 => TestToneError::NoSidetonePath,
+            17 => TestToneError::StreamStopped,
+            code => TestToneError::Unknown(code),
+        }
+    }
+}
+
+// blocks until the tone has been played, which the kernel cuts off after 10 seconds
+pub fn play_test_tone(endpoint: Option<Endpoint>, frequency: u32, duration_ms: usize) -> Result<(), TestToneError> {
+    let endpoint = match endpoint {
+        Some(endpoint) => endpoint.as_usize(),
+        None => NO_ENDPOINT,
+    };
+
+    match syscall3(SystemCall::AudioPlayTestTone, endpoint, frequency as usize, duration_ms) {
+        0 => Ok(()),
+        code => Err(TestToneError::from_code(code)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeakerTestSignal {
+    // sine sweep from 100 Hz to 10 kHz on all channels, which takes about 3 seconds
+    Sweep,
+    // beeps on a single channel, once for channel 0, twice for channel 1 and so on
+    Channel(u8),
+}
+
+// The format, the channels of each endpoint and the widget paths the speaker test uses, or None if the endpoint can't be tested.
+// Without an endpoint, the default line out endpoint is used.
+pub fn speaker_test_report(endpoint: Option<Endpoint>) -> Option<String> {
+    let endpoint = endpoint.map_or(NO_ENDPOINT, |endpoint| endpoint.as_usize());
+    let report = read_string_with(|buffer, length| syscall3(SystemCall::AudioSpeakerTestReport, endpoint, buffer as usize, length));
+    if report.is_empty() { None } else { Some(report) }
+}
+
+// blocks until the signal has been played
+pub fn test_speakers(endpoint: Option<Endpoint>, signal: SpeakerTestSignal) -> Result<(), TestToneError> {
+    let endpoint = endpoint.map_or(NO_ENDPOINT, |endpoint| endpoint.as_usize());
+    let signal = match signal {
+        SpeakerTestSignal::Sweep => 0,
+        SpeakerTestSignal::Channel(channel) => channel as usize + 1,
+    };
+
+    match syscall2(SystemCall::AudioTestSpeakers, endpoint, signal) {
+        0 => Ok(()),
+        code => Err(TestToneError::from_code(code)),
+    }
+}
+
+// Lets the codec mix the input endpoint which is being recorded into the default output endpoint, so that the user hears
+// themselves without delay. The level is given in percent, 0 turns the sidetone off. It applies to the running recording
+// and all following ones.
+pub fn set_sidetone_level(level_percent: u8) -> Result<(), TestToneError> {
+    match syscall1(SystemCall::AudioSetSidetoneLevel, level_percent as usize) {
+        0 => Ok(()),
+        code => Err(TestToneError::from_code(code)),
+    }
+}
+
+// the numbering must match SessionError::code() in the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionError {
+    NoAudioDevice,
+    // only 16 bit PCM with at least one channel and a rate derivable from 48 kHz or 44.1 kHz can be played
+    UnsupportedFormat,
+    // all stream descriptors of the sound card are in use
+    NoFreeStreamDescriptor,
+    UnknownSession,
+    // the amount of samples written at once must be a multiple of the amount of channels
+    IncompleteFrame,
+    // the sound card reported an error while setting up the stream
+    PlaybackError,
+    // the ring can only be mapped once and before the session has been started
+    NotMappable,
+    // the ring of the session is mapped, so the samples have to be written into it directly
+    Mapped,
+    // the write position in the control page of a mapped ring lies outside of the ring
+    InvalidWritePosition,
+    // another process holds an exclusive session, or the session can't become exclusive as other processes play as well
+    OutputBusy,
+    // the exclusive session got closed by the kernel to play a system sound, so it only can be closed anymore
+    Preempted,
+    Unknown(usize),
+}
+
+impl SessionError {
+    fn from_code(code: usize) -> Self {
+        match code {
+            1 => SessionError::NoAudioDevice,
+            2 => SessionError::UnsupportedFormat,
+            3 => SessionError::NoFreeStreamDescriptor,
+            4 => SessionError::UnknownSession,
+            5 => SessionError::IncompleteFrame,
+            6 => SessionError::PlaybackError,
+            7 => SessionError::NotMappable,
+            8 => SessionError::Mapped,
+            9 => SessionError::InvalidWritePosition,
+            10 => SessionError::OutputBusy,
+            11 => SessionError::Preempted,
+            code => SessionError::Unknown(code),
+        }
+    }
+}
+
+fn session_result(code: usize) -> Result<(), SessionError> {
+    match code {
+        0 => Ok(()),
+        code => Err(SessionError::from_code(code)),
+    }
+}
+
+// Playback of interleaved 16 bit samples on the default output endpoint. The kernel copies the samples into a ring buffer,
+// which starts playing as soon as it is full for the first time, so a session has to be written to continuously.
+pub struct Session {
+    handle: usize,
+    number_of_channels: u8,
+}
+
+impl Session {
+    pub fn open(sample_rate: u32, number_of_channels: u8) -> Result<Self, SessionError> {
+        let mut handle = 0usize;
+        session_result(syscall3(SystemCall::AudioOpen, sample_rate as usize, number_of_channels as usize, &mut handle as *mut usize as usize))?;
+        Ok(Self { handle, number_of_channels })
+    }
+
+    // Maps the ring buffer of the session into the process, which then writes its samples into it directly instead of through
+    // fn write. Must be called before the session plays anything, and fn start has to be called once the ring has been filled.
+    pub fn map(&self) -> Result<MappedRing, SessionError> {
+        let mut mapping = [0usize; 7];
+        session_result(syscall2(SystemCall::AudioMapSession, self.handle, mapping.as_mut_ptr() as usize))?;
+        Ok(MappedRing {
+            control: mapping[0] as *mut u32,
+            ring: mapping[1] as *mut u8,
+            buffer_amount: mapping[2],
+            buffer_length_in_bytes: mapping[3],
+            buffer_stride_in_bytes: mapping[4],
+            frame_size_in_bytes: self.number_of_channels as usize * size_of::<i16>(),
+            clock: StreamClock { wall_clock: mapping[5] as *const u32, position: mapping[6] as *const u32 },
+        })
+    }
+
+    // starts playing the samples written so far, which a mapped ring needs, as it never gets full through fn write
+    pub fn start(&self) -> Result<(), SessionError> {
+        session_result(syscall1(SystemCall::AudioStartSession, self.handle))
+    }
+
+    // blocks until all samples are in the ring buffer of the session
+    pub fn write(&self, samples: &[i16]) -> Result<(), SessionError> {
+        session_result(syscall3(SystemCall::AudioWrite, self.handle, samples.as_ptr() as usize, samples.len()))
+    }
+
+    // only applies to samples written afterwards
+    pub fn set_volume(&self, volume_percent: u8) -> Result<(), SessionError> {
+        session_result(syscall2(SystemCall::AudioSetVolume, self.handle, volume_percent as usize))
+    }
+
+    // An exclusive session keeps other processes from playing until it gets closed or shared again. Fails with
+    // SessionError::OutputBusy if other processes have sessions.
+    pub fn set_exclusive(&self, exclusive: bool) -> Result<(), SessionError> {
+        session_result(syscall2(SystemCall::AudioSetSessionMode, self.handle, exclusive as usize))
+    }
+
+    // blocks until the samples left in the ring buffer have been played
+    pub fn close(self) -> Result<(), SessionError> {
+        session_result(syscall1(SystemCall::AudioClose, self.handle))
+    }
+}
+
+// Ring buffer of a session mapped into the process (see Session::map), into which an audio engine writes interleaved 16 bit
+// samples directly. The frames from the position of the DMA engine up to the write position are still to be played, so only
+// the frames behind the write position can be written. The kernel adopts the write position on every buffer completion and
+// publishes its read position and the amount of completed buffers in the control page, whose u32 fields must match the
+// order in audio::session of the kernel. The ring is unmapped when the session gets closed.
+pub struct MappedRing {
+    control: *mut u32,
+    ring: *mut u8,
+    buffer_amount: usize,
+    buffer_length_in_bytes: usize,
+    // the buffers start on pages of their own, so they may lie further apart than their length
+    buffer_stride_in_bytes: usize,
+    frame_size_in_bytes: usize,
+    clock: StreamClock,
+}
+
+impl MappedRing {
+    const WRITE_POSITION: usize = 0;
+    const READ_POSITION: usize = 1;
+    const BUFFER_COMPLETIONS: usize = 2;
+
+    pub fn length_in_frames(&self) -> u32 {
+        (self.buffer_amount * self.buffer_length_in_bytes / self.frame_size_in_bytes) as u32
+    }
+
+    // position in frames behind the last frame written
+    pub fn write_position(&self) -> u32 {
+        unsafe { self.control.add(Self::WRITE_POSITION).read_volatile() }
+    }
+
+    // position of the DMA engine in frames at the last buffer completion
+    pub fn read_position(&self) -> u32 {
+        unsafe { self.control.add(Self::READ_POSITION).read_volatile() }
+    }
+
+    // buffers completed since the ring has been mapped, wraps around at u32::MAX
+    pub fn buffer_completions(&self) -> u32 {
+        unsafe { self.control.add(Self::BUFFER_COMPLETIONS).read_volatile() }
+    }
+
+    // current position of the DMA engine in frames, read from the position of the stream instead of the control page
+    pub fn hardware_position(&self) -> u32 {
+        self.clock.position_in_bytes() / self.frame_size_in_bytes as u32 % self.length_in_frames()
+    }
+
+    // Frames which can be written right now. One frame always stays free, so that a full ring can be told apart from an empty one.
+    pub fn free_frames(&self) -> u32 {
+        let length_in_frames = self.length_in_frames();
+        (self.hardware_position() + length_in_frames - self.write_position() - 1) % length_in_frames
+    }
+
+    // Copies as many whole frames as are free behind the write position into the ring, advances the write position and
+    // returns the amount of samples copied.
+    pub fn write(&self, samples: &[i16]) -> usize {
+        let samples_per_frame = self.frame_size_in_bytes / size_of::<i16>();
+        let frames = (samples.len() / samples_per_frame).min(self.free_frames() as usize);
+        let ring_length_in_bytes = self.buffer_amount * self.buffer_length_in_bytes;
+        let start_in_bytes = self.write_position() as usize * self.frame_size_in_bytes;
+
+        for (index, sample) in samples[..frames * samples_per_frame].iter().enumerate() {
+            let position = (start_in_bytes + index * size_of::<i16>()) % ring_length_in_bytes;
+            let offset = position / self.buffer_length_in_bytes * self.buffer_stride_in_bytes + position % self.buffer_length_in_bytes;
+            unsafe { (self.ring.add(offset) as *mut i16).write_volatile(*sample); }
+        }
+
+        let write_position = (self.write_position() + frames as u32) % self.length_in_frames();
+        unsafe { self.control.add(Self::WRITE_POSITION).write_volatile(write_position); }
+        frames * samples_per_frame
+    }
+
+    // the wall clock and the position of the stream of the ring
+    pub fn clock(&self) -> &StreamClock {
+        &self.clock
+    }
+}
+
+// the numbering must match AudioServiceError::code() in the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackError {
+    NoAudioDevice,
+    // the audio service only plays 16 bit samples
+    UnsupportedFormat,
+    NoSamples,
+    // the rate the codec runs at can't be expressed as a stream format
+    UnsupportedSampleRate,
+    NotPlaying,
+    // the sound card reported an error while setting up the stream
+    DeviceError,
+    // there is no file with the given name in the initial ramdisk or among the recordings
+    FileNotFound,
+    // the file is not a valid WAVE file
+    InvalidWaveFile,
+    // recordings can't replace a file of the initial ramdisk or an earlier recording
+    FileExists,
+    // the kernel has no space left for a recording of this length
+    RecordingTooLong,
+    // the kernel has no decoder for the encoding of the file (only PCM with 8, 16, 24 or 32 bit samples and IMA ADPCM are built in)
+    UnsupportedEncoding,
+    // another process holds an exclusive session
+    OutputBusy,
+    // the file name isn't valid UTF-8
+    InvalidFileName,
+    Unknown(usize),
+}
+
+impl PlaybackError {
+    fn from_code(code: usize) -> Self {
+        match code {
+            1 => PlaybackError::NoAudioDevice,
+            2 => PlaybackError::UnsupportedFormat,
+            3 => PlaybackError::NoSamples,
+            4 => PlaybackError::UnsupportedSampleRate,
+            6 => PlaybackError::NotPlaying,
+            7 => PlaybackError::DeviceError,
+            9 => PlaybackError::FileNotFound,
+            10 => PlaybackError::InvalidWaveFile,
+            11 => PlaybackError::FileExists,
+            12 => PlaybackError::RecordingTooLong,
+            13 => PlaybackError::UnsupportedEncoding,
+            14 => PlaybackError::OutputBusy,
+            15 => PlaybackError::InvalidFileName,
+            code => PlaybackError::Unknown(code),
+        }
+    }
+}
+
+fn playback_result(code: usize) -> Result<(), PlaybackError> {
+    match code {
+        0 => Ok(()),
+        code => Err(PlaybackError::from_code(code)),
+    }
+}
+
+// Streams a WAVE file from the initial ramdisk through the audio service of the kernel on the default output endpoint.
+// Returns as soon as the playback has started and replaces any playback of the audio service that is already running.
+pub fn play_file(name: &str) -> Result<(), PlaybackError> {
+    playback_result(syscall2(SystemCall::AudioPlayFile, name.as_ptr() as usize, name.len()))
+}
+
+// Records from the default input endpoint into a 16 bit WAVE file, which the kernel keeps in memory until it shuts down,
+// as there is no writable file system. Blocks until the recording is done and may record a little longer than requested,
+// as the kernel stops on a buffer boundary. The file can be played with fn play_file afterwards.
+pub fn record_file(name: &str, duration_ms: usize) -> Result<(), PlaybackError> {
+    playback_result(syscall3(SystemCall::AudioRecordFile, name.as_ptr() as usize, name.len(), duration_ms))
+}
+
+// The DMA engine keeps its position, so fn resume_playback continues exactly where the playback stopped.
+pub fn pause_playback() -> Result<(), PlaybackError> {
+    playback_result(syscall0(SystemCall::AudioPausePlayback))
+}
+
+pub fn resume_playback() -> Result<(), PlaybackError> {
+    playback_result(syscall0(SystemCall::AudioResumePlayback))
+}
+
+// does nothing if there is no playback
+pub fn stop_playback() {
+    syscall0(SystemCall::AudioStopPlayback);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaybackProgress {
+    pub played_frames: usize,
+    pub total_frames: usize,
+    pub sample_rate: u32,
+    pub paused: bool,
+}
+
+impl PlaybackProgress {
+    pub fn elapsed_ms(&self) -> usize {
+        (self.played_frames as u64 * 1000 / self.sample_rate as u64) as usize
+    }
+
+    pub fn total_ms(&self) -> usize {
+        (self.total_frames as u64 * 1000 / self.sample_rate as u64) as usize
+    }
+
+    // the audio service keeps its stream running with silence after the last frame
+    pub fn is_finished(&self) -> bool {
+        self.played_frames >= self.total_frames
+    }
+}
+
+// None if the audio service isn't streaming (e.g. because the playback got stopped)
+pub fn playback_progress() -> Option<PlaybackProgress> {
+    let mut progress = [0usize; 4];
+    match syscall1(SystemCall::AudioPlaybackProgress, progress.as_mut_ptr() as usize) {
+        0 => None,
+        _ => Some(PlaybackProgress { played_frames: progress[0], total_frames: progress[1], sample_rate: progress[2] as u32, paused: progress[3] != 0 }),
+    }
+}
+
+// the order must match CONVERTER_SAMPLE_RATES in the kernel
+const SAMPLE_RATES: [u32; 12] = [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000, 384000];
+const ROUTE_CAPABILITY_FIELDS: usize = 7;
+
+// Formats a route of the sound card accepts. Every channel count from 1 to max_channels can be used. The latencies are the
+// shortest and longest buffers the kernel can set up for a 16 bit stream on the route.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteCapabilities {
+    pub endpoint: Endpoint,
+    pub sample_rates: Vec<u32>,
+    pub bits_per_sample: Vec<u8>,
+    pub max_channels: u8,
+    pub min_latency_ms: u32,
+    pub max_latency_ms: u32,
+}
+
+impl RouteCapabilities {
+    // parses the fields described at sys_audio_capabilities in the kernel
+    fn parse(fields: &[usize]) -> Option<Self> {
+        if fields[0] == 0 {
+            return None;
+        }
+        Some(Self {
+            endpoint: Endpoint::new((fields[1] >> 8) as u8, fields[1] as u8),
+            sample_rates: SAMPLE_RATES.iter().enumerate().filter(|(index, _)| fields[2] & (1 << index) != 0).map(|(_, sample_rate)| *sample_rate).collect(),
+            bits_per_sample: (0..usize::BITS as u8).filter(|bit_depth| fields[3] & (1 << bit_depth) != 0).collect(),
+            max_channels: fields[4] as u8,
+            min_latency_ms: fields[5] as u32,
+            max_latency_ms: fields[6] as u32,
+        })
+    }
+
+    pub fn supports(&self, sample_rate: u32, bits_per_sample: u8, channels: u8) -> bool {
+        self.sample_rates.contains(&sample_rate) && self.bits_per_sample.contains(&bits_per_sample) && channels >= 1 && channels <= self.max_channels
+    }
+}
+
+// capabilities of the default output and input routes, None for a route without endpoint or PCM capable converter
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    pub output: Option<RouteCapabilities>,
+    pub input: Option<RouteCapabilities>,
+}
+
+// None if there is no sound card
+pub fn capabilities() -> Option<Capabilities> {
+    let mut fields = [0usize; 2 * ROUTE_CAPABILITY_FIELDS];
+    match syscall1(SystemCall::AudioCapabilities, fields.as_mut_ptr() as usize) {
+        0 => None,
+        _ => Some(Capabilities {
+            output: RouteCapabilities::parse(&fields[..ROUTE_CAPABILITY_FIELDS]),
+            input: RouteCapabilities::parse(&fields[ROUTE_CAPABILITY_FIELDS..]),
+        }),
+    }
+}
+
+const EVENT_FIELDS: usize = 4;
+
+// the numbering must match AudioEvent::code() in the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioEvent {
+    // output gets played on another endpoint now, None if there is no output endpoint left
+    OutputChanged(Option<Endpoint>),
+    JackInserted(Endpoint),
+    JackRemoved(Endpoint),
+    // the stream descriptor ran out of samples, so a part of the output got replaced by silence
+    Underrun(u32),
+    VolumeChanged { endpoint: Endpoint, volume_percent: u8, muted: bool },
+    DeviceError(DeviceError),
+    Unknown(usize),
+}
+
+// the numbering must match DeviceError::code() in the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceError {
+    FifoError(u32),
+    DescriptorError { stream_descriptor: u32, recovered: bool },
+    // the DMA engine of the stream descriptor stopped advancing and couldn't be restarted
+    StreamStalled(u32),
+    CodecRemoved(u8),
+    CommandRingError { recovered: bool },
+    Unknown(usize),
+}
+
+impl AudioEvent {
+    // parses the fields described at sys_audio_poll_event in the kernel
+    fn parse(fields: &[usize]) -> Self {
+        let endpoint = |field: usize| Endpoint::new((field >> 8) as u8, field as u8);
+        match fields[0] {
+            1 if fields[1] == NO_ENDPOINT => AudioEvent::OutputChanged(None),
+            1 => AudioEvent::OutputChanged(Some(endpoint(fields[1]))),
+            2 => AudioEvent::JackInserted(endpoint(fields[1])),
+            3 => AudioEvent::JackRemoved(endpoint(fields[1])),
+            4 => AudioEvent::Underrun(fields[1] as u32),
+            5 => AudioEvent::VolumeChanged { endpoint: endpoint(fields[1]), volume_percent: fields[2] as u8, muted: fields[3] != 0 },
+            6 => AudioEvent::DeviceError(match fields[1] {
+                1 => DeviceError::FifoError(fields[2] as u32),
+                2 => DeviceError::DescriptorError { stream_descriptor: fields[2] as u32, recovered: fields[3] != 0 },
+                3 => DeviceError::StreamStalled(fields[2] as u32),
+                4 => DeviceError::CodecRemoved(fields[2] as u8),
+                5 => DeviceError::CommandRingError { recovered: fields[3] != 0 },
+                code => DeviceError::Unknown(code),
+            }),
+            code => AudioEvent::Unknown(code),
+        }
+    }
+}
+
+// The kernel only queues events for processes which subscribed, starting with the events after the subscription.
+// Events which don't get polled in time are dropped, oldest first.
+pub fn subscribe_events(enable: bool) {
+    syscall1(SystemCall::AudioSubscribeEvents, enable as usize);
+}
+
+// None if no event has been queued since the last call
+pub fn poll_event() -> Option<AudioEvent> {
+    let mut fields = [0usize; EVENT_FIELDS];
+    match syscall1(SystemCall::AudioPollEvent, fields.as_mut_ptr() as usize) {
+        0 => None,
+        _ => Some(AudioEvent::parse(&fields)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamOwner {
+    Kernel(String),
+    Process(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamState {
+    Reset,
+    Configured,
+    Prepared,
+    Running,
+    Paused,
+    Draining,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    pub stream_descriptor: u32,
+    pub stream_id: u8,
+    pub owner: StreamOwner,
+    pub endpoint: Option<Endpoint>,
+    pub sample_rate: u32,
+    pub bits_per_sample: u8,
+    pub channels: u8,
+    pub state: StreamState,
+    pub buffer_length: u32,
+    pub fill_level: u32,
+    // bytes the DMA engine fetches ahead of the link position
+    pub fifo_size: u16,
+    // None if the controller doesn't report the watermark
+    pub fifo_watermark: Option<u8>,
+    // shortest buffer, which can still be refilled on buffer completion before the DMA engine fetches from it
+    pub min_buffer_length: u32,
+}
+
+impl StreamInfo {
+    // parses one line of the format described at sys_audio_active_streams in the kernel
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let stream_descriptor = fields.next()?.parse().ok()?;
+        let stream_id = fields.next()?.parse().ok()?;
+        let owner = match fields.next()?.split_once(':')? {
+            ("kernel", subsystem) => StreamOwner::Kernel(subsystem.replace('_', " ")),
+            ("process", id) => StreamOwner::Process(id.parse().ok()?),
+            _ => return None,
+        };
+        let endpoint = match fields.next()? {
+            "-" => None,
+            endpoint => {
+                let (codec_address, node_id) = endpoint.split_once(':')?;
+                Some(Endpoint::new(codec_address.parse().ok()?, node_id.parse().ok()?))
+            }
+        };
+        let sample_rate = fields.next()?.parse().ok()?;
+        let bits_per_sample = fields.next()?.parse().ok()?;
+        let channels = fields.next()?.parse().ok()?;
+        let state = match fields.next()? {
+            "reset" => StreamState::Reset,
+            "configured" => StreamState::Configured,
+            "prepared" => StreamState::Prepared,
+            "running" => StreamState::Running,
+            "paused" => StreamState::Paused,
+            "draining" => StreamState::Draining,
+            "error" => StreamState::Error,
+            _ => return None,
+        };
+        let buffer_length = fields.next()?.parse().ok()?;
+        let fill_level = fields.next()?.parse().ok()?;
+        let fifo_size = fields.next()?.parse().ok()?;
+        let fifo_watermark = match fields.next()? {
+            "-" => None,
+            watermark => Some(watermark.parse().ok()?),
+        };
+        let min_buffer_length = fields.next()?.parse().ok()?;
+
+        Some(Self { stream_descriptor, stream_id, owner, endpoint, sample_rate, bits_per_sample, channels, state, buffer_length, fill_level, fifo_size, fifo_watermark, min_buffer_length })
+    }
+}
+
+// all streams currently using the sound card, with their owners
+pub fn active_streams() -> Vec<StreamInfo> {
+    read_string(SystemCall::AudioActiveStreams)
+        .lines()
+        .filter_map(StreamInfo::parse)
+        .collect()
+}
+
+// frequency of the wall clock of the sound card, which is the same on every IHDA controller
+pub const WALL_CLOCK_FREQUENCY_HZ: u32 = 24_000_000;
+
+// Read-only view on the wall clock of the sound card and the position of one stream, which are both mapped into the process,
+// so that they can be read without a system call (e.g. for synchronizing video frames with audio playback).
+pub struct StreamClock {
+    wall_clock: *const u32,
+    position: *const u32,
+}
+
+impl StreamClock {
+    // None if there is no stream on the given stream descriptor
+    pub fn map(stream_descriptor: u32) -> Option<Self> {
+        let mut addresses = [0usize; 2];
+        match syscall2(SystemCall::AudioMapStreamClock, stream_descriptor as usize, addresses.as_mut_ptr() as usize) {
+            0 => None,
+            _ => Some(Self { wall_clock: addresses[0] as *const u32, position: addresses[1] as *const u32 }),
+        }
+    }
+
+    // ticks of the wall clock (see WALL_CLOCK_FREQUENCY_HZ), wraps around after about 179 seconds
+    pub fn wall_clock(&self) -> u32 {
+        unsafe { self.wall_clock.read_volatile() }
+    }
+
+    // position of the DMA engine in the cyclic buffer of the stream in bytes
+    pub fn position_in_bytes(&self) -> u32 {
+        unsafe { self.position.read_volatile() }
+    }
+}
+
+// index of the frame the DMA engine of a stream is at in its cyclic buffer
+pub fn stream_position(stream_descriptor: u32) -> Option<u32> {
+    match syscall1(SystemCall::AudioStreamPosition, stream_descriptor as usize) {
+        usize::MAX => None,
+        position => Some(position as u32),
+    }
+}
+
+// what the sound card should dump for debugging
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dump {
+    // controller and stream descriptor registers
+    Registers,
+    // codecs with their function groups, widgets and pin configuration defaults
+    Codecs,
+    // widget paths of the running output streams
+    PlaybackPaths,
+    // codec graph as JSON, including amp capabilities and the current control state of every widget
+    CodecGraph,
+    // same as CodecGraph, but the kernel also writes it to the serial port
+    CodecGraphToSerialPort,
+    // FIFO, descriptor and command ring errors reported by the controller and how often they got recovered from
+    Errors,
+    // default codec, output routing, pin roles and endpoint volumes in the format that gets restored at boot
+    Settings,
+    // same as Settings, but the kernel also writes them to the serial port, so that the host can save them
+    SettingsToSerialPort,
+    // widget controls on the playback paths which don't hold what the driver configured, e.g. because the codec ignored a verb
+    PathValidation,
+    // playback and capture path of every endpoint with the gain, mute and selected connection of each widget
+    Mixer,
+}
+
+// the numbering must match MixerError::code() in the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MixerError {
+    NoAudioDevice,
+    // the widget doesn't exist or belongs to a codec which stopped responding
+    UnknownWidget,
+    NoAmplifier,
+    NotMuteCapable,
+    // routes start at the pin widget of an endpoint
+    NotAnEndpoint,
+    // the widget is no converter of the direction of the endpoint
+    NotAConverter,
+    // the connections of the codec don't lead from the endpoint to the converter
+    NoRoute,
+    // the codec didn't answer or answered with an unexpected response
+    Command,
+    Unknown(usize),
+}
+
+impl MixerError {
+    fn from_code(code: usize) -> Self {
+        match code {
+            1 => MixerError::NoAudioDevice,
+            2 => MixerError::UnknownWidget,
+            3 => MixerError::NoAmplifier,
+            4 => MixerError::NotMuteCapable,
+            5 => MixerError::NotAnEndpoint,
+            6 => MixerError::NotAConverter,
+            7 => MixerError::NoRoute,
+            9 => MixerError::Command,
+            code => MixerError::Unknown(code),
+        }
+    }
+}
+
+fn mixer_result(code: usize) -> Result<(), MixerError> {
+    match code {
+        0 => Ok(()),
+        code => Err(MixerError::from_code(code)),
+    }
+}
+
+// Widgets are addressed like endpoints, so a pin widget is its endpoint. Input endpoints get the gain of the amp on their pin
+// widget, all other widgets the one of their output amp (or of all inputs of a mixer without output amp).
+pub fn set_widget_gain(widget: Endpoint, gain_percent: u8) -> Result<(), MixerError> {
+    mixer_result(syscall3(SystemCall::AudioMixerControl, widget.as_usize(), 0, gain_percent as usize))
+}
+
+pub fn set_widget_mute(widget: Endpoint, mute: bool) -> Result<(), MixerError> {
+    mixer_result(syscall3(SystemCall::AudioMixerControl, widget.as_usize(), 1, mute as usize))
+}
+
+// Selects the connections from the endpoint to the converter with the given node id, which lasts until a stream gets set up
+// on the endpoint again.
+pub fn route_endpoint(endpoint: Endpoint, converter_node_id: u8) -> Result<(), MixerError> {
+    mixer_result(syscall3(SystemCall::AudioMixerControl, endpoint.as_usize(), 2, converter_node_id as usize))
+}
+
+// Records every verb sent to the codecs and every response received from them, until it gets turned off again. Turning it on
+// drops the entries of the previous trace. Returns false if there is no sound card.
+pub fn set_verb_trace(enabled: bool) -> bool {
+    syscall1(SystemCall::AudioSetVerbTrace, enabled as usize) != 0
+}
+
+// Up to the given amount of the most recent verbs and responses with the wall clock ticks at which they were sent or received.
+// Empty if there is no sound card.
+pub fn verb_trace(amount: usize) -> String {
+    read_string_with(|buffer, length| syscall3(SystemCall::AudioVerbTrace, amount, buffer as usize, length))
+}
+
+// empty if there is no sound card
+pub fn dump(dump: Dump) -> String {
+    let kind = match dump {
+        Dump::Registers => 0,
+        Dump::Codecs => 1,
+        Dump::PlaybackPaths => 2,
+        Dump::CodecGraph => 3,
+        Dump::CodecGraphToSerialPort => 4,
+        Dump::Errors => 5,
+        Dump::Settings => 6,
+        Dump::SettingsToSerialPort => 7,
+        Dump::PathValidation => 8,
+        Dump::Mixer => 9,
+    };
+    read_string_with(|buffer, length| syscall3(SystemCall::AudioDump, kind, buffer as usize, length))
+}
+
+// for system calls, which copy a string into a buffer and return its full length
+fn read_string(call: SystemCall) -> String {
+    read_string_with(|buffer, length| syscall2(call, buffer as usize, length))
+}
+
+fn read_string_with(call: impl Fn(*mut u8, usize) -> usize) -> String {
+    let mut buffer = vec![0u8; 0];
+    loop {
+        let length = call(buffer.as_mut_ptr(), buffer.len());
+        if length <= buffer.len() {
+            buffer.truncate(length);
+            return from_utf8(&buffer).expect("System call returned invalid UTF-8!").to_string();
+        }
+        buffer.resize(length, 0);
+    }
+}
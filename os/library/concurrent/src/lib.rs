@@ -1,4 +1,6 @@
 #![no_std]
 
+extern crate alloc;
+
 pub mod process;
 pub mod thread;
\ No newline at end of file
@@ -1,4 +1,8 @@
-use syscall::{syscall0, SystemCall};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::from_utf8;
+use syscall::{syscall0, syscall2, SystemCall};
 
 pub struct Process {
     id: usize
@@ -17,4 +21,16 @@ impl Process {
 pub fn current() -> Process {
     let id = syscall0(SystemCall::ProcessId);
     Process::new(id)
-}
\ No newline at end of file
+}
+
+// arguments passed to the current process, without the application name
+pub fn arguments() -> Vec<String> {
+    let length = syscall2(SystemCall::ProcessArguments, 0, 0);
+    let mut buffer = vec![0u8; length];
+    syscall2(SystemCall::ProcessArguments, buffer.as_mut_ptr() as usize, length);
+
+    from_utf8(&buffer).expect("Process arguments are not valid UTF-8!")
+        .split_whitespace()
+        .map(|argument| argument.to_string())
+        .collect()
+}
@@ -38,8 +38,9 @@ pub fn exit() -> ! {
     panic!("System call 'ThreadExit' has returned!")
 }
 
-pub fn start_application(name: &str) -> Option<Thread> {
-    match syscall2(SystemCall::ApplicationStart, name.as_bytes().as_ptr() as usize, name.len()) {
+// the first word of the command is the name of the application, all following words are passed to it as arguments
+pub fn start_application(command: &str) -> Option<Thread> {
+    match syscall2(SystemCall::ApplicationStart, command.as_bytes().as_ptr() as usize, command.len()) {
         0 => None,
         id => Some(Thread::new(id))
     }
@@ -1,8 +1,9 @@
 #![no_std]
 
 use core::arch::asm;
-use crate::SystemCall::SetDate;
+use crate::SystemCall::AudioVerbTrace;
 
+#[derive(Clone, Copy)]
 #[repr(usize)]
 #[allow(dead_code)]
 pub enum SystemCall {
@@ -18,10 +19,38 @@ pub enum SystemCall {
     ApplicationStart,
     GetSystemTime,
     GetDate,
-    SetDate
+    SetDate,
+    ProcessArguments,
+    AudioPlayTestTone,
+    AudioActiveStreams,
+    AudioMapStreamClock,
+    AudioStreamPosition,
+    AudioOpen,
+    AudioWrite,
+    AudioSetVolume,
+    AudioClose,
+    AudioDump,
+    AudioPlayFile,
+    AudioPlaybackProgress,
+    AudioPausePlayback,
+    AudioResumePlayback,
+    AudioStopPlayback,
+    AudioRecordFile,
+    AudioCapabilities,
+    AudioMapSession,
+    AudioStartSession,
+    AudioSpeakerTestReport,
+    AudioTestSpeakers,
+    AudioSetSidetoneLevel,
+    AudioSubscribeEvents,
+    AudioPollEvent,
+    AudioSetSessionMode,
+    AudioMixerControl,
+    AudioSetVerbTrace,
+    AudioVerbTrace
 }
 
-pub const NUM_SYSCALLS: usize = SetDate as usize + 1;
+pub const NUM_SYSCALLS: usize = AudioVerbTrace as usize + 1;
 
 #[inline(always)]
 pub fn syscall0(call: SystemCall) -> usize {
@@ -11,6 +11,7 @@ readonly CONST_QEMU_OLD_AUDIO_ARGS="-soundhw pcspk"
 readonly CONST_QEMU_NEW_AUDIO_ARGS="-audio alsa,model=hda"
 readonly CONST_QEMU_BOOT_DEVICE="-drive driver=raw,node-name=boot,file.driver=file,file.filename=d3os.img"
 readonly CONST_QEMU_GDB_PORT="1234"
+readonly CONST_QEMU_IHDA_SELF_TEST_ARGS="-fw_cfg name=opt/d3os/ihda_self_test,string=1"
 
 QEMU_BIOS=""
 QEMU_MACHINE="${CONST_QEMU_MACHINE_PC}"
@@ -117,6 +118,10 @@ print_usage() {
         Enable debugging with a debug type ([default] | [vscode]) (default: Disabled)
     -b, --bios
         Set the BIOS file, which qemu should use (Default: Download OVMF from Ubuntu 20.04 packages)
+    -t, --ihda-self-test
+        Run the sound card self test instead of the demo and print its result to the serial port (Default: Disabled)
+    -a, --audio-settings
+        Restore the audio settings saved in the given file (output of 'ihda settings') at boot (Default: Disabled)
     -h, --help
         Show this help message\\n"
 }
@@ -145,6 +150,14 @@ parse_args() {
     -b | --bios)
       parse_bios "$val"
       ;;
+    -t | --ihda-self-test)
+      QEMU_ARGS="${QEMU_ARGS} ${CONST_QEMU_IHDA_SELF_TEST_ARGS}"
+      shift
+      continue
+      ;;
+    -a | --audio-settings)
+      QEMU_ARGS="${QEMU_ARGS} -fw_cfg name=opt/d3os/audio_settings,file=${val}"
+      ;;
     -h | --help)
       print_usage
       exit 0